@@ -0,0 +1,52 @@
+//! The probes of an EIP-1967 proxy traced with one EVM against one EVM each, and the whole
+//! detection, over 10k iterations.
+//!
+//! `cargo bench --bench probes`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use alloy_primitives::Bytes;
+use evm_proxy_tools::{detect_proxy, trace_dispatch, trace_dispatches, CalldataStrategy, DefaultProbes, DetectorConfig, TraceConfig, TraceEnvironment};
+
+#[allow(dead_code)]
+#[path = "../tests/common/fixtures.rs"]
+mod fixtures;
+
+use fixtures::EIP_1967_CODE;
+
+const ITERATIONS: u32 = 10_000;
+
+/// Mean time of `f` over [ITERATIONS] runs.
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let code = Bytes::from_static(EIP_1967_CODE);
+    let probes = DefaultProbes.probes(&code);
+    let config = TraceConfig::new(TraceEnvironment::from_seed(1));
+
+    let one_by_one = time(|| {
+        for calldata in &probes {
+            black_box(trace_dispatch(black_box(&code), calldata.clone(), &config)).unwrap();
+        }
+    });
+    let reused = time(|| {
+        black_box(trace_dispatches(black_box(&code), probes.iter().cloned(), &config));
+    });
+    println!(
+        "{} probes: one EVM each {:?}, one EVM {:?}, {:.2}x",
+        probes.len(), one_by_one, reused, one_by_one.as_secs_f64() / reused.as_secs_f64(),
+    );
+
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let detection = time(|| {
+        black_box(detect_proxy(black_box(EIP_1967_CODE), &config)).unwrap();
+    });
+    println!("detection: {:?}", detection);
+}
@@ -0,0 +1,89 @@
+//! Extends detection with an in-house forwarder the library doesn't know, and tunes which of
+//! the library's rules apply.
+//!
+//! Custom patterns are tried first; code they don't match goes through [detect_proxy] with a
+//! [RulePolicy] that here disables the diamond fallback, which classifies anything delegating
+//! inconsistently as a diamond.
+//!
+//! ```sh
+//! cargo run --example custom_strategy
+//! ```
+
+use alloy_primitives::Address;
+use evm_proxy_tools::{detect_proxy, DetectorConfig, ProxyDetectionResult, RuleId, RulePolicy};
+
+#[path = "support/mod.rs"]
+mod support;
+
+/// A forwarder embedding its target between a fixed prefix and suffix.
+pub struct CustomPattern {
+    pub name: &'static str,
+    pub prefix: &'static [u8],
+    pub suffix: &'static [u8],
+}
+
+impl CustomPattern {
+    /// The embedded address if `code` is exactly `prefix address suffix`.
+    pub fn matches(&self, code: &[u8]) -> Option<Address> {
+        let rest = code.strip_prefix(self.prefix)?;
+        let address = rest.strip_suffix(self.suffix).filter(|address| address.len() == 20)?;
+        Some(Address::from_slice(address))
+    }
+}
+
+/// Forwards calldata and returns whatever comes back, without checking success.
+pub const UNCHECKED_FORWARDER: CustomPattern = CustomPattern {
+    name: "unchecked forwarder",
+    prefix: &hex_literal::hex!("363d3d373d3d363d73"),
+    suffix: &hex_literal::hex!("5af4503d600060003e3d6000f3"),
+};
+
+// One per code, like the library's own DetectOutcome
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq)]
+pub enum Detection {
+    Custom { name: &'static str, implementation: Address },
+    Library(ProxyDetectionResult),
+}
+
+/// Custom patterns in front of the library's detection.
+pub struct Strategy {
+    pub patterns: Vec<CustomPattern>,
+    pub config: DetectorConfig,
+}
+
+impl Strategy {
+    pub fn new(patterns: Vec<CustomPattern>) -> Self {
+        let rules = RulePolicy::default().disable(RuleId::DiamondOther);
+        Self { patterns, config: DetectorConfig { rules, seed: Some(0), ..Default::default() } }
+    }
+
+    pub fn detect(&self, code: &[u8]) -> Option<Detection> {
+        self.patterns.iter()
+            .find_map(|pattern| pattern.matches(code).map(|implementation| Detection::Custom { name: pattern.name, implementation }))
+            .or_else(|| detect_proxy(code, &self.config).map(Detection::Library))
+    }
+}
+
+/// An instance of [UNCHECKED_FORWARDER] pointing at `0xbebe...`.
+pub fn unchecked_forwarder_code() -> Vec<u8> {
+    [UNCHECKED_FORWARDER.prefix, &[0xbe; 20], UNCHECKED_FORWARDER.suffix].concat()
+}
+
+pub fn run() -> Vec<(String, Option<Detection>)> {
+    let strategy = Strategy::new(vec![UNCHECKED_FORWARDER]);
+    let mut codes: Vec<(String, Vec<u8>)> = vec![("in-house forwarder".to_string(), unchecked_forwarder_code())];
+    codes.extend(support::CORPUS.iter().map(|(name, code)| (name.to_string(), code.to_vec())));
+    codes.into_iter().map(|(name, code)| (name, strategy.detect(&code))).collect()
+}
+
+#[allow(dead_code)]
+fn main() {
+    for (name, detection) in run() {
+        match detection {
+            Some(Detection::Custom { name: pattern, implementation }) => println!("{:<45} {} to {}", name, pattern, implementation),
+            Some(Detection::Library(result)) => println!("{:<45} {:?} via {:?}", name, result.proxy_type, result.rule),
+            None => println!("{:<45} not a proxy", name),
+        }
+    }
+}
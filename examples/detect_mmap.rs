@@ -0,0 +1,41 @@
+//! Detects the proxies of a corpus straight from a memory mapped file, without copying the
+//! bytecode out of the map.
+//!
+//! The corpus is a sequence of records, each a big endian `u32` length followed by that many
+//! bytes of runtime code (see `tests/fixtures/corpus.bin`).
+//!
+//! ```sh
+//! cargo run --example detect_mmap -- tests/fixtures/corpus.bin
+//! ```
+
+use std::fs::File;
+
+use evm_proxy_tools::{detect_proxies, DetectorConfig};
+use memmap2::Mmap;
+
+/// Splits the mapped corpus into borrowed code slices.
+fn records(mut data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        let (len, rest) = data.split_first_chunk::<4>()?;
+        let len = (u32::from_be_bytes(*len) as usize).min(rest.len());
+        let (code, rest) = rest.split_at(len);
+        data = rest;
+        Some(code)
+    })
+}
+
+fn main() -> std::io::Result<()> {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "tests/fixtures/corpus.bin".to_string());
+    let file = File::open(path)?;
+    // Safety: the file isn't expected to be modified while mapped
+    let map = unsafe { Mmap::map(&file)? };
+
+    let config = DetectorConfig::default();
+    for (idx, result) in detect_proxies(records(&map), &config).enumerate() {
+        match result {
+            Some(result) => println!("#{}: {:?} {:?}", idx, result.proxy_type, result.dispatch),
+            None => println!("#{}: not a proxy", idx),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Detects the proxies of the bundled fixture corpus, no network needed, and prints a summary.
+//!
+//! Minimal proxies and other known runtimes are matched statically; the rest is executed
+//! against synthetic probes to observe where it delegates.
+//!
+//! ```sh
+//! cargo run --example detect_offline
+//! ```
+
+use std::collections::BTreeMap;
+
+use evm_proxy_tools::{detect_proxy, DetectorConfig, ProxyDetectionResult, RuleId};
+
+#[path = "support/mod.rs"]
+mod support;
+
+/// Rules matching bytecode patterns, the others come from tracing.
+const STATIC_RULES: &[RuleId] = &[
+    RuleId::Eip1167Pattern,
+    RuleId::Eip7511Pattern,
+    RuleId::Eip3448Pattern,
+    RuleId::SoladyClonePattern,
+    RuleId::VyperForwarderPattern,
+    RuleId::SafeProxyPattern,
+    RuleId::MetamorphicInitPattern,
+    RuleId::BeaconProxyPattern,
+    RuleId::GeneratedRouterPattern,
+];
+
+/// What [run] found in the corpus.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub results: Vec<(&'static str, Option<ProxyDetectionResult>)>,
+    /// Proxies per type name.
+    pub by_type: BTreeMap<String, usize>,
+    pub matched_statically: usize,
+    pub traced: usize,
+    pub not_proxies: usize,
+}
+
+pub fn run() -> Summary {
+    // A fixed seed makes the synthetic environment, and so the output, reproducible
+    let config = DetectorConfig { seed: Some(0), ..Default::default() };
+    let mut summary = Summary::default();
+    for (name, code) in support::CORPUS {
+        let result = detect_proxy(code, &config);
+        match &result {
+            Some(result) => {
+                *summary.by_type.entry(format!("{:?}", result.proxy_type)).or_default() += 1;
+                if STATIC_RULES.contains(&result.rule) {
+                    summary.matched_statically += 1;
+                } else {
+                    summary.traced += 1;
+                }
+            },
+            None => summary.not_proxies += 1,
+        }
+        summary.results.push((name, result));
+    }
+    summary
+}
+
+#[allow(dead_code)]
+fn main() {
+    let summary = run();
+    for (name, result) in &summary.results {
+        match result {
+            Some(result) => println!("{:<45} {:?} via {:?}: {:?}", name, result.proxy_type, result.rule, result.dispatch),
+            None => println!("{:<45} not a proxy", name),
+        }
+    }
+    println!();
+    println!("{} proxies, {} matched statically, {} by tracing, {} other contracts", summary.matched_statically + summary.traced, summary.matched_statically, summary.traced, summary.not_proxies);
+    for (proxy_type, count) in &summary.by_type {
+        println!("  {:<20} {}", proxy_type, count);
+    }
+}
@@ -0,0 +1,87 @@
+//! Resolves the implementations of an EIP-1967 proxy and of a diamond end to end, detection
+//! then reads, against an in-memory chain instead of an RPC endpoint.
+//!
+//! Any `ethers` `Middleware` works with the readers; swapping the in-memory chain for
+//! `Provider::<Http>::try_from(url)` resolves real contracts.
+//!
+//! ```sh
+//! cargo run --example resolve_with_mock
+//! ```
+
+use std::sync::Arc;
+
+use alloy_primitives::{Address, U256};
+use ethers_core::abi::{encode, Token};
+use ethers_core::types::H160;
+use evm_proxy_tools::{detect_proxy, get_proxy_implementation, DetectorConfig, ProxyDispatch, ProxyImplementation, ProxyReadError, ProxyType, Selector, StorageReader};
+
+#[path = "support/mod.rs"]
+mod support;
+
+use support::{fixtures, InMemoryChain};
+
+pub const PROXY: Address = Address::new([0x11; 20]);
+pub const IMPLEMENTATION: Address = Address::new([0x22; 20]);
+pub const DIAMOND: Address = Address::new([0x33; 20]);
+pub const FACETS: [(Address, Selector); 2] = [
+    (Address::new([0x44; 20]), Selector::from_u32_be(0x70a08231)),
+    (Address::new([0x55; 20]), Selector::from_u32_be(0xa9059cbb)),
+];
+
+/// `keccak256("eip1967.proxy.implementation") - 1`
+const EIP_1967_IMPLEMENTATION_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+
+/// `facets()`
+const FACETS_SELECTOR: [u8; 4] = hex_literal::hex!("7a0ed627");
+
+/// The chain the example runs against: the proxy's slot points to [IMPLEMENTATION] and the
+/// diamond's loupe lists [FACETS].
+pub fn chain() -> InMemoryChain {
+    let chain = InMemoryChain::new();
+    chain.set_code(PROXY, fixtures::EIP_1967_CODE)
+        .set_storage(PROXY, EIP_1967_IMPLEMENTATION_SLOT, U256::from_be_slice(IMPLEMENTATION.as_slice()));
+
+    let facets = FACETS.iter()
+        .map(|(facet, selector)| Token::Tuple(vec![Token::Address(H160::from(facet.0 .0)), Token::Array(vec![Token::FixedBytes(selector.as_bytes().to_vec())])]))
+        .collect();
+    chain.set_code(DIAMOND, fixtures::DIAMOND_STANDARD_CODE)
+        .set_call(DIAMOND, &FACETS_SELECTOR, &encode(&[Token::Array(facets)]));
+    chain
+}
+
+/// A proxy of the chain, as detected from its code and read.
+#[derive(Debug)]
+pub struct Resolved {
+    pub address: Address,
+    pub proxy_type: ProxyType,
+    pub dispatch: ProxyDispatch,
+    pub implementation: ProxyImplementation,
+}
+
+/// Detects the proxy at `address` from its code and reads where it currently points.
+pub async fn resolve(chain: &InMemoryChain, address: Address) -> Result<Resolved, ProxyReadError> {
+    let rpc = Arc::new(chain.provider());
+    let code = rpc.code_at(address, None).await?;
+    let result = detect_proxy(&code, &DetectorConfig::default()).ok_or(ProxyReadError::UnknownProxy)?;
+    let implementation = get_proxy_implementation(rpc, &address, &result.dispatch).await?;
+    Ok(Resolved { address, proxy_type: result.proxy_type, dispatch: result.dispatch, implementation })
+}
+
+pub async fn run() -> Result<Vec<Resolved>, ProxyReadError> {
+    let chain = chain();
+    let mut resolved = Vec::new();
+    for address in [PROXY, DIAMOND] {
+        resolved.push(resolve(&chain, address).await?);
+    }
+    Ok(resolved)
+}
+
+#[allow(dead_code)]
+#[tokio::main]
+async fn main() -> Result<(), ProxyReadError> {
+    for resolved in run().await? {
+        println!("{}: {:?} dispatching through {:?}", resolved.address, resolved.proxy_type, resolved.dispatch);
+        println!("  -> {:?}", resolved.implementation);
+    }
+    Ok(())
+}
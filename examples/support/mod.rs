@@ -0,0 +1,107 @@
+//! Shared by the examples: the bundled fixture corpus and an in-memory chain to resolve
+//! implementations without an RPC endpoint.
+
+#![allow(dead_code)]
+
+use std::{collections::HashMap, fmt::Debug, sync::{Arc, Mutex}};
+
+use alloy_primitives::{Address, Bytes, U256};
+use async_trait::async_trait;
+use ethers_providers::{JsonRpcClient, JsonRpcError, MockError, Provider};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+#[path = "../../tests/common/fixtures.rs"]
+pub mod fixtures;
+
+/// Every bundled fixture with its name.
+pub const CORPUS: &[(&str, &[u8])] = &[
+    ("EIP-1967 (OpenZeppelin)", fixtures::EIP_1967_CODE),
+    ("EIP-897", fixtures::EIP_897_CODE),
+    ("Diamond (EIP-2535)", fixtures::DIAMOND_STANDARD_CODE),
+    ("ERC-20, not a proxy", fixtures::ERC20_BINARY_SEARCH_CODE),
+    ("ERC-5202 blueprint of an EIP-1167 clone", fixtures::BLUEPRINT_1167_CODE),
+    ("Generated router", fixtures::GENERATED_ROUTER_CODE),
+    ("Vyper forwarder", fixtures::VYPER_FORWARDER_V2_CODE),
+    ("Safe proxy", fixtures::SAFE_PROXY_CODE),
+    ("Solady PUSH0 clone", fixtures::SOLADY_PUSH0_CLONE_CODE),
+    ("Solady clone with immutable args", fixtures::SOLADY_CWIA_CODE),
+];
+
+#[derive(Debug, Default)]
+struct ChainState {
+    code: HashMap<Address, Bytes>,
+    storage: HashMap<(Address, U256), U256>,
+    /// `eth_call` results by callee and calldata.
+    calls: HashMap<(Address, Bytes), Bytes>,
+}
+
+/// A chain held in memory, answering `eth_getCode`, `eth_getStorageAt` and `eth_call` at every
+/// block. Unset code and storage read as empty and zero, unknown calls revert.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryChain {
+    state: Arc<Mutex<ChainState>>,
+}
+
+impl InMemoryChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_code(&self, address: Address, code: &[u8]) -> &Self {
+        self.state.lock().unwrap().code.insert(address, Bytes::copy_from_slice(code));
+        self
+    }
+
+    pub fn set_storage(&self, address: Address, slot: U256, value: U256) -> &Self {
+        self.state.lock().unwrap().storage.insert((address, slot), value);
+        self
+    }
+
+    pub fn set_call(&self, address: Address, calldata: &[u8], result: &[u8]) -> &Self {
+        self.state.lock().unwrap().calls.insert((address, Bytes::copy_from_slice(calldata)), Bytes::copy_from_slice(result));
+        self
+    }
+
+    pub fn provider(&self) -> Provider<InMemoryChain> {
+        Provider::new(self.clone())
+    }
+
+    fn answer(&self, method: &str, params: &Value) -> Result<Value, String> {
+        let state = self.state.lock().unwrap();
+        let address = |value: &Value| value.as_str().and_then(|s| s.parse::<Address>().ok()).ok_or("bad address");
+        match method {
+            "eth_getCode" => {
+                let code = state.code.get(&address(&params[0])?).cloned().unwrap_or_default();
+                Ok(json!(code))
+            },
+            "eth_getStorageAt" => {
+                let slot: U256 = params[1].as_str().and_then(|s| s.parse().ok()).ok_or("bad slot")?;
+                let value = state.storage.get(&(address(&params[0])?, slot)).copied().unwrap_or_default();
+                Ok(json!(format!("0x{:064x}", value)))
+            },
+            "eth_call" => {
+                let tx = &params[0];
+                let data: Bytes = tx["data"].as_str().or(tx["input"].as_str()).and_then(|s| s.parse().ok()).ok_or("bad calldata")?;
+                state.calls.get(&(address(&tx["to"])?, data)).map(|result| json!(result)).ok_or_else(|| "execution reverted".to_string())
+            },
+            _ => Err(format!("{} isn't supported in memory", method)),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for InMemoryChain {
+    type Error = MockError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, MockError>
+        where T: Debug + Serialize + Send + Sync,
+              R: DeserializeOwned + Send
+    {
+        let params = serde_json::to_value(params)?;
+        match self.answer(method, &params) {
+            Ok(value) => Ok(serde_json::from_value(value)?),
+            Err(message) => Err(MockError::JsonRpcError(JsonRpcError { code: -32000, message, data: None })),
+        }
+    }
+}
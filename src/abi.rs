@@ -0,0 +1,173 @@
+//! Typed bindings of the interfaces the read path talks to, so calls are built and decoded from
+//! their signatures instead of hand-written selectors and offsets.
+//!
+//! The bindings come from ethers' `abigen!` rather than alloy's `sol!`: the crate reads through
+//! ethers `Middleware`s and doesn't depend on `alloy-sol-types`, so the generated types fit the
+//! calls and logs it already handles. The selectors and topics that must stay constants, or
+//! come from the built-in data like the self-report getters, are checked against the bindings
+//! in the tests below.
+
+use alloy_primitives::Address;
+use ethers_contract::abigen;
+use ethers_core::abi::AbiDecode;
+
+use crate::utils::h160_to_b160;
+
+abigen!(
+    IBeacon, r"[
+    function implementation() external view returns (address)
+]";
+
+    IERC1822Proxiable, r"[
+    function proxiableUUID() external view returns (bytes32)
+]";
+
+    IERC1967, r"[
+    event Upgraded(address indexed implementation)
+    event AdminChanged(address previousAdmin, address newAdmin)
+    event BeaconUpgraded(address indexed beacon)
+]";
+
+    IUpgradeable, r"[
+    function upgradeTo(address newImplementation) external
+    function upgradeToAndCall(address newImplementation, bytes data) external payable
+]";
+
+    IProxyAdmin, r"[
+    function getProxyImplementation(address proxy) external view returns (address)
+    function getProxyAdmin(address proxy) external view returns (address)
+    function upgrade(address proxy, address implementation) external
+    function upgradeAndCall(address proxy, address implementation, bytes data) external payable
+]";
+
+    IDiamondLoupe, r"[
+    struct Facet {address facetAddress; bytes4[] functionSelectors;}
+
+    function facets() external view returns (Facet[])
+    function facetAddresses() external view returns (address[])
+    function facetAddress(bytes4 functionSelector) external view returns (address)
+    function facetFunctionSelectors(address facet) external view returns (bytes4[])
+]";
+
+    IDiamondCut, r"[
+    struct FacetCut {address facetAddress; uint8 action; bytes4[] functionSelectors;}
+
+    function diamondCut(FacetCut[] diamondCut, address init, bytes data) external
+    event DiamondCut(FacetCut[] diamondCut, address init, bytes data)
+]";
+
+    ICompoundDelegator, r"[
+    event NewImplementation(address oldImplementation, address newImplementation)
+]";
+
+    IMulticall3, r"[
+    struct Call3 {address target; bool allowFailure; bytes callData;}
+    struct Call3Result {bool success; bytes returnData;}
+
+    function aggregate3(Call3[] calls) external payable returns (Call3Result[] returnData)
+]";
+
+    IERC165, r"[
+    function supportsInterface(bytes4 interfaceId) external view returns (bool)
+]";
+
+    Ownable, r"[
+    function owner() external view returns (address)
+    event OwnershipTransferred(address indexed previousOwner, address indexed newOwner)
+]";
+);
+
+/// The address an `address` returning call answered with, its first word decoded as
+/// [ImplementationReturn]. Refused if the word has high bytes set, or if `exact` and more than
+/// the word was returned.
+pub(crate) fn decode_address_return(output: &[u8], exact: bool) -> Option<Address> {
+    let word = output.get(..32).filter(|_| !exact || output.len() == 32)?;
+    if word[..12].iter().any(|b| *b != 0) {
+	return None;
+    }
+    ImplementationReturn::decode(word).ok().map(|address| h160_to_b160(&address.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_contract::{EthCall, EthEvent};
+    use ethers_core::abi::AbiEncode;
+
+    use crate::consts::{ADMIN_CHANGED_TOPIC, BEACON_IMPLEMENTATION_SELECTOR, BEACON_UPGRADED_TOPIC, DIAMOND_CUT_TOPIC, DIAMOND_FACETS_SELECTOR, DIAMOND_FACET_ADDRESS_SELECTOR, SELECTOR_LABELS, SELF_REPORT_GETTERS, UPGRADED_TOPIC};
+    use crate::data::{resolver_selectors, SelectorKind};
+    use crate::Selector;
+
+    #[test]
+    fn test_selectors_match_bindings() {
+	// The constants the code and the tracer still match on
+	assert_eq!(ImplementationCall::selector(), *BEACON_IMPLEMENTATION_SELECTOR.as_bytes());
+	assert_eq!(ProxiableUUIDCall::selector(), alloy_primitives::keccak256("proxiableUUID()")[..4]);
+	assert_eq!(Aggregate3Call::selector(), hex_literal::hex!("82ad56cb"));
+	// And the built-in table entries of the interfaces bound here
+	let entries: Vec<(Selector, SelectorKind)> = resolver_selectors().unwrap().into_iter().map(|entry| (entry.selector, entry.kind)).collect();
+	assert!(entries.contains(&(Selector::new(FacetAddressCall::selector()), SelectorKind::Resolver)));
+	assert!(entries.contains(&(Selector::new(ImplementationCall::selector()), SelectorKind::SelfReport)));
+	assert!(SELF_REPORT_GETTERS.iter().any(|(selector, _)| *selector == Selector::new(ImplementationCall::selector())));
+    }
+
+    #[test]
+    fn test_constants_match_bindings() {
+	assert_eq!(DIAMOND_FACETS_SELECTOR, Selector::new(FacetsCall::selector()));
+	assert_eq!(DIAMOND_FACET_ADDRESS_SELECTOR, Selector::new(FacetAddressCall::selector()));
+	// Every label of a bound function names its selector
+	let bound = [
+	    (DiamondCutCall::selector(), DiamondCutCall::abi_signature()),
+	    (FacetsCall::selector(), FacetsCall::abi_signature()),
+	    (FacetFunctionSelectorsCall::selector(), FacetFunctionSelectorsCall::abi_signature()),
+	    (FacetAddressesCall::selector(), FacetAddressesCall::abi_signature()),
+	    (FacetAddressCall::selector(), FacetAddressCall::abi_signature()),
+	    (SupportsInterfaceCall::selector(), SupportsInterfaceCall::abi_signature()),
+	    (OwnerCall::selector(), OwnerCall::abi_signature()),
+	];
+	for (selector, signature) in bound {
+	    assert!(SELECTOR_LABELS.contains(&(Selector::new(selector), signature.as_ref())), "{}", signature);
+	}
+	// The topics monitoring advises watching are the ones the events are decoded from
+	assert_eq!(UPGRADED_TOPIC.0, UpgradedFilter::signature().0);
+	assert_eq!(ADMIN_CHANGED_TOPIC.0, AdminChangedFilter::signature().0);
+	assert_eq!(BEACON_UPGRADED_TOPIC.0, BeaconUpgradedFilter::signature().0);
+	assert_eq!(DIAMOND_CUT_TOPIC.0, DiamondCutFilter::signature().0);
+    }
+
+    #[test]
+    fn test_decode_address_return() {
+	let word = hex_literal::hex!("00000000000000000000000000000000000000000000000000000000000000aa");
+	assert_eq!(decode_address_return(&word, true), Some(Address::with_last_byte(0xaa)));
+	// A second word is ignored unless the return must be exactly an address
+	let longer = [word.as_slice(), &[0xff; 32]].concat();
+	assert_eq!(decode_address_return(&longer, false), Some(Address::with_last_byte(0xaa)));
+	assert_eq!(decode_address_return(&longer, true), None);
+	// High bytes set or short, not an address
+	assert_eq!(decode_address_return(&[0xff; 32], false), None);
+	assert_eq!(decode_address_return(&word[..31], false), None);
+    }
+
+    #[test]
+    fn test_encoding_matches_hand_rolled() {
+	// Argumentless calls are their selector
+	assert_eq!(ImplementationCall.encode(), BEACON_IMPLEMENTATION_SELECTOR.as_bytes());
+	assert_eq!(ProxiableUUIDCall.encode(), hex_literal::hex!("52d1902d"));
+	let facet_address = FacetAddressCall { function_selector: hex_literal::hex!("cdffacc6") }.encode();
+	assert_eq!(facet_address, hex_literal::hex!("cdffacc6" "cdffacc600000000000000000000000000000000000000000000000000000000"));
+	let proxy = "0x00000000000000000000000000000000000000aa".parse().unwrap();
+	let upgrade_to = UpgradeToCall { new_implementation: proxy }.encode();
+	assert_eq!(upgrade_to, hex_literal::hex!("3659cfe6" "00000000000000000000000000000000000000000000000000000000000000aa"));
+	let get_implementation = GetProxyImplementationCall { proxy }.encode();
+	assert_eq!(get_implementation, hex_literal::hex!("204e1c7a" "00000000000000000000000000000000000000000000000000000000000000aa"));
+    }
+
+    #[test]
+    fn test_event_topics() {
+	assert_eq!(UpgradedFilter::signature().0, hex_literal::hex!("bc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b"));
+	assert_eq!(AdminChangedFilter::signature().0, hex_literal::hex!("7e644d79422f17c01e4894b5f4f588d331ebfa28653d42ae832dc59e38c9798f"));
+	assert_eq!(BeaconUpgradedFilter::signature().0, hex_literal::hex!("1cf3b03a6cf19fa2baba4df148e9dcabedea7f8a5c07840e207e5c089be95d3e"));
+	assert_eq!(DiamondCutFilter::signature().0, hex_literal::hex!("8faa70878671ccd212d20771b795c50af8fd3ff6cf27f4bde57e5d4de0aeb673"));
+	assert_eq!(NewImplementationFilter::signature().0, *alloy_primitives::keccak256("NewImplementation(address,address)"));
+    }
+}
@@ -0,0 +1,157 @@
+use alloy_primitives::{keccak256, Address, B256};
+use ethers_core::types::H256;
+use ethers_providers::Middleware;
+use tracing::debug;
+
+use crate::read::ProxyReadError;
+use crate::utils::{h160_to_b160, raddress_to_h160};
+
+/// Salts tried at most per factory, caller provided salts beyond it are ignored.
+pub const MAX_SALT_CANDIDATES: usize = 256;
+
+/// How a factory turns the clone's runtime code into the init code it deploys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CreationCode {
+    /// OpenZeppelin's `Clones` copier, `RETURNDATASIZE PUSH1 len DUP1 PUSH1 0x0a RETURNDATASIZE
+    /// CODECOPY DUP2 RETURN` followed by the runtime code.
+    OzClones,
+    /// A fixed prefix followed by the runtime code.
+    Prefix(Vec<u8>),
+}
+
+impl CreationCode {
+    pub fn init_code(&self, runtime: &[u8]) -> Option<Vec<u8>> {
+	let mut init = match self {
+	    CreationCode::OzClones => {
+		let len = u8::try_from(runtime.len()).ok()?;
+		vec![0x3d, 0x60, len, 0x80, 0x60, 0x0a, 0x3d, 0x39, 0x81, 0xf3]
+	    },
+	    CreationCode::Prefix(prefix) => prefix.clone(),
+	};
+	init.extend_from_slice(runtime);
+	Some(init)
+    }
+}
+
+/// A contract known to deploy clones with CREATE2.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloneFactory {
+    pub name: String,
+    pub address: Address,
+    pub creation: CreationCode,
+}
+
+impl CloneFactory {
+    pub fn new(name: impl Into<String>, address: Address, creation: CreationCode) -> Self {
+	Self { name: name.into(), address, creation }
+    }
+}
+
+/// The factories [attribute_clone] derives CREATE2 addresses for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FactoryRegistry {
+    factories: Vec<CloneFactory>,
+}
+
+impl Default for FactoryRegistry {
+    fn default() -> Self {
+	Self::builtin()
+    }
+}
+
+impl FactoryRegistry {
+    pub fn empty() -> Self {
+	Self { factories: Vec::new() }
+    }
+
+    /// The public CREATE2 deployers, with clones deployed through them using the
+    /// OpenZeppelin `Clones` init code.
+    pub fn builtin() -> Self {
+	Self {
+	    factories: vec![
+		CloneFactory::new("Deterministic Deployment Proxy", Address::new(hex_literal::hex!("4e59b44847b379578588920ca78fbf26c0b4956c")), CreationCode::OzClones),
+		CloneFactory::new("ImmutableCreate2Factory", Address::new(hex_literal::hex!("0000000000ffe8b47b3e2130213b802212439497")), CreationCode::OzClones),
+	    ]
+	}
+    }
+
+    /// Registers a factory, e.g. a contract deploying clones with `Clones.cloneDeterministic`.
+    pub fn register(&mut self, factory: CloneFactory) {
+	self.factories.push(factory);
+    }
+
+    pub fn factories(&self) -> &[CloneFactory] {
+	&self.factories
+    }
+}
+
+/// What the caller knows about the clone's deployment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttributionHints {
+    /// Salts to try besides the implementation address, at most [MAX_SALT_CANDIDATES].
+    pub salts: Vec<B256>,
+    /// The transaction that created the clone.
+    pub creation_tx: Option<B256>,
+}
+
+/// Who deployed a clone.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CloneAttribution {
+    pub factory: Option<Address>,
+    /// The clone's address was derived with CREATE2 from the factory, salt and init code.
+    pub deterministic: bool,
+    pub salt: Option<B256>,
+}
+
+/// The salts tried for every factory: the implementation address, left padded, then the
+/// caller's.
+fn salt_candidates(implementation: &Address, hints: &AttributionHints) -> Vec<B256> {
+    std::iter::once(implementation.into_word())
+	.chain(hints.salts.iter().copied())
+	.take(MAX_SALT_CANDIDATES)
+	.collect()
+}
+
+/// Finds the registered factory, and salt, whose CREATE2 derivation gives `clone`.
+pub fn derive_clone_factory(registry: &FactoryRegistry, clone: &Address, runtime: &[u8], salts: &[B256]) -> Option<(Address, B256)> {
+    registry.factories.iter().find_map(|factory| {
+	let init_code_hash = keccak256(factory.creation.init_code(runtime)?);
+	salts.iter()
+	    .find(|salt| factory.address.create2(*salt, init_code_hash) == *clone)
+	    .map(|salt| (factory.address, *salt))
+    })
+}
+
+/// Attributes the clone at `clone` of `implementation` to the factory that deployed it.
+///
+/// The CREATE2 derivation is tried first for every registered factory, with the implementation
+/// address as salt and then the caller's salts. Otherwise the receipt of `hints.creation_tx` names the contract the
+/// transaction called as the factory, or nothing if the clone was deployed by the transaction
+/// itself. Clones that can't be attributed have an empty [CloneAttribution].
+pub async fn attribute_clone<M>(rpc: &M, registry: &FactoryRegistry, clone: &Address, implementation: &Address, hints: &AttributionHints) -> Result<CloneAttribution, ProxyReadError>
+    where M: Middleware
+{
+    let runtime = rpc.get_code(raddress_to_h160(clone), None).await.map_err(ProxyReadError::from_middleware)?;
+    if runtime.is_empty() {
+	return Err(ProxyReadError::NoCode(*clone));
+    }
+
+    let salts = salt_candidates(implementation, hints);
+    if let Some((factory, salt)) = derive_clone_factory(registry, clone, &runtime, &salts) {
+	return Ok(CloneAttribution { factory: Some(factory), deterministic: true, salt: Some(salt) });
+    }
+
+    let Some(tx) = hints.creation_tx else {
+	return Ok(CloneAttribution::default());
+    };
+    let receipt = rpc.get_transaction_receipt(H256::from(tx.0)).await.map_err(ProxyReadError::from_middleware)?;
+    debug!("creation receipt: {:?}", receipt);
+    let factory = match receipt {
+	// Deployed by the transaction, no factory involved
+	Some(receipt) if receipt.contract_address.map(|a| h160_to_b160(&a)) == Some(*clone) => None,
+	Some(receipt) => receipt.to.map(|to| h160_to_b160(&to)),
+	None => None,
+    };
+    Ok(CloneAttribution { factory, deterministic: false, salt: None })
+}
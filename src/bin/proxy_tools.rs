@@ -1,12 +1,15 @@
-use std::{str::FromStr, sync::Arc};
+use std::{collections::{BTreeMap, HashMap}, io::{IsTerminal, Read, Write}, num::NonZeroUsize, str::FromStr, sync::{Arc, Mutex}, time::Duration};
 
-use clap::Parser;
-use ethers_core::types::{NameOrAddress, BlockId};
-use ethers_providers::{Http, Middleware, Provider};
-use evm_proxy_tools::ProxyDispatch;
+use clap::{Parser, Subcommand, ValueEnum};
+use ethers_core::types::{NameOrAddress, BlockId, BlockNumber};
+use ethers_providers::{Http, Middleware, Provider, Ws};
+use futures::{stream, Stream, StreamExt};
+use evm_proxy_tools::{Finding, ImplementationChange, Inspector, InspectorError, NoProgress, ProxyReport, ProgressReporter, ProxyDetectionResult, ProxyDispatch, ProxyImplementation, ProxyReadError, ProxyType, RateTracker, ReadConfig, TrustSet, UpgradeEventHistory, UpgradeSignal};
+use serde::Serialize;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
-use evm_proxy_tools::utils::EARGlue;
+use evm_proxy_tools::{AnalysisProfile, DetectOutcome, DetectorConfig};
+use evm_proxy_tools::utils::{normalize_code_input, CodeInput};
 
 
 /// A `clap` `value_parser` that removes a `0x` prefix if it exists
@@ -14,16 +17,111 @@ pub fn strip_0x_prefix(s: &str) -> Result<String, &'static str> {
     Ok(s.strip_prefix("0x").unwrap_or(s).to_string())
 }
 
+/// A `clap` `value_parser` for block ranges written `from..to`, both included
+fn parse_block_range(s: &str) -> Result<(u64, u64), String> {
+    let (from, to) = s.split_once("..").ok_or("expected a range like 100..200")?;
+    let (from, to) = (from.parse::<u64>().map_err(|e| e.to_string())?, to.parse::<u64>().map_err(|e| e.to_string())?);
+    if from > to {
+	return Err(format!("the range {} ends before it starts", s));
+    }
+    Ok((from, to))
+}
+
+/// A `clap` `value_parser` for durations written with a unit, `500ms`, `12s`, `1m` or `1h`,
+/// seconds without one
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let (value, unit) = s.find(|c: char| !c.is_ascii_digit()).map_or((s, ""), |unit| s.split_at(unit));
+    let value: u64 = value.parse().map_err(|_| format!("expected a duration like 12s, got {}", s))?;
+    match unit {
+	"ms" => Ok(Duration::from_millis(value)),
+	"" | "s" => Ok(Duration::from_secs(value)),
+	"m" => Ok(Duration::from_secs(value * 60)),
+	"h" => Ok(Duration::from_secs(value * 3600)),
+	_ => Err(format!("unknown unit {} in {}, expected ms, s, m or h", unit, s)),
+    }
+}
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Lines for humans.
+    Text,
+    /// JSON documents.
+    Json,
+}
+
+/// What `proxy-tools` does instead of analysing an address.
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// List the selectors a diamond added, removed and routed to another facet between two
+    /// blocks. Everything is added if it wasn't a diamond yet.
+    Diff {
+        /// The diamond's address.
+        address: alloy_primitives::Address,
+
+        /// The block to compare from, a number or a tag.
+        #[clap(long)]
+        from_block: BlockId,
+
+        /// The block to compare to, a number or a tag.
+        #[clap(long)]
+        to_block: BlockId,
+    },
+    /// Print the implementation of a proxy, then a line each time it changes. Over a websocket
+    /// URL, proxies that log their upgrades are read again on each upgrade log instead of
+    /// being polled.
+    Watch {
+        /// The proxy's address.
+        address: alloy_primitives::Address,
+
+        /// Time between two polls, e.g. `500ms`, `12s` or `1m`.
+        #[clap(long, default_value = "12s", value_parser = parse_interval)]
+        interval: Duration,
+
+        /// Command run with `sh -c` on each change, with `PROXY_ADDRESS`, `PROXY_BLOCK`,
+        /// `PROXY_PREVIOUS` and `PROXY_CURRENT` set.
+        #[clap(long)]
+        exec: Option<String>,
+    },
+}
+
 /// CLI arguments for `proxy-tools`.
 #[derive(Debug, Clone, Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 // #[command(
 //     help_template = "{author-with-newline} {about-section}Version: {version} \n {usage-heading} {usage} \n {all-args} {tab}"
 // )]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The contract address.
-    #[clap(value_parser = NameOrAddress::from_str)]
-    address: NameOrAddress,
+    #[clap(value_parser = NameOrAddress::from_str, required_unless_present_any = ["code", "code_hex", "batch"])]
+    address: Option<NameOrAddress>,
+
+    /// Analyse this bytecode instead of an address, as hex, `@file` with the hex or `-` to read
+    /// it from stdin. Creation code is recognized and deployed first. Only the detection runs,
+    /// no RPC needed, so implementations are only known when the code holds them.
+    #[clap(long, conflicts_with = "address")]
+    code: Option<String>,
+
+    /// `--code` with hex only, for code that can't be mistaken for a file.
+    #[clap(long, conflicts_with_all = ["address", "code"])]
+    code_hex: Option<String>,
+
+    /// Analyse every address listed in this file, or stdin for `-`, one per line with blank
+    /// lines and `#` comments skipped. A line is printed per address as its analysis completes,
+    /// failures included.
+    #[clap(long, conflicts_with_all = ["address", "code", "code_hex"])]
+    batch: Option<String>,
+
+    /// Addresses analysed at once with `--batch`.
+    #[clap(long, default_value_t = NonZeroUsize::new(evm_proxy_tools::MAX_CONCURRENT_ANALYSES).unwrap())]
+    concurrency: NonZeroUsize,
+
+    /// Count the addresses of each proxy type once a `--batch` is done.
+    #[clap(long, requires = "batch")]
+    summary: bool,
 
     /// The block height to query at.
     ///
@@ -31,9 +129,532 @@ pub struct Args {
     #[clap(long, short)]
     block: Option<BlockId>,
 
-    /// The RPC endpoint.
-    #[clap(short = 'r', long = "rpc-url", env = "ETH_RPC_URL")]
-    pub url: String,
+    /// Report how recently the implementation was deployed relative to the proxy, flagging
+    /// implementations deployed within this many blocks after the proxy.
+    #[clap(long)]
+    freshness_window: Option<u64>,
+
+    /// Recover the implementation's selectors, fallback/receive handlers and payable hints
+    /// from its dispatcher.
+    #[clap(long)]
+    interface: bool,
+
+    /// Ask the implementation for `proxiableUUID()`, to tell UUPS proxies from other storage
+    /// proxies, and advise where their upgrades are logged.
+    #[clap(long)]
+    classify: bool,
+
+    /// List the implementations the proxy pointed to between two blocks, `from..to`, with the
+    /// block each was installed in.
+    #[clap(long, value_parser = parse_block_range)]
+    history: Option<(u64, u64)>,
+
+    /// Blocks between two reads of the implementation with `--history`, upgrades undone in
+    /// fewer blocks are missed unless logged by an EIP-1967 proxy.
+    #[clap(long, default_value_t = 10_000)]
+    history_step: u64,
+
+    /// Analysis profile to detect with, `latest` or a frozen one like `v1-frozen` that keeps
+    /// classifying as the release that introduced it.
+    #[clap(long, default_value = "latest")]
+    profile: String,
+
+    /// File of candidate namespaces, one per line, to name detected custom slots after: a slot
+    /// is named by the namespace it derives from like EIP-1967 slots do, `keccak256(ns) - 1`.
+    #[clap(long)]
+    namespaces: Option<String>,
+
+    /// Run every detector instead of stopping at the first match, and print what each found,
+    /// to spot code that looks like one proxy but runs like another.
+    #[clap(long)]
+    all_strategies: bool,
+
+    /// How to print the results. `json` prints a single document with the address, the
+    /// detection, the implementations, the admin and the addresses calls go through, leaving
+    /// the other reports out, and errors as `{"error": ...}` on stderr. With `--batch`, a
+    /// document per line, failures being `{"address": ..., "error": ...}`.
+    #[clap(long, value_enum, default_value_t = Format::Text, global = true)]
+    format: Format,
+
+    /// Same as `--format json`.
+    #[clap(long, conflicts_with = "format", global = true)]
+    json: bool,
+
+    /// Proxies to go through when implementations are proxies themselves, past which the
+    /// analysis fails.
+    #[clap(long, default_value_t = evm_proxy_tools::DEFAULT_MAX_DEPTH, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_depth: usize,
+
+    /// Don't display the progress of long operations.
+    #[clap(long, short)]
+    quiet: bool,
+
+    /// The RPC endpoint, needed unless analysing code.
+    #[clap(short = 'r', long = "rpc-url", env = "ETH_RPC_URL", global = true)]
+    pub url: Option<String>,
+}
+
+impl Args {
+    fn format(&self) -> Format {
+	if self.json { Format::Json } else { self.format }
+    }
+}
+
+/// What `--format json` prints for an address, or for code without `address` and `block`.
+#[derive(Serialize)]
+struct JsonReport {
+    address: Option<alloy_primitives::Address>,
+    is_proxy: bool,
+    proxy_type: Option<evm_proxy_tools::ProxyType>,
+    dispatch: Option<ProxyDispatch>,
+    /// Empty if they couldn't be read, or only from a node for code.
+    implementations: Vec<alloy_primitives::Address>,
+    admin: Option<alloy_primitives::Address>,
+    /// The addresses a call goes through before the implementations: the proxy, then its
+    /// beacon or resolver, then the same for implementations that are proxies themselves.
+    chain: Vec<alloy_primitives::Address>,
+    block: Option<BlockId>,
+    /// Why the implementations couldn't be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl JsonReport {
+    fn new(address: Option<alloy_primitives::Address>, block: Option<BlockId>, detection: Option<&ProxyDetectionResult>) -> Self {
+	let implementations = match detection.map(|detection| &detection.dispatch) {
+	    Some(ProxyDispatch::Static(implementation) | ProxyDispatch::StaticWithArgs(implementation, _)) => vec![*implementation],
+	    _ => Vec::new(),
+	};
+	let chain = address.into_iter().chain(match detection.map(|detection| &detection.dispatch) {
+	    Some(ProxyDispatch::External(resolver, _)) => Some(*resolver),
+	    _ => None,
+	}).collect();
+	Self {
+	    address,
+	    is_proxy: detection.is_some(),
+	    proxy_type: detection.map(|detection| detection.proxy_type),
+	    dispatch: detection.map(|detection| detection.dispatch.clone()),
+	    implementations,
+	    admin: None,
+	    chain,
+	    block,
+	    error: None,
+	}
+    }
+
+    /// The report of an analysed address, with the implementations read.
+    fn from_report(report: &ProxyReport) -> Self {
+	let mut json = Self::new(Some(report.address), report.block, report.detection.as_ref());
+	json.admin = report.admin;
+	json.chain.extend(report.beacon);
+	match &report.implementation {
+	    Ok(implementation) => json.implementations = implementation.to_vec(),
+	    Err(ProxyReadError::UnknownProxy) => {},
+	    Err(e) => json.error = Some(e.to_string()),
+	}
+	json
+    }
+
+    /// The report of the first proxy of `chain`, the implementations being those of the last.
+    fn from_chain(chain: &[ProxyReport]) -> Self {
+	let mut json = Self::from_report(&chain[0]);
+	for hop in &chain[1..] {
+	    let hop = Self::from_report(hop);
+	    json.chain.extend(hop.chain);
+	    (json.implementations, json.error) = (hop.implementations, hop.error);
+	}
+	json
+    }
+
+    fn print(&self) {
+	println!("{}", serde_json::to_string_pretty(self).expect("reports serialize"));
+    }
+}
+
+/// Prints `message` as an error on stderr, as `{"error": ...}` with `--format json`, and exits.
+fn fail(format: Format, message: impl std::fmt::Display) -> ! {
+    match format {
+	Format::Text => eprintln!("error: {}", message),
+	Format::Json => eprintln!("{}", serde_json::json!({ "error": message.to_string() })),
+    }
+    std::process::exit(1);
+}
+
+/// Analyses the addresses listed at `path` with `concurrency` analyses at once, printing each
+/// result as it completes and, with `summary`, the count of each proxy type at the end.
+async fn analyse_batch(inspector: &Inspector<Provider<Http>>, path: &str, block: Option<BlockId>, concurrency: usize, summary: bool, format: Format) {
+    let lines = read_lines(path).unwrap_or_else(|e| fail(format, format!("couldn't read {}: {}", path, e)));
+    let mut results = stream::iter(lines)
+	.map(|line| async move {
+	    let result = match alloy_primitives::Address::from_str(&line) {
+		Ok(address) => inspector.analyze_at(address, block).await.map_err(|e| e.to_string()),
+		Err(e) => Err(format!("invalid address: {}", e)),
+	    };
+	    (line, result)
+	})
+	.buffer_unordered(concurrency);
+    let mut counts = BTreeMap::<String, usize>::new();
+    while let Some((line, result)) = results.next().await {
+	// Counted by the name of the type for text, by its identifier as in the records for JSON
+	let kind = match (&result, format) {
+	    (Err(_), _) => "error".to_string(),
+	    (Ok(report), Format::Text) => report.detection.as_ref().map_or(ProxyType::NoProxy, |detection| detection.proxy_type).to_string(),
+	    (Ok(report), Format::Json) => format!("{:?}", report.detection.as_ref().map_or(ProxyType::NoProxy, |detection| detection.proxy_type)),
+	};
+	*counts.entry(kind).or_default() += 1;
+	match (format, result) {
+	    (Format::Text, Ok(report)) => match &report.detection {
+		Some(detection) => println!("{}: {} {:?}, implementation {:?}", report.address, detection.proxy_type, detection.dispatch, report.implementation),
+		None => println!("{}: not a proxy", report.address),
+	    },
+	    (Format::Text, Err(e)) => println!("{}: error: {}", line, e),
+	    (Format::Json, Ok(report)) => println!("{}", serde_json::to_string(&JsonReport::from_report(&report)).expect("reports serialize")),
+	    (Format::Json, Err(e)) => println!("{}", serde_json::json!({ "address": line, "error": e })),
+	}
+    }
+    if summary {
+	match format {
+	    Format::Text => {
+		println!("summary:");
+		for (kind, count) in &counts {
+		    println!("  {}: {}", kind, count);
+		}
+	    },
+	    Format::Json => println!("{}", serde_json::json!({ "summary": counts })),
+	}
+    }
+}
+
+/// `block` as given on the command line: its number, tag or hash.
+fn block_label(block: &BlockId) -> String {
+    match block {
+	BlockId::Number(BlockNumber::Number(number)) => number.to_string(),
+	BlockId::Number(tag) => tag.to_string(),
+	BlockId::Hash(hash) => format!("{:#x}", hash),
+    }
+}
+
+/// The facets of `address` at `block`, none if it wasn't a diamond or had no code yet.
+async fn facets_at(inspector: &Inspector<Provider<Http>>, address: alloy_primitives::Address, block: BlockId, format: Format) -> ProxyImplementation {
+    let report = match inspector.analyze_at(address, Some(block)).await {
+	Ok(report) => report,
+	Err(InspectorError::Read(ProxyReadError::NoCode(_))) => return ProxyImplementation::Facets(HashMap::new()),
+	Err(e) => fail(format, format!("couldn't analyse the address at block {}: {}", block_label(&block), e)),
+    };
+    match report.implementation {
+	Ok(facets @ ProxyImplementation::Facets(_)) => facets,
+	Ok(_) | Err(ProxyReadError::UnknownProxy) => ProxyImplementation::Facets(HashMap::new()),
+	Err(e) => fail(format, format!("couldn't read the facets at block {}: {}", block_label(&block), e)),
+    }
+}
+
+/// Prints the selectors the diamond at `address` routes differently at `to_block` than at
+/// `from_block`, as a table or a JSON document.
+async fn diff_facets_between(inspector: &Inspector<Provider<Http>>, address: alloy_primitives::Address, from_block: BlockId, to_block: BlockId, format: Format) {
+    let before = facets_at(inspector, address, from_block, format).await;
+    let after = facets_at(inspector, address, to_block, format).await;
+    let diff = evm_proxy_tools::diff_facets(&before, &after);
+    match format {
+	Format::Text => {
+	    println!("facets of {} from block {} to {}:", address, block_label(&from_block), block_label(&to_block));
+	    println!("  {:<8}  {:<10}  {:<42}  function", "change", "selector", "facet");
+	    let label = |selector: &evm_proxy_tools::Selector| selector.label().unwrap_or("?");
+	    for (selector, facet) in &diff.added_selectors {
+		println!("  {:<8}  {}  {:<42}  {}", "added", selector, facet.to_string(), label(selector));
+	    }
+	    for (selector, facet) in &diff.removed_selectors {
+		println!("  {:<8}  {}  {:<42}  {}", "removed", selector, facet.to_string(), label(selector));
+	    }
+	    for (selector, (before, after)) in &diff.replaced_selectors {
+		println!("  {:<8}  {}  {:<42}  {} (was {})", "replaced", selector, after.to_string(), label(selector), before);
+	    }
+	    if diff.added_selectors.is_empty() && diff.removed_selectors.is_empty() && diff.replaced_selectors.is_empty() {
+		println!("  no selector changed");
+	    }
+	},
+	Format::Json => {
+	    let routed = |selectors: &BTreeMap<evm_proxy_tools::Selector, alloy_primitives::Address>| selectors.iter()
+		.map(|(selector, facet)| serde_json::json!({ "selector": selector, "function": selector.label(), "facet": facet }))
+		.collect::<Vec<_>>();
+	    let replaced: Vec<_> = diff.replaced_selectors.iter()
+		.map(|(selector, (before, after))| serde_json::json!({ "selector": selector, "function": selector.label(), "before": before, "after": after }))
+		.collect();
+	    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+		"address": address,
+		"from_block": from_block,
+		"to_block": to_block,
+		"added_facets": diff.added,
+		"removed_facets": diff.removed,
+		"added": routed(&diff.added_selectors),
+		"removed": routed(&diff.removed_selectors),
+		"replaced": replaced,
+	    })).expect("reports serialize"));
+	},
+    }
+}
+
+/// `implementation` in a line: the address, the addresses or the size of the facet map.
+fn implementation_label(implementation: &ProxyImplementation) -> String {
+    match implementation {
+	ProxyImplementation::Single(address) => address.to_string(),
+	ProxyImplementation::Multiple(addresses) => format!("[{}]", addresses.iter().map(|address| address.to_string()).collect::<Vec<_>>().join(", ")),
+	ProxyImplementation::Facets(facets) => format!("{} selectors on {} facets", facets.len(), implementation.to_vec().len()),
+    }
+}
+
+/// Prints the changes of `changes` as they come, running `exec` on each after the first.
+async fn print_changes(changes: impl Stream<Item = Result<ImplementationChange, ProxyReadError>>, address: alloy_primitives::Address, exec: Option<&str>, format: Format) {
+    futures::pin_mut!(changes);
+    while let Some(change) = changes.next().await {
+	let change = match change {
+	    Ok(change) => change,
+	    Err(e) => {
+		match format {
+		    Format::Text => eprintln!("warning: {}", e),
+		    Format::Json => eprintln!("{}", serde_json::json!({ "address": address, "error": e.to_string() })),
+		}
+		continue;
+	    },
+	};
+	match (format, &change.previous) {
+	    (Format::Text, None) => println!("watching {}, implementation {} at block {}", address, implementation_label(&change.current), change.block),
+	    (Format::Text, Some(previous)) => println!("block {}: {} -> {}", change.block, implementation_label(previous), implementation_label(&change.current)),
+	    (Format::Json, _) => println!("{}", serde_json::json!({ "address": address, "block": change.block, "previous": change.previous, "current": change.current })),
+	}
+	if let (Some(exec), Some(previous)) = (exec, &change.previous) {
+	    let mut command = std::process::Command::new("sh");
+	    command.arg("-c").arg(exec)
+		.env("PROXY_ADDRESS", address.to_string())
+		.env("PROXY_BLOCK", change.block.to_string())
+		.env("PROXY_PREVIOUS", implementation_label(previous))
+		.env("PROXY_CURRENT", implementation_label(&change.current));
+	    // Waiting on the hook would stall the runtime the stream is polled on
+	    match tokio::task::spawn_blocking(move || command.status()).await.expect("the hook doesn't panic") {
+		Ok(status) if !status.success() => eprintln!("warning: --exec exited with {}", status),
+		Ok(_) => {},
+		Err(e) => eprintln!("warning: couldn't run --exec: {}", e),
+	    }
+	}
+    }
+}
+
+/// Follows the implementation of `address` on the node at `url`, over its upgrade logs for
+/// websocket URLs when the upgrades are logged, polling every `interval` otherwise.
+async fn watch(url: &str, address: alloy_primitives::Address, interval: Duration, exec: Option<&str>, config: &DetectorConfig, format: Format) {
+    let detect = |code: &[u8]| evm_proxy_tools::detect_proxy(code, config).unwrap_or_else(|| fail(format, format!("{} isn't a proxy", address)));
+    if url.starts_with("ws://") || url.starts_with("wss://") {
+	let rpc = Arc::new(Provider::<Ws>::connect(url).await.unwrap_or_else(|e| fail(format, format!("couldn't connect to {}: {}", url, e))));
+	let code = rpc.get_code(evm_proxy_tools::utils::raddress_to_h160(&address), None).await.unwrap_or_else(|e| fail(format, format!("couldn't read the code: {}", e)));
+	let detection = detect(&code);
+	let advice = evm_proxy_tools::get_event_monitoring_advice(rpc.as_ref(), &address, detection.proxy_type, &detection.dispatch, None).await
+	    .unwrap_or_else(|e| fail(format, format!("couldn't find where upgrades are logged: {}", e)));
+	match advice.filter(|advice| advice.signals.iter().all(|signal| matches!(signal, UpgradeSignal::Events { .. }))) {
+	    Some(advice) => print_changes(evm_proxy_tools::watch_implementation_logs(rpc, address, detection.dispatch, &advice), address, exec, format).await,
+	    None => print_changes(evm_proxy_tools::watch_implementation(rpc, address, detection.dispatch, interval), address, exec, format).await,
+	}
+    } else {
+	let rpc = Arc::new(Provider::<Http>::try_from(url).unwrap_or_else(|e| fail(format, format!("invalid rpc url: {}", e))));
+	let code = rpc.get_code(evm_proxy_tools::utils::raddress_to_h160(&address), None).await.unwrap_or_else(|e| fail(format, format!("couldn't read the code: {}", e)));
+	let detection = detect(&code);
+	print_changes(evm_proxy_tools::watch_implementation(rpc, address, detection.dispatch, interval), address, exec, format).await;
+    }
+}
+
+/// Renders progress on a single, rewritten, line of stderr.
+struct TerminalProgress {
+    rate: Mutex<RateTracker>,
+}
+
+impl TerminalProgress {
+    fn new() -> Self {
+	Self { rate: Mutex::new(RateTracker::new(Duration::from_secs(5))) }
+    }
+}
+
+impl ProgressReporter for TerminalProgress {
+    fn on_progress(&self, completed: u64, total_hint: Option<u64>, stage: &str) {
+	let mut rate = self.rate.lock().unwrap();
+	rate.record(completed);
+	let mut line = match total_hint {
+	    Some(total) => format!("{}: {}/{}", stage, completed, total),
+	    None => format!("{}: {}", stage, completed),
+	};
+	if let Some(throughput) = rate.throughput() {
+	    line.push_str(&format!(" ({:.1}/s", throughput));
+	    if let Some(eta) = total_hint.and_then(|total| rate.eta(total)) {
+		line.push_str(&format!(", eta {}s", eta.as_secs()));
+	    }
+	    line.push(')');
+	}
+	let mut stderr = std::io::stderr().lock();
+	let _ = write!(stderr, "\r\x1b[2K{}", line);
+	let _ = stderr.flush();
+    }
+}
+
+impl TerminalProgress {
+    /// Erases the progress line before regular output resumes.
+    fn clear(&self) {
+	let _ = write!(std::io::stderr(), "\r\x1b[2K");
+    }
+}
+
+/// The hex given with `--code`: itself, the content of the file after `@` or stdin for `-`.
+fn read_code_arg(arg: &str, format: Format) -> String {
+    if arg == "-" {
+	let mut raw = String::new();
+	std::io::stdin().read_to_string(&mut raw).unwrap_or_else(|e| fail(format, format!("couldn't read stdin: {}", e)));
+	return raw;
+    }
+    match arg.strip_prefix('@') {
+	Some(path) => std::fs::read_to_string(path).unwrap_or_else(|e| fail(format, format!("couldn't read {}: {}", path, e))),
+	None => arg.to_string(),
+    }
+}
+
+/// Detects the proxy in the bytecode given with `--code` or `--code-hex`, no RPC needed.
+fn analyse_code(raw: &str, config: &DetectorConfig, all_strategies: bool, format: Format) {
+    let input = normalize_code_input(raw).unwrap_or_else(|e| fail(format, e));
+
+    if format == Format::Json {
+	let result = match &input {
+	    CodeInput::LikelyCreationCode { initcode, .. } => evm_proxy_tools::detect_creation_code(initcode, config),
+	    CodeInput::RuntimeCode(code) | CodeInput::Unknown(code) => evm_proxy_tools::detect_proxy(code, config),
+	};
+	return JsonReport::new(None, None, result.as_ref()).print();
+    }
+    if let Some(DetectOutcome::CodeTooSmall(len)) = DetectOutcome::for_code_size(input.bytes()) {
+	println!("Code is only {} bytes, too small to be a proxy", len);
+	return;
+    }
+    let result = match &input {
+	CodeInput::LikelyCreationCode { initcode, runtime } => {
+	    eprintln!("notice: input looks like creation code (runtime at bytes {}..{}), analysing the code it deploys", runtime.start, runtime.end);
+	    evm_proxy_tools::detect_creation_code(initcode, config)
+	},
+	CodeInput::Unknown(code) => {
+	    eprintln!("warning: input doesn't start with a valid opcode, it may not be bytecode");
+	    evm_proxy_tools::detect_proxy(code, config)
+	},
+	CodeInput::RuntimeCode(code) => evm_proxy_tools::detect_proxy(code, config),
+    };
+    match result {
+	Some(result) => {
+	    println!("proxy type: {} {:?}", result.proxy_type, result.dispatch);
+	    report_slot_name(&result);
+	    if let ProxyDispatch::Static(implementation) | ProxyDispatch::StaticWithArgs(implementation, _) = &result.dispatch {
+		println!("proxy impl: {}", implementation);
+	    }
+	},
+	None => println!("Couldn't identify a proxy in that code"),
+    }
+    if all_strategies {
+	let runtime = match &input {
+	    CodeInput::LikelyCreationCode { initcode, runtime } => &initcode[runtime.clone()],
+	    CodeInput::RuntimeCode(code) | CodeInput::Unknown(code) => code,
+	};
+	report_strategies(runtime, config);
+    }
+}
+
+/// Prints what every detector makes of `code` and the result they agree on.
+fn report_strategies(code: &[u8], config: &DetectorConfig) {
+    let opinions = evm_proxy_tools::detect_all(code, config);
+    println!("strategies:");
+    for (name, result) in &opinions {
+	println!("  {}: {} {:?} by {:?}", name, result.proxy_type, result.dispatch, result.rule);
+    }
+    if let Some(consensus) = evm_proxy_tools::consensus(&opinions) {
+	println!("consensus: {} {:?}", consensus.proxy_type, consensus.dispatch);
+	for finding in consensus.findings.iter().filter(|finding| matches!(finding, Finding::StrategyConflict { .. })) {
+	    println!("  conflicts with {:?}", finding);
+	}
+    }
+}
+
+/// The lines of the file at `path`, or of stdin for `-`, trimmed, without blank lines and `#`
+/// comments.
+fn read_lines(path: &str) -> std::io::Result<Vec<String>> {
+    let content = match path {
+	"-" => {
+	    let mut content = String::new();
+	    std::io::stdin().read_to_string(&mut content)?;
+	    content
+	},
+	path => std::fs::read_to_string(path)?,
+    };
+    Ok(content.lines()
+	.map(|line| line.split_once('#').map_or(line, |(line, _)| line).trim())
+	.filter(|line| !line.is_empty())
+	.map(str::to_string)
+	.collect())
+}
+
+/// `slot` in hex, followed by its name if well known.
+fn display_slot(slot: &alloy_primitives::U256) -> String {
+    match evm_proxy_tools::slot_name(slot) {
+	Some(name) => format!("{:#x} ({})", slot, name),
+	None => format!("{:#x}", slot),
+    }
+}
+
+fn report_slot_name(result: &ProxyDetectionResult) {
+    if let (Some(namespace), ProxyDispatch::Storage(slot, _)) = (&result.slot_namespace, &result.dispatch) {
+	println!("custom slot {:#x} is namespace \"{}\"", slot, namespace);
+    }
+    if let (Some(slot_preimage), ProxyDispatch::Storage(slot, _) | ProxyDispatch::Beacon(slot)) = (&result.slot_preimage, &result.dispatch) {
+	let preimage = match slot_preimage.name() {
+	    Some(name) => format!("\"{}\"", name),
+	    None => slot_preimage.preimage.to_string(),
+	};
+	println!("slot {:#x} is keccak256({}){}", slot, preimage, if slot_preimage.minus_one { " - 1" } else { "" });
+    }
+    if let (Some(name), ProxyDispatch::Storage(slot, _) | ProxyDispatch::Beacon(slot)) = (&result.slot_name, &result.dispatch) {
+	println!("slot {:#x} is known as \"{}\"", slot, name);
+    }
+}
+
+/// Prints every address the proxy trusts, one per role.
+fn report_trust_set(trust: &TrustSet) {
+    if trust.entries.is_empty() {
+	return;
+    }
+    println!("trust set:");
+    for party in &trust.entries {
+	let mutable_by = party.mutable_by.map(|address| format!(", replaceable by {}", address)).unwrap_or_default();
+	println!("  {} {:?} via {:?}{}", party.address, party.role, party.mechanism, mutable_by);
+    }
+}
+
+/// Prints where a monitor has to watch for upgrades of `proxy`, and whether its last upgrade
+/// was logged there when the node has the history.
+async fn report_event_monitoring(rpc: &Arc<Provider<Http>>, proxy: &alloy_primitives::Address, proxy_type: evm_proxy_tools::ProxyType, dispatch: &ProxyDispatch, implementation: &alloy_primitives::Address, block: Option<BlockId>) {
+    let advice = match evm_proxy_tools::get_event_monitoring_advice(rpc.as_ref(), proxy, proxy_type, dispatch, block).await {
+	Ok(Some(advice)) => advice,
+	Ok(None) => return,
+	Err(e) => return println!("couldn't build the event monitoring advice: {}", e),
+    };
+    for signal in &advice.signals {
+	match signal {
+	    UpgradeSignal::Events { address, topics } => println!("event monitoring advice: watch logs of {} with topic0 in {:?}", address, topics),
+	    UpgradeSignal::StorageSlot { address, slot } => println!("event monitoring advice: no standard event, poll slot {:#x} of {}", slot, address),
+	}
+    }
+    let head = match evm_proxy_tools::resolve_block_number(rpc.as_ref(), block).await {
+	Ok(head) => head,
+	Err(e) => return println!("couldn't resolve the block: {}", e),
+    };
+    let history = match evm_proxy_tools::find_last_upgrade_block(rpc.clone(), proxy, dispatch, head, evm_proxy_tools::DEFAULT_SEARCH_BUDGET).await {
+	Ok(Some(upgrade)) => evm_proxy_tools::check_upgrade_events(rpc.as_ref(), &advice, implementation, upgrade).await.map(|history| (upgrade, history)),
+	Ok(None) => return,
+	Err(e) => return println!("couldn't find the last upgrade: {}", e),
+    };
+    match history {
+	Ok((_, UpgradeEventHistory::AtAdvisedAddress)) => {},
+	Ok((upgrade, UpgradeEventHistory::ElsewhereAt(emitter))) => println!("warning: the upgrade at block {} was logged by {}, not where the advice watches", upgrade, emitter),
+	Ok((upgrade, UpgradeEventHistory::Missing)) => println!("warning: the upgrade at block {} logged no standard upgrade event", upgrade),
+	Err(e) => println!("couldn't check the upgrade events: {}", e),
+    }
 }
 
 #[tokio::main]
@@ -43,46 +664,198 @@ async fn main() {
 
     FmtSubscriber::builder()
         .with_env_filter(filter)
+        .with_writer(std::io::stderr)
         .init();
 
     let args = Args::parse();
+    let format = args.format();
 
-    println!("{:?}", args);
+    if format == Format::Text {
+	println!("{:?}", args);
+    }
 
-    // let url = Url::from(args.url).unwrap();
-    let rpc = Arc::new(Provider::<Http>::try_from(&args.url).expect("failed to create rpc connection with url"));
-    // let code = rpc.get_code(args.address, args.block).await;
+    let profile = AnalysisProfile::builtin(&args.profile).unwrap_or_else(|e| fail(format, format!("{} (built-in profiles: {})", e, AnalysisProfile::BUILTIN.join(", "))));
+    let mut config = profile.config.clone();
+    if let Some(path) = &args.namespaces {
+	config.namespaces = read_lines(path).unwrap_or_else(|e| fail(format, format!("couldn't read {}: {}", path, e)));
+    }
 
-    let mut address = args.address.clone();
+    let code = args.code.as_deref().map(|arg| read_code_arg(arg, format)).or_else(|| args.code_hex.clone());
+    if let Some(code) = &code {
+	analyse_code(code, &config, args.all_strategies, format);
+	return;
+    }
 
-    loop {
-	println!("Analysing address {:?}", address.as_address().unwrap());
+    let terminal = (!args.quiet && format == Format::Text && std::io::stderr().is_terminal()).then(TerminalProgress::new);
+    let progress: &dyn ProgressReporter = match &terminal {
+	Some(terminal) => terminal,
+	None => &NoProgress,
+    };
 
-	let rpc = rpc.clone();
-	let code = rpc.get_code(address.clone(), args.block).await.expect("failed to find address at block");
-	// println!("code: {:?}", code);
+    let url = args.url.as_deref().unwrap_or_else(|| fail(format, "an RPC URL is required, give --rpc-url or set ETH_RPC_URL"));
+    if let Some(Command::Watch { address, interval, exec }) = &args.command {
+	watch(url, *address, *interval, exec.as_deref(), &config, format).await;
+	return;
+    }
+
+    let rpc = Provider::<Http>::try_from(url).unwrap_or_else(|e| fail(format, format!("invalid rpc url: {}", e)));
+    let inspector = Inspector::from_provider(rpc, config.clone()).await.unwrap_or_else(|e| fail(format, e));
+    let rpc = inspector.provider().clone();
+
+    if let Some(Command::Diff { address, from_block, to_block }) = args.command {
+	diff_facets_between(&inspector, address, from_block, to_block, format).await;
+	return;
+    }
+
+    if let Some(path) = &args.batch {
+	analyse_batch(&inspector, path, args.block, args.concurrency.get(), args.summary, format).await;
+	return;
+    }
+
+    let address = args.address.clone().expect("address is required without --code");
+    let Some(h160) = address.as_address() else { fail(format, "ENS names aren't supported, give an address") };
 
-	if code.is_empty() {
+    if format == Format::Text {
+	println!("Analysing address {:?}", h160);
+    }
+
+    let raddress = evm_proxy_tools::utils::h160_to_b160(h160);
+    let chain = match inspector.follow_at(raddress, args.block, args.max_depth).await {
+	Ok(chain) => chain,
+	Err(InspectorError::Read(ProxyReadError::NoCode(_))) if format == Format::Text => {
 	    println!("Address doesn't have a contract");
 	    std::process::exit(1);
-	}
+	},
+	Err(e) => fail(format, format!("couldn't analyse the address: {}", e)),
+    };
 
-	let proxy_type = evm_proxy_tools::get_proxy_type(&code);
+    let report = &chain[0];
+    if format == Format::Json {
+	let mut json = JsonReport::from_chain(&chain);
+	let error = json.error.take();
+	json.print();
+	if let Some(e) = error {
+	    fail(format, format!("couldn't read the implementation: {}", e));
+	}
+	return;
+    }
 
-	println!("proxy type: {:?}", proxy_type);
-	if let Some((_proxy_type, proxy_dispatch)) = proxy_type {
-	    if let ProxyDispatch::External(ext_address, _call) = proxy_dispatch {
-		println!("going into proxy child");
-		address = ext_address.convert();
-		continue;
-	    } else {
-		let raddress = evm_proxy_tools::utils::h160_to_b160(&address.as_address().unwrap());
-		let proxy_impl = evm_proxy_tools::get_proxy_implementation(rpc, &raddress, &proxy_dispatch).await.expect("somehow failed to");
-		println!("proxy impl: {:?}", proxy_impl);
+    if let Some(result) = &report.detection {
+	println!("proxy type: {} {:?}", result.proxy_type, result.dispatch);
+	report_slot_name(result);
+    }
+    if args.all_strategies {
+	let code = rpc.get_code(address.clone(), args.block).await.expect("failed to find address at block");
+	report_strategies(&code, &config);
+    }
+    if let Some(detection) = &report.detection {
+	let ProxyDetectionResult { proxy_type, dispatch: proxy_dispatch, admin_slot, upgradeable_slots, facet_slots, .. } = detection.clone();
+	println!("proxy impl: {:?}", report.implementation);
+	if chain.len() > 1 {
+	    println!("proxy chain:");
+	    for hop in &chain {
+		let proxy_type = hop.detection.as_ref().map(|detection| detection.proxy_type).unwrap_or(ProxyType::NoProxy);
+		println!("  {}: {}", hop.address, proxy_type);
+	    }
+	    println!("  implementation: {:?}", chain[chain.len() - 1].implementation);
+	}
+	let Ok(proxy_impl) = &report.implementation else { return };
+	match evm_proxy_tools::verify_implementation(rpc.as_ref(), proxy_impl, args.block, &ReadConfig::default()).await {
+	    Ok(resolved) => for implementation in resolved.iter().filter(|implementation| !implementation.has_code) {
+		println!("warning: implementation {} has no code", implementation.address);
+	    },
+	    Err(e) => println!("warning: {}", e),
+	}
+	if admin_slot.is_some() {
+	    match report.admin {
+		Some(address) => println!("proxy admin: {}", address),
+		None => println!("proxy admin: none, upgraded through the implementation (UUPS)"),
+	    }
+	}
+	report_trust_set(&report.trust_set);
+	for slot in &upgradeable_slots {
+	    println!("upgraded through the proxy: upgradeTo writes slot {}", display_slot(slot));
+	}
+	for (selector, slot) in &facet_slots {
+	    println!("facet of {}: loaded from slot {}", selector, display_slot(slot));
+	}
+	for finding in report.findings() {
+	    if let Finding::SelfReportMismatch { slot_value, getter_value } = finding {
+		println!("warning: proxy reports implementation {} but its slot holds {}", getter_value, slot_value);
+	    }
+	}
+	if let (true, ProxyImplementation::Single(impl_address)) = (args.classify, proxy_impl) {
+	    let upgradeability = match evm_proxy_tools::probe_upgradeability(rpc.as_ref(), proxy_type, impl_address, args.block).await {
+		Ok((upgradeability, probe)) => {
+		    println!("upgradeability: {}", upgradeability);
+		    if let Some(probe) = probe.filter(|probe| !probe.is_success()) {
+			println!("proxiableUUID() probe: {:?}", probe);
+		    }
+		    upgradeability
+		},
+		Err(e) => {
+		    println!("couldn't classify the upgradeability: {}", e);
+		    proxy_type
+		},
+	    };
+	    report_event_monitoring(&rpc, &raddress, upgradeability, &proxy_dispatch, impl_address, args.block).await;
+	}
+	if let (Some(window), ProxyImplementation::Single(impl_address)) = (args.freshness_window, proxy_impl) {
+	    let freshness = evm_proxy_tools::get_proxy_freshness_with_progress(&rpc, &raddress, impl_address, args.block, evm_proxy_tools::DEFAULT_SEARCH_BUDGET, progress).await;
+	    if let Some(terminal) = &terminal {
+		terminal.clear();
+	    }
+	    match freshness {
+		Ok(freshness) => {
+		    println!("freshness: {:?}", freshness);
+		    if let Some(Finding::RecentImplementation { proxy_deploy_block, impl_deploy_block }) = freshness.finding(window) {
+			println!("info: implementation deployed at block {} is newer than the proxy (block {})", impl_deploy_block, proxy_deploy_block);
+		    }
+		},
+		Err(e) => println!("couldn't compute freshness: {}", e)
+	    }
+	}
+	if let Some((from, to)) = args.history {
+	    let history = evm_proxy_tools::get_implementation_history_with_progress(rpc.clone(), &raddress, &proxy_dispatch, from, to, args.history_step, progress).await;
+	    match history {
+		Ok(history) => {
+		    // Annotated with the upgrades logged in the same block, when the proxy logs them
+		    let events = evm_proxy_tools::scan_upgrade_events_with_progress(rpc.as_ref(), &raddress, from, to, progress).await;
+		    if let Some(terminal) = &terminal {
+			terminal.clear();
+		    }
+		    let events = events.unwrap_or_else(|e| {
+			println!("couldn't scan the upgrade events: {}", e);
+			Vec::new()
+		    });
+		    println!("implementation history from block {} to {}:", from, to);
+		    for (block, implementation) in history {
+			let logged: Vec<String> = events.iter().filter(|event| event.block() == block).map(|event| format!("{} in {}", event.name(), event.transaction())).collect();
+			if logged.is_empty() {
+			    println!("  {}: {}", block, implementation);
+			} else {
+			    println!("  {}: {} ({})", block, implementation, logged.join(", "));
+			}
+		    }
+		},
+		Err(e) => {
+		    if let Some(terminal) = &terminal {
+			terminal.clear();
+		    }
+		    println!("couldn't read the implementation history: {}", e)
+		},
+	    }
+	}
+	if let (true, ProxyImplementation::Single(impl_address)) = (args.interface, proxy_impl) {
+	    let impl_code = rpc.get_code(evm_proxy_tools::utils::raddress_to_h160(impl_address), args.block).await.expect("failed to fetch implementation code");
+	    let sketch = evm_proxy_tools::recover_interface(&impl_code);
+	    println!("implementation interface: fallback: {}, receive: {}", sketch.fallback, sketch.receive);
+	    for selector in &sketch.selectors {
+		let payable = if sketch.payable_hints.contains(selector) { " (payable)" } else { "" };
+		println!("  {}{}", selector, payable);
 	    }
-	} else {
-	    println!("Couldn't identify a proxy in that address");
 	}
-	break;
+    } else {
+	println!("Couldn't identify a proxy in that address");
     }
 }
@@ -0,0 +1,48 @@
+use crate::detect::DetectError;
+
+/// ERC-5202 magic, `INVALID` then a byte marking the contract as a blueprint.
+pub const BLUEPRINT_MAGIC: [u8; 2] = [0xfe, 0x71];
+
+/// The preamble and payload of an ERC-5202 blueprint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Blueprint<'a> {
+    pub version: u8,
+    /// The optional data section, empty if absent.
+    pub data: &'a [u8],
+    /// The initcode deployed by `create_from_blueprint`, at `initcode_offset` in the container.
+    pub initcode: &'a [u8],
+    pub initcode_offset: usize,
+}
+
+fn invalid(position: usize, reason: &'static str) -> DetectError {
+    DetectError::InvalidBytecode { position, reason }
+}
+
+/// Parses the ERC-5202 preamble of `code`: `0xFE71`, a byte with the version in its high 6
+/// bits and the size of the data length field in its low 2, the big endian data length, the
+/// data and the initcode.
+///
+/// `None` if `code` isn't a blueprint.
+pub fn parse_blueprint(code: &[u8]) -> Result<Option<Blueprint<'_>>, DetectError> {
+    if !code.starts_with(&BLUEPRINT_MAGIC) {
+	return Ok(None);
+    }
+    let preamble = *code.get(2).ok_or(invalid(2, "missing version byte"))?;
+    let version = preamble >> 2;
+    let length_size = (preamble & 0b11) as usize;
+    if length_size == 0b11 {
+	return Err(invalid(2, "reserved data length encoding"));
+    }
+
+    let data_start = 3 + length_size;
+    let length_field = code.get(3..data_start).ok_or(invalid(3, "truncated data length"))?;
+    let data_len = length_field.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+    let data = code.get(data_start..data_start + data_len).ok_or(invalid(data_start, "data section past the end of the code"))?;
+
+    let initcode_offset = data_start + data_len;
+    let initcode = &code[initcode_offset..];
+    if initcode.is_empty() {
+	return Err(invalid(initcode_offset, "empty initcode"));
+    }
+    Ok(Some(Blueprint { version, data, initcode, initcode_offset }))
+}
@@ -0,0 +1,94 @@
+//! The calldata the dynamic detector calls the analysed code with.
+
+use std::fmt::Debug;
+
+use alloy_primitives::Bytes;
+
+use crate::disasm::{scan, Push4Constants};
+use crate::Selector;
+
+/// The arbitrary probes every ruleset calls with, unlikely to be any function's selector.
+const QUICK_PROBES: [&[u8]; 3] = [
+    &[0xaa, 0xcc, 0xbb, 0xdd],
+    &[0xcc, 0xbb, 0xdd, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1],
+    &[0x01, 0x02, 0x04, 0x11],
+];
+
+/// `transfer(address,uint256)`, `balanceOf(address)`, `owner()` and `implementation()`.
+const COMMON_SELECTORS: [[u8; 4]; 4] = [
+    [0xa9, 0x05, 0x9c, 0xbb],
+    [0x70, 0xa0, 0x82, 0x31],
+    [0x8d, 0xa5, 0xcb, 0x5b],
+    [0x5c, 0x60, 0xda, 0x1b],
+];
+
+/// Calls `selector` with room for a few static arguments, so functions taking some don't revert
+/// decoding them.
+pub(crate) fn selector_call(selector: &[u8]) -> Bytes {
+    Bytes::from([selector, &[0; 128]].concat())
+}
+
+/// Chooses the calldata the dynamic detector traces `code` with. Every probe is traced; those
+/// the contract serves itself are left out of the classification when others forward.
+///
+/// `probes` should return at least one calldata: with none nothing is traced, and the code is
+/// reported as not a proxy.
+pub trait CalldataStrategy: Debug + Send + Sync {
+    fn probes(&self, code: &Bytes) -> Vec<Bytes>;
+}
+
+/// The arbitrary quick probes followed by common real selectors, for fallbacks that reject
+/// calldata that isn't some function of the implementation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DefaultProbes;
+
+impl CalldataStrategy for DefaultProbes {
+    fn probes(&self, code: &Bytes) -> Vec<Bytes> {
+	QuickProbes.probes(code).into_iter()
+	    .chain(COMMON_SELECTORS.iter().map(|selector| selector_call(selector)))
+	    .collect()
+    }
+}
+
+/// Only the arbitrary quick probes, the default of rulesets before 10.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct QuickProbes;
+
+impl CalldataStrategy for QuickProbes {
+    fn probes(&self, _code: &Bytes) -> Vec<Bytes> {
+	QUICK_PROBES.iter().map(|probe| Bytes::copy_from_slice(probe)).collect()
+    }
+}
+
+/// The quick probes followed by a call to each distinct selector the code pushes with PUSH4,
+/// in code order and at most `max_selectors` of them. Suits contracts whose fallback checks
+/// the selector against a table in the code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushedSelectorProbes {
+    pub max_selectors: usize,
+}
+
+impl Default for PushedSelectorProbes {
+    fn default() -> Self {
+	Self { max_selectors: 64 }
+    }
+}
+
+impl CalldataStrategy for PushedSelectorProbes {
+    fn probes(&self, code: &Bytes) -> Vec<Bytes> {
+	let mut pushes = Push4Constants::default();
+	scan(code, &mut pushes);
+	let mut selectors: Vec<Selector> = Vec::new();
+	for selector in pushes.selectors {
+	    if selectors.len() == self.max_selectors {
+		break;
+	    }
+	    if !selectors.contains(&selector) {
+		selectors.push(selector);
+	    }
+	}
+	QuickProbes.probes(code).into_iter()
+	    .chain(selectors.iter().map(|selector| selector_call(selector.as_bytes().as_slice())))
+	    .collect()
+    }
+}
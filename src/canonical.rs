@@ -0,0 +1,151 @@
+use alloy_primitives::Address;
+use once_cell::sync::Lazy;
+use revm::interpreter::opcode;
+
+use crate::disasm::{disassemble, Instruction};
+use crate::rules::RuleId;
+use crate::ProxyType;
+
+/// An instruction after canonicalization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// A provably zero value: PUSH0, a PUSH of zeros, RETURNDATASIZE before any call or a DUP of
+    /// one of those.
+    Zero,
+    /// A PUSH of a JUMPDEST right before a jump, identified by the index of the target
+    /// instruction so rewrites that change byte lengths don't matter.
+    JumpTarget(usize),
+    Push(&'a [u8]),
+    Op(u8),
+}
+
+/// Code as instructions and their canonical tokens, index for index.
+pub(crate) struct CanonicalCode<'a> {
+    pub instructions: Vec<Instruction<'a>>,
+    pub tokens: Vec<Token<'a>>,
+}
+
+fn is_zero_push(ins: &Instruction) -> bool {
+    ins.opcode == opcode::PUSH0 || (ins.is_push() && ins.operand.iter().all(|b| *b == 0))
+}
+
+/// Rewrites the zero producing idioms in the prefix before the first call to [Token::Zero].
+///
+/// Conservative: values are followed on a small symbolic stack and the rewriting stops at the
+/// first call or at any instruction the stack model doesn't know, the rest is kept verbatim.
+pub(crate) fn canonicalize(code: &[u8]) -> CanonicalCode<'_> {
+    let instructions: Vec<Instruction> = disassemble(code).collect();
+    // `true` for zero values
+    let mut stack: Vec<bool> = Vec::new();
+    let mut in_prefix = true;
+    let mut tokens = Vec::with_capacity(instructions.len());
+
+    for (idx, ins) in instructions.iter().enumerate() {
+	if in_prefix {
+	    let zero = match ins.opcode {
+		opcode::RETURNDATASIZE => Some(true),
+		opcode::PUSH0..=opcode::PUSH32 => Some(is_zero_push(ins)),
+		opcode::CALLDATASIZE | opcode::GAS | opcode::ADDRESS | opcode::CALLER | opcode::CALLVALUE => Some(false),
+		opcode::DUP1..=opcode::DUP16 => {
+		    let n = (ins.opcode - opcode::DUP1 + 1) as usize;
+		    stack.len().checked_sub(n).map(|pos| stack[pos])
+		},
+		opcode::SWAP1..=opcode::SWAP16 => {
+		    let n = (ins.opcode - opcode::SWAP1 + 1) as usize;
+		    let top = stack.len().checked_sub(1);
+		    match (top, top.and_then(|top| top.checked_sub(n))) {
+			(Some(top), Some(other)) => stack.swap(top, other),
+			_ => in_prefix = false
+		    }
+		    None
+		},
+		opcode::CALLDATACOPY | opcode::CODECOPY | opcode::MSTORE | opcode::POP => {
+		    let pops = match ins.opcode { opcode::POP => 1, opcode::MSTORE => 2, _ => 3 };
+		    match stack.len().checked_sub(pops) {
+			Some(len) => stack.truncate(len),
+			None => in_prefix = false
+		    }
+		    None
+		},
+		_ => {
+		    in_prefix = false;
+		    None
+		}
+	    };
+	    let is_dup = (opcode::DUP1..=opcode::DUP16).contains(&ins.opcode);
+	    if is_dup && zero.is_none() {
+		in_prefix = false;
+	    }
+	    if let Some(zero) = zero {
+		stack.push(zero);
+		if zero {
+		    tokens.push(Token::Zero);
+		    continue;
+		}
+	    }
+	}
+	tokens.push(plain_token(&instructions, idx));
+    }
+    CanonicalCode { instructions, tokens }
+}
+
+fn plain_token<'a>(instructions: &[Instruction<'a>], idx: usize) -> Token<'a> {
+    let ins = &instructions[idx];
+    if !ins.is_push() {
+	return Token::Op(ins.opcode);
+    }
+    let jumps = instructions.get(idx + 1).is_some_and(|next| next.opcode == opcode::JUMP || next.opcode == opcode::JUMPI);
+    let target = ins.push_value()
+	.and_then(|value| usize::try_from(value).ok())
+	.and_then(|offset| instructions.binary_search_by_key(&offset, |ins| ins.offset).ok())
+	.filter(|target| instructions[*target].opcode == opcode::JUMPDEST);
+    match target {
+	Some(target) if jumps => Token::JumpTarget(target),
+	_ => Token::Push(ins.operand)
+    }
+}
+
+/// The canonical form of a known forwarder, with the index of the PUSH of its address.
+pub(crate) struct Skeleton {
+    pub rule: RuleId,
+    pub proxy_type: ProxyType,
+    tokens: Vec<Token<'static>>,
+    address_index: usize,
+}
+
+const PLACEHOLDER: [u8; 20] = [0xbe; 20];
+
+impl Skeleton {
+    fn derive(rule: RuleId, proxy_type: ProxyType, code: &'static [u8]) -> Self {
+	let canonical = canonicalize(code);
+	let address_index = canonical.tokens.iter().position(|t| *t == Token::Push(&PLACEHOLDER)).expect("skeleton without address");
+	Self { rule, proxy_type, tokens: canonical.tokens, address_index }
+    }
+
+    /// The address PUSH if `code` starts with this skeleton, the address may be 16 or 20 bytes.
+    pub fn matches<'a>(&self, code: &CanonicalCode<'a>) -> Option<Instruction<'a>> {
+	if code.tokens.len() < self.tokens.len() {
+	    return None;
+	}
+	let same = self.tokens.iter().zip(&code.tokens).enumerate().all(|(idx, (expected, token))| {
+	    match token {
+		Token::Push(operand) if idx == self.address_index => operand.len() == 16 || operand.len() == 20,
+		_ => expected == token
+	    }
+	});
+	same.then(|| code.instructions[self.address_index])
+    }
+}
+
+/// Canonical skeletons of the minimal forwarders, derived from their reference runtimes.
+pub(crate) static MINIMAL_SKELETONS: Lazy<Vec<Skeleton>> = Lazy::new(|| vec![
+    Skeleton::derive(RuleId::Eip1167Pattern, ProxyType::EIP_1167, &hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")),
+    Skeleton::derive(RuleId::Eip7511Pattern, ProxyType::EIP_7511, &hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3")),
+]);
+
+/// Address pushed by a matched skeleton, left padded if it was pushed with 16 bytes.
+pub(crate) fn pushed_address(ins: &Instruction) -> Address {
+    let mut address = [0u8; 20];
+    address[20 - ins.operand.len()..].copy_from_slice(ins.operand);
+    Address::from(address)
+}
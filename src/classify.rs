@@ -0,0 +1,116 @@
+use alloy_primitives::{Address, Bytes, U256};
+use ethers_core::abi::AbiEncode;
+use ethers_core::types::BlockId;
+use ethers_providers::Middleware;
+use revm::interpreter::opcode;
+use tracing::debug;
+
+use crate::abi::ProxiableUUIDCall;
+use crate::consts::{EIP_1822_PROXIABLE_SLOT, EIP_1967_IMPLEMENTATION_SLOT};
+use crate::detect::DetectorConfig;
+use crate::disasm::disassemble;
+use crate::probe::{probe_call, ProbeOutcome};
+use crate::read::ProxyReadError;
+use crate::rules::{is_compound_slot, is_low_slot, is_safe_slot, RuleId};
+use crate::{ProxyType, Selector};
+
+/// What is known of a proxy delegating through a single storage slot, for [refine_proxy_type].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SlotObservations {
+    /// The slot the implementation is read from.
+    pub slot: U256,
+    /// Selectors the proxy's code compares the calldata with.
+    pub selectors: Vec<Selector>,
+    /// Whether calls from the EIP-1967 admin are forwarded, `None` if none was traced.
+    pub admin_forwarded: Option<bool>,
+    /// What the implementation's `proxiableUUID()` returned, `None` if it didn't answer.
+    pub proxiable_uuid: Option<U256>,
+}
+
+impl SlotObservations {
+    /// The observations of the code alone: its selectors are the PUSH4 immediates, and the
+    /// first 4 bytes of PUSH32 ones for words compared with left aligned selectors.
+    pub fn from_code(slot: U256, code: &[u8]) -> Self {
+	let selectors = disassemble(code)
+	    .filter(|ins| matches!(ins.opcode, opcode::PUSH4 | opcode::PUSH32))
+	    .filter_map(|ins| Selector::from_slice(ins.operand))
+	    .collect();
+	SlotObservations { slot, selectors, ..Default::default() }
+    }
+}
+
+/// The most specific [ProxyType] of a storage slot proxy:
+///
+/// - the EIP-1967 slot is [ProxyType::EIP_1967_TRANSPARENT] if the admin's calls aren't
+///   forwarded, the other known slots are the type they are listed with, e.g.
+///   [ProxyType::EIP_1967_BEACON] for the beacon slot;
+/// - slot 0 with `masterCopy()` is [ProxyType::GnosisSafe], slots up to 0x10 with Compound's
+///   getters or setters are [ProxyType::CompoundDelegator], other slots up to 0x100 are
+///   [ProxyType::EIP_897] and higher ones [ProxyType::EIP_1967_CUSTOM];
+/// - EIP-1967, EIP-1822 and custom slot proxies whose implementation answers
+///   `proxiableUUID()` with the slot it upgrades through are [ProxyType::UUPS].
+///
+/// The same mapping the detectors' rules make from a trace with the default config, minus what
+/// only the trace tells, like a slot pushed as an immutable ([ProxyType::ImmutableSlotProxy]).
+pub fn refine_proxy_type(obs: &SlotObservations) -> ProxyType {
+    refine_proxy_type_with(obs, &DetectorConfig::default())
+}
+
+/// [refine_proxy_type] with the slot registry and rules of `config`, e.g. of a pinned ruleset.
+pub fn refine_proxy_type_with(obs: &SlotObservations, config: &DetectorConfig) -> ProxyType {
+    let answers = |selector: &Selector| obs.selectors.contains(selector);
+    let known = config.slot_registry().get(&obs.slot).filter(|_| config.applies(RuleId::KnownStorageSlot));
+    let proxy_type = match known {
+	Some(ProxyType::EIP_1967) if obs.admin_forwarded == Some(false) && config.applies(RuleId::TransparentAdminBranch) => ProxyType::EIP_1967_TRANSPARENT,
+	Some(proxy_type) => *proxy_type,
+	None if config.applies(RuleId::SafeStorageSlot) && is_safe_slot(&obs.slot, answers) => ProxyType::GnosisSafe,
+	None if config.applies(RuleId::CompoundStorageSlot) && is_compound_slot(&obs.slot, answers) => ProxyType::CompoundDelegator,
+	None if is_low_slot(&obs.slot) => ProxyType::EIP_897,
+	None => ProxyType::EIP_1967_CUSTOM,
+    };
+    match obs.proxiable_uuid {
+	Some(uuid) if is_upgradeable_through_implementation(proxy_type, uuid) => ProxyType::UUPS,
+	_ => proxy_type,
+    }
+}
+
+fn is_upgradeable_through_implementation(proxy_type: ProxyType, proxiable_uuid: U256) -> bool {
+    matches!(proxy_type, ProxyType::EIP_1967 | ProxyType::EIP_1967_CUSTOM | ProxyType::EIP_1822)
+	&& (proxiable_uuid == EIP_1822_PROXIABLE_SLOT || proxiable_uuid == EIP_1967_IMPLEMENTATION_SLOT)
+}
+
+/// Refines `proxy_type`, read from the proxy's slot alone, with where the upgrade logic lives.
+///
+/// If `implementation`, the proxy's resolved implementation, answers `proxiableUUID()` with
+/// the EIP-1822 or EIP-1967 implementation slot it upgrades through, the proxy is
+/// [ProxyType::UUPS]. Implementations that revert, e.g. behind transparent proxies, or answer
+/// anything else keep `proxy_type`, as do proxies that don't dispatch through a single slot.
+pub async fn classify_upgradeability<M>(rpc: &M, proxy_type: ProxyType, implementation: &Address, block: Option<BlockId>) -> Result<ProxyType, ProxyReadError>
+    where M: Middleware
+{
+    probe_upgradeability(rpc, proxy_type, implementation, block).await.map(|(proxy_type, _)| proxy_type)
+}
+
+/// [classify_upgradeability], also returning how the `proxiableUUID()` call ended, `None` if
+/// `proxy_type` didn't need it.
+pub async fn probe_upgradeability<M>(rpc: &M, proxy_type: ProxyType, implementation: &Address, block: Option<BlockId>) -> Result<(ProxyType, Option<ProbeOutcome>), ProxyReadError>
+    where M: Middleware
+{
+    if !matches!(proxy_type, ProxyType::EIP_1967 | ProxyType::EIP_1967_CUSTOM | ProxyType::EIP_1822) {
+	return Ok((proxy_type, None));
+    }
+    let outcome = probe_call(rpc, implementation, Bytes::from(ProxiableUUIDCall.encode()), block).await?;
+    let uuid = match &outcome {
+	ProbeOutcome::Success(output) if output.len() == 32 => Some(U256::from_be_slice(output)),
+	ProbeOutcome::Success(_) => None,
+	failed => {
+	    debug!("proxiableUUID() failed on {}: {:?}", implementation, failed);
+	    None
+	},
+    };
+    let refined = match uuid {
+	Some(uuid) if is_upgradeable_through_implementation(proxy_type, uuid) => ProxyType::UUPS,
+	_ => proxy_type,
+    };
+    Ok((refined, Some(outcome)))
+}
@@ -0,0 +1,723 @@
+//! Compact binary encoding of detection results, for pipelines storing millions of them.
+//!
+//! # Layout
+//!
+//! Every blob is `version kind body`:
+//!
+//! - `version`: the [FormatVersion] of the encoding as a varint, currently 10. Older versions are
+//!   read too: v1 results end after `attribution`, v2/v3 after `blueprint`, v4 after
+//!   `admin_slot`, v5 after the upgradeable slots, v6 after the facet slots, v7 after
+//!   `slot_namespace`, v8 after `slot_preimage`, v9 after `slot_name`, and v1/v2 facet
+//!   selectors are byte swapped.
+//! - `kind`: one byte, `0x01` [ProxyDetectionResult], `0x02` [ProxyDispatch], `0x03`
+//!   [ProxyImplementation].
+//! - `body`: the value, nothing may follow it.
+//!
+//! Primitive encodings:
+//!
+//! - `varint`: unsigned LEB128, 7 bits per byte, least significant group first, high bit set
+//!   on every byte but the last. At most 10 bytes (`u64`).
+//! - `address`: 20 bytes. `word` (slots, masks, hashes): 32 bytes big endian. `selector`: 4
+//!   bytes big endian. `bytes`: `varint` length, then the bytes. `string`: `bytes` holding
+//!   UTF-8.
+//! - `bool`: one byte, `0x00` or `0x01`. `option<T>`: a `bool` presence byte, then `T` if
+//!   present. `list<T>`: `varint` count, then the items.
+//!
+//! Values, enum tags are one byte:
+//!
+//! ```text
+//! result         := proxy_type dispatch rule evasive:bool list<finding> list<provenance> option<attribution> option<blueprint> option<admin_slot:word> list<upgradeable_slot:word> list<facet_slot:selector word> option<slot_namespace:string> option<slot_preimage> option<slot_name:string> option<trigger_calldata:bytes> option<probes_agree:bool> option<raw_slot:word>
+//! dispatch       := 0x00                                       Unknown
+//!                 | 0x01 slot:word option<extraction>          Storage
+//!                 | 0x02 list<word>                            MultipleStorage
+//!                 | 0x03 address                               Static
+//!                 | 0x04                                       Facet_EIP_2535
+//!                 | 0x05                                       FacetStorageSlot
+//!                 | 0x06 address selector                      External
+//!                 | 0x07 list<selector address>                PerSelector
+//!                 | 0x08 address args:bytes                    StaticWithArgs
+//!                 | 0x09 slot:word                             Beacon
+//! extraction     := shift:varint mask:word
+//! finding        := 0x00                                       EvasiveBehavior
+//!                 | 0x01 slot_value:address getter_value:address SelfReportMismatch
+//!                 | 0x02 original:dispatch                     DispatchCorrected
+//!                 | 0x03 contradiction                         ResolutionContradiction
+//!                 | 0x04 rule dispatch                         StrategyConflict
+//!                 | 0x05 proxy:varint implementation:varint    RecentImplementation, deploy blocks
+//!                 | 0x06 unfetched:varint                      IncompleteForkState
+//! contradiction  := 0x00 slot:word                             UninitializedSlot
+//!                 | 0x01 address                               DanglingTarget
+//!                 | 0x02 slot:word                             StorageNotAddress
+//! provenance     := kind:u8 offset:varint length:varint        kind as ProvenanceKind below
+//! attribution    := option<address> deterministic:bool option<word>
+//! blueprint      := version:u8 data_len:varint initcode:bool
+//! slot_preimage  := preimage:bytes minus_one:bool
+//! implementation := 0x00 address                               Single
+//!                 | 0x01 list<address>                         Multiple
+//!                 | 0x02 list<address selector>                Facets, sorted by address
+//! ```
+//!
+//! `proxy_type`, `rule` and provenance `kind` are one byte, the position of the variant in
+//! [PROXY_TYPE_CODES], [RULE_CODES] and [PROVENANCE_KIND_CODES]. New variants are appended so
+//! codes never change meaning.
+
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use thiserror::Error;
+
+use crate::attribution::CloneAttribution;
+use crate::compat::{check_readable, ArtifactKind, CompatError, FormatVersion};
+use crate::{BlueprintInfo, Contradiction, Selector, ByteProvenance, Finding, ProvenanceKind, ProxyDetectionResult, ProxyDispatch, ProxyImplementation, ProxyType, RuleId, SlotExtraction, SlotPreimage};
+
+/// Wire codes of [ProxyType], by position.
+pub const PROXY_TYPE_CODES: &[ProxyType] = &[
+    ProxyType::NoProxy,
+    ProxyType::Unknown,
+    ProxyType::EIP_1167,
+    ProxyType::EIP_3448,
+    ProxyType::EIP_7511,
+    ProxyType::StaticAddress,
+    ProxyType::EIP_897,
+    ProxyType::EIP_1967,
+    ProxyType::EIP_1967_CUSTOM,
+    ProxyType::EIP_1967_ZOS,
+    ProxyType::EIP_1967_BEACON,
+    ProxyType::EIP_1822,
+    ProxyType::EIP_2535,
+    ProxyType::DiamondOther,
+    ProxyType::External,
+    ProxyType::ImmutableSlotProxy,
+    ProxyType::SoladyClone,
+    ProxyType::GeneratedRouter,
+    ProxyType::VyperForwarder,
+    ProxyType::GnosisSafe,
+    ProxyType::Metamorphic,
+    ProxyType::UUPS,
+    ProxyType::CompoundDelegator,
+    ProxyType::EIP_1967_TRANSPARENT,
+    ProxyType::OpenDelegateCall,
+    ProxyType::ReadOnlyRouter,
+    ProxyType::DelegatesButNotProxy,
+    ProxyType::CodePointer,
+];
+
+/// Wire codes of [RuleId], by position.
+pub const RULE_CODES: &[RuleId] = &[
+    RuleId::Eip1167Pattern,
+    RuleId::Eip7511Pattern,
+    RuleId::Eip3448Pattern,
+    RuleId::StaticDelegateCall,
+    RuleId::KnownStorageSlot,
+    RuleId::CustomStorageSlot,
+    RuleId::LowStorageSlot,
+    RuleId::ExternalResolver,
+    RuleId::DiamondLoupeSelector,
+    RuleId::DiamondStorageSlot,
+    RuleId::DiamondOther,
+    RuleId::ImmutableStorageSlot,
+    RuleId::SoladyClonePattern,
+    RuleId::GeneratedRouterPattern,
+    RuleId::VyperForwarderPattern,
+    RuleId::SafeProxyPattern,
+    RuleId::SafeStorageSlot,
+    RuleId::BeaconProxyPattern,
+    RuleId::BeaconStorageSlot,
+    RuleId::MetamorphicInitPattern,
+    RuleId::CompoundStorageSlot,
+    RuleId::TransparentAdminBranch,
+    RuleId::OpenDelegateCall,
+    RuleId::ReadOnlyRouter,
+    RuleId::RegistryDelegateCall,
+    RuleId::CodePointer,
+];
+
+/// Wire codes of [ProvenanceKind], by position.
+pub const PROVENANCE_KIND_CODES: &[ProvenanceKind] = &[
+    ProvenanceKind::ImplementationAddress,
+    ProvenanceKind::BeaconAddress,
+    ProvenanceKind::SlotConstant,
+    ProvenanceKind::SelectorConstant,
+    ProvenanceKind::ImmutableArgs,
+];
+
+const RESULT_KIND: u8 = 0x01;
+const DISPATCH_KIND: u8 = 0x02;
+const IMPLEMENTATION_KIND: u8 = 0x03;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum CompactError {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("unknown {what} tag {tag:#04x}")]
+    UnknownTag { what: &'static str, tag: u8 },
+    #[error("varint longer than 64 bits")]
+    VarintOverflow,
+    #[error("string isn't valid UTF-8")]
+    InvalidUtf8,
+    #[error("{0} bytes left after the value")]
+    TrailingBytes(usize),
+    #[error(transparent)]
+    Version(#[from] CompatError),
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn header(kind: u8) -> Self {
+	let mut writer = Writer(Vec::new());
+	writer.varint(ArtifactKind::CompactEncoding.current_version().0 as u64);
+	writer.u8(kind);
+	writer
+    }
+
+    fn u8(&mut self, value: u8) {
+	self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+	self.u8(value as u8);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+	while value >= 0x80 {
+	    self.u8(value as u8 | 0x80);
+	    value >>= 7;
+	}
+	self.u8(value as u8);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+	self.0.extend_from_slice(bytes);
+    }
+
+    fn address(&mut self, address: &Address) {
+	self.bytes(address.as_slice());
+    }
+
+    fn word(&mut self, word: &U256) {
+	self.bytes(&word.to_be_bytes::<32>());
+    }
+
+    fn selector(&mut self, selector: Selector) {
+	self.bytes(selector.as_bytes());
+    }
+
+    fn code<T: PartialEq>(&mut self, codes: &[T], value: &T) {
+	let code = codes.iter().position(|v| v == value).expect("variant without wire code");
+	self.u8(code as u8);
+    }
+
+    fn option<T>(&mut self, value: Option<&T>, write: impl FnOnce(&mut Self, &T)) {
+	self.bool(value.is_some());
+	if let Some(value) = value {
+	    write(self, value);
+	}
+    }
+
+    fn list<T>(&mut self, items: &[T], mut write: impl FnMut(&mut Self, &T)) {
+	self.varint(items.len() as u64);
+	for item in items {
+	    write(self, item);
+	}
+    }
+
+    fn dispatch(&mut self, dispatch: &ProxyDispatch) {
+	match dispatch {
+	    ProxyDispatch::Unknown => self.u8(0x00),
+	    ProxyDispatch::Storage(slot, extraction) => {
+		self.u8(0x01);
+		self.word(slot);
+		self.option(extraction.as_ref(), |w, extraction| {
+		    w.varint(extraction.shift as u64);
+		    w.word(&extraction.mask);
+		});
+	    },
+	    ProxyDispatch::MultipleStorage(slots) => {
+		self.u8(0x02);
+		self.list(slots, Self::word);
+	    },
+	    ProxyDispatch::Static(address) => {
+		self.u8(0x03);
+		self.address(address);
+	    },
+	    ProxyDispatch::Facet_EIP_2535 => self.u8(0x04),
+	    ProxyDispatch::FacetStorageSlot => self.u8(0x05),
+	    ProxyDispatch::External(address, selector) => {
+		self.u8(0x06);
+		self.address(address);
+		self.selector(*selector);
+	    },
+	    ProxyDispatch::PerSelector(table) => {
+		self.u8(0x07);
+		self.list(table, |w, (selector, address)| {
+		    w.selector(*selector);
+		    w.address(address);
+		});
+	    },
+	    ProxyDispatch::StaticWithArgs(address, args) => {
+		self.u8(0x08);
+		self.address(address);
+		self.varint(args.len() as u64);
+		self.bytes(args);
+	    },
+	    ProxyDispatch::Beacon(slot) => {
+		self.u8(0x09);
+		self.word(slot);
+	    },
+	}
+    }
+
+    fn finding(&mut self, finding: &Finding) {
+	match finding {
+	    Finding::EvasiveBehavior => self.u8(0x00),
+	    Finding::SelfReportMismatch { slot_value, getter_value } => {
+		self.u8(0x01);
+		self.address(slot_value);
+		self.address(getter_value);
+	    },
+	    Finding::DispatchCorrected { original } => {
+		self.u8(0x02);
+		self.dispatch(original);
+	    },
+	    Finding::ResolutionContradiction(contradiction) => {
+		self.u8(0x03);
+		match contradiction {
+		    Contradiction::UninitializedSlot(slot) => {
+			self.u8(0x00);
+			self.word(slot);
+		    },
+		    Contradiction::DanglingTarget(address) => {
+			self.u8(0x01);
+			self.address(address);
+		    },
+		    Contradiction::StorageNotAddress(slot) => {
+			self.u8(0x02);
+			self.word(slot);
+		    },
+		}
+	    },
+	    Finding::StrategyConflict { rule, dispatch } => {
+		self.u8(0x04);
+		self.code(RULE_CODES, rule);
+		self.dispatch(dispatch);
+	    },
+	    Finding::RecentImplementation { proxy_deploy_block, impl_deploy_block } => {
+		self.u8(0x05);
+		self.varint(*proxy_deploy_block);
+		self.varint(*impl_deploy_block);
+	    },
+	    Finding::IncompleteForkState { unfetched } => {
+		self.u8(0x06);
+		self.varint(*unfetched);
+	    },
+	}
+    }
+
+    fn attribution(&mut self, attribution: &CloneAttribution) {
+	self.option(attribution.factory.as_ref(), Self::address);
+	self.bool(attribution.deterministic);
+	self.option(attribution.salt.as_ref(), |w, salt| w.bytes(salt.as_slice()));
+    }
+
+    fn implementation(&mut self, implementation: &ProxyImplementation) {
+	match implementation {
+	    ProxyImplementation::Single(address) => {
+		self.u8(0x00);
+		self.address(address);
+	    },
+	    ProxyImplementation::Multiple(addresses) => {
+		self.u8(0x01);
+		self.list(addresses, Self::address);
+	    },
+	    ProxyImplementation::Facets(facets) => {
+		let mut facets: Vec<(&Address, &Selector)> = facets.iter().map(|(selector, address)| (address, selector)).collect();
+		facets.sort_unstable();
+		self.u8(0x02);
+		self.list(&facets, |w, (address, selector)| {
+		    w.address(address);
+		    w.selector(**selector);
+		});
+	    },
+	}
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    version: FormatVersion,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+	Reader { data, version: ArtifactKind::CompactEncoding.current_version() }
+    }
+
+    /// Checks the header of a blob of `kind`.
+    fn header(data: &'a [u8], kind: u8) -> Result<Self, CompactError> {
+	let mut reader = Reader::new(data);
+	let version = u32::try_from(reader.varint()?).map_err(|_| CompactError::VarintOverflow)?;
+	reader.version = FormatVersion(version);
+	check_readable(ArtifactKind::CompactEncoding, reader.version)?;
+	match reader.u8()? {
+	    found if found == kind => Ok(reader),
+	    tag => Err(CompactError::UnknownTag { what: "blob kind", tag })
+	}
+    }
+
+    fn finish<T>(self, value: T) -> Result<T, CompactError> {
+	match self.data.len() {
+	    0 => Ok(value),
+	    left => Err(CompactError::TrailingBytes(left))
+	}
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CompactError> {
+	if self.data.len() < len {
+	    return Err(CompactError::UnexpectedEnd);
+	}
+	let (bytes, rest) = self.data.split_at(len);
+	self.data = rest;
+	Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, CompactError> {
+	Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, CompactError> {
+	match self.u8()? {
+	    0 => Ok(false),
+	    1 => Ok(true),
+	    tag => Err(CompactError::UnknownTag { what: "bool", tag })
+	}
+    }
+
+    fn varint(&mut self) -> Result<u64, CompactError> {
+	let mut value = 0u64;
+	for shift in (0..64).step_by(7) {
+	    let byte = self.u8()?;
+	    let bits = (byte & 0x7f) as u64;
+	    if shift == 63 && bits > 1 {
+		return Err(CompactError::VarintOverflow);
+	    }
+	    value |= bits << shift;
+	    if byte & 0x80 == 0 {
+		return Ok(value);
+	    }
+	}
+	Err(CompactError::VarintOverflow)
+    }
+
+    fn usize(&mut self) -> Result<usize, CompactError> {
+	usize::try_from(self.varint()?).map_err(|_| CompactError::VarintOverflow)
+    }
+
+    fn address(&mut self) -> Result<Address, CompactError> {
+	Ok(Address::from_slice(self.bytes(20)?))
+    }
+
+    fn word(&mut self) -> Result<U256, CompactError> {
+	Ok(U256::from_be_slice(self.bytes(32)?))
+    }
+
+    fn selector(&mut self) -> Result<Selector, CompactError> {
+	Ok(Selector::new(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn code<T: Copy>(&mut self, codes: &[T], what: &'static str) -> Result<T, CompactError> {
+	let tag = self.u8()?;
+	codes.get(tag as usize).copied().ok_or(CompactError::UnknownTag { what, tag })
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, CompactError>) -> Result<Option<T>, CompactError> {
+	if self.bool()? { read(self).map(Some) } else { Ok(None) }
+    }
+
+    fn list<T>(&mut self, mut read: impl FnMut(&mut Self) -> Result<T, CompactError>) -> Result<Vec<T>, CompactError> {
+	let count = self.usize()?;
+	// Every item takes at least a byte, don't trust the count beyond the input
+	let mut items = Vec::with_capacity(count.min(self.data.len()));
+	for _ in 0..count {
+	    items.push(read(self)?);
+	}
+	Ok(items)
+    }
+
+    fn dispatch(&mut self) -> Result<ProxyDispatch, CompactError> {
+	Ok(match self.u8()? {
+	    0x00 => ProxyDispatch::Unknown,
+	    0x01 => {
+		let slot = self.word()?;
+		let extraction = self.option(|r| Ok(SlotExtraction::new(r.usize()?, r.word()?)))?;
+		ProxyDispatch::Storage(slot, extraction)
+	    },
+	    0x02 => ProxyDispatch::MultipleStorage(self.list(Self::word)?),
+	    0x03 => ProxyDispatch::Static(self.address()?),
+	    0x04 => ProxyDispatch::Facet_EIP_2535,
+	    0x05 => ProxyDispatch::FacetStorageSlot,
+	    0x06 => ProxyDispatch::External(self.address()?, self.selector()?),
+	    0x07 => ProxyDispatch::PerSelector(self.list(|r| Ok((r.selector()?, r.address()?)))?),
+	    0x08 => {
+		let address = self.address()?;
+		let len = self.usize()?;
+		ProxyDispatch::StaticWithArgs(address, Bytes::copy_from_slice(self.bytes(len)?))
+	    },
+	    0x09 => ProxyDispatch::Beacon(self.word()?),
+	    tag => return Err(CompactError::UnknownTag { what: "dispatch", tag })
+	})
+    }
+
+    fn finding(&mut self) -> Result<Finding, CompactError> {
+	Ok(match self.u8()? {
+	    0x00 => Finding::EvasiveBehavior,
+	    0x01 => Finding::SelfReportMismatch { slot_value: self.address()?, getter_value: self.address()? },
+	    0x02 => Finding::DispatchCorrected { original: self.dispatch()? },
+	    0x03 => Finding::ResolutionContradiction(match self.u8()? {
+		0x00 => Contradiction::UninitializedSlot(self.word()?),
+		0x01 => Contradiction::DanglingTarget(self.address()?),
+		0x02 => Contradiction::StorageNotAddress(self.word()?),
+		tag => return Err(CompactError::UnknownTag { what: "contradiction", tag })
+	    }),
+	    0x04 => Finding::StrategyConflict { rule: self.code(RULE_CODES, "rule")?, dispatch: self.dispatch()? },
+	    0x05 => Finding::RecentImplementation { proxy_deploy_block: self.varint()?, impl_deploy_block: self.varint()? },
+	    0x06 => Finding::IncompleteForkState { unfetched: self.varint()? },
+	    tag => return Err(CompactError::UnknownTag { what: "finding", tag })
+	})
+    }
+
+    fn provenance(&mut self) -> Result<ByteProvenance, CompactError> {
+	let kind = self.code(PROVENANCE_KIND_CODES, "provenance kind")?;
+	Ok(ByteProvenance::new(kind, self.usize()?, self.usize()?))
+    }
+
+    fn attribution(&mut self) -> Result<CloneAttribution, CompactError> {
+	Ok(CloneAttribution {
+	    factory: self.option(Self::address)?,
+	    deterministic: self.bool()?,
+	    salt: self.option(|r| Ok(B256::from_slice(r.bytes(32)?)))?,
+	})
+    }
+
+    fn blueprint(&mut self) -> Result<BlueprintInfo, CompactError> {
+	Ok(BlueprintInfo { version: self.u8()?, data_len: self.usize()?, initcode: self.bool()? })
+    }
+
+    fn implementation(&mut self) -> Result<ProxyImplementation, CompactError> {
+	Ok(match self.u8()? {
+	    0x00 => ProxyImplementation::Single(self.address()?),
+	    0x01 => ProxyImplementation::Multiple(self.list(Self::address)?),
+	    0x02 => {
+		let mut facets: Vec<(Address, Selector)> = self.list(|r| Ok((r.address()?, r.selector()?)))?;
+		// Before v3 facet selectors were little endian integers
+		if self.version < FormatVersion(3) {
+		    facets.iter_mut().for_each(|(_, selector)| selector.0.reverse());
+		}
+		ProxyImplementation::Facets(facets.into_iter().map(|(address, selector)| (selector, address)).collect())
+	    },
+	    tag => return Err(CompactError::UnknownTag { what: "implementation", tag })
+	})
+    }
+}
+
+impl ProxyDetectionResult {
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+	let mut w = Writer::header(RESULT_KIND);
+	w.code(PROXY_TYPE_CODES, &self.proxy_type);
+	w.dispatch(&self.dispatch);
+	w.code(RULE_CODES, &self.rule);
+	w.bool(self.evasive);
+	w.list(&self.findings, Writer::finding);
+	w.list(&self.provenance, |w, provenance| {
+	    w.code(PROVENANCE_KIND_CODES, &provenance.kind);
+	    w.varint(provenance.offset as u64);
+	    w.varint(provenance.length as u64);
+	});
+	w.option(self.attribution.as_ref(), Writer::attribution);
+	w.option(self.blueprint.as_ref(), |w, blueprint| {
+	    w.u8(blueprint.version);
+	    w.varint(blueprint.data_len as u64);
+	    w.bool(blueprint.initcode);
+	});
+	w.option(self.admin_slot.as_ref(), Writer::word);
+	w.list(&self.upgradeable_slots, Writer::word);
+	w.list(&self.facet_slots, |w, (selector, slot)| {
+	    w.selector(*selector);
+	    w.word(slot);
+	});
+	w.option(self.slot_namespace.as_ref(), |w, namespace| {
+	    w.varint(namespace.len() as u64);
+	    w.bytes(namespace.as_bytes());
+	});
+	w.option(self.slot_preimage.as_ref(), |w, slot_preimage| {
+	    w.varint(slot_preimage.preimage.len() as u64);
+	    w.bytes(&slot_preimage.preimage);
+	    w.bool(slot_preimage.minus_one);
+	});
+	w.option(self.slot_name.as_ref(), |w, name| {
+	    w.varint(name.len() as u64);
+	    w.bytes(name.as_bytes());
+	});
+	w.option(self.trigger_calldata.as_ref(), |w, calldata| {
+	    w.varint(calldata.len() as u64);
+	    w.bytes(calldata);
+	});
+	w.option(self.probes_agree.as_ref(), |w, agree| w.bool(*agree));
+	w.option(self.raw_slot.as_ref(), Writer::word);
+	w.0
+    }
+
+    pub fn from_compact_bytes(data: &[u8]) -> Result<Self, CompactError> {
+	let mut r = Reader::header(data, RESULT_KIND)?;
+	let mut result = ProxyDetectionResult::new(r.code(PROXY_TYPE_CODES, "proxy type")?, r.dispatch()?, r.code(RULE_CODES, "rule")?);
+	result.evasive = r.bool()?;
+	result.findings = r.list(Reader::finding)?;
+	result.provenance = r.list(Reader::provenance)?;
+	result.attribution = r.option(Reader::attribution)?;
+	if r.version >= FormatVersion(2) {
+	    result.blueprint = r.option(Reader::blueprint)?;
+	}
+	if r.version >= FormatVersion(4) {
+	    result.admin_slot = r.option(Reader::word)?;
+	}
+	if r.version >= FormatVersion(5) {
+	    result.upgradeable_slots = r.list(Reader::word)?;
+	}
+	if r.version >= FormatVersion(6) {
+	    result.facet_slots = r.list(|r| Ok((r.selector()?, r.word()?)))?;
+	}
+	if r.version >= FormatVersion(7) {
+	    result.slot_namespace = r.option(|r| {
+		let len = r.usize()?;
+		String::from_utf8(r.bytes(len)?.to_vec()).map_err(|_| CompactError::InvalidUtf8)
+	    })?;
+	}
+	if r.version >= FormatVersion(8) {
+	    result.slot_preimage = r.option(|r| {
+		let len = r.usize()?;
+		let preimage = Bytes::copy_from_slice(r.bytes(len)?);
+		Ok(SlotPreimage { preimage, minus_one: r.bool()? })
+	    })?;
+	}
+	if r.version >= FormatVersion(9) {
+	    result.slot_name = r.option(|r| {
+		let len = r.usize()?;
+		String::from_utf8(r.bytes(len)?.to_vec()).map_err(|_| CompactError::InvalidUtf8)
+	    })?;
+	}
+	if r.version >= FormatVersion(10) {
+	    result.trigger_calldata = r.option(|r| {
+		let len = r.usize()?;
+		Ok(Bytes::copy_from_slice(r.bytes(len)?))
+	    })?;
+	    result.probes_agree = r.option(Reader::bool)?;
+	    result.raw_slot = r.option(Reader::word)?;
+	}
+	r.finish(result)
+    }
+}
+
+impl ProxyDispatch {
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+	let mut w = Writer::header(DISPATCH_KIND);
+	w.dispatch(self);
+	w.0
+    }
+
+    pub fn from_compact_bytes(data: &[u8]) -> Result<Self, CompactError> {
+	let mut r = Reader::header(data, DISPATCH_KIND)?;
+	let dispatch = r.dispatch()?;
+	r.finish(dispatch)
+    }
+}
+
+impl ProxyImplementation {
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+	let mut w = Writer::header(IMPLEMENTATION_KIND);
+	w.implementation(self);
+	w.0
+    }
+
+    pub fn from_compact_bytes(data: &[u8]) -> Result<Self, CompactError> {
+	let mut r = Reader::header(data, IMPLEMENTATION_KIND)?;
+	let implementation = r.implementation()?;
+	r.finish(implementation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_varint() {
+	for value in [0, 1, 0x7f, 0x80, 300, u32::MAX as u64, u64::MAX] {
+	    let mut w = Writer(Vec::new());
+	    w.varint(value);
+	    let mut r = Reader::new(&w.0);
+	    assert_eq!(r.varint(), Ok(value));
+	    assert!(r.data.is_empty());
+	}
+	assert_eq!(Reader::new(&[0xff; 10]).varint(), Err(CompactError::VarintOverflow));
+	assert_eq!(Reader::new(&[0x80]).varint(), Err(CompactError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_layout() {
+	let dispatch = ProxyDispatch::External(Address::repeat_byte(0xaa), Selector::from(0xcdffacc6));
+	let mut expected = vec![0x0a, DISPATCH_KIND, 0x06];
+	expected.extend_from_slice(&[0xaa; 20]);
+	expected.extend_from_slice(&[0xcd, 0xff, 0xac, 0xc6]);
+	assert_eq!(dispatch.to_compact_bytes(), expected);
+
+	// Every rule and proxy type has a code
+	assert!(RuleId::ALL.iter().all(|rule| RULE_CODES.contains(rule)));
+	assert_eq!(RULE_CODES.len(), RuleId::ALL.len());
+    }
+
+    #[test]
+    fn test_invalid_blobs() {
+	let blob = ProxyDispatch::Unknown.to_compact_bytes();
+	assert_eq!(ProxyImplementation::from_compact_bytes(&blob).unwrap_err(), CompactError::UnknownTag { what: "blob kind", tag: DISPATCH_KIND });
+	assert_eq!(ProxyDispatch::from_compact_bytes(&[blob.as_slice(), &[0]].concat()), Err(CompactError::TrailingBytes(1)));
+	assert!(matches!(ProxyDispatch::from_compact_bytes(&[0x0b, DISPATCH_KIND, 0x00]), Err(CompactError::Version(_))));
+	assert_eq!(ProxyDispatch::from_compact_bytes(&[0x0a, DISPATCH_KIND, 0x03, 0xaa]), Err(CompactError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_v1_result() {
+	let mut result = ProxyDetectionResult::new(ProxyType::EIP_1167, ProxyDispatch::Static(Address::repeat_byte(0xbe)), RuleId::Eip1167Pattern);
+	let current = result.to_compact_bytes();
+	// v1 had no blueprint, v3 no admin slot, v4 no upgradeable slots, v5 no facet slots, v6
+	// no slot namespace, v7 no slot preimage, v8 no slot name, v9 none of the trigger, probe
+	// agreement and raw slot
+	for (version, trimmed) in [(0x01, 10), (0x03, 9), (0x04, 8), (0x05, 7), (0x06, 6), (0x07, 5), (0x08, 4), (0x09, 3)] {
+	    let mut old = current[..current.len() - trimmed].to_vec();
+	    old[0] = version;
+	    assert_eq!(ProxyDetectionResult::from_compact_bytes(&old), Ok(result.clone()));
+	}
+
+	result.blueprint = Some(BlueprintInfo { version: 0, data_len: 3, initcode: true });
+	result.upgradeable_slots = vec![U256::from(7)];
+	result.facet_slots = vec![(Selector::from(0xcdffacc6), U256::from(9))];
+	result.slot_namespace = Some("myproject.proxy.implementation".to_string());
+	result.slot_preimage = Some(SlotPreimage { preimage: Bytes::from_static(b"myproject.proxy.implementation"), minus_one: true });
+	result.slot_name = Some("matic.network.proxy.implementation".to_string());
+	result.trigger_calldata = Some(Bytes::from_static(&[0xcd, 0xff, 0xac, 0xc6]));
+	result.probes_agree = Some(false);
+	result.raw_slot = Some(U256::from(11));
+	assert_eq!(ProxyDetectionResult::from_compact_bytes(&result.to_compact_bytes()), Ok(result));
+    }
+
+    #[test]
+    fn test_v2_facets() {
+	let facets = ProxyImplementation::Facets(HashMap::from([(Selector::from(0xcdffacc6), Address::repeat_byte(0xfa))]));
+	let mut v2 = facets.to_compact_bytes();
+	assert_eq!(v2[v2.len() - 4..], [0xcd, 0xff, 0xac, 0xc6]);
+	// v2 wrote the little endian integer
+	v2[0] = 0x02;
+	let len = v2.len();
+	v2[len - 4..].reverse();
+	assert_eq!(ProxyImplementation::from_compact_bytes(&v2), Ok(facets));
+    }
+}
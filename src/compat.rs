@@ -0,0 +1,187 @@
+//! Versioning policy for every file format the crate reads or writes.
+//!
+//! Each [ArtifactKind] has a current version, written by this crate, and a minimum readable
+//! version. Files between the two are upgraded to the current layout on read, anything else is
+//! rejected with [CompatError::UnsupportedVersion].
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+/// Version of a persisted format, embedded as the `version` field of every versioned file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FormatVersion(pub u32);
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	write!(f, "v{}", self.0)
+    }
+}
+
+/// Every kind of versioned file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArtifactKind {
+    /// `data/storage_slots.json`
+    StorageSlotTable,
+    /// `data/resolver_selectors.json`
+    ResolverSelectorTable,
+    /// The binary encoding of the `compact` module.
+    CompactEncoding,
+}
+
+impl ArtifactKind {
+    pub const ALL: &'static [ArtifactKind] = &[ArtifactKind::StorageSlotTable, ArtifactKind::ResolverSelectorTable, ArtifactKind::CompactEncoding];
+
+    pub fn name(&self) -> &'static str {
+	match self {
+	    ArtifactKind::StorageSlotTable => "storage slot table",
+	    ArtifactKind::ResolverSelectorTable => "resolver selector table",
+	    ArtifactKind::CompactEncoding => "compact encoding",
+	}
+    }
+
+    /// The version this crate writes.
+    pub fn current_version(&self) -> FormatVersion {
+	match self {
+	    ArtifactKind::StorageSlotTable => FormatVersion(2),
+	    ArtifactKind::ResolverSelectorTable => FormatVersion(3),
+	    ArtifactKind::CompactEncoding => FormatVersion(10),
+	}
+    }
+
+    /// The oldest version that can still be upgraded.
+    pub fn min_readable_version(&self) -> FormatVersion {
+	match self {
+	    ArtifactKind::StorageSlotTable | ArtifactKind::ResolverSelectorTable | ArtifactKind::CompactEncoding => FormatVersion(1),
+	}
+    }
+
+    /// Version of a parsed file. Files that predate versioning are v1.
+    pub fn version_of(&self, value: &Value) -> Result<FormatVersion, CompatError> {
+	match self {
+	    // v1 tables were a bare array of entries
+	    ArtifactKind::StorageSlotTable | ArtifactKind::ResolverSelectorTable if value.is_array() => Ok(FormatVersion(1)),
+	    _ => embedded_version(*self, value),
+	}
+    }
+
+    /// Upgrades `value` from `from` to the next version.
+    fn upgrade_step(&self, from: FormatVersion, value: Value) -> Result<Value, CompatError> {
+	match (self, from) {
+	    // v2 wraps the entries to embed the version
+	    (ArtifactKind::StorageSlotTable | ArtifactKind::ResolverSelectorTable, FormatVersion(1)) => {
+		Ok(json!({ "version": 2, "entries": value }))
+	    },
+	    // v3 adds the selector `kind`, every older entry is a resolver
+	    (ArtifactKind::ResolverSelectorTable, FormatVersion(2)) => {
+		let mut value = value;
+		for entry in value.get_mut("entries").and_then(Value::as_array_mut).into_iter().flatten() {
+		    if let Some(entry) = entry.as_object_mut() {
+			entry.entry("kind").or_insert_with(|| json!("resolver"));
+		    }
+		}
+		value["version"] = json!(3);
+		Ok(value)
+	    },
+	    _ => Err(self.unsupported(from)),
+	}
+    }
+
+    fn unsupported(&self, found: FormatVersion) -> CompatError {
+	CompatError::UnsupportedVersion { artifact: *self, found, min: self.min_readable_version(), current: self.current_version() }
+    }
+}
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum CompatError {
+    #[error("{artifact} {found} is not supported, readable versions are {min} to {current}")]
+    UnsupportedVersion { artifact: ArtifactKind, found: FormatVersion, min: FormatVersion, current: FormatVersion },
+    #[error("{artifact} has no valid `version` field")]
+    MissingVersion { artifact: ArtifactKind },
+}
+
+fn embedded_version(artifact: ArtifactKind, value: &Value) -> Result<FormatVersion, CompatError> {
+    value.get("version")
+	.and_then(Value::as_u64)
+	.and_then(|v| u32::try_from(v).ok())
+	.map(FormatVersion)
+	.ok_or(CompatError::MissingVersion { artifact })
+}
+
+/// Checks `version` can be read as `artifact`.
+pub fn check_readable(artifact: ArtifactKind, version: FormatVersion) -> Result<(), CompatError> {
+    if version < artifact.min_readable_version() || version > artifact.current_version() {
+	return Err(artifact.unsupported(version));
+    }
+    Ok(())
+}
+
+/// Brings a parsed file to the current version of `artifact`, ready to be deserialized.
+pub fn upgrade(artifact: ArtifactKind, mut value: Value) -> Result<Value, CompatError> {
+    let mut version = artifact.version_of(&value)?;
+    check_readable(artifact, version)?;
+    while version < artifact.current_version() {
+	value = artifact.upgrade_step(version, value)?;
+	version = FormatVersion(version.0 + 1);
+    }
+    Ok(value)
+}
+
+/// A versioned table of entries, the layout of the data files since v2.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VersionedTable<T> {
+    pub version: FormatVersion,
+    pub entries: Vec<T>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_upgrade() {
+	let v1 = json!([{ "a": 1 }]);
+	let v2 = upgrade(ArtifactKind::StorageSlotTable, v1.clone()).unwrap();
+	assert_eq!(v2, json!({ "version": 2, "entries": v1 }));
+	// Current versions go through untouched
+	assert_eq!(upgrade(ArtifactKind::StorageSlotTable, v2.clone()).unwrap(), v2);
+
+	let v3 = json!({ "version": 3, "entries": [{ "a": 1, "kind": "resolver" }] });
+	assert_eq!(upgrade(ArtifactKind::ResolverSelectorTable, v1).unwrap(), v3);
+	assert_eq!(upgrade(ArtifactKind::ResolverSelectorTable, json!({ "version": 2, "entries": [{ "a": 1 }] })).unwrap(), v3);
+	assert_eq!(upgrade(ArtifactKind::ResolverSelectorTable, v3.clone()).unwrap(), v3);
+    }
+
+    #[test]
+    fn test_table_round_trip() {
+	for artifact in ArtifactKind::ALL {
+	    let table = VersionedTable { version: artifact.current_version(), entries: vec![json!({ "a": 1 })] };
+	    let value = serde_json::to_value(&table).unwrap();
+	    assert_eq!(serde_json::from_value::<VersionedTable<Value>>(upgrade(*artifact, value).unwrap()).unwrap(), table);
+	}
+    }
+
+    #[test]
+    fn test_unsupported_versions() {
+	let err = upgrade(ArtifactKind::StorageSlotTable, json!({ "version": 3, "entries": [] })).unwrap_err();
+	assert_eq!(err, CompatError::UnsupportedVersion {
+	    artifact: ArtifactKind::StorageSlotTable,
+	    found: FormatVersion(3),
+	    min: FormatVersion(1),
+	    current: FormatVersion(2),
+	});
+	assert_eq!(err.to_string(), "storage slot table v3 is not supported, readable versions are v1 to v2");
+	assert!(matches!(upgrade(ArtifactKind::StorageSlotTable, json!({ "version": 0, "entries": [] })), Err(CompatError::UnsupportedVersion { .. })));
+	assert_eq!(upgrade(ArtifactKind::ResolverSelectorTable, json!({ "entries": [] })), Err(CompatError::MissingVersion { artifact: ArtifactKind::ResolverSelectorTable }));
+    }
+}
@@ -1,32 +1,166 @@
-use std::collections::HashMap;
-
-use ethers_core::types::H256;
 use once_cell::sync::Lazy;
-use alloy_primitives::U256;
+use alloy_primitives::{B256, U256};
 
-use crate::ProxyType;
+use crate::{data::{self, SelectorKind}, ProxyType, Selector};
 
-pub static ADDR_MASK_H256: Lazy<H256> = Lazy::new(|| {
-    H256::from(hex_literal::hex!("000000000000000000000000ffffffffffffffffffffffffffffffffffffffff"))
-});
+// 0age's metamorphic init code: asks its deployer for `getImplementation()`, then EXTCODECOPYs
+// and returns the implementation's runtime
+pub const METAMORPHIC_INIT_CODE: &[u8] = &hex_literal::hex!("5860208158601c335a63aaf10f428752fa158151803b80938091923cf3");
 
-pub static EIP_1967_DEFAULT_STORAGE: Lazy<HashMap<U256, ProxyType>> = Lazy::new(|| {
-    [
-            (U256::from_be_bytes(hex_literal::hex!("7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3")), ProxyType::EIP_1967_ZOS),
-            (U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")), ProxyType::EIP_1967),
-            (U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50")), ProxyType::EIP_1967_BEACON),
-            (U256::from_be_bytes(hex_literal::hex!("c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7")), ProxyType::EIP_1822),
-    ].into_iter().collect()
-});
+// Offset of the `getImplementation()` selector in METAMORPHIC_INIT_CODE
+pub const METAMORPHIC_SELECTOR_OFFSET: usize = 10;
+
+// implementation(), what beacons answer with the implementation of their proxies
+pub const BEACON_IMPLEMENTATION_SELECTOR: Selector = Selector::from_u32_be(0x5c60da1b);
+
+// facets(), what diamond loupes answer with every facet and its selectors
+pub const DIAMOND_FACETS_SELECTOR: Selector = Selector::from_u32_be(0x7a0ed627);
+
+// facetAddress(bytes4), what diamond loupes answer with the facet of a selector
+pub const DIAMOND_FACET_ADDRESS_SELECTOR: Selector = Selector::from_u32_be(0xcdffacc6);
+
+// Functions found on most diamonds: the loupe, the cut, ownership and ERC-165
+pub const SELECTOR_LABELS: &[(Selector, &str)] = &[
+    (Selector::from_u32_be(0x1f931c1c), "diamondCut((address,uint8,bytes4[])[],address,bytes)"),
+    (Selector::from_u32_be(0x7a0ed627), "facets()"),
+    (Selector::from_u32_be(0xadfca15e), "facetFunctionSelectors(address)"),
+    (Selector::from_u32_be(0x52ef6b2c), "facetAddresses()"),
+    (Selector::from_u32_be(0xcdffacc6), "facetAddress(bytes4)"),
+    (Selector::from_u32_be(0x01ffc9a7), "supportsInterface(bytes4)"),
+    (Selector::from_u32_be(0x8da5cb5b), "owner()"),
+    (Selector::from_u32_be(0xf2fde38b), "transferOwnership(address)"),
+    (Selector::from_u32_be(0x715018a6), "renounceOwnership()"),
+    (Selector::from_u32_be(0xe30c3978), "pendingOwner()"),
+    (Selector::from_u32_be(0x79ba5097), "acceptOwnership()"),
+];
 
 pub static DIAMOND_STANDARD_STORAGE_SLOT_LESSBYTES: Lazy<Vec<u8>> = Lazy::new(|| hex_literal::hex!("c8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c13").to_vec());
 
 // pub static DIAMOND_STANDARD_STORAGE_SLOT: Lazy<Vec<u8>> = Lazy::new(|| hex_literal::hex!("c8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c13").to_vec());
-pub static DIAMOND_STANDARD_STORAGE_SLOT: Lazy<U256> = Lazy::new(|| U256::from_be_bytes(hex_literal::hex!("c8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c131b")));
+pub const DIAMOND_STANDARD_STORAGE_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("c8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c131b"));
+
+// eip1967.proxy.admin - 1, who can upgrade a transparent proxy
+pub const EIP_1967_ADMIN_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103"));
+
+// eip1967.proxy.beacon - 1, the beacon of beacon proxies
+pub const EIP_1967_BEACON_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+
+// eip1967.proxy.rollback - 1, set by OpenZeppelin's UUPS while testing an upgrade
+pub const EIP_1967_ROLLBACK_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("4910fdfa16fed3260ed0e7147f7cc6da11a60208b5b9406d12a635614ffd9143"));
+
+// org.zeppelinos.proxy.implementation, the implementation slot of ZeppelinOS proxies
+pub const ZOS_IMPLEMENTATION_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3"));
+
+// org.zeppelinos.proxy.admin, the admin slot of ZeppelinOS proxies before EIP-1967
+pub const ZOS_ADMIN_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("10d6a54a4754c8869d6886b5f5d7fbfa5b4522237ea5c60d11bc4e7a1ff9390b"));
+
+// eip1967.proxy.implementation - 1
+pub const EIP_1967_IMPLEMENTATION_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+
+// keccak256("PROXIABLE"), the implementation slot of EIP-1822 proxies
+pub const EIP_1822_PROXIABLE_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7"));
 
-pub static FUN_TO_PROXY: Lazy<HashMap<u32, ProxyType>> = Lazy::new(|| {
-    [
-	// facetAddress(bytes4)
-	(0xcdffacc6, ProxyType::EIP_2535)
-     ].into_iter().collect()
+// Well known slots with the name they are known by, usually their preimage
+const NAMED_SLOTS: &[(U256, &str)] = &[
+    (EIP_1967_IMPLEMENTATION_SLOT, "eip1967.proxy.implementation"),
+    (EIP_1967_ADMIN_SLOT, "eip1967.proxy.admin"),
+    (EIP_1967_BEACON_SLOT, "eip1967.proxy.beacon"),
+    (EIP_1967_ROLLBACK_SLOT, "eip1967.proxy.rollback"),
+    (EIP_1822_PROXIABLE_SLOT, "PROXIABLE"),
+    (DIAMOND_STANDARD_STORAGE_SLOT, "diamond.standard.diamond.storage"),
+    (ZOS_IMPLEMENTATION_SLOT, "org.zeppelinos.proxy.implementation"),
+    (ZOS_ADMIN_SLOT, "org.zeppelinos.proxy.admin"),
+];
+
+/// The name `slot` is known by, e.g. `eip1967.proxy.implementation`, for display. `None` for
+/// slots that aren't one of the [named_slots], however standard they look.
+pub fn slot_name(slot: &U256) -> Option<&'static str> {
+    NAMED_SLOTS.iter().find(|(named, _)| named == slot).map(|(_, name)| *name)
+}
+
+/// Every slot [slot_name] knows: the EIP-1967 implementation, admin, beacon and rollback slots,
+/// EIP-1822's, the diamond storage and the ZeppelinOS ones.
+pub fn named_slots() -> &'static [(U256, &'static str)] {
+    NAMED_SLOTS
+}
+
+// Upgraded(address), logged by EIP-1967 proxies and by beacons when upgraded
+pub const UPGRADED_TOPIC: B256 = B256::new(hex_literal::hex!("bc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b"));
+
+// AdminChanged(address,address), logged by EIP-1967 proxies when their admin changes
+pub const ADMIN_CHANGED_TOPIC: B256 = B256::new(hex_literal::hex!("7e644d79422f17c01e4894b5f4f588d331ebfa28653d42ae832dc59e38c9798f"));
+
+// BeaconUpgraded(address), logged by EIP-1967 beacon proxies when their beacon changes
+pub const BEACON_UPGRADED_TOPIC: B256 = B256::new(hex_literal::hex!("1cf3b03a6cf19fa2baba4df148e9dcabedea7f8a5c07840e207e5c089be95d3e"));
+
+// DiamondCut((address,uint8,bytes4[])[],address,bytes), logged by EIP-2535 diamonds
+pub const DIAMOND_CUT_TOPIC: B256 = B256::new(hex_literal::hex!("8faa70878671ccd212d20771b795c50af8fd3ff6cf27f4bde57e5d4de0aeb673"));
+
+// Getters a proxy answers with its own implementation, with the family they are typical of
+pub static SELF_REPORT_GETTERS: Lazy<Vec<(Selector, ProxyType)>> = Lazy::new(|| {
+    data::resolver_selectors().unwrap_or_else(|e| panic!("invalid built-in data: {}", e))
+	.into_iter().filter(|entry| entry.kind == SelectorKind::SelfReport).map(|entry| (entry.selector, entry.proxy_type)).collect()
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+    use revm::interpreter::opcode;
+
+    #[test]
+    fn test_metamorphic_init_code() {
+	let ins: Vec<_> = disassemble(METAMORPHIC_INIT_CODE).collect();
+	let selector = ins.iter().find(|ins| ins.opcode == opcode::PUSH4).unwrap();
+	assert_eq!((selector.operand_offset(), selector.operand), (METAMORPHIC_SELECTOR_OFFSET, &hex_literal::hex!("aaf10f42")[..]));
+	// CALLER is asked, the answer's code copied and returned
+	let opcodes: Vec<u8> = ins.iter().map(|ins| ins.opcode).collect();
+	assert!(opcodes.contains(&opcode::CALLER) && opcodes.contains(&opcode::STATICCALL));
+	assert_eq!(opcodes[opcodes.len() - 2..], [opcode::EXTCODECOPY, opcode::RETURN]);
+    }
+
+    #[test]
+    fn test_admin_slots() {
+	let hash = |name: &str| U256::from_be_bytes(alloy_primitives::keccak256(name).0);
+	assert_eq!(EIP_1967_ADMIN_SLOT, hash("eip1967.proxy.admin") - U256::from(1));
+	assert_eq!(ZOS_ADMIN_SLOT, hash("org.zeppelinos.proxy.admin"));
+    }
+
+    #[test]
+    fn test_uups_constants() {
+	let hash = |name: &str| U256::from_be_bytes(alloy_primitives::keccak256(name).0);
+	assert_eq!(EIP_1967_IMPLEMENTATION_SLOT, hash("eip1967.proxy.implementation") - U256::from(1));
+	assert_eq!(EIP_1822_PROXIABLE_SLOT, hash("PROXIABLE"));
+    }
+
+    #[test]
+    fn test_slot_names() {
+	for slot in [EIP_1967_IMPLEMENTATION_SLOT, EIP_1967_ADMIN_SLOT, EIP_1967_BEACON_SLOT, EIP_1967_ROLLBACK_SLOT, EIP_1822_PROXIABLE_SLOT, DIAMOND_STANDARD_STORAGE_SLOT, ZOS_IMPLEMENTATION_SLOT, ZOS_ADMIN_SLOT] {
+	    assert!(slot_name(&slot).is_some(), "{:#x}", slot);
+	}
+	let hash = |name: &str| U256::from_be_bytes(alloy_primitives::keccak256(name).0);
+	assert_eq!(EIP_1967_BEACON_SLOT, hash("eip1967.proxy.beacon") - U256::from(1));
+	assert_eq!(EIP_1967_ROLLBACK_SLOT, hash("eip1967.proxy.rollback") - U256::from(1));
+	assert_eq!(ZOS_IMPLEMENTATION_SLOT, hash("org.zeppelinos.proxy.implementation"));
+	assert_eq!(named_slots().len(), 8);
+	// Not guessed from looking like one: off by one, or a plain small slot
+	assert_eq!(slot_name(&(EIP_1967_IMPLEMENTATION_SLOT + U256::from(1))), None);
+	assert_eq!(slot_name(&U256::ZERO), None);
+    }
+
+    #[test]
+    fn test_upgrade_topics() {
+	let hash = |signature: &str| alloy_primitives::keccak256(signature);
+	assert_eq!(UPGRADED_TOPIC, hash("Upgraded(address)"));
+	assert_eq!(ADMIN_CHANGED_TOPIC, hash("AdminChanged(address,address)"));
+	assert_eq!(BEACON_UPGRADED_TOPIC, hash("BeaconUpgraded(address)"));
+	assert_eq!(DIAMOND_CUT_TOPIC, hash("DiamondCut((address,uint8,bytes4[])[],address,bytes)"));
+    }
+
+    #[test]
+    fn test_selector_labels() {
+	for (selector, label) in SELECTOR_LABELS {
+	    assert_eq!(selector.as_bytes()[..], alloy_primitives::keccak256(label)[..4], "{}", label);
+	}
+    }
+}
@@ -0,0 +1,171 @@
+//! Detection of the proxy an ERC-4337 factory would deploy for a counterfactual account, by
+//! simulating the factory call of the account's `initCode` offline.
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use revm::{
+    inspector_handle_register,
+    interpreter::{opcode, CallInputs, CallOutcome, CreateInputs, CreateOutcome, CreateScheme, Gas, InstructionResult, Interpreter, InterpreterResult},
+    primitives::{BlockEnv, ExecutionResult, TransactTo, TxEnv},
+    EvmBuilder, EvmContext, Inspector,
+};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::detect::{detect_proxy, fill_block_env, DetectorConfig};
+use crate::environment::TraceEnvironment;
+use crate::proxy_inspector::{analyzed_bytecode, ProxyDetectDB};
+use crate::{ProxyDetectionResult, ProxyDispatch};
+
+/// Length of the `0xff ++ deployer ++ salt ++ init code hash` preimage of CREATE2 addresses.
+const CREATE2_PREIMAGE_LEN: usize = 85;
+
+/// What deploys a CREATE2 account, which with the factory's address gives the account's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Create2Params {
+    pub salt: B256,
+    pub init_code_hash: B256,
+}
+
+impl Create2Params {
+    /// The address the account gets when `factory` deploys it.
+    pub fn address(&self, factory: &Address) -> Address {
+	factory.create2(self.salt, self.init_code_hash)
+    }
+}
+
+/// The account a factory call deploys, as simulated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterfactualAccount {
+    /// The runtime code the factory deployed.
+    pub runtime: Bytes,
+    /// The proxy detected in `runtime`, `None` if it isn't one.
+    pub detection: Option<ProxyDetectionResult>,
+    /// The implementation the account delegates to: the one in its code, or the one its
+    /// constructor wrote to the dispatch slot. `None` for beacons and other dispatches.
+    pub implementation: Option<Address>,
+    /// `None` if the account was deployed with CREATE, its address depends on the factory's
+    /// nonce.
+    pub create2: Option<Create2Params>,
+}
+
+/// Why [analyze_counterfactual] couldn't tell what the factory deploys.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum CounterfactualError {
+    /// Simulating a factory needs its code, there's no RPC-backed database to fetch it from.
+    #[error("the factory's code is needed to simulate its call")]
+    MissingFactoryCode,
+    #[error("the EVM couldn't execute the factory call: {0}")]
+    Transact(String),
+    /// The call failed, e.g. because the factory depends on on-chain state or checks its caller.
+    #[error("the factory call failed: {0}")]
+    Failed(String),
+    #[error("the factory call didn't deploy any code")]
+    NothingDeployed,
+}
+
+/// A contract the traced call created.
+struct Created {
+    address: Address,
+    runtime: Bytes,
+    create2: Option<Create2Params>,
+}
+
+/// Lets the factory create its account in the synthetic environment: addresses the factory
+/// computes as CREATE2 targets and the targets of its creations are reported empty, so the
+/// factory doesn't find an account there already. Calls out of the factory are stubbed.
+struct CreationInspector {
+    factory: Address,
+    /// The last successful creation, the outermost one if they're nested.
+    created: Option<Created>,
+}
+
+impl Inspector<ProxyDetectDB> for CreationInspector {
+    fn step(&mut self, interpreter: &mut Interpreter, context: &mut EvmContext<ProxyDetectDB>) {
+	if interpreter.current_opcode() != opcode::KECCAK256 {
+	    return;
+	}
+	let (Ok(offset), Ok(len)) = (interpreter.stack.peek(0), interpreter.stack.peek(1)) else { return };
+	if len != U256::from(CREATE2_PREIMAGE_LEN) {
+	    return;
+	}
+	let Ok(offset) = usize::try_from(offset) else { return };
+	if offset.saturating_add(CREATE2_PREIMAGE_LEN) > interpreter.shared_memory.len() {
+	    return;
+	}
+	let preimage = interpreter.shared_memory.slice(offset, CREATE2_PREIMAGE_LEN);
+	if preimage[0] == 0xff {
+	    let address = Address::from_slice(&keccak256(preimage)[12..]);
+	    debug!("factory computed CREATE2 address {:x}", address);
+	    context.db.insert_empty_account(address);
+	}
+    }
+
+    fn call(&mut self, _context: &mut EvmContext<ProxyDetectDB>, call: &mut CallInputs) -> Option<CallOutcome> {
+	if call.bytecode_address == self.factory {
+	    return None;
+	}
+	Some(CallOutcome { result: InterpreterResult { result: InstructionResult::Return, output: Bytes::new(), gas: Gas::new(call.gas_limit) }, memory_offset: 0..0 })
+    }
+
+    fn create(&mut self, context: &mut EvmContext<ProxyDetectDB>, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+	let nonce = context.journaled_state.state.get(&inputs.caller).map_or(0, |account| account.info.nonce);
+	context.db.insert_empty_account(inputs.created_address(nonce));
+	None
+    }
+
+    fn create_end(&mut self, _context: &mut EvmContext<ProxyDetectDB>, inputs: &CreateInputs, outcome: CreateOutcome) -> CreateOutcome {
+	if let (true, Some(address)) = (outcome.result.is_ok(), outcome.address) {
+	    let create2 = match inputs.scheme {
+		CreateScheme::Create2 { salt } => Some(Create2Params { salt: salt.into(), init_code_hash: keccak256(&inputs.init_code) }),
+		CreateScheme::Create => None,
+	    };
+	    self.created = Some(Created { address, runtime: outcome.result.output.clone(), create2 });
+	}
+	outcome
+    }
+}
+
+/// Simulates an ERC-4337 factory call, the `initCode` of a counterfactual account after the
+/// factory's address, and detects the proxy in the account it deploys.
+///
+/// The factory runs at a synthetic address with synthetic state, so the account's address is
+/// given as [Create2Params] to combine with the factory's real address. Calls out of the
+/// factory are stubbed and succeed, factories that read on-chain state, or accept calls only
+/// from the EntryPoint's sender creator, fail with [CounterfactualError::Failed].
+pub fn analyze_counterfactual(factory_calldata: &Bytes, factory_code: Option<Bytes>, config: &DetectorConfig) -> Result<CounterfactualAccount, CounterfactualError> {
+    let factory_code = factory_code.ok_or(CounterfactualError::MissingFactoryCode)?;
+    let env = config.seed.map(TraceEnvironment::from_seed).unwrap_or_else(TraceEnvironment::random);
+    let mut db = ProxyDetectDB::new(env.clone());
+    db.install_contract(env.contract, &analyzed_bytecode(&factory_code));
+    let inspector = CreationInspector { factory: env.contract, created: None };
+    let mut evm = EvmBuilder::default()
+	.with_db(db)
+	.with_external_context(inspector)
+	.append_handler_register(inspector_handle_register)
+	.modify_block_env(|block: &mut BlockEnv| fill_block_env(block, &env))
+	.modify_tx_env(|tx: &mut TxEnv| {
+	    tx.caller = env.caller;
+	    tx.transact_to = TransactTo::Call(env.contract);
+	    tx.data = factory_calldata.clone();
+	    tx.value = U256::ZERO;
+	    tx.gas_price = U256::from(env.basefee);
+	    tx.gas_limit = 30_000_000;
+	})
+	.build();
+    let outcome = evm.transact().map_err(|e| CounterfactualError::Transact(e.to_string()))?;
+    if let ExecutionResult::Revert { .. } | ExecutionResult::Halt { .. } = outcome.result {
+	return Err(CounterfactualError::Failed(format!("{:?}", outcome.result)));
+    }
+    let created = evm.context.external.created.take().filter(|created| !created.runtime.is_empty()).ok_or(CounterfactualError::NothingDeployed)?;
+    let detection = detect_proxy(&created.runtime, config);
+    let stored = |slot: &U256| outcome.state.get(&created.address).and_then(|account| account.storage.get(slot)).map(|slot| slot.present_value);
+    let implementation = match detection.as_ref().map(|result| &result.dispatch) {
+	Some(ProxyDispatch::Static(address) | ProxyDispatch::StaticWithArgs(address, _)) => Some(*address),
+	Some(ProxyDispatch::Storage(slot, extraction)) => stored(slot)
+	    .map(|value| extraction.map_or(value, |extraction| extraction.apply(value)))
+	    .map(|value| Address::from_word(value.to_be_bytes::<32>().into()))
+	    .filter(|address| !address.is_zero()),
+	_ => None,
+    };
+    Ok(CounterfactualAccount { runtime: created.runtime, detection, implementation, create2: created.create2 })
+}
@@ -0,0 +1,320 @@
+//! Built-in tables shipped as data files under `data/` and embedded at compile time.
+//!
+//! Every file is a [VersionedTable], `{ "version": <n>, "entries": [...] }`, with entries:
+//!
+//! - `data/storage_slots.json` (v2): `{ "slot": "0x<64 hex digits>", "proxy_type": "<ProxyType>", "name": "<preimage or label>" }`
+//! - `data/resolver_selectors.json` (v3): `{ "selector": "0x<8 hex digits>", "proxy_type": "<ProxyType>", "signature": "<fn(args)>", "kind": "resolver" | "self_report" }`
+//!
+//! `proxy_type` is the name of a [ProxyType] variant. `resolver` selectors are called on another
+//! contract to find the implementation, `self_report` ones are getters a proxy answers with its
+//! own implementation, `proxy_type` being the family they are typical of. Keys (`slot`, `selector`) must be unique
+//! within a file. Older versions are upgraded through [crate::compat].
+//!
+//! Tables as an earlier ruleset shipped them are kept under `data/ruleset-<n>/`, so pinned
+//! [Rulesets](crate::Ruleset) classify with the data they were released with. A ruleset uses
+//! the first frozen copy at or after its version, the current file if there is none.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use alloy_primitives::U256;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::compat::{self, ArtifactKind, CompatError, VersionedTable};
+use crate::{ProxyType, Selector};
+
+pub(crate) const STORAGE_SLOTS_FILE: &str = "data/storage_slots.json";
+pub(crate) const RESOLVER_SELECTORS_FILE: &str = "data/resolver_selectors.json";
+
+const STORAGE_SLOTS: &str = include_str!("../data/storage_slots.json");
+const RESOLVER_SELECTORS: &str = include_str!("../data/resolver_selectors.json");
+
+/// Frozen copies of the slot table, by the last ruleset that used them.
+const FROZEN_STORAGE_SLOTS: &[(u32, &str, &str)] = &[
+    (14, "data/ruleset-14/storage_slots.json", include_str!("../data/ruleset-14/storage_slots.json")),
+];
+
+/// Frozen copies of the selector table, by the last ruleset that used them.
+const FROZEN_RESOLVER_SELECTORS: &[(u32, &str, &str)] = &[
+    (3, "data/ruleset-3/resolver_selectors.json", include_str!("../data/ruleset-3/resolver_selectors.json")),
+    (14, "data/ruleset-14/resolver_selectors.json", include_str!("../data/ruleset-14/resolver_selectors.json")),
+];
+
+/// An invalid entry in one of the data files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataError {
+    pub file: &'static str,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for DataError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotEntry {
+    pub slot: U256,
+    pub proxy_type: ProxyType,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectorKind {
+    Resolver,
+    SelfReport,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectorEntry {
+    pub selector: Selector,
+    pub proxy_type: ProxyType,
+    pub signature: String,
+    pub kind: SelectorKind,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSlotEntry {
+    slot: String,
+    proxy_type: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSelectorEntry {
+    selector: String,
+    proxy_type: String,
+    signature: String,
+    kind: String,
+}
+
+/// The [ProxyType] spelled as its identifier, e.g. `EIP_1967_ZOS`, case included.
+pub(crate) fn proxy_type_from_name(name: &str) -> Option<ProxyType> {
+    ProxyType::ALL.iter().find(|proxy_type| format!("{:?}", proxy_type) == name).copied()
+}
+
+/// Line of the first occurrence of `needle` after the `skip` first ones, used to point errors
+/// at the offending entry.
+fn line_of(source: &str, needle: &str, skip: usize) -> usize {
+    let source = source.to_lowercase();
+    source.match_indices(&needle.to_lowercase()).nth(skip)
+	.map(|(pos, _)| source[..pos].lines().count().max(1))
+	.unwrap_or(0)
+}
+
+fn parse_hex(file: &'static str, source: &str, value: &str, digits: usize) -> Result<Vec<u8>, DataError> {
+    let error = |message: String| DataError { file, line: line_of(source, value, 0), message };
+    let hex_digits = value.strip_prefix("0x").ok_or_else(|| error(format!("`{}` is missing the 0x prefix", value)))?;
+    if hex_digits.len() != digits {
+	return Err(error(format!("`{}` should have {} hex digits", value, digits)));
+    }
+    hex::decode(hex_digits).map_err(|e| error(format!("`{}` is not valid hex: {}", value, e)))
+}
+
+fn parse_proxy_type(file: &'static str, source: &str, name: &str) -> Result<ProxyType, DataError> {
+    proxy_type_from_name(name).ok_or_else(|| DataError { file, line: line_of(source, &format!("\"{}\"", name), 0), message: format!("unknown proxy type `{}`", name) })
+}
+
+fn parse_selector_kind(file: &'static str, source: &str, name: &str) -> Result<SelectorKind, DataError> {
+    match name {
+	"resolver" => Ok(SelectorKind::Resolver),
+	"self_report" => Ok(SelectorKind::SelfReport),
+	_ => Err(DataError { file, line: line_of(source, &format!("\"{}\"", name), 0), message: format!("unknown selector kind `{}`", name) })
+    }
+}
+
+fn parse_json<T: for<'de> Deserialize<'de>>(file: &'static str, artifact: ArtifactKind, source: &str) -> Result<Vec<T>, DataError> {
+    let json_error = |e: serde_json::Error| DataError { file, line: e.line(), message: e.to_string() };
+    let compat_error = |e: CompatError| DataError { file, line: line_of(source, "\"version\"", 0), message: e.to_string() };
+
+    let value: Value = serde_json::from_str(source).map_err(json_error)?;
+    let version = artifact.version_of(&value).map_err(compat_error)?;
+    compat::check_readable(artifact, version).map_err(compat_error)?;
+    let table: VersionedTable<T> = if version == artifact.current_version() {
+	// Straight from the source so errors keep their line
+	serde_json::from_str(source).map_err(json_error)?
+    } else {
+	serde_json::from_value(compat::upgrade(artifact, value).map_err(compat_error)?).map_err(json_error)?
+    };
+    Ok(table.entries)
+}
+
+/// Keys are compared case insensitively and only against keys of the same scope.
+fn check_unique(file: &'static str, source: &str, keys: &[(&str, &str)]) -> Result<(), DataError> {
+    let mut seen = HashSet::new();
+    for (i, (key, scope)) in keys.iter().enumerate() {
+	if !seen.insert((key.to_lowercase(), *scope)) {
+	    let earlier = keys[..i].iter().filter(|(other, _)| other.eq_ignore_ascii_case(key)).count();
+	    return Err(DataError { file, line: line_of(source, key, earlier), message: format!("duplicate key `{}`", key) });
+	}
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_storage_slots(file: &'static str, source: &str) -> Result<Vec<SlotEntry>, DataError> {
+    let raw: Vec<RawSlotEntry> = parse_json(file, ArtifactKind::StorageSlotTable, source)?;
+    check_unique(file, source, &raw.iter().map(|e| (e.slot.as_str(), "")).collect::<Vec<_>>())?;
+    raw.iter().map(|entry| Ok(SlotEntry {
+	slot: U256::from_be_slice(&parse_hex(file, source, &entry.slot, 64)?),
+	proxy_type: parse_proxy_type(file, source, &entry.proxy_type)?,
+	name: entry.name.clone(),
+    })).collect()
+}
+
+pub(crate) fn parse_resolver_selectors(file: &'static str, source: &str) -> Result<Vec<SelectorEntry>, DataError> {
+    let raw: Vec<RawSelectorEntry> = parse_json(file, ArtifactKind::ResolverSelectorTable, source)?;
+    // A selector can be both a resolver and a self-report getter
+    check_unique(file, source, &raw.iter().map(|e| (e.selector.as_str(), e.kind.as_str())).collect::<Vec<_>>())?;
+    raw.iter().map(|entry| Ok(SelectorEntry {
+	selector: Selector::new(parse_hex(file, source, &entry.selector, 8)?.try_into().unwrap()),
+	proxy_type: parse_proxy_type(file, source, &entry.proxy_type)?,
+	signature: entry.signature.clone(),
+	kind: parse_selector_kind(file, source, &entry.kind)?,
+    })).collect()
+}
+
+pub fn storage_slots() -> Result<Vec<SlotEntry>, DataError> {
+    parse_storage_slots(STORAGE_SLOTS_FILE, STORAGE_SLOTS)
+}
+
+pub fn resolver_selectors() -> Result<Vec<SelectorEntry>, DataError> {
+    parse_resolver_selectors(RESOLVER_SELECTORS_FILE, RESOLVER_SELECTORS)
+}
+
+/// The slot table ruleset `version` shipped with.
+pub fn storage_slots_of(version: u32) -> Result<Vec<SlotEntry>, DataError> {
+    match FROZEN_STORAGE_SLOTS.iter().find(|(last, _, _)| version <= *last) {
+	Some((_, file, source)) => parse_storage_slots(file, source),
+	None => storage_slots(),
+    }
+}
+
+/// The selector table ruleset `version` shipped with.
+pub fn resolver_selectors_of(version: u32) -> Result<Vec<SelectorEntry>, DataError> {
+    match FROZEN_RESOLVER_SELECTORS.iter().find(|(last, _, _)| version <= *last) {
+	Some((_, file, source)) => parse_resolver_selectors(file, source),
+	None => resolver_selectors(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_files_are_valid() {
+	assert!(!storage_slots().unwrap().is_empty());
+	assert!(!resolver_selectors().unwrap().is_empty());
+	for (version, _, _) in FROZEN_STORAGE_SLOTS {
+	    assert!(!storage_slots_of(*version).unwrap().is_empty());
+	}
+	for (version, _, _) in FROZEN_RESOLVER_SELECTORS {
+	    assert!(!resolver_selectors_of(*version).unwrap().is_empty());
+	}
+    }
+
+    #[test]
+    fn test_frozen_tables() {
+	// Before Compound delegators were recognized
+	let selectors: Vec<(u32, ProxyType, SelectorKind)> = resolver_selectors_of(3).unwrap().into_iter().map(|e| (e.selector.into(), e.proxy_type, e.kind)).collect();
+	assert_eq!(selectors.last(), Some(&(0xbb82aa5e, ProxyType::EIP_897, SelectorKind::SelfReport)));
+	assert_eq!(resolver_selectors_of(4).unwrap(), resolver_selectors_of(14).unwrap());
+	// Before the Polygon slot, getImplementation() and childImplementation()
+	assert_eq!(storage_slots_of(14).unwrap().len() + 1, storage_slots().unwrap().len());
+	assert_eq!(resolver_selectors_of(14).unwrap().len() + 2, resolver_selectors().unwrap().len());
+	assert_eq!(storage_slots_of(15).unwrap(), storage_slots().unwrap());
+	assert_eq!(resolver_selectors_of(15).unwrap(), resolver_selectors().unwrap());
+    }
+
+    #[test]
+    fn test_builtin_tables_snapshot() {
+	let slots: Vec<(U256, ProxyType)> = storage_slots().unwrap().into_iter().map(|e| (e.slot, e.proxy_type)).collect();
+	assert_eq!(slots, vec![
+	    (U256::from_be_bytes(hex_literal::hex!("7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3")), ProxyType::EIP_1967_ZOS),
+	    (U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")), ProxyType::EIP_1967),
+	    (U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50")), ProxyType::EIP_1967_BEACON),
+	    (U256::from_be_bytes(hex_literal::hex!("c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7")), ProxyType::EIP_1822),
+	    (U256::from_be_bytes(hex_literal::hex!("baab7dbf64751104133af04abc7d9979f0fda3b059a322a8333f533d3f32bf7f")), ProxyType::EIP_1967_CUSTOM),
+	]);
+	let selectors: Vec<(u32, ProxyType, SelectorKind)> = resolver_selectors().unwrap().into_iter().map(|e| (e.selector.into(), e.proxy_type, e.kind)).collect();
+	assert_eq!(selectors, vec![
+	    (0xcdffacc6, ProxyType::EIP_2535, SelectorKind::Resolver),
+	    (0x5c60da1b, ProxyType::EIP_1967, SelectorKind::SelfReport),
+	    (0xa619486e, ProxyType::GnosisSafe, SelectorKind::SelfReport),
+	    (0xbb82aa5e, ProxyType::CompoundDelegator, SelectorKind::SelfReport),
+	    (0xbb82aa5e, ProxyType::CompoundDelegator, SelectorKind::Resolver),
+	    (0xaaf10f42, ProxyType::External, SelectorKind::Resolver),
+	    (0xda525716, ProxyType::External, SelectorKind::Resolver),
+	]);
+    }
+
+    #[test]
+    fn test_proxy_type_names() {
+	for proxy_type in ProxyType::ALL {
+	    assert_eq!(proxy_type_from_name(&format!("{:?}", proxy_type)), Some(*proxy_type));
+	}
+	// The files spell identifiers exactly, unlike FromStr
+	assert_eq!(proxy_type_from_name("eip_1967"), None);
+	assert_eq!(proxy_type_from_name(ProxyType::EIP_1967.name()), None);
+    }
+
+    #[test]
+    fn test_invalid_entries_point_at_line() {
+	let source = "{\"version\": 2, \"entries\": [\n  {\"slot\": \"0x01\", \"proxy_type\": \"EIP_1967\", \"name\": \"a\"}\n]}";
+	let err = parse_storage_slots("test.json", source).unwrap_err();
+	assert_eq!((err.file, err.line), ("test.json", 2));
+
+	let source = "{\"version\": 3, \"entries\": [\n  {\"selector\": \"0xcdffacc6\", \"proxy_type\": \"EIP_2535\", \"signature\": \"a\", \"kind\": \"resolver\"},\n  {\"selector\": \"0xCDFFACC6\", \"proxy_type\": \"EIP_2535\", \"signature\": \"b\", \"kind\": \"resolver\"}\n]}";
+	let err = parse_resolver_selectors("test.json", source).unwrap_err();
+	assert_eq!(err.line, 3);
+	assert!(err.message.contains("duplicate"));
+	// Once per kind is fine
+	let source = source.replacen("\"resolver\"", "\"self_report\"", 1);
+	assert_eq!(parse_resolver_selectors("test.json", &source).unwrap().len(), 2);
+
+	let source = "{\"version\": 3, \"entries\": [\n  {\"selector\": \"0xcdffacc6\", \"proxy_type\": \"EIP_2535\", \"signature\": \"a\", \"kind\": \"resolver\"},\n  {\"selector\": \"0x7a0ed627\", \"proxy_type\": \"Nope\", \"signature\": \"b\", \"kind\": \"resolver\"}\n]}";
+	let err = parse_resolver_selectors("test.json", source).unwrap_err();
+	assert_eq!(err.line, 3);
+
+	let source = "{\"version\": 3, \"entries\": [\n  {\"selector\": \"0xcdffacc6\", \"proxy_type\": \"Nope\"}\n]}";
+	let err = parse_resolver_selectors("test.json", source).unwrap_err();
+	assert_eq!(err.line, 2);
+
+	let source = "{\n  \"version\": 9,\n  \"entries\": []\n}";
+	let err = parse_resolver_selectors("test.json", source).unwrap_err();
+	assert_eq!(err.line, 2);
+	assert!(err.message.contains("v9 is not supported"));
+
+	let source = "{\"version\": 3, \"entries\": [\n  {\"selector\": \"0xcdffacc6\", \"proxy_type\": \"EIP_2535\", \"signature\": \"a\", \"kind\": \"getter\"}\n]}";
+	let err = parse_resolver_selectors("test.json", source).unwrap_err();
+	assert_eq!(err.line, 2);
+	assert!(err.message.contains("selector kind"));
+    }
+
+    #[test]
+    fn test_old_tables() {
+	// Bare arrays, before the version was embedded
+	let source = "[\n  {\"slot\": \"0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc\", \"proxy_type\": \"EIP_1967\", \"name\": \"eip1967.proxy.implementation\"}\n]";
+	let slots = parse_storage_slots("v1.json", source).unwrap();
+	assert_eq!(slots, vec![SlotEntry {
+	    slot: U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")),
+	    proxy_type: ProxyType::EIP_1967,
+	    name: "eip1967.proxy.implementation".to_string(),
+	}]);
+
+	let source = "[\n  {\"selector\": \"0xcdffacc6\", \"proxy_type\": \"EIP_2535\", \"signature\": \"facetAddress(bytes4)\"}\n]";
+	let selectors = parse_resolver_selectors("v1.json", source).unwrap();
+	assert_eq!((selectors[0].selector, selectors[0].kind), (Selector::from(0xcdffacc6), SelectorKind::Resolver));
+
+	// v2 didn't have the kind
+	let source = "{\"version\": 2, \"entries\": [\n  {\"selector\": \"0xcdffacc6\", \"proxy_type\": \"EIP_2535\", \"signature\": \"facetAddress(bytes4)\"}\n]}";
+	assert_eq!(parse_resolver_selectors("v2.json", source).unwrap()[0].kind, SelectorKind::Resolver);
+    }
+}
@@ -1,270 +1,1435 @@
 
-use crate::consts::{EIP_1967_DEFAULT_STORAGE, DIAMOND_STANDARD_STORAGE_SLOT_LESSBYTES, FUN_TO_PROXY};
 // use hardfork::Hardfork;
-use crate::proxy_inspector::{ProxyInspector, ProxyDetectDB, InspectorData};
-use once_cell::sync::Lazy;
-use revm::{inspector_handle_register, primitives::{TransactTo, TxEnv}, EvmBuilder};
+use std::{collections::HashMap, fmt::Debug, sync::{Arc, Mutex}, time::Duration};
+
+use crate::proxy_inspector::{analyzed_bytecode, synthetic_address, ProxyInspector, ProxyDetectDB, InspectorData};
+use revm::{inspector_handle_register, interpreter::opcode, primitives::{BlockEnv, Bytecode, ExecutionResult, Output, TransactTo, TxEnv}, EvmBuilder};
 use alloy_primitives::{Address, Bytes, U256};
+use ethers_core::abi::AbiEncode;
+use thiserror::Error;
 use tracing::debug;
 use twoway::find_bytes;
 
-use crate::{ProxyType, ProxyDispatch};
+use crate::abi::{UpgradeToAndCallCall, UpgradeToCall};
+use crate::utils::{match_namespace, opcode_iter, raddress_to_h160};
+use crate::calldata::{selector_call, CalldataStrategy, DefaultProbes, QuickProbes};
+use crate::blueprint::{parse_blueprint, BLUEPRINT_MAGIC};
+use crate::canonical::{canonicalize, pushed_address, MINIMAL_SKELETONS};
+use crate::consts::{slot_name, BEACON_IMPLEMENTATION_SELECTOR, DIAMOND_FACETS_SELECTOR, DIAMOND_STANDARD_STORAGE_SLOT, EIP_1822_PROXIABLE_SLOT, EIP_1967_ADMIN_SLOT, ZOS_ADMIN_SLOT, METAMORPHIC_INIT_CODE, METAMORPHIC_SELECTOR_OFFSET};
+use crate::disasm::{any_opcode, disassemble, find_push_value, fold_constants, scan, FoldedConstant, Instruction, OpcodePresence, Push32Constants, Push4Constants};
+use crate::environment::TraceEnvironment;
+use crate::findings::Finding;
+use crate::interface::recover_interface;
+use crate::progress::{NoProgress, ProgressEmitter, ProgressReporter};
+use crate::router::recover_router_table;
+use crate::profile::Ruleset;
+use crate::rules::{classify_trace, rule_tables, RuleId, RulePolicy, TraceObservations, CALLDATA_PROBES_SINCE, FOLDED_SLOTS_SINCE, FORWARDING_REQUIRED_SINCE, PROBE_RETRY_SINCE, SHIFTED_JUMPS_SINCE, SOLADY_PUSH0_PROLOGUE_SINCE, VANITY_PUSHES_SINCE};
+use crate::upgrade::split_metadata;
+use crate::types::{BlueprintInfo, ByteProvenance, ProvenanceKind, SlotPreimage};
+use crate::fork::ForkState;
+use crate::registry::{SelectorRegistry, SlotRegistry};
+use crate::{ProxyType, ProxyDispatch, ProxyDetectionResult, Selector};
+
+/// Configuration shared by every detector.
+#[derive(Clone, Debug, Default)]
+pub struct DetectorConfig {
+    pub rules: RulePolicy,
+    /// Seed for the synthetic environment, a random one is used for every run if `None`.
+    pub seed: Option<u64>,
+    /// Run the probes in a second environment and flag contracts that delegate differently.
+    pub anti_evasion: bool,
+    /// Follow how loaded slot values are shifted and masked, to resolve implementations packed
+    /// with other values in their slot (see [SlotExtraction](crate::SlotExtraction)).
+    pub layout_analysis: bool,
+    /// Trace more inputs than the quick probes, see [WidenedAnalysis].
+    pub widened: Option<WidenedAnalysis>,
+    /// Rules and tables to classify with, the latest unless pinned by an
+    /// [AnalysisProfile](crate::AnalysisProfile).
+    pub ruleset: Ruleset,
+    /// Namespaces to name custom slots after, see
+    /// [slot_namespace](crate::ProxyDetectionResult::slot_namespace).
+    pub namespaces: Vec<String>,
+    /// Probes to retry with when the quick ones all stop before forwarding anything.
+    pub retry: ProbeRetry,
+    /// Selectors whose STATICCALLs get a synthetic address back, so delegatecalls to what a
+    /// registry returns are seen, see [RuleId::RegistryDelegateCall]. `None` for
+    /// `implementation()` and the known resolver functions, or nothing for pinned rulesets
+    /// before 12.
+    pub synthetic_returns: Option<Vec<Selector>>,
+    /// Chooses the probes, [DefaultProbes] if `None`. Pinned rulesets before 10 default to the
+    /// quick probes alone and classify every probe.
+    pub calldata: Option<Arc<dyn CalldataStrategy>>,
+    /// Steps a traced call may run, only bound by its 30M gas if `None`. A probe running out
+    /// of steps or [timeout](Self::timeout) observed nothing, the others are still classified.
+    pub max_steps: Option<u64>,
+    /// Time a traced call may run.
+    pub timeout: Option<Duration>,
+    /// Detectors tried before the built-in ones, in order. The first to match wins, so they
+    /// shadow the built-in detectors.
+    pub strategies: Vec<Arc<dyn DetectionStrategy>>,
+    /// Only match the built-in patterns, without tracing the code.
+    pub static_only: bool,
+    /// Slots delegatecall targets are known to be loaded from, the ruleset's built-in ones if
+    /// `None`. Registered slots aren't part of the ruleset's
+    /// [fingerprint](crate::Ruleset::fingerprint).
+    pub slots: Option<SlotRegistry>,
+    /// Functions called on another contract to find the implementation, the ruleset's
+    /// built-in ones if `None`. They are also [synthetic_returns](Self::synthetic_returns) by
+    /// default.
+    pub selectors: Option<SelectorRegistry>,
+    /// Real chain state to trace over instead of synthetic storage and code, the proxy running
+    /// at its own address and block, see [detect_on_fork](crate::detect_on_fork).
+    pub fork: Option<Arc<ForkState>>,
+}
+
+/// A detector of the caller's, tried before the built-in ones, see
+/// [DetectorConfig::strategies].
+///
+/// ```
+/// use std::sync::Arc;
+/// use alloy_primitives::Address;
+/// use evm_proxy_tools::{detect_all, detect_proxy, DetectionStrategy, DetectorConfig, ProxyDetectionResult, ProxyDispatch, ProxyType, RuleId};
+///
+/// /// Our router: `PUSH2 0xfeed POP` then the `PUSH20` of its implementation.
+/// #[derive(Debug)]
+/// struct MarkedRouter;
+///
+/// impl DetectionStrategy for MarkedRouter {
+///     fn name(&self) -> &'static str {
+///         "marked_router"
+///     }
+///
+///     fn detect(&self, code: &[u8], _config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+///         let implementation = code.strip_prefix(&[0x61, 0xfe, 0xed, 0x50, 0x73])?.get(..20)?;
+///         let dispatch = ProxyDispatch::Static(Address::from_slice(implementation));
+///         Some(ProxyDetectionResult::new(ProxyType::StaticAddress, dispatch, RuleId::StaticDelegateCall))
+///     }
+/// }
+///
+/// let config = DetectorConfig { strategies: vec![Arc::new(MarkedRouter)], ..Default::default() };
+/// let code = [&[0x61, 0xfe, 0xed, 0x50, 0x73][..], &[0xbb; 20], &[0x00]].concat();
+/// let result = detect_proxy(&code, &config).unwrap();
+/// assert_eq!(result.dispatch, ProxyDispatch::Static(Address::repeat_byte(0xbb)));
+/// assert_eq!(detect_all(&code, &config)[0].0, "marked_router");
+/// ```
+pub trait DetectionStrategy: Debug + Send + Sync {
+    /// The detector's name in [detect_all].
+    fn name(&self) -> &'static str;
+
+    /// The proxy in `code`, `None` to leave it to the next detector.
+    fn detect(&self, code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult>;
+}
+
+impl DetectorConfig {
+    /// Whether `rule` may produce a result: enabled by the policy and part of the ruleset.
+    pub fn applies(&self, rule: RuleId) -> bool {
+	self.rules.is_enabled(rule) && self.ruleset.includes(rule)
+    }
+
+    /// The selectors whose STATICCALLs the dynamic detector answers, see
+    /// [synthetic_returns](Self::synthetic_returns).
+    pub fn synthetic_return_selectors(&self) -> Vec<Selector> {
+	match &self.synthetic_returns {
+	    Some(selectors) => selectors.clone(),
+	    None if self.ruleset.includes(RuleId::RegistryDelegateCall) => {
+		let mut selectors: Vec<Selector> = self.selector_registry().selectors().copied().collect();
+		selectors.push(BEACON_IMPLEMENTATION_SELECTOR);
+		selectors.sort();
+		selectors.dedup();
+		selectors
+	    },
+	    None => Vec::new(),
+	}
+    }
+
+    /// The known slots, see [slots](Self::slots).
+    pub fn slot_registry(&self) -> &SlotRegistry {
+	self.slots.as_ref().unwrap_or(&rule_tables(&self.ruleset).storage_slots)
+    }
+
+    /// The known resolver functions, see [selectors](Self::selectors).
+    pub fn selector_registry(&self) -> &SelectorRegistry {
+	self.selectors.as_ref().unwrap_or(&rule_tables(&self.ruleset).resolvers)
+    }
+
+    /// The environment probes are traced in, from [seed](Self::seed) and pinned to the
+    /// [fork](Self::fork).
+    fn trace_environment(&self) -> TraceEnvironment {
+	self.pinned(self.seed.map(TraceEnvironment::from_seed).unwrap_or_else(TraceEnvironment::random))
+    }
+
+    fn pinned(&self, env: TraceEnvironment) -> TraceEnvironment {
+	match &self.fork {
+	    Some(fork) => fork.pin(env),
+	    None => env,
+	}
+    }
+
+    /// The strategy the dynamic detector probes with.
+    pub fn calldata_strategy(&self) -> &dyn CalldataStrategy {
+	match &self.calldata {
+	    Some(strategy) => strategy.as_ref(),
+	    None if self.ruleset.version() >= CALLDATA_PROBES_SINCE => &DefaultProbes,
+	    None => &QuickProbes,
+	}
+    }
+}
+
+/// Probes run when none of the quick ones forwards and some revert, e.g. for fallbacks that
+/// only forward calls paying something. Calls the contract serves itself are left out of the
+/// classification. Pinned rulesets before 9 don't retry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeRetry {
+    /// Wei sent with the quick probes again, none if zero.
+    pub call_value: U256,
+    /// Also call with empty calldata, with and without `call_value`, like a plain transfer.
+    pub empty_calldata: bool,
+}
+
+impl Default for ProbeRetry {
+    fn default() -> Self {
+	Self { call_value: U256::from(1), empty_calldata: true }
+    }
+}
+
+/// Extra probes for contracts the quick pass got wrong, e.g. proxies whose forwarding depends on
+/// real storage or on the caller. Every set of probes is classified on its own and the first
+/// one that matches wins: the default probes over `storage`, then from each alternate caller,
+/// then `extra_calldata` and the harvested selectors.
+///
+/// Only the traced analysis is widened, codes matched statically give the same result.
+#[derive(Clone, Debug, Default)]
+pub struct WidenedAnalysis {
+    /// Storage values of the contract, e.g. read from a node, returned instead of synthetic
+    /// ones. Delegatecalls to a zero address loaded from them are ignored.
+    pub storage: HashMap<U256, U256>,
+    /// How many callers besides the environment's own to run the default probes from.
+    pub alternate_callers: usize,
+    /// Also call every selector the code's dispatcher compares against.
+    pub harvest_selectors: bool,
+    /// More calldata to trace, together with the harvested selectors.
+    pub extra_calldata: Vec<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum DetectError {
+    #[error("invalid bytecode at byte {position}: {reason}")]
+    InvalidBytecode { position: usize, reason: &'static str },
+    /// A detector panicked on the code, see [detect_many].
+    #[error("detection panicked: {0}")]
+    Panicked(String),
+}
+
+/// Why [trace_dispatch] couldn't trace a call.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum TraceError {
+    /// The EVM refused the transaction, e.g. the environment's caller can't pay for its gas.
+    #[error("the EVM couldn't execute the call: {0}")]
+    Transact(String),
+    /// The call ran out of [max_steps](TraceConfig::max_steps) or [timeout](TraceConfig::timeout)
+    /// after `steps` steps, at `pc` in the frame it was halted in.
+    #[error("the call was halted after {steps} steps, at pc {pc}")]
+    BudgetExceeded { steps: u64, pc: usize },
+}
+
+/// How [trace_dispatch] runs the code.
+#[derive(Clone, Debug)]
+pub struct TraceConfig {
+    /// The synthetic chain state, with the address the code runs at and the caller.
+    pub environment: TraceEnvironment,
+    /// See [DetectorConfig::layout_analysis].
+    pub layout_analysis: bool,
+    /// Real values of the contract's slots, see [WidenedAnalysis::storage].
+    pub storage: HashMap<U256, U256>,
+    /// Wei sent with the call, the environment's caller can afford plenty.
+    pub call_value: U256,
+    /// See [DetectorConfig::synthetic_returns], nothing is returned by default.
+    pub synthetic_returns: Vec<Selector>,
+    /// See [DetectorConfig::max_steps].
+    pub max_steps: Option<u64>,
+    /// See [DetectorConfig::timeout].
+    pub timeout: Option<Duration>,
+    /// What the synthetic storage values are XORed with to make addresses, the environment's
+    /// [storage_key](TraceEnvironment::storage_key) if `None`.
+    pub storage_key: Option<Address>,
+}
+
+impl TraceConfig {
+    pub fn new(environment: TraceEnvironment) -> Self {
+	Self { environment, layout_analysis: false, storage: HashMap::new(), call_value: U256::ZERO, synthetic_returns: Vec::new(), max_steps: None, timeout: None, storage_key: None }
+    }
+}
+
+impl Default for TraceConfig {
+    /// A random environment, like [DetectorConfig] without a seed.
+    fn default() -> Self {
+	Self::new(TraceEnvironment::random())
+    }
+}
+
+/// Runs `code` with `calldata` the way the detector does, returning what it observed whether
+/// or not it's a proxy. Calls out of the contract aren't executed, they return nothing.
+pub fn trace_dispatch(code: &Bytes, calldata: Bytes, config: &TraceConfig) -> Result<InspectorData, TraceError> {
+    StorageCallTaint::new(code, config.layout_analysis)
+	.with_storage(config.storage.clone())
+	.with_synthetic_returns(config.synthetic_returns.clone())
+	.with_budget(config.max_steps, config.timeout)
+	.with_storage_key(config.storage_key)
+	.try_trace_call(&config.environment, calldata, config.call_value)
+}
+
+/// Like [trace_dispatch] for each calldata of `calldata`, in order. The calls are traced one
+/// after the other with the same EVM, which is faster than tracing them one by one and
+/// observes the same.
+pub fn trace_dispatches<I>(code: &Bytes, calldata: I, config: &TraceConfig) -> Vec<Result<InspectorData, TraceError>>
+    where I: IntoIterator<Item = Bytes>
+{
+    StorageCallTaint::new(code, config.layout_analysis)
+	.with_storage(config.storage.clone())
+	.with_synthetic_returns(config.synthetic_returns.clone())
+	.with_budget(config.max_steps, config.timeout)
+	.with_storage_key(config.storage_key)
+	.try_trace_calls(&config.environment, calldata.into_iter().map(|calldata| (calldata, config.call_value)))
+}
+
+/// Codes shorter than this aren't analysed. The shortest code forwarding its calldata is 11 bytes,
+/// `365f5f375f5f365f335af4`: CALLDATACOPY, then DELEGATECALL to the caller, zeros pushed with
+/// PUSH0. The bound is a byte under it so that no forwarder is skipped, while the 1 to 3 byte
+/// stubs of destroyed contracts and cut off clone prologues never reach the tracer.
+pub const MIN_PROXY_CODE_SIZE: usize = 10;
+
+/// Whether `code` runs nothing: it starts with INVALID, like the `0xfe` placeholders, or is
+/// metadata only.
+fn is_inert(code: &[u8]) -> bool {
+    opcode_iter(split_metadata(code).0).next().is_none_or(|(_, op)| op == opcode::INVALID)
+}
+
+/// Whether `code` can run another contract's code as its own: it runs and has a DELEGATECALL or
+/// CALLCODE outside PUSH immediates.
+fn may_delegate(code: &[u8]) -> bool {
+    !is_inert(code) && opcode_iter(code).any(|(_, op)| op == opcode::DELEGATECALL || op == opcode::CALLCODE)
+}
+
+/// Whether tracing `code` can match a rule: it [may_delegate], or runs and copies another
+/// contract's code or pushes the selector of a resolver to call, see [RuleId::CodePointer] and
+/// [RuleId::ExternalResolver]. Most contracts fail it and aren't traced.
+fn worth_tracing(code: &[u8], config: &DetectorConfig) -> bool {
+    may_delegate(code) || (!is_inert(code) && disassemble(code).any(|ins| ins.opcode == opcode::EXTCODECOPY
+	|| (ins.opcode == opcode::PUSH4 && Selector::from_slice(ins.operand).is_some_and(|selector| config.selector_registry().contains_key(&selector)))))
+}
+
+/// Most selectors probed to map a diamond's facets, see
+/// [facet_slots](crate::ProxyDetectionResult::facet_slots).
+pub const MAX_FACET_PROBES: usize = 256;
+
+/// Why [detect_proxy_outcome] found no proxy in a code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotAProxyReason {
+    /// The code has no DELEGATECALL nor CALLCODE, or never runs them, it can't run another
+    /// contract's code as its own.
+    NoDelegatecall,
+    /// The code has a DELEGATECALL or CALLCODE that none of the probes delegated through.
+    DelegatecallNotReached,
+}
+
+/// What [detect_proxy_outcome] concluded about a code.
+// Returned by value once per code, boxing the proxies would cost an allocation for nothing
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum DetectOutcome {
+    /// Empty code: an EOA, an undeployed address or a destroyed contract.
+    NotAContract,
+    /// Shorter than [MIN_PROXY_CODE_SIZE], like the stubs left by some selfdestructed
+    /// contracts. No strategy was run.
+    CodeTooSmall(usize),
+    /// Every strategy ran, none matched.
+    NotAProxy { reason: NotAProxyReason },
+    /// No strategy matched but a probe delegated: a proxy no rule describes, or a contract
+    /// delegating to a library. `observations` are those of the first probe that delegated, to
+    /// re-queue the code for a deeper analysis or apply other heuristics.
+    Inconclusive { observations: InspectorData },
+    Proxy(ProxyDetectionResult),
+}
+
+impl DetectOutcome {
+    /// The outcome for codes too short to analyse, `None` for the others.
+    pub fn for_code_size(code: &[u8]) -> Option<DetectOutcome> {
+	match code.len() {
+	    0 => Some(DetectOutcome::NotAContract),
+	    len if len < MIN_PROXY_CODE_SIZE => Some(DetectOutcome::CodeTooSmall(len)),
+	    _ => None
+	}
+    }
+
+    pub fn into_proxy(self) -> Option<ProxyDetectionResult> {
+	match self {
+	    DetectOutcome::Proxy(result) => Some(result),
+	    _ => None
+	}
+    }
+}
 
 pub trait ProxyDetector {
-    fn try_match(code: &[u8]) -> Option<(ProxyType, ProxyDispatch)>;
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult>;
 }
 
 pub struct MinimalProxy {}
 
+/// How far into the code a minimal proxy body is searched for, past the guards some factories
+/// prepend (CALLVALUE checks, pushes of immutables).
+pub const MAX_MINIMAL_PREFIX_LEN: usize = 64;
+
+/// Address pushes a minimal proxy may use: PUSH20, or down to PUSH15 for vanity addresses whose
+/// leading zero bytes the factory strips.
+pub const MINIMAL_ADDRESS_PUSHES: std::ops::RangeInclusive<u8> = opcode::PUSH15..=opcode::PUSH20;
+
+/// Finds `prefix`, a PUSH15 to PUSH20 of an address and `suffix` in `code`, returning the offset
+/// of `prefix` and the address, left padded to 20 bytes.
+///
+/// `suffix` is the one assembled after a PUSH20. If `jump` is the index in it of a JUMPI target,
+/// that target moves back by the bytes a narrower push saves.
+///
+/// At offset 0 the code may go on after the pattern. Behind a prefix of up to
+/// [MAX_MINIMAL_PREFIX_LEN] bytes the pattern has to end the code but for its metadata, so
+/// unrelated code merely containing it doesn't match.
 #[inline(always)]
-pub fn extract_minimal_contract<const ADDR_SIZE: usize>(code: &[u8], min_size: usize, first_part: &[u8], second_part: &[u8]) -> Option<Address> {
-    let second_start = first_part.len() + ADDR_SIZE;
-    if code.len() >= min_size && &code[0..first_part.len()] == first_part && &code[second_start..second_start + second_part.len()] == second_part {
-	let addr = &code[first_part.len()..second_start];
-	if ADDR_SIZE == 16 {
-	    let mut addr_vec = vec![0; 20];
-	    addr_vec[4..].clone_from_slice(addr);
-	    Some(Address::from_slice(&addr_vec))
-	    // Some
-	} else {
-	    Some(Address::from_slice(&addr))
-	}
+pub fn extract_minimal_contract(code: &[u8], prefix: &[u8], suffix: &[u8], jump: Option<usize>) -> Option<(usize, Address)> {
+    // Width of the address pushed right after a prefix at `start`
+    let push_width = |start: usize| code.get(start + prefix.len())
+	.filter(|op| MINIMAL_ADDRESS_PUSHES.contains(op))
+	.map(|op| (op - opcode::PUSH0) as usize);
+    let suffix_start = |start: usize, width: usize| start + prefix.len() + 1 + width;
+    let suffix_matches = |found: &[u8], width: usize| found.iter().zip(suffix).enumerate().all(|(idx, (found, expected))| match jump {
+	Some(jump) if jump == idx => expected.checked_sub((20 - width) as u8) == Some(*found),
+	_ => found == expected,
+    });
+    let suffix_end = |start: usize| push_width(start)
+	.map(|width| (suffix_start(start, width), width))
+	.filter(|(from, width)| code.get(*from..*from + suffix.len()).is_some_and(|found| suffix_matches(found, *width)))
+	.map(|(from, _)| from + suffix.len());
+    let start = if code.starts_with(prefix) && suffix_end(0).is_some() {
+	0
     } else {
-	None
-    }
+	let body_end = split_metadata(code).0.len();
+	let window = &code[..code.len().min(MAX_MINIMAL_PREFIX_LEN + prefix.len())];
+	let mut from = 1;
+	loop {
+	    let start = from + find_bytes(window.get(from..)?, prefix)?;
+	    if suffix_end(start) == Some(body_end) {
+		break start;
+	    }
+	    from = start + 1;
+	}
+    };
+    let width = push_width(start)?;
+    let mut address = [0u8; 20];
+    address[20 - width..].copy_from_slice(&code[start + prefix.len() + 1..suffix_start(start, width)]);
+    Some((start, Address::from(address)))
+}
+
+/// `idx`, the index of the JUMPI target in a minimal proxy suffix, if `ruleset` moves it with
+/// the width of the address push.
+fn jump_target(ruleset: &Ruleset, idx: usize) -> Option<usize> {
+    (ruleset.version() >= SHIFTED_JUMPS_SINCE).then_some(idx)
 }
 
 impl MinimalProxy {
-    fn is_eip_1667_long(code: &[u8]) -> Option<Address> {
-	const EIP_1667_FIRST_BYTES: &[u8] = &hex_literal::hex!("363d3d373d3d3d363d73");
+    fn is_eip_1667(code: &[u8], ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const EIP_1667_FIRST_BYTES: &[u8] = &hex_literal::hex!("363d3d373d3d3d363d");
 	const EIP_1667_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d82803e903d91602b57fd5bf3");
 
-	extract_minimal_contract::<20>(code, 45, EIP_1667_FIRST_BYTES, EIP_1667_SECOND_BYTES)
+	extract_minimal_contract(code, EIP_1667_FIRST_BYTES, EIP_1667_SECOND_BYTES, jump_target(ruleset, 10))
     }
 
-    fn is_eip_1667_short(code: &[u8]) -> Option<Address> {
-	const EIP_1667_FIRST_BYTES: &[u8] = &hex_literal::hex!("363d3d373d3d3d363d6f");
-	const EIP_1667_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d82803e903d91602b57fd5bf3");
+    fn is_eip_7511(code: &[u8], ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const EIP_7511_FIRST_BYTES: &[u8] = &hex_literal::hex!("365f5f375f5f365f");
+	const EIP_7511_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d5f5f3e5f3d91602a57fd5bf3");
 
-	extract_minimal_contract::<16>(code, 41, EIP_1667_FIRST_BYTES, EIP_1667_SECOND_BYTES)
+	extract_minimal_contract(code, EIP_7511_FIRST_BYTES, EIP_7511_SECOND_BYTES, jump_target(ruleset, 10))
     }
 
-    fn is_eip_7511_long(code: &[u8]) -> Option<Address> {
-	const EIP_7511_FIRST_BYTES: &[u8] = &hex_literal::hex!("365f5f375f5f365f73");
-	const EIP_7511_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d5f5f3e5f3d91602a57fd5bf3");
+    fn is_eip_3448(code: &[u8], ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const EIP_3448_FIRST_BYTES: &[u8] = &hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d");
+	const EIP_3448_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d3d93803e603457fd5bf3");
 
-	extract_minimal_contract::<20>(code, 44, EIP_7511_FIRST_BYTES, EIP_7511_SECOND_BYTES)
+	extract_minimal_contract(code, EIP_3448_FIRST_BYTES, EIP_3448_SECOND_BYTES, jump_target(ruleset, 8))
     }
 
-    fn is_eip_7511_short(code: &[u8]) -> Option<Address> {
-	const EIP_7511_FIRST_BYTES: &[u8] = &hex_literal::hex!("365f5f375f5f365f6f");
-	const EIP_7511_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d5f5f3e5f3d91602a57fd5bf3");
+    /// Solady's `LibClone.clone_PUSH0`: pushes the zeros of the return area before copying the
+    /// calldata, and ends with a `JUMPI` based epilogue. Rulesets before 17 expected the EIP-7511
+    /// prologue instead.
+    fn is_solady_push0(code: &[u8], ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const SOLADY_PUSH0_FIRST_BYTES: &[u8] = &hex_literal::hex!("5f5f365f5f37365f");
+	const SOLADY_PUSH0_LEGACY_FIRST_BYTES: &[u8] = &hex_literal::hex!("365f5f375f5f365f");
+	const SOLADY_PUSH0_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d5f5f3e6029573d5ffd5b3d5ff3");
 
-	extract_minimal_contract::<16>(code, 40, EIP_7511_FIRST_BYTES, EIP_7511_SECOND_BYTES)
+	let first_bytes = if ruleset.version() >= SOLADY_PUSH0_PROLOGUE_SINCE { SOLADY_PUSH0_FIRST_BYTES } else { SOLADY_PUSH0_LEGACY_FIRST_BYTES };
+	extract_minimal_contract(code, first_bytes, SOLADY_PUSH0_SECOND_BYTES, jump_target(ruleset, 7))
     }
 
-    fn is_eip_3448_long(code: &[u8]) -> Option<Address> {
-	const EIP_3448_FIRST_BYTES: &[u8] = &hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d73");
-	const EIP_3448_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d3d93803e603457fd5bf3");
+    /// Solady's `LibClone` clone with immutable args: a `receive` emitting `ReceiveETH`, then
+    /// calldata followed by the args is delegated. The args length is a PUSH2 ahead of the
+    /// address and the args trail the runtime, so only the bytes around both are anchored.
+    fn is_solady_cwia(code: &[u8], _ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const SOLADY_CWIA_FIRST_BYTES: &[u8] = &hex_literal::hex!("36602c57343d527f9e4ac34f21c619cefc926c8bd93b54bf5a39c7ab2127a895af1cc0691d7e3dff593da1005b363d3d373d3d3d3d61");
+	const SOLADY_CWIA_MIDDLE_BYTES: &[u8] = &hex_literal::hex!("806062363936013d");
+	const SOLADY_CWIA_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d3d93803e606057fd5bf3");
 
-	extract_minimal_contract::<20>(code, 44, EIP_3448_FIRST_BYTES, EIP_3448_SECOND_BYTES)
+	let middle_start = SOLADY_CWIA_FIRST_BYTES.len() + 2;
+	let (head, rest) = code.split_at_checked(middle_start)?;
+	if !head.starts_with(SOLADY_CWIA_FIRST_BYTES) {
+	    return None;
+	}
+	// The middle is anchored right after the length, and with a fixed runtime length the
+	// address is always a PUSH20
+	extract_minimal_contract(rest, SOLADY_CWIA_MIDDLE_BYTES, SOLADY_CWIA_SECOND_BYTES, None)
+	    .filter(|(start, _)| *start == 0 && rest[SOLADY_CWIA_MIDDLE_BYTES.len()] == opcode::PUSH20)
     }
 
-    fn is_eip_3448_short(code: &[u8]) -> Option<Address> {
-	const EIP_3448_FIRST_BYTES: &[u8] = &hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d6f");
-	const EIP_3448_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af43d3d93803e603457fd5bf3");
+    /// The args of a Solady clone with immutable args: everything after the runtime but the
+    /// trailing 2 byte length, which counts itself and must match the code.
+    fn solady_cwia_args(code: &[u8]) -> Option<&[u8]> {
+	const SOLADY_CWIA_RUNTIME_LEN: usize = 98;
 
-	extract_minimal_contract::<16>(code, 44, EIP_3448_FIRST_BYTES, EIP_3448_SECOND_BYTES)
+	let extra = code.get(SOLADY_CWIA_RUNTIME_LEN..).filter(|extra| extra.len() >= 2)?;
+	let (args, len) = extra.split_at(extra.len() - 2);
+	(u16::from_be_bytes([len[0], len[1]]) as usize == extra.len()).then_some(args)
     }
 
-    fn is_eip_3448(code: &[u8]) -> Option<Address> {
-	Self::is_eip_3448_long(code).or_else(|| Self::is_eip_3448_short(code))
+    /// Vyper 0.2 `create_forwarder_to`: copies at most 4096 bytes of returndata, reverts with
+    /// `REVERT` on failure.
+    fn is_vyper_forwarder_v2(code: &[u8], ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const VYPER_FORWARDER_FIRST_BYTES: &[u8] = &hex_literal::hex!("3660006000376110006000366000");
+	const VYPER_FORWARDER_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af4602c57600080fd5b6110006000f3");
+
+	extract_minimal_contract(code, VYPER_FORWARDER_FIRST_BYTES, VYPER_FORWARDER_SECOND_BYTES, jump_target(ruleset, 3))
     }
 
-    fn is_eip_7511(code: &[u8]) -> Option<Address> {
-	Self::is_eip_7511_long(code).or_else(|| Self::is_eip_7511_short(code))
+    /// Vyper 0.1 `create_forwarder_to`: as 0.2 but fails with `ISZERO PC JUMPI`, an invalid jump.
+    fn is_vyper_forwarder_v1(code: &[u8], _ruleset: &Ruleset) -> Option<(usize, Address)> {
+	const VYPER_FORWARDER_FIRST_BYTES: &[u8] = &hex_literal::hex!("3660006000376110006000366000");
+	const VYPER_FORWARDER_SECOND_BYTES: &[u8] = &hex_literal::hex!("5af41558576110006000f3");
+
+	extract_minimal_contract(code, VYPER_FORWARDER_FIRST_BYTES, VYPER_FORWARDER_SECOND_BYTES, None)
     }
 
-    fn is_eip_1667(code: &[u8]) -> Option<Address> {
-	Self::is_eip_1667_long(code).or_else(|| Self::is_eip_1667_short(code))
+    /// Forwarders deployed by Vyper 0.1 and 0.2. From 0.3 on, `create_forwarder_to` (and its
+    /// replacement `create_minimal_proxy_to`) deploys the standard EIP-1167 runtime, which is
+    /// matched as [ProxyType::EIP_1167] instead.
+    fn is_vyper_forwarder(code: &[u8], ruleset: &Ruleset) -> Option<(usize, Address)> {
+	Self::is_vyper_forwarder_v2(code, ruleset).or_else(|| Self::is_vyper_forwarder_v1(code, ruleset))
     }
+}
+
+/// Finds a minimal proxy body, returning its offset in the code and the address.
+type AddressMatcher = fn(&[u8], &Ruleset) -> Option<(usize, Address)>;
+
+/// Extracts the immutable args a matched clone appends to its runtime.
+type ArgsMatcher = fn(&[u8]) -> Option<&[u8]>;
 
+impl MinimalProxy {
+    /// Matches forwarders that only differ from the known ones in how they push zeros (PUSH0,
+    /// PUSH1 0x00, DUP of a zero...), see [canonicalize].
+    fn try_match_canonical(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	let canonical = canonicalize(code);
+	MINIMAL_SKELETONS.iter()
+	    .filter(|skeleton| config.applies(skeleton.rule))
+	    .find_map(|skeleton| skeleton.matches(&canonical).map(|push| {
+		let mut result = ProxyDetectionResult::new(skeleton.proxy_type, ProxyDispatch::Static(pushed_address(&push)), skeleton.rule);
+		result.provenance.push(ByteProvenance::new(ProvenanceKind::ImplementationAddress, push.operand_offset(), push.operand.len()));
+		result
+	    }))
+    }
 }
 
 impl ProxyDetector for  MinimalProxy {
-    fn try_match(code: &[u8]) -> Option<(ProxyType, ProxyDispatch)> {
-	if let Some(address) = Self::is_eip_1667(code) {
-	    Some((ProxyType::EIP_1167, ProxyDispatch::Static(address)))
-	} else if let Some(address) = Self::is_eip_7511(code) {
-	    Some((ProxyType::EIP_7511, ProxyDispatch::Static(address)))
-	} else if let Some(address) = Self::is_eip_3448(code) {
-	    Some((ProxyType::EIP_3448, ProxyDispatch::Static(address)))
-	} else {
-	    None
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	// The address is pushed right after each prefix
+	let matchers: [(RuleId, ProxyType, usize, AddressMatcher, Option<ArgsMatcher>); 6] = [
+	    (RuleId::Eip1167Pattern, ProxyType::EIP_1167, 9, Self::is_eip_1667, None),
+	    (RuleId::Eip7511Pattern, ProxyType::EIP_7511, 8, Self::is_eip_7511, None),
+	    (RuleId::Eip3448Pattern, ProxyType::EIP_3448, 20, Self::is_eip_3448, None),
+	    (RuleId::SoladyClonePattern, ProxyType::SoladyClone, 8, Self::is_solady_push0, None),
+	    (RuleId::SoladyClonePattern, ProxyType::SoladyClone, 64, Self::is_solady_cwia, Some(Self::solady_cwia_args)),
+	    (RuleId::VyperForwarderPattern, ProxyType::VyperForwarder, 14, Self::is_vyper_forwarder, None),
+	];
+	matchers.iter()
+	    .filter(|(rule, _, _, _, _)| config.applies(*rule))
+	    .find_map(|(rule, proxy_type, prefix_len, matcher, args_matcher)| matcher(code, &config.ruleset)
+		// Older rulesets only knew the full and the 16 byte address
+		.filter(|(start, _)| config.ruleset.version() >= VANITY_PUSHES_SINCE || matches!(code[start + prefix_len], opcode::PUSH16 | opcode::PUSH20))
+		.map(|(start, address)| {
+		    let args = args_matcher.and_then(|args_matcher| args_matcher(code)).filter(|args| !args.is_empty());
+		    let dispatch = match args {
+			Some(args) => ProxyDispatch::StaticWithArgs(address, Bytes::copy_from_slice(args)),
+			None => ProxyDispatch::Static(address),
+		    };
+		    let mut result = ProxyDetectionResult::new(*proxy_type, dispatch, *rule);
+		    let push_len = (code[start + prefix_len] - opcode::PUSH0) as usize;
+		    result.provenance.push(ByteProvenance::new(ProvenanceKind::ImplementationAddress, start + prefix_len + 1, push_len));
+		    if let Some(args) = args {
+			// Args are a suffix of the code but for the length word
+			result.provenance.push(ByteProvenance::new(ProvenanceKind::ImmutableArgs, code.len() - 2 - args.len(), args.len()));
+		    }
+		    result
+		}))
+	    .or_else(|| Self::try_match_canonical(code, config))
+    }
+}
+
+struct GeneratedRouter {}
+
+impl ProxyDetector for GeneratedRouter {
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	if !config.applies(RuleId::GeneratedRouterPattern) {
+	    return None;
 	}
+	let table = recover_router_table(code)?;
+	let dispatch = ProxyDispatch::PerSelector(table.iter().map(|entry| (entry.selector, entry.implementation)).collect());
+	let mut result = ProxyDetectionResult::new(ProxyType::GeneratedRouter, dispatch, RuleId::GeneratedRouterPattern);
+	result.provenance = table.iter().map(|entry| ByteProvenance::new(ProvenanceKind::ImplementationAddress, entry.offset, 20)).collect();
+	Some(result)
     }
 }
 
-struct StorageSlotProxy {}
+/// Runtime of the Safe 1.3.0 and 1.4.1 proxies without their metadata: returns slot 0 for
+/// `masterCopy()` and delegates everything else to it.
+const SAFE_PROXY_RUNTIME: &[u8] = &hex_literal::hex!("608060405273ffffffffffffffffffffffffffffffffffffffff600054167fa619486e0000000000000000000000000000000000000000000000000000000060003514156050578060005260206000f35b3660008037600080366000845af43d6000803e60008114156070573d6000fd5b3d6000f3fe");
 
-impl StorageSlotProxy {
+/// Offset of the `PUSH1 0` slot immediate in [SAFE_PROXY_RUNTIME].
+const SAFE_PROXY_SLOT_OFFSET: usize = 27;
+
+struct SafeProxy {}
 
+impl ProxyDetector for SafeProxy {
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	if !config.applies(RuleId::SafeProxyPattern) || split_metadata(code).0 != SAFE_PROXY_RUNTIME {
+	    return None;
+	}
+	let mut result = ProxyDetectionResult::new(ProxyType::GnosisSafe, ProxyDispatch::Storage(U256::ZERO, None), RuleId::SafeProxyPattern);
+	result.provenance.push(ByteProvenance::new(ProvenanceKind::SlotConstant, SAFE_PROXY_SLOT_OFFSET, 1));
+	Some(result)
+    }
 }
 
+/// OpenZeppelin `BeaconProxy` and look-alikes: the code pushes an EIP-1967 beacon slot and the
+/// `implementation()` selector, and both STATICCALLs and DELEGATECALLs. Codes also pushing the
+/// EIP-1967 implementation slot are left to the tracer, they may just support both.
+struct BeaconProxy {}
 
-struct StorageCallTaint {
-    code: Bytes,
-    address: Address
+impl ProxyDetector for BeaconProxy {
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	if !config.applies(RuleId::BeaconProxyPattern) {
+	    return None;
+	}
+	let folded = if config.ruleset.version() >= FOLDED_SLOTS_SINCE { fold_constants(code) } else { Vec::new() };
+	let mut beacon_slot = None;
+	for (slot, proxy_type) in config.slot_registry().iter() {
+	    match (slot_pushes(code, &folded, slot), proxy_type) {
+		(Some(pushes), ProxyType::EIP_1967_BEACON) => beacon_slot = Some((*slot, pushes)),
+		(Some(_), _) => return None,
+		(None, _) => ()
+	    }
+	}
+	let (slot, slot_pushes) = beacon_slot?;
+	let selector_push = find_push_value(code, &U256::from(BEACON_IMPLEMENTATION_SELECTOR.to_u32_be()), 4)?;
+	if !any_opcode(code, &[opcode::STATICCALL]) || !any_opcode(code, &[opcode::DELEGATECALL]) {
+	    return None;
+	}
+	let mut result = ProxyDetectionResult::new(ProxyType::EIP_1967_BEACON, ProxyDispatch::Beacon(slot), RuleId::BeaconProxyPattern);
+	result.slot_name = config.slot_registry().name(&slot).or_else(|| slot_name(&slot)).map(str::to_string);
+	result.provenance = slot_pushes.iter().map(|push| ByteProvenance::new(ProvenanceKind::SlotConstant, push.operand_offset(), push.operand.len())).collect();
+	result.provenance.push(ByteProvenance::new(ProvenanceKind::SelectorConstant, selector_push.operand_offset(), selector_push.operand.len()));
+	Some(result)
+    }
 }
 
-static DEFAULT_CALLER_ADDRESS: Lazy<Address> = Lazy::new(|| hex_literal::hex!("11ff0000ff0000ff0000ff0000ff0000ff0000ff").into());
-static DEFAULT_CONTRACT_ADDRESS: Lazy<Address> = Lazy::new(|| hex_literal::hex!("00ff0000ff0000ff0000ff0000ff0000ff0000ff").into());
+/// 0age's metamorphic init code. It isn't a proxy, but the runtime it deploys is whatever the
+/// factory serves at the time, so the contract can be replaced under the same address. The
+/// factory is the deployer, the code doesn't embed it.
+struct MetamorphicInit {}
 
+impl ProxyDetector for MetamorphicInit {
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	if !config.applies(RuleId::MetamorphicInitPattern) || code != METAMORPHIC_INIT_CODE {
+	    return None;
+	}
+	let mut result = ProxyDetectionResult::new(ProxyType::Metamorphic, ProxyDispatch::Unknown, RuleId::MetamorphicInitPattern);
+	result.provenance.push(ByteProvenance::new(ProvenanceKind::SelectorConstant, METAMORPHIC_SELECTOR_OFFSET, 4));
+	Some(result)
+    }
+}
+
+struct StorageSlotProxy {}
 
-impl StorageCallTaint {
+impl StorageSlotProxy {
 
-    pub fn new_with_info(code: &[u8], address: Address, _caller: Address) -> Self {
+}
+
+
+struct StorageCallTaint<'a> {
+    code: &'a [u8],
+    /// The only copy of `code`, shared by every traced run.
+    bytecode: Bytecode,
+    track_layout: bool,
+    /// Real storage values, see [WidenedAnalysis::storage].
+    storage: HashMap<U256, U256>,
+    /// Real state of every account, see [DetectorConfig::fork].
+    fork: Option<Arc<ForkState>>,
+    /// See [TraceConfig::storage_key].
+    storage_key: Option<Address>,
+    /// The calldata of the probes, see [CalldataStrategy].
+    probes: Vec<Bytes>,
+    /// Run the STATICCALLs the contract makes to itself, see [RuleId::ReadOnlyRouter].
+    static_self_calls: bool,
+    /// See [DetectorConfig::synthetic_returns].
+    synthetic_returns: Vec<Selector>,
+    /// See [DetectorConfig::max_steps].
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+}
+
+impl<'a> StorageCallTaint<'a> {
+
+    pub fn new(code: &'a [u8], track_layout: bool) -> Self {
 	Self {
-	    code: Bytes::copy_from_slice(code),
-	    address
+	    code,
+	    bytecode: analyzed_bytecode(code),
+	    track_layout,
+	    storage: HashMap::new(),
+	    fork: None,
+	    storage_key: None,
+	    probes: QuickProbes.probes(&Bytes::new()),
+	    static_self_calls: false,
+	    synthetic_returns: Vec::new(),
+	    max_steps: None,
+	    timeout: None,
 	}
     }
 
-    pub fn new(code: &[u8]) -> Self {
-	Self::new_with_info(code, *DEFAULT_CONTRACT_ADDRESS, *DEFAULT_CALLER_ADDRESS)
+    pub fn with_budget(mut self, max_steps: Option<u64>, timeout: Option<Duration>) -> Self {
+	self.max_steps = max_steps;
+	self.timeout = timeout;
+	self
+    }
+
+    pub fn with_static_self_calls(mut self, enabled: bool) -> Self {
+	self.static_self_calls = enabled;
+	self
     }
 
-    pub fn trace_calldata(&self, calldata: Bytes) -> InspectorData {
+    pub fn with_synthetic_returns(mut self, selectors: Vec<Selector>) -> Self {
+	self.synthetic_returns = selectors;
+	self
+    }
+
+    pub fn with_calldata_strategy(mut self, strategy: &dyn CalldataStrategy) -> Self {
+	self.probes = strategy.probes(&Bytes::copy_from_slice(self.code));
+	self
+    }
+
+    pub fn with_storage(mut self, storage: HashMap<U256, U256>) -> Self {
+	self.storage = storage;
+	self
+    }
+
+    pub fn with_fork(mut self, fork: Option<Arc<ForkState>>) -> Self {
+	self.fork = fork;
+	self
+    }
+
+    pub fn with_storage_key(mut self, key: Option<Address>) -> Self {
+	self.storage_key = key;
+	self
+    }
+
+    /// Traces a call with each calldata of `calldata` in `env`, with one EVM. A transaction the
+    /// EVM refuses traced nothing.
+    fn trace_calldata<I>(&self, env: &TraceEnvironment, calldata: I) -> Vec<InspectorData>
+	where I: IntoIterator<Item = Bytes>
+    {
+	self.try_trace_calls(env, calldata.into_iter().map(|calldata| (calldata, U256::ZERO))).into_iter().map(Result::unwrap_or_default).collect()
+    }
+
+    fn try_trace_call(&self, env: &TraceEnvironment, calldata: Bytes, value: U256) -> Result<InspectorData, TraceError> {
+	self.try_trace_calls(env, [(calldata, value)]).remove(0)
+    }
+
+    /// Traces a call per calldata and value of `calls` in `env`, one after the other with the
+    /// same EVM and database: the code is installed once, what a call gathered is reset before
+    /// the next.
+    fn try_trace_calls<I>(&self, env: &TraceEnvironment, calls: I) -> Vec<Result<InspectorData, TraceError>>
+	where I: IntoIterator<Item = (Bytes, U256)>
+    {
 
 	// init revm
-	let mut db = ProxyDetectDB::new(self.address);
-	db.install_contract(self.address, &self.code);
+	let mut db = ProxyDetectDB::new(env.clone()).with_packed_values(self.track_layout).with_known_storage(self.storage.clone())
+	    .with_fork(self.fork.clone());
+	if let Some(key) = self.storage_key {
+	    db = db.with_storage_key(key);
+	}
+	db.install_contract(env.contract, &self.bytecode);
 
-	let inspector = ProxyInspector::new();
+	let inspector = ProxyInspector::new().with_layout_tracking(self.track_layout).with_static_self_calls(self.static_self_calls)
+	    .with_synthetic_returns(self.synthetic_returns.clone()).with_budget(self.max_steps, self.timeout);
 
         let mut evm = EvmBuilder::default()
             .with_db(db)
             .with_external_context(inspector)
             .append_handler_register(inspector_handle_register)
+            .modify_block_env(|block: &mut BlockEnv| fill_block_env(block, env))
             .modify_tx_env(|tx: &mut TxEnv| {
-                tx.transact_to = TransactTo::Call(self.address);
-                tx.data = calldata;
-                tx.value = U256::ZERO;
+                tx.caller = env.caller;
+                tx.transact_to = TransactTo::Call(env.contract);
+                tx.gas_price = U256::from(env.basefee);
                 // Block gas limit is 30M
                 tx.gas_limit = 30_000_000;
             })
             .build();
 
-        let _res = evm.transact();
-	// if let Ok(ok_res) = res {
-	//     println!("success");
-	// } else {
-	//     println!("fail");
-	// }
-	// println!("res: {:?}", res);
-	// let db = evm.db.unwrap();
-        evm.context.external.collect()
-    }
-
-    fn identify_proxy_by_storage(storage: &U256) -> ProxyType {
-	if let Some(proxy) = EIP_1967_DEFAULT_STORAGE.get(storage) {
-	    *proxy
-	} else if *storage > U256::from(0x100) {
-	    ProxyType::EIP_1967_CUSTOM
-	} else {
-	    ProxyType::EIP_897
-	}
+	calls.into_iter().map(|(calldata, value)| {
+	    // Each call gets the whole budget
+	    evm.context.external.reset();
+	    evm.db_mut().reset();
+	    let tx = evm.tx_mut();
+	    tx.data = calldata.clone();
+	    tx.value = value;
+	    match evm.transact() {
+		Err(e) => Err(TraceError::Transact(e.to_string())),
+		Ok(result) => match evm.context.external.exhausted() {
+		    Some((steps, pc)) => {
+			debug!("probe halted after {} steps at pc {}", steps, pc);
+			Err(TraceError::BudgetExceeded { steps, pc })
+		    },
+		    None => {
+			let mut data = evm.context.external.collect();
+			data.reverted = !result.result.is_success();
+			data.calldata = calldata;
+			Ok(data)
+		    },
+		},
+	    }
+	}).collect()
     }
 
+    /// Whether every run observed the same, reverting on some probes only doesn't count.
     fn check_all_are_equal(data: &[InspectorData]) -> bool {
-	let first = &data[0];
-	data.iter().all(|e| e == first)
+	let Some(first) = data.first() else {
+	    return true;
+	};
+	// The call tree has the probe's own selector, only some functions check their targets'
+	// code size and mappings are keyed by selector
+	data.iter().all(|e| InspectorData {
+	    reverted: first.reverted,
+	    calldata: first.calldata.clone(),
+	    calls: first.calls.clone(),
+	    code_reads: first.code_reads.clone(),
+	    code_read_storage: first.code_read_storage.clone(),
+	    code_jump: first.code_jump,
+	    keccaks: first.keccaks.clone(),
+	    ..e.clone()
+	} == *first)
     }
 
-    fn detect_proxy_from_data(&self, data: &[InspectorData]) -> Option<(ProxyType, ProxyDispatch)> {
-	// First check if all the calldata were equals
-	// println!("data: {:#?}", data);
+    /// The address the contract finds in `slot` in `env`: its real value if known, else the
+    /// synthetic one.
+    fn slot_address(&self, env: &TraceEnvironment, slot: &U256) -> Address {
+	match self.storage.get(slot) {
+	    Some(value) => Address::from_word(value.to_be_bytes::<32>().into()),
+	    None => synthetic_address(slot, &self.storage_key.unwrap_or_else(|| env.storage_key())),
+	}
+    }
+
+    /// Classifies `data`, traced in `env`. If the runs loaded an admin slot the probes are run
+    /// again from the admin, to see whether it's served differently. Since ruleset 13 static
+    /// addresses and EIP-1967 slots whose delegatecall doesn't forward the call in every run
+    /// are [ProxyType::DelegatesButNotProxy]. Without runs, e.g. from a calldata strategy
+    /// returning no probes, there is nothing to classify.
+    fn detect_proxy_from_data(&self, env: &TraceEnvironment, data: &[InspectorData], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
 	debug!("inspector_data: {:#?}", data);
+	if data.is_empty() {
+	    return None;
+	}
+
+	let admin_slot = data.iter().flat_map(|run| &run.storage_access).find(|slot| [EIP_1967_ADMIN_SLOT, ZOS_ADMIN_SLOT].contains(slot)).copied();
+	let admin_runs = match admin_slot {
+	    Some(slot) if config.applies(RuleId::TransparentAdminBranch) => self.trace_probes(&env.clone().with_caller(self.slot_address(env, &slot))),
+	    _ => Vec::new(),
+	};
+	let consistent = Self::check_all_are_equal(data);
+	let observations = TraceObservations {
+	    code: self.code,
+	    runs: data,
+	    admin_runs: &admin_runs,
+	    consistent,
+	    slots: config.slot_registry(),
+	    resolvers: config.selector_registry(),
+	};
+	classify_trace(&observations, &config.rules, &config.ruleset).map(|(mut proxy_type, dispatch, rule)| {
+	    let forwarding = data.iter().filter(|run| !run.static_delegation.is_empty()).all(InspectorData::forwards_calldata);
+	    if matches!(proxy_type, ProxyType::StaticAddress | ProxyType::EIP_1967) && config.ruleset.version() >= FORWARDING_REQUIRED_SINCE && !forwarding {
+		proxy_type = ProxyType::DelegatesButNotProxy;
+	    }
+	    let mut result = ProxyDetectionResult::new(proxy_type, dispatch, rule);
+	    result.provenance = dispatch_provenance(self.code, &result.dispatch);
+	    let trigger = data.iter().find(|run| Self::forwards(run));
+	    result.trigger_calldata = trigger.map(|run| run.calldata.clone());
+	    result.probes_agree = Some(consistent);
+	    // Beacons and code pointers hold their target at one remove
+	    result.raw_slot = trigger.and_then(|run| run.delegatecall_storage.first()
+		.or(run.storage_calls.first().map(|(slot, _)| slot))
+		.or(run.code_read_storage.first()).copied());
+	    result.admin_slot = admin_slot;
+	    result.upgradeable_slots = self.upgradeable_slots(env, admin_slot);
+	    if let (ProxyType::EIP_1967_CUSTOM | ProxyType::ImmutableSlotProxy, ProxyDispatch::Storage(slot, _)) = (result.proxy_type, &result.dispatch) {
+		result.slot_namespace = match_namespace(&config.namespaces, slot).map(str::to_string);
+	    }
+	    if let ProxyDispatch::Storage(slot, _) | ProxyDispatch::Beacon(slot) = &result.dispatch {
+		result.slot_preimage = data.iter().find_map(|run| run.preimage_of(slot))
+		    .map(|(preimage, minus_one)| SlotPreimage { preimage: preimage.clone(), minus_one });
+		result.slot_name = config.slot_registry().name(slot).or_else(|| slot_name(slot)).map(str::to_string);
+	    }
+	    if matches!(result.proxy_type, ProxyType::EIP_2535 | ProxyType::DiamondOther) {
+		result.facet_slots = self.facet_slots(env);
+	    }
+	    result
+	})
+    }
 
-	let consistent_execution = Self::check_all_are_equal(data);
-	// println!("consistent: {}", consistent_execution);
-	if consistent_execution {
-	    if data[0].delegatecall_unknown.len() == 1 {
-		let static_address = data[0].delegatecall_unknown[0];
-		Some((ProxyType::StaticAddress, ProxyDispatch::Static(static_address)))
-	    }  else if data[0].delegatecall_storage.len() == 1{
-		let storage_slot = data[0].delegatecall_storage[0];
-		Some((Self::identify_proxy_by_storage(&storage_slot), ProxyDispatch::Storage(storage_slot)))
-	    } else if data[0].external_calls.len() ==1 {
-		let address = data[0].external_calls[0].0;
-		let fun = data[0].external_calls[0].1;
-		if FUN_TO_PROXY.contains_key(&fun) {
-		    // let proxy = FUN_TO_PROXY.get(&fun);
-		    Some((ProxyType::External, ProxyDispatch::External(address, fun)))
-		} else {
-		    None
+    /// The slot each selector pushed by the code loads its delegatecall target from, sorted
+    /// by selector. At most [MAX_FACET_PROBES] selectors are tried, in code order; those that
+    /// revert (e.g. whose facet is unset in the given storage) or don't delegate through a
+    /// single slot are left out.
+    fn facet_slots(&self, env: &TraceEnvironment) -> Vec<(Selector, U256)> {
+	let mut pushes = Push4Constants::default();
+	scan(self.code, &mut pushes);
+	let mut selectors: Vec<Selector> = Vec::new();
+	for selector in pushes.selectors {
+	    if selectors.len() == MAX_FACET_PROBES {
+		break;
+	    }
+	    if !selectors.contains(&selector) {
+		selectors.push(selector);
+	    }
+	}
+	// Room for a few static arguments, like the widened probes
+	let runs = self.trace_calldata(env, selectors.iter().map(|selector| selector_call(selector.as_bytes().as_slice())));
+	let mut slots: Vec<(Selector, U256)> = selectors.into_iter().zip(runs).filter_map(|(selector, run)| {
+	    match run.delegatecall_storage[..] {
+		[slot] if !run.reverted => Some((selector, slot)),
+		_ => None
+	    }
+	}).collect();
+	slots.sort();
+	slots
+    }
+
+    /// Slots the code writes the new implementation to when asked to upgrade, by the caller of
+    /// `env` and by the admin if the code has an admin slot. Upgrades the implementation
+    /// performs aren't seen, calls out of the contract aren't executed.
+    fn upgradeable_slots(&self, env: &TraceEnvironment, admin_slot: Option<U256>) -> Vec<U256> {
+	// Any contract, the upgrade functions check the target has code
+	let target = env.alternate().contract;
+	let probes = [
+	    UpgradeToCall { new_implementation: raddress_to_h160(&target) }.encode(),
+	    UpgradeToAndCallCall { new_implementation: raddress_to_h160(&target), data: Default::default() }.encode(),
+	];
+	let mut envs = vec![env.clone()];
+	if let Some(slot) = admin_slot {
+	    envs.push(env.clone().with_caller(self.slot_address(env, &slot)));
+	}
+	let mut slots = Vec::new();
+	for run in envs.iter().flat_map(|env| self.trace_calldata(env, probes.iter().map(|calldata| calldata.clone().into()))) {
+	    for (slot, _) in run.sstores.iter().filter(|(_, value)| Address::from_word(value.to_be_bytes::<32>().into()) == target) {
+		if !slots.contains(slot) {
+		    slots.push(*slot);
 		}
-	    } else {
-		None
 	    }
+	}
+	slots
+    }
+
+    fn trace_probes(&self, env: &TraceEnvironment) -> Vec<InspectorData> {
+	self.trace_probes_paying(env, U256::ZERO)
+    }
+
+    fn trace_probes_paying(&self, env: &TraceEnvironment, value: U256) -> Vec<InspectorData> {
+	// Different calldata, to check if we get different DelegateCall
+	self.try_trace_calls(env, self.probes.iter().map(|calldata| (calldata.clone(), value))).into_iter().map(Result::unwrap_or_default).collect()
+    }
+
+    /// The runs of `runs` to classify: since ruleset 10, those that forward if any does.
+    fn classified_runs(runs: &[InspectorData], config: &DetectorConfig) -> Vec<InspectorData> {
+	if config.ruleset.version() >= CALLDATA_PROBES_SINCE && runs.iter().any(Self::forwards) {
+	    runs.iter().filter(|run| Self::forwards(run)).cloned().collect()
 	} else {
-	    // if data[0].delegatecall_storage.len() > 0 {
-		// println!("code: {}", self.code);
-		if find_bytes(&self.code, &hex_literal::hex!("637a0ed627")).is_some() {
-		    Some((ProxyType::EIP_2535, ProxyDispatch::Facet_EIP_2535))
-		} else {
-		    // if data.iter().all(|d| d.storage_access.contains(&DIAMOND_STANDARD_STORAGE_SLOT)) {
-		    if find_bytes(&self.code, &DIAMOND_STANDARD_STORAGE_SLOT_LESSBYTES).is_some() {
-			Some((ProxyType::EIP_2535, ProxyDispatch::FacetStorageSlot))
-		    } else {
-			Some((ProxyType::DiamondOther, ProxyDispatch::Unknown))
-		    }
-		}
-	    // } else if data[0].delegatecall_unknown.len() > 0 {
-	    // 	Some((ProxyType::EIP_2535, ProxyDispatch::Unknown))
-	    // } else {
-	    // 	None
-	    // }
+	    runs.to_vec()
 	}
     }
 
-    fn get_proxy(&self) -> Option<(ProxyType, ProxyDispatch)> {
-	// Run with 3 different call data to check if we get different DelegateCall
+    /// Whether none of `runs` forwarded anything, and some stopped by reverting.
+    fn reverted_without_forwarding(runs: &[InspectorData]) -> bool {
+	runs.iter().any(|run| run.reverted) && !runs.iter().any(Self::forwards)
+    }
+
+    fn forwards(run: &InspectorData) -> bool {
+	!run.delegatecall_storage.is_empty() || !run.delegatecall_addresses().is_empty() || !run.delegatecall_registry.is_empty() || !run.external_calls.is_empty()
+    }
+
+    /// Classifies the runs of the [ProbeRetry] probes that forward.
+    fn retry_probes(&self, env: &TraceEnvironment, retry: &ProbeRetry, config: &DetectorConfig) -> Option<ProxyDetectionResult> {
 	let mut runs = Vec::new();
+	if !retry.call_value.is_zero() {
+	    runs.extend(self.trace_probes_paying(env, retry.call_value));
+	}
+	if retry.empty_calldata {
+	    runs.extend(self.try_trace_calls(env, [U256::ZERO, retry.call_value].map(|value| (Bytes::new(), value))).into_iter().map(Result::unwrap_or_default));
+	}
+	runs.retain(Self::forwards);
+	if runs.is_empty() {
+	    return None;
+	}
+	self.detect_proxy_from_data(env, &runs, config)
+    }
 
-	let calldata_detectors = vec![
-	    vec![0xaa, 0xcc, 0xbb, 0xdd],
-	    vec![0xcc, 0xbb, 0xdd, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1, 0xf1],
-	    vec![0x01, 0x02, 0x04, 0x11]
-	];
-	for calldata in calldata_detectors {
-	    let ret = self.trace_calldata(calldata.into());
-	    runs.push(ret);
+    fn same_delegation(runs: &[InspectorData], other_runs: &[InspectorData]) -> bool {
+	runs.iter().zip(other_runs).all(|(a, b)| {
+	    a.delegatecall_storage == b.delegatecall_storage && a.delegatecall_addresses() == b.delegatecall_addresses()
+	})
+    }
+
+    /// The probe sets of [WidenedAnalysis] in order, the first one classified wins.
+    fn get_proxy_widened(&self, env: &TraceEnvironment, widened: &WidenedAnalysis, config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	let runs = self.trace_probes(env);
+	if let Some(result) = self.detect_proxy_from_data(env, &Self::classified_runs(&runs, config), config) {
+	    return Some(result);
+	}
+	let mut alternate = env.clone();
+	for _ in 0..widened.alternate_callers {
+	    alternate = alternate.alternate();
+	    let caller_env = env.clone().with_caller(alternate.caller);
+	    let runs = self.trace_probes(&caller_env);
+	    if let Some(result) = self.detect_proxy_from_data(&caller_env, &Self::classified_runs(&runs, config), config) {
+		return Some(result);
+	    }
 	}
-	self.detect_proxy_from_data(&runs)
 
-	// ProxyType::Unknown
+	let mut calldata = widened.extra_calldata.clone();
+	if widened.harvest_selectors {
+	    // Room for a few static arguments, so functions taking some don't revert decoding them
+	    calldata.extend(recover_interface(self.code).selectors.iter().map(|selector| selector_call(selector.as_bytes().as_slice())));
+	}
+	// The calls the contract served itself don't say anything about its forwarding
+	let runs: Vec<InspectorData> = self.trace_calldata(env, calldata).into_iter()
+	    .filter(Self::forwards)
+	    .collect();
+	if runs.is_empty() {
+	    return None;
+	}
+	self.detect_proxy_from_data(env, &runs, config)
+    }
+
+    fn get_proxy(&self, config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	let env = config.trace_environment();
+	if let Some(widened) = &config.widened {
+	    return self.get_proxy_widened(&env, widened, config);
+	}
+	let runs = self.trace_probes(&env);
+	let mut result = self.detect_proxy_from_data(&env, &Self::classified_runs(&runs, config), config);
+	if result.is_none() && config.ruleset.version() >= PROBE_RETRY_SINCE && Self::reverted_without_forwarding(&runs) {
+	    debug!("no probe forwarded, retrying with {:?}", config.retry);
+	    result = self.retry_probes(&env, &config.retry, config);
+	}
+	if !config.anti_evasion {
+	    return result;
+	}
+
+	let alt_env = config.pinned(env.alternate());
+	let alt_runs = self.trace_probes(&alt_env);
+	if Self::same_delegation(&runs, &alt_runs) {
+	    return result;
+	}
+	debug!("delegation differs between environments {} and {}", env.seed, alt_env.seed);
+	let mut result = result.or_else(|| self.detect_proxy_from_data(&alt_env, &Self::classified_runs(&alt_runs, config), config))?;
+	result.evasive = true;
+	result.findings.push(Finding::EvasiveBehavior);
+	Some(result)
     }
 }
 
 
 impl ProxyDetector for StorageSlotProxy {
-    fn try_match(code: &[u8]) -> Option<(ProxyType, ProxyDispatch)> {
-	// let storage_inspector = ();
-	// run_code_with_inspector
-        let tainter = StorageCallTaint::new(code);
-	tainter.get_proxy()
+    fn try_match(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+	// Not worth building an EVM for
+	if DetectOutcome::for_code_size(code).is_some() || !worth_tracing(code, config) {
+	    return None;
+	}
+        let mut tainter = StorageCallTaint::new(code, config.layout_analysis).with_calldata_strategy(config.calldata_strategy())
+	    .with_static_self_calls(config.ruleset.includes(RuleId::ReadOnlyRouter))
+	    .with_synthetic_returns(config.synthetic_return_selectors())
+	    .with_budget(config.max_steps, config.timeout)
+	    .with_fork(config.fork.clone());
+	if let Some(widened) = &config.widened {
+	    tainter = tainter.with_storage(widened.storage.clone());
+	}
+	tainter.get_proxy(config)
     }
 }
 
-pub fn get_proxy_type(code: &[u8]) -> Option<(ProxyType, ProxyDispatch)> {
-    if let Some(proxy_type) = MinimalProxy::try_match(code) {
-	Some(proxy_type)
-    } else if let Some(proxy_type) = StorageSlotProxy::try_match(code) {
-	Some(proxy_type)
+pub(crate) fn fill_block_env(block: &mut BlockEnv, env: &TraceEnvironment) {
+    block.number = U256::from(env.block_number);
+    block.timestamp = U256::from(env.timestamp);
+    block.basefee = U256::from(env.basefee);
+}
+
+/// Runs `initcode` as a contract creation from the environment's caller, returning the
+/// deployed runtime code.
+fn deploy_initcode(initcode: &[u8], env: &TraceEnvironment) -> Option<Bytes> {
+    // Deployed at `env.contract`, the DB has to report it empty to avoid a collision
+    let created = env.contract;
+    let db = ProxyDetectDB::new(env.clone()).with_empty_account(created);
+    let mut evm = EvmBuilder::default()
+	.with_db(db)
+	.modify_block_env(|block: &mut BlockEnv| fill_block_env(block, env))
+	.modify_tx_env(|tx: &mut TxEnv| {
+	    tx.caller = env.caller;
+	    tx.transact_to = TransactTo::Create;
+	    tx.data = Bytes::copy_from_slice(initcode);
+	    tx.value = U256::ZERO;
+	    tx.gas_price = U256::from(env.basefee);
+	    tx.gas_limit = 30_000_000;
+	})
+	.build();
+    match evm.transact().ok()?.result {
+	ExecutionResult::Success { output: Output::Create(code, _), .. } if !code.is_empty() => Some(code),
+	result => {
+	    debug!("initcode didn't deploy code: {:?}", result);
+	    None
+	}
+    }
+}
+
+/// Detects the proxy deployed by `initcode`, by running it in the synthetic environment and
+/// analysing the returned runtime code.
+///
+/// Provenance is relative to the runtime code, unless `initcode` contains it verbatim (the
+/// usual constructor copying its tail) in which case it points into `initcode`.
+pub fn detect_creation_code(initcode: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+    if DetectOutcome::for_code_size(initcode).is_some() {
+	return None;
+    }
+    // Deploys the factory's current implementation, the runtime says nothing about the contract
+    if let Some(result) = MetamorphicInit::try_match(initcode, config) {
+	return Some(result);
+    }
+    let mut env = config.trace_environment();
+    // The code runs at the caller's first creation, not at the traced contract
+    env.contract = env.caller.create(0);
+    let runtime = deploy_initcode(initcode, &env)?;
+    let mut result = detect_proxy(&runtime, config)?;
+    match find_bytes(initcode, &runtime) {
+	Some(start) => result.provenance.iter_mut().for_each(|p| p.offset += start),
+	None => result.provenance.clear(),
+    }
+    Some(result)
+}
+
+/// Detects the proxy in an ERC-5202 blueprint, `None` if `code` isn't one.
+///
+/// The payload is deployed as initcode, per the standard, and the result flagged with the
+/// [BlueprintInfo]. Payloads that don't deploy anything are analysed as runtime code instead.
+/// Provenance is relative to the container.
+pub fn detect_blueprint(code: &[u8], config: &DetectorConfig) -> Result<Option<ProxyDetectionResult>, DetectError> {
+    let Some(blueprint) = parse_blueprint(code)? else {
+	return Ok(None);
+    };
+    let info = |initcode| BlueprintInfo { version: blueprint.version, data_len: blueprint.data.len(), initcode };
+    let result = detect_creation_code(blueprint.initcode, config)
+	.map(|result| (result, info(true)))
+	.or_else(|| detect_proxy(blueprint.initcode, config).map(|result| (result, info(false))));
+    Ok(result.map(|(mut result, info)| {
+	result.provenance.iter_mut().for_each(|p| p.offset += blueprint.initcode_offset);
+	result.blueprint = Some(info);
+	result
+    }))
+}
+
+/// Where `slot` is pushed in `code`: a PUSH32 of it, or else the pushes of a value in `folded`
+/// equal to it.
+fn slot_pushes<'a>(code: &'a [u8], folded: &[FoldedConstant<'a>], slot: &U256) -> Option<Vec<Instruction<'a>>> {
+    find_push_value(code, slot, 32).map(|push| vec![push])
+	.or_else(|| folded.iter().find(|constant| constant.value == *slot).map(|constant| constant.pushes.clone()))
+}
+
+/// [ProvenanceKind::SlotConstant]s of the pushes of `slot`, see [slot_pushes].
+fn slot_provenance(code: &[u8], folded: &[FoldedConstant], slot: &U256) -> Vec<ByteProvenance> {
+    slot_pushes(code, folded, slot).unwrap_or_default().iter()
+	.map(|push| ByteProvenance::new(ProvenanceKind::SlotConstant, push.operand_offset(), push.operand.len()))
+	.collect()
+}
+
+fn push_provenance(code: &[u8], kind: ProvenanceKind, value: &U256, min_len: usize) -> Option<ByteProvenance> {
+    find_push_value(code, value, min_len).map(|ins| ByteProvenance::new(kind, ins.operand_offset(), ins.operand.len()))
+}
+
+/// Locates the values of a dispatch found by tracing as PUSH immediates in `code`. Values that
+/// can't be found (e.g. computed at runtime) have no provenance.
+fn dispatch_provenance(code: &[u8], dispatch: &ProxyDispatch) -> Vec<ByteProvenance> {
+    let address_value = |address: &Address| U256::from_be_slice(address.as_slice());
+    match dispatch {
+	ProxyDispatch::Static(address) | ProxyDispatch::StaticWithArgs(address, _) => {
+	    push_provenance(code, ProvenanceKind::ImplementationAddress, &address_value(address), 1).into_iter().collect()
+	},
+	ProxyDispatch::Storage(slot, _) => slot_provenance(code, &fold_constants(code), slot),
+	ProxyDispatch::Beacon(slot) => {
+	    let mut provenance = slot_provenance(code, &fold_constants(code), slot);
+	    provenance.extend(push_provenance(code, ProvenanceKind::SelectorConstant, &U256::from(BEACON_IMPLEMENTATION_SELECTOR.to_u32_be()), 4));
+	    provenance
+	},
+	ProxyDispatch::MultipleStorage(slots) => {
+	    let folded = fold_constants(code);
+	    slots.iter().flat_map(|slot| slot_provenance(code, &folded, slot)).collect()
+	},
+	ProxyDispatch::External(address, fun) => {
+	    [
+		push_provenance(code, ProvenanceKind::BeaconAddress, &address_value(address), 1),
+		push_provenance(code, ProvenanceKind::SelectorConstant, &U256::from(fun.to_u32_be()), 4),
+	    ].into_iter().flatten().collect()
+	},
+	ProxyDispatch::Facet_EIP_2535 => {
+	    // facetAddresses()
+	    push_provenance(code, ProvenanceKind::SelectorConstant, &U256::from(0x7a0ed627u32), 4).into_iter().collect()
+	},
+	ProxyDispatch::FacetStorageSlot => slot_provenance(code, &fold_constants(code), &DIAMOND_STANDARD_STORAGE_SLOT),
+	ProxyDispatch::PerSelector(table) => {
+	    table.iter().filter_map(|(_, address)| push_provenance(code, ProvenanceKind::ImplementationAddress, &address_value(address), 1)).collect()
+	},
+	ProxyDispatch::Unknown => Vec::new(),
+    }
+}
+
+/// Detects the proxy implemented by `code` using the rules allowed by `config`.
+///
+/// `code` is only borrowed: the static matchers work on the slice without copying it, the
+/// tracing detector copies it exactly once into the bytecode revm executes, shared by all its
+/// runs.
+pub fn detect_proxy(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+    if DetectOutcome::for_code_size(code).is_some() {
+	return None;
+    }
+    match_proxy(code, config)
+}
+
+fn match_proxy(code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+    if code.starts_with(&BLUEPRINT_MAGIC) {
+	// Blueprints aren't callable, see detect_blueprint for malformed ones
+	detect_blueprint(code, config).ok().flatten()
     } else {
-	None
+	config.strategies.iter().find_map(|strategy| strategy.detect(code, config))
+	    .or_else(|| builtin_strategies(config).find_map(|(_, strategy)| strategy(code, config)))
+    }
+}
+
+/// [detect_proxy] telling empty and too short codes apart from codes that aren't proxies, and
+/// those from codes that delegated without matching a rule. Telling them apart traces the
+/// probes again when no strategy matched a code with a DELEGATECALL.
+pub fn detect_proxy_outcome(code: &[u8], config: &DetectorConfig) -> DetectOutcome {
+    if let Some(outcome) = DetectOutcome::for_code_size(code) {
+	return outcome;
     }
+    if let Some(result) = match_proxy(code, config) {
+	return DetectOutcome::Proxy(result);
+    }
+    if code.starts_with(&BLUEPRINT_MAGIC) || !may_delegate(code) {
+	return DetectOutcome::NotAProxy { reason: NotAProxyReason::NoDelegatecall };
+    }
+    let env = config.trace_environment();
+    let runs = StorageCallTaint::new(code, config.layout_analysis).with_calldata_strategy(config.calldata_strategy())
+	.with_synthetic_returns(config.synthetic_return_selectors())
+	.with_budget(config.max_steps, config.timeout)
+	.with_fork(config.fork.clone())
+	.trace_probes(&env);
+    match runs.into_iter().find(|run| !run.static_delegation.is_empty()) {
+	Some(observations) => DetectOutcome::Inconclusive { observations },
+	None => DetectOutcome::NotAProxy { reason: NotAProxyReason::DelegatecallNotReached },
+    }
+}
+
+/// A detector, by name.
+type Strategy = (&'static str, fn(&[u8], &DetectorConfig) -> Option<ProxyDetectionResult>);
+
+/// The built-in detectors [detect_proxy] tries after [DetectorConfig::strategies], in order, the
+/// trace last.
+const STRATEGIES: &[Strategy] = &[
+    ("minimal", MinimalProxy::try_match),
+    ("generated_router", GeneratedRouter::try_match),
+    ("safe", SafeProxy::try_match),
+    ("metamorphic", MetamorphicInit::try_match),
+    ("beacon", BeaconProxy::try_match),
+    ("trace", StorageSlotProxy::try_match),
+];
+
+/// The [STRATEGIES] `config` allows.
+fn builtin_strategies(config: &DetectorConfig) -> impl Iterator<Item = &'static Strategy> + '_ {
+    STRATEGIES.iter().filter(|(name, _)| !(config.static_only && *name == "trace"))
+}
+
+/// What every detector [detect_proxy] tries makes of `code`, by name, instead of the first one
+/// that matches. Telling when they disagree shows code that looks like one proxy but runs like
+/// another, e.g. a guard ahead of a clone's body delegating elsewhere. Blueprints only have
+/// the `blueprint` detector. See [consensus](crate::consensus) to pick one.
+pub fn detect_all(code: &[u8], config: &DetectorConfig) -> Vec<(&'static str, ProxyDetectionResult)> {
+    if DetectOutcome::for_code_size(code).is_some() {
+	return Vec::new();
+    }
+    if code.starts_with(&BLUEPRINT_MAGIC) {
+	return detect_blueprint(code, config).ok().flatten().map(|result| ("blueprint", result)).into_iter().collect();
+    }
+    let custom = config.strategies.iter()
+	.filter_map(|strategy| strategy.detect(code, config).map(|result| (strategy.name(), result)));
+    let builtin = builtin_strategies(config)
+	.filter_map(|(name, strategy)| strategy(code, config).map(|result| (*name, result)));
+    custom.chain(builtin).collect()
+}
+
+/// Codes up to this size that DELEGATECALL are taken for proxies by [is_likely_proxy].
+pub const LIKELY_PROXY_MAX_SIZE: usize = 2_048;
+
+/// A cheap guess at whether `code` could be a proxy, to skip the trace of [detect_proxy] on
+/// most of a corpus. Runs the static matchers, then a single pass over the instructions, PUSH
+/// immediates skipped so a `0xf4` byte in one isn't taken for a DELEGATECALL: code that
+/// delegates is likely a proxy if it is at most [LIKELY_PROXY_MAX_SIZE] bytes, pushes a
+/// known implementation slot or has a diamond's `facets()`.
+///
+/// Meant to be followed by [detect_proxy], so it leans towards recall. Small contracts calling
+/// libraries and UUPS implementations, which push the EIP-1967 slot, pass it. Proxies it
+/// misses are the large ones keeping their implementation in a custom slot, and those that
+/// forward with CALL rather than DELEGATECALL.
+pub fn is_likely_proxy(code: &[u8], config: &DetectorConfig) -> bool {
+    if DetectOutcome::for_code_size(code).is_some() {
+	return false;
+    }
+    if code.starts_with(&BLUEPRINT_MAGIC) || STRATEGIES.iter().any(|(name, strategy)| *name != "trace" && strategy(code, config).is_some()) {
+	return true;
+    }
+    if is_inert(code) {
+	return false;
+    }
+    let mut visitors = (OpcodePresence::new(opcode::DELEGATECALL), Push32Constants::default(), Push4Constants::default());
+    scan(code, &mut visitors);
+    let (delegates, pushed32, pushed4) = visitors;
+    delegates.found && (code.len() <= LIKELY_PROXY_MAX_SIZE
+	|| pushed32.values.iter().any(|value| config.slot_registry().contains_key(value) || *value == DIAMOND_STANDARD_STORAGE_SLOT || *value == EIP_1822_PROXIABLE_SLOT)
+	|| pushed4.selectors.contains(&DIAMOND_FACETS_SELECTOR))
+}
+
+/// Detects the proxies of a corpus lazily, one result per code in order.
+///
+/// Codes can be anything that derefs to bytes without owning them, e.g. slices of a memory
+/// mapped file, `Cow<[u8]>` or `Bytes`, with the same copy guarantees as [detect_proxy].
+pub fn detect_proxies<'a, I>(codes: I, config: &'a DetectorConfig) -> impl Iterator<Item = Option<ProxyDetectionResult>> + 'a
+    where I: IntoIterator + 'a,
+	  I::Item: AsRef<[u8]>
+{
+    codes.into_iter().map(move |code| detect_proxy(code.as_ref(), config))
+}
+
+/// [detect_proxies] reporting the codes done to `progress` under the `detect` stage, with the
+/// iterator's size hint as total.
+pub fn detect_proxies_with_progress<'a, I>(codes: I, config: &'a DetectorConfig, progress: &'a dyn ProgressReporter) -> impl Iterator<Item = Option<ProxyDetectionResult>> + 'a
+    where I: IntoIterator + 'a,
+	  I::Item: AsRef<[u8]>
+{
+    let codes = codes.into_iter();
+    let total_hint = match codes.size_hint() {
+	(low, Some(high)) if low == high => Some(low as u64),
+	_ => None,
+    };
+    DetectWithProgress { codes, config, emitter: ProgressEmitter::new(progress, "detect", total_hint) }
+}
+
+struct DetectWithProgress<'a, I> {
+    codes: I,
+    config: &'a DetectorConfig,
+    emitter: ProgressEmitter<'a>,
+}
+
+impl<I> Iterator for DetectWithProgress<'_, I>
+    where I: Iterator,
+	  I::Item: AsRef<[u8]>
+{
+    type Item = Option<ProxyDetectionResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+	match self.codes.next() {
+	    Some(code) => {
+		let result = detect_proxy(code.as_ref(), self.config);
+		self.emitter.advance();
+		Some(result)
+	    },
+	    None => {
+		self.emitter.finish();
+		None
+	    }
+	}
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+	self.codes.size_hint()
+    }
+}
+
+/// Detects the proxies of a corpus eagerly, one result per code in order.
+///
+/// Identical codes, e.g. the clones of a factory, are detected once. With the `parallel`
+/// feature the distinct codes are detected on the rayon thread pool. A detector panicking on
+/// a code fails that code with [DetectError::Panicked] instead of the batch.
+pub fn detect_many<C: AsRef<[u8]> + Sync>(codes: &[C], config: &DetectorConfig) -> Vec<Result<Option<ProxyDetectionResult>, DetectError>> {
+    detect_many_with_progress(codes, config, &NoProgress)
+}
+
+/// [detect_many] reporting the distinct codes done to `progress` under the `detect` stage, with
+/// their number as total.
+pub fn detect_many_with_progress<C: AsRef<[u8]> + Sync>(codes: &[C], config: &DetectorConfig, progress: &dyn ProgressReporter) -> Vec<Result<Option<ProxyDetectionResult>, DetectError>> {
+    let mut distinct: Vec<&[u8]> = Vec::new();
+    let mut seen: HashMap<&[u8], usize> = HashMap::new();
+    let indices: Vec<usize> = codes.iter()
+	.map(|code| *seen.entry(code.as_ref()).or_insert_with(|| {
+	    distinct.push(code.as_ref());
+	    distinct.len() - 1
+	}))
+	.collect();
+
+    // Advanced from the rayon threads with the `parallel` feature
+    let emitter = Mutex::new(ProgressEmitter::new(progress, "detect", Some(distinct.len() as u64)));
+    let detect = |code: &&[u8]| {
+	let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| detect_proxy(code, config)))
+	    .map_err(|panic| DetectError::Panicked(panic_message(panic)));
+	emitter.lock().unwrap_or_else(|e| e.into_inner()).advance();
+	result
+    };
+    #[cfg(feature = "parallel")]
+    let results: Vec<_> = {
+	use rayon::prelude::*;
+	distinct.par_iter().map(detect).collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let results: Vec<_> = distinct.iter().map(detect).collect();
+    emitter.into_inner().unwrap_or_else(|e| e.into_inner()).finish();
+
+    indices.into_iter().map(|index| results[index].clone()).collect()
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    panic.downcast_ref::<&str>().map(|message| message.to_string())
+	.or_else(|| panic.downcast_ref::<String>().cloned())
+	.unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// The type and dispatch of the proxy in `code` with the default [DetectorConfig], `None` if
+/// it isn't one. [detect_proxy] gives the rest of the detection.
+pub fn get_proxy_type(code: &[u8]) -> Option<(ProxyType, ProxyDispatch)> {
+    detect_proxy(code, &DetectorConfig::default()).map(|result| (result.proxy_type, result.dispatch))
 }
 
 #[cfg(test)]
@@ -273,19 +1438,75 @@ mod tests {
 
     #[test]
     fn test_minimal_proxy() {
-        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")), Some(Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"))));
-        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("363d3d373d3d3d363d6fbebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")), Some(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))));
-        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("9999999999")), None);
-        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")), None);
-
-	assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3")), Some(Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"))));
-        assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("365f5f375f5f365f6fbebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3")), Some(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))));
-        assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("9999999999")), None);
-        assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")), None);
-
-	assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e603457fd5bf3")), Some(Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"))));
-        assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d6fbebebebebebebebebebebebebebebebe5af43d3d93803e603457fd5bf3")), Some(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))));
-        assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("9999999999")), None);
-        assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")), None);
+        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("363d3d373d3d3d363d6fbebebebebebebebebebebebebebebebe5af43d82803e903d91602757fd5bf3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("9999999999"), &Ruleset::LATEST), None);
+        // Behind a prefix only when nothing follows
+        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("5b5b363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"), &Ruleset::LATEST), Some((2, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("5b5b363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf300"), &Ruleset::LATEST), None);
+        assert_eq!(MinimalProxy::is_eip_1667(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"), &Ruleset::LATEST), None);
+
+	assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("365f5f375f5f365f6fbebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602657fd5bf3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("9999999999"), &Ruleset::LATEST), None);
+        assert_eq!(MinimalProxy::is_eip_7511(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"), &Ruleset::LATEST), None);
+
+	assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e603457fd5bf3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d6fbebebebebebebebebebebebebebebebe5af43d3d93803e603057fd5bf3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("9999999999"), &Ruleset::LATEST), None);
+        assert_eq!(MinimalProxy::is_eip_3448(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"), &Ruleset::LATEST), None);
+    }
+
+    #[test]
+    fn test_solady_clone() {
+        const PUSH0_CLONE: [u8; 45] = hex_literal::hex!("5f5f365f5f37365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e6029573d5ffd5b3d5ff3");
+        const LEGACY_PUSH0_CLONE: [u8; 45] = hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e6029573d5ffd5b3d5ff3");
+        let pinned = Ruleset::pinned(16).unwrap();
+        assert_eq!(MinimalProxy::is_solady_push0(&PUSH0_CLONE, &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_solady_push0(&LEGACY_PUSH0_CLONE, &Ruleset::LATEST), None);
+        // Rulesets before 17 expected the EIP-7511 prologue
+        assert_eq!(MinimalProxy::is_solady_push0(&LEGACY_PUSH0_CLONE, &pinned), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_solady_push0(&PUSH0_CLONE, &pinned), None);
+        // An EIP-7511 clone
+        assert_eq!(MinimalProxy::is_solady_push0(&hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3"), &Ruleset::LATEST), None);
+        assert_eq!(MinimalProxy::is_solady_cwia(&hex_literal::hex!("36602c57343d527f9e4ac34f21c619cefc926c8bd93b54bf5a39c7ab2127a895af1cc0691d7e3dff593da1005b363d3d373d3d3d3d61ffff806062363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e606057fd5bf3aa"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        // Truncated right after the address
+        assert_eq!(MinimalProxy::is_solady_cwia(&hex_literal::hex!("36602c57343d527f9e4ac34f21c619cefc926c8bd93b54bf5a39c7ab2127a895af1cc0691d7e3dff593da1005b363d3d373d3d3d3d61ffff806062363936013d73bebebebebebebebebebebebebebebebebebebebe"), &Ruleset::LATEST), None);
+        assert_eq!(MinimalProxy::is_solady_cwia(&hex_literal::hex!("9999999999"), &Ruleset::LATEST), None);
+        // One byte of args, then its length word
+        const SOLADY_CWIA_ARGS_CODE: [u8; 101] = hex_literal::hex!("36602c57343d527f9e4ac34f21c619cefc926c8bd93b54bf5a39c7ab2127a895af1cc0691d7e3dff593da1005b363d3d373d3d3d3d610003806062363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e606057fd5bf3" "aa" "0003");
+        assert_eq!(MinimalProxy::solady_cwia_args(&SOLADY_CWIA_ARGS_CODE), Some(&SOLADY_CWIA_ARGS_CODE[98..99]));
+        assert_eq!(MinimalProxy::solady_cwia_args(&SOLADY_CWIA_ARGS_CODE[..100]), None);
+        assert_eq!(MinimalProxy::solady_cwia_args(&SOLADY_CWIA_ARGS_CODE[..98]), None);
+    }
+
+    #[test]
+    fn test_vyper_forwarder() {
+        assert_eq!(MinimalProxy::is_vyper_forwarder(&hex_literal::hex!("366000600037611000600036600073bebebebebebebebebebebebebebebebebebebebe5af4602c57600080fd5b6110006000f3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        assert_eq!(MinimalProxy::is_vyper_forwarder(&hex_literal::hex!("366000600037611000600036600073bebebebebebebebebebebebebebebebebebebebe5af41558576110006000f3"), &Ruleset::LATEST), Some((0, Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe")))));
+        // Truncated epilogue
+        assert_eq!(MinimalProxy::is_vyper_forwarder(&hex_literal::hex!("366000600037611000600036600073bebebebebebebebebebebebebebebebebebebebe5af4602c57600080fd5b"), &Ruleset::LATEST), None);
+        // What 0.3 deploys is left to the EIP-1167 matcher
+        assert_eq!(MinimalProxy::is_vyper_forwarder(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"), &Ruleset::LATEST), None);
+    }
+
+    #[test]
+    fn test_may_delegate() {
+        assert!(may_delegate(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")));
+        // callcode(gas, 0xbe..be, 0, 0, 0, 0, 0)
+        assert!(may_delegate(&hex_literal::hex!("6000600060006000600073bebebebebebebebebebebebebebebebebebebebe5af200")));
+        // Only inside a PUSH32
+        assert!(!may_delegate(&hex_literal::hex!("7ff4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f400")));
+        assert!(!may_delegate(&hex_literal::hex!("fe" "60006000600060006000f4")));
+        assert!(!may_delegate(&hex_literal::hex!("a164736f6c63430008f4" "000a")));
+        assert!(!may_delegate(&[]));
+
+        // extcodecopy(0xbe..be, 0, 0, 2), then a jump
+        let config = DetectorConfig::default();
+        assert!(worth_tracing(&hex_literal::hex!("6002600060007300000000000000000000000000000000000000be3c600051565b"), &config));
+        // staticcall(gas, 0xbe..be, 0, 36, 0, 32) of facetAddress(bytes4)
+        assert!(worth_tracing(&hex_literal::hex!("63cdffacc6600052602060006024600073bebebebebebebebebebebebebebebebebebebebe5afa00"), &config));
+        assert!(!worth_tracing(&hex_literal::hex!("6312345678600052602060006024600073bebebebebebebebebebebebebebebebebebebebe5afa00"), &config));
+        assert!(!worth_tracing(&hex_literal::hex!("fe" "6002600060007300000000000000000000000000000000000000be3c600051565b"), &config));
     }
 }
@@ -0,0 +1,262 @@
+use alloy_primitives::U256;
+use revm::interpreter::{opcode, OpCode};
+
+use crate::selector::Selector;
+
+/// A decoded instruction, `operand` holds the immediate bytes of PUSH instructions and may be
+/// shorter than expected if the code is truncated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction<'a> {
+    pub offset: usize,
+    pub opcode: u8,
+    pub operand: &'a [u8],
+}
+
+impl Instruction<'_> {
+    pub fn is_push(&self) -> bool {
+	(opcode::PUSH1..=opcode::PUSH32).contains(&self.opcode)
+    }
+
+    /// Offset of the first immediate byte.
+    pub fn operand_offset(&self) -> usize {
+	self.offset + 1
+    }
+
+    pub fn push_value(&self) -> Option<U256> {
+	self.is_push().then(|| U256::from_be_slice(self.operand))
+    }
+}
+
+/// Linear disassembler that skips PUSH immediates. Lazy: instructions borrow the code and are
+/// decoded as they are asked for, nothing is allocated.
+#[derive(Clone, Debug)]
+pub struct Disassembler<'a> {
+    code: &'a [u8],
+    pc: usize,
+}
+
+impl<'a> Iterator for Disassembler<'a> {
+    type Item = Instruction<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+	let opcode = *self.code.get(self.pc)?;
+	let offset = self.pc;
+	let immediate = if (opcode::PUSH1..=opcode::PUSH32).contains(&opcode) { (opcode - opcode::PUSH0) as usize } else { 0 };
+	let end = (offset + 1 + immediate).min(self.code.len());
+	self.pc = offset + 1 + immediate;
+	Some(Instruction { offset, opcode, operand: &self.code[offset + 1..end] })
+    }
+}
+
+pub fn disassemble(code: &[u8]) -> Disassembler<'_> {
+    Disassembler { code, pc: 0 }
+}
+
+/// Finds the first PUSH whose immediate, as a number, equals `value` and is at least
+/// `min_len` bytes long.
+pub fn find_push_value<'a>(code: &'a [u8], value: &U256, min_len: usize) -> Option<Instruction<'a>> {
+    find_first_push_matching(code, |ins| ins.operand.len() >= min_len && ins.push_value().as_ref() == Some(value))
+}
+
+/// The first PUSH `predicate` accepts, decoding no further.
+pub fn find_first_push_matching<'a>(code: &'a [u8], mut predicate: impl FnMut(&Instruction<'a>) -> bool) -> Option<Instruction<'a>> {
+    disassemble(code).find(|ins| ins.is_push() && predicate(ins))
+}
+
+/// Whether the code has any of `opcodes` outside PUSH immediates, decoding up to the first one.
+pub fn any_opcode(code: &[u8], opcodes: &[u8]) -> bool {
+    disassemble(code).any(|ins| opcodes.contains(&ins.opcode))
+}
+
+/// Instructions a value folded by [fold_constants] may span, from its first push to the
+/// operation computing it.
+pub const FOLD_WINDOW: usize = 8;
+
+/// A value straight-line code computes from pushed constants with ADD, SUB, SHL and OR, e.g. a
+/// slot pushed as `keccak - 1` or as two PUSH16 halves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FoldedConstant<'a> {
+    pub value: U256,
+    /// The pushes it is computed from, in code order.
+    pub pushes: Vec<Instruction<'a>>,
+}
+
+/// A stack entry of [fold_constants] known to hold a constant.
+#[derive(Clone)]
+struct KnownValue<'a> {
+    value: U256,
+    pushes: Vec<Instruction<'a>>,
+    /// Index of the first instruction it depends on.
+    first: usize,
+}
+
+/// The values `code` computes from constants within [FOLD_WINDOW] instructions, in the order
+/// they are computed. Only straight-line code is followed: the stack is forgotten at every
+/// JUMPDEST and after every jump or halt, and anything but PUSH, DUP, SWAP and the folded
+/// operations makes its outputs unknown.
+pub fn fold_constants(code: &[u8]) -> Vec<FoldedConstant<'_>> {
+    let mut stack: Vec<Option<KnownValue>> = Vec::new();
+    let mut folded = Vec::new();
+    for (index, ins) in disassemble(code).enumerate() {
+	let mut pop = || stack.pop().flatten();
+	match ins.opcode {
+	    opcode::PUSH0..=opcode::PUSH32 => {
+		let value = ins.push_value().unwrap_or_default();
+		stack.push(Some(KnownValue { value, pushes: vec![ins], first: index }));
+	    },
+	    opcode::ADD | opcode::SUB | opcode::SHL | opcode::OR => {
+		let (a, b) = (pop(), pop());
+		let result = a.zip(b).filter(|(a, b)| index - a.first.min(b.first) < FOLD_WINDOW).map(|(a, b)| {
+		    let value = match ins.opcode {
+			opcode::ADD => a.value.wrapping_add(b.value),
+			opcode::SUB => a.value.wrapping_sub(b.value),
+			opcode::SHL => b.value.checked_shl(a.value.saturating_to()).unwrap_or_default(),
+			_ => a.value | b.value,
+		    };
+		    let mut pushes = [a.pushes, b.pushes].concat();
+		    pushes.sort_by_key(|push| push.offset);
+		    folded.push(FoldedConstant { value, pushes: pushes.clone() });
+		    KnownValue { value, pushes, first: a.first.min(b.first) }
+		});
+		stack.push(result);
+	    },
+	    opcode::DUP1..=opcode::DUP16 => {
+		let depth = (ins.opcode - opcode::DUP1 + 1) as usize;
+		let entry = stack.len().checked_sub(depth).and_then(|i| stack[i].clone());
+		stack.push(entry);
+	    },
+	    opcode::SWAP1..=opcode::SWAP16 => {
+		let depth = (ins.opcode - opcode::SWAP1 + 1) as usize;
+		match stack.len().checked_sub(depth + 1) {
+		    Some(i) => {
+			let top = stack.len() - 1;
+			stack.swap(i, top);
+		    },
+		    // Swapped with an entry from before the straight-line code
+		    None => if let Some(top) = stack.last_mut() {
+			*top = None;
+		    },
+		}
+	    },
+	    opcode::JUMPDEST | opcode::JUMP | opcode::JUMPI | opcode::STOP | opcode::RETURN | opcode::REVERT | opcode::INVALID | opcode::SELFDESTRUCT => stack.clear(),
+	    op => match OpCode::info_by_op(op) {
+		Some(info) => {
+		    for _ in 0..info.inputs() {
+			pop();
+		    }
+		    stack.extend((0..info.outputs()).map(|_| None));
+		},
+		None => stack.clear(),
+	    },
+	}
+    }
+    folded
+}
+
+/// Receives the instructions of a [scan] in order.
+///
+/// Visitors combine as tuples, so several scans of the same code share one decoding pass
+/// without dynamic dispatch.
+pub trait InstructionVisitor {
+    fn visit(&mut self, ins: &Instruction);
+
+    /// Once true the visitor gets no more instructions, and the scan stops when every
+    /// visitor is done.
+    fn done(&self) -> bool {
+	false
+    }
+}
+
+impl<V: InstructionVisitor + ?Sized> InstructionVisitor for &mut V {
+    fn visit(&mut self, ins: &Instruction) {
+	(**self).visit(ins)
+    }
+
+    fn done(&self) -> bool {
+	(**self).done()
+    }
+}
+
+macro_rules! tuple_visitor {
+    ($($name:ident $index:tt),+) => {
+	impl<$($name: InstructionVisitor),+> InstructionVisitor for ($($name,)+) {
+	    fn visit(&mut self, ins: &Instruction) {
+		$(if !self.$index.done() { self.$index.visit(ins); })+
+	    }
+
+	    fn done(&self) -> bool {
+		$(self.$index.done())&&+
+	    }
+	}
+    };
+}
+
+tuple_visitor!(A 0, B 1);
+tuple_visitor!(A 0, B 1, C 2);
+tuple_visitor!(A 0, B 1, C 2, D 3);
+
+/// Feeds the instructions of `code` to `visitor` until it is done or the code ends.
+pub fn scan<V: InstructionVisitor>(code: &[u8], visitor: &mut V) {
+    for ins in disassemble(code) {
+	if visitor.done() {
+	    break;
+	}
+	visitor.visit(&ins);
+    }
+}
+
+/// Whether an opcode appears, done as soon as it does. The prefilter of corpus scans.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpcodePresence {
+    pub opcode: u8,
+    pub found: bool,
+}
+
+impl OpcodePresence {
+    pub fn new(opcode: u8) -> Self {
+	Self { opcode, found: false }
+    }
+}
+
+impl InstructionVisitor for OpcodePresence {
+    #[inline]
+    fn visit(&mut self, ins: &Instruction) {
+	self.found |= ins.opcode == self.opcode;
+    }
+
+    #[inline]
+    fn done(&self) -> bool {
+	self.found
+    }
+}
+
+/// The PUSH32 immediates in order, candidate storage slots.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Push32Constants {
+    pub values: Vec<U256>,
+}
+
+impl InstructionVisitor for Push32Constants {
+    #[inline]
+    fn visit(&mut self, ins: &Instruction) {
+	if ins.opcode == opcode::PUSH32 && ins.operand.len() == 32 {
+	    self.values.push(U256::from_be_slice(ins.operand));
+	}
+    }
+}
+
+/// The PUSH4 immediates in order, candidate function selectors.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Push4Constants {
+    pub selectors: Vec<Selector>,
+}
+
+impl InstructionVisitor for Push4Constants {
+    #[inline]
+    fn visit(&mut self, ins: &Instruction) {
+	if let Some(selector) = (ins.opcode == opcode::PUSH4).then(|| Selector::from_slice(ins.operand)).flatten() {
+	    self.selectors.push(selector);
+	}
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+
+const GWEI: u64 = 1_000_000_000;
+
+/// splitmix64, enough to derive varied but reproducible synthetic environments from a seed.
+#[derive(Clone, Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+	self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+	let mut z = self.0;
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+	z ^ (z >> 31)
+    }
+
+    fn range(&mut self, low: u64, high: u64) -> u64 {
+	low + self.next_u64() % (high - low + 1)
+    }
+
+    fn address(&mut self) -> Address {
+	let mut bytes = [0u8; 20];
+	for chunk in bytes.chunks_mut(8) {
+	    chunk.copy_from_slice(&self.next_u64().to_be_bytes()[..chunk.len()]);
+	}
+	// Stay away from the precompiles and other low addresses
+	bytes[0] |= 0x10;
+	Address::from(bytes)
+    }
+}
+
+/// The synthetic chain state the dynamic detector executes the analysed code in.
+///
+/// Every value is derived from `seed` so runs are reproducible, while different seeds make it
+/// hard for the analysed code to fingerprint the tracer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEnvironment {
+    pub seed: u64,
+    pub contract: Address,
+    pub caller: Address,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub basefee: u64,
+}
+
+impl TraceEnvironment {
+    pub fn from_seed(seed: u64) -> Self {
+	let mut rng = SplitMix64(seed);
+	let contract = rng.address();
+	let caller = rng.address();
+	let block_number = rng.range(18_000_000, 21_000_000);
+	Self {
+	    seed,
+	    contract,
+	    caller,
+	    block_number,
+	    // Roughly where mainnet was around those blocks
+	    timestamp: 1_693_000_000 + (block_number - 18_000_000) * 12 + rng.range(0, 11),
+	    basefee: rng.range(5, 60) * GWEI,
+	}
+    }
+
+    /// An environment seeded from the clock.
+    pub fn random() -> Self {
+	let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+	Self::from_seed(nanos)
+    }
+
+    /// The same environment called from `caller`.
+    pub fn with_caller(mut self, caller: Address) -> Self {
+	self.caller = caller;
+	self
+    }
+
+    /// A second, different environment derived from this one, used to compare behaviors.
+    pub fn alternate(&self) -> Self {
+	Self::from_seed(SplitMix64(self.seed ^ 0x5851f42d4c957f2d).next_u64())
+    }
+
+    fn account_rng(&self, address: &Address) -> SplitMix64 {
+	let hash = keccak256([&self.seed.to_be_bytes()[..], address.as_slice()].concat());
+	SplitMix64(u64::from_be_bytes(hash[..8].try_into().unwrap()))
+    }
+
+    /// Code given to accounts that aren't installed in the database. It is never executed (the
+    /// inspector stubs calls) but it is observable through EXTCODESIZE/EXTCODEHASH/EXTCODECOPY.
+    pub fn dummy_code(&self, address: &Address) -> Bytes {
+	let mut rng = self.account_rng(address);
+	let len = rng.range(24, 96) as usize;
+	let mut code = hex_literal::hex!("6080604052").to_vec();
+	while code.len() < len {
+	    code.extend_from_slice(&rng.next_u64().to_be_bytes());
+	}
+	code.truncate(len);
+	code.push(0xfe);
+	code.into()
+    }
+
+    /// What synthetic storage values are XORed with to make addresses, so that the contract
+    /// can't tell them apart from real ones nor a run predict another's.
+    pub fn storage_key(&self) -> Address {
+	Address::from_word(keccak256([&self.seed.to_be_bytes()[..], b"storage"].concat()))
+    }
+
+    /// Hash of block `number`, answered for BLOCKHASH.
+    pub fn dummy_block_hash(&self, number: U256) -> B256 {
+	keccak256([&self.seed.to_be_bytes()[..], &number.to_be_bytes::<32>()[..]].concat())
+    }
+
+    pub fn dummy_balance(&self, address: &Address) -> U256 {
+	if *address == self.caller {
+	    U256::from(10_000 * GWEI) * U256::from(GWEI)
+	} else {
+	    U256::from(self.account_rng(address).range(GWEI, 1000 * GWEI)) * U256::from(GWEI)
+	}
+    }
+}
@@ -0,0 +1,181 @@
+//! Upgrades as proxies log them: EIP-1967's `Upgraded`, `AdminChanged` and `BeaconUpgraded`, and
+//! EIP-2535's `DiamondCut` with the facets it cut.
+
+use alloy_primitives::{Address, Bytes, B256};
+use ethers_contract::EthEvent;
+use ethers_core::{abi::RawLog, types::{Filter, Log, H256}};
+use ethers_providers::Middleware;
+use tracing::debug;
+
+use crate::abi::{AdminChangedFilter, BeaconUpgradedFilter, DiamondCutFilter, UpgradedFilter};
+use crate::progress::{NoProgress, ProgressEmitter, ProgressReporter, Throttle};
+use crate::read::{with_retries, ProxyReadError, ReadConfig, RpcError, RpcErrorKind};
+use crate::utils::{h160_to_b160, h256_to_b256, raddress_to_h160};
+use crate::Selector;
+
+/// Blocks asked for per `eth_getLogs` request, halved while the provider refuses the range as
+/// too large.
+pub const LOG_CHUNK_BLOCKS: u64 = 10_000;
+
+/// What a `DiamondCut` does to the selectors of a facet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FacetCutAction {
+    Add,
+    Replace,
+    Remove,
+}
+
+impl FacetCutAction {
+    fn from_u8(action: u8) -> Option<Self> {
+	match action {
+	    0 => Some(FacetCutAction::Add),
+	    1 => Some(FacetCutAction::Replace),
+	    2 => Some(FacetCutAction::Remove),
+	    _ => None,
+	}
+    }
+}
+
+/// One entry of a `DiamondCut`: `selectors` added to, replaced with or removed from `facet`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FacetCut {
+    /// Zero when removing.
+    pub facet: Address,
+    pub action: FacetCutAction,
+    pub selectors: Vec<Selector>,
+}
+
+/// An upgrade logged by a proxy, with the block and transaction that logged it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpgradeEvent {
+    Upgraded { block: u64, transaction: B256, implementation: Address },
+    AdminChanged { block: u64, transaction: B256, previous_admin: Address, new_admin: Address },
+    BeaconUpgraded { block: u64, transaction: B256, beacon: Address },
+    /// `init` is called with `data` once the cuts are made, unless zero.
+    DiamondCut { block: u64, transaction: B256, cuts: Vec<FacetCut>, init: Address, data: Bytes },
+}
+
+impl UpgradeEvent {
+    pub fn block(&self) -> u64 {
+	match self {
+	    UpgradeEvent::Upgraded { block, .. } | UpgradeEvent::AdminChanged { block, .. }
+	    | UpgradeEvent::BeaconUpgraded { block, .. } | UpgradeEvent::DiamondCut { block, .. } => *block,
+	}
+    }
+
+    pub fn transaction(&self) -> B256 {
+	match self {
+	    UpgradeEvent::Upgraded { transaction, .. } | UpgradeEvent::AdminChanged { transaction, .. }
+	    | UpgradeEvent::BeaconUpgraded { transaction, .. } | UpgradeEvent::DiamondCut { transaction, .. } => *transaction,
+	}
+    }
+
+    /// The event's name, e.g. `Upgraded`.
+    pub fn name(&self) -> &'static str {
+	match self {
+	    UpgradeEvent::Upgraded { .. } => "Upgraded",
+	    UpgradeEvent::AdminChanged { .. } => "AdminChanged",
+	    UpgradeEvent::BeaconUpgraded { .. } => "BeaconUpgraded",
+	    UpgradeEvent::DiamondCut { .. } => "DiamondCut",
+	}
+    }
+
+    /// Decodes `log`, `None` if it isn't one of the events or doesn't follow their ABI, e.g.
+    /// an `Upgraded` whose implementation isn't indexed.
+    pub fn from_log(log: &Log) -> Option<Self> {
+	let block = log.block_number?.as_u64();
+	let transaction = h256_to_b256(log.transaction_hash?);
+	let topic0 = *log.topics.first()?;
+	let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+	let event = if topic0 == UpgradedFilter::signature() {
+	    let event = UpgradedFilter::decode_log(&raw).ok()?;
+	    UpgradeEvent::Upgraded { block, transaction, implementation: h160_to_b160(&event.implementation) }
+	} else if topic0 == AdminChangedFilter::signature() {
+	    let event = AdminChangedFilter::decode_log(&raw).ok()?;
+	    UpgradeEvent::AdminChanged { block, transaction, previous_admin: h160_to_b160(&event.previous_admin), new_admin: h160_to_b160(&event.new_admin) }
+	} else if topic0 == BeaconUpgradedFilter::signature() {
+	    let event = BeaconUpgradedFilter::decode_log(&raw).ok()?;
+	    UpgradeEvent::BeaconUpgraded { block, transaction, beacon: h160_to_b160(&event.beacon) }
+	} else if topic0 == DiamondCutFilter::signature() {
+	    let event = DiamondCutFilter::decode_log(&raw).ok()?;
+	    let cuts = event.diamond_cut.iter().map(|cut| Some(FacetCut {
+		facet: h160_to_b160(&cut.facet_address),
+		action: FacetCutAction::from_u8(cut.action)?,
+		selectors: cut.function_selectors.iter().map(|selector| Selector::new(*selector)).collect(),
+	    })).collect::<Option<Vec<_>>>()?;
+	    UpgradeEvent::DiamondCut { block, transaction, cuts, init: h160_to_b160(&event.init), data: Bytes::from(event.data.to_vec()) }
+	} else {
+	    return None;
+	};
+	Some(event)
+    }
+}
+
+/// Whether the provider refused a `eth_getLogs` for spanning too many blocks or matching too
+/// many logs. Rate limits word themselves alike ("limit exceeded") but are retried instead.
+fn is_log_range_error(msg: &str) -> bool {
+    const RANGE_CAPPED: &[&str] = &["block range", "range too large", "range is too large", "too many blocks", "query returned more than", "response size exceeded"];
+    let msg = msg.to_lowercase();
+    RANGE_CAPPED.iter().any(|m| msg.contains(m))
+}
+
+/// The logs matching `filter` in `[from_block, to_block]`, asked for in chunks of at most
+/// [LOG_CHUNK_BLOCKS] blocks, smaller for providers capping the range. Rate limited requests
+/// are retried as `config` says. Every chunk answered is reported to `progress` under the
+/// `upgrade-logs` stage, without a total since refused ranges change the count.
+pub(crate) async fn get_logs_chunked<M>(rpc: &M, filter: &Filter, from_block: u64, to_block: u64, config: &ReadConfig, progress: &dyn ProgressReporter) -> Result<Vec<Log>, ProxyReadError>
+    where M: Middleware
+{
+    let mut emitter = ProgressEmitter::with_throttle(progress, "upgrade-logs", None, Throttle::every_item());
+    let mut logs = Vec::new();
+    let (mut low, mut chunk) = (from_block, LOG_CHUNK_BLOCKS);
+    while low <= to_block {
+	let high = low.saturating_add(chunk - 1).min(to_block);
+	let chunk_filter = filter.clone().from_block(low).to_block(high);
+	let request = || async {
+	    rpc.get_logs(&chunk_filter).await.map_err(|e| {
+		let error = RpcError::from_middleware(&e);
+		// Some providers answer a capped range with their rate limit code, retrying won't help
+		if is_log_range_error(&error.message) { RpcError { kind: RpcErrorKind::Rejected, ..error } } else { error }
+	    })
+	};
+	match with_retries(config, request).await {
+	    Ok(chunk_logs) => {
+		logs.extend(chunk_logs);
+		emitter.advance();
+		if high == to_block {
+		    break;
+		}
+		low = high + 1;
+	    },
+	    Err(e) if chunk > 1 && is_log_range_error(&e.message) => chunk /= 2,
+	    Err(e) => return Err(ProxyReadError::Rpc(e)),
+	}
+    }
+    Ok(logs)
+}
+
+/// The upgrade events `address` logged in `[from_block, to_block]`, in order. Logs with one of
+/// the events' topics but not their ABI are skipped.
+pub async fn scan_upgrade_events<M>(rpc: &M, address: &Address, from_block: u64, to_block: u64) -> Result<Vec<UpgradeEvent>, ProxyReadError>
+    where M: Middleware
+{
+    scan_upgrade_events_with_progress(rpc, address, from_block, to_block, &NoProgress).await
+}
+
+/// [scan_upgrade_events] reporting every `eth_getLogs` chunk answered to `progress` under the
+/// `upgrade-logs` stage.
+pub async fn scan_upgrade_events_with_progress<M>(rpc: &M, address: &Address, from_block: u64, to_block: u64, progress: &dyn ProgressReporter) -> Result<Vec<UpgradeEvent>, ProxyReadError>
+    where M: Middleware
+{
+    let topics: Vec<H256> = vec![UpgradedFilter::signature(), AdminChangedFilter::signature(), BeaconUpgradedFilter::signature(), DiamondCutFilter::signature()];
+    let filter = Filter::new().address(raddress_to_h160(address)).topic0(topics);
+    let logs = get_logs_chunked(rpc, &filter, from_block, to_block, &ReadConfig::default(), progress).await?;
+    Ok(logs.iter().filter_map(|log| {
+	let event = UpgradeEvent::from_log(log);
+	if event.is_none() {
+	    debug!("undecodable upgrade log at block {:?}: {:?}", log.block_number, log.topics);
+	}
+	event
+    }).collect())
+}
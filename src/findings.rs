@@ -0,0 +1,47 @@
+use alloy_primitives::Address;
+
+use crate::redetect::Contradiction;
+use crate::{ProxyDispatch, RuleId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+/// Noteworthy facts about a proxy that don't change its classification.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Finding {
+    /// The delegation behavior changed between two synthetic environments, the contract is
+    /// probably trying to detect the analysis.
+    EvasiveBehavior,
+    /// The proxy's own getter (e.g. `implementation()`) reports a different address than the
+    /// one its dispatch reads from storage.
+    SelfReportMismatch { slot_value: Address, getter_value: Address },
+    /// Resolving the quick pass' `original` dispatch contradicted it, the reported one comes
+    /// from a widened analysis.
+    DispatchCorrected { original: ProxyDispatch },
+    /// Resolving the dispatch contradicted it and a widened analysis found nothing better.
+    ResolutionContradiction(Contradiction),
+    /// Another detector's `rule` found a different dispatch, see [consensus](crate::consensus).
+    StrategyConflict { rule: RuleId, dispatch: ProxyDispatch },
+    /// The implementation was deployed shortly after the proxy, see
+    /// [ProxyFreshness::is_recent_swap](crate::ProxyFreshness::is_recent_swap).
+    RecentImplementation { proxy_deploy_block: u64, impl_deploy_block: u64 },
+    /// The last round of [detect_on_fork](crate::detect_on_fork) read `unfetched` values that
+    /// weren't fetched, the result is partly over synthetic state.
+    IncompleteForkState { unfetched: u64 },
+}
+
+impl Finding {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Finding::EvasiveBehavior | Finding::SelfReportMismatch { .. } | Finding::StrategyConflict { .. } => Severity::High,
+            Finding::ResolutionContradiction(_) | Finding::IncompleteForkState { .. } => Severity::Medium,
+            Finding::DispatchCorrected { .. } | Finding::RecentImplementation { .. } => Severity::Info,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Dynamic detection over a proxy's real chain state: [detect_on_fork] traces the probes with
+//! the storage and code of every account read from a node at a pinned block, instead of the
+//! synthetic values, for proxies whose dispatch depends on several stored values.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use alloy_primitives::{Address, Bytes, U256};
+use ethers_core::types::BlockId;
+use tracing::debug;
+
+use crate::detect::{detect_proxy, DetectorConfig};
+use crate::environment::TraceEnvironment;
+use crate::read::ProxyReadError;
+use crate::reader::StorageReader;
+use crate::{Finding, ProxyDetectionResult};
+
+/// Traces [detect_on_fork] runs at most, each one after fetching what the previous one missed.
+pub const MAX_FORK_ROUNDS: usize = 8;
+
+/// Something a trace over a [ForkState] read that wasn't fetched yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ForkRead {
+    Code(Address),
+    Storage(Address, U256),
+}
+
+/// The chain state of the proxy at `address` as of `block`, as much of it as the traces read.
+/// Reads of state that wasn't fetched are answered with synthetic values and remembered, see
+/// [DetectorConfig::fork].
+#[derive(Debug, Default)]
+pub struct ForkState {
+    pub address: Address,
+    pub block: u64,
+    /// Words of every account, zero if unset.
+    pub storage: HashMap<(Address, U256), U256>,
+    /// Code of every account, empty for those without.
+    pub code: HashMap<Address, Bytes>,
+    missing: Mutex<HashSet<ForkRead>>,
+}
+
+impl ForkState {
+    pub fn new(address: Address, block: u64) -> Self {
+	Self { address, block, ..Default::default() }
+    }
+
+    /// The word at `slot` of `address`, `None` if it wasn't fetched, which is remembered.
+    pub(crate) fn storage(&self, address: &Address, slot: &U256) -> Option<U256> {
+	let value = self.storage.get(&(*address, *slot)).copied();
+	if value.is_none() {
+	    self.missing.lock().unwrap().insert(ForkRead::Storage(*address, *slot));
+	}
+	value
+    }
+
+    /// The code of `address`, `None` if it wasn't fetched, which is remembered.
+    pub(crate) fn code(&self, address: &Address) -> Option<&Bytes> {
+	let code = self.code.get(address);
+	if code.is_none() {
+	    self.missing.lock().unwrap().insert(ForkRead::Code(*address));
+	}
+	code
+    }
+
+    /// What the traces read that wasn't fetched, forgotten.
+    pub fn take_missing(&self) -> Vec<ForkRead> {
+	self.missing.lock().unwrap().drain().collect()
+    }
+
+    /// The state fetched so far, with nothing missing yet.
+    fn fetched(&self) -> Self {
+	Self { storage: self.storage.clone(), code: self.code.clone(), ..Self::new(self.address, self.block) }
+    }
+
+    /// `env` running the proxy itself at the pinned block.
+    pub(crate) fn pin(&self, mut env: TraceEnvironment) -> TraceEnvironment {
+	env.contract = self.address;
+	env.block_number = self.block;
+	env
+    }
+
+    async fn fetch<M>(&mut self, rpc: &M, read: ForkRead) -> Result<(), ProxyReadError>
+	where M: StorageReader
+    {
+	let block = Some(BlockId::from(self.block));
+	match read {
+	    ForkRead::Code(address) => {
+		let code = rpc.code_at(address, block).await?;
+		self.code.insert(address, code);
+	    },
+	    ForkRead::Storage(address, slot) => {
+		let word = rpc.storage_at(address, slot, block).await?;
+		self.storage.insert((address, slot), U256::from_be_bytes(word.0));
+	    },
+	}
+	Ok(())
+    }
+}
+
+/// Detects the proxy at `address` as of `block` with the probes traced over its real state, see
+/// [DetectorConfig::fork]. The state is fetched as the traces read it: each trace runs over what
+/// was fetched, then what it read on top is fetched, until a trace reads nothing new or
+/// [MAX_FORK_ROUNDS] ran. If the last one still read unfetched state, partly answered with
+/// synthetic values, the result carries a [Finding::IncompleteForkState]. Calls to other
+/// contracts are run over their real state too, except those of the
+/// [synthetic_returns](DetectorConfig::synthetic_returns) selectors.
+///
+/// Slower than [detect_proxy] and needs a node serving `block`, for proxies whose dispatch the
+/// synthetic storage leads astray, e.g. through a registry or packed values.
+pub async fn detect_on_fork<M>(rpc: &M, address: Address, block: u64, config: &DetectorConfig) -> Result<Option<ProxyDetectionResult>, ProxyReadError>
+    where M: StorageReader
+{
+    let mut state = ForkState::new(address, block);
+    state.fetch(rpc, ForkRead::Code(address)).await?;
+    let code = state.code[&address].clone();
+    for round in 0..MAX_FORK_ROUNDS {
+	// A fresh copy each round, the config may outlive the detection
+	let fork = Arc::new(state.fetched());
+	let mut result = detect_proxy(&code, &DetectorConfig { fork: Some(fork.clone()), ..config.clone() });
+	let missing = fork.take_missing();
+	if missing.is_empty() {
+	    return Ok(result);
+	}
+	if round + 1 == MAX_FORK_ROUNDS {
+	    debug!("fork of {} still read {} unfetched values after {} rounds", address, missing.len(), MAX_FORK_ROUNDS);
+	    if let Some(result) = &mut result {
+		result.findings.push(Finding::IncompleteForkState { unfetched: missing.len() as u64 });
+	    }
+	    return Ok(result);
+	}
+	debug!("fork round {} of {} read {} more values", round, address, missing.len());
+	for read in missing {
+	    state.fetch(rpc, read).await?;
+	}
+    }
+    unreachable!("the last round returns")
+}
@@ -0,0 +1,255 @@
+//! Content derived identifiers of detections, to deduplicate results of workers scanning
+//! overlapping address sets.
+//!
+//! # Canonical serialization
+//!
+//! [ProxyDetectionResult::detection_id] is the keccak256 of, in order:
+//!
+//! ```text
+//! domain      "evm-proxy-tools/detection-id"   ASCII, no length prefix
+//! ruleset     u32 big endian                   RULESET_VERSION
+//! code_hash   32 bytes                         keccak256 of the normalized code, below
+//! proxy_type  len:u8 name:ASCII                ProxyType::name, e.g. `EIP-1967 Transparent Proxy`
+//! dispatch    tag:u8 fields                    below
+//! ```
+//!
+//! The normalized code is the code without its CBOR metadata, with the bytes of every
+//! [ImplementationAddress](ProvenanceKind::ImplementationAddress),
+//! [BeaconAddress](ProvenanceKind::BeaconAddress) and [ImmutableArgs](ProvenanceKind::ImmutableArgs)
+//! provenance range zeroed: clones of one template hash the same, the values they differ by
+//! are in the dispatch.
+//!
+//! Integers are big endian, counts and lengths `u32`. Dispatch fields by tag:
+//!
+//! ```text
+//! 0x00 Unknown
+//! 0x01 Storage          slot:32 has_extraction:u8 (shift:u32 mask:32)?
+//! 0x02 MultipleStorage  count:u32 slot:32...                 slots sorted ascending
+//! 0x03 Static           address:20
+//! 0x04 Facet_EIP_2535
+//! 0x05 FacetStorageSlot
+//! 0x06 External         address:20 selector:u32
+//! 0x07 PerSelector      count:u32 (selector:u32 address:20)... sorted by selector
+//! 0x08 StaticWithArgs   address:20 len:u32 args
+//! 0x09 Beacon           slot:32
+//! ```
+//!
+//! Findings, provenance, attribution, the admin slot and the rule that matched aren't part of
+//! the id: they describe how a worker got to the result, not what the contract is.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use alloy_primitives::{keccak256, B256};
+
+use crate::rules::{RuleId, RULESET_VERSION};
+use crate::upgrade::split_metadata;
+use crate::findings::Finding;
+use crate::{ProvenanceKind, ProxyDetectionResult, ProxyDispatch};
+
+const DOMAIN: &[u8] = b"evm-proxy-tools/detection-id";
+
+fn canonical_bytes_under(result: &ProxyDetectionResult, code: &[u8], ruleset: u32) -> Vec<u8> {
+    let mut out = DOMAIN.to_vec();
+    out.extend_from_slice(&ruleset.to_be_bytes());
+    out.extend_from_slice(keccak256(normalized_code(result, code)).as_slice());
+    let name = result.proxy_type.name();
+    out.push(name.len() as u8);
+    out.extend_from_slice(name.as_bytes());
+    canonical_dispatch(&mut out, &result.dispatch);
+    out
+}
+
+/// `code` without its metadata and with the per instance values of `result`'s provenance zeroed.
+fn normalized_code(result: &ProxyDetectionResult, code: &[u8]) -> Vec<u8> {
+    let mut code = split_metadata(code).0.to_vec();
+    let immutables = result.provenance.iter()
+	.filter(|p| matches!(p.kind, ProvenanceKind::ImplementationAddress | ProvenanceKind::BeaconAddress | ProvenanceKind::ImmutableArgs));
+    for provenance in immutables {
+	let end = provenance.offset.saturating_add(provenance.length).min(code.len());
+	if let Some(range) = code.get_mut(provenance.offset..end) {
+	    range.fill(0);
+	}
+    }
+    code
+}
+
+/// The id of `result` as classified by ruleset `ruleset` rather than the latest.
+pub(crate) fn detection_id_under(result: &ProxyDetectionResult, code: &[u8], ruleset: u32) -> B256 {
+    keccak256(canonical_bytes_under(result, code, ruleset))
+}
+
+fn put_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&u32::try_from(value).expect("length fits in u32").to_be_bytes());
+}
+
+fn canonical_dispatch(out: &mut Vec<u8>, dispatch: &ProxyDispatch) {
+    match dispatch {
+	ProxyDispatch::Unknown => out.push(0x00),
+	ProxyDispatch::Storage(slot, extraction) => {
+	    out.push(0x01);
+	    out.extend_from_slice(&slot.to_be_bytes::<32>());
+	    out.push(extraction.is_some() as u8);
+	    if let Some(extraction) = extraction {
+		put_u32(out, extraction.shift);
+		out.extend_from_slice(&extraction.mask.to_be_bytes::<32>());
+	    }
+	},
+	ProxyDispatch::MultipleStorage(slots) => {
+	    out.push(0x02);
+	    let mut slots = slots.clone();
+	    slots.sort();
+	    put_u32(out, slots.len());
+	    slots.iter().for_each(|slot| out.extend_from_slice(&slot.to_be_bytes::<32>()));
+	},
+	ProxyDispatch::Static(address) => {
+	    out.push(0x03);
+	    out.extend_from_slice(address.as_slice());
+	},
+	ProxyDispatch::Facet_EIP_2535 => out.push(0x04),
+	ProxyDispatch::FacetStorageSlot => out.push(0x05),
+	ProxyDispatch::External(address, selector) => {
+	    out.push(0x06);
+	    out.extend_from_slice(address.as_slice());
+	    out.extend_from_slice(selector.as_bytes());
+	},
+	ProxyDispatch::PerSelector(table) => {
+	    out.push(0x07);
+	    let mut table = table.clone();
+	    table.sort();
+	    put_u32(out, table.len());
+	    for (selector, address) in table {
+		out.extend_from_slice(selector.as_bytes());
+		out.extend_from_slice(address.as_slice());
+	    }
+	},
+	ProxyDispatch::StaticWithArgs(address, args) => {
+	    out.push(0x08);
+	    out.extend_from_slice(address.as_slice());
+	    put_u32(out, args.len());
+	    out.extend_from_slice(args);
+	},
+	ProxyDispatch::Beacon(slot) => {
+	    out.push(0x09);
+	    out.extend_from_slice(&slot.to_be_bytes::<32>());
+	},
+    }
+}
+
+impl ProxyDetectionResult {
+    /// The canonical serialization [detection_id](Self::detection_id) hashes, see the module
+    /// documentation.
+    pub fn canonical_bytes(&self, code: &[u8]) -> Vec<u8> {
+	canonical_bytes_under(self, code, RULESET_VERSION)
+    }
+
+    /// Identifier of this result for `code`, the code it was detected in. Equal for equal
+    /// classifications of codes differing only in metadata, and stable across runs and
+    /// releases as long as [RULESET_VERSION] is.
+    pub fn detection_id(&self, code: &[u8]) -> B256 {
+	keccak256(self.canonical_bytes(code))
+    }
+}
+
+/// Orders results with the same id by how much to trust them: the rule that matched (earlier
+/// rules in [RuleId::ALL] are more specific, static patterns before trace heuristics), then
+/// the one carrying more information: flagged evasion, attribution, admin slot, upgradeable
+/// slots, facet slots, provenance, findings.
+pub fn compare_confidence(a: &ProxyDetectionResult, b: &ProxyDetectionResult) -> Ordering {
+    let rule_rank = |rule: RuleId| RuleId::ALL.iter().position(|r| *r == rule).unwrap_or(usize::MAX);
+    rule_rank(b.rule).cmp(&rule_rank(a.rule))
+	.then_with(|| a.evasive.cmp(&b.evasive))
+	.then_with(|| a.attribution.is_some().cmp(&b.attribution.is_some()))
+	.then_with(|| a.admin_slot.is_some().cmp(&b.admin_slot.is_some()))
+	.then_with(|| a.upgradeable_slots.len().cmp(&b.upgradeable_slots.len()))
+	.then_with(|| a.facet_slots.len().cmp(&b.facet_slots.len()))
+	.then_with(|| a.provenance.len().cmp(&b.provenance.len()))
+	.then_with(|| a.findings.len().cmp(&b.findings.len()))
+}
+
+/// The result the detectors of [detect_all](crate::detect_all) agree on: one with the dispatch
+/// most of them found, the most confident on a tie, see [compare_confidence]. Each opinion
+/// with another dispatch is added to it as a [Finding::StrategyConflict].
+pub fn consensus(opinions: &[(&'static str, ProxyDetectionResult)]) -> Option<ProxyDetectionResult> {
+    let agreeing = |result: &ProxyDetectionResult| opinions.iter().filter(|(_, other)| other.dispatch == result.dispatch).count();
+    let (_, best) = opinions.iter().max_by(|(_, a), (_, b)| agreeing(a).cmp(&agreeing(b)).then_with(|| compare_confidence(a, b)))?;
+    let mut result = best.clone();
+    result.findings.extend(opinions.iter()
+	.filter(|(_, other)| other.dispatch != best.dispatch)
+	.map(|(_, other)| Finding::StrategyConflict { rule: other.rule, dispatch: other.dispatch.clone() }));
+    Some(result)
+}
+
+/// Results keyed by [detection_id](ProxyDetectionResult::detection_id), keeping the most
+/// confident result per id.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DetectionSet {
+    results: BTreeMap<B256, ProxyDetectionResult>,
+}
+
+impl DetectionSet {
+    pub fn new() -> Self {
+	Self::default()
+    }
+
+    /// Adds `result` under `id`, replacing the one there if `result` is more confident. Returns
+    /// whether `result` was kept.
+    pub fn insert(&mut self, id: B256, result: ProxyDetectionResult) -> bool {
+	match self.results.get_mut(&id) {
+	    Some(current) if compare_confidence(&result, current) != Ordering::Greater => false,
+	    Some(current) => {
+		*current = result;
+		true
+	    },
+	    None => {
+		self.results.insert(id, result);
+		true
+	    }
+	}
+    }
+
+    /// Adds a result detected in `code`.
+    pub fn insert_detected(&mut self, code: &[u8], result: ProxyDetectionResult) -> bool {
+	self.insert(result.detection_id(code), result)
+    }
+
+    /// Merges the results of another worker.
+    pub fn merge(&mut self, other: DetectionSet) {
+	for (id, result) in other.results {
+	    self.insert(id, result);
+	}
+    }
+
+    pub fn get(&self, id: &B256) -> Option<&ProxyDetectionResult> {
+	self.results.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+	self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.results.is_empty()
+    }
+
+    /// Results sorted by id.
+    pub fn iter(&self) -> impl Iterator<Item = (&B256, &ProxyDetectionResult)> {
+	self.results.iter()
+    }
+}
+
+impl FromIterator<(B256, ProxyDetectionResult)> for DetectionSet {
+    fn from_iter<I: IntoIterator<Item = (B256, ProxyDetectionResult)>>(iter: I) -> Self {
+	let mut set = DetectionSet::new();
+	iter.into_iter().for_each(|(id, result)| { set.insert(id, result); });
+	set
+    }
+}
+
+/// Merges the result sets of several workers, see [DetectionSet::merge].
+pub fn merge_detections(sets: impl IntoIterator<Item = DetectionSet>) -> DetectionSet {
+    sets.into_iter().fold(DetectionSet::new(), |mut merged, set| {
+	merged.merge(set);
+	merged
+    })
+}
@@ -0,0 +1,489 @@
+//! One-shot analysis of deployed contracts: [Inspector] connects to a node from its URL and
+//! reports what the crate finds about an address with the default options.
+
+use std::{fmt::Debug, sync::Arc};
+
+use alloy_primitives::{Address, U256};
+use async_trait::async_trait;
+use ethers_core::types::BlockId;
+use ethers_providers::{Http, JsonRpcClient, Middleware, MiddlewareError, Provider, ProviderError, Ws};
+use futures::{stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tracing::debug;
+use url::Url;
+
+use crate::detect::{detect_proxy, DetectorConfig};
+use crate::findings::{Finding, Severity};
+use crate::read::{check_self_report_at, get_proxy_admin, get_proxy_freshness, read_single_storage_implementation, ErrorCategory, ProxyImplementation, ProxyReadError, RpcError, DEFAULT_SEARCH_BUDGET};
+use crate::redetect::{resolve_with_redetection_at, RedetectConfig};
+use crate::trust::{trust_set, TrustSet};
+use crate::utils::raddress_to_h160;
+use crate::{ProxyDetectionResult, ProxyDispatch, ProxyType};
+
+/// Addresses [Inspector::analyze_many] analyses at once.
+pub const MAX_CONCURRENT_ANALYSES: usize = 8;
+
+/// Proxies [Inspector::follow_at] goes through by default before giving up.
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+/// The transport of a node URL, picked from its scheme.
+#[derive(Clone, Debug)]
+pub enum RpcTransport {
+    Http(Http),
+    Ws(Ws),
+}
+
+impl RpcTransport {
+    /// Connects to `url`: over HTTP for `http` and `https` URLs, over a websocket for `ws` and
+    /// `wss` ones.
+    pub async fn connect(url: &str) -> Result<Self, InspectorError> {
+	let parsed = Url::parse(url)?;
+	match parsed.scheme() {
+	    "http" | "https" => Ok(RpcTransport::Http(Http::new(parsed))),
+	    "ws" | "wss" => Ws::connect(url).await
+		.map(RpcTransport::Ws)
+		.map_err(InspectorError::connect),
+	    scheme => Err(InspectorError::UnsupportedScheme(scheme.to_string())),
+	}
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcTransport {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+	where T: Debug + Serialize + Send + Sync,
+	      R: DeserializeOwned + Send
+    {
+	match self {
+	    RpcTransport::Http(http) => Ok(http.request(method, params).await?),
+	    RpcTransport::Ws(ws) => Ok(ws.request(method, params).await?),
+	}
+    }
+}
+
+/// Why an [Inspector] couldn't be built or an address analysed. The errors of the node and of
+/// the connection to it are kept as [source](std::error::Error::source).
+#[derive(Clone, Debug, Error)]
+pub enum InspectorError {
+    #[error("no RPC URL was given")]
+    MissingUrl,
+    #[error("invalid RPC URL: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("unsupported RPC URL scheme `{0}`, expected http, https, ws or wss")]
+    UnsupportedScheme(String),
+    #[error("couldn't connect to the node: {0}")]
+    Connect(#[source] Arc<dyn std::error::Error + Send + Sync>),
+    #[error("couldn't get the node's chain id: {0}")]
+    ChainId(#[source] RpcError),
+    #[error("the node's chain id {0} doesn't fit 64 bits")]
+    ChainIdOverflow(ethers_core::types::U256),
+    /// The proxies followed, the last one being the first seen again.
+    #[error("proxy chain cycle detected: {}", .0.iter().map(Address::to_string).collect::<Vec<_>>().join(" -> "))]
+    ChainCycle(Vec<Address>),
+    #[error("proxy chain longer than {0} proxies")]
+    ChainTooDeep(usize),
+    #[error(transparent)]
+    Read(#[from] ProxyReadError),
+}
+
+impl InspectorError {
+    /// The connection to the node failing with `error`.
+    pub fn connect(error: impl std::error::Error + Send + Sync + 'static) -> Self {
+	InspectorError::Connect(Arc::new(error))
+    }
+
+    /// `eth_chainId` failing with `error`, through an ethers middleware.
+    pub fn chain_id<E: MiddlewareError>(error: &E) -> Self {
+	InspectorError::ChainId(RpcError::from_middleware(error))
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+	match self {
+	    InspectorError::MissingUrl | InspectorError::InvalidUrl(_) | InspectorError::UnsupportedScheme(_)
+	    | InspectorError::ChainTooDeep(_) => ErrorCategory::User,
+	    InspectorError::Connect(_) | InspectorError::ChainId(_) => ErrorCategory::Rpc,
+	    InspectorError::ChainIdOverflow(_) => ErrorCategory::Decoding,
+	    InspectorError::ChainCycle(_) => ErrorCategory::NotSupported,
+	    InspectorError::Read(e) => e.category(),
+	}
+    }
+
+    /// Whether trying again later may succeed, see [ProxyReadError::is_retryable]. A failed
+    /// connection is.
+    pub fn is_retryable(&self) -> bool {
+	match self {
+	    InspectorError::Connect(_) => true,
+	    InspectorError::ChainId(e) => e.kind.is_retryable(),
+	    InspectorError::Read(e) => e.is_retryable(),
+	    _ => false,
+	}
+    }
+}
+
+/// How much of a [ProxyReport] [Inspector::analyze] computes and [ProxyReport::summary]
+/// serializes. Each tier adds to the previous one, sections a tier doesn't need aren't
+/// requested from the node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReportDetail {
+    /// The address, the proxy type, the kind of dispatch and the primary implementation.
+    Minimal,
+    /// Adds the rule that matched, which is what the confidence in the result rests on, the
+    /// resolved implementations and the findings of medium severity or more, which needs the
+    /// self-reported implementation.
+    Standard,
+    /// Adds the chain, the code size, the admin, the beacon, the trust set, every finding and
+    /// the slots of the detection.
+    #[default]
+    Full,
+}
+
+/// Everything [Inspector::analyze] found about an address, as of [ProxyReport::block].
+#[derive(Clone, Debug)]
+pub struct ProxyReport {
+    pub address: Address,
+    pub chain_id: u64,
+    /// The block analysed at, `None` for the latest one.
+    pub block: Option<BlockId>,
+    pub code_size: usize,
+    /// `None` if the code isn't a proxy. A widened detection replaces the quick one when the
+    /// node contradicts it, see [resolve_with_redetection](crate::resolve_with_redetection).
+    pub detection: Option<ProxyDetectionResult>,
+    /// The proxy's implementation, or why it couldn't be resolved. [ProxyReadError::UnknownProxy]
+    /// when the code isn't a proxy.
+    pub implementation: Result<ProxyImplementation, ProxyReadError>,
+    /// Read when the proxy loads an admin slot, `None` if it doesn't or the slot is empty.
+    pub admin: Option<Address>,
+    /// Read from the slot of a [ProxyDispatch::Beacon].
+    pub beacon: Option<Address>,
+    /// Every address the proxy trusts, empty if the code isn't a proxy.
+    pub trust_set: TrustSet,
+    /// What was computed: below [ReportDetail::Full] the admin, beacon and trust set are left
+    /// empty, below [ReportDetail::Standard] the self-report isn't checked.
+    pub detail: ReportDetail,
+}
+
+impl ProxyReport {
+    pub fn is_proxy(&self) -> bool {
+	self.detection.is_some()
+    }
+
+    /// The detection's findings and those of checking it against the node.
+    pub fn findings(&self) -> &[Finding] {
+	self.detection.as_ref().map_or(&[], |detection| &detection.findings)
+    }
+
+    /// The sections of [ProxyReport::detail], to serialize.
+    pub fn summary(&self) -> ReportSummary {
+	let detection = self.detection.as_ref();
+	let implementation = self.implementation.as_ref().ok();
+	let standard = self.detail >= ReportDetail::Standard;
+	let full = self.detail == ReportDetail::Full;
+	let min_severity = if full { Severity::Info } else { Severity::Medium };
+	ReportSummary {
+	    address: self.address,
+	    proxy_type: detection.map_or(ProxyType::NoProxy, |detection| detection.proxy_type),
+	    dispatch: detection.map(|detection| dispatch_kind(&detection.dispatch)),
+	    implementation: implementation.and_then(primary_implementation),
+	    rule: detection.filter(|_| standard).map(|detection| format!("{:?}", detection.rule)),
+	    implementations: implementation.filter(|_| standard).map(|implementation| {
+		let mut addresses = implementation.to_vec();
+		addresses.sort();
+		addresses
+	    }),
+	    implementation_error: self.implementation.as_ref().err()
+		.filter(|_| standard && detection.is_some())
+		.map(|e| e.to_string()),
+	    findings: self.findings().iter()
+		.filter(|finding| standard && finding.severity() >= min_severity)
+		.map(|finding| format!("{:?}", finding))
+		.collect(),
+	    chain_id: Some(self.chain_id).filter(|_| full),
+	    code_size: Some(self.code_size).filter(|_| full),
+	    evasive: full && detection.is_some_and(|detection| detection.evasive),
+	    admin_slot: detection.filter(|_| full).and_then(|detection| detection.admin_slot),
+	    upgradeable_slots: detection.filter(|_| full).map_or(Vec::new(), |detection| detection.upgradeable_slots.clone()),
+	    admin: self.admin.filter(|_| full),
+	    beacon: self.beacon.filter(|_| full),
+	    trust_set: Some(&self.trust_set).filter(|trust_set| full && !trust_set.entries.is_empty()).cloned(),
+	}
+    }
+}
+
+/// The serialized form of a [ProxyReport], with the sections of its [ReportDetail]. Empty
+/// sections are left out.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReportSummary {
+    pub address: Address,
+    #[serde(rename = "type")]
+    pub proxy_type: ProxyType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dispatch: Option<&'static str>,
+    /// The implementation of a single or the first of multiple storage slots, `None` for
+    /// diamonds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    /// Every implementation, sorted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementations: Option<Vec<Address>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation_error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_size: Option<usize>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub evasive: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_slot: Option<U256>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upgradeable_slots: Vec<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beacon: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trust_set: Option<TrustSet>,
+}
+
+fn dispatch_kind(dispatch: &ProxyDispatch) -> &'static str {
+    match dispatch {
+	ProxyDispatch::Unknown => "unknown",
+	ProxyDispatch::Storage(..) => "storage",
+	ProxyDispatch::MultipleStorage(_) => "multiple_storage",
+	ProxyDispatch::Beacon(_) => "beacon",
+	ProxyDispatch::Static(_) => "static",
+	ProxyDispatch::StaticWithArgs(..) => "static_with_args",
+	ProxyDispatch::Facet_EIP_2535 => "facets",
+	ProxyDispatch::FacetStorageSlot => "facet_storage_slot",
+	ProxyDispatch::PerSelector(_) => "per_selector",
+	ProxyDispatch::External(..) => "external",
+    }
+}
+
+fn primary_implementation(implementation: &ProxyImplementation) -> Option<Address> {
+    match implementation {
+	ProxyImplementation::Single(address) => Some(*address),
+	ProxyImplementation::Multiple(addresses) => addresses.first().copied(),
+	ProxyImplementation::Facets(_) => None,
+    }
+}
+
+/// Builds an [Inspector] connected to a node by URL.
+#[derive(Clone, Debug, Default)]
+pub struct InspectorBuilder {
+    url: Option<String>,
+    config: DetectorConfig,
+    redetect: Option<RedetectConfig>,
+    detail: ReportDetail,
+    freshness_window: Option<u64>,
+}
+
+impl InspectorBuilder {
+    /// The node's `http(s)://` or `ws(s)://` URL.
+    pub fn rpc_url(mut self, url: &str) -> Self {
+	self.url = Some(url.to_string());
+	self
+    }
+
+    pub fn config(mut self, config: DetectorConfig) -> Self {
+	self.config = config;
+	self
+    }
+
+    pub fn redetect(mut self, redetect: RedetectConfig) -> Self {
+	self.redetect = Some(redetect);
+	self
+    }
+
+    pub fn detail(mut self, detail: ReportDetail) -> Self {
+	self.detail = detail;
+	self
+    }
+
+    pub fn freshness_window(mut self, window: u64) -> Self {
+	self.freshness_window = Some(window);
+	self
+    }
+
+    /// Connects to the node and gets its chain id. Async since websockets connect eagerly.
+    pub async fn build(self) -> Result<Inspector, InspectorError> {
+	let url = self.url.ok_or(InspectorError::MissingUrl)?;
+	let rpc = Provider::new(RpcTransport::connect(&url).await?);
+	let inspector = Inspector::with_config(rpc, self.config, self.redetect.unwrap_or_default()).await?.detail(self.detail);
+	Ok(match self.freshness_window {
+	    Some(window) => inspector.freshness_window(window),
+	    None => inspector,
+	})
+    }
+}
+
+/// Analyses deployed contracts through a node: fetches their code, detects the proxy, resolves
+/// its implementation and admin, and checks its self-reported implementation.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), evm_proxy_tools::InspectorError> {
+/// use evm_proxy_tools::Inspector;
+///
+/// let inspector = Inspector::builder().rpc_url("http://localhost:8545").build().await?;
+/// let address = "0xdac17f958d2ee523a2206206994597c13d831ec7".parse().unwrap();
+/// let report = inspector.analyze_at(address, Some(19_000_000u64.into())).await?;
+/// if let Some(detection) = &report.detection {
+///     println!("{:?} proxy of {:?}, admin {:?}", detection.proxy_type, report.implementation, report.admin);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Inspector<M = Provider<RpcTransport>> {
+    rpc: Arc<M>,
+    chain_id: u64,
+    config: DetectorConfig,
+    redetect: RedetectConfig,
+    detail: ReportDetail,
+    freshness_window: Option<u64>,
+}
+
+impl Inspector {
+    pub fn builder() -> InspectorBuilder {
+	InspectorBuilder::default()
+    }
+}
+
+impl<M> Inspector<M>
+    where M: Middleware + 'static
+{
+    /// An inspector over a provider built by the caller, e.g. with middleware.
+    pub async fn from_provider(rpc: M, config: DetectorConfig) -> Result<Self, InspectorError> {
+	Self::with_config(rpc, config, RedetectConfig::default()).await
+    }
+
+    async fn with_config(rpc: M, config: DetectorConfig, redetect: RedetectConfig) -> Result<Self, InspectorError> {
+	let chain_id = rpc.get_chainid().await.map_err(|e| InspectorError::chain_id(&e))?;
+	let chain_id = u64::try_from(chain_id).map_err(|_| InspectorError::ChainIdOverflow(chain_id))?;
+	Ok(Self { rpc: Arc::new(rpc), chain_id, config, redetect, detail: ReportDetail::default(), freshness_window: None })
+    }
+
+    /// The sections of the reports to compute, [ReportDetail::Full] by default.
+    pub fn detail(mut self, detail: ReportDetail) -> Self {
+	self.detail = detail;
+	self
+    }
+
+    /// Searches when a single implementation and its proxy were deployed, to report a
+    /// [Finding::RecentImplementation] if the implementation came less than `window` blocks
+    /// after the proxy. Only in [ReportDetail::Full] reports, off by default since each search
+    /// takes up to [DEFAULT_SEARCH_BUDGET] calls.
+    pub fn freshness_window(mut self, window: u64) -> Self {
+	self.freshness_window = Some(window);
+	self
+    }
+
+    pub fn chain_id(&self) -> u64 {
+	self.chain_id
+    }
+
+    pub fn provider(&self) -> &Arc<M> {
+	&self.rpc
+    }
+
+    /// Analyses the contract at `address`, [ProxyReadError::NoCode] if there's none. Failures
+    /// resolving the implementation are reported in [ProxyReport::implementation], other RPC
+    /// failures fail the analysis.
+    pub async fn analyze(&self, address: Address) -> Result<ProxyReport, InspectorError> {
+	self.analyze_at(address, None).await
+    }
+
+    /// [Inspector::analyze] at `block`, the code and every read being the ones at that block.
+    /// An admin slot that doesn't hold an address leaves [ProxyReport::admin] empty.
+    pub async fn analyze_at(&self, address: Address, block: Option<BlockId>) -> Result<ProxyReport, InspectorError> {
+	let code = self.rpc.get_code(raddress_to_h160(&address), block).await
+	    .map_err(ProxyReadError::from_middleware)?;
+	if code.is_empty() {
+	    return Err(ProxyReadError::NoCode(address).into());
+	}
+	let mut report = ProxyReport { address, chain_id: self.chain_id, block, code_size: code.len(), detection: None, implementation: Err(ProxyReadError::UnknownProxy), admin: None, beacon: None, trust_set: TrustSet::default(), detail: self.detail };
+	let Some(detection) = detect_proxy(&code, &self.config) else {
+	    return Ok(report);
+	};
+	debug!("{} is a {:?} proxy", address, detection.proxy_type);
+	let resolution = resolve_with_redetection_at(self.rpc.clone(), &address, &code, detection, &self.config, &self.redetect, block).await;
+	let mut detection = resolution.detection;
+	if self.detail >= ReportDetail::Standard {
+	    if let (ProxyDispatch::Storage(..), Ok(ProxyImplementation::Single(implementation))) = (&detection.dispatch, &resolution.implementation) {
+		let self_report = check_self_report_at(self.rpc.as_ref(), &address, detection.proxy_type, *implementation, block).await;
+		detection.findings.extend(self_report.and_then(|self_report| self_report.finding()));
+	    }
+	}
+	if self.detail == ReportDetail::Full {
+	    if detection.admin_slot.is_some() {
+		// An admin slot holding something else doesn't undo the detection
+		report.admin = match get_proxy_admin(self.rpc.as_ref(), &address, block).await {
+		    Ok(admin) => admin,
+		    Err(e) if e.category() == ErrorCategory::Rpc => return Err(e.into()),
+		    Err(e) => {
+			debug!("couldn't read the admin of {}: {}", address, e);
+			None
+		    },
+		};
+	    }
+	    if let ProxyDispatch::Beacon(slot) = &detection.dispatch {
+		report.beacon = read_single_storage_implementation(self.rpc.as_ref(), &address, slot, None, block).await.ok();
+	    }
+	    report.trust_set = trust_set(&address, &detection, resolution.implementation.as_ref().ok(), report.admin, report.beacon);
+	    if let (Some(window), Ok(ProxyImplementation::Single(implementation))) = (self.freshness_window, &resolution.implementation) {
+		// Pruned nodes can't tell, which isn't worth failing the analysis over
+		match get_proxy_freshness(self.rpc.as_ref(), &address, implementation, block, DEFAULT_SEARCH_BUDGET).await {
+		    Ok(freshness) => detection.findings.extend(freshness.finding(window)),
+		    Err(e) => debug!("couldn't tell when {} and {} were deployed: {}", address, implementation, e),
+		}
+	    }
+	}
+	report.detection = Some(detection);
+	report.implementation = resolution.implementation;
+	Ok(report)
+    }
+
+    /// [Inspector::analyze_at] of `address`, then of its implementation while that is a single
+    /// contract itself a proxy, one report per proxy of the chain. Fails with
+    /// [InspectorError::ChainCycle] when an implementation was already followed and with
+    /// [InspectorError::ChainTooDeep] past `max_depth` proxies.
+    pub async fn follow_at(&self, address: Address, block: Option<BlockId>, max_depth: usize) -> Result<Vec<ProxyReport>, InspectorError> {
+	let mut reports = vec![self.analyze_at(address, block).await?];
+	while let Some(Ok(ProxyImplementation::Single(next))) = reports.last().map(|report| &report.implementation) {
+	    let next = *next;
+	    if reports.iter().any(|report| report.address == next) {
+		let mut cycle: Vec<Address> = reports.iter().map(|report| report.address).collect();
+		cycle.push(next);
+		return Err(InspectorError::ChainCycle(cycle));
+	    }
+	    let report = match self.analyze_at(next, block).await {
+		Ok(report) if report.is_proxy() => report,
+		// The implementation the chain ends at
+		Ok(_) | Err(InspectorError::Read(ProxyReadError::NoCode(_))) => break,
+		Err(e) => return Err(e),
+	    };
+	    if reports.len() >= max_depth {
+		return Err(InspectorError::ChainTooDeep(max_depth));
+	    }
+	    debug!("implementation {} is itself a {:?} proxy", next, report.detection.as_ref().map(|detection| detection.proxy_type));
+	    reports.push(report);
+	}
+	Ok(reports)
+    }
+
+    /// [Inspector::analyze] of each address, in order, [MAX_CONCURRENT_ANALYSES] at a time.
+    pub async fn analyze_many(&self, addresses: &[Address]) -> Vec<Result<ProxyReport, InspectorError>> {
+	stream::iter(addresses)
+	    .map(|address| self.analyze(*address))
+	    .buffered(MAX_CONCURRENT_ANALYSES)
+	    .collect()
+	    .await
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+
+use revm::interpreter::opcode;
+
+use crate::disasm::{disassemble, Instruction};
+use crate::selector::Selector;
+
+/// Instructions followed from a function entry while looking for its CALLVALUE guard.
+const GUARD_SCAN_LIMIT: usize = 64;
+
+/// Direct jumps followed from a function entry while looking for its CALLVALUE guard.
+const GUARD_JUMP_LIMIT: usize = 8;
+
+/// Dispatcher blocks visited at most, binary search dispatchers branch on every pivot.
+const DISPATCH_BLOCK_LIMIT: usize = 256;
+
+/// The externally callable surface of a contract, recovered from its selector dispatcher
+/// without the ABI.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InterfaceSketch {
+    /// Selectors the dispatcher compares against, sorted.
+    pub selectors: Vec<Selector>,
+    /// Calls matching no selector are handled instead of reverted.
+    pub fallback: bool,
+    /// Calls without calldata have their own branch.
+    pub receive: bool,
+    /// Selectors whose entry doesn't check CALLVALUE, likely `payable`.
+    pub payable_hints: Vec<Selector>,
+}
+
+/// Instructions indexed by offset.
+pub(crate) struct Listing<'a> {
+    pub(crate) ins: Vec<Instruction<'a>>,
+}
+
+impl<'a> Listing<'a> {
+    pub(crate) fn new(code: &'a [u8]) -> Self {
+	Self { ins: disassemble(code).collect() }
+    }
+
+    fn index_of(&self, offset: usize) -> Option<usize> {
+	self.ins.binary_search_by_key(&offset, |ins| ins.offset).ok()
+    }
+
+    /// Index of the JUMPDEST a PUSH immediate points at.
+    pub(crate) fn jump_target(&self, push: &Instruction) -> Option<usize> {
+	let offset: usize = push.push_value()?.try_into().ok()?;
+	self.index_of(offset).filter(|idx| self.ins[*idx].opcode == opcode::JUMPDEST)
+    }
+
+    pub(crate) fn opcode(&self, idx: usize) -> Option<u8> {
+	self.ins.get(idx).map(|ins| ins.opcode)
+    }
+
+    fn is_zero_push(&self, idx: usize) -> bool {
+	self.ins.get(idx).is_some_and(|ins| ins.opcode == opcode::PUSH0 || ins.push_value().is_some_and(|v| v.is_zero()))
+    }
+
+    /// `JUMPDEST? PUSH 0 (DUP1|PUSH 0) REVERT` or `INVALID`.
+    fn is_revert(&self, idx: usize) -> bool {
+	let idx = self.skip_jumpdest(idx);
+	if self.opcode(idx) == Some(opcode::INVALID) {
+	    return true;
+	}
+	self.is_zero_push(idx)
+	    && (self.opcode(idx + 1) == Some(opcode::DUP1) || self.is_zero_push(idx + 1))
+	    && self.opcode(idx + 2) == Some(opcode::REVERT)
+    }
+
+    fn skip_jumpdest(&self, idx: usize) -> usize {
+	if self.opcode(idx) == Some(opcode::JUMPDEST) { idx + 1 } else { idx }
+    }
+
+    /// Skips solc's free memory pointer setup, `PUSH1 0x80 PUSH1 0x40 MSTORE`.
+    fn skip_prologue(&self) -> usize {
+	match self.ins.get(..3) {
+	    Some([a, b, c]) if a.operand == [0x80] && b.operand == [0x40] && c.opcode == opcode::MSTORE => 3,
+	    _ => 0
+	}
+    }
+}
+
+/// Where the selector is on the stack: after `PUSH1 0xe0 SHR` or, for old compilers,
+/// `DIV PUSH4 0xffffffff AND`.
+pub(crate) fn dispatcher_start(listing: &Listing) -> Option<usize> {
+    listing.ins.windows(3).position(|w| {
+	(w[0].opcode == opcode::PUSH1 && w[0].operand == [0xe0] && w[1].opcode == opcode::SHR)
+	    || (w[0].opcode == opcode::DIV && w[1].opcode == opcode::PUSH4 && w[1].operand == [0xff; 4] && w[2].opcode == opcode::AND)
+    }).map(|idx| if listing.ins[idx].opcode == opcode::DIV { idx + 3 } else { idx + 2 })
+}
+
+/// Matches `PUSHn(n <= 4) selector DUPn? op ISZERO? PUSH dest JUMPI` at `idx`, returning the
+/// selector, the comparison opcode, the jump target and the index after the JUMPI. `ISZERO` is
+/// only accepted after the `GT`/`LT` pivots, where it just swaps the halves.
+fn comparison(listing: &Listing, idx: usize) -> Option<(u32, u8, Option<usize>, usize)> {
+    let push = listing.ins.get(idx)?;
+    if !push.is_push() || push.operand.len() > 4 {
+	return None;
+    }
+    let mut next = idx + 1;
+    // Yul dispatchers keep the selector deeper than solc's DUP2
+    if listing.opcode(next).is_some_and(|op| (opcode::DUP1..=opcode::DUP16).contains(&op)) {
+	next += 1;
+    }
+    let op = listing.opcode(next)?;
+    if matches!(op, opcode::GT | opcode::LT) && listing.opcode(next + 1) == Some(opcode::ISZERO) {
+	next += 1;
+    }
+    let dest = listing.ins.get(next + 1).filter(|ins| ins.is_push())?;
+    (listing.opcode(next + 2) == Some(opcode::JUMPI))
+	.then(|| (push.push_value().unwrap().to::<u32>(), op, listing.jump_target(dest), next + 3))
+}
+
+/// Walks the dispatcher tree, linear `EQ` chains and the `GT`/`LT` pivots binary search
+/// dispatchers branch on, returning every selector with the index of its entry.
+pub(crate) fn dispatch_entries(listing: &Listing, start: usize) -> Vec<(Selector, usize)> {
+    let mut entries = Vec::new();
+    let mut pending = vec![start];
+    let mut visited = HashSet::new();
+    while let Some(mut idx) = pending.pop() {
+	if !visited.insert(idx) || visited.len() > DISPATCH_BLOCK_LIMIT {
+	    continue;
+	}
+	loop {
+	    match listing.opcode(idx) {
+		Some(opcode::JUMPDEST | opcode::DUP1) => idx += 1,
+		_ => match comparison(listing, idx) {
+		    Some((selector, opcode::EQ, Some(dest), next)) => {
+			entries.push((selector.into(), dest));
+			idx = next;
+		    },
+		    Some((_, opcode::GT | opcode::LT, Some(dest), next)) => {
+			pending.push(dest);
+			idx = next;
+		    },
+		    // `selector - x` jumps away when they differ, the function follows
+		    Some((selector, opcode::SUB, _, next)) => {
+			entries.push((selector.into(), next));
+			break;
+		    },
+		    // The zeroed return variable of a Yul lookup function
+		    None if listing.is_zero_push(idx) => idx += 1,
+		    _ => break
+		}
+	    }
+	}
+    }
+    entries
+}
+
+/// Whether the code from `idx` reaches a branch or the end of the function without reading
+/// CALLVALUE, following direct jumps.
+fn lacks_callvalue_guard(listing: &Listing, mut idx: usize) -> bool {
+    let mut jumps = 0;
+    for _ in 0..GUARD_SCAN_LIMIT {
+	let Some(ins) = listing.ins.get(idx) else { return true };
+	match ins.opcode {
+	    opcode::CALLVALUE => return false,
+	    opcode::JUMPI | opcode::STOP | opcode::RETURN | opcode::REVERT | opcode::INVALID | opcode::SELFDESTRUCT => return true,
+	    opcode::JUMP => {
+		let target = idx.checked_sub(1).and_then(|prev| listing.jump_target(&listing.ins[prev]));
+		match target {
+		    Some(target) if jumps < GUARD_JUMP_LIMIT => {
+			jumps += 1;
+			idx = target;
+		    },
+		    _ => return true
+		}
+	    },
+	    _ => idx += 1
+	}
+    }
+    true
+}
+
+/// The branch taken when calldata is shorter than a selector:
+/// `PUSH1 4 CALLDATASIZE LT ISZERO? PUSH dest JUMPI`.
+fn short_calldata_branch(listing: &Listing) -> Option<usize> {
+    (0..listing.ins.len()).find_map(|idx| {
+	let ins = &listing.ins[idx..];
+	if ins.len() < 5 || ins[0].opcode != opcode::PUSH1 || ins[0].operand != [4] || ins[1].opcode != opcode::CALLDATASIZE || ins[2].opcode != opcode::LT {
+	    return None;
+	}
+	let negated = ins[3].opcode == opcode::ISZERO;
+	let (dest, jumpi) = if negated { (ins.get(4)?, ins.get(5)?) } else { (&ins[3], &ins[4]) };
+	if !dest.is_push() || jumpi.opcode != opcode::JUMPI {
+	    return None;
+	}
+	if negated { listing.index_of(jumpi.offset).map(|i| i + 1) } else { listing.jump_target(dest) }
+    })
+}
+
+/// `(fallback, receive)` from the branch handling calls that match no selector. With a
+/// `receive` function it first splits on `CALLDATASIZE ISZERO? PUSH dest JUMPI`.
+fn default_handlers(listing: &Listing, idx: usize) -> (bool, bool) {
+    if listing.is_revert(idx) {
+	return (false, false);
+    }
+    let idx = listing.skip_jumpdest(idx);
+    if listing.opcode(idx) != Some(opcode::CALLDATASIZE) {
+	return (true, false);
+    }
+    let negated = listing.opcode(idx + 1) == Some(opcode::ISZERO);
+    let push = if negated { idx + 2 } else { idx + 1 };
+    let target = listing.ins.get(push).filter(|ins| ins.is_push()).and_then(|ins| listing.jump_target(ins));
+    match (target, listing.opcode(push + 1)) {
+	(Some(target), Some(opcode::JUMPI)) => {
+	    let (fallback, receive) = if negated { (push + 2, target) } else { (target, push + 2) };
+	    (!listing.is_revert(fallback), !listing.is_revert(receive))
+	},
+	_ => (true, false)
+    }
+}
+
+/// Recovers the selectors a contract dispatches on, whether it has `fallback`/`receive`
+/// handlers and which functions are likely payable.
+///
+/// Understands the solc dispatchers (linear and binary search, legacy and via-IR); other
+/// compilers may give partial results.
+pub fn recover_interface(code: &[u8]) -> InterfaceSketch {
+    let listing = Listing::new(code);
+    let Some(start) = dispatcher_start(&listing) else {
+	// No selector dispatch, every call goes to the default handlers
+	let (fallback, receive) = if code.is_empty() { (false, false) } else { default_handlers(&listing, listing.skip_prologue()) };
+	return InterfaceSketch { fallback, receive, ..Default::default() };
+    };
+
+    let mut entries = dispatch_entries(&listing, start);
+    entries.sort_unstable();
+    entries.dedup_by_key(|(selector, _)| *selector);
+
+    let (fallback, receive) = short_calldata_branch(&listing)
+	.map(|idx| default_handlers(&listing, idx))
+	.unwrap_or((false, false));
+
+    // A CALLVALUE check ahead of the dispatcher makes every function non payable
+    let guarded = listing.ins[..start].iter().any(|ins| ins.opcode == opcode::CALLVALUE);
+    let payable_hints = if guarded {
+	Vec::new()
+    } else {
+	entries.iter().filter(|(_, entry)| lacks_callvalue_guard(&listing, *entry)).map(|(selector, _)| *selector).collect()
+    };
+
+    InterfaceSketch {
+	selectors: entries.into_iter().map(|(selector, _)| selector).collect(),
+	fallback,
+	receive,
+	payable_hints,
+    }
+}
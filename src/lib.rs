@@ -1,10 +1,74 @@
+mod abi;
 mod consts;
+pub mod compat;
+pub mod data;
 mod read;
 mod detect;
+mod canonical;
 mod types;
 pub mod utils;
 mod proxy_inspector;
+mod rules;
+mod environment;
+mod findings;
+pub mod disasm;
+mod interface;
+mod attribution;
+mod blueprint;
+mod progress;
+mod router;
+mod upgrade;
+mod identity;
+mod selector;
+mod redetect;
+mod classify;
+mod probe;
+mod calldata;
+mod counterfactual;
+mod monitoring;
+mod events;
+mod watch;
+mod reader;
+mod registry;
+mod fork;
+mod loupe;
+mod profile;
+mod trust;
+#[cfg(feature = "binary-format")]
+pub mod compact;
+#[cfg(feature = "rpc")]
+mod inspector;
 
-pub use types::{ProxyType, ProxyDispatch};
-pub use read::get_proxy_implementation;
-pub use detect::get_proxy_type;
+pub use types::{ProxyType, ProxyTypeParseError, ProxyDispatch, DispatchKind, ProxyDetectionResult, ByteProvenance, ProvenanceKind, SlotExtraction, SlotPreimage, BlueprintInfo};
+pub use read::{get_proxy_admin, read_single_storage_implementation, get_proxy_implementation, get_proxy_implementation_at, get_proxy_implementation_with_config, get_implementation_history, get_implementation_history_with_progress, get_code_at, get_proxy_freshness, get_proxy_freshness_with_progress, find_deploy_block, find_deploy_block_with_progress, find_first_block, find_first_block_with_progress, resolve_block_number, ErrorCategory, ProxyFreshness, ProxyImplementation, ProxyReadError, ReadConfig, ResolvedImplementation, RpcError, RpcErrorKind, DEFAULT_SEARCH_BUDGET, MULTICALL3, SelfReport, check_self_report, check_self_report_at, read_facets, verify_implementation};
+pub use loupe::{decode_facets, decode_facets_lenient, LoupeDecodeError, LoupeDecoding, LoupeFacets, MAX_LOUPE_FACETS, MAX_LOUPE_SELECTORS};
+pub use detect::{get_proxy_type, detect_all, detect_proxy, detect_proxy_outcome, detect_proxies, detect_proxies_with_progress, detect_many, detect_many_with_progress, detect_blueprint, detect_creation_code, is_likely_proxy, trace_dispatch, trace_dispatches, DetectError, DetectionStrategy, DetectOutcome, DetectorConfig, NotAProxyReason, ProbeRetry, TraceConfig, TraceError, WidenedAnalysis, LIKELY_PROXY_MAX_SIZE, MAX_FACET_PROBES, MIN_PROXY_CODE_SIZE};
+pub use classify::{classify_upgradeability, probe_upgradeability, refine_proxy_type, refine_proxy_type_with, SlotObservations};
+pub use monitoring::{check_upgrade_events, event_monitoring_advice, find_last_upgrade_block, get_event_monitoring_advice, EventMonitoringAdvice, UpgradeEventHistory, UpgradeSignal};
+pub use consts::{named_slots, slot_name};
+pub use reader::StorageReader;
+pub use fork::{detect_on_fork, ForkRead, ForkState, MAX_FORK_ROUNDS};
+pub use registry::{SelectorRegistry, SlotRegistry};
+pub use watch::{watch_implementation, watch_implementation_logs, ImplementationChange, DEFAULT_WATCH_INTERVAL};
+pub use events::{scan_upgrade_events, scan_upgrade_events_with_progress, FacetCut, FacetCutAction, UpgradeEvent, LOG_CHUNK_BLOCKS};
+pub use counterfactual::{analyze_counterfactual, CounterfactualAccount, CounterfactualError, Create2Params};
+#[cfg(feature = "rpc")]
+pub use inspector::{Inspector, InspectorBuilder, InspectorError, ProxyReport, ReportDetail, ReportSummary, RpcTransport, DEFAULT_MAX_DEPTH, MAX_CONCURRENT_ANALYSES};
+pub use calldata::{CalldataStrategy, DefaultProbes, PushedSelectorProbes};
+pub use probe::{decode_revert_reason, outcome_of_error, probe_call, ProbeOutcome};
+pub use redetect::{find_contradiction, resolve_with_redetection, resolve_with_redetection_at, Contradiction, RedetectConfig, Resolution};
+pub use rules::{RuleId, RulePolicy, RuleState, ruleset_fingerprint, MIN_PINNED_RULESET, RULESET_VERSION};
+pub use profile::{AnalysisProfile, ProfileError, Ruleset, TableVersions};
+pub use selector::{Selector, SelectorParseError};
+pub use identity::{compare_confidence, consensus, merge_detections, DetectionSet};
+pub use compat::FormatVersion;
+pub use environment::TraceEnvironment;
+pub use proxy_inspector::{synthetic_return, CallKind, CallNode, InspectorData, MAX_KECCAK_PREIMAGE};
+pub use findings::{Finding, Severity};
+pub use trust::{trust_set, TrustMechanism, TrustRole, TrustSet, TrustedParty};
+pub use interface::{recover_interface, InterfaceSketch};
+pub use router::{recover_router_table, RouterEntry};
+pub use upgrade::{characterize_upgrade, compare_proxy_code, diff_code, diff_facets, split_metadata, CodeComparison, CodeDiffSummary, FacetDiff, UpgradeCharacterization};
+pub use attribution::{attribute_clone, derive_clone_factory, AttributionHints, CloneAttribution, CloneFactory, CreationCode, FactoryRegistry, MAX_SALT_CANDIDATES};
+pub use blueprint::{parse_blueprint, Blueprint, BLUEPRINT_MAGIC};
+pub use progress::{NoProgress, ProgressEmitter, ProgressReporter, RateTracker, Throttle, DEFAULT_REPORT_EVERY, DEFAULT_REPORT_INTERVAL};
@@ -0,0 +1,141 @@
+//! Decoding of the `facets()` responses of diamond loupes, including the non-canonical ones
+//! some hand-written assembly loupes return.
+
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, U256};
+use ethers_core::abi::AbiDecode;
+use thiserror::Error;
+
+use crate::abi::FacetsReturn;
+use crate::read::ProxyImplementation;
+use crate::utils::h160_to_b160;
+use crate::Selector;
+
+/// Most facets a lenient decoding accepts, more means the data isn't a facet list.
+pub const MAX_LOUPE_FACETS: usize = 1024;
+
+/// Most selectors per facet a lenient decoding accepts.
+pub const MAX_LOUPE_SELECTORS: usize = 4096;
+
+/// How to decode `facets()` responses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoupeDecoding {
+    /// The canonical ABI encoding only.
+    #[default]
+    Strict,
+    /// Fall back to [decode_facets_lenient] when the strict decoding fails.
+    Lenient,
+}
+
+/// The facets a loupe returned, in its order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoupeFacets {
+    pub facets: Vec<(Address, Vec<Selector>)>,
+    /// The response wasn't canonically encoded and was recovered by [decode_facets_lenient].
+    pub leniently_decoded: bool,
+}
+
+impl LoupeFacets {
+    pub fn into_implementation(self) -> ProxyImplementation {
+	ProxyImplementation::Facets(self.facets.into_iter()
+	    .flat_map(|(facet, selectors)| selectors.into_iter().map(move |selector| (selector, facet)))
+	    .collect::<HashMap<Selector, Address>>())
+    }
+}
+
+/// A `facets()` response that couldn't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("facets() response doesn't decode: {strict}")]
+pub struct LoupeDecodeError {
+    /// Why the canonical decoding failed.
+    pub strict: String,
+    /// Why the lenient decoding failed too, `None` if it wasn't tried.
+    pub lenient: Option<String>,
+}
+
+/// Decodes the `facets()` response `data`, strictly unless `decoding` allows recovering
+/// non-canonical encodings.
+pub fn decode_facets(data: &[u8], decoding: LoupeDecoding) -> Result<LoupeFacets, LoupeDecodeError> {
+    let strict = match FacetsReturn::decode(data) {
+	Ok(FacetsReturn(facets)) => {
+	    let facets = facets.into_iter()
+		.map(|facet| (h160_to_b160(&facet.0), facet.1.into_iter().map(Selector::new).collect()))
+		.collect();
+	    return Ok(LoupeFacets { facets, leniently_decoded: false });
+	},
+	Err(e) => e.to_string(),
+    };
+    if decoding == LoupeDecoding::Strict {
+	return Err(LoupeDecodeError { strict, lenient: None });
+    }
+    decode_facets_lenient(data)
+	.map(|facets| LoupeFacets { facets, leniently_decoded: true })
+	.map_err(|lenient| LoupeDecodeError { strict, lenient: Some(lenient) })
+}
+
+fn word(data: &[u8], at: usize) -> Result<U256, String> {
+    data.get(at..at.checked_add(32).ok_or("offset overflows")?)
+	.map(U256::from_be_slice)
+	.ok_or_else(|| format!("word at {:#x} past the end", at))
+}
+
+/// The offset or length in the word at `at`, which has to be below `limit`.
+fn small(data: &[u8], at: usize, limit: usize) -> Result<usize, String> {
+    let value = word(data, at)?;
+    usize::try_from(value).ok().filter(|value| *value <= limit)
+	.ok_or_else(|| format!("{:#x} at {:#x} is out of range", value, at))
+}
+
+/// Recovers `(address, bytes4[])[]` from `data` encoded with non-minimal offsets, offsets
+/// relative to the wrong base, junk between the values or after them, or junk padding the
+/// selectors. The recovered facets have to be plausible: addresses with zero high bytes and
+/// not zero, at most [MAX_LOUPE_FACETS] facets of at most [MAX_LOUPE_SELECTORS] selectors.
+pub fn decode_facets_lenient(data: &[u8]) -> Result<Vec<(Address, Vec<Selector>)>, String> {
+    let array = small(data, 0, data.len())?;
+    let count = small(data, array, MAX_LOUPE_FACETS)?;
+    let heads = array + 32;
+    // Canonically from the first head, hand-written loupes also count from the length or the start
+    let mut errors = Vec::new();
+    for base in [heads, array, 0] {
+	match (0..count).map(|i| facet_at(data, base, heads + 32 * i)).collect() {
+	    Ok(facets) => return Ok(facets),
+	    Err(e) => errors.push(format!("offsets from {:#x}: {}", base, e)),
+	}
+    }
+    Err(errors.join("; "))
+}
+
+fn facet_at(data: &[u8], base: usize, head: usize) -> Result<(Address, Vec<Selector>), String> {
+    let start = base + small(data, head, data.len())?;
+    let address = word(data, start)?;
+    if address >> 160 != U256::ZERO || address.is_zero() {
+	return Err(format!("implausible facet address {:#x} at {:#x}", address, start));
+    }
+    let offset = small(data, start + 32, data.len())?;
+    let selectors = selectors_at(data, start + offset).or_else(|_| selectors_at(data, offset))?;
+    Ok((Address::from_word(address.to_be_bytes::<32>().into()), selectors))
+}
+
+fn selectors_at(data: &[u8], at: usize) -> Result<Vec<Selector>, String> {
+    let len = small(data, at, MAX_LOUPE_SELECTORS)?;
+    // The selector is the high 4 bytes, whatever pads it
+    (0..len).map(|i| word(data, at + 32 * (i + 1)).map(|word| Selector::from_slice(&word.to_be_bytes::<32>()[..4]).expect("4 bytes"))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caps() {
+	// A huge facet count is junk, not a list to allocate
+	let mut data = [0u8; 64];
+	data[31] = 0x20;
+	data[32..].copy_from_slice(&U256::from(MAX_LOUPE_FACETS + 1).to_be_bytes::<32>());
+	assert!(decode_facets_lenient(&data).unwrap_err().contains("out of range"));
+	// No facets is a valid list
+	data[32..].fill(0);
+	assert_eq!(decode_facets_lenient(&data), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,220 @@
+//! Where a monitor has to look to see a given proxy upgraded. Watching `Upgraded` at the proxy
+//! misses beacon upgrades, which are logged by the beacon, and proxies whose upgrades log
+//! nothing standard or log it from another address.
+
+use std::sync::Arc;
+
+use alloy_primitives::{Address, B256, U256};
+use ethers_contract::EthEvent;
+use ethers_core::types::{BlockId, Filter, Log, H256};
+use ethers_providers::Middleware;
+use once_cell::sync::Lazy;
+
+use crate::abi::NewImplementationFilter;
+use crate::consts::{ADMIN_CHANGED_TOPIC, BEACON_UPGRADED_TOPIC, DIAMOND_CUT_TOPIC, UPGRADED_TOPIC};
+use crate::read::{find_first_block, get_proxy_implementation_at, read_single_storage_implementation, ProxyReadError};
+use crate::utils::{h160_to_b160, h256_to_b256};
+use crate::{ProxyDispatch, ProxyType};
+
+static NEW_IMPLEMENTATION: Lazy<B256> = Lazy::new(|| h256_to_b256(NewImplementationFilter::signature()));
+
+/// Something that changes when a proxy is upgraded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpgradeSignal {
+    /// A log emitted by `address` with one of `topics` as its first topic.
+    Events { address: Address, topics: Vec<B256> },
+    /// Nothing standard is logged: `slot` of `address` has to be polled.
+    StorageSlot { address: Address, slot: U256 },
+}
+
+/// What to watch to see one proxy upgraded, from [event_monitoring_advice].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventMonitoringAdvice {
+    pub proxy: Address,
+    pub proxy_type: ProxyType,
+    /// All of them are needed, e.g. a beacon proxy is upgraded by replacing its beacon and by
+    /// upgrading the beacon.
+    pub signals: Vec<UpgradeSignal>,
+}
+
+impl EventMonitoringAdvice {
+    /// Whether every upgrade is logged by the proxy itself, the only place most monitors look.
+    pub fn events_at_proxy(&self) -> bool {
+	self.signals.iter().all(|signal| matches!(signal, UpgradeSignal::Events { address, .. } if *address == self.proxy))
+    }
+
+    fn watches(&self, log: &Log) -> bool {
+	let emitter = h160_to_b160(&log.address);
+	let Some(topic0) = log.topics.first().map(|topic| h256_to_b256(*topic)) else { return false };
+	self.signals.iter().any(|signal| matches!(signal, UpgradeSignal::Events { address, topics } if *address == emitter && topics.contains(&topic0)))
+    }
+}
+
+/// The events a family logs at the proxy when upgraded, `None` if it logs no standard ones.
+fn proxy_events(proxy_type: ProxyType) -> Option<Vec<B256>> {
+    match proxy_type {
+	// The upgrade functions are the proxy's own
+	ProxyType::EIP_1967 | ProxyType::EIP_1967_TRANSPARENT | ProxyType::EIP_1967_ZOS => Some(vec![UPGRADED_TOPIC, ADMIN_CHANGED_TOPIC]),
+	// The implementation's `upgradeTo` runs in the proxy's context, so the log is the proxy's
+	ProxyType::UUPS => Some(vec![UPGRADED_TOPIC]),
+	// Only the beacon being replaced, upgrades of the beacon itself are logged by the beacon
+	ProxyType::EIP_1967_BEACON => Some(vec![BEACON_UPGRADED_TOPIC]),
+	ProxyType::CompoundDelegator => Some(vec![*NEW_IMPLEMENTATION]),
+	ProxyType::EIP_2535 => Some(vec![DIAMOND_CUT_TOPIC]),
+	_ => None,
+    }
+}
+
+/// What to watch to see `proxy`, detected as `proxy_type` dispatching through `dispatch`,
+/// upgraded. `beacon` is the beacon of beacon proxies, without it the advice misses upgrades of
+/// the beacon. `None` for proxies that can't be upgraded and families nothing is known about.
+pub fn event_monitoring_advice(proxy: &Address, proxy_type: ProxyType, dispatch: &ProxyDispatch, beacon: Option<&Address>) -> Option<EventMonitoringAdvice> {
+    let events = proxy_events(proxy_type);
+    let mut signals = Vec::new();
+    match dispatch {
+	ProxyDispatch::Beacon(_) => if let Some(beacon) = beacon {
+	    // OpenZeppelin's UpgradeableBeacon
+	    signals.push(UpgradeSignal::Events { address: *beacon, topics: vec![UPGRADED_TOPIC] });
+	},
+	// EIP-897, Safe, custom and immutable slots, EIP-1822's reference proxy: nothing standard
+	ProxyDispatch::Storage(slot, _) if events.is_none() => signals.push(UpgradeSignal::StorageSlot { address: *proxy, slot: *slot }),
+	ProxyDispatch::MultipleStorage(slots) if events.is_none() => {
+	    signals.extend(slots.iter().map(|slot| UpgradeSignal::StorageSlot { address: *proxy, slot: *slot }));
+	},
+	_ => {},
+    }
+    if let Some(topics) = events {
+	signals.insert(0, UpgradeSignal::Events { address: *proxy, topics });
+    }
+    (!signals.is_empty()).then_some(EventMonitoringAdvice { proxy: *proxy, proxy_type, signals })
+}
+
+/// [event_monitoring_advice] reading the beacon of beacon proxies at `block`.
+pub async fn get_event_monitoring_advice<M>(rpc: &M, proxy: &Address, proxy_type: ProxyType, dispatch: &ProxyDispatch, block: Option<BlockId>) -> Result<Option<EventMonitoringAdvice>, ProxyReadError>
+    where M: Middleware
+{
+    let beacon = match dispatch {
+	ProxyDispatch::Beacon(slot) => Some(read_single_storage_implementation(rpc, proxy, slot, None, block).await?),
+	_ => None,
+    };
+    Ok(event_monitoring_advice(proxy, proxy_type, dispatch, beacon.as_ref()))
+}
+
+/// Where the last upgrade of a proxy was logged, from [check_upgrade_events].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpgradeEventHistory {
+    /// Where the advice says to watch.
+    AtAdvisedAddress,
+    /// A standard upgrade event naming the implementation was logged, but by this address: a
+    /// monitor following the advice missed it.
+    ElsewhereAt(Address),
+    /// No standard upgrade event names the implementation.
+    Missing,
+}
+
+/// Whether a word of `log` is `address`, as an indexed argument or in its data.
+fn names_address(log: &Log, address: &Address) -> bool {
+    let word = B256::left_padding_from(address.as_slice());
+    log.topics.iter().skip(1).any(|topic| h256_to_b256(*topic) == word)
+	|| log.data.chunks(32).any(|chunk| chunk == word.as_slice())
+}
+
+/// Checks the logs of `block`, where `implementation` was installed (see
+/// [find_last_upgrade_block]), against `advice`.
+pub async fn check_upgrade_events<M>(rpc: &M, advice: &EventMonitoringAdvice, implementation: &Address, block: u64) -> Result<UpgradeEventHistory, ProxyReadError>
+    where M: Middleware
+{
+    let mut topics: Vec<H256> = [UPGRADED_TOPIC, BEACON_UPGRADED_TOPIC, DIAMOND_CUT_TOPIC, *NEW_IMPLEMENTATION].iter().map(|topic| H256(topic.0)).collect();
+    for signal in &advice.signals {
+	if let UpgradeSignal::Events { topics: advised, .. } = signal {
+	    topics.extend(advised.iter().map(|topic| H256(topic.0)).filter(|topic| !topics.contains(topic)).collect::<Vec<_>>());
+	}
+    }
+    let filter = Filter::new().from_block(block).to_block(block).topic0(topics);
+    let logs = rpc.get_logs(&filter).await.map_err(ProxyReadError::from_middleware)?;
+    if logs.iter().any(|log| advice.watches(log)) {
+	return Ok(UpgradeEventHistory::AtAdvisedAddress);
+    }
+    Ok(match logs.iter().find(|log| names_address(log, implementation)) {
+	Some(log) => UpgradeEventHistory::ElsewhereAt(h160_to_b160(&log.address)),
+	None => UpgradeEventHistory::Missing,
+    })
+}
+
+/// The block the current implementation of `proxy` was installed in, searching up to `head`
+/// with at most `budget` reads. Assumes the proxy never went back to an implementation it had.
+pub async fn find_last_upgrade_block<M>(rpc: Arc<M>, proxy: &Address, dispatch: &ProxyDispatch, head: u64, budget: usize) -> Result<Option<u64>, ProxyReadError>
+    where M: Middleware + 'static
+{
+    let current = get_proxy_implementation_at(rpc.clone(), proxy, dispatch, Some(head.into())).await?;
+    find_first_block(0, head, budget, |block| {
+	let (rpc, current) = (rpc.clone(), &current);
+	async move {
+	    match get_proxy_implementation_at(rpc, proxy, dispatch, Some(block.into())).await {
+		Ok(implementation) => Ok(implementation == *current),
+		// Before the proxy or its beacon existed
+		Err(ProxyReadError::NoCode(_) | ProxyReadError::BeaconNotAddress(_) | ProxyReadError::StorageNotAddress) => Ok(false),
+		Err(e) if e.is_missing_state() => Err(ProxyReadError::HistoricalStateUnavailable(block)),
+		Err(e) => Err(e),
+	    }
+	}
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::EIP_1967_IMPLEMENTATION_SLOT;
+
+    const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+    const BEACON: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+    const BEACON_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+
+    fn advice(proxy_type: ProxyType, dispatch: ProxyDispatch) -> Option<EventMonitoringAdvice> {
+	event_monitoring_advice(&PROXY, proxy_type, &dispatch, Some(&BEACON))
+    }
+
+    #[test]
+    fn test_advice_per_family() {
+	let logic = ProxyDispatch::Storage(EIP_1967_IMPLEMENTATION_SLOT, None);
+	for proxy_type in [ProxyType::EIP_1967, ProxyType::EIP_1967_TRANSPARENT, ProxyType::EIP_1967_ZOS] {
+	    let advice = advice(proxy_type, logic.clone()).unwrap();
+	    assert_eq!(advice.signals, vec![UpgradeSignal::Events { address: PROXY, topics: vec![UPGRADED_TOPIC, ADMIN_CHANGED_TOPIC] }]);
+	    assert!(advice.events_at_proxy());
+	}
+	let uups = advice(ProxyType::UUPS, logic.clone()).unwrap();
+	assert_eq!(uups.signals, vec![UpgradeSignal::Events { address: PROXY, topics: vec![UPGRADED_TOPIC] }]);
+
+	// The implementation is upgraded at the beacon
+	let beacon = advice(ProxyType::EIP_1967_BEACON, ProxyDispatch::Beacon(BEACON_SLOT)).unwrap();
+	assert_eq!(beacon.signals, vec![
+	    UpgradeSignal::Events { address: PROXY, topics: vec![BEACON_UPGRADED_TOPIC] },
+	    UpgradeSignal::Events { address: BEACON, topics: vec![UPGRADED_TOPIC] },
+	]);
+	assert!(!beacon.events_at_proxy());
+
+	let diamond = advice(ProxyType::EIP_2535, ProxyDispatch::Facet_EIP_2535).unwrap();
+	assert_eq!(diamond.signals, vec![UpgradeSignal::Events { address: PROXY, topics: vec![DIAMOND_CUT_TOPIC] }]);
+
+	let compound = advice(ProxyType::CompoundDelegator, ProxyDispatch::Storage(U256::from(2), None)).unwrap();
+	assert_eq!(compound.signals, vec![UpgradeSignal::Events { address: PROXY, topics: vec![*NEW_IMPLEMENTATION] }]);
+    }
+
+    #[test]
+    fn test_advice_without_standard_events() {
+	// Slot 0 of a Safe and an EIP-1822 proxy log nothing standard
+	let safe = advice(ProxyType::GnosisSafe, ProxyDispatch::Storage(U256::ZERO, None)).unwrap();
+	assert_eq!(safe.signals, vec![UpgradeSignal::StorageSlot { address: PROXY, slot: U256::ZERO }]);
+	assert!(!safe.events_at_proxy());
+	let slots = vec![U256::from(1), U256::from(2)];
+	let multiple = advice(ProxyType::EIP_1967_CUSTOM, ProxyDispatch::MultipleStorage(slots)).unwrap();
+	assert_eq!(multiple.signals.len(), 2);
+
+	// Nothing to watch on clones, nor on families nothing is known about
+	assert_eq!(advice(ProxyType::EIP_1167, ProxyDispatch::Static(BEACON)), None);
+	assert_eq!(advice(ProxyType::DiamondOther, ProxyDispatch::Unknown), None);
+	// A beacon proxy without its beacon still has its own events
+	let unread = event_monitoring_advice(&PROXY, ProxyType::EIP_1967_BEACON, &ProxyDispatch::Beacon(BEACON_SLOT), None).unwrap();
+	assert!(unread.events_at_proxy());
+    }
+}
@@ -0,0 +1,153 @@
+use alloy_primitives::{Address, Bytes, U256};
+use ethers_core::types::{BlockId, TransactionRequest};
+use ethers_providers::{JsonRpcError, Middleware, MiddlewareError};
+use serde_json::Value;
+
+use crate::read::ProxyReadError;
+use crate::utils::raddress_to_h160;
+
+/// Selector of `Error(string)`, the payload of `require` and `revert` with a message.
+const ERROR_STRING_SELECTOR: [u8; 4] = hex_literal::hex!("08c379a0");
+/// Selector of `Panic(uint256)`, the payload of failed asserts and checked arithmetic.
+const PANIC_SELECTOR: [u8; 4] = hex_literal::hex!("4e487b71");
+
+/// How a probe call through a node ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// The call returned this data.
+    Success(Bytes),
+    /// The call reverted with a decodable reason, see [decode_revert_reason].
+    RevertWithReason(String),
+    /// The call reverted with data that isn't a reason, e.g. a custom error or nothing.
+    RevertRaw(Bytes),
+    /// The call ran out of gas, or needed more than the node allows for calls.
+    OutOfGas,
+    /// The call halted otherwise: invalid opcode, bad jump, stack errors...
+    ExecutionError(String),
+}
+
+impl ProbeOutcome {
+    pub fn is_success(&self) -> bool {
+	matches!(self, ProbeOutcome::Success(_))
+    }
+}
+
+/// The reason in `data`, returned by a reverted call: the message of `Error(string)`, or the
+/// code of `Panic(uint256)` as `panic 0x..`.
+pub fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    let (selector, args) = data.split_at_checked(4)?;
+    if selector == ERROR_STRING_SELECTOR {
+	let offset = usize::try_from(U256::from_be_slice(args.get(..32)?)).ok()?;
+	let len_word = args.get(offset..offset.checked_add(32)?)?;
+	let len = usize::try_from(U256::from_be_slice(len_word)).ok()?;
+	let message = args.get(offset + 32..(offset + 32).checked_add(len)?)?;
+	String::from_utf8(message.to_vec()).ok()
+    } else if selector == PANIC_SELECTOR {
+	Some(format!("panic {:#x}", U256::from_be_slice(args.get(..32)?)))
+    } else {
+	None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Halt {
+    Revert,
+    OutOfGas,
+    Error,
+}
+
+/// How providers word failed calls, matched in order against the lowercased error message.
+/// Errors matching none are taken as the node failing to answer, not as the call failing.
+const HALT_MESSAGES: &[(&str, Halt)] = &[
+    // Geth, Erigon, Reth, Alchemy, Infura and Anvil, optionally followed by `: <reason>`
+    ("execution reverted", Halt::Revert),
+    // Nethermind puts `Reverted 0x..` in the data of a generic message, Ganache says `revert`
+    ("revert", Halt::Revert),
+    // Geth and Erigon
+    ("out of gas", Halt::OutOfGas),
+    // Geth when the call needs more than its RPC gas cap
+    ("gas required exceeds allowance", Halt::OutOfGas),
+    // Geth (`invalid opcode: INVALID`), Erigon (`invalid opcode: opcode 0xfe not defined`)
+    ("invalid opcode", Halt::Error),
+    ("invalid jump destination", Halt::Error),
+    ("bad jump destination", Halt::Error),
+    ("stack underflow", Halt::Error),
+    ("stack limit reached", Halt::Error),
+    ("write protection", Halt::Error),
+    // Nethermind's generic message, its data says why
+    ("vm execution error", Halt::Error),
+];
+
+/// Revert data carried by an error response: a hex string, Nethermind's `Reverted 0x..`, or
+/// nested in an object as some gateways do.
+fn revert_data(data: &Value) -> Option<Bytes> {
+    match data {
+	Value::String(s) => s.trim_start_matches("Reverted ").parse().ok(),
+	Value::Object(map) => ["data", "result", "return"].iter().find_map(|key| map.get(*key).and_then(revert_data)),
+	_ => None,
+    }
+}
+
+/// What a failed call reported as `error` says about how it ended, `None` if it isn't about
+/// the call but the node.
+pub fn outcome_of_error(error: &JsonRpcError) -> Option<ProbeOutcome> {
+    let message = error.message.to_lowercase();
+    // Nethermind only says what happened in the data
+    let detail = error.data.as_ref().and_then(Value::as_str).map(str::to_lowercase).unwrap_or_default();
+    // Geth and its forks answer reverts with code 3
+    let halt = if error.code == 3 {
+	Halt::Revert
+    } else {
+	HALT_MESSAGES.iter().find(|(needle, _)| message.contains(needle) || detail.contains(needle)).map(|(_, halt)| *halt)?
+    };
+    Some(match halt {
+	Halt::Revert => {
+	    let data = error.data.as_ref().and_then(revert_data);
+	    match data.as_ref().and_then(|data| decode_revert_reason(data)) {
+		Some(reason) => ProbeOutcome::RevertWithReason(reason),
+		None => match (data, error.message.split_once(": ")) {
+		    (Some(data), _) if !data.is_empty() => ProbeOutcome::RevertRaw(data),
+		    // Some only give the reason in the message
+		    (_, Some((_, reason))) if message.starts_with("execution reverted") => ProbeOutcome::RevertWithReason(reason.to_string()),
+		    (data, _) => ProbeOutcome::RevertRaw(data.unwrap_or_default()),
+		},
+	    }
+	},
+	Halt::OutOfGas => ProbeOutcome::OutOfGas,
+	Halt::Error if detail.is_empty() => ProbeOutcome::ExecutionError(error.message.clone()),
+	Halt::Error => ProbeOutcome::ExecutionError(format!("{}: {}", error.message, detail)),
+    })
+}
+
+/// Calls `to` with `calldata` at `block` through `rpc`, telling how the call ended apart from
+/// failures to get an answer, which are errors.
+pub async fn probe_call<M>(rpc: &M, to: &Address, calldata: Bytes, block: Option<BlockId>) -> Result<ProbeOutcome, ProxyReadError>
+    where M: Middleware
+{
+    let tx = TransactionRequest::new().to(raddress_to_h160(to)).data(calldata.to_vec());
+    match rpc.call(&tx.into(), block).await {
+	Ok(output) => Ok(ProbeOutcome::Success(Bytes::from(output.to_vec()))),
+	Err(e) => e.as_error_response()
+	    .and_then(outcome_of_error)
+	    .ok_or_else(|| ProxyReadError::from_middleware(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_revert_reason() {
+	// require(false, "Ownable: caller is not the owner")
+	let data = hex_literal::hex!("08c379a0" "0000000000000000000000000000000000000000000000000000000000000020" "0000000000000000000000000000000000000000000000000000000000000020" "4f776e61626c653a2063616c6c6572206973206e6f7420746865206f776e6572");
+	assert_eq!(decode_revert_reason(&data).as_deref(), Some("Ownable: caller is not the owner"));
+	// Arithmetic overflow
+	let data = hex_literal::hex!("4e487b71" "0000000000000000000000000000000000000000000000000000000000000011");
+	assert_eq!(decode_revert_reason(&data).as_deref(), Some("panic 0x11"));
+	// A custom error, and a message running past the data
+	assert_eq!(decode_revert_reason(&hex_literal::hex!("82b42900")), None);
+	let data = hex_literal::hex!("08c379a0" "0000000000000000000000000000000000000000000000000000000000000020" "0000000000000000000000000000000000000000000000000000000000000040" "4f776e61626c65");
+	assert_eq!(decode_revert_reason(&data), None);
+    }
+}
@@ -0,0 +1,147 @@
+use alloy_primitives::B256;
+use thiserror::Error;
+
+use crate::detect::DetectorConfig;
+use crate::identity::detection_id_under;
+use crate::rules::{fingerprint_of, RuleId, MIN_PINNED_RULESET, RULESET_VERSION};
+use crate::ProxyDetectionResult;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ProfileError {
+    #[error("unknown analysis profile `{0}`")]
+    UnknownProfile(String),
+    #[error("ruleset {0} can't be pinned, only {MIN_PINNED_RULESET} to {RULESET_VERSION}")]
+    UnsupportedRuleset(u32),
+}
+
+/// Versions of the built-in data tables, as the ruleset of that version shipped them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TableVersions {
+    pub storage_slots: u32,
+    pub resolver_selectors: u32,
+}
+
+impl TableVersions {
+    /// The tables ruleset `version` shipped with.
+    pub const fn of(version: u32) -> Self {
+	Self { storage_slots: version, resolver_selectors: version }
+    }
+}
+
+/// The classification rules of a ruleset version and the data tables they consult.
+///
+/// Rules introduced after the version are skipped, so results match what that version
+/// produced as long as the detector's probes don't change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ruleset {
+    version: u32,
+    tables: TableVersions,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+	Self::LATEST
+    }
+}
+
+fn check_version(version: u32) -> Result<u32, ProfileError> {
+    (MIN_PINNED_RULESET..=RULESET_VERSION).contains(&version).then_some(version).ok_or(ProfileError::UnsupportedRuleset(version))
+}
+
+impl Ruleset {
+    pub const LATEST: Ruleset = Ruleset { version: RULESET_VERSION, tables: TableVersions::of(RULESET_VERSION) };
+
+    /// Ruleset `version` with its own tables.
+    pub fn pinned(version: u32) -> Result<Self, ProfileError> {
+	Ok(Self { version: check_version(version)?, tables: TableVersions::of(version) })
+    }
+
+    /// The same rules consulting other tables.
+    pub fn with_tables(self, tables: TableVersions) -> Result<Self, ProfileError> {
+	check_version(tables.storage_slots)?;
+	check_version(tables.resolver_selectors)?;
+	Ok(Self { tables, ..self })
+    }
+
+    pub fn version(&self) -> u32 {
+	self.version
+    }
+
+    pub fn tables(&self) -> TableVersions {
+	self.tables
+    }
+
+    /// Whether `rule` existed in this ruleset.
+    pub fn includes(&self, rule: RuleId) -> bool {
+	rule.since() <= self.version
+    }
+
+    /// [ruleset_fingerprint](crate::ruleset_fingerprint) of this ruleset.
+    pub fn fingerprint(&self) -> B256 {
+	fingerprint_of(self)
+    }
+}
+
+/// A named bundle of everything classification depends on: the detector configuration, its
+/// rule policy and the [Ruleset] with its tables.
+///
+/// Frozen profiles pin all of it, seed included, and keep classifying as the release that
+/// introduced them; `tests/profiles.rs` holds their conformance vectors. New capabilities
+/// only reach `latest`.
+#[derive(Clone, Debug)]
+pub struct AnalysisProfile {
+    pub name: String,
+    pub frozen: bool,
+    pub config: DetectorConfig,
+}
+
+impl AnalysisProfile {
+    /// Names of the built-in profiles.
+    pub const BUILTIN: &'static [&'static str] = &["latest", "v1-frozen"];
+
+    /// Everything the crate can do, changing as it improves.
+    pub fn latest() -> Self {
+	Self { name: "latest".to_string(), frozen: false, config: DetectorConfig::default() }
+    }
+
+    /// A built-in profile by name:
+    ///
+    /// - `latest`, see [AnalysisProfile::latest].
+    /// - `v1-frozen`: ruleset 3, before Compound delegators were told apart from EIP-897.
+    pub fn builtin(name: &str) -> Result<Self, ProfileError> {
+	match name {
+	    "latest" => Ok(Self::latest()),
+	    "v1-frozen" => Ok(Self::frozen(name, Ruleset::pinned(3)?)),
+	    _ => Err(ProfileError::UnknownProfile(name.to_string())),
+	}
+    }
+
+    fn frozen(name: &str, ruleset: Ruleset) -> Self {
+	let config = DetectorConfig { seed: Some(0), ruleset, ..Default::default() };
+	Self { name: name.to_string(), frozen: true, config }
+    }
+
+    pub fn ruleset(&self) -> Ruleset {
+	self.config.ruleset
+    }
+
+    /// Id of `result`, detected in `code` with this profile. As
+    /// [detection_id](ProxyDetectionResult::detection_id) but for the profile's ruleset.
+    pub fn detection_id(&self, result: &ProxyDetectionResult, code: &[u8]) -> B256 {
+	detection_id_under(result, code, self.config.ruleset.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_fingerprints() {
+	// As pinned by test_ruleset_version when ruleset 3 was the latest
+	assert_eq!(Ruleset::pinned(3).unwrap().fingerprint(), alloy_primitives::b256!("decf52a57cf0d3ee9aee80ff4a482338a5ea976e9dfe02729bced848c47bbc86"));
+	assert_eq!(Ruleset::pinned(RULESET_VERSION).unwrap(), Ruleset::LATEST);
+	assert_eq!(Ruleset::pinned(2), Err(ProfileError::UnsupportedRuleset(2)));
+	assert_eq!(Ruleset::LATEST.with_tables(TableVersions::of(RULESET_VERSION + 1)), Err(ProfileError::UnsupportedRuleset(RULESET_VERSION + 1)));
+    }
+}
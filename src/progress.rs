@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Items between two reports of a [ProgressEmitter] at most, unless time forces one earlier.
+pub const DEFAULT_REPORT_EVERY: u64 = 64;
+
+/// Time between two reports of a [ProgressEmitter] at most, unless items force one earlier.
+pub const DEFAULT_REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receives the progress of long operations.
+///
+/// Operations report through a [ProgressEmitter], which throttles the calls, so implementations
+/// can afford to lock or print.
+pub trait ProgressReporter: Send + Sync {
+    /// `completed` units of work of `stage` are done, out of `total_hint` if it is known.
+    /// `completed` never decreases within a stage.
+    fn on_progress(&self, _completed: u64, _total_hint: Option<u64>, _stage: &str) {}
+}
+
+/// Ignores the progress.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
+
+impl<F> ProgressReporter for F
+    where F: Fn(u64, Option<u64>, &str) + Send + Sync
+{
+    fn on_progress(&self, completed: u64, total_hint: Option<u64>, stage: &str) {
+	self(completed, total_hint, stage)
+    }
+}
+
+/// Decides when progress is worth reporting: after `every` items or `interval` since the last
+/// report, whichever comes first.
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    every: u64,
+    interval: Duration,
+    last: Option<(Instant, u64)>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+	Self::new(DEFAULT_REPORT_EVERY, DEFAULT_REPORT_INTERVAL)
+    }
+}
+
+impl Throttle {
+    pub fn new(every: u64, interval: Duration) -> Self {
+	Self { every: every.max(1), interval, last: None }
+    }
+
+    /// Lets every item through, for operations where each item is an RPC round trip anyway.
+    pub fn every_item() -> Self {
+	Self::new(1, Duration::ZERO)
+    }
+
+    /// Whether `completed` should be reported at `now`, the first call always is.
+    pub fn should_report_at(&mut self, now: Instant, completed: u64) -> bool {
+	let due = match self.last {
+	    None => true,
+	    Some((time, items)) => completed >= items.saturating_add(self.every) || now.duration_since(time) >= self.interval,
+	};
+	if due {
+	    self.last = Some((now, completed));
+	}
+	due
+    }
+
+    /// The last reported count.
+    pub fn last_reported(&self) -> Option<u64> {
+	self.last.map(|(_, items)| items)
+    }
+}
+
+/// Throughput and ETA over a sliding time window.
+#[derive(Clone, Debug)]
+pub struct RateTracker {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RateTracker {
+    pub fn new(window: Duration) -> Self {
+	Self { window, samples: VecDeque::new() }
+    }
+
+    /// Records that `completed` items were done at `now`, dropping the samples that left the
+    /// window. The newest sample before the window is kept as its start.
+    pub fn record_at(&mut self, now: Instant, completed: u64) {
+	self.samples.push_back((now, completed));
+	while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= self.window {
+	    self.samples.pop_front();
+	}
+    }
+
+    pub fn record(&mut self, completed: u64) {
+	self.record_at(Instant::now(), completed)
+    }
+
+    /// Items per second over the window, `None` until two samples some time apart exist.
+    pub fn throughput(&self) -> Option<f64> {
+	let (first, last) = (self.samples.front()?, self.samples.back()?);
+	let elapsed = last.0.duration_since(first.0).as_secs_f64();
+	(elapsed > 0.0).then(|| last.1.saturating_sub(first.1) as f64 / elapsed)
+    }
+
+    /// Time left to reach `total` at the current throughput.
+    pub fn eta(&self, total: u64) -> Option<Duration> {
+	let remaining = total.saturating_sub(self.samples.back()?.1);
+	if remaining == 0 {
+	    return Some(Duration::ZERO);
+	}
+	let throughput = self.throughput().filter(|t| *t > 0.0)?;
+	Some(Duration::from_secs_f64(remaining as f64 / throughput))
+    }
+}
+
+/// Reports the progress of one stage of an operation through a [Throttle].
+pub struct ProgressEmitter<'a> {
+    reporter: &'a dyn ProgressReporter,
+    stage: &'a str,
+    total_hint: Option<u64>,
+    throttle: Throttle,
+    completed: u64,
+}
+
+impl<'a> ProgressEmitter<'a> {
+    pub fn new(reporter: &'a dyn ProgressReporter, stage: &'a str, total_hint: Option<u64>) -> Self {
+	Self::with_throttle(reporter, stage, total_hint, Throttle::default())
+    }
+
+    pub fn with_throttle(reporter: &'a dyn ProgressReporter, stage: &'a str, total_hint: Option<u64>, throttle: Throttle) -> Self {
+	Self { reporter, stage, total_hint, throttle, completed: 0 }
+    }
+
+    /// One more item done.
+    pub fn advance(&mut self) {
+	self.completed += 1;
+	if self.throttle.should_report_at(Instant::now(), self.completed) {
+	    self.reporter.on_progress(self.completed, self.total_hint, self.stage);
+	}
+    }
+
+    /// Reports the final count if the throttle held it back.
+    pub fn finish(&mut self) {
+	if self.throttle.last_reported() != Some(self.completed) {
+	    self.throttle.should_report_at(Instant::now(), self.completed);
+	    self.reporter.on_progress(self.completed, self.total_hint, self.stage);
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_math() {
+	let start = Instant::now();
+	let mut rate = RateTracker::new(Duration::from_secs(10));
+	rate.record_at(start, 0);
+	assert_eq!(rate.throughput(), None);
+	rate.record_at(start + Duration::from_secs(2), 100);
+	assert_eq!(rate.throughput(), Some(50.0));
+	assert_eq!(rate.eta(300), Some(Duration::from_secs(4)));
+	assert_eq!(rate.eta(50), Some(Duration::ZERO));
+
+	// The early fast samples leave the window, only the slow pace is left
+	rate.record_at(start + Duration::from_secs(20), 110);
+	rate.record_at(start + Duration::from_secs(30), 120);
+	assert_eq!(rate.throughput(), Some(1.0));
+	assert_eq!(rate.eta(130), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_throttle() {
+	let start = Instant::now();
+	let mut throttle = Throttle::new(10, Duration::from_millis(100));
+	let reported: Vec<u64> = (1..=35).filter(|i| throttle.should_report_at(start, *i)).collect();
+	assert_eq!(reported, vec![1, 11, 21, 31]);
+	// Time alone triggers a report too
+	assert!(!throttle.should_report_at(start + Duration::from_millis(99), 32));
+	assert!(throttle.should_report_at(start + Duration::from_millis(100), 33));
+	assert_eq!(throttle.last_reported(), Some(33));
+    }
+}
@@ -1,11 +1,14 @@
-use std::{collections::HashMap, ops::{BitAnd, BitXor}};
+use std::collections::HashMap;
 
 use once_cell::sync::Lazy;
 use revm::{
-    interpreter::{opcode, CallInputs, CallScheme, Gas, InstructionResult, Interpreter}, primitives::{AccountInfo, Bytecode}, Database, EvmContext, Inspector
+    interpreter::{opcode, CallInputs, CallScheme, Gas, InstructionResult, Interpreter}, primitives::{bitvec::{bitvec, order::Lsb0}, AccountInfo, Bytecode, JumpTable, LegacyAnalyzedBytecode}, Database, EvmContext, Inspector
 };
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use alloy_primitives::{
+    keccak256,
     Bytes,
     Address, U256, B256, FixedBytes,
 };
@@ -14,15 +17,138 @@ use revm_interpreter::{CallOutcome, InterpreterResult, OpCode};
 use thiserror::Error;
 use tracing::debug;
 
-use crate::utils::slice_as_u32_be;
+use crate::disasm::disassemble;
+use crate::environment::TraceEnvironment;
+use crate::fork::ForkState;
+use crate::types::SlotExtraction;
+use crate::selector::Selector;
 
-/// The collected results of [`InspectorStack`].
-#[derive(Clone, Debug, PartialEq)]
+/// What a traced call did, as observed by the detector. Addresses the contract loads from
+/// storage are synthetic unless the slot's real value was given, see
+/// [WidenedAnalysis::storage](crate::WidenedAnalysis::storage).
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct InspectorData {
+    /// Slots read with SLOAD, in order and with repetitions.
     pub storage_access: Vec<U256>,
+    /// Slots the targets of delegatecalls were loaded from.
     pub delegatecall_storage: Vec<U256>,
+    /// Targets of delegatecalls taken from the calldata, which any caller can point anywhere.
+    pub delegatecall_from_calldata: Vec<Address>,
+    /// Targets of delegatecalls pushed by the code or copied from it, e.g. minimal proxies'.
+    pub delegatecall_from_code: Vec<Address>,
+    /// Targets of delegatecalls whose origin was lost, e.g. returned by another call or loaded
+    /// from a slot that couldn't be told.
     pub delegatecall_unknown: Vec<Address>,
-    pub external_calls: Vec<(Address, u32)>
+    /// Target and selector of the calls, staticcalls and callcodes with at least 4 bytes of
+    /// input.
+    pub external_calls: Vec<(Address, Selector)>,
+    /// External calls to an address loaded from storage, with the slot and selector.
+    pub storage_calls: Vec<(U256, Selector)>,
+    /// Slots in `delegatecall_storage` whose address isn't in the low 160 bits.
+    pub delegatecall_extractions: Vec<(U256, SlotExtraction)>,
+    /// Slot and value of every SSTORE executed, including the ones a revert undid.
+    pub sstores: Vec<(U256, U256)>,
+    /// Whether each delegatecall, in the order they were made, ran in a static context, e.g.
+    /// within a STATICCALL the contract made to itself.
+    pub static_delegation: Vec<bool>,
+    /// Registry and selector of the STATICCALLs whose synthetic return value was then
+    /// delegatecalled, like beacon proxies do with `implementation()`. Those delegatecalls
+    /// aren't in the other buckets.
+    pub delegatecall_registry: Vec<(Address, Selector)>,
+    /// Every call made, the traced one included, in the order they were made.
+    pub calls: Vec<CallNode>,
+    /// Other contracts whose code was read with EXTCODECOPY or EXTCODESIZE, in order and with
+    /// repetitions.
+    pub code_reads: Vec<Address>,
+    /// Slots the targets of `code_reads` were loaded from.
+    pub code_read_storage: Vec<U256>,
+    /// A JUMP or JUMPI went to a destination copied from another contract's code, which then
+    /// drives the contract like SSTORE2-style code pointers do.
+    pub code_jump: bool,
+    /// Hash and input of every KECCAK256 run on at most [MAX_KECCAK_PREIMAGE] bytes, in order,
+    /// e.g. the slots of mappings and those derived from a name like EIP-1967's.
+    pub keccaks: Vec<(U256, Bytes)>,
+    /// The call reverted or halted exceptionally.
+    pub reverted: bool,
+    /// The calldata of the traced call.
+    pub calldata: Bytes,
+}
+
+impl InspectorData {
+    /// How the address in `slot` was extracted, if it isn't in the low 160 bits.
+    pub fn extraction(&self, slot: &U256) -> Option<SlotExtraction> {
+        self.delegatecall_extractions.iter().find(|(s, _)| s == slot).map(|(_, extraction)| *extraction)
+    }
+
+    /// Whether the call delegated, and only ever in a static context, so the targets can't
+    /// change the contract's state.
+    pub fn only_static_delegation(&self) -> bool {
+	!self.static_delegation.is_empty() && self.static_delegation.iter().all(|is_static| *is_static)
+    }
+
+    /// Targets of delegatecalls that weren't loaded from storage, whatever their origin.
+    pub fn delegatecall_addresses(&self) -> Vec<Address> {
+        [&self.delegatecall_from_calldata, &self.delegatecall_from_code, &self.delegatecall_unknown].into_iter().flatten().copied().collect()
+    }
+
+    /// The input of a KECCAK256 whose hash is `slot`, or `slot + 1` like EIP-1967 derives its
+    /// slots, with whether it was the latter.
+    pub fn preimage_of(&self, slot: &U256) -> Option<(&Bytes, bool)> {
+        self.keccaks.iter().find_map(|(hash, preimage)| {
+            if hash == slot {
+                Some((preimage, false))
+            } else {
+                (hash.wrapping_sub(U256::from(1)) == *slot).then_some((preimage, true))
+            }
+        })
+    }
+
+    /// Whether a delegatecall [forwarded](CallNode::is_forwarding) the call.
+    pub fn forwards_calldata(&self) -> bool {
+        self.calls.iter().any(|call| call.is_forwarding)
+    }
+}
+
+/// How a call in the [call tree](InspectorData::calls) was made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+}
+
+/// A call in the [call tree](InspectorData::calls).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallNode {
+    /// Index of the call it was made from, `None` for the traced call.
+    pub parent: Option<usize>,
+    /// 0 for the traced call.
+    pub depth: usize,
+    pub kind: CallKind,
+    /// The account whose code runs, the library of delegatecalls and callcodes.
+    pub target: Address,
+    pub selector: Option<Selector>,
+    pub is_static: bool,
+    /// Index of the stubbed call that returned `target` as its synthetic value.
+    pub target_from: Option<usize>,
+    /// A delegatecall passed the whole calldata of the transaction, possibly with data appended,
+    /// and the caller then returned or reverted with the return data, copied or written where
+    /// it asked, like proxies do.
+    pub is_forwarding: bool,
+}
+
+/// Longest KECCAK256 input recorded in [InspectorData::keccaks], names and mapping keys are
+/// shorter.
+pub const MAX_KECCAK_PREIMAGE: usize = 256;
+
+/// Steps between two reads of the clock for [ProxyInspector::with_budget]'s timeout.
+const DEADLINE_CHECK_STEPS: u64 = 1024;
+
+/// The address a stubbed STATICCALL of `selector` to `target` returns, see
+/// [ProxyInspector::with_synthetic_returns].
+pub fn synthetic_return(target: &Address, selector: &Selector) -> Address {
+    Address::from_slice(&keccak256([target.as_slice(), selector.as_bytes().as_slice()].concat())[12..])
 }
 
 /// An inspector that calls multiple inspectors in sequence.
@@ -33,8 +159,50 @@ pub struct InspectorData {
 pub struct ProxyInspector {
     storage_access: Vec<U256>,
     delegatecall_storage: Vec<U256>,
+    delegatecall_from_calldata: Vec<Address>,
+    delegatecall_from_code: Vec<Address>,
     delegatecall_unknown: Vec<Address>,
-    external_calls: Vec<(Address, u32)>
+    external_calls: Vec<(Address, Selector)>,
+    storage_calls: Vec<(U256, Selector)>,
+    delegatecall_extractions: Vec<(U256, SlotExtraction)>,
+    sstores: Vec<(U256, U256)>,
+    static_delegation: Vec<bool>,
+    delegatecall_registry: Vec<(Address, Selector)>,
+    calls: Vec<CallNode>,
+    code_reads: Vec<Address>,
+    code_read_storage: Vec<U256>,
+    code_jump: bool,
+    keccaks: Vec<(U256, Bytes)>,
+    /// Indices in `calls` of the calls being run.
+    open_calls: Vec<usize>,
+    /// The last delegatecall passed the whole calldata, waiting for its caller to hand back
+    /// the return data.
+    epilogue: Option<Epilogue>,
+    /// Selectors whose STATICCALLs return a synthetic address.
+    synthetic_returns: Vec<Selector>,
+    /// The synthetic addresses returned, with the index of the call, its target and selector.
+    returned: HashMap<Address, (usize, Address, Selector)>,
+    /// Run the STATICCALLs the contract makes to itself instead of stubbing them.
+    static_self_calls: bool,
+    /// Follow how SLOAD results are shifted and masked, to find addresses packed with other
+    /// values.
+    track_layout: bool,
+    /// Values derived from a SLOAD, with the slot and how they were extracted from it.
+    tainted: HashMap<U256, (U256, SlotExtraction)>,
+    /// Taint for the value the current instruction pushes.
+    pending_taint: Option<(U256, SlotExtraction)>,
+    /// Origins in the frames being run, by depth.
+    frames: Vec<Tainter>,
+    /// Origin of the address the call about to be made goes to.
+    call_origin: Option<Origin>,
+    /// Steps run, in every frame.
+    steps: u64,
+    /// See [ProxyInspector::with_budget].
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    /// Steps run and program counter when the budget ran out.
+    exhausted: Option<(u64, usize)>,
 }
 
 impl ProxyInspector {
@@ -48,39 +216,354 @@ impl ProxyInspector {
         Self::default()
     }
 
-    /// Collects all the data gathered during inspection into a single struct.
+    pub fn with_layout_tracking(mut self, enabled: bool) -> Self {
+        self.track_layout = enabled;
+        self
+    }
+
+    pub fn with_static_self_calls(mut self, enabled: bool) -> Self {
+        self.static_self_calls = enabled;
+        self
+    }
+
+    /// Answer the STATICCALLs of `selectors` with a [synthetic_return] address instead of
+    /// nothing, so the code goes on to use it.
+    pub fn with_synthetic_returns(mut self, selectors: Vec<Selector>) -> Self {
+        self.synthetic_returns = selectors;
+        self
+    }
+
+    /// Halts every frame once `max_steps` steps have run or `timeout` has passed since now.
+    pub fn with_budget(mut self, max_steps: Option<u64>, timeout: Option<Duration>) -> Self {
+        self.max_steps = max_steps;
+        self.timeout = timeout;
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self
+    }
+
+    /// Forgets what the last call gathered and restarts the budget, for the next call traced
+    /// with the same EVM. The configuration is kept.
+    pub fn reset(&mut self) {
+        self.storage_access.clear();
+        self.delegatecall_storage.clear();
+        self.delegatecall_from_calldata.clear();
+        self.delegatecall_from_code.clear();
+        self.delegatecall_unknown.clear();
+        self.external_calls.clear();
+        self.storage_calls.clear();
+        self.delegatecall_extractions.clear();
+        self.sstores.clear();
+        self.static_delegation.clear();
+        self.delegatecall_registry.clear();
+        self.calls.clear();
+        self.code_reads.clear();
+        self.code_read_storage.clear();
+        self.code_jump = false;
+        self.keccaks.clear();
+        self.open_calls.clear();
+        self.epilogue = None;
+        self.returned.clear();
+        self.tainted.clear();
+        self.pending_taint = None;
+        self.frames.clear();
+        self.call_origin = None;
+        self.steps = 0;
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.exhausted = None;
+    }
+
+    /// Steps run and program counter when the budget ran out and the call was halted.
+    pub fn exhausted(&self) -> Option<(u64, usize)> {
+        self.exhausted
+    }
+
+    /// Whether the budget is exhausted, the clock is only read every [DEADLINE_CHECK_STEPS].
+    fn over_budget(&self) -> bool {
+        self.exhausted.is_some()
+            || self.max_steps.is_some_and(|max_steps| self.steps > max_steps)
+            || (self.steps.is_multiple_of(DEADLINE_CHECK_STEPS) && self.deadline.is_some_and(|deadline| Instant::now() >= deadline))
+    }
+
+    /// Collects all the data gathered during inspection into a single struct, see
+    /// [reset](Self::reset) to trace another call.
     #[inline]
-    pub fn collect(self) -> InspectorData {
+    pub fn collect(&mut self) -> InspectorData {
         InspectorData {
-	    storage_access: self.storage_access,
-            delegatecall_storage: self.delegatecall_storage,
-            delegatecall_unknown: self.delegatecall_unknown,
-            external_calls: self.external_calls,
+	    storage_access: std::mem::take(&mut self.storage_access),
+            delegatecall_storage: std::mem::take(&mut self.delegatecall_storage),
+            delegatecall_from_calldata: std::mem::take(&mut self.delegatecall_from_calldata),
+            delegatecall_from_code: std::mem::take(&mut self.delegatecall_from_code),
+            delegatecall_unknown: std::mem::take(&mut self.delegatecall_unknown),
+            external_calls: std::mem::take(&mut self.external_calls),
+            storage_calls: std::mem::take(&mut self.storage_calls),
+            delegatecall_extractions: std::mem::take(&mut self.delegatecall_extractions),
+            sstores: std::mem::take(&mut self.sstores),
+            static_delegation: std::mem::take(&mut self.static_delegation),
+            delegatecall_registry: std::mem::take(&mut self.delegatecall_registry),
+            calls: std::mem::take(&mut self.calls),
+            code_reads: std::mem::take(&mut self.code_reads),
+            code_read_storage: std::mem::take(&mut self.code_read_storage),
+            code_jump: self.code_jump,
+            keccaks: std::mem::take(&mut self.keccaks),
+            reverted: false,
+            calldata: Bytes::new(),
         }
     }
 
+    /// The slot and extraction of the operation about to run if it derives a value from a
+    /// tainted one: SLOAD, SHR and DIV by a power of two, AND.
+    fn taint_operation(&self, interpreter: &Interpreter) -> Option<(U256, SlotExtraction)> {
+        let stack = &interpreter.stack;
+        let tainted = |value: U256| self.tainted.get(&value).copied();
+        match interpreter.current_opcode() {
+            opcode::SLOAD => Some((stack.peek(0).ok()?, SlotExtraction::default())),
+            opcode::SHR => {
+                let (slot, extraction) = tainted(stack.peek(1).ok()?)?;
+                Some((slot, extraction.shifted(stack.peek(0).ok()?.try_into().unwrap_or(usize::MAX))))
+            },
+            opcode::DIV => {
+                let (slot, extraction) = tainted(stack.peek(0).ok()?)?;
+                let divisor = stack.peek(1).ok()?;
+                divisor.is_power_of_two().then(|| (slot, extraction.shifted(divisor.trailing_zeros())))
+            },
+            opcode::AND => {
+                let (a, b) = (stack.peek(0).ok()?, stack.peek(1).ok()?);
+                tainted(a).map(|(slot, extraction)| (slot, extraction.masked(b)))
+                    .or_else(|| tainted(b).map(|(slot, extraction)| (slot, extraction.masked(a))))
+            },
+            _ => None
+        }
+    }
+
+    /// Records the input and hash of the KECCAK256 about to run, read from memory.
+    fn record_keccak(&mut self, interpreter: &Interpreter) {
+        let (Ok(offset), Ok(len)) = (interpreter.stack.peek(0), interpreter.stack.peek(1)) else { return };
+        let (Ok(offset), Ok(len)) = (usize::try_from(offset), usize::try_from(len)) else { return };
+        if len > MAX_KECCAK_PREIMAGE || offset.saturating_add(len) > interpreter.shared_memory.len() {
+            return;
+        }
+        let preimage = interpreter.shared_memory.slice(offset, len);
+        self.keccaks.push((U256::from_be_bytes(keccak256(preimage).0), Bytes::copy_from_slice(preimage)));
+    }
+
+    /// Marks the awaited delegatecall as forwarding once its caller, at `depth`, copies the
+    /// return data and returns or reverts. Any other call replaces the return data first.
+    fn follow_epilogue(&mut self, op: u8, depth: usize) {
+        let Some(epilogue) = self.epilogue.as_mut() else {
+            return;
+        };
+        if depth < epilogue.depth {
+            // The frame ended some other way
+            self.epilogue = None;
+        } else if depth == epilogue.depth {
+            match op {
+                opcode::RETURNDATACOPY => epilogue.returndata_copied = true,
+                opcode::RETURN | opcode::REVERT => {
+                    if epilogue.returndata_copied {
+                        self.calls[epilogue.call].is_forwarding = true;
+                    }
+                    self.epilogue = None;
+                },
+                opcode::CALL | opcode::CALLCODE | opcode::DELEGATECALL | opcode::STATICCALL | opcode::CREATE | opcode::CREATE2 => self.epilogue = None,
+                _ => (),
+            }
+        }
+    }
+
+    /// Slot and extraction of a delegatecall target derived from a SLOAD.
+    fn tainted_address(&self, address: &Address) -> Option<(U256, SlotExtraction)> {
+        self.tainted.iter()
+            .find(|(value, _)| value.bitand(*ADDR_MASK) == U256::from_be_slice(address.as_slice()))
+            .map(|(_, (slot, extraction))| (*slot, extraction.masked(*ADDR_MASK)))
+    }
+
 }
 
-// enum TaintDetail {
-//     // Variables embedded in the code, minimal proxies and others
-//     CodeData(u16, u16),
-//     CallData(u16, u16),
-//     Storage(Rc<TaintInfo>),
-//     Static
-// }
+/// What the frame of a delegatecall that passed the whole calldata did since it returned.
+#[derive(Clone, Copy, Debug)]
+struct Epilogue {
+    /// Index in the call tree.
+    call: usize,
+    /// Journal depth of the frame that made it.
+    depth: usize,
+    returndata_copied: bool,
+}
+
+/// Where a value on the stack or in memory came from. Values computed from several take the
+/// greatest, so anything the caller can influence counts as calldata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Origin {
+    /// Pushed by the code or copied from it: constants, immutables, minimal proxy targets.
+    Code,
+    /// Anything else: the environment, what other calls returned...
+    Unknown,
+    /// Loaded with SLOAD.
+    Storage,
+    /// Copied from another contract's code with EXTCODECOPY.
+    ExtCode,
+    /// Read from the calldata, chosen by whoever calls.
+    Calldata,
+}
+
+/// Memory past this isn't followed, the gas limit makes touching it unlikely.
+const MAX_TRACKED_MEMORY: usize = 1 << 20;
+
+/// Follows the [Origin] of every stack item and memory byte of a call frame.
+#[derive(Debug, Default)]
+struct Tainter {
+    stack: Vec<Origin>,
+    /// Bytes never written are zero, a constant.
+    memory: Vec<Origin>,
+}
+
+impl Tainter {
+    /// Origin of the `n`th item from the top of the stack.
+    fn peek(&self, n: usize) -> Origin {
+        self.stack.len().checked_sub(n + 1).map(|i| self.stack[i]).unwrap_or(Origin::Unknown)
+    }
+
+    fn memory_range(offset: U256, len: U256) -> Option<std::ops::Range<usize>> {
+        let offset = usize::try_from(offset).ok()?;
+        let end = offset.checked_add(usize::try_from(len).ok()?)?;
+        (offset < end && end <= MAX_TRACKED_MEMORY).then_some(offset..end)
+    }
+
+    fn memory_origin(&self, offset: U256, len: U256) -> Origin {
+        match Self::memory_range(offset, len) {
+            Some(range) => range.filter_map(|i| self.memory.get(i)).copied().max().unwrap_or(Origin::Code),
+            None => Origin::Unknown,
+        }
+    }
+
+    fn set_memory(&mut self, offset: U256, len: U256, origin: Origin) {
+        if let Some(range) = Self::memory_range(offset, len) {
+            if self.memory.len() < range.end {
+                self.memory.resize(range.end, Origin::Code);
+            }
+            self.memory[range].fill(origin);
+        }
+    }
+
+    fn copy_memory(&mut self, to: U256, from: U256, len: U256) {
+        let Some(source) = Self::memory_range(from, len) else {
+            return self.set_memory(to, len, Origin::Unknown);
+        };
+        let origins: Vec<Origin> = source.map(|i| self.memory.get(i).copied().unwrap_or(Origin::Code)).collect();
+        if let Some(range) = Self::memory_range(to, len) {
+            if self.memory.len() < range.end {
+                self.memory.resize(range.end, Origin::Code);
+            }
+            self.memory[range].copy_from_slice(&origins);
+        }
+    }
+
+    /// Applies the operation about to run to the origins, before it changes the stack.
+    fn step(&mut self, interpreter: &Interpreter) {
+        let stack = &interpreter.stack;
+        // Only out of sync if a previous operation halted, keep the top aligned
+        if self.stack.len() > stack.len() {
+            self.stack.drain(..self.stack.len() - stack.len());
+        } else if self.stack.len() < stack.len() {
+            self.stack.splice(0..0, std::iter::repeat_n(Origin::Unknown, stack.len() - self.stack.len()));
+        }
+        let op = interpreter.current_opcode();
+        let Some(info) = OpCode::info_by_op(op) else {
+            return;
+        };
+        let word = |n: usize| stack.peek(n).unwrap_or_default();
+        let output = match op {
+            opcode::PUSH0..=opcode::PUSH32 | opcode::PC | opcode::CODESIZE => Origin::Code,
+            opcode::CALLDATALOAD | opcode::CALLDATASIZE => Origin::Calldata,
+            opcode::SLOAD => Origin::Storage,
+            opcode::MLOAD => self.memory_origin(word(0), U256::from(32)),
+            opcode::KECCAK256 => self.memory_origin(word(0), word(1)),
+            // Arithmetic, comparisons and bitwise operations carry their operands' origin
+            opcode::ADD..=opcode::SAR if stack.len() >= info.inputs() as usize => {
+                let origins = self.stack.split_off(self.stack.len() - info.inputs() as usize);
+                self.stack.push(origins.into_iter().max().unwrap_or(Origin::Code));
+                return;
+            },
+            opcode::DUP1..=opcode::DUP16 => {
+                let origin = self.peek((op - opcode::DUP1) as usize);
+                self.stack.push(origin);
+                return;
+            },
+            opcode::SWAP1..=opcode::SWAP16 => {
+                let depth = (op - opcode::SWAP1 + 1) as usize;
+                if let Some(other) = self.stack.len().checked_sub(depth + 1) {
+                    self.stack.swap(other, other + depth);
+                }
+                return;
+            },
+            opcode::MSTORE | opcode::MSTORE8 => {
+                let len = if op == opcode::MSTORE { 32 } else { 1 };
+                self.set_memory(word(0), U256::from(len), self.peek(1));
+                Origin::Unknown
+            },
+            opcode::CALLDATACOPY => {
+                self.set_memory(word(0), word(2), Origin::Calldata);
+                Origin::Unknown
+            },
+            opcode::CODECOPY => {
+                self.set_memory(word(0), word(2), Origin::Code);
+                Origin::Unknown
+            },
+            opcode::RETURNDATACOPY => {
+                self.set_memory(word(0), word(2), Origin::Unknown);
+                Origin::Unknown
+            },
+            opcode::EXTCODECOPY => {
+                self.set_memory(word(1), word(3), Origin::ExtCode);
+                Origin::Unknown
+            },
+            opcode::MCOPY => {
+                self.copy_memory(word(0), word(1), word(2));
+                Origin::Unknown
+            },
+            // What the call returns is written where the caller asked
+            opcode::CALL | opcode::CALLCODE => {
+                self.set_memory(word(5), word(6), Origin::Unknown);
+                Origin::Unknown
+            },
+            opcode::DELEGATECALL | opcode::STATICCALL => {
+                self.set_memory(word(4), word(5), Origin::Unknown);
+                Origin::Unknown
+            },
+            _ => Origin::Unknown,
+        };
+        self.stack.truncate(self.stack.len().saturating_sub(info.inputs() as usize));
+        self.stack.extend(std::iter::repeat_n(output, info.outputs() as usize));
+    }
+}
 
-// struct TaintInfo {
-//     taint_detail: TaintDetail,
-//     clean_taint: bool
-// }
+/// Builds the revm bytecode for `code` ready to execute, copying it exactly once into the padded
+/// buffer revm runs from. Clones share that buffer and the jump table, so the same contract can
+/// be traced any number of times without copying or analysing it again.
+pub fn analyzed_bytecode(code: &[u8]) -> Bytecode {
+    // Padded like revm does, so execution can't run past the end
+    let padded_len = code.len() + 33;
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.extend_from_slice(code);
+    padded.resize(padded_len, 0);
 
-// struct Tainter {
-//     memory: Vec<(U256, TaintInfo)>,
-//     stack: Vec<(U256, TaintInfo)>
-// }
+    let mut jumps = bitvec![u8, Lsb0; 0; padded_len];
+    for ins in disassemble(&padded).filter(|ins| ins.opcode == opcode::JUMPDEST) {
+	jumps.set(ins.offset, true);
+    }
+    Bytecode::LegacyAnalyzed(LegacyAnalyzedBytecode::new(Bytes::from(padded), code.len(), JumpTable(Arc::new(jumps))))
+}
 
 static ADDR_MASK: Lazy<U256> = Lazy::new(|| U256::from_be_bytes(hex_literal::hex!("000000000000000000000000ffffffffffffffffffffffffffffffffffffffff")));
-static ADDR_XOR: Lazy<U256> = Lazy::new(|| U256::from_be_bytes(hex_literal::hex!("000000000000000000000000c1d50e94dbe44a2e3595f7d5311d788076ac6188")));
+
+/// Low bits of a synthetic address a delegatecall target has to keep to be traced back to its
+/// slot when the contract cleared the others.
+const MIN_MANGLED_BITS: usize = 64;
+
+/// The address the database answers for `slot` of a contract whose storage isn't known, XORed
+/// with `key`, see [TraceEnvironment::storage_key].
+pub fn synthetic_address(slot: &U256, key: &Address) -> Address {
+    Address::from_word(FixedBytes::from(slot.bitand(*ADDR_MASK).bitxor(U256::from_be_slice(key.as_slice())).to_be_bytes::<32>()))
+}
 
 #[derive(Clone, Debug, Error)]
 pub enum ProxyDetectError {
@@ -89,24 +572,122 @@ pub enum ProxyDetectError {
 
 pub struct ProxyDetectDB {
     contract_address: Address,
-    code: HashMap<Address, Bytes>,
+    env: TraceEnvironment,
+    /// Installed code with its hash.
+    code: HashMap<Address, (B256, Bytecode)>,
+    /// Every code given out, installed or dummy, by hash.
+    code_by_hash: HashMap<B256, Bytecode>,
     values_to_storage: HashMap<Address, U256>,
-    delegatecalls: Vec<Address>
+    delegatecalls: Vec<Address>,
+    packed_values: bool,
+    empty_accounts: Vec<Address>,
+    /// Real values of the contract's slots, returned instead of synthetic ones.
+    known_storage: HashMap<U256, U256>,
+    /// Real state of every account, see [with_fork](Self::with_fork).
+    fork: Option<Arc<ForkState>>,
+    /// What synthetic values are XORed with, see [TraceEnvironment::storage_key].
+    storage_key: U256,
 }
 
 
 impl ProxyDetectDB {
-    pub fn new(contract_address: Address) -> Self {
+    pub fn new(env: TraceEnvironment) -> Self {
 	Self {
-            contract_address,
+            contract_address: env.contract,
 	    code: HashMap::new(),
+	    code_by_hash: HashMap::new(),
 	    values_to_storage: HashMap::new(),
-            delegatecalls: Vec::new()
+            delegatecalls: Vec::new(),
+	    packed_values: false,
+	    empty_accounts: Vec::new(),
+	    known_storage: HashMap::new(),
+	    fork: None,
+	    storage_key: U256::from_be_slice(env.storage_key().as_slice()),
+	    env,
 	}
     }
 
-    pub fn install_contract(&mut self, address: Address, code: &Bytes) {
-	self.code.insert(address, code.clone());
+    /// Return these values for the contract's slots instead of synthetic ones. Addresses in
+    /// their low 160 bits are still traced back to the slot.
+    pub fn with_known_storage(mut self, storage: HashMap<U256, U256>) -> Self {
+	self.known_storage = storage;
+	self
+    }
+
+    /// XOR synthetic values with `key` rather than the environment's
+    /// [storage_key](TraceEnvironment::storage_key).
+    pub fn with_storage_key(mut self, key: Address) -> Self {
+	self.storage_key = U256::from_be_slice(key.as_slice());
+	self
+    }
+
+    /// The slot whose value `address` is with its high bytes cleared, and the mask clearing
+    /// them. At least [MIN_MANGLED_BITS] bits have to be left.
+    fn mangled_storage(&self, address: &Address) -> Option<(U256, SlotExtraction)> {
+	let target = U256::from_be_slice(address.as_slice());
+	let bits = target.bit_len().next_multiple_of(8);
+	if bits < MIN_MANGLED_BITS {
+	    return None;
+	}
+	let mask = (U256::from(1) << bits) - U256::from(1);
+	self.values_to_storage.iter()
+	    .filter(|(value, _)| U256::from_be_slice(value.as_slice()) & mask == target)
+	    .map(|(_, slot)| *slot)
+	    .min()
+	    .map(|slot| (slot, SlotExtraction::default().masked(mask)))
+    }
+
+    /// Answer with the storage and code `fork` has, for every account. What it lacks is
+    /// answered synthetically and remembered by `fork` to be fetched. Calls to other contracts
+    /// with code in `fork` are run.
+    pub fn with_fork(mut self, fork: Option<Arc<ForkState>>) -> Self {
+	self.fork = fork;
+	self
+    }
+
+    /// Whether storage values are real ones, where zero means unset.
+    fn has_real_storage(&self) -> bool {
+	!self.known_storage.is_empty() || self.fork.is_some()
+    }
+
+    /// Whether calls to `address` are run rather than stubbed.
+    fn runs_calls_to(&self, address: &Address) -> bool {
+	self.fork.as_ref().is_some_and(|fork| fork.code.get(address).is_some_and(|code| !code.is_empty()))
+    }
+
+    /// Report `address` as a non existent account, e.g. where a contract is going to be
+    /// created.
+    pub fn with_empty_account(mut self, address: Address) -> Self {
+	self.insert_empty_account(address);
+	self
+    }
+
+    /// Like [with_empty_account](Self::with_empty_account), for addresses found while tracing.
+    /// Accounts the EVM already loaded aren't affected.
+    pub fn insert_empty_account(&mut self, address: Address) {
+	self.empty_accounts.push(address);
+    }
+
+    /// Fill the high 96 bits of storage values too, so addresses packed at any offset are
+    /// distinct and non zero.
+    pub fn with_packed_values(mut self, enabled: bool) -> Self {
+	self.packed_values = enabled;
+	self
+    }
+
+    /// Installs `code` at `address`, see [analyzed_bytecode] to share it between runs without
+    /// copying.
+    pub fn install_contract(&mut self, address: Address, code: &Bytecode) {
+	let hash = code.hash_slow();
+	self.code_by_hash.insert(hash, code.clone());
+	self.code.insert(address, (hash, code.clone()));
+    }
+
+    /// Forgets the synthetic values and delegatecalls of the last call, for the next call
+    /// traced with the same EVM. The installed code is kept.
+    pub(crate) fn reset(&mut self) {
+	self.values_to_storage.clear();
+	self.delegatecalls.clear();
     }
 
     fn insert_delegatecall(&mut self, contract: Address) {
@@ -119,48 +700,76 @@ impl Database for ProxyDetectDB {
 
     fn basic(&mut self, address: Address) -> Result<Option<AccountInfo> ,Self::Error>  {
         debug!("basic(): addr: {:?}", address);
-	if let Some(code) = self.code.get(&address) {
-	    Ok(Some(
-		AccountInfo {
-		    balance: U256::ZERO,
-		    nonce: 0,
-		    code_hash: B256::ZERO,
-		    code: Some(Bytecode::new_raw(code.clone())),
-		}
-	    ))
-	} else if address == Address::ZERO {
+	if address == Address::ZERO || self.empty_accounts.contains(&address) {
 	    // Return empty account for null, revm asks for it
-	    Ok(None)
-	} else {
-	    Ok(Some(
-		AccountInfo {
-		    balance: U256::ZERO,
-		    nonce: 0,
-		    code_hash: B256::ZERO,
-		    // Let's give it some code
-		    code: Some(Bytecode::new_raw(Bytes::copy_from_slice(&[0xcc, 0xaa, 0xdd, 0xbb]))),
-		}
-	    ))
+	    return Ok(None);
 	}
+	let balance = self.env.dummy_balance(&address);
+	if address == self.env.caller {
+	    // The caller has to be an EOA (EIP-3607)
+	    return Ok(Some(AccountInfo::from_balance(balance)));
+	}
+	// Let's give it some code, varied so it can't be used to fingerprint the tracer
+	let (code_hash, code) = match self.code.get(&address) {
+	    Some(installed) => installed.clone(),
+	    None => {
+		let real = self.fork.as_ref().and_then(|fork| fork.code(&address).cloned());
+		let code = Bytecode::new_raw(real.unwrap_or_else(|| self.env.dummy_code(&address)));
+		let hash = code.hash_slow();
+		self.code_by_hash.insert(hash, code.clone());
+		(hash, code)
+	    },
+	};
+	Ok(Some(
+	    AccountInfo {
+		balance,
+		nonce: 1,
+		code_hash,
+		code: Some(code),
+	    }
+	))
     }
 
-    fn code_by_hash(&mut self, _code_hash: B256) -> Result<Bytecode,Self::Error>  {
-        // println!("code_by_hash(): {}", code_hash);
-	todo!()
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode,Self::Error>  {
+	debug!("code_by_hash(): {:x}", code_hash);
+	// A hash no account was given is answered with dummy code, the same for the same hash
+	Ok(self.code_by_hash.get(&code_hash).cloned()
+	    .unwrap_or_else(|| Bytecode::new_raw(self.env.dummy_code(&Address::from_word(code_hash)))))
     }
 
     fn storage(&mut self, address: Address,index: U256) -> Result<U256,Self::Error>  {
-        let magic_value = index.bitand(*ADDR_MASK).bitxor(*ADDR_XOR);
+	if let Some(value) = self.fork.as_ref().and_then(|fork| fork.storage(&address, &index)) {
+	    let low_address = value.bitand(*ADDR_MASK);
+	    if address == self.contract_address && !low_address.is_zero() {
+		self.values_to_storage.insert(Address::from_word(FixedBytes::from(low_address.to_be_bytes::<32>())), index);
+	    }
+	    return Ok(value);
+	}
+	if address == self.contract_address {
+	    if let Some(value) = self.known_storage.get(&index) {
+		let low_address = value.bitand(*ADDR_MASK);
+		if !low_address.is_zero() {
+		    self.values_to_storage.insert(Address::from_word(FixedBytes::from(low_address.to_be_bytes::<32>())), index);
+		}
+		return Ok(*value);
+	    }
+	}
+        let mut magic_value = index.bitand(*ADDR_MASK).bitxor(self.storage_key);
+	if self.packed_values {
+	    magic_value |= U256::from_be_bytes(keccak256(index.to_be_bytes::<32>()).0) & !*ADDR_MASK;
+	}
 	let magic_address = Address::from_word(FixedBytes::from_slice(&magic_value.to_be_bytes::<32>()));
 	debug!("storage(): {:x} -> {:x} = {:x}", address, index, magic_value);
 
-        self.values_to_storage.insert(magic_address, index);
+	// Over a fork, the slots of the contracts it calls aren't the proxy's
+	if self.fork.is_none() || address == self.contract_address {
+            self.values_to_storage.insert(magic_address, index);
+	}
 	Ok(magic_value)
     }
 
-    fn block_hash(&mut self, _number: U256) -> Result<B256,Self::Error>  {
-	// println!("block_hash(): {}", number);
-        todo!()
+    fn block_hash(&mut self, number: U256) -> Result<B256,Self::Error>  {
+	Ok(self.env.dummy_block_hash(number))
     }
 }
 
@@ -171,8 +780,15 @@ impl Inspector<ProxyDetectDB> for ProxyInspector {
     fn step(
         &mut self,
         interpreter: &mut Interpreter,
-        _context: &mut EvmContext<ProxyDetectDB>,
+        context: &mut EvmContext<ProxyDetectDB>,
     ) {
+        self.steps += 1;
+        if self.over_budget() {
+            // The frames it returns to halt on their next step
+            self.exhausted.get_or_insert((self.steps - 1, interpreter.program_counter()));
+            interpreter.instruction_result = InstructionResult::OutOfGas;
+            return;
+        }
         // debug!("addr: {}", interpreter.contract.address);
         // debug!("opcode: {}", interpreter.current_opcode());
         debug!("opcode: {}", OpCode::new(interpreter.current_opcode()).unwrap());
@@ -180,15 +796,65 @@ impl Inspector<ProxyDetectDB> for ProxyInspector {
             debug!("STACK: {:x}", mem);
         }
         debug!("--");
-        match interpreter.current_opcode() {
-            opcode::SLOAD => {
-                if let Ok(memory) = interpreter.stack.peek(0) {
-		    self.storage_access.push(memory);
-                    debug!("SLOAD detected {}", memory);
+        if interpreter.current_opcode() == opcode::SLOAD {
+            if let Ok(memory) = interpreter.stack.peek(0) {
+                self.storage_access.push(memory);
+                debug!("SLOAD detected {}", memory);
+            }
+        }
+        // Seen before it runs, so reverting the journal afterwards doesn't hide it
+        if interpreter.current_opcode() == opcode::SSTORE {
+            if let (Ok(slot), Ok(value)) = (interpreter.stack.peek(0), interpreter.stack.peek(1)) {
+                self.sstores.push((slot, value));
+            }
+        }
+        if matches!(interpreter.current_opcode(), opcode::EXTCODECOPY | opcode::EXTCODESIZE) {
+            if let Ok(word) = interpreter.stack.peek(0) {
+                let address = Address::from_word(word.to_be_bytes::<32>().into());
+                if address != context.db.contract_address {
+                    self.code_reads.push(address);
+                    let slot = context.db.values_to_storage.get(&address).copied()
+                        .or_else(|| self.tainted_address(&address).map(|(slot, _)| slot));
+                    self.code_read_storage.extend(slot);
                 }
-            },
-            _ => ()
-        };
+            }
+        }
+        if interpreter.current_opcode() == opcode::KECCAK256 {
+            self.record_keccak(interpreter);
+        }
+        if self.track_layout {
+            self.pending_taint = self.taint_operation(interpreter);
+        }
+        let depth = context.journaled_state.depth() as usize;
+        self.follow_epilogue(interpreter.current_opcode(), depth);
+        // A frame at the same depth as a finished one starts over
+        self.frames.truncate(depth);
+        self.frames.resize_with(depth, Tainter::default);
+        if let Some(tainter) = self.frames.last_mut() {
+            if matches!(interpreter.current_opcode(), opcode::CALL | opcode::CALLCODE | opcode::DELEGATECALL | opcode::STATICCALL) {
+                self.call_origin = Some(tainter.peek(1));
+            }
+            if matches!(interpreter.current_opcode(), opcode::JUMP | opcode::JUMPI) && tainter.peek(0) == Origin::ExtCode {
+                self.code_jump = true;
+            }
+            tainter.step(interpreter);
+        }
+    }
+
+    #[inline(always)]
+    fn step_end(
+        &mut self,
+        interpreter: &mut Interpreter,
+        _context: &mut EvmContext<ProxyDetectDB>,
+    ) {
+        if let Some(taint) = self.pending_taint.take() {
+            match interpreter.stack.peek(0) {
+                Ok(value) if interpreter.instruction_result == InstructionResult::Continue && !value.is_zero() => {
+                    self.tainted.insert(value, taint);
+                },
+                _ => ()
+            }
+        }
     }
 
     #[inline(always)]
@@ -199,28 +865,93 @@ impl Inspector<ProxyDetectDB> for ProxyInspector {
     ) -> Option<CallOutcome> {
         // println!("call!!! {:?} {}", call.scheme, call.target_address);
         // return (InstructionResult::Continue, Gas::new(call.gas_limit), Bytes::new());
-        if call.scheme == CallScheme::Call && call.target_address == context.db.contract_address {
+        let index = self.calls.len();
+        let returned = self.returned.get(&call.bytecode_address).copied();
+        self.calls.push(CallNode {
+            parent: self.open_calls.last().copied(),
+            depth: self.open_calls.len(),
+            kind: match call.scheme {
+                CallScheme::Call => CallKind::Call,
+                CallScheme::CallCode => CallKind::CallCode,
+                CallScheme::DelegateCall => CallKind::DelegateCall,
+                CallScheme::StaticCall => CallKind::StaticCall,
+            },
+            target: call.bytecode_address,
+            selector: Selector::from_slice(&call.input),
+            is_static: call.is_static,
+            target_from: returned.map(|(from, _, _)| from),
+            is_forwarding: false,
+        });
+        if call.scheme == CallScheme::DelegateCall && call.input.starts_with(&context.env.tx.data) {
+            // Old forwarders have the return data written where they asked instead of copying it
+            self.epilogue = Some(Epilogue { call: index, depth: context.journaled_state.depth() as usize, returndata_copied: !call.return_memory_offset.is_empty() });
+        }
+        self.open_calls.push(index);
+        let self_call = call.scheme == CallScheme::Call || (self.static_self_calls && call.scheme == CallScheme::StaticCall);
+        if self_call && call.target_address == context.db.contract_address {
             return None;
         }
 	match call.scheme {
+	    // An unset slot of the real storage, the call would go nowhere
+	    CallScheme::DelegateCall if call.bytecode_address == Address::ZERO && context.db.has_real_storage() => (),
 	    CallScheme::DelegateCall => {
 		context.db.delegatecalls.push(call.bytecode_address);
-		if let Some(storage) = context.db.values_to_storage.get(&call.bytecode_address) {
+		self.static_delegation.push(call.is_static);
+		let origin = self.call_origin.take();
+		if let Some((_, registry, selector)) = returned {
+		    self.delegatecall_registry.push((registry, selector));
+		} else if origin == Some(Origin::Code) {
+		    // A constant, even one equal to a synthetic value
+		    self.delegatecall_from_code.push(call.bytecode_address);
+		} else if let Some(storage) = context.db.values_to_storage.get(&call.bytecode_address) {
                     self.delegatecall_storage.push(*storage);
+		} else if let Some((slot, extraction)) = self.tainted_address(&call.bytecode_address).or_else(|| context.db.mangled_storage(&call.bytecode_address)) {
+                    self.delegatecall_storage.push(slot);
+		    if !extraction.is_low_address() {
+			self.delegatecall_extractions.push((slot, extraction));
+		    }
 		} else {
-                    self.delegatecall_unknown.push(call.bytecode_address);
+		    match origin {
+			Some(Origin::Calldata) => self.delegatecall_from_calldata.push(call.bytecode_address),
+			Some(Origin::Code) => self.delegatecall_from_code.push(call.bytecode_address),
+			_ => self.delegatecall_unknown.push(call.bytecode_address),
+		    }
 		}
 		context.db.insert_delegatecall(call.bytecode_address);
             },
 	    CallScheme::Call | CallScheme::CallCode | CallScheme::StaticCall => {
-		if call.input.len() >= 4 {
-		    let fun = slice_as_u32_be(&call.input);
+		if let Some(fun) = Selector::from_slice(&call.input) {
 		    self.external_calls.push((call.target_address, fun));
+		    let slot = context.db.values_to_storage.get(&call.target_address).copied()
+			.or_else(|| self.tainted_address(&call.target_address).map(|(slot, _)| slot));
+		    if let Some(slot) = slot {
+			self.storage_calls.push((slot, fun));
+		    }
 		    debug!("external call detected {:x}: {:x}", call.target_address, fun);
+		    if call.scheme == CallScheme::StaticCall && self.synthetic_returns.contains(&fun) {
+			let address = synthetic_return(&call.target_address, &fun);
+			self.returned.insert(address, (index, call.target_address, fun));
+			let output = Bytes::copy_from_slice(address.into_word().as_slice());
+			return Some(CallOutcome { result: InterpreterResult { result: InstructionResult::Return, output, gas: Gas::new(call.gas_limit) }, memory_offset: call.return_memory_offset.clone() });
+		    }
+		}
+		if context.db.runs_calls_to(&call.bytecode_address) {
+		    return None;
 		}
 
 	    }
 	};
         Some(CallOutcome { result: InterpreterResult { result: InstructionResult::Return, output: Bytes::new(), gas: Gas::new(call.gas_limit) }, memory_offset: 0..0 })
     }
+
+    #[inline(always)]
+    fn call_end(
+        &mut self,
+        _context: &mut EvmContext<ProxyDetectDB>,
+        _inputs: &CallInputs,
+        outcome: CallOutcome,
+    ) -> CallOutcome {
+        self.open_calls.pop();
+        outcome
+    }
 }
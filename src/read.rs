@@ -1,118 +1,971 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{BTreeMap, HashMap, HashSet}, future::Future, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 
 use async_recursion::async_recursion;
-use ethers_contract::abigen;
+use ethers_contract::{EthCall, EthEvent};
+use ethers_core::abi::{AbiDecode, AbiEncode};
 // use ethers_core::types::H256;
-use ethers_providers::Middleware;
-use futures::future::join_all;
-use alloy_primitives::{Address, U256};
+use ethers_core::types::{BlockId, BlockNumber, Filter, TransactionRequest};
+use ethers_providers::{JsonRpcError, Middleware, MiddlewareError};
+use futures::future::{join_all, try_join_all};
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::debug;
+use tracing::{debug, warn};
 
-use crate::{types::ProxyDispatch, consts::{DIAMOND_STANDARD_STORAGE_SLOT, ADDR_MASK_H256}, utils::{ru256_to_h256_be, raddress_to_h160, h256_to_raddress_unchecked, as_u32_le, h160_to_b160}};
+use crate::{reader::StorageReader, probe::{outcome_of_error, ProbeOutcome}, abi::{decode_address_return, Aggregate3Call, Aggregate3Return, Call3, FacetAddressesCall, FacetAddressesReturn, FacetFunctionSelectorsCall, FacetFunctionSelectorsReturn, FacetsCall, ImplementationCall, UpgradedFilter}, loupe::{decode_facets, LoupeDecodeError, LoupeDecoding, LoupeFacets, MAX_LOUPE_FACETS, MAX_LOUPE_SELECTORS}, types::{ProxyDispatch, SlotExtraction}, consts::{DIAMOND_FACET_ADDRESS_SELECTOR, DIAMOND_STANDARD_STORAGE_SLOT, EIP_1967_ADMIN_SLOT, EIP_1967_IMPLEMENTATION_SLOT, SELF_REPORT_GETTERS, ZOS_ADMIN_SLOT}, events::{get_logs_chunked, UpgradeEvent}, findings::Finding, progress::{NoProgress, ProgressEmitter, ProgressReporter, Throttle}, ProxyType, utils::{raddress_to_h160, h160_to_b160}, Selector};
 
+/// Why reading a proxy failed. Failed requests are [ProxyReadError::Rpc], with the node's error
+/// response as their source when it answered:
+///
+/// ```no_run
+/// # async fn run() {
+/// use std::{error::Error, sync::Arc};
+/// use alloy_primitives::{Address, U256};
+/// use ethers_providers::{Http, Provider};
+/// use evm_proxy_tools::{get_proxy_implementation, ProxyDispatch, ProxyReadError};
+///
+/// let rpc = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+/// let dispatch = ProxyDispatch::Storage(U256::from(1), None);
+/// match get_proxy_implementation(rpc, &Address::ZERO, &dispatch).await {
+///     Ok(implementation) => println!("{:?}", implementation),
+///     Err(ProxyReadError::Rpc(e)) => println!("{:?} after {} attempts: {:?}", e.kind, e.attempts, e.source()),
+///     Err(e) => println!("{}", e),
+/// }
+/// # }
+/// ```
 #[derive(Clone, Debug, Error)]
 pub enum ProxyReadError {
     #[error("unknown proxy")]
     UnknownProxy,
-    #[error("RPC error: `{0}`")]
-    RPCError(String),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error("block {0:?} not found")]
+    BlockNotFound(BlockId),
     #[error("the storage doesn't contain an address")]
     StorageNotAddress,
-    #[error("proxy is implemented in a different address")]
-    ExternalProxy,
+    #[error("resolver {0} answered {1} with `{2}`, not an address")]
+    ResolverNotAddress(Address, Selector, Bytes),
+    #[error("resolver {0} failed {1}: {2:?}")]
+    ResolverFailed(Address, Selector, ProbeOutcome),
+    #[error("the proxy has several implementations, get them with get_implementations")]
+    MultipleImplementations,
+    #[error("address {0} has no code")]
+    NoCode(Address),
+    #[error("beacon {0} didn't return an address")]
+    BeaconNotAddress(Address),
+    #[error("historical state unavailable at block {0} (pruned node?)")]
+    HistoricalStateUnavailable(u64),
+    #[error("block search exceeded its budget of {0} RPC calls")]
+    SearchBudgetExhausted(usize),
+    #[error(transparent)]
+    Loupe(#[from] LoupeDecodeError),
+    #[error("the diamond storage follows no known layout")]
+    UnknownDiamondLayout,
+    #[error("the implementation slot is empty, the proxy is uninitialized")]
+    UninitializedProxy,
     #[error("unknown data store error")]
     Unknown,
 }
 
-#[derive(Clone, Debug)]
+impl ProxyReadError {
+    /// The error of a request through an ethers middleware, keeping the node's response as
+    /// its source.
+    pub fn from_middleware<E: MiddlewareError>(error: E) -> Self {
+	ProxyReadError::Rpc(RpcError::from_middleware(&error))
+    }
+
+    /// Whether the node failed for lack of the state asked for, e.g. a pruned block.
+    pub fn is_missing_state(&self) -> bool {
+	match self {
+	    ProxyReadError::Rpc(e) => is_missing_state_error(&e.message),
+	    ProxyReadError::HistoricalStateUnavailable(_) => true,
+	    _ => false,
+	}
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+	match self {
+	    ProxyReadError::Rpc(_) | ProxyReadError::HistoricalStateUnavailable(_) | ProxyReadError::Unknown => ErrorCategory::Rpc,
+	    ProxyReadError::ResolverFailed(..) => ErrorCategory::Execution,
+	    ProxyReadError::StorageNotAddress | ProxyReadError::ResolverNotAddress(..) | ProxyReadError::BeaconNotAddress(_)
+	    | ProxyReadError::Loupe(_) | ProxyReadError::UninitializedProxy => ErrorCategory::Decoding,
+	    ProxyReadError::UnknownProxy | ProxyReadError::UnknownDiamondLayout => ErrorCategory::NotSupported,
+	    ProxyReadError::BlockNotFound(_) | ProxyReadError::MultipleImplementations | ProxyReadError::NoCode(_)
+	    | ProxyReadError::SearchBudgetExhausted(_) => ErrorCategory::User,
+	}
+    }
+
+    /// Whether the same read may succeed later: the node limited the rate of requests or
+    /// didn't answer, even after the retries of the [ReadConfig].
+    pub fn is_retryable(&self) -> bool {
+	matches!(self, ProxyReadError::Rpc(e) if e.kind.is_retryable())
+    }
+}
+
+/// What an error is about, to tell errors worth retrying or reporting from errors in the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The node failed or lacks the state asked for.
+    Rpc,
+    /// Code the analysis ran failed, e.g. a resolver reverted.
+    Execution,
+    /// What was read isn't what it should be, e.g. a slot holding more than an address.
+    Decoding,
+    /// The contract is of a kind the crate can't read.
+    NotSupported,
+    /// The request itself is wrong, e.g. an address without code or a block that doesn't exist.
+    User,
+}
+
+/// Why an RPC request failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcErrorKind {
+    /// The node answered that it limits the rate of requests, retried.
+    RateLimited,
+    /// No answer from the node, e.g. a timeout or a dropped connection, retried.
+    Transport,
+    /// The node answered with an error, e.g. a revert or pruned state, not retried.
+    Rejected,
+}
+
+impl RpcErrorKind {
+    pub fn is_retryable(&self) -> bool {
+	*self != RpcErrorKind::Rejected
+    }
+}
+
+/// An RPC request that failed, after `attempts` tries.
+#[derive(Clone, Debug, Error)]
+#[error("RPC error: `{message}`")]
+pub struct RpcError {
+    pub kind: RpcErrorKind,
+    pub attempts: u32,
+    /// The error response of the node, unless it didn't answer.
+    #[source]
+    pub response: Option<JsonRpcError>,
+    pub message: String,
+}
+
+impl RpcError {
+    /// The error of a request through an ethers middleware, after one attempt.
+    pub fn from_middleware<E: MiddlewareError>(error: &E) -> Self {
+	const RATE_LIMITED_CODES: &[i64] = &[429, -32005, -32029];
+	const RATE_LIMITED: &[&str] = &["rate limit", "too many requests", "exceeded its compute units"];
+	let response = error.as_error_response().cloned();
+	let message = error.to_string();
+	let lowercase = message.to_lowercase();
+	let kind = if response.as_ref().is_some_and(|r| RATE_LIMITED_CODES.contains(&r.code)) || RATE_LIMITED.iter().any(|m| lowercase.contains(m)) {
+	    RpcErrorKind::RateLimited
+	} else if response.is_some() {
+	    RpcErrorKind::Rejected
+	} else {
+	    RpcErrorKind::Transport
+	};
+	Self { kind, attempts: 1, response, message }
+    }
+}
+
+/// Serialized as an object tagged by `kind`, e.g. `{"kind":"single","implementation":"0x…"}`,
+/// facets as an object of facet addresses by selector, sorted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ImplementationRepr", into = "ImplementationRepr")]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ProxyImplementation {
     Single(Address),
     Multiple(Vec<Address>),
-    Facets(HashMap<Address, u32>)
+    /// The facet of each selector of a diamond.
+    Facets(HashMap<Selector, Address>)
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ImplementationRepr {
+    Single { implementation: Address },
+    Multiple { implementations: Vec<Address> },
+    Facets { facets: BTreeMap<Selector, Address> },
+}
+
+impl From<ProxyImplementation> for ImplementationRepr {
+    fn from(implementation: ProxyImplementation) -> Self {
+	match implementation {
+	    ProxyImplementation::Single(implementation) => ImplementationRepr::Single { implementation },
+	    ProxyImplementation::Multiple(implementations) => ImplementationRepr::Multiple { implementations },
+	    ProxyImplementation::Facets(facets) => ImplementationRepr::Facets { facets: facets.into_iter().collect() },
+	}
+    }
+}
+
+impl From<ImplementationRepr> for ProxyImplementation {
+    fn from(repr: ImplementationRepr) -> Self {
+	match repr {
+	    ImplementationRepr::Single { implementation } => ProxyImplementation::Single(implementation),
+	    ImplementationRepr::Multiple { implementations } => ProxyImplementation::Multiple(implementations),
+	    ImplementationRepr::Facets { facets } => ProxyImplementation::Facets(facets.into_iter().collect()),
+	}
+    }
 }
 
 impl ProxyImplementation {
+    /// Every implementation, facets once each and sorted.
     pub fn to_vec(&self) -> Vec<Address> {
         match self {
-            ProxyImplementation::Single(addr) => vec![addr.clone()],
+            ProxyImplementation::Single(addr) => vec![*addr],
             ProxyImplementation::Multiple(addrs) => addrs.to_owned(),
-            ProxyImplementation::Facets(addrs) => addrs.iter().map(|(k, _v)| k.clone()).collect(),
+            ProxyImplementation::Facets(facets) => {
+                let mut addrs: Vec<Address> = facets.values().copied().collect();
+                addrs.sort();
+                addrs.dedup();
+                addrs
+            },
         }
     }
 }
 
-// #[derive(EthAbiType)]
-// struct Facet {
-//     facetAddress: H160,
-//     functionSelectors: Vec<u32>
-// }
-
-abigen!(
-    IDiamondLoupe, r"[
-    struct Facet {address facetAddress; bytes4[] functionSelectors;}
-
-    function facets() external view returns (Facet[])
-]",
-);
+/// Reads the address stored in `storage` as of `block` (latest if `None`), which has to be the
+/// whole slot unless an `extraction` recipe says where it is packed. Retried as
+/// [ReadConfig::default] says.
+pub async fn read_single_storage_implementation<M>(rpc: &M, address: &Address, storage: &U256, extraction: Option<&SlotExtraction>, block: Option<BlockId>) -> Result<Address, ProxyReadError>
+    where M: StorageReader
+{
+    read_single_storage_implementation_with_config(rpc, address, storage, extraction, block, &ReadConfig::default()).await
+}
 
-pub async fn read_single_storage_implementation<M>(rpc: &M, address: &Address, storage: &U256) -> Result<Address, ProxyReadError>
-    where M: Middleware
+async fn read_single_storage_implementation_with_config<M>(rpc: &M, address: &Address, storage: &U256, extraction: Option<&SlotExtraction>, block: Option<BlockId>, config: &ReadConfig) -> Result<Address, ProxyReadError>
+    where M: StorageReader
 {
-    let h256_storage = ru256_to_h256_be(storage);
-    let h256_value = rpc.get_storage_at(raddress_to_h160(address), h256_storage, None).await.map_err(|e| ProxyReadError::RPCError(e.to_string()))?;
-    // let value = h256_to_u256_be(h256_value);
+    let value = with_retries(config, || rpc.storage_at(*address, *storage, block)).await?;
 
-    debug!("stored value:: {:?}", h256_value);
-    if (h256_value & *ADDR_MASK_H256) == h256_value {
-	let stored_address = h256_to_raddress_unchecked(&h256_value);
-	Ok(stored_address)
+    debug!("stored value:: {:?}", value);
+    if let Some(extraction) = extraction {
+	let value = extraction.apply(U256::from_be_bytes(value.0));
+	if value.bit_len() > 160 {
+	    return Err(ProxyReadError::StorageNotAddress);
+	}
+	Ok(Address::from_word(value.into()))
+    } else if value[..12].iter().all(|b| *b == 0) {
+	Ok(Address::from_word(value))
     } else {
 	Err(ProxyReadError::StorageNotAddress)
     }
 }
 
-pub async fn read_facet_list_from_function<M>(rpc: Arc<M>, address: &Address) -> Result<ProxyImplementation, ProxyReadError>
-where M: Middleware + 'static
+/// The implementation the beacon at `beacon` serves as of `block`, from its `implementation()`.
+pub async fn read_beacon_implementation<M>(rpc: &M, beacon: &Address, block: Option<BlockId>, config: &ReadConfig) -> Result<Address, ProxyReadError>
+    where M: StorageReader
 {
-    let address = raddress_to_h160(address);
-    let contract = IDiamondLoupe::new(address, rpc);
-    let facets = contract.facets().await.map_err(|e| ProxyReadError::RPCError(e.to_string()))?;
-    let facets_hashmap: HashMap<Address, u32> = facets.iter().map(|v| {
-	v.1.iter().map(|v1| (h160_to_b160(&v.0), as_u32_le(v1)))
-    }).flatten().collect();
-    Ok(ProxyImplementation::Facets(facets_hashmap))
+    let code = with_retries(config, || rpc.code_at(*beacon, block)).await?;
+    if code.is_empty() {
+	return Err(ProxyReadError::NoCode(*beacon));
+    }
+    let data = Bytes::from(ImplementationCall.encode());
+    let output = with_retries(config, || rpc.call_at(*beacon, data.clone(), block)).await?;
+    // Longer returns are accepted like abi.decode does, BeaconProxy itself only checks the first word
+    decode_address_return(&output, false).ok_or(ProxyReadError::BeaconNotAddress(*beacon))
 }
 
-pub async fn read_diamond_implementation<M>(_rpc: &M, _address: &Address, _diamond_base: &U256) -> Result<ProxyImplementation, ProxyReadError>
-    where M: Middleware
+/// The admin of the proxy at `address` as of `block` (latest if `None`), from the EIP-1967 admin
+/// slot or else the ZeppelinOS one. `None` if both are empty, as in UUPS proxies, which upgrade
+/// through their implementation.
+pub async fn get_proxy_admin<M>(rpc: &M, address: &Address, block: Option<BlockId>) -> Result<Option<Address>, ProxyReadError>
+    where M: StorageReader
 {
-    // TODO: implement properly
-    return Ok(ProxyImplementation::Multiple(Vec::new()))
-    // Scan storage to find the first array (should have its size)
+    for slot in [EIP_1967_ADMIN_SLOT, ZOS_ADMIN_SLOT] {
+	let admin = read_single_storage_implementation(rpc, address, &slot, None, block).await?;
+	if !admin.is_zero() {
+	    return Ok(Some(admin));
+	}
+    }
+    Ok(None)
+}
 
+/// Multicall3, deployed at this address on most chains.
+pub const MULTICALL3: Address = Address::new(hex_literal::hex!("ca11bde05977b3631167028862be2a173976ca11"));
 
-    // Go to the base of the array and get the structs
+/// How implementations are read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadConfig {
+    /// A Multicall3 to batch the calls of a read in `aggregate3`s, e.g. [MULTICALL3]. Calls are
+    /// made one by one if `None` or if it doesn't answer. Storage can't be read by contracts,
+    /// so slots are still read one by one.
+    pub multicall: Option<Address>,
+    /// Most calls per `aggregate3`.
+    pub max_batch: usize,
+    /// Times a request is retried when the node doesn't answer or limits the rate, none if
+    /// zero. Errors the node answers with, like reverts, aren't retried.
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each next one and again when rate limited.
+    pub retry_delay: Duration,
+    /// Add up to the wait again at random, so that concurrent reads don't retry in step.
+    pub retry_jitter: bool,
+}
 
+impl Default for ReadConfig {
+    fn default() -> Self {
+	Self { multicall: None, max_batch: 100, retries: 3, retry_delay: Duration::from_millis(250), retry_jitter: true }
+    }
+}
 
-    // For each struct read the arrays of function signatures
+impl ReadConfig {
+    /// The wait before retrying after `attempts` tries failed with `kind`.
+    fn backoff(&self, kind: RpcErrorKind, attempts: u32) -> Duration {
+	let factor = 1u32 << (attempts - 1 + u32::from(kind == RpcErrorKind::RateLimited)).min(16);
+	let delay = self.retry_delay.saturating_mul(factor);
+	if !self.retry_jitter || delay.is_zero() {
+	    return delay;
+	}
+	let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+	delay + delay.mul_f64(f64::from(nanos) / 1e9)
+    }
+}
+
+/// Makes the request of `request` until it succeeds, fails with an error the node answered, or
+/// `config.retries` retries failed.
+pub(crate) async fn with_retries<T, F, Fut>(config: &ReadConfig, mut request: F) -> Result<T, RpcError>
+where F: FnMut() -> Fut,
+      Fut: Future<Output = Result<T, RpcError>>
+{
+    let mut attempts = 0;
+    loop {
+	attempts += 1;
+	let error = match request().await {
+	    Ok(value) => return Ok(value),
+	    Err(e) => RpcError { attempts, ..e },
+	};
+	if !error.kind.is_retryable() || attempts > config.retries {
+	    return Err(error);
+	}
+	let delay = config.backoff(error.kind, attempts);
+	debug!("{:?} RPC error, retrying in {:?}: {}", error.kind, delay, error.message);
+	tokio::time::sleep(delay).await;
+    }
+}
+
+/// Calls `calls`, as targets and calldata, through `multicall` in batches of `config.max_batch`.
+/// `None` if an `aggregate3` fails or doesn't decode, e.g. without a multicall at the address.
+async fn aggregate3<M>(rpc: &M, multicall: &Address, calls: &[(Address, Vec<u8>)], block: Option<BlockId>, config: &ReadConfig) -> Option<Vec<Result<Bytes, String>>>
+where M: StorageReader
+{
+    let batches = calls.chunks(config.max_batch.max(1)).map(|batch| async move {
+	let calls = batch.iter().map(|(target, data)| Call3 { target: raddress_to_h160(target), allow_failure: true, call_data: data.clone().into() }).collect();
+	let output = call_loupe(rpc, multicall, Aggregate3Call { calls }.encode(), block, config).await.map_err(|e| debug!("aggregate3 failed: {}", e)).ok()?;
+	let results = Aggregate3Return::decode(&output).map_err(|e| debug!("aggregate3 doesn't decode: {}", e)).ok()?.return_data;
+	(results.len() == batch.len()).then_some(results)
+    });
+    let mut outputs = Vec::with_capacity(calls.len());
+    for results in join_all(batches).await {
+	outputs.extend(results?.into_iter().map(|(success, output)| if success { Ok(Bytes(output.0)) } else { Err("execution reverted".to_string()) }));
+    }
+    Some(outputs)
+}
+
+/// Makes `calls`, as targets and calldata, batched through the multicall of `config` if it
+/// has one, one by one otherwise.
+async fn call_all<M>(rpc: &M, calls: &[(Address, Vec<u8>)], config: &ReadConfig, block: Option<BlockId>) -> Vec<Result<Bytes, String>>
+where M: StorageReader
+{
+    if let Some(multicall) = &config.multicall {
+	if let Some(outputs) = aggregate3(rpc, multicall, calls, block, config).await {
+	    return outputs;
+	}
+    }
+    join_all(calls.iter().map(|(target, data)| call_loupe(rpc, target, data.clone(), block, config))).await
+}
+
+/// The facets of the diamond at `address` from its loupe, `facets()` and, if it fails or lists
+/// none, `facetAddresses()` and `facetFunctionSelectors(facet)`: some loupes only have those,
+/// others a `facets()` running out of gas with hundreds of selectors.
+pub async fn read_facet_list_from_function<M>(rpc: Arc<M>, address: &Address, block: Option<BlockId>, config: &ReadConfig) -> Result<ProxyImplementation, ProxyReadError>
+where M: StorageReader + 'static
+{
+    let facets = read_facets_with_config(rpc.as_ref(), address, block, LoupeDecoding::Strict, config).await;
+    if matches!(&facets, Ok(facets) if !facets.facets.is_empty()) {
+	return Ok(facets?.into_implementation());
+    }
+    match read_facets_granular(rpc.as_ref(), address, block, config).await {
+	Some(granular) => Ok(ProxyImplementation::Facets(granular)),
+	None => Ok(facets?.into_implementation()),
+    }
+}
+
+async fn call_loupe<M>(rpc: &M, address: &Address, data: Vec<u8>, block: Option<BlockId>, config: &ReadConfig) -> Result<Bytes, String>
+where M: StorageReader
+{
+    let data = Bytes::from(data);
+    with_retries(config, || rpc.call_at(*address, data.clone(), block)).await.map_err(|e| e.message)
+}
+
+/// The facet of each selector from `facetAddresses()` and `facetFunctionSelectors(facet)`, all
+/// facets queried at once, batched as `config` allows. Facets whose selectors can't be read
+/// are left out with a warning, `None` if the facet addresses can't be.
+async fn read_facets_granular<M>(rpc: &M, address: &Address, block: Option<BlockId>, config: &ReadConfig) -> Option<HashMap<Selector, Address>>
+where M: StorageReader
+{
+    let output = call_loupe(rpc, address, FacetAddressesCall.encode(), block, config).await.map_err(|e| debug!("facetAddresses() failed: {}", e)).ok()?;
+    let facets = FacetAddressesReturn::decode(&output).map_err(|e| debug!("facetAddresses() doesn't decode: {}", e)).ok()?.0;
+    let calls: Vec<(Address, Vec<u8>)> = facets.iter().map(|facet| (*address, FacetFunctionSelectorsCall { facet: *facet }.encode())).collect();
+    let outputs = call_all(rpc, &calls, config, block).await;
+    let mut facet_of = HashMap::new();
+    for (facet, output) in facets.iter().zip(outputs) {
+	let facet = h160_to_b160(facet);
+	match output.and_then(|output| FacetFunctionSelectorsReturn::decode(&output).map_err(|e| e.to_string())) {
+	    Ok(selectors) => facet_of.extend(selectors.0.into_iter().map(|selector| (Selector::new(selector), facet))),
+	    Err(e) => warn!("facet {} of {} left out, facetFunctionSelectors() failed: {}", facet, address, e),
+	}
+    }
+    Some(facet_of)
+}
+
+/// The facets the loupe of the diamond at `address` returns from `facets()`, decoded as
+/// `decoding` allows. Retried as [ReadConfig::default] says.
+pub async fn read_facets<M>(rpc: &M, address: &Address, block: Option<BlockId>, decoding: LoupeDecoding) -> Result<LoupeFacets, ProxyReadError>
+where M: StorageReader
+{
+    read_facets_with_config(rpc, address, block, decoding, &ReadConfig::default()).await
+}
+
+async fn read_facets_with_config<M>(rpc: &M, address: &Address, block: Option<BlockId>, decoding: LoupeDecoding, config: &ReadConfig) -> Result<LoupeFacets, ProxyReadError>
+where M: StorageReader
+{
+    let data = Bytes::from(FacetsCall.encode());
+    let output = with_retries(config, || rpc.call_at(*address, data.clone(), block)).await?;
+    Ok(decode_facets(&output, decoding)?)
+}
+
+/// A storage word of `address` as of `block`.
+async fn read_storage_word<M>(rpc: &M, address: &Address, slot: U256, block: Option<BlockId>, config: &ReadConfig) -> Result<U256, ProxyReadError>
+    where M: StorageReader
+{
+    let word = with_retries(config, || rpc.storage_at(*address, slot, block)).await?;
+    Ok(U256::from_be_bytes(word.0))
+}
+
+/// The slot of the entry of `key`, padded to a word, in the mapping at `slot`.
+fn mapping_slot(key: B256, slot: U256) -> U256 {
+    U256::from_be_bytes(keccak256([key.0, slot.to_be_bytes::<32>()].concat()).0)
+}
+
+/// The slot of the first element of the dynamic array at `slot`.
+fn array_data_slot(slot: U256) -> U256 {
+    U256::from_be_bytes(keccak256(slot.to_be_bytes::<32>()).0)
+}
+
+/// The `bytes4[]` at `slot`, packed 8 to a word from its low bytes. `None` if it claims more
+/// than `max` elements.
+async fn read_selector_array<M>(rpc: &M, address: &Address, slot: U256, max: usize, block: Option<BlockId>, config: &ReadConfig) -> Result<Option<Vec<Selector>>, ProxyReadError>
+    where M: StorageReader
+{
+    let len = read_storage_word(rpc, address, slot, block, config).await?;
+    if len > U256::from(max) {
+	return Ok(None);
+    }
+    let len = len.to::<usize>();
+    let data = array_data_slot(slot);
+    let words = try_join_all((0..len.div_ceil(8)).map(|i| read_storage_word(rpc, address, data + U256::from(i), block, config))).await?;
+    Ok(Some((0..len).map(|i| {
+	let word = words[i / 8].to_be_bytes::<32>();
+	let end = 32 - 4 * (i % 8);
+	Selector::from_slice(&word[end - 4..end]).expect("4 bytes")
+    }).collect()))
+}
+
+/// The reference LibDiamond layout: `selectors` one slot past the base, the facet of each and
+/// its position in `selectors` packed in the mapping at the base. `None` if they disagree.
+async fn read_selectors_layout<M>(rpc: &M, address: &Address, base: U256, block: Option<BlockId>, config: &ReadConfig) -> Result<Option<HashMap<Selector, Address>>, ProxyReadError>
+    where M: StorageReader
+{
+    let Some(selectors) = read_selector_array(rpc, address, base + U256::from(1), MAX_LOUPE_SELECTORS, block, config).await? else { return Ok(None) };
+    let entries = try_join_all(selectors.iter().map(|selector| {
+	let key = B256::right_padding_from(selector.as_bytes());
+	read_storage_word(rpc, address, mapping_slot(key, base), block, config)
+    })).await?;
+    let mut facets = HashMap::new();
+    for (position, (selector, entry)) in selectors.into_iter().zip(entries).enumerate() {
+	let facet = Address::from_word(entry.into());
+	if facet == Address::ZERO || entry >> 160 != U256::from(position) {
+	    return Ok(None);
+	}
+	facets.insert(selector, facet);
+    }
+    Ok(Some(facets))
+}
+
+/// The layout keeping `facetAddresses` two slots past the base, and the selectors of each facet
+/// with its position in `facetAddresses` in the mapping one slot past the base. `None` if they
+/// disagree.
+async fn read_facet_addresses_layout<M>(rpc: &M, address: &Address, base: U256, block: Option<BlockId>, config: &ReadConfig) -> Result<Option<HashMap<Selector, Address>>, ProxyReadError>
+    where M: StorageReader
+{
+    let slot = base + U256::from(2);
+    let len = read_storage_word(rpc, address, slot, block, config).await?;
+    if len > U256::from(MAX_LOUPE_FACETS) {
+	return Ok(None);
+    }
+    let data = array_data_slot(slot);
+    let words = try_join_all((0..len.to::<usize>()).map(|i| read_storage_word(rpc, address, data + U256::from(i), block, config))).await?;
+    let facets = try_join_all(words.into_iter().enumerate().map(|(position, word)| async move {
+	let facet = Address::from_word(word.into());
+	if facet == Address::ZERO || word >> 160 != U256::ZERO {
+	    return Ok(None);
+	}
+	let entry = mapping_slot(B256::left_padding_from(facet.as_slice()), base + U256::from(1));
+	let (selectors, stored_position) = futures::try_join!(
+	    read_selector_array(rpc, address, entry, MAX_LOUPE_SELECTORS, block, config),
+	    read_storage_word(rpc, address, entry + U256::from(1), block, config),
+	)?;
+	Ok::<_, ProxyReadError>(selectors.filter(|_| stored_position == U256::from(position)).map(|selectors| (facet, selectors)))
+    })).await?;
+    let Some(facets) = facets.into_iter().collect::<Option<Vec<_>>>() else { return Ok(None) };
+    Ok(Some(facets.into_iter().flat_map(|(facet, selectors)| selectors.into_iter().map(move |selector| (selector, facet))).collect()))
+}
+
+/// The facet of each selector of the diamond at `address` read from its storage, laid out by
+/// LibDiamond from `diamond_base`, as of `block`. Both layouts of the reference
+/// implementations are tried, an empty `selectors` array meaning the second one.
+pub async fn read_diamond_implementation<M>(rpc: &M, address: &Address, diamond_base: &U256, block: Option<BlockId>, config: &ReadConfig) -> Result<ProxyImplementation, ProxyReadError>
+    where M: StorageReader
+{
+    if let Some(facets) = read_selectors_layout(rpc, address, *diamond_base, block, config).await?.filter(|facets| !facets.is_empty()) {
+	return Ok(ProxyImplementation::Facets(facets));
+    }
+    match read_facet_addresses_layout(rpc, address, *diamond_base, block, config).await? {
+	Some(facets) => Ok(ProxyImplementation::Facets(facets)),
+	None => Err(ProxyReadError::UnknownDiamondLayout),
+    }
+}
+
+/// Calls `selector` on `resolver`, an external resolver of a proxy, as of `block`.
+async fn call_resolver<M>(rpc: &M, resolver: &Address, selector: Selector, block: Option<BlockId>, config: &ReadConfig) -> Result<Bytes, ProxyReadError>
+    where M: StorageReader
+{
+    let data = Bytes::copy_from_slice(selector.as_bytes());
+    match with_retries(config, || rpc.call_at(*resolver, data.clone(), block)).await {
+	Ok(output) => Ok(output),
+	Err(e) => match e.response.as_ref().and_then(outcome_of_error) {
+	    Some(outcome) => Err(ProxyReadError::ResolverFailed(*resolver, selector, outcome)),
+	    None => Err(e.into()),
+	},
+    }
+}
+
+/// The implementation `resolver` answers `selector` with as of `block`, an address like
+/// beacons do. Resolvers answering `facetAddress(bytes4)`, which needs the selector called on the
+/// proxy, are asked for all their facets with `facetAddresses()` instead.
+async fn read_external_implementation<M>(rpc: &M, resolver: &Address, selector: Selector, block: Option<BlockId>, config: &ReadConfig) -> Result<ProxyImplementation, ProxyReadError>
+    where M: StorageReader
+{
+    if selector == DIAMOND_FACET_ADDRESS_SELECTOR {
+	let selector = Selector::new(FacetAddressesCall::selector());
+	let output = call_resolver(rpc, resolver, selector, block, config).await?;
+	let facets = FacetAddressesReturn::decode(&output).map_err(|_| ProxyReadError::ResolverNotAddress(*resolver, selector, output))?.0;
+	return Ok(ProxyImplementation::Multiple(facets.iter().map(h160_to_b160).collect()));
+    }
+    let output = call_resolver(rpc, resolver, selector, block, config).await?;
+    // Longer returns are accepted like for beacons
+    match decode_address_return(&output, false) {
+	Some(implementation) => Ok(ProxyImplementation::Single(implementation)),
+	None => Err(ProxyReadError::ResolverNotAddress(*resolver, selector, output)),
+    }
 }
 
-#[async_recursion]
 pub async fn get_proxy_implementation<M>(rpc: Arc<M>, address: &Address, proxy_dispatch: &ProxyDispatch) -> Result<ProxyImplementation, ProxyReadError>
-    where M: Middleware + 'static
+    where M: StorageReader + 'static
+{
+    get_proxy_implementation_at(rpc, address, proxy_dispatch, None).await
+}
+
+/// [get_proxy_implementation] as of `block` (latest if `None`).
+pub async fn get_proxy_implementation_at<M>(rpc: Arc<M>, address: &Address, proxy_dispatch: &ProxyDispatch, block: Option<BlockId>) -> Result<ProxyImplementation, ProxyReadError>
+    where M: StorageReader + 'static
+{
+    get_proxy_implementation_with_config(rpc, address, proxy_dispatch, block, &ReadConfig::default()).await
+}
+
+/// [get_proxy_implementation_at] reading as `config` says, e.g. batching the loupe calls of
+/// diamonds in a multicall.
+#[async_recursion]
+pub async fn get_proxy_implementation_with_config<M>(rpc: Arc<M>, address: &Address, proxy_dispatch: &ProxyDispatch, block: Option<BlockId>, config: &ReadConfig) -> Result<ProxyImplementation, ProxyReadError>
+    where M: StorageReader + 'static
 {
     match proxy_dispatch {
         ProxyDispatch::Unknown => Err(ProxyReadError::UnknownProxy),
-        ProxyDispatch::Storage(slot) => Ok(ProxyImplementation::Single(read_single_storage_implementation(&rpc, address, slot).await?)),
+        ProxyDispatch::Storage(slot, extraction) => Ok(ProxyImplementation::Single(read_single_storage_implementation_with_config(rpc.as_ref(), address, slot, extraction.as_ref(), block, config).await?)),
+        ProxyDispatch::Beacon(slot) => {
+	    let beacon = read_single_storage_implementation_with_config(rpc.as_ref(), address, slot, None, block, config).await?;
+	    Ok(ProxyImplementation::Single(read_beacon_implementation(rpc.as_ref(), &beacon, block, config).await?))
+	},
         ProxyDispatch::MultipleStorage(slots) => {
-	    let addrs: Result<Vec<Address>, ProxyReadError> = join_all(slots.iter().map(|s| async { read_single_storage_implementation(&rpc, address, s).await })).await.into_iter().collect();
+	    let addrs: Result<Vec<Address>, ProxyReadError> = join_all(slots.iter().map(|s| async { read_single_storage_implementation_with_config(rpc.as_ref(), address, s, None, block, config).await })).await.into_iter().collect();
 	    Ok(ProxyImplementation::Multiple(addrs?))
 	},
-        ProxyDispatch::Static(address) => Ok(ProxyImplementation::Single(address.clone())),
-        ProxyDispatch::Facet_EIP_2535 => { Ok(read_facet_list_from_function(rpc, address).await?) },
-        ProxyDispatch::FacetStorageSlot => Ok(read_diamond_implementation(&rpc, address, &DIAMOND_STANDARD_STORAGE_SLOT).await?),
-        ProxyDispatch::External(_, _) => Err(ProxyReadError::ExternalProxy)
-        // ProxyDispatch::External(address, dispatch) => Ok(get_proxy_implementation(rpc, address, dispatch).await?),
+        ProxyDispatch::Static(address) | ProxyDispatch::StaticWithArgs(address, _) => Ok(ProxyImplementation::Single(*address)),
+        ProxyDispatch::Facet_EIP_2535 => { Ok(read_facet_list_from_function(rpc, address, block, config).await?) },
+        ProxyDispatch::FacetStorageSlot => Ok(read_diamond_implementation(rpc.as_ref(), address, &DIAMOND_STANDARD_STORAGE_SLOT, block, config).await?),
+        ProxyDispatch::PerSelector(table) => {
+	    let mut addrs: Vec<Address> = Vec::new();
+	    for (_, implementation) in table {
+		if !addrs.contains(implementation) {
+		    addrs.push(*implementation);
+		}
+	    }
+	    Ok(ProxyImplementation::Multiple(addrs))
+	},
+        ProxyDispatch::External(resolver, selector) => read_external_implementation(rpc.as_ref(), resolver, *selector, block, config).await,
+    }
+}
+
+/// An implementation of a proxy and its code, see [verify_implementation].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedImplementation {
+    pub address: Address,
+    /// Whether there's code at `address`, not if it's an account or was selfdestructed.
+    pub has_code: bool,
+    /// The keccak256 of the code, that of no code without any.
+    pub code_hash: B256,
+}
+
+/// The code of each address of `implementation`, once, as of `block` (latest if `None`), to
+/// tell an implementation that isn't a contract, e.g. an account set by a botched upgrade.
+/// Fails with [ProxyReadError::UninitializedProxy] if one is the zero address.
+pub async fn verify_implementation<M>(rpc: &M, implementation: &ProxyImplementation, block: Option<BlockId>, config: &ReadConfig) -> Result<Vec<ResolvedImplementation>, ProxyReadError>
+    where M: StorageReader
+{
+    let mut addresses = implementation.to_vec();
+    let mut seen = HashSet::new();
+    addresses.retain(|address| seen.insert(*address));
+    if addresses.contains(&Address::ZERO) {
+	return Err(ProxyReadError::UninitializedProxy);
+    }
+    try_join_all(addresses.into_iter().map(|address| async move {
+	let code = with_retries(config, || rpc.code_at(address, block)).await?;
+	Ok::<_, ProxyReadError>(ResolvedImplementation { address, has_code: !code.is_empty(), code_hash: keccak256(&code) })
+    })).await
+}
+
+impl ProxyDispatch {
+    /// The implementation the proxy at `proxy` delegates to as of `block` (latest if `None`),
+    /// see [get_proxy_implementation_at]. Static dispatches need no RPC, those with several
+    /// implementations fail with [ProxyReadError::MultipleImplementations].
+    ///
+    /// ```no_run
+    /// # async fn run() -> Result<(), evm_proxy_tools::ProxyReadError> {
+    /// use std::sync::Arc;
+    /// use ethers_providers::{Http, Middleware, Provider};
+    /// use evm_proxy_tools::{detect_proxy, utils::raddress_to_h160, DetectorConfig};
+    ///
+    /// let rpc = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+    /// let proxy = "0xdac17f958d2ee523a2206206994597c13d831ec7".parse().unwrap();
+    /// let code = rpc.get_code(raddress_to_h160(&proxy), None).await.unwrap();
+    /// if let Some(detection) = detect_proxy(&code, &DetectorConfig::default()) {
+    ///     let implementation = detection.dispatch.get_implementation(rpc, &proxy, None).await?;
+    ///     println!("{} is a {} of {}", proxy, detection.proxy_type, implementation);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_implementation<M>(&self, rpc: Arc<M>, proxy: &Address, block: Option<BlockId>) -> Result<Address, ProxyReadError>
+        where M: StorageReader + 'static
+    {
+        match self {
+            ProxyDispatch::MultipleStorage(_) | ProxyDispatch::PerSelector(_) | ProxyDispatch::Facet_EIP_2535 | ProxyDispatch::FacetStorageSlot => Err(ProxyReadError::MultipleImplementations),
+            _ => match get_proxy_implementation_at(rpc, proxy, self, block).await? {
+                ProxyImplementation::Single(implementation) => Ok(implementation),
+                ProxyImplementation::Multiple(_) | ProxyImplementation::Facets(_) => Err(ProxyReadError::MultipleImplementations),
+            },
+        }
+    }
+
+    /// Every implementation the proxy at `proxy` delegates to as of `block`, in the order of
+    /// the dispatch's slots, see [get_proxy_implementation_at].
+    pub async fn get_implementations<M>(&self, rpc: Arc<M>, proxy: &Address, block: Option<BlockId>) -> Result<Vec<Address>, ProxyReadError>
+        where M: StorageReader + 'static
+    {
+        Ok(get_proxy_implementation_at(rpc, proxy, self, block).await?.to_vec())
+    }
+}
+
+/// The implementation a proxy reports through its own getter, next to the one read from its
+/// dispatch slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelfReport {
+    pub selector: Selector,
+    pub slot_value: Address,
+    pub getter_value: Address,
+}
+
+impl SelfReport {
+    pub fn agrees(&self) -> bool {
+	self.slot_value == self.getter_value
+    }
+
+    /// A [Finding::SelfReportMismatch] if the two sources disagree.
+    pub fn finding(&self) -> Option<Finding> {
+	(!self.agrees()).then_some(Finding::SelfReportMismatch { slot_value: self.slot_value, getter_value: self.getter_value })
+    }
+}
+
+/// Calls the self-report getter `selector` on `address`, `None` if it reverts or doesn't
+/// return an address.
+async fn call_self_report<M>(rpc: &M, address: &Address, selector: Selector, block: Option<BlockId>) -> Option<Address>
+    where M: Middleware
+{
+    let tx = TransactionRequest::new().to(raddress_to_h160(address)).data(selector.as_bytes().to_vec());
+    let output = rpc.call(&tx.into(), block).await.map_err(|e| debug!("getter 0x{:08x} failed: {}", selector, e)).ok()?;
+    decode_address_return(&output, true)
+}
+
+/// Cross-checks `slot_value`, the implementation read from the proxy's dispatch, against the
+/// proxy's own getter (`implementation()`, `masterCopy()`, ...).
+///
+/// Getters typical of `proxy_type` are tried first, the first one answering with an address is
+/// used. `None` if the proxy answers none of them.
+pub async fn check_self_report<M>(rpc: &M, address: &Address, proxy_type: ProxyType, slot_value: Address) -> Option<SelfReport>
+    where M: Middleware
+{
+    check_self_report_at(rpc, address, proxy_type, slot_value, None).await
+}
+
+/// [check_self_report], calling the getters at `block`.
+pub async fn check_self_report_at<M>(rpc: &M, address: &Address, proxy_type: ProxyType, slot_value: Address, block: Option<BlockId>) -> Option<SelfReport>
+    where M: Middleware
+{
+    let mut getters: Vec<(Selector, ProxyType)> = SELF_REPORT_GETTERS.clone();
+    getters.sort_by_key(|(_, family)| *family != proxy_type);
+    for (selector, _) in getters {
+	if let Some(getter_value) = call_self_report(rpc, address, selector, block).await {
+	    return Some(SelfReport { selector, slot_value, getter_value });
+	}
+    }
+    None
+}
+
+/// Default amount of RPC calls a bounded block search is allowed to issue.
+pub const DEFAULT_SEARCH_BUDGET: usize = 64;
+
+pub(crate) fn is_missing_state_error(msg: &str) -> bool {
+    const MISSING_STATE: &[&str] = &["missing trie node", "header not found", "historical state", "state is not available", "pruned"];
+    let msg = msg.to_lowercase();
+    MISSING_STATE.iter().any(|m| msg.contains(m))
+}
+
+/// Finds the first block in `[low, high]` for which `predicate` holds, assuming the predicate is
+/// monotonic (false up to some block and true afterwards). Returns `None` if it doesn't hold at
+/// `high`. Every evaluation counts against `budget`.
+pub async fn find_first_block<F, Fut>(low: u64, high: u64, budget: usize, predicate: F) -> Result<Option<u64>, ProxyReadError>
+    where F: FnMut(u64) -> Fut,
+	  Fut: Future<Output = Result<bool, ProxyReadError>>
+{
+    find_first_block_with_progress(low, high, budget, predicate, &NoProgress, "search").await
+}
+
+/// The evaluations [find_first_block] makes at most over `[low, high]`.
+fn search_probes(low: u64, high: u64, budget: usize) -> u64 {
+    let range = high.saturating_sub(low) as u128 + 1;
+    let halvings = 128 - (range - 1).leading_zeros() as u64;
+    (halvings + 1).min(budget as u64)
+}
+
+/// [find_first_block] reporting every evaluation to `progress` under `stage`.
+pub async fn find_first_block_with_progress<F, Fut>(low: u64, high: u64, budget: usize, mut predicate: F, progress: &dyn ProgressReporter, stage: &str) -> Result<Option<u64>, ProxyReadError>
+    where F: FnMut(u64) -> Fut,
+	  Fut: Future<Output = Result<bool, ProxyReadError>>
+{
+    // Each evaluation is an RPC round trip, reporting all of them costs nothing in comparison
+    let mut emitter = ProgressEmitter::with_throttle(progress, stage, Some(search_probes(low, high, budget)), Throttle::every_item());
+    let mut calls = 0;
+    let mut check = |block: u64| {
+	calls += 1;
+	let over_budget = calls > budget;
+	if !over_budget {
+	    emitter.advance();
+	}
+	let fut = predicate(block);
+	async move {
+	    if over_budget {
+		Err(ProxyReadError::SearchBudgetExhausted(budget))
+	    } else {
+		fut.await
+	    }
+	}
+    };
+
+    if low > high || !check(high).await? {
+	return Ok(None);
+    }
+    let (mut low, mut high) = (low, high);
+    while low < high {
+	let mid = low + (high - low) / 2;
+	if check(mid).await? {
+	    high = mid;
+	} else {
+	    low = mid + 1;
+	}
+    }
+    Ok(Some(low))
+}
+
+/// Returns whether `address` had code at `block`.
+pub async fn has_code_at<M>(rpc: &M, address: &Address, block: u64) -> Result<bool, ProxyReadError>
+    where M: Middleware
+{
+    Ok(!get_code_at(rpc, address, block).await?.is_empty())
+}
+
+/// The code of `address` at `block`, telling pruned state apart from other RPC errors.
+pub async fn get_code_at<M>(rpc: &M, address: &Address, block: u64) -> Result<ethers_core::types::Bytes, ProxyReadError>
+    where M: Middleware
+{
+    match rpc.get_code(raddress_to_h160(address), Some(BlockId::from(block))).await {
+	Ok(code) => Ok(code),
+	Err(e) if is_missing_state_error(&e.to_string()) => Err(ProxyReadError::HistoricalStateUnavailable(block)),
+	Err(e) => Err(ProxyReadError::from_middleware(e)),
+    }
+}
+
+/// Estimates the block in which `address` got its code by binary searching `eth_getCode` over
+/// `[0, head]`. Returns `None` if there is no code at `head`.
+pub async fn find_deploy_block<M>(rpc: &M, address: &Address, head: u64, budget: usize) -> Result<Option<u64>, ProxyReadError>
+    where M: Middleware
+{
+    find_deploy_block_with_progress(rpc, address, head, budget, &NoProgress).await
+}
+
+/// [find_deploy_block] reporting its probes to `progress` under the `deploy-block` stage.
+pub async fn find_deploy_block_with_progress<M>(rpc: &M, address: &Address, head: u64, budget: usize, progress: &dyn ProgressReporter) -> Result<Option<u64>, ProxyReadError>
+    where M: Middleware
+{
+    find_first_block_with_progress(0, head, budget, |block| has_code_at(rpc, address, block), progress, "deploy-block").await
+}
+
+/// How recently the implementation was deployed relative to the proxy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyFreshness {
+    pub head_block: u64,
+    pub proxy_deploy_block: u64,
+    pub impl_deploy_block: u64,
+    pub impl_newer_than_proxy: bool,
+}
+
+impl ProxyFreshness {
+    /// True if the implementation was deployed after the proxy, by less than `window` blocks.
+    pub fn is_recent_swap(&self, window: u64) -> bool {
+	self.impl_newer_than_proxy && self.impl_deploy_block.saturating_sub(self.proxy_deploy_block) < window
+    }
+
+    /// A [Finding::RecentImplementation] if [is_recent_swap](Self::is_recent_swap).
+    pub fn finding(&self, window: u64) -> Option<Finding> {
+	self.is_recent_swap(window).then_some(Finding::RecentImplementation { proxy_deploy_block: self.proxy_deploy_block, impl_deploy_block: self.impl_deploy_block })
+    }
+}
+
+/// The number of `block` (latest if `None`), asking the node for tags like `finalized` and
+/// hashes.
+pub async fn resolve_block_number<M>(rpc: &M, block: Option<BlockId>) -> Result<u64, ProxyReadError>
+    where M: Middleware
+{
+    match block {
+	Some(BlockId::Number(BlockNumber::Number(number))) => Ok(number.as_u64()),
+	Some(block) => rpc.get_block(block).await.map_err(ProxyReadError::from_middleware)?
+	    .and_then(|block| block.number)
+	    .map(|number| number.as_u64())
+	    .ok_or(ProxyReadError::BlockNotFound(block)),
+	None => Ok(rpc.get_block_number().await.map_err(ProxyReadError::from_middleware)?.as_u64()),
+    }
+}
+
+/// Computes the [ProxyFreshness] of `proxy` pointing to `implementation` as seen at `block`
+/// (latest if `None`). `budget` bounds the RPC calls of each of the two searches.
+pub async fn get_proxy_freshness<M>(rpc: &M, proxy: &Address, implementation: &Address, block: Option<BlockId>, budget: usize) -> Result<ProxyFreshness, ProxyReadError>
+    where M: Middleware
+{
+    get_proxy_freshness_with_progress(rpc, proxy, implementation, block, budget, &NoProgress).await
+}
+
+/// [get_proxy_freshness] reporting the probes of the searches to `progress`, under the
+/// `proxy-deploy-block` and `impl-deploy-block` stages.
+pub async fn get_proxy_freshness_with_progress<M>(rpc: &M, proxy: &Address, implementation: &Address, block: Option<BlockId>, budget: usize, progress: &dyn ProgressReporter) -> Result<ProxyFreshness, ProxyReadError>
+    where M: Middleware
+{
+    let head_block = resolve_block_number(rpc, block).await?;
+    let proxy_deploy_block = find_first_block_with_progress(0, head_block, budget, |block| has_code_at(rpc, proxy, block), progress, "proxy-deploy-block").await?.ok_or(ProxyReadError::NoCode(*proxy))?;
+    let impl_deploy_block = find_first_block_with_progress(0, head_block, budget, |block| has_code_at(rpc, implementation, block), progress, "impl-deploy-block").await?.ok_or(ProxyReadError::NoCode(*implementation))?;
+    Ok(ProxyFreshness {
+	head_block,
+	proxy_deploy_block,
+	impl_deploy_block,
+	impl_newer_than_proxy: impl_deploy_block > proxy_deploy_block
+    })
+}
+
+/// The implementation of the proxy at `block`, `None` before it had one.
+async fn implementation_at<M>(rpc: Arc<M>, address: &Address, dispatch: &ProxyDispatch, block: u64) -> Result<Option<Address>, ProxyReadError>
+    where M: Middleware + 'static
+{
+    match dispatch.get_implementation(rpc, address, Some(block.into())).await {
+	Ok(implementation) => Ok((implementation != Address::ZERO).then_some(implementation)),
+	// Before the proxy or its beacon existed
+	Err(ProxyReadError::NoCode(_) | ProxyReadError::BeaconNotAddress(_) | ProxyReadError::StorageNotAddress) => Ok(None),
+	Err(e) if e.is_missing_state() => Err(ProxyReadError::HistoricalStateUnavailable(block)),
+	Err(e) => Err(e),
+    }
+}
+
+/// The implementations the proxy at `address` pointed to in `[from_block, to_block]`, each with
+/// the block it was installed in (`from_block` for the one it started with), in order.
+///
+/// The implementation is read every `step` blocks, and binary searched between two reads that
+/// differ to find the exact block of each change. An upgrade undone within `step` blocks is
+/// missed, except at EIP-1967 proxies whose `Upgraded` logs are scanned too. Only dispatches
+/// with a single implementation are supported, others fail with
+/// [ProxyReadError::MultipleImplementations].
+pub async fn get_implementation_history<M>(rpc: Arc<M>, address: &Address, dispatch: &ProxyDispatch, from_block: u64, to_block: u64, step: u64) -> Result<Vec<(u64, Address)>, ProxyReadError>
+    where M: Middleware + 'static
+{
+    get_implementation_history_with_progress(rpc, address, dispatch, from_block, to_block, step, &NoProgress).await
+}
+
+/// [get_implementation_history] reporting to `progress`: the `Upgraded` log chunks under the
+/// `upgrade-logs` stage, then every `step` blocks read and searched under `history`.
+pub async fn get_implementation_history_with_progress<M>(rpc: Arc<M>, address: &Address, dispatch: &ProxyDispatch, from_block: u64, to_block: u64, step: u64, progress: &dyn ProgressReporter) -> Result<Vec<(u64, Address)>, ProxyReadError>
+    where M: Middleware + 'static
+{
+    let mut changes: Vec<(u64, Option<Address>)> = Vec::new();
+    // Logged upgrades go before the reads, which see the last implementation of their block
+    if matches!(dispatch, ProxyDispatch::Storage(slot, None) if *slot == EIP_1967_IMPLEMENTATION_SLOT) {
+	let filter = Filter::new().address(raddress_to_h160(address)).topic0(UpgradedFilter::signature());
+	for log in get_logs_chunked(rpc.as_ref(), &filter, from_block, to_block, &ReadConfig::default(), progress).await? {
+	    if let Some(UpgradeEvent::Upgraded { block, implementation, .. }) = UpgradeEvent::from_log(&log) {
+		changes.push((block, Some(implementation)));
+	    }
+	}
+    }
+
+    let mut current = implementation_at(rpc.clone(), address, dispatch, from_block).await?;
+    changes.push((from_block, current));
+    let windows = to_block.saturating_sub(from_block).div_ceil(step.max(1));
+    let mut emitter = ProgressEmitter::with_throttle(progress, "history", Some(windows), Throttle::every_item());
+    let mut block = from_block;
+    while block < to_block {
+	let next = block.saturating_add(step.max(1)).min(to_block);
+	// Every search finds the next change up to `next`, none once it holds what `next` does
+	let mut low = block + 1;
+	while let Some(changed) = find_first_block(low, next, DEFAULT_SEARCH_BUDGET, |block| {
+	    let rpc = rpc.clone();
+	    async move { Ok(implementation_at(rpc, address, dispatch, block).await? != current) }
+	}).await? {
+	    current = implementation_at(rpc.clone(), address, dispatch, changed).await?;
+	    changes.push((changed, current));
+	    low = changed + 1;
+	}
+	block = next;
+	emitter.advance();
+    }
+
+    changes.sort_by_key(|(block, _)| *block);
+    let mut history: Vec<(u64, Address)> = Vec::new();
+    for (block, implementation) in changes {
+	if let Some(implementation) = implementation.filter(|implementation| history.last().map(|(_, last)| last) != Some(implementation)) {
+	    history.push((block, implementation));
+	}
     }
+    Ok(history)
 }
@@ -0,0 +1,46 @@
+//! The node reads implementations are resolved with, behind [StorageReader] so that clients
+//! other than ethers can serve them with alloy types.
+
+use std::future::Future;
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use ethers_core::types::{BlockId, TransactionRequest};
+use ethers_providers::Middleware;
+
+use crate::read::RpcError;
+use crate::utils::{h256_to_b256, raddress_to_h160, ru256_to_h256_be};
+
+/// Storage, code and calls of accounts as of a block (latest if `None`). Implemented for every
+/// ethers [Middleware].
+///
+/// Errors the node answered with have their [RpcError::response], with `kind`
+/// [Rejected](crate::RpcErrorKind::Rejected) unless they limit the rate, and are retried as
+/// the [ReadConfig](crate::ReadConfig) of the read says otherwise.
+pub trait StorageReader: Send + Sync {
+    /// The word at `slot` of `address`.
+    fn storage_at(&self, address: Address, slot: U256, block: Option<BlockId>) -> impl Future<Output = Result<B256, RpcError>> + Send;
+
+    /// The code of `address`, empty for accounts.
+    fn code_at(&self, address: Address, block: Option<BlockId>) -> impl Future<Output = Result<Bytes, RpcError>> + Send;
+
+    /// What calling `to` with `data` returns, reverts are errors.
+    fn call_at(&self, to: Address, data: Bytes, block: Option<BlockId>) -> impl Future<Output = Result<Bytes, RpcError>> + Send;
+}
+
+impl<M: Middleware> StorageReader for M {
+    async fn storage_at(&self, address: Address, slot: U256, block: Option<BlockId>) -> Result<B256, RpcError> {
+	let word = self.get_storage_at(raddress_to_h160(&address), ru256_to_h256_be(&slot), block).await.map_err(|e| RpcError::from_middleware(&e))?;
+	Ok(h256_to_b256(word))
+    }
+
+    async fn code_at(&self, address: Address, block: Option<BlockId>) -> Result<Bytes, RpcError> {
+	let code = self.get_code(raddress_to_h160(&address), block).await.map_err(|e| RpcError::from_middleware(&e))?;
+	Ok(Bytes(code.0))
+    }
+
+    async fn call_at(&self, to: Address, data: Bytes, block: Option<BlockId>) -> Result<Bytes, RpcError> {
+	let tx = TransactionRequest::new().to(raddress_to_h160(&to)).data(data.0).into();
+	let output = self.call(&tx, block).await.map_err(|e| RpcError::from_middleware(&e))?;
+	Ok(Bytes(output.0))
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use alloy_primitives::{Address, U256};
+use ethers_core::types::BlockId;
+use ethers_providers::Middleware;
+use tracing::debug;
+
+use crate::detect::{detect_proxy, DetectorConfig, WidenedAnalysis};
+use crate::disasm::{scan, Push32Constants};
+use crate::findings::Finding;
+use crate::read::{get_proxy_implementation_at, read_single_storage_implementation, ProxyImplementation, ProxyReadError};
+use crate::registry::SlotRegistry;
+use crate::utils::{raddress_to_h160, ru256_to_h256_be};
+use crate::{ProxyDetectionResult, ProxyDispatch};
+
+/// Why resolving a dispatch contradicts the detection that produced it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Contradiction {
+    /// The dispatch slot holds zero, the proxy would forward calls nowhere.
+    UninitializedSlot(U256),
+    /// The implementation, beacon or facet the dispatch leads to has no code.
+    DanglingTarget(Address),
+    /// The dispatch slot holds something else than an address.
+    StorageNotAddress(U256),
+}
+
+/// Probes and budget of [resolve_with_redetection].
+#[derive(Clone, Debug)]
+pub struct RedetectConfig {
+    /// Probes of the widened passes, its `storage` is completed with values read from the node.
+    pub widened: WidenedAnalysis,
+    /// Widened detections run at most.
+    pub max_passes: usize,
+    /// Slots read from the node at most for the widened storage.
+    pub max_storage_reads: usize,
+}
+
+impl Default for RedetectConfig {
+    fn default() -> Self {
+	Self {
+	    widened: WidenedAnalysis { alternate_callers: 2, harvest_selectors: true, ..Default::default() },
+	    max_passes: 2,
+	    max_storage_reads: 16,
+	}
+    }
+}
+
+/// A proxy's implementation together with the detection it was read with.
+#[derive(Clone, Debug)]
+pub struct Resolution {
+    /// The quick detection, or the widened one that replaced it, marked with a
+    /// [Finding::DispatchCorrected]. Carries a [Finding::ResolutionContradiction] if neither
+    /// resolved cleanly.
+    pub detection: ProxyDetectionResult,
+    pub implementation: Result<ProxyImplementation, ProxyReadError>,
+    /// Widened detections run.
+    pub passes: usize,
+    /// Slots read from the node for them.
+    pub storage_reads: usize,
+}
+
+/// What contradicts `dispatch` in `implementation`, the result of resolving it for the proxy at
+/// `address`. Errors unrelated to the dispatch, e.g. from the RPC, aren't contradictions.
+pub async fn find_contradiction<M>(rpc: &M, address: &Address, dispatch: &ProxyDispatch, implementation: &Result<ProxyImplementation, ProxyReadError>) -> Result<Option<Contradiction>, ProxyReadError>
+    where M: Middleware
+{
+    find_contradiction_at(rpc, address, dispatch, implementation, None).await
+}
+
+async fn find_contradiction_at<M>(rpc: &M, address: &Address, dispatch: &ProxyDispatch, implementation: &Result<ProxyImplementation, ProxyReadError>, block: Option<BlockId>) -> Result<Option<Contradiction>, ProxyReadError>
+    where M: Middleware
+{
+    let implementation = match (dispatch, implementation) {
+	(ProxyDispatch::Storage(slot, _) | ProxyDispatch::Beacon(slot), Err(ProxyReadError::StorageNotAddress)) => {
+	    return Ok(Some(Contradiction::StorageNotAddress(*slot)));
+	},
+	(ProxyDispatch::MultipleStorage(slots), Err(ProxyReadError::StorageNotAddress)) => {
+	    // The error doesn't say which slot, read them one by one
+	    for slot in slots {
+		if let Err(ProxyReadError::StorageNotAddress) = read_single_storage_implementation(rpc, address, slot, None, block).await {
+		    return Ok(Some(Contradiction::StorageNotAddress(*slot)));
+		}
+	    }
+	    return Ok(None);
+	},
+	(ProxyDispatch::Beacon(slot), Err(ProxyReadError::NoCode(beacon))) if beacon.is_zero() => {
+	    return Ok(Some(Contradiction::UninitializedSlot(*slot)));
+	},
+	(ProxyDispatch::Beacon(_), Err(ProxyReadError::NoCode(beacon))) => return Ok(Some(Contradiction::DanglingTarget(*beacon))),
+	(_, Err(_)) => return Ok(None),
+	(_, Ok(implementation)) => implementation,
+    };
+
+    let slots = match dispatch {
+	ProxyDispatch::Storage(slot, _) => vec![*slot],
+	ProxyDispatch::MultipleStorage(slots) => slots.clone(),
+	_ => Vec::new(),
+    };
+    for (i, target) in implementation.to_vec().into_iter().enumerate() {
+	if target.is_zero() {
+	    if let Some(slot) = slots.get(i) {
+		return Ok(Some(Contradiction::UninitializedSlot(*slot)));
+	    }
+	}
+	let code = rpc.get_code(raddress_to_h160(&target), block).await.map_err(ProxyReadError::from_middleware)?;
+	if code.is_empty() {
+	    return Ok(Some(Contradiction::DanglingTarget(target)));
+	}
+    }
+    Ok(None)
+}
+
+/// [find_contradiction], taking a failure to check as no contradiction.
+async fn contradiction_of<M>(rpc: &M, address: &Address, dispatch: &ProxyDispatch, implementation: &Result<ProxyImplementation, ProxyReadError>, block: Option<BlockId>) -> Option<Contradiction>
+    where M: Middleware
+{
+    find_contradiction_at(rpc, address, dispatch, implementation, block).await
+	.unwrap_or_else(|e| {
+	    debug!("couldn't check the resolution of {}: {}", address, e);
+	    None
+	})
+}
+
+/// Slots worth knowing for a widened pass: the dispatch's own, the 32 byte constants the code
+/// pushes and the known ones of `slots`.
+fn candidate_slots(code: &[u8], dispatch: &ProxyDispatch, slots: &SlotRegistry) -> Vec<U256> {
+    let mut candidates = match dispatch {
+	ProxyDispatch::Storage(slot, _) | ProxyDispatch::Beacon(slot) => vec![*slot],
+	ProxyDispatch::MultipleStorage(slots) => slots.clone(),
+	_ => Vec::new(),
+    };
+    let mut pushed = Push32Constants::default();
+    scan(code, &mut pushed);
+    candidates.extend(pushed.values);
+    let mut known: Vec<U256> = slots.iter().map(|(slot, _)| *slot).collect();
+    known.sort();
+    candidates.extend(known);
+
+    let mut slots = Vec::new();
+    for slot in candidates {
+	if !slots.contains(&slot) {
+	    slots.push(slot);
+	}
+    }
+    slots
+}
+
+async fn read_storage<M>(rpc: &M, address: &Address, slot: &U256, block: Option<BlockId>) -> Result<U256, ProxyReadError>
+    where M: Middleware
+{
+    let value = rpc.get_storage_at(raddress_to_h160(address), ru256_to_h256_be(slot), block).await.map_err(ProxyReadError::from_middleware)?;
+    Ok(U256::from_be_bytes(value.0))
+}
+
+/// Resolves the proxy at `address`, running `code`, with the dispatch of `detection`.
+///
+/// If the resolution contradicts the detection (see [Contradiction]), detection is run again
+/// widened by `redetect`, over the proxy's real storage. The first widened dispatch that differs
+/// and resolves without error or contradiction replaces the quick one, noted with a
+/// [Finding::DispatchCorrected]. Otherwise the quick resolution is kept and the contradiction is
+/// reported as a [Finding::ResolutionContradiction].
+pub async fn resolve_with_redetection<M>(rpc: Arc<M>, address: &Address, code: &[u8], detection: ProxyDetectionResult, config: &DetectorConfig, redetect: &RedetectConfig) -> Resolution
+    where M: Middleware + 'static
+{
+    resolve_with_redetection_at(rpc, address, code, detection, config, redetect, None).await
+}
+
+/// [resolve_with_redetection] with the proxy's state at `block`, `code` being its code there.
+pub async fn resolve_with_redetection_at<M>(rpc: Arc<M>, address: &Address, code: &[u8], detection: ProxyDetectionResult, config: &DetectorConfig, redetect: &RedetectConfig, block: Option<BlockId>) -> Resolution
+    where M: Middleware + 'static
+{
+    let implementation = get_proxy_implementation_at(rpc.clone(), address, &detection.dispatch, block).await;
+    let mut resolution = Resolution { detection, implementation, passes: 0, storage_reads: 0 };
+    let Some(contradiction) = contradiction_of(rpc.as_ref(), address, &resolution.detection.dispatch, &resolution.implementation, block).await else {
+	return resolution;
+    };
+    debug!("resolving {} contradicts its detection: {:?}", address, contradiction);
+
+    // Values given by the caller win over the node's
+    let mut widened = redetect.widened.clone();
+    for slot in candidate_slots(code, &resolution.detection.dispatch, config.slot_registry()) {
+	if resolution.storage_reads >= redetect.max_storage_reads {
+	    break;
+	}
+	if widened.storage.contains_key(&slot) {
+	    continue;
+	}
+	resolution.storage_reads += 1;
+	match read_storage(rpc.as_ref(), address, &slot, block).await {
+	    Ok(value) => { widened.storage.insert(slot, value); },
+	    Err(e) => debug!("couldn't read slot {:x} of {}: {}", slot, address, e),
+	}
+    }
+
+    for pass in 0..redetect.max_passes {
+	resolution.passes += 1;
+	let pass_config = DetectorConfig {
+	    // Other inputs on every pass, unless the caller wants them random
+	    seed: config.seed.map(|seed| seed.wrapping_add(pass as u64 + 1)),
+	    widened: Some(widened.clone()),
+	    ..config.clone()
+	};
+	let Some(mut candidate) = detect_proxy(code, &pass_config) else {
+	    continue;
+	};
+	if candidate.dispatch == resolution.detection.dispatch {
+	    continue;
+	}
+	let implementation = get_proxy_implementation_at(rpc.clone(), address, &candidate.dispatch, block).await;
+	if implementation.is_err() || contradiction_of(rpc.as_ref(), address, &candidate.dispatch, &implementation, block).await.is_some() {
+	    debug!("widened dispatch {:?} of {} doesn't resolve either", candidate.dispatch, address);
+	    continue;
+	}
+	candidate.findings.push(Finding::DispatchCorrected { original: resolution.detection.dispatch.clone() });
+	resolution.detection = candidate;
+	resolution.implementation = implementation;
+	return resolution;
+    }
+    resolution.detection.findings.push(Finding::ResolutionContradiction(contradiction));
+    resolution
+}
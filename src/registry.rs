@@ -0,0 +1,132 @@
+//! The storage slots and resolver functions the detectors know proxies by: a ruleset's
+//! built-in tables, plus what the caller registers for protocols the crate doesn't ship.
+
+use std::collections::HashMap;
+
+use alloy_primitives::U256;
+
+use crate::data::{resolver_selectors_of, storage_slots_of, SelectorKind};
+use crate::profile::{Ruleset, TableVersions};
+use crate::rules::rule_tables;
+use crate::{ProxyType, Selector};
+
+/// Slots proxies keep their implementation or beacon in, with the [ProxyType] they are typical
+/// of and a name, usually their preimage. A delegatecall target loaded from a registered slot
+/// is classified as that type, and results dispatching through it carry the name as
+/// [slot_name](crate::ProxyDetectionResult::slot_name).
+///
+/// ```
+/// use alloy_primitives::U256;
+/// use evm_proxy_tools::{DetectorConfig, ProxyType, SlotRegistry};
+///
+/// let slot = U256::from_be_bytes(alloy_primitives::keccak256("myprotocol.proxy.implementation").0);
+/// let slots = SlotRegistry::default().register_slot(slot, ProxyType::EIP_1967_CUSTOM, "myprotocol.proxy.implementation");
+/// assert_eq!(slots.name(&slot), Some("myprotocol.proxy.implementation"));
+/// let config = DetectorConfig { slots: Some(slots), ..Default::default() };
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotRegistry {
+    slots: HashMap<U256, (ProxyType, String)>,
+}
+
+impl Default for SlotRegistry {
+    /// The built-in slots of the latest ruleset.
+    fn default() -> Self {
+	Self::builtin(&Ruleset::LATEST)
+    }
+}
+
+impl SlotRegistry {
+    /// The slots `ruleset` shipped with.
+    pub fn builtin(ruleset: &Ruleset) -> Self {
+	rule_tables(ruleset).storage_slots.clone()
+    }
+
+    pub(crate) fn load(tables: TableVersions) -> Self {
+	let slots = storage_slots_of(tables.storage_slots).expect("built-in slots are valid")
+	    .into_iter().map(|entry| (entry.slot, (entry.proxy_type, entry.name))).collect();
+	Self { slots }
+    }
+
+    /// No slot at all, not even the built-in ones.
+    pub fn empty() -> Self {
+	Self { slots: HashMap::new() }
+    }
+
+    /// Adds `slot`, replacing what was known of it.
+    pub fn register_slot(mut self, slot: U256, proxy_type: ProxyType, name: impl Into<String>) -> Self {
+	self.slots.insert(slot, (proxy_type, name.into()));
+	self
+    }
+
+    pub fn get(&self, slot: &U256) -> Option<&ProxyType> {
+	self.slots.get(slot).map(|(proxy_type, _)| proxy_type)
+    }
+
+    pub fn name(&self, slot: &U256) -> Option<&str> {
+	self.slots.get(slot).map(|(_, name)| name.as_str())
+    }
+
+    pub fn contains_key(&self, slot: &U256) -> bool {
+	self.slots.contains_key(slot)
+    }
+
+    /// Every slot with its type, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&U256, &ProxyType)> {
+	self.slots.iter().map(|(slot, (proxy_type, _))| (slot, proxy_type))
+    }
+}
+
+/// Functions proxies call on another contract to find their implementation, like a diamond's
+/// `facetAddress(bytes4)` or a factory's `getImplementation()`, with the [ProxyType] they are
+/// typical of. A proxy calling one is classified as [ProxyType::External], and the traced call
+/// gets an address back so what it delegates to is seen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectorRegistry {
+    resolvers: HashMap<Selector, ProxyType>,
+}
+
+impl Default for SelectorRegistry {
+    /// The built-in resolvers of the latest ruleset.
+    fn default() -> Self {
+	Self::builtin(&Ruleset::LATEST)
+    }
+}
+
+impl SelectorRegistry {
+    /// The resolvers `ruleset` shipped with.
+    pub fn builtin(ruleset: &Ruleset) -> Self {
+	rule_tables(ruleset).resolvers.clone()
+    }
+
+    pub(crate) fn load(tables: TableVersions) -> Self {
+	let resolvers = resolver_selectors_of(tables.resolver_selectors).expect("built-in selectors are valid")
+	    .into_iter().filter(|entry| entry.kind == SelectorKind::Resolver).map(|entry| (entry.selector, entry.proxy_type)).collect();
+	Self { resolvers }
+    }
+
+    /// No resolver at all, not even the built-in ones.
+    pub fn empty() -> Self {
+	Self { resolvers: HashMap::new() }
+    }
+
+    /// Adds the resolver function `selector`, e.g. `0xaaf10f42` for `getImplementation()`,
+    /// replacing what was known of it.
+    pub fn register_selector(mut self, selector: u32, proxy_type: ProxyType) -> Self {
+	self.resolvers.insert(Selector::from_u32_be(selector), proxy_type);
+	self
+    }
+
+    pub fn get(&self, selector: &Selector) -> Option<&ProxyType> {
+	self.resolvers.get(selector)
+    }
+
+    pub fn contains_key(&self, selector: &Selector) -> bool {
+	self.resolvers.contains_key(selector)
+    }
+
+    /// Every resolver, in no particular order.
+    pub fn selectors(&self) -> impl Iterator<Item = &Selector> {
+	self.resolvers.keys()
+    }
+}
@@ -0,0 +1,61 @@
+use alloy_primitives::Address;
+use revm::interpreter::opcode;
+
+use crate::interface::{dispatch_entries, dispatcher_start, Listing};
+use crate::selector::Selector;
+
+/// Instructions scanned from a selector's entry for the address it selects.
+const ENTRY_SCAN_LIMIT: usize = 16;
+
+/// A selector of a generated router and the module it hardcodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouterEntry {
+    pub selector: Selector,
+    pub implementation: Address,
+    /// Offset of the PUSH20 immediate holding `implementation`.
+    pub offset: usize,
+}
+
+/// The address an entry block selects: `JUMPDEST PUSH20 addr ... JUMP` without touching state
+/// or calldata on the way.
+fn entry_address(listing: &Listing, entry: usize) -> Option<(Address, usize)> {
+    let mut found = None;
+    for ins in listing.ins.iter().skip(entry).take(ENTRY_SCAN_LIMIT) {
+	match ins.opcode {
+	    opcode::PUSH20 if ins.operand.len() == 20 => {
+		if found.is_some() {
+		    return None;
+		}
+		found = Some((Address::from_slice(ins.operand), ins.operand_offset()));
+	    },
+	    opcode::JUMP => return found,
+	    opcode::SLOAD | opcode::SSTORE | opcode::CALLDATALOAD | opcode::CALL | opcode::STATICCALL | opcode::DELEGATECALL
+		| opcode::JUMPI | opcode::STOP | opcode::RETURN | opcode::REVERT | opcode::INVALID => return None,
+	    _ => {}
+	}
+    }
+    None
+}
+
+/// Recovers the selector to module table of a generated router, like Synthetix's: a selector
+/// dispatcher, linear or binary search, whose every case only sets a hardcoded address before
+/// jumping to a shared `DELEGATECALL` block.
+///
+/// `None` unless every dispatched selector resolves to an address, sorted by selector
+/// otherwise.
+pub fn recover_router_table(code: &[u8]) -> Option<Vec<RouterEntry>> {
+    let listing = Listing::new(code);
+    if !listing.ins.iter().any(|ins| ins.opcode == opcode::DELEGATECALL) {
+	return None;
+    }
+    let start = dispatcher_start(&listing)?;
+    let mut entries = dispatch_entries(&listing, start);
+    if entries.len() < 2 {
+	return None;
+    }
+    entries.sort_unstable();
+    entries.dedup_by_key(|(selector, _)| *selector);
+    entries.into_iter()
+	.map(|(selector, entry)| entry_address(&listing, entry).map(|(implementation, offset)| RouterEntry { selector, implementation, offset }))
+	.collect()
+}
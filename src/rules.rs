@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{keccak256, Address, B256, U256};
+use once_cell::sync::Lazy;
+use revm::interpreter::opcode;
+use twoway::find_bytes;
+
+use crate::disasm::{find_first_push_matching, find_push_value, FOLD_WINDOW};
+use crate::data::{resolver_selectors_of, storage_slots_of};
+use crate::consts::{BEACON_IMPLEMENTATION_SELECTOR, DIAMOND_STANDARD_STORAGE_SLOT_LESSBYTES};
+use crate::profile::{Ruleset, TableVersions};
+use crate::proxy_inspector::InspectorData;
+use crate::registry::{SelectorRegistry, SlotRegistry};
+use crate::{ProxyType, ProxyDispatch, Selector};
+
+/// Every classification rule the detectors can apply, in the order they are tried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum RuleId {
+    /// Runtime matches the EIP-1167 minimal proxy.
+    Eip1167Pattern,
+    /// Runtime matches the EIP-7511 (PUSH0) minimal proxy.
+    Eip7511Pattern,
+    /// Runtime matches the EIP-3448 metaproxy.
+    Eip3448Pattern,
+    /// Runtime matches a Solady `LibClone` PUSH0 clone or clone with immutable args.
+    SoladyClonePattern,
+    /// Runtime matches a forwarder of Vyper's `create_forwarder_to` (0.1 and 0.2).
+    VyperForwarderPattern,
+    /// Runtime matches a Safe proxy, up to its metadata.
+    SafeProxyPattern,
+    /// Code matches 0age's metamorphic init code, which copies the code of the implementation
+    /// its factory reports.
+    MetamorphicInitPattern,
+    /// The code reads an EIP-1967 beacon slot, calls `implementation()` and delegatecalls, like
+    /// OpenZeppelin's `BeaconProxy`.
+    BeaconProxyPattern,
+    /// The selector dispatcher only picks hardcoded addresses for a shared delegatecall.
+    GeneratedRouterPattern,
+    /// A probe delegatecalls an address taken from its calldata.
+    OpenDelegateCall,
+    /// Every probe that delegates does so only in a static context, e.g. within a STATICCALL
+    /// the contract makes to itself.
+    ReadOnlyRouter,
+    /// Every probe delegatecalls the same address that wasn't loaded from storage.
+    StaticDelegateCall,
+    /// Every probe delegatecalls the address stored in the EIP-1967 slot, but none does when
+    /// run from the address in the admin slot the code loaded, like OpenZeppelin's
+    /// `TransparentUpgradeableProxy`.
+    TransparentAdminBranch,
+    /// Every probe delegatecalls the address stored in a well known slot.
+    KnownStorageSlot,
+    /// Every probe calls `implementation()` on the address stored in a well known beacon slot.
+    BeaconStorageSlot,
+    /// The delegatecall target comes from a slot that isn't well known but is pushed verbatim
+    /// by the code, e.g. an immutable.
+    ImmutableStorageSlot,
+    /// The delegatecall target comes from a slot above 0x100, assumed to be a custom EIP-1967 slot.
+    CustomStorageSlot,
+    /// The delegatecall target comes from slot 0 and the code answers `masterCopy()`, like Safe
+    /// proxies.
+    SafeStorageSlot,
+    /// The delegatecall target comes from a slot up to 0x10 and the code answers Compound's
+    /// getters or setters of it, like the Unitroller and CErc20Delegator.
+    CompoundStorageSlot,
+    /// The delegatecall target comes from a low slot, like EIP-897 proxies.
+    LowStorageSlot,
+    /// Every probe calls a known resolver function (e.g. `facetAddress(bytes4)`) on another contract.
+    ExternalResolver,
+    /// Every probe STATICCALLs a registry and delegatecalls the address it returns, like
+    /// beacon proxies with a beacon slot that isn't well known or a hardcoded registry.
+    RegistryDelegateCall,
+    /// Probes dispatch differently and the code contains the `facetAddresses()` selector.
+    DiamondLoupeSelector,
+    /// Probes dispatch differently and the code contains the diamond standard storage slot.
+    DiamondStorageSlot,
+    /// Probes dispatch differently without further diamond evidence.
+    DiamondOther,
+    /// Every probe only reads the code of the same other contract and jumps to destinations
+    /// copied from it, like SSTORE2-style code pointers.
+    CodePointer,
+}
+
+/// Version of the classification rules, part of every
+/// [detection_id](crate::ProxyDetectionResult::detection_id). Bump it whenever rules are added,
+/// removed, reordered or their data tables change, so ids of results classified differently
+/// don't collide; `test_ruleset_version` fails until then.
+pub const RULESET_VERSION: u32 = 17;
+
+/// Oldest ruleset a [Ruleset] can be pinned to, the first whose data tables are kept.
+pub const MIN_PINNED_RULESET: u32 = 3;
+
+/// Ruleset since which minimal proxy patterns take addresses pushed with PUSH15 to PUSH20, not
+/// only PUSH16 and PUSH20.
+pub(crate) const VANITY_PUSHES_SINCE: u32 = 5;
+
+/// Ruleset since which static patterns also find slots computed from pushed constants, see
+/// [fold_constants](crate::disasm::fold_constants).
+pub(crate) const FOLDED_SLOTS_SINCE: u32 = 7;
+
+/// Ruleset since which the dynamic detector retries with [ProbeRetry](crate::ProbeRetry) when
+/// no quick probe forwards.
+pub(crate) const PROBE_RETRY_SINCE: u32 = 9;
+
+/// Ruleset since which the dynamic detector also probes with common selectors by default and
+/// leaves the probes the contract serves itself out, see [DefaultProbes](crate::DefaultProbes).
+pub(crate) const CALLDATA_PROBES_SINCE: u32 = 10;
+
+/// Ruleset since which static addresses and EIP-1967 slots found by tracing are only proxies
+/// if the delegatecall [forwards](crate::CallNode::is_forwarding) the call, see
+/// [ProxyType::DelegatesButNotProxy].
+pub(crate) const FORWARDING_REQUIRED_SINCE: u32 = 13;
+
+/// Ruleset since which minimal proxy patterns expect their JUMPI target moved back by the bytes
+/// a narrower address push saves, see [extract_minimal_contract](crate::detect::extract_minimal_contract).
+pub(crate) const SHIFTED_JUMPS_SINCE: u32 = 16;
+
+/// Ruleset since which [SoladyClonePattern](RuleId::SoladyClonePattern) expects the prologue
+/// `LibClone.clone_PUSH0` deploys, not the EIP-7511 one.
+pub(crate) const SOLADY_PUSH0_PROLOGUE_SINCE: u32 = 17;
+
+/// Hash of everything the trace classification depends on besides code: the rules in order and
+/// the built-in slot and selector tables. Workers can compare it before merging results to
+/// check they ran the same rules.
+pub fn ruleset_fingerprint() -> B256 {
+    fingerprint_of(&Ruleset::LATEST)
+}
+
+pub(crate) fn fingerprint_of(ruleset: &Ruleset) -> B256 {
+    let mut registry = String::new();
+    for rule in RuleId::ALL.iter().filter(|rule| ruleset.includes(**rule)) {
+	registry.push_str(&format!("rule {:?}\n", rule));
+    }
+    for (rule, _) in TRACE_RULES.iter().filter(|(rule, _)| ruleset.includes(*rule)) {
+	registry.push_str(&format!("trace {:?}\n", rule));
+    }
+    for entry in storage_slots_of(ruleset.tables().storage_slots).expect("built-in slots are valid") {
+	registry.push_str(&format!("slot {:x} {:?}\n", entry.slot, entry.proxy_type));
+    }
+    for entry in resolver_selectors_of(ruleset.tables().resolver_selectors).expect("built-in selectors are valid") {
+	registry.push_str(&format!("selector {:08x} {:?} {:?}\n", entry.selector, entry.proxy_type, entry.kind));
+    }
+    if ruleset.version() >= VANITY_PUSHES_SINCE {
+	registry.push_str("minimal pushes 15-20\n");
+    }
+    if ruleset.version() >= FOLDED_SLOTS_SINCE {
+	registry.push_str(&format!("folded slots window {}\n", FOLD_WINDOW));
+    }
+    if ruleset.version() >= PROBE_RETRY_SINCE {
+	registry.push_str("probe retry\n");
+    }
+    if ruleset.version() >= CALLDATA_PROBES_SINCE {
+	registry.push_str("common selector probes\n");
+    }
+    if ruleset.version() >= FORWARDING_REQUIRED_SINCE {
+	registry.push_str("forwarding required\n");
+    }
+    if ruleset.version() >= SHIFTED_JUMPS_SINCE {
+	registry.push_str("minimal jump targets shifted\n");
+    }
+    if ruleset.version() >= SOLADY_PUSH0_PROLOGUE_SINCE {
+	registry.push_str("solady push0 prologue\n");
+    }
+    keccak256(registry)
+}
+
+/// The built-in tables the trace rules consult, as some ruleset shipped them.
+pub(crate) struct RuleTables {
+    pub storage_slots: SlotRegistry,
+    pub resolvers: SelectorRegistry,
+}
+
+impl RuleTables {
+    fn load(versions: TableVersions) -> Self {
+	Self { storage_slots: SlotRegistry::load(versions), resolvers: SelectorRegistry::load(versions) }
+    }
+}
+
+// Every combination a Ruleset accepts, there are few
+static RULE_TABLES: Lazy<HashMap<TableVersions, RuleTables>> = Lazy::new(|| {
+    let versions = MIN_PINNED_RULESET..=RULESET_VERSION;
+    versions.clone()
+	.flat_map(|storage_slots| versions.clone().map(move |resolver_selectors| TableVersions { storage_slots, resolver_selectors }))
+	.map(|tables| (tables, RuleTables::load(tables)))
+	.collect()
+});
+
+/// The tables of `ruleset`.
+pub(crate) fn rule_tables(ruleset: &Ruleset) -> &'static RuleTables {
+    &RULE_TABLES[&ruleset.tables()]
+}
+
+impl RuleId {
+    pub const ALL: &'static [RuleId] = &[
+	RuleId::Eip1167Pattern,
+	RuleId::Eip7511Pattern,
+	RuleId::Eip3448Pattern,
+	RuleId::SoladyClonePattern,
+	RuleId::VyperForwarderPattern,
+	RuleId::SafeProxyPattern,
+	RuleId::MetamorphicInitPattern,
+	RuleId::BeaconProxyPattern,
+	RuleId::GeneratedRouterPattern,
+	RuleId::OpenDelegateCall,
+	RuleId::ReadOnlyRouter,
+	RuleId::StaticDelegateCall,
+	RuleId::TransparentAdminBranch,
+	RuleId::KnownStorageSlot,
+	RuleId::BeaconStorageSlot,
+	RuleId::ImmutableStorageSlot,
+	RuleId::CustomStorageSlot,
+	RuleId::SafeStorageSlot,
+	RuleId::CompoundStorageSlot,
+	RuleId::LowStorageSlot,
+	RuleId::ExternalResolver,
+	RuleId::RegistryDelegateCall,
+	RuleId::DiamondLoupeSelector,
+	RuleId::DiamondStorageSlot,
+	RuleId::DiamondOther,
+	RuleId::CodePointer,
+    ];
+
+    /// The ruleset version that introduced the rule. Rules older than [MIN_PINNED_RULESET]
+    /// count as introduced by it.
+    pub fn since(self) -> u32 {
+	match self {
+	    RuleId::CompoundStorageSlot => 4,
+	    RuleId::TransparentAdminBranch => 6,
+	    RuleId::OpenDelegateCall => 8,
+	    RuleId::ReadOnlyRouter => 11,
+	    RuleId::RegistryDelegateCall => 12,
+	    RuleId::CodePointer => 14,
+	    _ => MIN_PINNED_RULESET,
+	}
+    }
+
+    /// Whether the rule matches the code itself rather than what tracing it showed, which
+    /// depends on the probes and the synthetic environment.
+    pub fn is_pattern(self) -> bool {
+	!TRACE_RULES.iter().any(|(rule, _)| *rule == self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleState {
+    Enabled,
+    Disabled
+}
+
+/// Which classification rules are allowed to produce a result. Rules are enabled unless
+/// explicitly disabled; a disabled rule falls through to the next one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RulePolicy {
+    overrides: HashMap<RuleId, RuleState>
+}
+
+impl RulePolicy {
+    pub fn set(&mut self, rule: RuleId, state: RuleState) -> &mut Self {
+	self.overrides.insert(rule, state);
+	self
+    }
+
+    pub fn disable(mut self, rule: RuleId) -> Self {
+	self.set(rule, RuleState::Disabled);
+	self
+    }
+
+    pub fn enable(mut self, rule: RuleId) -> Self {
+	self.set(rule, RuleState::Enabled);
+	self
+    }
+
+    pub fn state(&self, rule: RuleId) -> RuleState {
+	self.overrides.get(&rule).copied().unwrap_or(RuleState::Enabled)
+    }
+
+    pub fn is_enabled(&self, rule: RuleId) -> bool {
+	self.state(rule) == RuleState::Enabled
+    }
+}
+
+/// What the dynamic detector observed while running the probes.
+pub(crate) struct TraceObservations<'a> {
+    pub code: &'a [u8],
+    pub runs: &'a [InspectorData],
+    /// The default probes run from the admin, when `runs` loaded an admin slot. Kept apart from
+    /// `runs`, whose consistency they'd break: the admin is meant to be served differently.
+    pub admin_runs: &'a [InspectorData],
+    pub consistent: bool,
+    pub slots: &'a SlotRegistry,
+    pub resolvers: &'a SelectorRegistry,
+}
+
+/// `masterCopy()`, which Safe proxies answer from slot 0.
+pub(crate) const MASTER_COPY_SELECTOR: Selector = Selector::from_u32_be(0xa619486e);
+
+/// `comptrollerImplementation()` and `_setPendingImplementation(address)` of the Unitroller,
+/// `_setImplementation(address,bool,bytes)` of CErc20Delegator. Their `implementation()` alone
+/// doesn't tell them from EIP-897 proxies, which have it too.
+pub(crate) const COMPOUND_SELECTORS: [Selector; 3] = [Selector::from_u32_be(0xbb82aa5e), Selector::from_u32_be(0xe992a041), Selector::from_u32_be(0x555bcc40)];
+
+/// Whether a proxy delegating through `slot` is a Safe proxy, `answers` telling the selectors
+/// its code handles.
+pub(crate) fn is_safe_slot(slot: &U256, answers: impl Fn(&Selector) -> bool) -> bool {
+    slot.is_zero() && answers(&MASTER_COPY_SELECTOR)
+}
+
+/// Whether a proxy delegating through `slot` is a Compound delegator, see [is_safe_slot].
+pub(crate) fn is_compound_slot(slot: &U256, answers: impl Fn(&Selector) -> bool) -> bool {
+    *slot <= U256::from(0x10) && COMPOUND_SELECTORS.iter().any(answers)
+}
+
+/// Whether `slot` is one of the low slots Solidity lays variables out in, rather than a hashed
+/// one.
+pub(crate) fn is_low_slot(slot: &U256) -> bool {
+    *slot <= U256::from(0x100)
+}
+
+type RuleFn = fn(&TraceObservations) -> Option<(ProxyType, ProxyDispatch)>;
+
+/// Rules applied to the dynamic detector observations, in order.
+pub(crate) static TRACE_RULES: &[(RuleId, RuleFn)] = &[
+    (RuleId::OpenDelegateCall, open_delegatecall),
+    (RuleId::ReadOnlyRouter, read_only_router),
+    (RuleId::StaticDelegateCall, static_delegatecall),
+    (RuleId::TransparentAdminBranch, transparent_admin_branch),
+    (RuleId::KnownStorageSlot, known_storage_slot),
+    (RuleId::BeaconStorageSlot, beacon_storage_slot),
+    (RuleId::ImmutableStorageSlot, immutable_storage_slot),
+    (RuleId::CustomStorageSlot, custom_storage_slot),
+    (RuleId::SafeStorageSlot, safe_storage_slot),
+    (RuleId::CompoundStorageSlot, compound_storage_slot),
+    (RuleId::LowStorageSlot, low_storage_slot),
+    (RuleId::ExternalResolver, external_resolver),
+    (RuleId::RegistryDelegateCall, registry_delegatecall),
+    (RuleId::DiamondLoupeSelector, diamond_loupe_selector),
+    (RuleId::DiamondStorageSlot, diamond_storage_slot),
+    (RuleId::DiamondOther, diamond_other),
+    (RuleId::CodePointer, code_pointer),
+];
+
+/// Applies the enabled trace rules and returns the first match with the rule that produced it.
+pub(crate) fn classify_trace(obs: &TraceObservations, policy: &RulePolicy, ruleset: &Ruleset) -> Option<(ProxyType, ProxyDispatch, RuleId)> {
+    TRACE_RULES.iter()
+	.filter(|(id, _)| policy.is_enabled(*id) && ruleset.includes(*id))
+	.find_map(|(id, rule)| rule(obs).map(|(proxy_type, dispatch)| (proxy_type, dispatch, *id)))
+}
+
+fn single_storage_slot(obs: &TraceObservations) -> Option<U256> {
+    if obs.consistent && obs.runs[0].delegatecall_storage.len() == 1 {
+	Some(obs.runs[0].delegatecall_storage[0])
+    } else {
+	None
+    }
+}
+
+fn storage_dispatch(obs: &TraceObservations, slot: U256) -> ProxyDispatch {
+    ProxyDispatch::Storage(slot, obs.runs[0].extraction(&slot))
+}
+
+fn open_delegatecall(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    obs.runs.iter().any(|run| !run.delegatecall_from_calldata.is_empty())
+	.then_some((ProxyType::OpenDelegateCall, ProxyDispatch::Unknown))
+}
+
+fn read_only_router(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let delegating: Vec<&InspectorData> = obs.runs.iter().filter(|run| !run.static_delegation.is_empty()).collect();
+    if delegating.is_empty() || !delegating.iter().all(|run| run.only_static_delegation()) {
+	return None;
+    }
+    let dispatch = match (single_storage_slot(obs), &obs.runs[0].delegatecall_addresses()[..]) {
+	(Some(slot), _) => storage_dispatch(obs, slot),
+	(None, [address]) if obs.consistent => ProxyDispatch::Static(*address),
+	_ => ProxyDispatch::Unknown,
+    };
+    Some((ProxyType::ReadOnlyRouter, dispatch))
+}
+
+fn static_delegatecall(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    match obs.runs[0].delegatecall_addresses()[..] {
+	[address] if obs.consistent => Some((ProxyType::StaticAddress, ProxyDispatch::Static(address))),
+	_ => None
+    }
+}
+
+fn known_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let slot = single_storage_slot(obs)?;
+    obs.slots.get(&slot).map(|proxy_type| (*proxy_type, storage_dispatch(obs, slot)))
+}
+
+fn transparent_admin_branch(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let slot = single_storage_slot(obs)?;
+    let forwards = |run: &InspectorData| !run.delegatecall_storage.is_empty() || !run.delegatecall_addresses().is_empty() || !run.delegatecall_registry.is_empty();
+    (obs.slots.get(&slot) == Some(&ProxyType::EIP_1967) && !obs.admin_runs.is_empty() && !obs.admin_runs.iter().any(forwards))
+	.then(|| (ProxyType::EIP_1967_TRANSPARENT, storage_dispatch(obs, slot)))
+}
+
+fn beacon_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    if !obs.consistent || !obs.runs[0].delegatecall_storage.is_empty() {
+	return None;
+    }
+    match obs.runs[0].storage_calls[..] {
+	[(slot, BEACON_IMPLEMENTATION_SELECTOR)] if obs.slots.get(&slot) == Some(&ProxyType::EIP_1967_BEACON) => {
+	    Some((ProxyType::EIP_1967_BEACON, ProxyDispatch::Beacon(slot)))
+	},
+	_ => None
+    }
+}
+
+fn immutable_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let slot = single_storage_slot(obs)?;
+    (!obs.slots.contains_key(&slot) && find_push_value(obs.code, &slot, 32).is_some())
+	.then(|| (ProxyType::ImmutableSlotProxy, storage_dispatch(obs, slot)))
+}
+
+fn custom_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let slot = single_storage_slot(obs)?;
+    (!is_low_slot(&slot)).then_some((ProxyType::EIP_1967_CUSTOM, storage_dispatch(obs, slot)))
+}
+
+fn safe_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    // Pushed as a selector or left aligned for comparing the calldata word
+    let pushes = |selector: &Selector| find_first_push_matching(obs.code, |ins| matches!(ins.opcode, opcode::PUSH4 | opcode::PUSH32) && ins.operand.starts_with(selector.as_bytes())).is_some();
+    let slot = single_storage_slot(obs)?;
+    is_safe_slot(&slot, pushes).then(|| (ProxyType::GnosisSafe, storage_dispatch(obs, slot)))
+}
+
+fn compound_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let pushes = |selector: &Selector| find_first_push_matching(obs.code, |ins| ins.opcode == opcode::PUSH4 && ins.operand == selector.as_bytes()).is_some();
+    let slot = single_storage_slot(obs)?;
+    is_compound_slot(&slot, pushes).then(|| (ProxyType::CompoundDelegator, storage_dispatch(obs, slot)))
+}
+
+fn low_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let slot = single_storage_slot(obs)?;
+    is_low_slot(&slot).then_some((ProxyType::EIP_897, storage_dispatch(obs, slot)))
+}
+
+fn external_resolver(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    if obs.consistent && obs.runs[0].external_calls.len() == 1 {
+	let (address, fun) = obs.runs[0].external_calls[0];
+	obs.resolvers.contains_key(&fun).then_some((ProxyType::External, ProxyDispatch::External(address, fun)))
+    } else {
+	None
+    }
+}
+
+fn registry_delegatecall(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    let run = &obs.runs[0];
+    if !obs.consistent || !run.delegatecall_storage.is_empty() || !run.delegatecall_addresses().is_empty() {
+	return None;
+    }
+    let [(registry, selector)] = run.delegatecall_registry[..] else { return None };
+    // A registry loaded from storage has a synthetic address, only a beacon's slot says anything
+    match run.storage_calls.iter().find(|(_, fun)| *fun == selector) {
+	Some((slot, _)) => (selector == BEACON_IMPLEMENTATION_SELECTOR).then(|| {
+	    let proxy_type = obs.slots.get(slot).copied().filter(|proxy_type| *proxy_type == ProxyType::EIP_1967_BEACON);
+	    (proxy_type.unwrap_or(ProxyType::External), ProxyDispatch::Beacon(*slot))
+	}),
+	None => Some((ProxyType::External, ProxyDispatch::External(registry, selector))),
+    }
+}
+
+fn diamond_loupe_selector(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    // PUSH4 facetAddresses()
+    (!obs.consistent && find_bytes(obs.code, &hex_literal::hex!("637a0ed627")).is_some())
+	.then_some((ProxyType::EIP_2535, ProxyDispatch::Facet_EIP_2535))
+}
+
+fn diamond_storage_slot(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    (!obs.consistent && find_bytes(obs.code, &DIAMOND_STANDARD_STORAGE_SLOT_LESSBYTES).is_some())
+	.then_some((ProxyType::EIP_2535, ProxyDispatch::FacetStorageSlot))
+}
+
+fn diamond_other(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    (!obs.consistent).then_some((ProxyType::DiamondOther, ProxyDispatch::Unknown))
+}
+
+fn code_pointer(obs: &TraceObservations) -> Option<(ProxyType, ProxyDispatch)> {
+    // The only call made is the traced one
+    if !obs.runs.iter().all(|run| run.code_jump && run.calls.len() == 1) {
+	return None;
+    }
+    let mut slots: Vec<U256> = obs.runs.iter().flat_map(|run| run.code_read_storage.iter().copied()).collect();
+    slots.sort();
+    slots.dedup();
+    let mut addresses: Vec<Address> = obs.runs.iter().flat_map(|run| run.code_reads.iter().copied()).collect();
+    addresses.sort();
+    addresses.dedup();
+    let dispatch = match (&slots[..], &addresses[..]) {
+	([slot], [_]) => storage_dispatch(obs, *slot),
+	([], [address]) => ProxyDispatch::Static(*address),
+	_ => return None,
+    };
+    Some((ProxyType::CodePointer, dispatch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compound_selectors() {
+	for (selector, signature) in [("bb82aa5e", "comptrollerImplementation()"), ("e992a041", "_setPendingImplementation(address)"), ("555bcc40", "_setImplementation(address,bool,bytes)")] {
+	    assert_eq!(hex::encode(&keccak256(signature)[..4]), selector);
+	}
+    }
+
+    #[test]
+    fn test_pattern_rules() {
+	// Rules matching the code are named after it
+	for rule in RuleId::ALL {
+	    assert_eq!(rule.is_pattern(), format!("{:?}", rule).ends_with("Pattern"), "{:?}", rule);
+	}
+    }
+
+    #[test]
+    fn test_ruleset_version() {
+	// Changed the rules? Bump RULESET_VERSION and update both values together
+	assert_eq!((RULESET_VERSION, ruleset_fingerprint()), (17, alloy_primitives::b256!("71169b6f40468b8fd7d2b2dc967543accabc858ace0f3123dd242264f5d401a2")));
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::consts::SELECTOR_LABELS;
+
+/// A function selector, the first 4 bytes of calldata, in calldata order.
+///
+/// Integer conversions are big endian, so `0x5c60da1b` is `implementation()` whichever way it
+/// was obtained.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Selector(pub [u8; 4]);
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SelectorParseError {
+    #[error("selector `{0}` is missing the 0x prefix")]
+    MissingPrefix(String),
+    #[error("selector `{0}` isn't 8 hex digits")]
+    InvalidHex(String),
+}
+
+impl Selector {
+    pub const fn new(bytes: [u8; 4]) -> Self {
+	Self(bytes)
+    }
+
+    /// The selector of `calldata`, its first 4 bytes. `None` if it's shorter.
+    pub fn from_slice(calldata: &[u8]) -> Option<Self> {
+	calldata.first_chunk::<4>().map(|bytes| Self(*bytes))
+    }
+
+    pub const fn from_u32_be(value: u32) -> Self {
+	Self(value.to_be_bytes())
+    }
+
+    pub const fn to_u32_be(self) -> u32 {
+	u32::from_be_bytes(self.0)
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; 4] {
+	&self.0
+    }
+
+    /// The signature of the function if it's one most diamonds have, e.g. `facets()`.
+    pub fn label(&self) -> Option<&'static str> {
+	SELECTOR_LABELS.iter().find(|(selector, _)| selector == self).map(|(_, label)| *label)
+    }
+}
+
+impl From<u32> for Selector {
+    fn from(value: u32) -> Self {
+	Self::from_u32_be(value)
+    }
+}
+
+impl From<Selector> for u32 {
+    fn from(selector: Selector) -> Self {
+	selector.to_u32_be()
+    }
+}
+
+impl From<[u8; 4]> for Selector {
+    fn from(bytes: [u8; 4]) -> Self {
+	Self(bytes)
+    }
+}
+
+impl From<Selector> for [u8; 4] {
+    fn from(selector: Selector) -> Self {
+	selector.0
+    }
+}
+
+impl AsRef<[u8]> for Selector {
+    fn as_ref(&self) -> &[u8] {
+	&self.0
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	write!(f, "0x{:08x}", self.to_u32_be())
+    }
+}
+
+impl fmt::Debug for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	write!(f, "Selector({})", self)
+    }
+}
+
+impl fmt::LowerHex for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	fmt::LowerHex::fmt(&self.to_u32_be(), f)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorParseError;
+
+    /// Parses `0x` followed by 8 hex digits, either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	let digits = s.strip_prefix("0x").ok_or_else(|| SelectorParseError::MissingPrefix(s.to_string()))?;
+	if digits.len() != 8 || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+	    return Err(SelectorParseError::InvalidHex(s.to_string()));
+	}
+	Ok(Self::from_u32_be(u32::from_str_radix(digits, 16).expect("validated hex")))
+    }
+}
+
+impl Serialize for Selector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Selector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+	let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+	s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selector_conversions() {
+	let selector = Selector::from_slice(&hex_literal::hex!("5c60da1b00000000")).unwrap();
+	assert_eq!(selector, Selector::from(0x5c60da1b));
+	assert_eq!(u32::from(selector), 0x5c60da1b);
+	assert_eq!(selector.to_string(), "0x5c60da1b");
+	assert_eq!("0x5C60DA1B".parse::<Selector>().unwrap(), selector);
+	assert_eq!(Selector::from_slice(&[0x5c, 0x60, 0xda]), None);
+	assert!(matches!("5c60da1b".parse::<Selector>(), Err(SelectorParseError::MissingPrefix(_))));
+	assert!(matches!("0x5c60da".parse::<Selector>(), Err(SelectorParseError::InvalidHex(_))));
+	// Ordered like the big endian integers
+	assert!(Selector::from(0x00ffffff) < Selector::from(0x01000000));
+
+	let json = serde_json::to_string(&selector).unwrap();
+	assert_eq!(json, "\"0x5c60da1b\"");
+	assert_eq!(serde_json::from_str::<Selector>(&json).unwrap(), selector);
+    }
+}
@@ -0,0 +1,136 @@
+//! Everyone a proxy trusts: the addresses whose code runs as the proxy and those whose state or
+//! answers decide which code that is, e.g. the beacon of a beacon proxy or the admin allowed to
+//! upgrade it.
+
+use alloy_primitives::{Address, U256};
+use serde::Serialize;
+
+use crate::consts::{BEACON_IMPLEMENTATION_SELECTOR, DIAMOND_FACETS_SELECTOR};
+use crate::read::ProxyImplementation;
+use crate::{ProxyDetectionResult, ProxyDispatch, ProxyType, Selector};
+
+/// What a [TrustedParty] is to the proxy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum TrustRole {
+    /// Its code runs for every call, or for some selectors of a router.
+    Implementation,
+    /// Its code runs for the selectors a diamond maps to it.
+    Facet,
+    /// Answers `implementation()` with the implementation.
+    Beacon,
+    /// Answers another getter with the implementation.
+    Registry,
+    /// Allowed to upgrade the proxy.
+    Admin,
+}
+
+/// How a [TrustedParty] gets its role.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum TrustMechanism {
+    /// Hardcoded in the proxy's code.
+    Hardcoded,
+    /// Stored in a slot of the proxy.
+    StorageSlot(U256),
+    /// Returned by `selector` called on `target`.
+    Getter { target: Address, selector: Selector },
+    /// Stored in the proxy's admin slot, which grants the rights to upgrade it.
+    AdminRights(U256),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct TrustedParty {
+    pub address: Address,
+    pub role: TrustRole,
+    pub mechanism: TrustMechanism,
+    /// Who can replace it, when known: the admin of an upgradeable slot, the beacon or registry
+    /// answering with it, the implementation of a UUPS proxy or the diamond itself through
+    /// `diamondCut`. `None` when it can't be replaced or who can isn't known.
+    pub mutable_by: Option<Address>,
+}
+
+/// The [TrustedParty]s of a proxy, sorted by address then role. A party is listed once per
+/// role, whatever the number of ways it holds it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TrustSet {
+    pub entries: Vec<TrustedParty>,
+}
+
+impl TrustSet {
+    fn new(mut entries: Vec<TrustedParty>) -> Self {
+	entries.sort_by_key(|party| (party.address, party.role, party.mechanism));
+	entries.dedup_by_key(|party| (party.address, party.role));
+	Self { entries }
+    }
+
+    /// Every trusted address once, sorted.
+    pub fn parties(&self) -> Vec<Address> {
+	let mut parties: Vec<Address> = self.entries.iter().map(|party| party.address).collect();
+	parties.dedup();
+	parties
+    }
+
+    /// The roles `address` holds.
+    pub fn roles_of(&self, address: &Address) -> Vec<TrustRole> {
+	self.entries.iter().filter(|party| party.address == *address).map(|party| party.role).collect()
+    }
+}
+
+/// The trust set of the proxy at `proxy` given what an analysis resolved about it: its
+/// `implementation` (`None` if unresolved), the `admin` read from its admin slot and the
+/// `beacon` read from the slot of a [ProxyDispatch::Beacon].
+pub fn trust_set(proxy: &Address, detection: &ProxyDetectionResult, implementation: Option<&ProxyImplementation>, admin: Option<Address>, beacon: Option<Address>) -> TrustSet {
+    let mut entries = Vec::new();
+    let party = |address, role, mechanism, mutable_by| TrustedParty { address, role, mechanism, mutable_by };
+    // Who can write the implementation slots: the admin if there's one, else UUPS
+    // implementations upgrade themselves
+    let slot_writer = |implementation: Address| admin.or((detection.proxy_type == ProxyType::UUPS).then_some(implementation));
+
+    if let (Some(admin), Some(slot)) = (admin, detection.admin_slot) {
+	entries.push(party(admin, TrustRole::Admin, TrustMechanism::AdminRights(slot), Some(admin)));
+    }
+    match (&detection.dispatch, implementation) {
+	(ProxyDispatch::Storage(slot, _), Some(ProxyImplementation::Single(address))) => {
+	    entries.push(party(*address, TrustRole::Implementation, TrustMechanism::StorageSlot(*slot), slot_writer(*address)));
+	},
+	(ProxyDispatch::MultipleStorage(slots), Some(ProxyImplementation::Multiple(addresses))) => {
+	    for (slot, address) in slots.iter().zip(addresses) {
+		entries.push(party(*address, TrustRole::Implementation, TrustMechanism::StorageSlot(*slot), slot_writer(*address)));
+	    }
+	},
+	(ProxyDispatch::Beacon(slot), implementation) => {
+	    if let Some(beacon) = beacon {
+		entries.push(party(beacon, TrustRole::Beacon, TrustMechanism::StorageSlot(*slot), admin));
+		if let Some(ProxyImplementation::Single(address)) = implementation {
+		    let mechanism = TrustMechanism::Getter { target: beacon, selector: BEACON_IMPLEMENTATION_SELECTOR };
+		    entries.push(party(*address, TrustRole::Implementation, mechanism, Some(beacon)));
+		}
+	    }
+	},
+	(ProxyDispatch::Static(address) | ProxyDispatch::StaticWithArgs(address, _), _) => {
+	    entries.push(party(*address, TrustRole::Implementation, TrustMechanism::Hardcoded, None));
+	},
+	(ProxyDispatch::PerSelector(modules), _) => {
+	    for (_, address) in modules {
+		entries.push(party(*address, TrustRole::Implementation, TrustMechanism::Hardcoded, None));
+	    }
+	},
+	(ProxyDispatch::External(registry, selector), implementation) => {
+	    entries.push(party(*registry, TrustRole::Registry, TrustMechanism::Hardcoded, None));
+	    if let Some(ProxyImplementation::Single(address)) = implementation {
+		let mechanism = TrustMechanism::Getter { target: *registry, selector: *selector };
+		entries.push(party(*address, TrustRole::Implementation, mechanism, Some(*registry)));
+	    }
+	},
+	(ProxyDispatch::Facet_EIP_2535 | ProxyDispatch::FacetStorageSlot, Some(ProxyImplementation::Facets(facets))) => {
+	    for (selector, address) in facets {
+		let mechanism = match detection.facet_slots.iter().find(|(facet_selector, _)| facet_selector == selector) {
+		    Some((_, slot)) => TrustMechanism::StorageSlot(*slot),
+		    None => TrustMechanism::Getter { target: *proxy, selector: DIAMOND_FACETS_SELECTOR },
+		};
+		entries.push(party(*address, TrustRole::Facet, mechanism, Some(*proxy)));
+	    }
+	},
+	_ => (),
+    }
+    TrustSet::new(entries)
+}
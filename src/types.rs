@@ -1,7 +1,21 @@
-use alloy_primitives::{U256, Address};
+use std::fmt;
+use std::str::FromStr;
 
+use alloy_primitives::{U256, Address, Bytes};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::attribution::CloneAttribution;
+use crate::findings::Finding;
+use crate::rules::RuleId;
+use crate::selector::Selector;
+
+/// Serialized as the variant's name, e.g. `"EIP_1967"`. Variants are added as patterns are
+/// recognized, the existing ones keep their names. Ordered as declared, like [ProxyType::ALL].
 #[allow(non_camel_case_types)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[non_exhaustive]
 pub enum ProxyType {
     NoProxy,
 
@@ -12,33 +26,438 @@ pub enum ProxyType {
     EIP_1167,
     EIP_3448,
     EIP_7511,
+    // Solady LibClone runtimes that aren't one of the EIPs above
+    SoladyClone,
+    // Vyper's `create_forwarder_to` before 0.3, later versions emit EIP-1167
+    VyperForwarder,
     // Another type of static dispatch
     StaticAddress,
 
     // Storage slot
     EIP_897,
+    // Safe (Gnosis Safe) proxies, the singleton is in slot 0
+    GnosisSafe,
     EIP_1967,
+    // EIP-1967 proxy that doesn't forward its admin's calls, like OpenZeppelin's TransparentUpgradeableProxy
+    EIP_1967_TRANSPARENT,
     EIP_1967_CUSTOM,
+    // The slot is a constant in the code, usually an immutable set per instance
+    ImmutableSlotProxy,
+    // Compound's Unitroller and CErc20Delegator, the implementation is a named variable in a low slot
+    CompoundDelegator,
     EIP_1967_ZOS,
     EIP_1967_BEACON,
     EIP_1822,
+    // Storage slot proxy upgraded through its implementation, which answers `proxiableUUID()`
+    UUPS,
 
     // Diamond
     EIP_2535,
     DiamondOther,
+    // Hardcoded module per selector, e.g. Synthetix's generated routers
+    GeneratedRouter,
+
+    External,
+    // Delegatecalls whatever address its caller passes, so anyone can run code as the contract
+    OpenDelegateCall,
+    // Delegates only within a static context, e.g. routing views to a lens through a STATICCALL
+    // to itself, the target can read the contract's storage but not change it
+    ReadOnlyRouter,
+    // Delegatecalls without forwarding the call, e.g. a helper running a library's code on a
+    // part of its calldata, like Gnosis' MultiSend. Not a proxy
+    DelegatesButNotProxy,
+    // Runs no other code but jumps to destinations read from another contract's code, e.g.
+    // SSTORE2-style pointers, so that contract controls its behavior
+    CodePointer,
+
+    // Not a proxy: the code can be replaced under the same address by redeploying it
+    Metamorphic
+}
+
+impl ProxyType {
+    pub const ALL: &'static [ProxyType] = &[
+        ProxyType::NoProxy,
+        ProxyType::Unknown,
+        ProxyType::EIP_1167,
+        ProxyType::EIP_3448,
+        ProxyType::EIP_7511,
+        ProxyType::SoladyClone,
+        ProxyType::VyperForwarder,
+        ProxyType::StaticAddress,
+        ProxyType::EIP_897,
+        ProxyType::GnosisSafe,
+        ProxyType::EIP_1967,
+        ProxyType::EIP_1967_TRANSPARENT,
+        ProxyType::EIP_1967_CUSTOM,
+        ProxyType::ImmutableSlotProxy,
+        ProxyType::CompoundDelegator,
+        ProxyType::EIP_1967_ZOS,
+        ProxyType::EIP_1967_BEACON,
+        ProxyType::EIP_1822,
+        ProxyType::UUPS,
+        ProxyType::EIP_2535,
+        ProxyType::DiamondOther,
+        ProxyType::GeneratedRouter,
+        ProxyType::External,
+        ProxyType::OpenDelegateCall,
+        ProxyType::ReadOnlyRouter,
+        ProxyType::DelegatesButNotProxy,
+        ProxyType::CodePointer,
+        ProxyType::Metamorphic,
+    ];
+
+    /// The human name, e.g. `EIP-1167 Minimal Proxy`. The variant's name is the identifier.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProxyType::NoProxy => "Not a Proxy",
+            ProxyType::Unknown => "Unknown Proxy",
+            ProxyType::EIP_1167 => "EIP-1167 Minimal Proxy",
+            ProxyType::EIP_3448 => "EIP-3448 MetaProxy",
+            ProxyType::EIP_7511 => "EIP-7511 Minimal Proxy (PUSH0)",
+            ProxyType::SoladyClone => "Solady Clone",
+            ProxyType::VyperForwarder => "Vyper Forwarder",
+            ProxyType::StaticAddress => "Static Address Proxy",
+            ProxyType::EIP_897 => "EIP-897 Delegate Proxy",
+            ProxyType::GnosisSafe => "Safe Proxy",
+            ProxyType::EIP_1967 => "EIP-1967 Proxy",
+            ProxyType::EIP_1967_TRANSPARENT => "EIP-1967 Transparent Proxy",
+            ProxyType::EIP_1967_CUSTOM => "EIP-1967 Custom Slot Proxy",
+            ProxyType::ImmutableSlotProxy => "Immutable Slot Proxy",
+            ProxyType::CompoundDelegator => "Compound Delegator",
+            ProxyType::EIP_1967_ZOS => "EIP-1967 ZeppelinOS Proxy",
+            ProxyType::EIP_1967_BEACON => "EIP-1967 Beacon Proxy",
+            ProxyType::EIP_1822 => "EIP-1822 Proxiable",
+            ProxyType::UUPS => "UUPS Proxy",
+            ProxyType::EIP_2535 => "EIP-2535 Diamond",
+            ProxyType::DiamondOther => "Non-Standard Diamond",
+            ProxyType::GeneratedRouter => "Generated Router",
+            ProxyType::External => "External Resolver Proxy",
+            ProxyType::OpenDelegateCall => "Open Delegatecall",
+            ProxyType::ReadOnlyRouter => "Read-Only Router",
+            ProxyType::DelegatesButNotProxy => "Delegatecall Helper (Not a Proxy)",
+            ProxyType::CodePointer => "Code Pointer",
+            ProxyType::Metamorphic => "Metamorphic Contract",
+        }
+    }
+}
+
+impl fmt::Display for ProxyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("unknown proxy type `{0}`")]
+pub struct ProxyTypeParseError(pub String);
 
-    External
+impl FromStr for ProxyType {
+    type Err = ProxyTypeParseError;
+
+    /// Parses the [name](ProxyType::name) or the identifier, e.g. `EIP_1967_ZOS`, either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ProxyType::ALL.iter()
+            .find(|proxy_type| proxy_type.name().eq_ignore_ascii_case(s) || format!("{:?}", proxy_type).eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| ProxyTypeParseError(s.to_string()))
+    }
 }
 
+/// Serialized as an object tagged by `kind`, e.g.
+/// `{"kind":"storage","slot":"0x3608…"}`, with addresses, slots and bytes as hex strings.
+/// Ordered by variant as declared, which groups them by [kind](ProxyDispatch::kind), then by
+/// contents.
 #[allow(non_camel_case_types)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(from = "DispatchRepr", into = "DispatchRepr")]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ProxyDispatch {
     Unknown,
-    Storage(U256),
+    /// The slot, and how to extract the address when it isn't stored in the low 160 bits.
+    Storage(U256, Option<SlotExtraction>),
     MultipleStorage(Vec<U256>),
+    /// The slot holds a beacon, the implementation is what the beacon's `implementation()`
+    /// returns.
+    Beacon(U256),
     Static(Address),
+    /// A hardcoded implementation and the immutable args appended to the runtime, which the
+    /// implementation reads from the end of its calldata.
+    StaticWithArgs(Address, Bytes),
     Facet_EIP_2535,
     FacetStorageSlot,
+    /// A hardcoded implementation per selector, sorted by selector.
+    PerSelector(Vec<(Selector, Address)>),
     // Needs to be analysed
-    External(Address, u32)
+    External(Address, Selector)
+}
+
+/// How a [ProxyDispatch] finds the implementation, without the slots and addresses, to group
+/// proxies by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DispatchKind {
+    Unknown,
+    /// [Storage](ProxyDispatch::Storage) and [MultipleStorage](ProxyDispatch::MultipleStorage).
+    Storage,
+    Beacon,
+    /// [Static](ProxyDispatch::Static) and [StaticWithArgs](ProxyDispatch::StaticWithArgs).
+    Static,
+    /// [Facet_EIP_2535](ProxyDispatch::Facet_EIP_2535) and
+    /// [FacetStorageSlot](ProxyDispatch::FacetStorageSlot).
+    Facets,
+    PerSelector,
+    External,
+}
+
+impl ProxyDispatch {
+    pub fn kind(&self) -> DispatchKind {
+	match self {
+	    ProxyDispatch::Unknown => DispatchKind::Unknown,
+	    ProxyDispatch::Storage(..) | ProxyDispatch::MultipleStorage(_) => DispatchKind::Storage,
+	    ProxyDispatch::Beacon(_) => DispatchKind::Beacon,
+	    ProxyDispatch::Static(_) | ProxyDispatch::StaticWithArgs(..) => DispatchKind::Static,
+	    ProxyDispatch::Facet_EIP_2535 | ProxyDispatch::FacetStorageSlot => DispatchKind::Facets,
+	    ProxyDispatch::PerSelector(_) => DispatchKind::PerSelector,
+	    ProxyDispatch::External(..) => DispatchKind::External,
+	}
+    }
+}
+
+/// The serialized form of a [ProxyDispatch], the kinds being those of the inspector's reports.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum DispatchRepr {
+    Unknown,
+    Storage {
+	slot: U256,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	extraction: Option<SlotExtraction>,
+    },
+    MultipleStorage { slots: Vec<U256> },
+    Beacon { slot: U256 },
+    Static { implementation: Address },
+    StaticWithArgs { implementation: Address, args: Bytes },
+    Facets,
+    FacetStorageSlot,
+    PerSelector { implementations: Vec<(Selector, Address)> },
+    External { address: Address, selector: Selector },
+}
+
+impl From<ProxyDispatch> for DispatchRepr {
+    fn from(dispatch: ProxyDispatch) -> Self {
+	match dispatch {
+	    ProxyDispatch::Unknown => DispatchRepr::Unknown,
+	    ProxyDispatch::Storage(slot, extraction) => DispatchRepr::Storage { slot, extraction },
+	    ProxyDispatch::MultipleStorage(slots) => DispatchRepr::MultipleStorage { slots },
+	    ProxyDispatch::Beacon(slot) => DispatchRepr::Beacon { slot },
+	    ProxyDispatch::Static(implementation) => DispatchRepr::Static { implementation },
+	    ProxyDispatch::StaticWithArgs(implementation, args) => DispatchRepr::StaticWithArgs { implementation, args },
+	    ProxyDispatch::Facet_EIP_2535 => DispatchRepr::Facets,
+	    ProxyDispatch::FacetStorageSlot => DispatchRepr::FacetStorageSlot,
+	    ProxyDispatch::PerSelector(implementations) => DispatchRepr::PerSelector { implementations },
+	    ProxyDispatch::External(address, selector) => DispatchRepr::External { address, selector },
+	}
+    }
+}
+
+impl From<DispatchRepr> for ProxyDispatch {
+    fn from(repr: DispatchRepr) -> Self {
+	match repr {
+	    DispatchRepr::Unknown => ProxyDispatch::Unknown,
+	    DispatchRepr::Storage { slot, extraction } => ProxyDispatch::Storage(slot, extraction),
+	    DispatchRepr::MultipleStorage { slots } => ProxyDispatch::MultipleStorage(slots),
+	    DispatchRepr::Beacon { slot } => ProxyDispatch::Beacon(slot),
+	    DispatchRepr::Static { implementation } => ProxyDispatch::Static(implementation),
+	    DispatchRepr::StaticWithArgs { implementation, args } => ProxyDispatch::StaticWithArgs(implementation, args),
+	    DispatchRepr::Facets => ProxyDispatch::Facet_EIP_2535,
+	    DispatchRepr::FacetStorageSlot => ProxyDispatch::FacetStorageSlot,
+	    DispatchRepr::PerSelector { implementations } => ProxyDispatch::PerSelector(implementations),
+	    DispatchRepr::External { address, selector } => ProxyDispatch::External(address, selector),
+	}
+    }
+}
+
+/// Extraction of an address packed with other values in a slot: `(word >> shift) & mask`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SlotExtraction {
+    pub shift: usize,
+    pub mask: U256,
+}
+
+impl Default for SlotExtraction {
+    fn default() -> Self {
+        Self { shift: 0, mask: U256::MAX }
+    }
+}
+
+impl SlotExtraction {
+    pub fn new(shift: usize, mask: U256) -> Self {
+        Self { shift, mask }
+    }
+
+    /// The extraction followed by a right shift.
+    pub fn shifted(&self, bits: usize) -> Self {
+        Self { shift: self.shift.saturating_add(bits).min(256), mask: self.mask >> bits }
+    }
+
+    /// The extraction followed by a mask.
+    pub fn masked(&self, mask: U256) -> Self {
+        Self { shift: self.shift, mask: self.mask & mask }
+    }
+
+    pub fn apply(&self, word: U256) -> U256 {
+        (word >> self.shift) & self.mask
+    }
+
+    /// Reads the address from the low 160 bits, which needs no recipe.
+    pub fn is_low_address(&self) -> bool {
+        let low_address = U256::MAX >> 96;
+        self.shift == 0 && self.mask & low_address == low_address
+    }
+}
+
+/// A detected proxy together with the classification rule that identified it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ProxyDetectionResult {
+    pub proxy_type: ProxyType,
+    pub dispatch: ProxyDispatch,
+    pub rule: RuleId,
+    /// The contract delegated differently depending on the synthetic environment.
+    pub evasive: bool,
+    pub findings: Vec<Finding>,
+    /// Where the reported addresses and constants live in the analysed bytecode.
+    pub provenance: Vec<ByteProvenance>,
+    /// The factory that deployed a clone, set by the caller from
+    /// [attribute_clone](crate::attribute_clone) since detection only sees the code.
+    pub attribution: Option<CloneAttribution>,
+    /// Set when the code was an ERC-5202 blueprint and the proxy was found in its payload.
+    pub blueprint: Option<BlueprintInfo>,
+    /// The EIP-1967 or ZeppelinOS admin slot, when the traced code loaded it. See
+    /// [get_proxy_admin](crate::get_proxy_admin) for its value.
+    pub admin_slot: Option<U256>,
+    /// Slots the traced code wrote the new implementation to when called with `upgradeTo` or
+    /// `upgradeToAndCall`, i.e. upgraded by the proxy itself rather than its implementation.
+    pub upgradeable_slots: Vec<U256>,
+    /// For diamonds, the slot the facet of each selector pushed by the code is loaded from,
+    /// sorted by selector. Found without RPC, by tracing a call per selector.
+    pub facet_slots: Vec<(Selector, U256)>,
+    /// For custom and immutable slots, the namespace of [DetectorConfig::namespaces](crate::DetectorConfig::namespaces)
+    /// the slot derives from, see [namespaced_slot](crate::utils::namespaced_slot).
+    pub slot_namespace: Option<String>,
+    /// For storage and beacon dispatch, the preimage of the hash the traced code derived the
+    /// slot from.
+    pub slot_preimage: Option<SlotPreimage>,
+    /// For storage and beacon dispatch through a known slot, its name in the
+    /// [SlotRegistry](crate::SlotRegistry) of the [DetectorConfig](crate::DetectorConfig), else
+    /// its [slot_name](crate::slot_name).
+    pub slot_name: Option<String>,
+    /// For traced results, the calldata of the first classified probe that forwarded the call.
+    pub trigger_calldata: Option<Bytes>,
+    /// For traced results, whether every classified probe saw the same delegation. Probes
+    /// delegating differently, e.g. by selector, make the dispatch less certain.
+    pub probes_agree: Option<bool>,
+    /// For traced results, the slot the triggering probe loaded its delegatecall target from,
+    /// as the trace saw it: a diamond's mapping entry rather than its base slot, the beacon
+    /// slot of beacon proxies.
+    pub raw_slot: Option<U256>,
+}
+
+impl ProxyDetectionResult {
+    pub fn new(proxy_type: ProxyType, dispatch: ProxyDispatch, rule: RuleId) -> Self {
+        Self { proxy_type, dispatch, rule, evasive: false, findings: Vec::new(), provenance: Vec::new(), attribution: None, blueprint: None, admin_slot: None, upgradeable_slots: Vec::new(), facet_slots: Vec::new(), slot_namespace: None, slot_preimage: None, slot_name: None, trigger_calldata: None, probes_agree: None, raw_slot: None }
+    }
+}
+
+/// A slot the traced code computed with KECCAK256: `keccak256(preimage)`, minus one like
+/// EIP-1967's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SlotPreimage {
+    pub preimage: Bytes,
+    pub minus_one: bool,
+}
+
+impl SlotPreimage {
+    /// The preimage as text when it's printable ASCII, e.g. `eip1967.proxy.implementation`.
+    pub fn name(&self) -> Option<&str> {
+        let printable = !self.preimage.is_empty() && self.preimage.iter().all(|b| b.is_ascii_graphic() || *b == b' ');
+        printable.then(|| std::str::from_utf8(&self.preimage).ok()).flatten()
+    }
+}
+
+/// The ERC-5202 blueprint a proxy was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BlueprintInfo {
+    pub version: u8,
+    /// Length of the preamble's data section.
+    pub data_len: usize,
+    /// The payload was run as initcode and its deployed code analysed, otherwise the payload
+    /// was analysed as runtime code.
+    pub initcode: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ProvenanceKind {
+    ImplementationAddress,
+    /// Address of a contract queried for the implementation (beacon, resolver).
+    BeaconAddress,
+    SlotConstant,
+    SelectorConstant,
+    /// Immutable args appended to a clone's runtime.
+    ImmutableArgs,
+}
+
+/// A byte range of the analysed bytecode holding a value reported by the detector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ByteProvenance {
+    pub kind: ProvenanceKind,
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl ByteProvenance {
+    pub fn new(kind: ProvenanceKind, offset: usize, length: usize) -> Self {
+        Self { kind, offset, length }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails to compile when a variant is added, to add it to [ProxyType::ALL] too.
+    fn listed(proxy_type: ProxyType) -> bool {
+        match proxy_type {
+            ProxyType::NoProxy | ProxyType::Unknown | ProxyType::EIP_1167 | ProxyType::EIP_3448 | ProxyType::EIP_7511
+            | ProxyType::SoladyClone | ProxyType::VyperForwarder | ProxyType::StaticAddress | ProxyType::EIP_897
+            | ProxyType::GnosisSafe | ProxyType::EIP_1967 | ProxyType::EIP_1967_TRANSPARENT | ProxyType::EIP_1967_CUSTOM
+            | ProxyType::ImmutableSlotProxy | ProxyType::CompoundDelegator | ProxyType::EIP_1967_ZOS | ProxyType::EIP_1967_BEACON
+            | ProxyType::EIP_1822 | ProxyType::UUPS | ProxyType::EIP_2535 | ProxyType::DiamondOther | ProxyType::GeneratedRouter
+            | ProxyType::External | ProxyType::OpenDelegateCall | ProxyType::ReadOnlyRouter | ProxyType::DelegatesButNotProxy
+            | ProxyType::CodePointer | ProxyType::Metamorphic => ProxyType::ALL.contains(&proxy_type),
+        }
+    }
+
+    #[test]
+    fn test_proxy_type_names() {
+        for proxy_type in ProxyType::ALL {
+            assert!(listed(*proxy_type));
+            let identifier = format!("{:?}", proxy_type);
+            for s in [proxy_type.to_string(), proxy_type.name().to_uppercase(), identifier.clone(), identifier.to_lowercase()] {
+                assert_eq!(s.parse::<ProxyType>().as_ref(), Ok(proxy_type), "{}", s);
+            }
+        }
+        let mut names: Vec<String> = ProxyType::ALL.iter().map(|proxy_type| proxy_type.name().to_lowercase()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), ProxyType::ALL.len());
+
+        assert_eq!(ProxyType::EIP_1967_ZOS.to_string(), "EIP-1967 ZeppelinOS Proxy");
+        assert_eq!("eip-2535 diamond".parse::<ProxyType>(), Ok(ProxyType::EIP_2535));
+        assert_eq!("EIP-1967".parse::<ProxyType>(), Err(ProxyTypeParseError("EIP-1967".to_string())));
+    }
 }
@@ -0,0 +1,255 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use alloy_primitives::Address;
+use ethers_core::types::BlockId;
+use ethers_providers::Middleware;
+use revm::interpreter::opcode;
+
+use crate::detect::{detect_proxy, DetectorConfig};
+use crate::disasm::disassemble;
+use crate::read::{get_code_at, get_proxy_implementation_at, ProxyImplementation, ProxyReadError};
+use crate::Selector;
+
+/// How the code of a contract differs between two versions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodeComparison {
+    Identical,
+    Changed(CodeDiffSummary),
+}
+
+impl CodeComparison {
+    /// The logic is the same, at most metadata and immutables differ.
+    pub fn same_logic(&self) -> bool {
+	match self {
+	    CodeComparison::Identical => true,
+	    CodeComparison::Changed(summary) => !summary.logic_changed,
+	}
+    }
+}
+
+/// What differs between two codes once normalized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodeDiffSummary {
+    pub before_len: usize,
+    pub after_len: usize,
+    /// The trailing CBOR metadata (compiler version, source hash) differs.
+    pub metadata_changed: bool,
+    /// PUSH32 immediates differing between otherwise identical instructions, which is how
+    /// immutables are embedded.
+    pub immutables_changed: usize,
+    /// Instructions differ beyond metadata and immutables.
+    pub logic_changed: bool,
+}
+
+/// Splits off the CBOR metadata solc and vyper append: a map followed by its big endian
+/// 2 byte length. Code without it has empty metadata.
+pub fn split_metadata(code: &[u8]) -> (&[u8], &[u8]) {
+    let Some(len_bytes) = code.len().checked_sub(2).map(|at| &code[at..]) else {
+	return (code, &[]);
+    };
+    let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+    match code.len().checked_sub(len + 2) {
+	// Maps of 1 to 5 entries, all the compilers emit
+	Some(start) if len > 0 && (0xa1..=0xa5).contains(&code[start]) => code.split_at(start),
+	_ => (code, &[]),
+    }
+}
+
+/// Compares two codes instruction by instruction after splitting their metadata. Differing
+/// PUSH32 immediates are counted as immutables; this also covers constants that changed
+/// between compilations, which can't be told apart without the sources.
+pub fn diff_code(before: &[u8], after: &[u8]) -> CodeComparison {
+    if before == after {
+	return CodeComparison::Identical;
+    }
+    let (before_body, before_metadata) = split_metadata(before);
+    let (after_body, after_metadata) = split_metadata(after);
+
+    let mut immutables_changed = 0;
+    let mut logic_changed = before_body.len() != after_body.len();
+    if !logic_changed {
+	for (a, b) in disassemble(before_body).zip(disassemble(after_body)) {
+	    if a.opcode != b.opcode {
+		logic_changed = true;
+		break;
+	    }
+	    if a.operand != b.operand {
+		if a.opcode == opcode::PUSH32 {
+		    immutables_changed += 1;
+		} else {
+		    logic_changed = true;
+		    break;
+		}
+	    }
+	}
+    }
+
+    CodeComparison::Changed(CodeDiffSummary {
+	before_len: before.len(),
+	after_len: after.len(),
+	metadata_changed: before_metadata != after_metadata,
+	immutables_changed,
+	logic_changed,
+    })
+}
+
+/// Compares the code deployed at `address` at `block_a` and `block_b`.
+pub async fn compare_proxy_code<M>(rpc: &M, address: &Address, block_a: u64, block_b: u64) -> Result<CodeComparison, ProxyReadError>
+    where M: Middleware
+{
+    let before = get_code_at(rpc, address, block_a).await?;
+    let after = get_code_at(rpc, address, block_b).await?;
+    Ok(diff_code(&before, &after))
+}
+
+/// Facets, or router modules, present at only one of the two blocks, and the selectors routed
+/// differently.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FacetDiff {
+    pub added: Vec<Address>,
+    pub removed: Vec<Address>,
+    /// Selectors only routed after, with their facet.
+    pub added_selectors: BTreeMap<Selector, Address>,
+    /// Selectors only routed before, with the facet they were routed to.
+    pub removed_selectors: BTreeMap<Selector, Address>,
+    /// Selectors routed to another facet after, `(before, after)`.
+    pub replaced_selectors: BTreeMap<Selector, (Address, Address)>,
+}
+
+/// Everything an upgrade between two blocks changed: the proxy's own code, the implementation
+/// it points to and, for diamonds, its facets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpgradeCharacterization {
+    pub proxy_code: CodeComparison,
+    /// The implementation at each block, `None` if the code wasn't a proxy with a readable one.
+    pub implementation_before: Option<ProxyImplementation>,
+    pub implementation_after: Option<ProxyImplementation>,
+    /// The code of the implementation at each block when there is a single one.
+    pub implementation_code: Option<CodeComparison>,
+    pub facets: Option<FacetDiff>,
+}
+
+impl UpgradeCharacterization {
+    pub fn implementation_changed(&self) -> bool {
+	self.implementation_before != self.implementation_after
+    }
+
+    /// Only the slot contents changed, the proxy's own logic is the same.
+    pub fn proxy_logic_unchanged(&self) -> bool {
+	self.proxy_code.same_logic()
+    }
+}
+
+/// The implementation `code` at `address` dispatches to as of `block`.
+async fn implementation_at<M>(rpc: &Arc<M>, address: &Address, code: &[u8], block: u64) -> Result<Option<ProxyImplementation>, ProxyReadError>
+    where M: Middleware + 'static
+{
+    let Some(result) = detect_proxy(code, &DetectorConfig::default()) else {
+	return Ok(None);
+    };
+    match get_proxy_implementation_at(rpc.clone(), address, &result.dispatch, Some(BlockId::from(block))).await {
+	Ok(implementation) => Ok(Some(implementation)),
+	Err(ProxyReadError::UnknownProxy) => Ok(None),
+	Err(e) => Err(e),
+    }
+}
+
+/// What changed from the implementation `before` to `after`. Only [ProxyImplementation::Facets]
+/// route selectors, so a proxy that becomes a diamond has all of its selectors added.
+pub fn diff_facets(before: &ProxyImplementation, after: &ProxyImplementation) -> FacetDiff {
+    let selectors = |implementation: &ProxyImplementation| match implementation {
+	ProxyImplementation::Facets(facets) => facets.iter().map(|(selector, facet)| (*selector, *facet)).collect(),
+	_ => BTreeMap::new(),
+    };
+    let (selectors_before, selectors_after): (BTreeMap<_, _>, BTreeMap<_, _>) = (selectors(before), selectors(after));
+    let (before, after) = (before.to_vec(), after.to_vec());
+    let mut diff = FacetDiff {
+	added: after.iter().filter(|a| !before.contains(a)).copied().collect(),
+	removed: before.iter().filter(|a| !after.contains(a)).copied().collect(),
+	added_selectors: selectors_after.iter().filter(|(selector, _)| !selectors_before.contains_key(selector)).map(|(selector, facet)| (*selector, *facet)).collect(),
+	removed_selectors: selectors_before.iter().filter(|(selector, _)| !selectors_after.contains_key(selector)).map(|(selector, facet)| (*selector, *facet)).collect(),
+	replaced_selectors: selectors_before.iter().filter_map(|(selector, before)| {
+	    selectors_after.get(selector).filter(|after| *after != before).map(|after| (*selector, (*before, *after)))
+	}).collect(),
+    };
+    diff.added.sort();
+    diff.removed.sort();
+    diff
+}
+
+/// Characterizes what changed at the proxy `address` between `block_a` and `block_b`: whether
+/// its own code changed and how, whether it points elsewhere and whether the implementation's
+/// code changed, and which facets were added or removed for diamonds.
+pub async fn characterize_upgrade<M>(rpc: Arc<M>, address: &Address, block_a: u64, block_b: u64) -> Result<UpgradeCharacterization, ProxyReadError>
+    where M: Middleware + 'static
+{
+    let before = get_code_at(rpc.as_ref(), address, block_a).await?;
+    let after = get_code_at(rpc.as_ref(), address, block_b).await?;
+    let implementation_before = implementation_at(&rpc, address, &before, block_a).await?;
+    let implementation_after = implementation_at(&rpc, address, &after, block_b).await?;
+
+    let implementation_code = match (&implementation_before, &implementation_after) {
+	(Some(ProxyImplementation::Single(a)), Some(ProxyImplementation::Single(b))) => {
+	    Some(compare_code_of(rpc.as_ref(), a, block_a, b, block_b).await?)
+	},
+	_ => None
+    };
+    let facets = match (&implementation_before, &implementation_after) {
+	(Some(a @ ProxyImplementation::Facets(_)), Some(b)) | (Some(a), Some(b @ ProxyImplementation::Facets(_))) => Some(diff_facets(a, b)),
+	_ => None
+    };
+
+    Ok(UpgradeCharacterization {
+	proxy_code: diff_code(&before, &after),
+	implementation_before,
+	implementation_after,
+	implementation_code,
+	facets,
+    })
+}
+
+async fn compare_code_of<M>(rpc: &M, before: &Address, block_a: u64, after: &Address, block_b: u64) -> Result<CodeComparison, ProxyReadError>
+    where M: Middleware
+{
+    let before = get_code_at(rpc, before, block_a).await?;
+    let after = get_code_at(rpc, after, block_b).await?;
+    Ok(diff_code(&before, &after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_metadata() {
+	let code = hex_literal::hex!("6080604052" "a164736f6c6343000811000a");
+	assert_eq!(split_metadata(&code), (&code[..5], &code[5..]));
+	// A length pointing past the start, or at something that isn't a map
+	assert_eq!(split_metadata(&hex_literal::hex!("60806040ffff")).1, &[] as &[u8]);
+	assert_eq!(split_metadata(&hex_literal::hex!("6080604052600a")).1, &[] as &[u8]);
+	assert_eq!(split_metadata(&[0x00]).1, &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_diff_code() {
+	let code = hex_literal::hex!("7f00000000000000000000000000000000000000000000000000000000000000aa5450" "a164736f6c6343000811000a");
+	assert_eq!(diff_code(&code, &code), CodeComparison::Identical);
+
+	let mut metadata = code;
+	metadata[40] = 0x12;
+	let CodeComparison::Changed(summary) = diff_code(&code, &metadata) else { panic!() };
+	assert!(summary.metadata_changed && !summary.logic_changed && summary.immutables_changed == 0);
+
+	let mut immutable = code;
+	immutable[32] = 0xbb;
+	let CodeComparison::Changed(summary) = diff_code(&code, &immutable) else { panic!() };
+	assert!(!summary.metadata_changed && !summary.logic_changed && summary.immutables_changed == 1);
+	assert!(diff_code(&code, &immutable).same_logic());
+
+	let mut logic = code;
+	logic[33] = 0x55;
+	let CodeComparison::Changed(summary) = diff_code(&code, &logic) else { panic!() };
+	assert!(summary.logic_changed);
+	assert!(!diff_code(&code, &code[..34]).same_logic());
+    }
+}
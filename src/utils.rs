@@ -1,7 +1,13 @@
 use ethers_core::types::{H160 as eH160, U256 as eU256, H256 as eH256, NameOrAddress as eNameOrAddress};
 use ethers_core::types::transaction::eip2930::AccessListItem;
 
-use alloy_primitives::{Address as rAddress, U256 as rU256};
+use alloy_primitives::{keccak256, Address as rAddress, Bytes, U256 as rU256};
+use revm::interpreter::{opcode, OpCode};
+use std::ops::Range;
+use thiserror::Error;
+
+use crate::blueprint::BLUEPRINT_MAGIC;
+use crate::disasm::{disassemble, Instruction};
 
 /// Ethers/Alloy/REVM trait to convert for types from one to another
 pub trait EARGlue<To> {
@@ -113,26 +119,184 @@ pub fn h256_to_raddress_unchecked(h256: &eH256) -> rAddress {
     rAddress::from_slice(&h256.as_fixed_bytes()[12..])
 }
 
+#[deprecated(note = "use `Selector::from_slice`")]
 #[inline(always)]
 pub fn slice_as_u32_be(array: &[u8]) -> u32 {
     ((array[0] as u32) << 24) +
     ((array[1] as u32) << 16) +
     ((array[2] as u32) <<  8) +
-    ((array[3] as u32) <<  0)
+    (array[3] as u32)
 }
 
+#[deprecated(note = "use `Selector::to_u32_be`")]
 #[inline(always)]
 pub fn as_u32_be(array: &[u8; 4]) -> u32 {
     ((array[0] as u32) << 24) +
     ((array[1] as u32) << 16) +
     ((array[2] as u32) <<  8) +
-    ((array[3] as u32) <<  0)
+    (array[3] as u32)
 }
 
+/// Byte swapped, a selector read through it doesn't match the usual `0x` form.
+#[deprecated(note = "use `Selector`, whose integer conversions are big endian")]
 #[inline(always)]
 pub fn as_u32_le(array: &[u8; 4]) -> u32 {
-    ((array[0] as u32) <<  0) +
+    (array[0] as u32) +
     ((array[1] as u32) <<  8) +
     ((array[2] as u32) << 16) +
     ((array[3] as u32) << 24)
 }
+
+/// The slot derived from `namespace` the way EIP-1967 derives its own, `keccak256(namespace) - 1`:
+/// `eip1967.proxy.implementation` gives the implementation slot.
+pub fn namespaced_slot(namespace: &str) -> rU256 {
+    rU256::from_be_bytes(keccak256(namespace).0).wrapping_sub(rU256::from(1))
+}
+
+/// The first of `namespaces` whose [namespaced_slot] is `slot`.
+pub fn match_namespace<'a, S: AsRef<str>>(namespaces: &'a [S], slot: &rU256) -> Option<&'a str> {
+    namespaces.iter().map(AsRef::as_ref).find(|namespace| namespaced_slot(namespace) == *slot)
+}
+
+/// The slots a proxy namespace derives like EIP-1967 does from `eip1967.proxy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NamespacedSlots {
+    /// `<prefix>.implementation`
+    pub implementation: rU256,
+    /// `<prefix>.admin`
+    pub admin: rU256,
+    /// `<prefix>.beacon`
+    pub beacon: rU256,
+}
+
+impl NamespacedSlots {
+    pub fn of(prefix: &str) -> Self {
+        Self {
+            implementation: namespaced_slot(&format!("{}.implementation", prefix)),
+            admin: namespaced_slot(&format!("{}.admin", prefix)),
+            beacon: namespaced_slot(&format!("{}.beacon", prefix)),
+        }
+    }
+}
+
+/// Bytecode parsed from user input, see [normalize_code_input].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodeInput {
+    /// Deployed code, to analyse as is.
+    RuntimeCode(Bytes),
+    /// A constructor returning `initcode[runtime]`, possibly followed by constructor args (a
+    /// creation transaction payload).
+    LikelyCreationCode { initcode: Bytes, runtime: Range<usize> },
+    /// Doesn't start with a defined opcode, unlikely to be code at all.
+    Unknown(Bytes),
+}
+
+impl CodeInput {
+    pub fn bytes(&self) -> &Bytes {
+        match self {
+            CodeInput::RuntimeCode(code) | CodeInput::Unknown(code) => code,
+            CodeInput::LikelyCreationCode { initcode, .. } => initcode,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum CodeInputError {
+    #[error("no bytecode in input")]
+    Empty,
+    /// `position` is the character index in the raw input.
+    #[error("invalid hex character {character:?} at position {position}")]
+    InvalidCharacter { position: usize, character: char },
+    #[error("odd number of hex digits, the digit at position {position} has no pair")]
+    OddLength { position: usize },
+    #[error("input looks ABI encoded ({zero_words} of {words} words start with zero bytes), not bytecode")]
+    LooksAbiEncoded { words: usize, zero_words: usize },
+}
+
+/// The opcodes of `code` with their offsets, PUSH immediates skipped so bytes of a constant
+/// aren't taken for instructions. See [disassemble] for the immediates.
+pub fn opcode_iter(code: &[u8]) -> impl Iterator<Item = (usize, u8)> + '_ {
+    disassemble(code).map(|ins| (ins.offset, ins.opcode))
+}
+
+/// The value a constant-pushing instruction at `idx` leaves on the stack, looking through a
+/// `DUP1` of one.
+fn pushed_constant(ins: &[Instruction], idx: usize) -> Option<usize> {
+    let i = ins.get(idx)?;
+    match i.opcode {
+        opcode::PUSH0 | opcode::RETURNDATASIZE => Some(0),
+        opcode::DUP1 => pushed_constant(ins, idx.checked_sub(1)?),
+        _ => i.push_value()?.try_into().ok(),
+    }
+}
+
+/// The runtime a constructor returns: a `CODECOPY` of a constant range of the code shortly
+/// followed by `RETURN`.
+fn constructor_runtime(code: &[u8]) -> Option<Range<usize>> {
+    let ins: Vec<Instruction> = disassemble(code).collect();
+    ins.iter().enumerate()
+        .filter(|(idx, i)| i.opcode == opcode::CODECOPY && *idx >= 3)
+        .find_map(|(idx, _)| {
+            let returns = ins[idx + 1..].iter().take(4).any(|i| i.opcode == opcode::RETURN);
+            // Stack: dest, offset, size
+            let offset = pushed_constant(&ins, idx - 2)?;
+            let size = pushed_constant(&ins, idx - 3)?;
+            let end = offset.checked_add(size)?;
+            (returns && offset > 0 && size > 0 && end <= code.len()).then_some(offset..end)
+        })
+}
+
+/// ABI encoded values are 32 byte words, mostly small numbers and addresses with zero high
+/// bytes. Returns the number of words and of those starting with 4 zero bytes.
+fn abi_words(bytes: &[u8]) -> Option<(usize, usize)> {
+    if bytes.len() < 64 || !bytes.len().is_multiple_of(32) || bytes[0] != 0 {
+        return None;
+    }
+    let words = bytes.len() / 32;
+    let zero_words = bytes.chunks(32).filter(|word| word[..4] == [0; 4]).count();
+    (zero_words * 4 >= words * 3).then_some((words, zero_words))
+}
+
+/// Parses bytecode pasted by users: hex with or without `0x`, possibly split by whitespace.
+///
+/// Classifies it as runtime or creation code (a constructor copying and returning a tail of
+/// itself) and rejects input that looks ABI encoded rather than being code.
+pub fn normalize_code_input(raw: &str) -> Result<CodeInput, CodeInputError> {
+    let trimmed = raw.trim_start();
+    let leading = raw.len() - trimmed.len();
+    let prefixed = trimmed.starts_with("0x") || trimmed.starts_with("0X");
+    let skip = raw[..leading].chars().count() + if prefixed { 2 } else { 0 };
+
+    let mut bytes = Vec::with_capacity(raw.len() / 2);
+    let mut pending: Option<(usize, u8)> = None;
+    for (position, character) in raw.chars().enumerate().skip(skip) {
+        if character.is_whitespace() {
+            continue;
+        }
+        let nibble = character.to_digit(16).ok_or(CodeInputError::InvalidCharacter { position, character })? as u8;
+        pending = match pending {
+            Some((_, high)) => {
+                bytes.push(high << 4 | nibble);
+                None
+            },
+            None => Some((position, nibble)),
+        };
+    }
+    if let Some((position, _)) = pending {
+        return Err(CodeInputError::OddLength { position });
+    }
+    if bytes.is_empty() {
+        return Err(CodeInputError::Empty);
+    }
+    if let Some((words, zero_words)) = abi_words(&bytes) {
+        return Err(CodeInputError::LooksAbiEncoded { words, zero_words });
+    }
+
+    if let Some(runtime) = constructor_runtime(&bytes) {
+        return Ok(CodeInput::LikelyCreationCode { initcode: bytes.into(), runtime });
+    }
+    if OpCode::new(bytes[0]).is_none() && !bytes.starts_with(&BLUEPRINT_MAGIC) {
+        return Ok(CodeInput::Unknown(bytes.into()));
+    }
+    Ok(CodeInput::RuntimeCode(bytes.into()))
+}
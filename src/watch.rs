@@ -0,0 +1,113 @@
+//! Following the implementation of a proxy as blocks come: [watch_implementation] polls it,
+//! [watch_implementation_logs] reads it again when the logs its upgrades emit arrive.
+
+use std::{sync::Arc, time::Duration};
+
+use alloy_primitives::Address;
+use ethers_core::types::{BlockId, Filter, H256};
+use ethers_providers::{Middleware, Provider, PubsubClient};
+use futures::{Stream, StreamExt};
+
+use crate::monitoring::{EventMonitoringAdvice, UpgradeSignal};
+use crate::read::{get_proxy_implementation_at, ProxyImplementation, ProxyReadError};
+use crate::utils::raddress_to_h160;
+use crate::ProxyDispatch;
+
+/// Time between two polls of [watch_implementation], about a block on mainnet.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(12);
+
+/// The implementation of a watched proxy as of `block`. The first one read has no `previous`,
+/// the next ones differ from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImplementationChange {
+    pub block: u64,
+    pub previous: Option<ProxyImplementation>,
+    pub current: ProxyImplementation,
+}
+
+/// What the proxy at `address` dispatching with `dispatch` delegates to at `block`, `Some` if it
+/// isn't `last`, which it becomes.
+async fn read_change<M>(rpc: &Arc<M>, address: &Address, dispatch: &ProxyDispatch, block: u64, last: &mut Option<ProxyImplementation>) -> Result<Option<ImplementationChange>, ProxyReadError>
+    where M: Middleware + 'static
+{
+    let current = get_proxy_implementation_at(rpc.clone(), address, dispatch, Some(BlockId::from(block))).await?;
+    if last.as_ref() == Some(&current) {
+	return Ok(None);
+    }
+    let previous = last.replace(current.clone());
+    Ok(Some(ImplementationChange { block, previous, current }))
+}
+
+/// The implementations of the proxy at `address`, read at each new block seen when polling the
+/// node every `interval`. Starts with the implementation at the latest block and yields again
+/// when it changes. Failed reads are yielded and polling goes on, the stream never ends.
+pub fn watch_implementation<M>(rpc: Arc<M>, address: Address, dispatch: ProxyDispatch, interval: Duration) -> impl Stream<Item = Result<ImplementationChange, ProxyReadError>> + Send
+    where M: Middleware + 'static
+{
+    async_stream::stream! {
+	let (mut last_block, mut last) = (None, None);
+	loop {
+	    match rpc.get_block_number().await {
+		Ok(block) if Some(block.as_u64()) > last_block => {
+		    match read_change(&rpc, &address, &dispatch, block.as_u64(), &mut last).await {
+			Ok(change) => {
+			    last_block = Some(block.as_u64());
+			    if let Some(change) = change {
+				yield Ok(change);
+			    }
+			},
+			Err(e) => yield Err(e),
+		    }
+		},
+		Ok(_) => {},
+		Err(e) => yield Err(ProxyReadError::from_middleware(e)),
+	    }
+	    tokio::time::sleep(interval).await;
+	}
+    }
+}
+
+/// [watch_implementation] for a node pushing logs, e.g. over a websocket: the implementation is
+/// read at the latest block, then at the block of each log `advice` watches. Only the
+/// [UpgradeSignal::Events] of `advice` are subscribed to, proxies with a
+/// [UpgradeSignal::StorageSlot] have to be polled. The stream ends with the subscription.
+pub fn watch_implementation_logs<P>(rpc: Arc<Provider<P>>, address: Address, dispatch: ProxyDispatch, advice: &EventMonitoringAdvice) -> impl Stream<Item = Result<ImplementationChange, ProxyReadError>>
+    where P: PubsubClient + 'static
+{
+    let (mut emitters, mut topics) = (Vec::new(), Vec::new());
+    for signal in &advice.signals {
+	if let UpgradeSignal::Events { address, topics: signal_topics } = signal {
+	    emitters.push(raddress_to_h160(address));
+	    topics.extend(signal_topics.iter().map(|topic| H256(topic.0)));
+	}
+    }
+    let filter = Filter::new().address(emitters).topic0(topics);
+    async_stream::stream! {
+	// Subscribed first so that no upgrade falls between the first read and the first log
+	let mut logs = match rpc.subscribe_logs(&filter).await {
+	    Ok(logs) => logs,
+	    Err(e) => {
+		yield Err(ProxyReadError::from_middleware(e));
+		return;
+	    },
+	};
+	let mut last = None;
+	match rpc.get_block_number().await {
+	    Ok(block) => match read_change(&rpc, &address, &dispatch, block.as_u64(), &mut last).await {
+		Ok(change) => if let Some(change) = change {
+		    yield Ok(change);
+		},
+		Err(e) => yield Err(e),
+	    },
+	    Err(e) => yield Err(ProxyReadError::from_middleware(e)),
+	}
+	while let Some(log) = logs.next().await {
+	    let Some(block) = log.block_number else { continue };
+	    match read_change(&rpc, &address, &dispatch, block.as_u64(), &mut last).await {
+		Ok(Some(change)) => yield Ok(change),
+		Ok(None) => {},
+		Err(e) => yield Err(e),
+	    }
+	}
+    }
+}
@@ -0,0 +1,62 @@
+//! The static matchers must not copy the code they are given, allocations are counted per
+//! thread so tests running in parallel don't interfere.
+
+use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell};
+
+use evm_proxy_tools::{detect_proxy, DetectorConfig, ProxyType};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATED: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| {
+            let (count, bytes) = allocated.get();
+            allocated.set((count + 1, bytes + layout.size()));
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// `(allocations, bytes)` done by `f` on this thread.
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, (usize, usize)) {
+    let before = ALLOCATED.with(Cell::get);
+    let result = f();
+    let after = ALLOCATED.with(Cell::get);
+    (result, (after.0 - before.0, after.1 - before.1))
+}
+
+fn padded(code: &[u8], len: usize) -> Vec<u8> {
+    let mut padded = code.to_vec();
+    padded.resize(len, 0xfe);
+    padded
+}
+
+#[test]
+fn test_static_path_does_not_copy() {
+    let config = DetectorConfig::default();
+    // Warm up the lazily built tables
+    detect_proxy(&hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3"), &config);
+
+    // Exact patterns allocate only the result's provenance, however long the code is
+    let clone = padded(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"), 24 * 1024);
+    let (result, (count, bytes)) = allocations(|| detect_proxy(&clone, &config));
+    assert_eq!(result.unwrap().proxy_type, ProxyType::EIP_1167);
+    assert_eq!(count, 1);
+    assert!(bytes < 256, "{} bytes allocated", bytes);
+
+    let short = hex_literal::hex!("363d3d373d3d3d363d6fbebebebebebebebebebebebebebebebe5af43d82803e903d91602757fd5bf3");
+    let (result, (count, _)) = allocations(|| detect_proxy(&short, &config));
+    assert_eq!(result.unwrap().proxy_type, ProxyType::EIP_1167);
+    assert_eq!(count, 1);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use alloy_primitives::{keccak256, Address, B256};
+use evm_proxy_tools::{attribute_clone, AttributionHints, CloneAttribution, CloneFactory, CreationCode, FactoryRegistry, ProxyReadError};
+use serde_json::{json, Value};
+
+use common::FnRpc;
+
+const IMPLEMENTATION: Address = Address::new(hex_literal::hex!("5a1d7e3c9b0f2486ae13c5d7092b8f64e1a3c7d9"));
+const FACTORY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000fa"));
+
+fn clone_runtime() -> Vec<u8> {
+    [&hex_literal::hex!("363d3d373d3d3d363d73")[..], IMPLEMENTATION.as_slice(), &hex_literal::hex!("5af43d82803e903d91602b57fd5bf3")].concat()
+}
+
+fn clone_address(factory: &Address, salt: B256) -> Address {
+    factory.create2(salt, keccak256(CreationCode::OzClones.init_code(&clone_runtime()).unwrap()))
+}
+
+/// Serves the clone's code at every address and `receipt` for the creation transaction.
+fn chain(receipt: Value) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, _| match method {
+        "eth_getCode" => Ok(json!(format!("0x{}", hex::encode(clone_runtime())))),
+        "eth_getTransactionReceipt" => Ok(receipt.clone()),
+        _ => Err(format!("unexpected {}", method)),
+    }
+}
+
+fn receipt(to: Option<Address>, contract_address: Option<Address>) -> Value {
+    json!({
+        "transactionHash": format!("0x{}", "11".repeat(32)),
+        "transactionIndex": "0x0",
+        "blockHash": format!("0x{}", "22".repeat(32)),
+        "blockNumber": "0x1",
+        "from": "0x00000000000000000000000000000000000000ee",
+        "to": to,
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "contractAddress": contract_address,
+        "logs": [],
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "status": "0x1",
+    })
+}
+
+#[tokio::test]
+async fn test_create2_attribution() {
+    let mut registry = FactoryRegistry::builtin();
+    registry.register(CloneFactory::new("test factory", FACTORY, CreationCode::OzClones));
+
+    // Salted with the implementation
+    let clone = clone_address(&FACTORY, IMPLEMENTATION.into_word());
+    let (rpc, _) = FnRpc::provider(chain(Value::Null));
+    let attribution = attribute_clone(&rpc, &registry, &clone, &IMPLEMENTATION, &AttributionHints::default()).await.unwrap();
+    assert_eq!(attribution, CloneAttribution { factory: Some(FACTORY), deterministic: true, salt: Some(IMPLEMENTATION.into_word()) });
+
+    // Salted with one of the caller's, through a builtin deployer
+    let deployer = registry.factories()[0].address;
+    let salt = B256::repeat_byte(0x42);
+    let clone = clone_address(&deployer, salt);
+    let hints = AttributionHints { salts: vec![B256::repeat_byte(0x01), salt], creation_tx: None };
+    let attribution = attribute_clone(&rpc, &registry, &clone, &IMPLEMENTATION, &hints).await.unwrap();
+    assert_eq!(attribution, CloneAttribution { factory: Some(deployer), deterministic: true, salt: Some(salt) });
+}
+
+#[tokio::test]
+async fn test_receipt_attribution() {
+    let clone = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+    let hints = AttributionHints { salts: Vec::new(), creation_tx: Some(B256::repeat_byte(0x11)) };
+
+    let (rpc, _) = FnRpc::provider(chain(receipt(Some(FACTORY), None)));
+    let attribution = attribute_clone(&rpc, &FactoryRegistry::builtin(), &clone, &IMPLEMENTATION, &hints).await.unwrap();
+    assert_eq!(attribution, CloneAttribution { factory: Some(FACTORY), deterministic: false, salt: None });
+
+    // Deployed directly by the transaction
+    let (rpc, _) = FnRpc::provider(chain(receipt(None, Some(clone))));
+    let attribution = attribute_clone(&rpc, &FactoryRegistry::builtin(), &clone, &IMPLEMENTATION, &hints).await.unwrap();
+    assert_eq!(attribution, CloneAttribution::default());
+}
+
+#[tokio::test]
+async fn test_unattributable_clone() {
+    let clone = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+    let (rpc, client) = FnRpc::provider(chain(Value::Null));
+    let attribution = attribute_clone(&rpc, &FactoryRegistry::builtin(), &clone, &IMPLEMENTATION, &AttributionHints::default()).await.unwrap();
+    assert_eq!(attribution, CloneAttribution::default());
+    assert_eq!(client.calls(), 1);
+
+    let (rpc, _) = FnRpc::provider(|_, _| Ok(json!("0x")));
+    assert!(matches!(attribute_clone(&rpc, &FactoryRegistry::builtin(), &clone, &IMPLEMENTATION, &AttributionHints::default()).await, Err(ProxyReadError::NoCode(_))));
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::process::{Command, Stdio};
+
+use ethers_core::abi::{encode, Token};
+use serde_json::{json, Value};
+
+use common::fixtures::{DIAMOND_STANDARD_CODE, EIP_1967_CODE};
+
+const CLONE_CODE: &str = "0x363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3";
+
+fn proxy_tools(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_proxy_tools")).args(args).output().unwrap()
+}
+
+/// Serves JSON-RPC over HTTP on a local port, answering requests with `handler`.
+fn serve(handler: fn(&str, &Value) -> Value) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if header == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": handler(request["method"].as_str().unwrap(), &request["params"]) }).to_string();
+                write!(stream, "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}", response.len(), response).unwrap();
+            }
+        });
+    });
+    url
+}
+
+fn golden(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/cli/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_json_clone() {
+    let output = proxy_tools(&["--code", CLONE_CODE, "--json"]);
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report, golden("clone"));
+}
+
+#[test]
+fn test_json_diamond() {
+    let code = format!("0x{}", hex::encode(DIAMOND_STANDARD_CODE));
+    let output = proxy_tools(&["--code", &code, "--format", "json"]);
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report, golden("diamond"));
+}
+
+#[test]
+fn test_offline() {
+    let output = Command::new(env!("CARGO_BIN_EXE_proxy_tools")).args(["--code-hex", CLONE_CODE]).env_remove("ETH_RPC_URL").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().to_lowercase().contains("proxy impl: 0xbebebebebebebebebebebebebebebebebebebebe"));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_proxy_tools"))
+        .args(["--code", "-", "--json"])
+        .env_remove("ETH_RPC_URL")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(format!("{}\n", CLONE_CODE).as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report, golden("clone"));
+}
+
+#[test]
+fn test_json_error() {
+    let output = proxy_tools(&["--code", "0xzz", "--json"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let error: Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert!(error["error"].as_str().unwrap().contains("invalid hex"));
+}
+
+#[test]
+fn test_batch() {
+    let url = serve(|method, params| match method {
+        "eth_chainId" => json!("0x1"),
+        "eth_getCode" if params[0] == "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa" => json!(CLONE_CODE),
+        "eth_getCode" if params[0] == "0xbebebebebebebebebebebebebebebebebebebebe" => json!("0x6080604052"),
+        "eth_getCode" => json!("0x"),
+        "eth_getStorageAt" => json!(format!("0x{}", "00".repeat(32))),
+        _ => json!("0x"),
+    });
+    let mut child = Command::new(env!("CARGO_BIN_EXE_proxy_tools"))
+        .args(["--batch", "-", "--json", "--summary", "--concurrency", "2", "--rpc-url", &url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = "# clones\n0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n\n0xcccccccccccccccccccccccccccccccccccccccc # no code\nnot an address\n";
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let lines: Vec<Value> = output.stdout.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+    assert_eq!(lines.len(), 4);
+    let record = |address: &str| lines.iter().find(|line| line["address"] == address).unwrap();
+    let clone = record("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+    assert_eq!(clone["proxy_type"], "EIP_1167");
+    assert_eq!(clone["implementations"], json!(["0xbebebebebebebebebebebebebebebebebebebebe"]));
+    assert_eq!(clone["chain"], json!(["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"]));
+    assert!(record("0xcccccccccccccccccccccccccccccccccccccccc")["error"].as_str().unwrap().contains("no code"));
+    assert!(record("not an address")["error"].as_str().unwrap().starts_with("invalid address"));
+    assert_eq!(lines[3], json!({ "summary": { "EIP_1167": 1, "error": 2 } }));
+}
+
+#[test]
+fn test_block_reaches_reads() {
+    // The implementation slot holds 0xbebe... at block 100 and 0xcafe... afterwards
+    let url = serve(|method, params| match method {
+        "eth_chainId" => json!("0x1"),
+        "eth_getCode" if params[0] == "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa" => json!(format!("0x{}", hex::encode(EIP_1967_CODE))),
+        "eth_getCode" => json!("0x6080604052"),
+        "eth_getStorageAt" if params[1] == "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc" => match params[2].as_str() {
+            Some("0x64") => json!(format!("0x{}{}", "00".repeat(12), "be".repeat(20))),
+            _ => json!(format!("0x{}{}", "00".repeat(12), "ca".repeat(20))),
+        },
+        "eth_getStorageAt" => json!(format!("0x{}", "00".repeat(32))),
+        _ => json!("0x"),
+    });
+    let implementations = |extra: &[&str]| {
+        let output = proxy_tools(&[&["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "--json", "--rpc-url", &url], extra].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice::<Value>(&output.stdout).unwrap()["implementations"].clone()
+    };
+    assert_eq!(implementations(&["--block", "100"]), json!(["0xbebebebebebebebebebebebebebebebebebebebe"]));
+    assert_eq!(implementations(&[]), json!(["0xcacacacacacacacacacacacacacacacacacacaca"]));
+}
+
+/// The diamond at 0xaa..: no code before block 100, then `diamondCut` and `facets()` on facet
+/// 0xbb.., from block 200 `facets()` moved to 0xcc.. with `owner()`.
+fn diamond_upgrades(method: &str, params: &Value) -> Value {
+    let block = |param: &Value| u64::from_str_radix(param.as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+    let facet = |address: u8, selectors: &[u32]| Token::Tuple(vec![
+        Token::Address([address; 20].into()),
+        Token::Array(selectors.iter().map(|selector| Token::FixedBytes(selector.to_be_bytes().to_vec())).collect()),
+    ]);
+    match method {
+        "eth_chainId" => json!("0x1"),
+        "eth_getCode" if params[0] == "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa" && block(&params[1]) < 100 => json!("0x"),
+        "eth_getCode" if params[0] == "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa" => json!(format!("0x{}", hex::encode(DIAMOND_STANDARD_CODE))),
+        "eth_getCode" => json!("0x6080604052"),
+        "eth_call" if block(&params[1]) < 200 => json!(format!("0x{}", hex::encode(encode(&[Token::Array(vec![facet(0xbb, &[0x1f931c1c, 0x7a0ed627])])])))),
+        "eth_call" => json!(format!("0x{}", hex::encode(encode(&[Token::Array(vec![facet(0xbb, &[0x1f931c1c]), facet(0xcc, &[0x7a0ed627, 0x8da5cb5b])])])))),
+        _ => json!(format!("0x{}", "00".repeat(32))),
+    }
+}
+
+#[test]
+fn test_diff() {
+    let url = serve(diamond_upgrades);
+    let output = proxy_tools(&["diff", "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "--from-block", "150", "--to-block", "250", "--json", "--rpc-url", &url]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let diff: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(diff["added_facets"], json!(["0xcccccccccccccccccccccccccccccccccccccccc"]));
+    assert_eq!(diff["added"], json!([{ "selector": "0x8da5cb5b", "function": "owner()", "facet": "0xcccccccccccccccccccccccccccccccccccccccc" }]));
+    assert_eq!(diff["removed"], json!([]));
+    assert_eq!(diff["replaced"], json!([{ "selector": "0x7a0ed627", "function": "facets()", "before": "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", "after": "0xcccccccccccccccccccccccccccccccccccccccc" }]));
+
+    // Not deployed yet at block 50, every selector is added
+    let output = proxy_tools(&["diff", "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "--from-block", "50", "--to-block", "150", "--rpc-url", &url]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("added     0x1f931c1c") && text.contains("diamondCut("), "{}", text);
+    assert_eq!(text.lines().filter(|line| line.trim_start().starts_with("added")).count(), 2, "{}", text);
+}
+
+static WATCHED_HEAD: AtomicU64 = AtomicU64::new(0);
+
+#[test]
+fn test_watch() {
+    // An EIP-1967 proxy upgraded from 0xbb.. to 0xcc.. at block 3, a new block per poll
+    let url = serve(|method, params| match method {
+        "eth_chainId" => json!("0x1"),
+        "eth_blockNumber" => json!(format!("{:#x}", WATCHED_HEAD.fetch_add(1, Ordering::SeqCst) + 1)),
+        "eth_getCode" => json!(format!("0x{}", hex::encode(EIP_1967_CODE))),
+        "eth_getStorageAt" => {
+            let block = u64::from_str_radix(params[2].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+            let implementation = if block >= 3 { "cc" } else { "bb" };
+            json!(format!("0x{}{}", "00".repeat(12), implementation.repeat(20)))
+        },
+        _ => json!("0x"),
+    });
+    let hook = std::env::temp_dir().join(format!("proxy_tools_watch_{}", std::process::id()));
+    let exec = format!("echo \"$PROXY_BLOCK $PROXY_PREVIOUS $PROXY_CURRENT\" > {}.tmp && mv {0}.tmp {0}", hook.display());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_proxy_tools"))
+        .args(["watch", "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "--interval", "10ms", "--exec", &exec, "--rpc-url", &url])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines().map(|line| line.unwrap().to_lowercase()).filter(|line| !line.starts_with("args"));
+    let (first, second) = (lines.next().unwrap(), lines.next().unwrap());
+    // The hook runs once the change is printed
+    let hook_output = (0..500).find_map(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::read_to_string(&hook).ok()
+    });
+    child.kill().unwrap();
+    child.wait().unwrap();
+    let _ = std::fs::remove_file(&hook);
+
+    let (bb, cc) = (format!("0x{}", "bb".repeat(20)), format!("0x{}", "cc".repeat(20)));
+    assert_eq!(first, format!("watching 0x{}, implementation {} at block 1", "aa".repeat(20), bb));
+    assert_eq!(second, format!("block 3: {} -> {}", bb, cc));
+    assert_eq!(hook_output.unwrap().to_lowercase(), format!("3 {} {}\n", bb, cc));
+}
@@ -0,0 +1,114 @@
+//! Runtime bytecode shared by the integration tests.
+
+// https://etherscan.io/address/0xdd28b7fd7780e9388582af20e5247e1dcbac8ae9#code
+pub const EIP_1967_CODE: &[u8] = &hex_literal::hex!("60806040523661001357610011610017565b005b6100115b61004a7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc546001600160a01b031661007b565b565b90565b606061007483836040518060600160405280602781526020016102316027913961009f565b9392505050565b3660008037600080366000845af43d6000803e80801561009a573d6000f35b3d6000fd5b6060833b6101035760405162461bcd60e51b815260206004820152602660248201527f416464726573733a2064656c65676174652063616c6c20746f206e6f6e2d636f6044820152651b9d1c9858dd60d21b60648201526084015b60405180910390fd5b600080856001600160a01b03168560405161011e91906101b1565b600060405180830381855af49150503d8060008114610159576040519150601f19603f3d011682016040523d82523d6000602084013e61015e565b606091505b509150915061016e828286610178565b9695505050505050565b60608315610187575081610074565b8251156101975782518084602001fd5b8160405162461bcd60e51b81526004016100fa91906101cd565b600082516101c3818460208701610200565b9190910192915050565b60208152600082518060208401526101ec816040850160208701610200565b601f01601f19169190910160400192915050565b60005b8381101561021b578181015183820152602001610203565b8381111561022a576000848401525b5050505056fe416464726573733a206c6f772d6c6576656c2064656c65676174652063616c6c206661696c6564a2646970667358221220727e9c7322af70a33c460d6c97b3533591ca0a1b66f567d29a66e092f79e0a0d64736f6c63430008070033");
+
+// https://etherscan.io/address/0x1715a3e4a142d8b698131108995174f37aeba10d#code
+pub const EIP_897_CODE: &[u8] = &hex_literal::hex!("6080604052600436106100555760003560e01c80633ad06d161461009e57806354fd4d50146100d95780635c60da1b146101005780636fde820214610131578063a9c45fcb14610146578063f1739cae146101cb575b600061005f6101fe565b90506001600160a01b03811661007457600080fd5b60405136600082376000803683855af43d82016040523d6000833e80801561009a573d83f35b3d83fd5b3480156100aa57600080fd5b506100d7600480360360408110156100c157600080fd5b50803590602001356001600160a01b031661020d565b005b3480156100e557600080fd5b506100ee610240565b60408051918252519081900360200190f35b34801561010c57600080fd5b506101156101fe565b604080516001600160a01b039092168252519081900360200190f35b34801561013d57600080fd5b50610115610246565b6100d76004803603606081101561015c57600080fd5b8135916001600160a01b036020820135169181019060608101604082013564010000000081111561018c57600080fd5b82018360208201111561019e57600080fd5b803590602001918460018302840111640100000000831117156101c057600080fd5b509092509050610255565b3480156101d757600080fd5b506100d7600480360360208110156101ee57600080fd5b50356001600160a01b03166102fe565b600061020861038d565b905090565b610215610246565b6001600160a01b0316336001600160a01b03161461023257600080fd5b61023c828261039c565b5050565b60075490565b6006546001600160a01b031690565b61025d610246565b6001600160a01b0316336001600160a01b03161461027a57600080fd5b610284848461020d565b6000306001600160a01b0316348484604051808383808284376040519201945060009350909150508083038185875af1925050503d80600081146102e4576040519150601f19603f3d011682016040523d82523d6000602084013e6102e9565b606091505b50509050806102f757600080fd5b5050505050565b610306610246565b6001600160a01b0316336001600160a01b03161461032357600080fd5b6001600160a01b03811661033657600080fd5b7f5a3e66efaa1e445ebd894728a69d6959842ea1e97bd79b892797106e270efcd961035f610246565b604080516001600160a01b03928316815291841660208301528051918290030190a161038a81610432565b50565b6008546001600160a01b031690565b6008546001600160a01b03828116911614156103b757600080fd5b6103c081610454565b6103c957600080fd5b60075482116103d757600080fd5b6007829055600880546001600160a01b0383166001600160a01b031990911681179091556040805184815290517f4289d6195cf3c2d2174adf98d0e19d4d2d08887995b99cb7b100e7ffe795820e9181900360200190a25050565b600680546001600160a01b0319166001600160a01b0392909216919091179055565b6000813f7fc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a47081811480159061048857508115155b94935050505056fea2646970667358221220c0ef938c3cb0aabada971e1d0565a4ce5504320f0416427bd7838d4790e313e164736f6c63430007050033");
+
+pub const DIAMOND_STANDARD_CODE: &[u8] = &hex_literal::hex!("6080604052600436101561001e575b361561001c5761001c61131a565b005b60003560e01c806301ffc9a71461010e5780631f931c1c146101055780632c408059146100fc57806352ef6b2c146100f357806379ba5097146100ea5780637a0ed627146100e15780638ab5150a146100d85780638da5cb5b146100cf57806391423765146100c6578063adfca15e146100bd578063cdffacc6146100b45763f2fde38b0361000e576100af611160565b61000e565b506100af6110cc565b506100af610eed565b506100af610dc6565b506100af610d54565b506100af610ce2565b506100af610968565b506100af610694565b506100af6103ff565b506100af61033c565b506100af6102a3565b506100af610178565b600435907fffffffff000000000000000000000000000000000000000000000000000000008216820361014657565b600080fd5b35907fffffffff000000000000000000000000000000000000000000000000000000008216820361014657565b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc360112610146577fffffffff000000000000000000000000000000000000000000000000000000006101d1610117565b166000527f326d0c59a7612f6a9919e2a8ee333c80ba689d8ba2634de89c85cbb04832e705602052602060ff604060002054166040519015158152f35b6024359073ffffffffffffffffffffffffffffffffffffffff8216820361014657565b6004359073ffffffffffffffffffffffffffffffffffffffff8216820361014657565b359073ffffffffffffffffffffffffffffffffffffffff8216820361014657565b9181601f840112156101465782359167ffffffffffffffff8311610146576020838186019501011161014657565b50346101465760607ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc3601126101465760043567ffffffffffffffff808211610146573660238301121561014657816004013591818311610146573660248460051b830101116101465761031561020e565b6044359283116101465761001c936103336024943690600401610275565b949093016116f9565b50346101465760007ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657602073ffffffffffffffffffffffffffffffffffffffff7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc965416604051908152f35b6020908160408183019282815285518094520193019160005b8281106103d5575050505090565b835173ffffffffffffffffffffffffffffffffffffffff16855293810193928101926001016103c7565b5034610146576000807ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc3601126106915761046661045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b61ffff1690565b90610470826115c9565b908080815b858210610491578385526040518061048d87826103ae565b0390f35b6104c4816000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5483905b600882106104e1575b50506104dc9061163e565b610475565b9195939690926104f09061163e565b94818611610684576105a66105a061057a7fffffffff00000000000000000000000000000000000000000000000000000000868860051b1b167fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b547fffffffffffffffffffffffffffffffffffffffff0000000000000000000000001690565b60601c90565b8873ffffffffffffffffffffffffffffffffffffffff8216815b848110610621575b505061061657816105fe610603926105e3610609958a6116be565b9073ffffffffffffffffffffffffffffffffffffffff169052565b61163e565b9361163e565b90969395919492946104c8565b50926106099061163e565b61066461064b610631838c6116be565b5173ffffffffffffffffffffffffffffffffffffffff1690565b73ffffffffffffffffffffffffffffffffffffffff1690565b8214610678576106739061163e565b6105c0565b505050600138806105c8565b94928197949692506104d1565b80fd5b5034610146576000807ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc3601126106915773ffffffffffffffffffffffffffffffffffffffff807f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce6617890541633036107e55733907f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f67168046054167f8be0079c531659141344cd1fd0a4f28419497f9722a3daafe3b4186f6b6457e08380a37f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f67168046080547fffffffffffffffffffffffff000000000000000000000000000000000000000016331790556107e27f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce66178907fffffffffffffffffffffffff00000000000000000000000000000000000000008154169055565b80f35b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602960248201527f536166654f776e61626c653a2073656e646572206d757374206265206e6f6d6960448201527f6e6565206f776e657200000000000000000000000000000000000000000000006064820152fd5b90815180825260208080930193019160005b828110610889575050505090565b83517fffffffff00000000000000000000000000000000000000000000000000000000168552938101939281019260010161087b565b602080820190808352835180925260409283810182858560051b8401019601946000925b8584106108f4575050505050505090565b909192939495968580610957837fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc0866001960301885286838d5173ffffffffffffffffffffffffffffffffffffffff815116845201519181858201520190610869565b9901940194019295949391906108e3565b5034610146576000807ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc360112610691576109c861045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b6109d181611532565b906109db816115c9565b92809181825b828210610a3f575050505b818110610a04578183526040518061048d85826108bf565b80610a25610a1f610a18610a3a94886116be565b5160ff1690565b60ff1690565b6020610a3183876116be565b5101515261163e565b6109ec565b610a72816000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5484905b60088210610a8f575b5050610a8a9061163e565b6109e1565b9093919692610aa09098959861163e565b95828711610cd5577fffffffff00000000000000000000000000000000000000000000000000000000828660051b1b16610b2b6105a061057a837fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b8a73ffffffffffffffffffffffffffffffffffffffff8216815b858110610c12575b5050610c065791610bde610bf192610b88610bf795610b6c858b6116be565b519073ffffffffffffffffffffffffffffffffffffffff169052565b610bb7610b94886115c9565b60209081610ba2878d6116be565b510152610baf858b6116be565b5101516116a8565b907fffffffff00000000000000000000000000000000000000000000000000000000169052565b6105fe610beb828a6116be565b60019052565b9461163e565b90979497969193959296610a76565b505093610bf79061163e565b8a858a84610c4161064b610c2687856116be565b515173ffffffffffffffffffffffffffffffffffffffff1690565b14610c5757505050610c529061163e565b610b45565b610ccc955083809550610cbd93610c99610cb894610bb76020610c80610cc49a610a18986116be565b510151610c93610a1f610a1888886116be565b906116be565b610cb360ff80610cac610a1886866116be565b16106116e0565b6116be565b6116e7565b918b6116be565b9060ff169052565b60013880610b4d565b9592969193979497610a7f565b50346101465760007ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657602073ffffffffffffffffffffffffffffffffffffffff7f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce66178905416604051908152f35b50346101465760007ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657602073ffffffffffffffffffffffffffffffffffffffff7f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416604051908152f35b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657610dfe610231565b73ffffffffffffffffffffffffffffffffffffffff610e41817f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416331461122a565b7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc9691167fffffffffffffffffffffffff0000000000000000000000000000000000000000825416179055600080f35b6020908160408183019282815285518094520193019160005b828110610eb7575050505090565b83517fffffffff000000000000000000000000000000000000000000000000000000001685529381019392810192600101610ea9565b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657610f25610231565b610f5461045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b90610f5e826115c9565b9060009073ffffffffffffffffffffffffffffffffffffffff1681805b858210610f93578385526040518061048d8782610e90565b610fc6816000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5460005b60088110610fe3575b5050610fde9061163e565b610f7b565b9492610ff49097919796929661163e565b948186116110be577fffffffff00000000000000000000000000000000000000000000000000000000888260051b1b1661108261064b6105a061057a847fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b86146110a1575b506110939061163e565b969096959195949294610fca565b846105fe6110b792610bb76110939598886116be565b9390611089565b819750959195949294610fd3565b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc360112610146577fffffffff00000000000000000000000000000000000000000000000000000000611125610117565b166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052602060406000205460601c604051908152f35b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657611198610231565b73ffffffffffffffffffffffffffffffffffffffff6111db817f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416331461122a565b7f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce661789091167fffffffffffffffffffffffff0000000000000000000000000000000000000000825416179055600080f35b1561123157565b60646040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152601d60248201527f4f776e61626c653a2073656e646572206d757374206265206f776e65720000006044820152fd5b1561129657565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602660248201527f50726f78793a20696d706c656d656e746174696f6e206d75737420626520636f60448201527f6e747261637400000000000000000000000000000000000000000000000000006064820152fd5b5060007fffffffff0000000000000000000000000000000000000000000000000000000081351681527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc9360205280604081205460601c801561139f575b8061138583923b151561128f565b368280378136915af43d82803e1561139b573d90f35b3d90fd5b505073ffffffffffffffffffffffffffffffffffffffff7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc96541680156113e6578190611377565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152603260248201527f4469616d6f6e64426173653a206e6f20666163657420666f756e6420666f722060448201527f66756e6374696f6e207369676e617475726500000000000000000000000000006064820152fd5b507f4e487b7100000000000000000000000000000000000000000000000000000000600052604160045260246000fd5b604051906060820182811067ffffffffffffffff8211176114ba57604052565b6114c261146a565b604052565b907fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0601f604051930116820182811067ffffffffffffffff8211176114ba57604052565b60209067ffffffffffffffff8111611525575b60051b0190565b61152d61146a565b61151e565b9061154461153f8361150b565b6114c7565b8281527fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0611572829461150b565b0190600090815b8381106115865750505050565b60209060408051908082019082821067ffffffffffffffff8311176115bc575b5284815282606081830152828501015201611579565b6115c461146a565b6115a6565b906115d661153f8361150b565b8281527fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0611604829461150b565b0190602036910137565b507f4e487b7100000000000000000000000000000000000000000000000000000000600052601160045260246000fd5b6001907fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff811461166c570190565b61167461160e565b0190565b507f4e487b7100000000000000000000000000000000000000000000000000000000600052603260045260246000fd5b6020908051156116b6570190565b611674611678565b60209181518110156116d3575b60051b010190565b6116db611678565b6116cb565b1561014657565b60ff6001911660ff811461166c570190565b949390929461174073ffffffffffffffffffffffffffffffffffffffff7f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416331461122a565b61174c61153f8561150b565b9081948083526020809301600591821b8301923684116101465780915b84831061178e5750505050505061178c93946117869136916118a2565b91611b14565b565b67ffffffffffffffff8335818111610146578301606081360312610146576117b461149a565b916117be82610254565b835288820135600381101561014657898401526040918281013591821161014657019036601f83011215610146578135916117fb61153f8461150b565b928a808583815201918a1b8301019136831161014657918b80969492979593015b818110611836575050849550820152815201920191611769565b91939580919395976118478461014b565b8152019101918b95939196949261181c565b7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0601f60209267ffffffffffffffff8111611895575b01160190565b61189d61146a565b61188f565b9291926118b161153f83611859565b938285528282011161014657816000926020928387013784010152565b600311156118d857565b7f4e487b7100000000000000000000000000000000000000000000000000000000600052602160045260246000fd5b1561190e57565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602360248201527f4469616d6f6e64426173653a206e6f2073656c6563746f72732073706563696660448201527f69656400000000000000000000000000000000000000000000000000000000006064820152fd5b919082519283825260005b8481106119dc5750507fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0601f8460006020809697860101520116010190565b60208183018101518483018201520161199d565b93929091936060928382019380835281518095526080830160808660051b85010195602080940192600080915b838310611a6057505050505050611a5d9495611a509183019073ffffffffffffffffffffffffffffffffffffffff169052565b6040818403910152611992565b90565b9091929394987fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff8088820301865289519073ffffffffffffffffffffffffffffffffffffffff8251168152878201516003811015611ae757611ad960019385848c9594868096015281604080940151938201520190610869565b9b0196019493019190611a1d565b6024857f4e487b710000000000000000000000000000000000000000000000000000000081526021600452fd5b9091611b4561045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b91829460009260078516611cf2575b6000935b8351851015611c0b57611b6b85856116be565b51906020820151611b7b816118ce565b611b8b6040840151511515611907565b611b94816118ce565b80611bb3575090611ba89160019697611e47565b9490955b0193611b58565b611bc081979392976118ce565b60018103611bda575090611bd560019261275b565b611bac565b80611be66002926118ce565b14611bf5575b50600190611bac565b600195611c02929761221c565b94909590611bec565b8694507f8faa70878671ccd212d20771b795c50af8fd3ff6cf27f4bde57e5d4de0aeb67393919561178c9793988103611ca0575b60078116611c62575b5050611c5a85604051938493846119f0565b0390a161298d565b611c989060031c6000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b553880611c48565b7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc9480547fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00001661ffff8316179055611c3f565b9250611d2a8460031c6000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5492611b54565b15611d3857565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602360248201527f4469616d6f6e64426173653a204144442074617267657420686173206e6f206360448201527f6f646500000000000000000000000000000000000000000000000000000000006064820152fd5b15611dc357565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602360248201527f4469616d6f6e64426173653a2073656c6563746f7220616c726561647920616460448201527f64656400000000000000000000000000000000000000000000000000000000006064820152fd5b90929192611e93611e6c855173ffffffffffffffffffffffffffffffffffffffff1690565b3073ffffffffffffffffffffffffffffffffffffffff821614908115612096575b50611d31565b60009384925b6040820151805185101561208c57611eb485611eda926116be565b517fffffffff000000000000000000000000000000000000000000000000000000001690565b611f41611f3b61064b6105a061057a857fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b15611dbc565b817fffffffffffffffffffffffffffffffffffffffff000000000000000000000000611fac611f84865173ffffffffffffffffffffffffffffffffffffffff1690565b60601b7fffffffffffffffffffffffffffffffffffffffff0000000000000000000000001690565b1617612003827fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b5560e090818360051b16947fffffffff00000000000000000000000000000000000000000000000000000000809216861c91861c191617931461204d575b60019384019301611e99565b916120848360031c6000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b558491612041565b5092505092509190565b90503b151538611e8d565b156120a857565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602f60248201527f4469616d6f6e64426173653a2052454d4f564520746172676574206d7573742060448201527f6265207a65726f206164647265737300000000000000000000000000000000006064820152fd5b1561213357565b60646040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152601f60248201527f4469616d6f6e64426173653a2073656c6563746f72206e6f7420666f756e64006044820152fd5b1561219857565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602260248201527f4469616d6f6e64426173653a2073656c6563746f7220697320696d6d7574616260448201527f6c650000000000000000000000000000000000000000000000000000000000006064820152fd5b9061225a73ffffffffffffffffffffffffffffffffffffffff612253855173ffffffffffffffffffffffffffffffffffffffff1690565b16156120a1565b600780831692600390600090821c5b6040870151805183101561263757611eb483612284926116be565b946122da867fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b54966122f68860601c6122ee81151561212c565b301415612191565b816126075750507fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0190612353826000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5494849687915b60e07fffffffff000000000000000000000000000000000000000000000000000000009260006123e7858c600598891b1b169486811686036124d0577fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b5580881c611fff16941b16918584146124be579061246c9291612433856000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5491831c921c191617916000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b555b851561247e575b60010190612269565b935060006124b5826000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b55600093612475565b9180949893501c921c1916179361246e565b61255061252b61057a887fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b7fffffffffffffffffffffffffffffffffffffffff0000000000000000000000001690565b6bffffffffffffffffffffffff8516176125b5877fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b557fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0196909591929187919061235a565b50939695505090501b179190565b1561264c57565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602760248201527f4469616d6f6e64426173653a205245504c41434520746172676574206861732060448201527f6e6f20636f6465000000000000000000000000000000000000000000000000006064820152fd5b156126d757565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602860248201527f4469616d6f6e64426173653a205245504c41434520746172676574206973206960448201527f64656e746963616c0000000000000000000000000000000000000000000000006064820152fd5b61278a61278561277f835173ffffffffffffffffffffffffffffffffffffffff1690565b3b151590565b612645565b60005b60408201519081518110156128d7576127ab611eb4826001946116be565b6128d0612803827fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b5461284a8160601c61281681151561212c565b61282230821415612191565b61284361064b895173ffffffffffffffffffffffffffffffffffffffff1690565b14156126d0565b6bffffffffffffffffffffffff61287b61252b611f84895173ffffffffffffffffffffffffffffffffffffffff1690565b911617917fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b550161278d565b505050565b156128e357565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602e60248201527f4469616d6f6e64426173653a20696e697469616c697a6174696f6e207461726760448201527f657420686173206e6f20636f64650000000000000000000000000000000000006064820152fd5b3d15612988573d9061297b61153f83611859565b9182523d6000602084013e565b606090565b9073ffffffffffffffffffffffffffffffffffffffff821690811581511581036129fb57156129bb57505050565b600092839230036129ea575b602082519201905af46129d8612967565b50156129e057565b3d6000803e3d6000fd5b6129f6813b15156128dc565b6129c7565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602e60248201527f4469616d6f6e64426173653a20696e76616c696420696e697469616c697a617460448201527f696f6e20706172616d65746572730000000000000000000000000000000000006064820152fdfea164736f6c6343000811000a");
+
+// ERC-20 with Ownable, Pausable and Permit (22 functions, `mint` payable, `receive` but no
+// `fallback`). Hand assembled following the dispatcher solc's legacy codegen emits past four
+// functions: selectors sorted and split on `DUP1 PUSH4 pivot GT PUSH2 lower JUMPI`, function
+// bodies reduced to their CALLVALUE guard.
+pub const ERC20_BINARY_SEARCH_CODE: &[u8] = &hex_literal::hex!("6080604052600436106101795760003560e01c806370a08231116100cb57806395d89b411161007f578063d505accf11610059578063d505accf14610295578063dd62ed3e146102a4578063f2fde38b146102b357610179565b806395d89b4114610268578063a457c2d714610277578063a9059cbb1461028657610179565b80637ecebe00116100b05780637ecebe001461023b5780638456cb591461024a5780638da5cb5b1461025957610179565b806370a082311461021d578063715018a61461022c57610179565b80633644e5151161012d57806340c10f191161010757806340c10f19146101fd57806342966c68146101ff5780635c975abb1461020e57610179565b80633644e515146101d057806339509351146101df5780633f4ba83a146101ee57610179565b806318160ddd1161015e57806318160ddd146101a357806323b872dd146101b2578063313ce567146101c157610179565b806306fdde0314610185578063095ea7b31461019457610179565b3661018057005b600080fd5b34801561019157600080fd5b50005b3480156101a057600080fd5b50005b3480156101af57600080fd5b50005b3480156101be57600080fd5b50005b3480156101cd57600080fd5b50005b3480156101dc57600080fd5b50005b3480156101eb57600080fd5b50005b3480156101fa57600080fd5b50005b005b34801561020b57600080fd5b50005b34801561021a57600080fd5b50005b34801561022957600080fd5b50005b34801561023857600080fd5b50005b34801561024757600080fd5b50005b34801561025657600080fd5b50005b34801561026557600080fd5b50005b34801561027457600080fd5b50005b34801561028357600080fd5b50005b34801561029257600080fd5b50005b3480156102a157600080fd5b50005b3480156102b057600080fd5b50005b3480156102bf57600080fd5b5000");
+
+// ERC-5202 blueprint (version 0, no data) of the OpenZeppelin Clones initcode of an EIP-1167 forwarder to 0xbebe...be
+pub const BLUEPRINT_1167_CODE: &[u8] = &hex_literal::hex!("fe7100" "3d602d80600a3d3981f3" "363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3");
+
+// Same payload, version 1 with a 3 byte data section
+pub const BLUEPRINT_1167_DATA_CODE: &[u8] = &hex_literal::hex!("fe7105" "03" "aabbcc" "3d602d80600a3d3981f3" "363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3");
+
+// Generated router in the shape of Synthetix's: the selector picks a hardcoded module, owner
+// functions to 0x1111...11 and upgrade functions to 0x2222...22, for a shared delegatecall
+// block. Hand assembled with the lookup inlined and its `switch` cases split on
+// `PUSH4 pivot DUP3 LT ISZERO PUSH2 upper JUMPI` as in the generator's binary search layout.
+// Not compiler output: the module targets are placeholders, a deployed router's runtime should
+// be added alongside once captured from a node.
+pub const GENERATED_ROUTER_CODE: &[u8] = &hex_literal::hex!("608060405260003560e01c60006379ba509782101561005a576353a47bb782101561003f57631627540c821461009c57633659cfe682146100b8576100d4565b6353a47bb7821461009c5763718fe928821461009c576100d4565b63aaf10f42821015610081576379ba5097821461009c57638da5cb5b821461009c576100d4565b63aaf10f4282146100b85763c7f62cda82146100b8576100d4565b73111111111111111111111111111111111111111190506100d4565b73222222222222222222222222222222222222222290506100d4565b9050806100e057600080fd5b3660008037600080366000845af43d6000803e6100fc573d6000fd5b3d6000f3");
+
+// The same router with a single `switch` over four selectors, the generator's if-chain layout.
+// Hand assembled likewise.
+pub const GENERATED_ROUTER_LINEAR_CODE: &[u8] = &hex_literal::hex!("608060405260003560e01c6000631627540c821461003d57633659cfe68214610059576379ba5097821461003d57638da5cb5b821461003d57610075565b7311111111111111111111111111111111111111119050610075565b7322222222222222222222222222222222222222229050610075565b90508061008157600080fd5b3660008037600080366000845af43d6000803e61009d573d6000fd5b3d6000f3");
+
+// Vyper 0.2 `create_forwarder_to` runtime, assembled from the compiler's template with a
+// placeholder target rather than captured from a deployed forwarder.
+pub const VYPER_FORWARDER_V2_CODE: &[u8] = &hex_literal::hex!("366000600037611000600036600073" "bebebebebebebebebebebebebebebebebebebebe" "5af4602c57600080fd5b6110006000f3");
+
+// Vyper 0.1 `create_forwarder_to` runtime, failing with an invalid jump instead of REVERT
+pub const VYPER_FORWARDER_V1_CODE: &[u8] = &hex_literal::hex!("366000600037611000600036600073" "bebebebebebebebebebebebebebebebebebebebe" "5af41558576110006000f3");
+
+// Safe 1.3.0 `GnosisSafeProxy` runtime, solc 0.7.6
+pub const SAFE_PROXY_CODE: &[u8] = &hex_literal::hex!("608060405273ffffffffffffffffffffffffffffffffffffffff600054167fa619486e0000000000000000000000000000000000000000000000000000000060003514156050578060005260206000f35b3660008037600080366000845af43d6000803e60008114156070573d6000fd5b3d6000f3fe" "a2646970667358221220d1429297349653a4918076d650332de1a1068c5f3e07c5c82360c277770b955264736f6c63430007060033");
+
+// Solady `LibClone.clone_PUSH0` runtime for 0xbebe...be, as the library's `mstore`s lay it out
+// behind the creation code `602d5f8160095f39f3`
+pub const SOLADY_PUSH0_CLONE_CODE: &[u8] = &hex_literal::hex!("5f5f365f5f37365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e6029573d5ffd5b3d5ff3");
+
+// Beacon proxy with the control flow of OpenZeppelin's `BeaconProxy` without its Solidity
+// boilerplate: loads the beacon from the EIP-1967 beacon slot, STATICCALLs `implementation()`,
+// reverts unless it returned a word and delegatecalls the result. Hand assembled.
+pub const BEACON_PROXY_CODE: &[u8] = &hex_literal::hex!("7fa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d5054" "635c60da1b60e01b600052" "6020600060046000845afa156064573d602011606457" "60005136600080376000803681845af4" "3d6000803e605f573d6000fd5b3d6000f35b600080fd");
+
+// BEACON_PROXY_CODE pushing the beacon slot plus one and subtracting 1 at runtime.
+pub const BEACON_PROXY_MINUS_ONE_CODE: &[u8] = &hex_literal::hex!("60017fa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d510354" "635c60da1b60e01b600052" "6020600060046000845afa156067573d602011606757" "60005136600080376000803681845af4" "3d6000803e6062573d6000fd5b3d6000f35b600080fd");
+
+// BEACON_PROXY_CODE assembling the beacon slot from two PUSH16 halves with SHL and OR.
+pub const BEACON_PROXY_SPLIT_CODE: &[u8] = &hex_literal::hex!("6fa3f0ad74e5423aebfd80d3ef4346578360801b6f35a9a72aeaee59ff6cb3582b35133d501754" "635c60da1b60e01b600052" "6020600060046000845afa156069573d602011606957" "60005136600080376000803681845af4" "3d6000803e6064573d6000fd5b3d6000f35b600080fd");
+
+// Forwards to the EIP-1967 implementation slot, pushed plus one and decremented at runtime.
+pub const EIP_1967_MINUS_ONE_CODE: &[u8] = &hex_literal::hex!("60017f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbd0354" "36600060003760006000366000845af43d600060003e3d6000f3");
+
+// Proxy falling back to a slot of its own while the EIP-1967 implementation slot is unset:
+// loads the EIP-1967 slot, jumps to the fallback if it is zero, and delegatecalls whichever it
+// loaded. Hand assembled.
+pub const FALLBACK_SLOT_PROXY_CODE: &[u8] = &hex_literal::hex!("7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc54" "8015604157" "36600060003760006000366000845af43d600060003e3d6000f3" "5b50" "7f6b1c0ffee5e2c0e5d5e1b1f3b0a1c2d3e4f5061728394a5b6c7d8e9fa0b1c2d354" "36600060003760006000366000845af43d600060003e3d6000f3");
+
+// Compound's Unitroller laid out as solc compiles it: a dispatcher for admin(), pendingAdmin(),
+// comptrollerImplementation() and pendingComptrollerImplementation(), reading slots 0 to 3, and
+// the admin setters, then a fallback delegatecalling the address in slot 2. Hand assembled, the
+// setters' bodies reduced to a revert; it is not the runtime deployed at
+// 0x3d9819210A31b4961b30EF54bE2aeD79B9c9Cd3B, which should replace it once captured from a node.
+pub const UNITROLLER_CODE: &[u8] = &hex_literal::hex!("60806040526004361061006b5760003560e01c8063f851a4401461008f578063267822471461009b578063bb82aa5e146100a7578063dcfbc0c7146100b3578063e992a041146100bf578063c1e80334146100bf578063b71d1a0c146100bf578063e9c714f2146100bf575b36600080376000803660006002545af43d6000803e1561008a573d6000f35b3d6000fd5b60005460005260206000f35b60015460005260206000f35b60025460005260206000f35b60035460005260206000f35b600080fd");
+
+// Transparent proxy: loads the EIP-1967 admin slot and stops if the caller is the admin,
+// otherwise delegatecalls the address in the EIP-1967 implementation slot. Hand assembled.
+pub const TRANSPARENT_PROXY_CODE: &[u8] = &hex_literal::hex!("7fb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d610354" "3314606357" "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc54" "36600060003760006000366000845af43d600060003e3d6000f3" "5b00");
+
+// TRANSPARENT_PROXY_CODE whose admin branch answers `upgradeTo(address)` by storing the
+// address in the EIP-1967 implementation slot. Hand assembled.
+pub const TRANSPARENT_UPGRADE_PROXY_CODE: &[u8] = &hex_literal::hex!("7fb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d610354" "3314606357" "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc54" "36600060003760006000366000845af43d600060003e3d6000f3" "5b" "60003560e01c633659cfe614607457" "00" "5b" "600435" "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc" "55" "00");
+
+// 0age's metamorphic init code, from the MetamorphicContractFactory
+pub const METAMORPHIC_INIT_CODE: &[u8] = &hex_literal::hex!("5860208158601c335a63aaf10f428752fa158151803b80938091923cf3");
+
+// Solady `LibClone.clone(implementation, data)` runtime (clone with immutable args) for
+// 0xbebe...be and 32 bytes of `data`: the library appends the data and its length plus 2, which
+// is also pushed ahead of the address
+pub const SOLADY_CWIA_CODE: &[u8] = &hex_literal::hex!("36602c57343d527f9e4ac34f21c619cefc926c8bd93b54bf5a39c7ab2127a895af1cc0691d7e3dff593da1005b363d3d373d3d3d3d610022806062363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e606057fd5bf3" "000000000000000000000000cafecafecafecafecafecafecafecafecafecafe" "0022");
+
+// Data length says 0xffff bytes but the code ends right after
+pub const BLUEPRINT_MALFORMED_CODE: &[u8] = &hex_literal::hex!("fe7102ffff3d602d80600a3d3981f3");
+
+/// `(name, code)` of every fixture a proxy is detected in.
+pub fn corpus() -> Vec<(&'static str, &'static [u8])> {
+    vec![
+        ("EIP_1967_CODE", EIP_1967_CODE),
+        ("EIP_897_CODE", EIP_897_CODE),
+        ("DIAMOND_STANDARD_CODE", DIAMOND_STANDARD_CODE),
+        ("BLUEPRINT_1167_CODE", BLUEPRINT_1167_CODE),
+        ("BLUEPRINT_1167_DATA_CODE", BLUEPRINT_1167_DATA_CODE),
+        ("GENERATED_ROUTER_CODE", GENERATED_ROUTER_CODE),
+        ("GENERATED_ROUTER_LINEAR_CODE", GENERATED_ROUTER_LINEAR_CODE),
+        ("VYPER_FORWARDER_V2_CODE", VYPER_FORWARDER_V2_CODE),
+        ("VYPER_FORWARDER_V1_CODE", VYPER_FORWARDER_V1_CODE),
+        ("SAFE_PROXY_CODE", SAFE_PROXY_CODE),
+        ("SOLADY_PUSH0_CLONE_CODE", SOLADY_PUSH0_CLONE_CODE),
+        ("SOLADY_CWIA_CODE", SOLADY_CWIA_CODE),
+        ("BEACON_PROXY_CODE", BEACON_PROXY_CODE),
+        ("FALLBACK_SLOT_PROXY_CODE", FALLBACK_SLOT_PROXY_CODE),
+        ("TRANSPARENT_PROXY_CODE", TRANSPARENT_PROXY_CODE),
+        ("UNITROLLER_CODE", UNITROLLER_CODE),
+    ]
+}
@@ -0,0 +1,117 @@
+#![allow(dead_code)]
+
+pub mod fixtures;
+
+use std::{fmt::Debug, future::Future, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+
+use async_trait::async_trait;
+use ethers_core::types::U256;
+use ethers_providers::{JsonRpcClient, JsonRpcError, MockError, Provider, PubsubClient};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{value::RawValue, Value};
+
+type Handler = dyn Fn(&str, &Value) -> Result<Value, String> + Send + Sync;
+
+/// JSON-RPC client answering every request through a closure, so tests can model chain state
+/// (e.g. code presence per block) instead of a fixed sequence of responses.
+#[derive(Clone)]
+pub struct FnRpc {
+    handler: Arc<Handler>,
+    calls: Arc<AtomicUsize>,
+    /// Yield to the runtime before answering, so requests stay in flight like real ones.
+    suspend: bool,
+}
+
+impl Debug for FnRpc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnRpc").field("calls", &self.calls()).finish()
+    }
+}
+
+impl FnRpc {
+    pub fn new<F>(handler: F) -> Self
+        where F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static
+    {
+        Self { handler: Arc::new(handler), calls: Arc::new(AtomicUsize::new(0)), suspend: false }
+    }
+
+    pub fn provider<F>(handler: F) -> (Provider<FnRpc>, FnRpc)
+        where F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static
+    {
+        let client = Self::new(handler);
+        (Provider::new(client.clone()), client)
+    }
+
+    /// [FnRpc::provider] whose requests are pending until polled again, to drop callers while
+    /// they wait on one.
+    pub fn suspending_provider<F>(handler: F) -> (Provider<FnRpc>, FnRpc)
+        where F: Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static
+    {
+        let client = Self { suspend: true, ..Self::new(handler) };
+        (Provider::new(client.clone()), client)
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FnRpc {
+    type Error = MockError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, MockError>
+        where T: Debug + Serialize + Send + Sync,
+              R: DeserializeOwned + Send
+    {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.suspend {
+            tokio::task::yield_now().await;
+        }
+        let params = serde_json::to_value(params)?;
+        match (self.handler)(method, &params) {
+            Ok(value) => Ok(serde_json::from_value(value)?),
+            // Full error responses, see rpc_error, are answered as given
+            Err(message) => Err(MockError::JsonRpcError(serde_json::from_str(&message).unwrap_or(JsonRpcError { code: -32000, message, data: None }))),
+        }
+    }
+}
+
+/// Subscriptions are answered by the handler's `eth_subscribe` and never notified.
+impl PubsubClient for FnRpc {
+    type NotificationStream = futures::stream::Pending<Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, _id: T) -> Result<Self::NotificationStream, MockError> {
+        Ok(futures::stream::pending())
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, _id: T) -> Result<(), MockError> {
+        Ok(())
+    }
+}
+
+/// Polls `future` until it waits on a request, drops it, and lets the runtime run anything it
+/// left behind for a while. Returns the requests made before and after the drop.
+pub async fn calls_around_drop<F: Future>(future: F, client: &FnRpc) -> (usize, usize) {
+    let mut future = Box::pin(future);
+    assert!(futures::poll!(&mut future).is_pending());
+    let before = client.calls();
+    drop(future);
+    for _ in 0..64 {
+        tokio::task::yield_now().await;
+    }
+    (before, client.calls())
+}
+
+/// A handler error answered as this error response, rather than as a `-32000` with the message.
+pub fn rpc_error(code: i64, message: &str, data: Option<Value>) -> String {
+    serde_json::json!({ "code": code, "message": message, "data": data }).to_string()
+}
+
+/// Parses a hex quantity block parameter, `latest` is mapped to `u64::MAX`.
+pub fn block_param(value: &Value) -> u64 {
+    match value.as_str() {
+        Some("latest") | None => u64::MAX,
+        Some(s) => u64::from_str_radix(s.trim_start_matches("0x"), 16).unwrap(),
+    }
+}
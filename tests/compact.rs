@@ -0,0 +1,87 @@
+//! Round trips of the compact encoding over arbitrary values, and its size against JSON.
+
+mod common;
+
+use arbitrary::{Arbitrary, Unstructured};
+use evm_proxy_tools::{compact::CompactError, detect_proxies, DetectorConfig, ProxyDetectionResult, ProxyDispatch, ProxyImplementation};
+use serde_json::json;
+
+use common::fixtures::{DIAMOND_STANDARD_CODE, EIP_1967_CODE, EIP_897_CODE};
+
+const CASES: u64 = 2000;
+
+/// Deterministic input for [Unstructured], splitmix64 of `seed`.
+fn entropy(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len).map(|_| {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let z = (state ^ (state >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        ((z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb) >> 56) as u8
+    }).collect()
+}
+
+/// Checks `decode(encode(v)) == v` for `CASES` arbitrary values, and that every strict prefix
+/// of an encoding is rejected.
+fn round_trip<T>(encode: impl Fn(&T) -> Vec<u8>, decode: impl Fn(&[u8]) -> Result<T, CompactError>)
+    where T: for<'a> Arbitrary<'a> + PartialEq + std::fmt::Debug
+{
+    for seed in 0..CASES {
+        let data = entropy(seed, 4096);
+        let value = T::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        let bytes = encode(&value);
+        assert_eq!(decode(&bytes).unwrap(), value, "seed {}", seed);
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err(), "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_result_round_trip() {
+    round_trip(ProxyDetectionResult::to_compact_bytes, ProxyDetectionResult::from_compact_bytes);
+}
+
+#[test]
+fn test_dispatch_round_trip() {
+    round_trip(ProxyDispatch::to_compact_bytes, ProxyDispatch::from_compact_bytes);
+}
+
+#[test]
+fn test_implementation_round_trip() {
+    round_trip(ProxyImplementation::to_compact_bytes, ProxyImplementation::from_compact_bytes);
+}
+
+/// The JSON a pipeline would otherwise store for `result`.
+fn as_json(result: &ProxyDetectionResult) -> String {
+    json!({
+        "proxy_type": format!("{:?}", result.proxy_type),
+        "dispatch": format!("{:?}", result.dispatch),
+        "rule": format!("{:?}", result.rule),
+        "evasive": result.evasive,
+        "findings": result.findings.iter().map(|f| format!("{:?}", f)).collect::<Vec<_>>(),
+        "provenance": result.provenance.iter().map(|p| json!({ "kind": format!("{:?}", p.kind), "offset": p.offset, "length": p.length })).collect::<Vec<_>>(),
+        "attribution": null,
+        "slot_preimage": result.slot_preimage.as_ref().map(|p| json!({ "preimage": p.preimage.to_string(), "minus_one": p.minus_one })),
+        "slot_name": result.slot_name,
+        "trigger_calldata": result.trigger_calldata.as_ref().map(ToString::to_string),
+        "probes_agree": result.probes_agree,
+        "raw_slot": result.raw_slot.map(|slot| format!("{:#x}", slot)),
+    }).to_string()
+}
+
+#[test]
+fn test_size_against_json() {
+    let corpus = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/corpus.bin")).unwrap();
+    let mut codes: Vec<&[u8]> = vec![EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE];
+    let mut data = &corpus[..];
+    while let Some((len, rest)) = data.split_first_chunk::<4>() {
+        let (code, rest) = rest.split_at(u32::from_be_bytes(*len) as usize);
+        codes.push(code);
+        data = rest;
+    }
+
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let results: Vec<ProxyDetectionResult> = detect_proxies(codes, &config).flatten().collect();
+    assert_eq!(results.len(), 6);
+    let compact: usize = results.iter().map(|r| r.to_compact_bytes().len()).sum();
+    let json: usize = results.iter().map(|r| as_json(r).len()).sum();
+    assert!(compact * 5 < json, "compact {} bytes, json {} bytes", compact, json);
+}
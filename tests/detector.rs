@@ -1,9 +1,14 @@
-use std::sync::Once;
+use std::{borrow::Cow, collections::HashMap, fs::File, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, Once}, time::{Duration, Instant}};
 
-use evm_proxy_tools::{get_proxy_type, ProxyType, ProxyDispatch};
-use alloy_primitives::{Address, U256};
+use evm_proxy_tools::{get_proxy_type, analyze_counterfactual, consensus, detect_all, AnalysisProfile, CounterfactualError, detect_proxy, detect_proxy_outcome, detect_creation_code, detect_proxies, detect_proxies_with_progress, detect_many, detect_many_with_progress, detect_blueprint, is_likely_proxy, LIKELY_PROXY_MAX_SIZE, MIN_PROXY_CODE_SIZE, DetectionStrategy, parse_blueprint, CalldataStrategy, DefaultProbes, PushedSelectorProbes, BlueprintInfo, DetectError, DetectOutcome, NotAProxyReason, refine_proxy_type, refine_proxy_type_with, SelectorRegistry, SlotObservations, SlotRegistry, ProxyDetectionResult, DetectorConfig, WidenedAnalysis, Finding, ByteProvenance, ProvenanceKind, ProxyType, ProxyDispatch, ProbeRetry, RuleId, RulePolicy, Ruleset, Selector, SlotExtraction, SlotPreimage, trace_dispatch, trace_dispatches, TraceConfig, TraceEnvironment, TraceError, synthetic_return, CallKind};
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use evm_proxy_tools::utils::{match_namespace, namespaced_slot, NamespacedSlots};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
+mod common;
+
+use common::fixtures::{EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE, ERC20_BINARY_SEARCH_CODE, BLUEPRINT_1167_CODE, BLUEPRINT_1167_DATA_CODE, BLUEPRINT_MALFORMED_CODE, BEACON_PROXY_CODE, BEACON_PROXY_MINUS_ONE_CODE, BEACON_PROXY_SPLIT_CODE, EIP_1967_MINUS_ONE_CODE, METAMORPHIC_INIT_CODE, SAFE_PROXY_CODE, SOLADY_PUSH0_CLONE_CODE, SOLADY_CWIA_CODE, TRANSPARENT_PROXY_CODE, TRANSPARENT_UPGRADE_PROXY_CODE, UNITROLLER_CODE, VYPER_FORWARDER_V1_CODE, VYPER_FORWARDER_V2_CODE, GENERATED_ROUTER_CODE, GENERATED_ROUTER_LINEAR_CODE};
+
 static INIT: Once = Once::new();
 
 fn init() {
@@ -20,7 +25,7 @@ fn init() {
 fn test_eip_1167() {
     init();
     assert_eq!(get_proxy_type(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")), Some((ProxyType::EIP_1167, ProxyDispatch::Static(Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"))))));
-    assert_eq!(get_proxy_type(&hex_literal::hex!("363d3d373d3d3d363d6fbebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")), Some((ProxyType::EIP_1167, ProxyDispatch::Static(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))))));
+    assert_eq!(get_proxy_type(&hex_literal::hex!("363d3d373d3d3d363d6fbebebebebebebebebebebebebebebebe5af43d82803e903d91602757fd5bf3")), Some((ProxyType::EIP_1167, ProxyDispatch::Static(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))))));
     assert!(get_proxy_type(&hex_literal::hex!("9999999999")).is_none());
     assert!(get_proxy_type(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")).is_none());
 }
@@ -29,7 +34,7 @@ fn test_eip_1167() {
 fn test_eip_7511() {
     init();
     assert_eq!(get_proxy_type(&hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3")), Some((ProxyType::EIP_7511, ProxyDispatch::Static(Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"))))));
-    assert_eq!(get_proxy_type(&hex_literal::hex!("365f5f375f5f365f6fbebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3")), Some((ProxyType::EIP_7511, ProxyDispatch::Static(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))))));
+    assert_eq!(get_proxy_type(&hex_literal::hex!("365f5f375f5f365f6fbebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602657fd5bf3")), Some((ProxyType::EIP_7511, ProxyDispatch::Static(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))))));
     assert!(get_proxy_type(&hex_literal::hex!("9999999999")).is_none());
     assert!(get_proxy_type(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")).is_none());
 }
@@ -38,11 +43,319 @@ fn test_eip_7511() {
 fn test_eip_3448() {
     init();
     assert_eq!(get_proxy_type(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e603457fd5bf3")), Some((ProxyType::EIP_3448, ProxyDispatch::Static(Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"))))));
-    assert_eq!(get_proxy_type(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d6fbebebebebebebebebebebebebebebebe5af43d3d93803e603457fd5bf3")), Some((ProxyType::EIP_3448, ProxyDispatch::Static(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))))));
+    assert_eq!(get_proxy_type(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d6fbebebebebebebebebebebebebebebebe5af43d3d93803e603057fd5bf3")), Some((ProxyType::EIP_3448, ProxyDispatch::Static(Address::from(hex_literal::hex!("00000000bebebebebebebebebebebebebebebebe"))))));
     assert!(get_proxy_type(&hex_literal::hex!("9999999999")).is_none());
     assert!(get_proxy_type(&hex_literal::hex!("9999999999aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")).is_none());
 }
 
+#[test]
+fn test_minimal_proxy_push_widths() {
+    init();
+    // Prefix, then the suffix after a PUSH20 and the index of its JUMPI target in it
+    type Pattern = (ProxyType, RuleId, &'static [u8], &'static [u8], usize);
+    let patterns: [Pattern; 3] = [
+        (ProxyType::EIP_1167, RuleId::Eip1167Pattern, &hex_literal::hex!("363d3d373d3d3d363d"), &hex_literal::hex!("5af43d82803e903d91602b57fd5bf3"), 10),
+        (ProxyType::EIP_7511, RuleId::Eip7511Pattern, &hex_literal::hex!("365f5f375f5f365f"), &hex_literal::hex!("5af43d5f5f3e5f3d91602a57fd5bf3"), 10),
+        (ProxyType::EIP_3448, RuleId::Eip3448Pattern, &hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d"), &hex_literal::hex!("5af43d3d93803e603457fd5bf3"), 8),
+    ];
+    // A narrower push moves the JUMPDEST back, as a factory assembling the clone would
+    let assemble = |prefix: &[u8], width: usize, address: &[u8], suffix: &[u8], jump: usize| {
+        let mut suffix = suffix.to_vec();
+        suffix[jump] -= 20 - width as u8;
+        [prefix, &[0x5f + width as u8], address, &suffix].concat()
+    };
+    for (proxy_type, rule, prefix, suffix, jump) in patterns {
+        for width in 15..=20usize {
+            // Vanity addresses lose their leading zero bytes
+            let mut address = [0u8; 20];
+            address[20 - width..].fill(0xbe);
+            let code = assemble(prefix, width, &address[20 - width..], suffix, jump);
+            // JUMPDEST
+            assert_eq!(code[code[prefix.len() + 1 + width + jump] as usize], 0x5b, "{:?} with PUSH{}", proxy_type, width);
+            let result = detect_proxy(&code, &DetectorConfig::default()).unwrap_or_else(|| panic!("{:?} with PUSH{} not detected", proxy_type, width));
+            assert_eq!((result.proxy_type, result.dispatch, result.rule), (proxy_type, ProxyDispatch::Static(Address::from(address)), rule), "PUSH{}", width);
+            assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, prefix.len() + 1, width)]);
+        }
+        // A narrower push that keeps the PUSH20 jump target jumps into the middle of the code
+        let code = [prefix, &[0x72], &[0xbe; 19], suffix].concat();
+        let result = detect_proxy(&code, &DetectorConfig::default());
+        assert!(result.is_none_or(|result| result.rule != rule), "{:?} with PUSH19 and the PUSH20 jump", proxy_type);
+        // As rulesets before 16 expected it
+        let pinned = DetectorConfig { ruleset: Ruleset::pinned(15).unwrap(), ..Default::default() };
+        assert_eq!(detect_proxy(&code, &pinned).map(|result| result.rule), Some(rule), "{:?} pinned to ruleset 15", proxy_type);
+        // Outside of the range the pattern doesn't match, only the tracer may still find a call
+        for width in [14usize, 21] {
+            let code = [prefix, &[0x5f + width as u8], &vec![0xbe; width], suffix].concat();
+            let result = detect_proxy(&code, &DetectorConfig::default());
+            assert!(result.is_none_or(|result| result.rule != rule), "{:?} with PUSH{}", proxy_type, width);
+        }
+    }
+}
+
+#[test]
+fn test_solady_clone() {
+    init();
+    let implementation = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    assert_eq!(get_proxy_type(SOLADY_PUSH0_CLONE_CODE), Some((ProxyType::SoladyClone, ProxyDispatch::Static(implementation))));
+    // The args tail has no fixed length
+    let no_args = &SOLADY_CWIA_CODE[..98];
+    assert_eq!(get_proxy_type(no_args), Some((ProxyType::SoladyClone, ProxyDispatch::Static(implementation))));
+
+    let result = detect_proxy(SOLADY_CWIA_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.rule, RuleId::SoladyClonePattern);
+    assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, 65, 20), ByteProvenance::new(ProvenanceKind::ImmutableArgs, 98, 32)]);
+    let result = detect_proxy(SOLADY_PUSH0_CLONE_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, 9, 20)]);
+}
+
+#[test]
+fn test_prefixed_minimal_proxy() {
+    init();
+    const BODY: [u8; 45] = hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3");
+    let implementation = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    // `require(msg.value == 0)` guard ahead of the clone, with metadata after it
+    let code = [&hex_literal::hex!("34156006" "57fe5b")[..], &BODY, &hex_literal::hex!("a164736f6c6343000811000a")].concat();
+    let result = detect_proxy(&code, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::EIP_1167, ProxyDispatch::Static(implementation), RuleId::Eip1167Pattern));
+    assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, 17, 20)]);
+
+    // Returns before reaching the embedded body, which more code follows
+    let code = [&hex_literal::hex!("60006000f3")[..], &BODY, &hex_literal::hex!("600160025500")].concat();
+    assert_eq!(detect_proxy(&code, &DetectorConfig { seed: Some(1), ..Default::default() }), None);
+
+    // Too far into the code for the static matcher, the tracer still finds it
+    let code = [hex_literal::hex!("600050").repeat(24), BODY.to_vec()].concat();
+    let result = detect_proxy(&code, &DetectorConfig { seed: Some(1), ..Default::default() }).unwrap();
+    assert_eq!((result.dispatch, result.rule), (ProxyDispatch::Static(implementation), RuleId::StaticDelegateCall));
+}
+
+#[test]
+fn test_metamorphic() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let result = detect_proxy(METAMORPHIC_INIT_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, &result.dispatch, result.rule), (ProxyType::Metamorphic, &ProxyDispatch::Unknown, RuleId::MetamorphicInitPattern));
+    assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::SelectorConstant, 10, 4)]);
+    // As creation code it isn't deployed, what it would deploy depends on the factory
+    assert_eq!(detect_creation_code(METAMORPHIC_INIT_CODE, &config), Some(result));
+
+    // Only the exact init code
+    let mut altered = METAMORPHIC_INIT_CODE.to_vec();
+    altered[11] ^= 0xff;
+    assert_eq!(detect_proxy(&altered, &config).map(|result| result.proxy_type), None);
+}
+
+#[test]
+fn test_gnosis_safe() {
+    init();
+    let result = detect_proxy(SAFE_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::GnosisSafe, ProxyDispatch::Storage(U256::ZERO, None), RuleId::SafeProxyPattern));
+    assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::SlotConstant, 27, 1)]);
+
+    // Other Safe versions only differ in their metadata
+    let mut recompiled = SAFE_PROXY_CODE.to_vec();
+    let hash_byte = recompiled.len() - 20;
+    recompiled[hash_byte] ^= 0xff;
+    assert_eq!(detect_proxy(&recompiled, &DetectorConfig::default()).unwrap().rule, RuleId::SafeProxyPattern);
+
+    // Unknown Safe-like runtimes are recognized from the trace
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::SafeProxyPattern), ..Default::default() };
+    let result = detect_proxy(SAFE_PROXY_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::GnosisSafe, RuleId::SafeStorageSlot));
+    assert!(matches!(result.dispatch, ProxyDispatch::Storage(slot, _) if slot.is_zero()));
+
+    let config = DetectorConfig { rules: config.rules.disable(RuleId::SafeStorageSlot), ..Default::default() };
+    assert_eq!(detect_proxy(SAFE_PROXY_CODE, &config).unwrap().proxy_type, ProxyType::EIP_897);
+    // Slot 0 alone isn't enough
+    assert_eq!(get_proxy_type(EIP_897_CODE).map(|(proxy_type, _)| proxy_type), Some(ProxyType::EIP_897));
+}
+
+#[test]
+fn test_beacon_proxy() {
+    init();
+    let beacon_slot = U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+    let result = detect_proxy(BEACON_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::EIP_1967_BEACON, ProxyDispatch::Beacon(beacon_slot), RuleId::BeaconProxyPattern));
+    assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::SlotConstant, 1, 32), ByteProvenance::new(ProvenanceKind::SelectorConstant, 35, 4)]);
+
+    // The tracer follows the implementation() call to the address loaded from the slot
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::BeaconProxyPattern), ..Default::default() };
+    let result = detect_proxy(BEACON_PROXY_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::EIP_1967_BEACON, ProxyDispatch::Beacon(beacon_slot), RuleId::BeaconStorageSlot));
+    assert_eq!(result.provenance.len(), 2);
+
+    // Delegating straight to the implementation slot isn't a beacon proxy
+    assert_eq!(get_proxy_type(EIP_1967_CODE).map(|(proxy_type, _)| proxy_type), Some(ProxyType::EIP_1967));
+}
+
+#[test]
+fn test_trace_evidence() {
+    init();
+    let implementation_slot = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+    // Every probe delegates through the slot, the first one is kept as the trigger
+    let config = DetectorConfig::default();
+    let result = detect_proxy(EIP_1967_CODE, &config).unwrap();
+    assert_eq!((result.probes_agree, result.raw_slot), (Some(true), Some(implementation_slot)));
+    let trigger = result.trigger_calldata.unwrap();
+    assert!(config.calldata_strategy().probes(&Bytes::from_static(EIP_1967_CODE)).contains(&trigger));
+
+    // Beacon proxies load the beacon, not the implementation, from their slot
+    let beacon_slot = U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::BeaconProxyPattern), ..Default::default() };
+    assert_eq!(detect_proxy(BEACON_PROXY_CODE, &config).unwrap().raw_slot, Some(beacon_slot));
+
+    // A diamond delegates by selector, its probes don't agree
+    let result = detect_proxy(DIAMOND_STANDARD_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.rule, result.probes_agree), (RuleId::DiamondLoupeSelector, Some(false)));
+
+    // Pattern matches run nothing
+    let result = detect_proxy(SOLADY_PUSH0_CLONE_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.trigger_calldata, result.probes_agree, result.raw_slot), (None, None, None));
+}
+
+#[test]
+fn test_folded_slot_constants() {
+    init();
+    let beacon_slot = U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+    // The provenance points at every push the slot is computed from
+    let result = detect_proxy(BEACON_PROXY_MINUS_ONE_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.dispatch, result.rule), (ProxyDispatch::Beacon(beacon_slot), RuleId::BeaconProxyPattern));
+    assert_eq!(result.provenance, vec![
+        ByteProvenance::new(ProvenanceKind::SlotConstant, 1, 1),
+        ByteProvenance::new(ProvenanceKind::SlotConstant, 3, 32),
+        ByteProvenance::new(ProvenanceKind::SelectorConstant, 38, 4),
+    ]);
+    let result = detect_proxy(BEACON_PROXY_SPLIT_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.dispatch, result.rule), (ProxyDispatch::Beacon(beacon_slot), RuleId::BeaconProxyPattern));
+    let slot_pushes: Vec<(usize, usize)> = result.provenance.iter().filter(|p| p.kind == ProvenanceKind::SlotConstant).map(|p| (p.offset, p.length)).collect();
+    assert_eq!(slot_pushes, vec![(1, 16), (18, 1), (21, 16)]);
+
+    // Rulesets before folding only find them by tracing
+    let config = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert_eq!(detect_proxy(BEACON_PROXY_SPLIT_CODE, &config).unwrap().rule, RuleId::BeaconStorageSlot);
+
+    // Traced proxies get the provenance too
+    let implementation_slot = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+    let result = detect_proxy(EIP_1967_MINUS_ONE_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch), (ProxyType::EIP_1967, ProxyDispatch::Storage(implementation_slot, None)));
+    assert_eq!(result.provenance.len(), 2);
+}
+
+#[test]
+fn test_transparent_proxy_admin() {
+    init();
+    let admin_slot = U256::from_be_bytes(hex_literal::hex!("b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103"));
+    let implementation_slot = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+    // Calls from the address the admin slot holds aren't forwarded
+    let result = detect_proxy(TRANSPARENT_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_1967_TRANSPARENT, RuleId::TransparentAdminBranch));
+    assert_eq!(result.dispatch, ProxyDispatch::Storage(implementation_slot, None));
+    assert_eq!(result.admin_slot, Some(admin_slot));
+    // Its admin branch doesn't write anything
+    assert!(result.upgradeable_slots.is_empty());
+    let result = detect_proxy(TRANSPARENT_UPGRADE_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.upgradeable_slots), (ProxyType::EIP_1967_TRANSPARENT, vec![implementation_slot]));
+
+    // Also with the real admin, or without the rule
+    let admin = U256::from_be_bytes(hex_literal::hex!("000000000000000000000000a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0"));
+    let widened = WidenedAnalysis { storage: [(admin_slot, admin)].into(), ..Default::default() };
+    let result = detect_proxy(TRANSPARENT_PROXY_CODE, &DetectorConfig { widened: Some(widened), ..Default::default() }).unwrap();
+    assert_eq!(result.proxy_type, ProxyType::EIP_1967_TRANSPARENT);
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::TransparentAdminBranch), ..Default::default() };
+    let result = detect_proxy(TRANSPARENT_PROXY_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.rule, result.admin_slot), (ProxyType::EIP_1967, RuleId::KnownStorageSlot, Some(admin_slot)));
+
+    // Never loaded, never reported
+    let result = detect_proxy(EIP_1967_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.admin_slot), (ProxyType::EIP_1967, None));
+    assert!(result.upgradeable_slots.is_empty());
+}
+
+#[test]
+fn test_compound_delegator() {
+    init();
+    let result = detect_proxy(UNITROLLER_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::CompoundDelegator, RuleId::CompoundStorageSlot));
+    assert_eq!(result.dispatch, ProxyDispatch::Storage(U256::from(2), None));
+
+    // EIP-897 proxies read a low slot too, but don't answer Compound's functions
+    let result = detect_proxy(EIP_897_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_897, RuleId::LowStorageSlot));
+}
+
+#[test]
+fn test_vyper_forwarder() {
+    init();
+    let implementation = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    for code in [VYPER_FORWARDER_V2_CODE, VYPER_FORWARDER_V1_CODE] {
+        let result = detect_proxy(code, &DetectorConfig::default()).unwrap();
+        assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::VyperForwarder, ProxyDispatch::Static(implementation), RuleId::VyperForwarderPattern));
+        assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, 15, 20)]);
+    }
+
+    // Without the static rule the forwarder is only recognized by tracing
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::VyperForwarderPattern), ..Default::default() };
+    let result = detect_proxy(VYPER_FORWARDER_V2_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch), (ProxyType::StaticAddress, ProxyDispatch::Static(implementation)));
+}
+
+#[test]
+fn test_clone_immutable_args() {
+    init();
+    let implementation = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    let args = Bytes::from(hex_literal::hex!("000000000000000000000000cafecafecafecafecafecafecafecafecafecafe"));
+    assert_eq!(get_proxy_type(SOLADY_CWIA_CODE), Some((ProxyType::SoladyClone, ProxyDispatch::StaticWithArgs(implementation, args))));
+
+    // A length word not matching the code doesn't delimit args
+    let mut bad_length = SOLADY_CWIA_CODE.to_vec();
+    *bad_length.last_mut().unwrap() = 0x21;
+    assert_eq!(get_proxy_type(&bad_length), Some((ProxyType::SoladyClone, ProxyDispatch::Static(implementation))));
+    // Only the length word, no args
+    let empty = [&SOLADY_CWIA_CODE[..98], &[0x00, 0x02]].concat();
+    assert_eq!(get_proxy_type(&empty), Some((ProxyType::SoladyClone, ProxyDispatch::Static(implementation))));
+}
+
+fn selector(signature: &str) -> Selector {
+    Selector::from_slice(alloy_primitives::keccak256(signature).as_slice()).unwrap()
+}
+
+#[test]
+fn test_generated_router() {
+    init();
+    let owner_module = Address::repeat_byte(0x11);
+    let upgrade_module = Address::repeat_byte(0x22);
+    let mut expected: Vec<(Selector, Address)> = [
+        ("owner()", owner_module),
+        ("acceptOwnership()", owner_module),
+        ("nominateNewOwner(address)", owner_module),
+        ("nominatedOwner()", owner_module),
+        ("renounceNomination()", owner_module),
+        ("upgradeTo(address)", upgrade_module),
+        ("getImplementation()", upgrade_module),
+        ("simulateUpgradeTo(address)", upgrade_module),
+    ].iter().map(|(signature, module)| (selector(signature), *module)).collect();
+    expected.sort();
+
+    let result = detect_proxy(GENERATED_ROUTER_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.proxy_type, ProxyType::GeneratedRouter);
+    assert_eq!(result.rule, RuleId::GeneratedRouterPattern);
+    assert_eq!(result.dispatch, ProxyDispatch::PerSelector(expected.clone()));
+    // One PUSH20 per selector, shared by the selectors of a module
+    assert_eq!(result.provenance.len(), 8);
+    assert!(result.provenance.iter().all(|p| p.kind == ProvenanceKind::ImplementationAddress && p.length == 20));
+
+    let linear: Vec<(Selector, Address)> = expected.iter().copied()
+        .filter(|(s, _)| [selector("owner()"), selector("acceptOwnership()"), selector("nominateNewOwner(address)"), selector("upgradeTo(address)")].contains(s))
+        .collect();
+    assert_eq!(get_proxy_type(GENERATED_ROUTER_LINEAR_CODE), Some((ProxyType::GeneratedRouter, ProxyDispatch::PerSelector(linear))));
+
+    // Turned off, the router is traced like any other delegating contract
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::GeneratedRouterPattern), ..Default::default() };
+    assert_ne!(detect_proxy(GENERATED_ROUTER_CODE, &config).map(|r| r.proxy_type), Some(ProxyType::GeneratedRouter));
+    // Contracts whose functions do more than pick an address aren't routers
+    assert!(evm_proxy_tools::recover_router_table(EIP_897_CODE).is_none());
+}
+
 #[test]
 fn test_diamond_zksyncera() {
     init();
@@ -53,7 +366,7 @@ fn test_diamond_zksyncera() {
 #[test]
 fn test_diamond_standard() {
     init();
-    assert_eq!(get_proxy_type(&hex_literal::hex!("6080604052600436101561001e575b361561001c5761001c61131a565b005b60003560e01c806301ffc9a71461010e5780631f931c1c146101055780632c408059146100fc57806352ef6b2c146100f357806379ba5097146100ea5780637a0ed627146100e15780638ab5150a146100d85780638da5cb5b146100cf57806391423765146100c6578063adfca15e146100bd578063cdffacc6146100b45763f2fde38b0361000e576100af611160565b61000e565b506100af6110cc565b506100af610eed565b506100af610dc6565b506100af610d54565b506100af610ce2565b506100af610968565b506100af610694565b506100af6103ff565b506100af61033c565b506100af6102a3565b506100af610178565b600435907fffffffff000000000000000000000000000000000000000000000000000000008216820361014657565b600080fd5b35907fffffffff000000000000000000000000000000000000000000000000000000008216820361014657565b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc360112610146577fffffffff000000000000000000000000000000000000000000000000000000006101d1610117565b166000527f326d0c59a7612f6a9919e2a8ee333c80ba689d8ba2634de89c85cbb04832e705602052602060ff604060002054166040519015158152f35b6024359073ffffffffffffffffffffffffffffffffffffffff8216820361014657565b6004359073ffffffffffffffffffffffffffffffffffffffff8216820361014657565b359073ffffffffffffffffffffffffffffffffffffffff8216820361014657565b9181601f840112156101465782359167ffffffffffffffff8311610146576020838186019501011161014657565b50346101465760607ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc3601126101465760043567ffffffffffffffff808211610146573660238301121561014657816004013591818311610146573660248460051b830101116101465761031561020e565b6044359283116101465761001c936103336024943690600401610275565b949093016116f9565b50346101465760007ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657602073ffffffffffffffffffffffffffffffffffffffff7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc965416604051908152f35b6020908160408183019282815285518094520193019160005b8281106103d5575050505090565b835173ffffffffffffffffffffffffffffffffffffffff16855293810193928101926001016103c7565b5034610146576000807ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc3601126106915761046661045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b61ffff1690565b90610470826115c9565b908080815b858210610491578385526040518061048d87826103ae565b0390f35b6104c4816000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5483905b600882106104e1575b50506104dc9061163e565b610475565b9195939690926104f09061163e565b94818611610684576105a66105a061057a7fffffffff00000000000000000000000000000000000000000000000000000000868860051b1b167fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b547fffffffffffffffffffffffffffffffffffffffff0000000000000000000000001690565b60601c90565b8873ffffffffffffffffffffffffffffffffffffffff8216815b848110610621575b505061061657816105fe610603926105e3610609958a6116be565b9073ffffffffffffffffffffffffffffffffffffffff169052565b61163e565b9361163e565b90969395919492946104c8565b50926106099061163e565b61066461064b610631838c6116be565b5173ffffffffffffffffffffffffffffffffffffffff1690565b73ffffffffffffffffffffffffffffffffffffffff1690565b8214610678576106739061163e565b6105c0565b505050600138806105c8565b94928197949692506104d1565b80fd5b5034610146576000807ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc3601126106915773ffffffffffffffffffffffffffffffffffffffff807f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce6617890541633036107e55733907f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f67168046054167f8be0079c531659141344cd1fd0a4f28419497f9722a3daafe3b4186f6b6457e08380a37f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f67168046080547fffffffffffffffffffffffff000000000000000000000000000000000000000016331790556107e27f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce66178907fffffffffffffffffffffffff00000000000000000000000000000000000000008154169055565b80f35b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602960248201527f536166654f776e61626c653a2073656e646572206d757374206265206e6f6d6960448201527f6e6565206f776e657200000000000000000000000000000000000000000000006064820152fd5b90815180825260208080930193019160005b828110610889575050505090565b83517fffffffff00000000000000000000000000000000000000000000000000000000168552938101939281019260010161087b565b602080820190808352835180925260409283810182858560051b8401019601946000925b8584106108f4575050505050505090565b909192939495968580610957837fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc0866001960301885286838d5173ffffffffffffffffffffffffffffffffffffffff815116845201519181858201520190610869565b9901940194019295949391906108e3565b5034610146576000807ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc360112610691576109c861045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b6109d181611532565b906109db816115c9565b92809181825b828210610a3f575050505b818110610a04578183526040518061048d85826108bf565b80610a25610a1f610a18610a3a94886116be565b5160ff1690565b60ff1690565b6020610a3183876116be565b5101515261163e565b6109ec565b610a72816000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5484905b60088210610a8f575b5050610a8a9061163e565b6109e1565b9093919692610aa09098959861163e565b95828711610cd5577fffffffff00000000000000000000000000000000000000000000000000000000828660051b1b16610b2b6105a061057a837fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b8a73ffffffffffffffffffffffffffffffffffffffff8216815b858110610c12575b5050610c065791610bde610bf192610b88610bf795610b6c858b6116be565b519073ffffffffffffffffffffffffffffffffffffffff169052565b610bb7610b94886115c9565b60209081610ba2878d6116be565b510152610baf858b6116be565b5101516116a8565b907fffffffff00000000000000000000000000000000000000000000000000000000169052565b6105fe610beb828a6116be565b60019052565b9461163e565b90979497969193959296610a76565b505093610bf79061163e565b8a858a84610c4161064b610c2687856116be565b515173ffffffffffffffffffffffffffffffffffffffff1690565b14610c5757505050610c529061163e565b610b45565b610ccc955083809550610cbd93610c99610cb894610bb76020610c80610cc49a610a18986116be565b510151610c93610a1f610a1888886116be565b906116be565b610cb360ff80610cac610a1886866116be565b16106116e0565b6116be565b6116e7565b918b6116be565b9060ff169052565b60013880610b4d565b9592969193979497610a7f565b50346101465760007ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657602073ffffffffffffffffffffffffffffffffffffffff7f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce66178905416604051908152f35b50346101465760007ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657602073ffffffffffffffffffffffffffffffffffffffff7f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416604051908152f35b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657610dfe610231565b73ffffffffffffffffffffffffffffffffffffffff610e41817f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416331461122a565b7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc9691167fffffffffffffffffffffffff0000000000000000000000000000000000000000825416179055600080f35b6020908160408183019282815285518094520193019160005b828110610eb7575050505090565b83517fffffffff000000000000000000000000000000000000000000000000000000001685529381019392810192600101610ea9565b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657610f25610231565b610f5461045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b90610f5e826115c9565b9060009073ffffffffffffffffffffffffffffffffffffffff1681805b858210610f93578385526040518061048d8782610e90565b610fc6816000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5460005b60088110610fe3575b5050610fde9061163e565b610f7b565b9492610ff49097919796929661163e565b948186116110be577fffffffff00000000000000000000000000000000000000000000000000000000888260051b1b1661108261064b6105a061057a847fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b86146110a1575b506110939061163e565b969096959195949294610fca565b846105fe6110b792610bb76110939598886116be565b9390611089565b819750959195949294610fd3565b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc360112610146577fffffffff00000000000000000000000000000000000000000000000000000000611125610117565b166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052602060406000205460601c604051908152f35b50346101465760207ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffc36011261014657611198610231565b73ffffffffffffffffffffffffffffffffffffffff6111db817f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416331461122a565b7f24aa1f7b31fd188a8d3ecfb06bc55c806040e59b03bd4396283442fce661789091167fffffffffffffffffffffffff0000000000000000000000000000000000000000825416179055600080f35b1561123157565b60646040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152601d60248201527f4f776e61626c653a2073656e646572206d757374206265206f776e65720000006044820152fd5b1561129657565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602660248201527f50726f78793a20696d706c656d656e746174696f6e206d75737420626520636f60448201527f6e747261637400000000000000000000000000000000000000000000000000006064820152fd5b5060007fffffffff0000000000000000000000000000000000000000000000000000000081351681527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc9360205280604081205460601c801561139f575b8061138583923b151561128f565b368280378136915af43d82803e1561139b573d90f35b3d90fd5b505073ffffffffffffffffffffffffffffffffffffffff7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc96541680156113e6578190611377565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152603260248201527f4469616d6f6e64426173653a206e6f20666163657420666f756e6420666f722060448201527f66756e6374696f6e207369676e617475726500000000000000000000000000006064820152fd5b507f4e487b7100000000000000000000000000000000000000000000000000000000600052604160045260246000fd5b604051906060820182811067ffffffffffffffff8211176114ba57604052565b6114c261146a565b604052565b907fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0601f604051930116820182811067ffffffffffffffff8211176114ba57604052565b60209067ffffffffffffffff8111611525575b60051b0190565b61152d61146a565b61151e565b9061154461153f8361150b565b6114c7565b8281527fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0611572829461150b565b0190600090815b8381106115865750505050565b60209060408051908082019082821067ffffffffffffffff8311176115bc575b5284815282606081830152828501015201611579565b6115c461146a565b6115a6565b906115d661153f8361150b565b8281527fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0611604829461150b565b0190602036910137565b507f4e487b7100000000000000000000000000000000000000000000000000000000600052601160045260246000fd5b6001907fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff811461166c570190565b61167461160e565b0190565b507f4e487b7100000000000000000000000000000000000000000000000000000000600052603260045260246000fd5b6020908051156116b6570190565b611674611678565b60209181518110156116d3575b60051b010190565b6116db611678565b6116cb565b1561014657565b60ff6001911660ff811461166c570190565b949390929461174073ffffffffffffffffffffffffffffffffffffffff7f8a22373512790c48b83a1fe2efdd2888d4a917bcdc24d0adf63e60f6716804605416331461122a565b61174c61153f8561150b565b9081948083526020809301600591821b8301923684116101465780915b84831061178e5750505050505061178c93946117869136916118a2565b91611b14565b565b67ffffffffffffffff8335818111610146578301606081360312610146576117b461149a565b916117be82610254565b835288820135600381101561014657898401526040918281013591821161014657019036601f83011215610146578135916117fb61153f8461150b565b928a808583815201918a1b8301019136831161014657918b80969492979593015b818110611836575050849550820152815201920191611769565b91939580919395976118478461014b565b8152019101918b95939196949261181c565b7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0601f60209267ffffffffffffffff8111611895575b01160190565b61189d61146a565b61188f565b9291926118b161153f83611859565b938285528282011161014657816000926020928387013784010152565b600311156118d857565b7f4e487b7100000000000000000000000000000000000000000000000000000000600052602160045260246000fd5b1561190e57565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602360248201527f4469616d6f6e64426173653a206e6f2073656c6563746f72732073706563696660448201527f69656400000000000000000000000000000000000000000000000000000000006064820152fd5b919082519283825260005b8481106119dc5750507fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0601f8460006020809697860101520116010190565b60208183018101518483018201520161199d565b93929091936060928382019380835281518095526080830160808660051b85010195602080940192600080915b838310611a6057505050505050611a5d9495611a509183019073ffffffffffffffffffffffffffffffffffffffff169052565b6040818403910152611992565b90565b9091929394987fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff8088820301865289519073ffffffffffffffffffffffffffffffffffffffff8251168152878201516003811015611ae757611ad960019385848c9594868096015281604080940151938201520190610869565b9b0196019493019190611a1d565b6024857f4e487b710000000000000000000000000000000000000000000000000000000081526021600452fd5b9091611b4561045f7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc945461ffff1690565b91829460009260078516611cf2575b6000935b8351851015611c0b57611b6b85856116be565b51906020820151611b7b816118ce565b611b8b6040840151511515611907565b611b94816118ce565b80611bb3575090611ba89160019697611e47565b9490955b0193611b58565b611bc081979392976118ce565b60018103611bda575090611bd560019261275b565b611bac565b80611be66002926118ce565b14611bf5575b50600190611bac565b600195611c02929761221c565b94909590611bec565b8694507f8faa70878671ccd212d20771b795c50af8fd3ff6cf27f4bde57e5d4de0aeb67393919561178c9793988103611ca0575b60078116611c62575b5050611c5a85604051938493846119f0565b0390a161298d565b611c989060031c6000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b553880611c48565b7f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc9480547fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00001661ffff8316179055611c3f565b9250611d2a8460031c6000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5492611b54565b15611d3857565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602360248201527f4469616d6f6e64426173653a204144442074617267657420686173206e6f206360448201527f6f646500000000000000000000000000000000000000000000000000000000006064820152fd5b15611dc357565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602360248201527f4469616d6f6e64426173653a2073656c6563746f7220616c726561647920616460448201527f64656400000000000000000000000000000000000000000000000000000000006064820152fd5b90929192611e93611e6c855173ffffffffffffffffffffffffffffffffffffffff1690565b3073ffffffffffffffffffffffffffffffffffffffff821614908115612096575b50611d31565b60009384925b6040820151805185101561208c57611eb485611eda926116be565b517fffffffff000000000000000000000000000000000000000000000000000000001690565b611f41611f3b61064b6105a061057a857fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b15611dbc565b817fffffffffffffffffffffffffffffffffffffffff000000000000000000000000611fac611f84865173ffffffffffffffffffffffffffffffffffffffff1690565b60601b7fffffffffffffffffffffffffffffffffffffffff0000000000000000000000001690565b1617612003827fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b5560e090818360051b16947fffffffff00000000000000000000000000000000000000000000000000000000809216861c91861c191617931461204d575b60019384019301611e99565b916120848360031c6000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b558491612041565b5092505092509190565b90503b151538611e8d565b156120a857565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602f60248201527f4469616d6f6e64426173653a2052454d4f564520746172676574206d7573742060448201527f6265207a65726f206164647265737300000000000000000000000000000000006064820152fd5b1561213357565b60646040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152601f60248201527f4469616d6f6e64426173653a2073656c6563746f72206e6f7420666f756e64006044820152fd5b1561219857565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602260248201527f4469616d6f6e64426173653a2073656c6563746f7220697320696d6d7574616260448201527f6c650000000000000000000000000000000000000000000000000000000000006064820152fd5b9061225a73ffffffffffffffffffffffffffffffffffffffff612253855173ffffffffffffffffffffffffffffffffffffffff1690565b16156120a1565b600780831692600390600090821c5b6040870151805183101561263757611eb483612284926116be565b946122da867fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b54966122f68860601c6122ee81151561212c565b301415612191565b816126075750507fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0190612353826000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5494849687915b60e07fffffffff000000000000000000000000000000000000000000000000000000009260006123e7858c600598891b1b169486811686036124d0577fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b5580881c611fff16941b16918584146124be579061246c9291612433856000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b5491831c921c191617916000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b555b851561247e575b60010190612269565b935060006124b5826000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc95602052604060002090565b55600093612475565b9180949893501c921c1916179361246e565b61255061252b61057a887fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b7fffffffffffffffffffffffffffffffffffffffff0000000000000000000000001690565b6bffffffffffffffffffffffff8516176125b5877fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b557fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0196909591929187919061235a565b50939695505090501b179190565b1561264c57565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602760248201527f4469616d6f6e64426173653a205245504c41434520746172676574206861732060448201527f6e6f20636f6465000000000000000000000000000000000000000000000000006064820152fd5b156126d757565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602860248201527f4469616d6f6e64426173653a205245504c41434520746172676574206973206960448201527f64656e746963616c0000000000000000000000000000000000000000000000006064820152fd5b61278a61278561277f835173ffffffffffffffffffffffffffffffffffffffff1690565b3b151590565b612645565b60005b60408201519081518110156128d7576127ab611eb4826001946116be565b6128d0612803827fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b5461284a8160601c61281681151561212c565b61282230821415612191565b61284361064b895173ffffffffffffffffffffffffffffffffffffffff1690565b14156126d0565b6bffffffffffffffffffffffff61287b61252b611f84895173ffffffffffffffffffffffffffffffffffffffff1690565b911617917fffffffff00000000000000000000000000000000000000000000000000000000166000527f177481ac65e4292921c69f62d1cc7f57541261e5d61c8175cf4e36a01c9bfc93602052604060002090565b550161278d565b505050565b156128e357565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602e60248201527f4469616d6f6e64426173653a20696e697469616c697a6174696f6e207461726760448201527f657420686173206e6f20636f64650000000000000000000000000000000000006064820152fd5b3d15612988573d9061297b61153f83611859565b9182523d6000602084013e565b606090565b9073ffffffffffffffffffffffffffffffffffffffff821690811581511581036129fb57156129bb57505050565b600092839230036129ea575b602082519201905af46129d8612967565b50156129e057565b3d6000803e3d6000fd5b6129f6813b15156128dc565b6129c7565b60846040517f08c379a000000000000000000000000000000000000000000000000000000000815260206004820152602e60248201527f4469616d6f6e64426173653a20696e76616c696420696e697469616c697a617460448201527f696f6e20706172616d65746572730000000000000000000000000000000000006064820152fdfea164736f6c6343000811000a")), Some((ProxyType::EIP_2535, ProxyDispatch::Facet_EIP_2535)));
+    assert_eq!(get_proxy_type(DIAMOND_STANDARD_CODE), Some((ProxyType::EIP_2535, ProxyDispatch::Facet_EIP_2535)));
 }
 
 #[test]
@@ -67,13 +380,1112 @@ fn test_diamond_1() {
 #[test]
 fn test_eip_897() {
     init();
-    // https://etherscan.io/address/0x1715a3e4a142d8b698131108995174f37aeba10d#code
-    assert_eq!(get_proxy_type(&hex_literal::hex!("6080604052600436106100555760003560e01c80633ad06d161461009e57806354fd4d50146100d95780635c60da1b146101005780636fde820214610131578063a9c45fcb14610146578063f1739cae146101cb575b600061005f6101fe565b90506001600160a01b03811661007457600080fd5b60405136600082376000803683855af43d82016040523d6000833e80801561009a573d83f35b3d83fd5b3480156100aa57600080fd5b506100d7600480360360408110156100c157600080fd5b50803590602001356001600160a01b031661020d565b005b3480156100e557600080fd5b506100ee610240565b60408051918252519081900360200190f35b34801561010c57600080fd5b506101156101fe565b604080516001600160a01b039092168252519081900360200190f35b34801561013d57600080fd5b50610115610246565b6100d76004803603606081101561015c57600080fd5b8135916001600160a01b036020820135169181019060608101604082013564010000000081111561018c57600080fd5b82018360208201111561019e57600080fd5b803590602001918460018302840111640100000000831117156101c057600080fd5b509092509050610255565b3480156101d757600080fd5b506100d7600480360360208110156101ee57600080fd5b50356001600160a01b03166102fe565b600061020861038d565b905090565b610215610246565b6001600160a01b0316336001600160a01b03161461023257600080fd5b61023c828261039c565b5050565b60075490565b6006546001600160a01b031690565b61025d610246565b6001600160a01b0316336001600160a01b03161461027a57600080fd5b610284848461020d565b6000306001600160a01b0316348484604051808383808284376040519201945060009350909150508083038185875af1925050503d80600081146102e4576040519150601f19603f3d011682016040523d82523d6000602084013e6102e9565b606091505b50509050806102f757600080fd5b5050505050565b610306610246565b6001600160a01b0316336001600160a01b03161461032357600080fd5b6001600160a01b03811661033657600080fd5b7f5a3e66efaa1e445ebd894728a69d6959842ea1e97bd79b892797106e270efcd961035f610246565b604080516001600160a01b03928316815291841660208301528051918290030190a161038a81610432565b50565b6008546001600160a01b031690565b6008546001600160a01b03828116911614156103b757600080fd5b6103c081610454565b6103c957600080fd5b60075482116103d757600080fd5b6007829055600880546001600160a01b0383166001600160a01b031990911681179091556040805184815290517f4289d6195cf3c2d2174adf98d0e19d4d2d08887995b99cb7b100e7ffe795820e9181900360200190a25050565b600680546001600160a01b0319166001600160a01b0392909216919091179055565b6000813f7fc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a47081811480159061048857508115155b94935050505056fea2646970667358221220c0ef938c3cb0aabada971e1d0565a4ce5504320f0416427bd7838d4790e313e164736f6c63430007050033")), Some((ProxyType::EIP_897, ProxyDispatch::Storage(U256::from_be_bytes(hex_literal::hex!("0000000000000000000000000000000000000000000000000000000000000008"))))));
+    assert_eq!(get_proxy_type(EIP_897_CODE), Some((ProxyType::EIP_897, ProxyDispatch::Storage(U256::from_be_bytes(hex_literal::hex!("0000000000000000000000000000000000000000000000000000000000000008")), None))));
 }
 
 #[test]
 fn test_eip_1967() {
     init();
-    // https://etherscan.io/address/0xdd28b7fd7780e9388582af20e5247e1dcbac8ae9#code
-    assert_eq!(get_proxy_type(&hex_literal::hex!("60806040523661001357610011610017565b005b6100115b61004a7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc546001600160a01b031661007b565b565b90565b606061007483836040518060600160405280602781526020016102316027913961009f565b9392505050565b3660008037600080366000845af43d6000803e80801561009a573d6000f35b3d6000fd5b6060833b6101035760405162461bcd60e51b815260206004820152602660248201527f416464726573733a2064656c65676174652063616c6c20746f206e6f6e2d636f6044820152651b9d1c9858dd60d21b60648201526084015b60405180910390fd5b600080856001600160a01b03168560405161011e91906101b1565b600060405180830381855af49150503d8060008114610159576040519150601f19603f3d011682016040523d82523d6000602084013e61015e565b606091505b509150915061016e828286610178565b9695505050505050565b60608315610187575081610074565b8251156101975782518084602001fd5b8160405162461bcd60e51b81526004016100fa91906101cd565b600082516101c3818460208701610200565b9190910192915050565b60208152600082518060208401526101ec816040850160208701610200565b601f01601f19169190910160400192915050565b60005b8381101561021b578181015183820152602001610203565b8381111561022a576000848401525b5050505056fe416464726573733a206c6f772d6c6576656c2064656c65676174652063616c6c206661696c6564a2646970667358221220727e9c7322af70a33c460d6c97b3533591ca0a1b66f567d29a66e092f79e0a0d64736f6c63430008070033")), Some((ProxyType::EIP_1967, ProxyDispatch::Storage(U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"))))));
+    assert_eq!(get_proxy_type(EIP_1967_CODE), Some((ProxyType::EIP_1967, ProxyDispatch::Storage(U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")), None))));
+}
+
+#[test]
+fn test_rule_policy() {
+    init();
+    let eip_1967_slot = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+    let result = detect_proxy(EIP_1967_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.rule, RuleId::KnownStorageSlot);
+
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::KnownStorageSlot), ..Default::default() };
+    let result = detect_proxy(EIP_1967_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::EIP_1967_CUSTOM, ProxyDispatch::Storage(eip_1967_slot, None), RuleId::CustomStorageSlot));
+
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::KnownStorageSlot).disable(RuleId::CustomStorageSlot), ..Default::default() };
+    assert!(detect_proxy(EIP_1967_CODE, &config).is_none());
+
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::DiamondLoupeSelector).disable(RuleId::DiamondStorageSlot), ..Default::default() };
+    let result = detect_proxy(DIAMOND_STANDARD_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::DiamondOther, RuleId::DiamondOther));
+
+    let clone = hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3");
+    let config = DetectorConfig { rules: RulePolicy::default().disable(RuleId::Eip1167Pattern), ..Default::default() };
+    let result = detect_proxy(&clone, &config).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::StaticAddress, RuleId::StaticDelegateCall));
+}
+
+#[test]
+fn test_anti_evasion() {
+    init();
+    let probe = Address::from(hex_literal::hex!("beefbeefbeefbeefbeefbeefbeefbeefbeefbeef"));
+    let implementation = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    // Only delegates when EXTCODESIZE(probe) is odd, i.e. it depends on the tracer's dummy code
+    let code = hex_literal::hex!("73beefbeefbeefbeefbeefbeefbeefbeefbeefbeef3b600116601d57005b3660006000376000600036600073bebebebebebebebebebebebebebebebebebebebe5af43d600060003e3d6000f3");
+
+    let seed = (0..u64::MAX).find(|seed| {
+        let env = TraceEnvironment::from_seed(*seed);
+        env.dummy_code(&probe).len() % 2 != env.alternate().dummy_code(&probe).len() % 2
+    }).unwrap();
+
+    let config = DetectorConfig { seed: Some(seed), anti_evasion: true, ..Default::default() };
+    let result = detect_proxy(&code, &config).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch), (ProxyType::StaticAddress, ProxyDispatch::Static(implementation)));
+    assert!(result.evasive);
+    assert_eq!(result.findings, vec![Finding::EvasiveBehavior]);
+
+    let result = detect_proxy(EIP_1967_CODE, &config).unwrap();
+    assert_eq!(result.proxy_type, ProxyType::EIP_1967);
+    assert!(!result.evasive);
+}
+
+#[test]
+fn test_provenance() {
+    init();
+    let provenance = |code: &[u8]| detect_proxy(code, &DetectorConfig::default()).unwrap().provenance;
+    let implementation = |offset, length| vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, offset, length)];
+
+    assert_eq!(provenance(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3")), implementation(10, 20));
+    assert_eq!(provenance(&hex_literal::hex!("363d3d373d3d3d363d6fbebebebebebebebebebebebebebebebe5af43d82803e903d91602757fd5bf3")), implementation(10, 16));
+    assert_eq!(provenance(&hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602a57fd5bf3")), implementation(9, 20));
+    assert_eq!(provenance(&hex_literal::hex!("363d3d373d3d3d3d60368038038091363936013d73bebebebebebebebebebebebebebebebebebebebe5af43d3d93803e603457fd5bf3")), implementation(21, 20));
+    // Dynamically detected static delegatecall
+    assert_eq!(provenance(&hex_literal::hex!("3660006000376000600036600073bebebebebebebebebebebebebebebebebebebebe5af400")), implementation(14, 20));
+
+    assert_eq!(provenance(EIP_1967_CODE), vec![ByteProvenance::new(ProvenanceKind::SlotConstant, 28, 32)]);
+    assert_eq!(provenance(DIAMOND_STANDARD_CODE), vec![ByteProvenance::new(ProvenanceKind::SelectorConstant, 94, 4)]);
+    // Slot 8 is pushed as a single byte, indistinguishable from any other constant
+    assert_eq!(provenance(EIP_897_CODE), vec![]);
+}
+
+#[test]
+fn test_packed_slot() {
+    init();
+    let config = DetectorConfig { layout_analysis: true, ..Default::default() };
+    let address_mask = U256::MAX >> 96;
+
+    // Address in the high 20 bytes of slot 3: SLOAD PUSH1 0x60 SHR
+    let high_packed = hex_literal::hex!("3660006000376000600036600060035460601c5af43d600060003e3d6000f3");
+    let result = detect_proxy(&high_packed, &config).unwrap();
+    assert_eq!(result.dispatch, ProxyDispatch::Storage(U256::from(3), Some(SlotExtraction::new(96, address_mask))));
+    // Without the analysis the shifted value isn't traced back to the slot
+    assert!(!matches!(detect_proxy(&high_packed, &DetectorConfig::default()).unwrap().dispatch, ProxyDispatch::Storage(..)));
+
+    // Address after a 4 byte counter in slot 5, old style: SLOAD PUSH5 2**32 SWAP1 DIV PUSH20 0xff..ff AND
+    let mid_packed = hex_literal::hex!("36600060003760006000366000600554640100000000900473ffffffffffffffffffffffffffffffffffffffff165af43d600060003e3d6000f3");
+    let result = detect_proxy(&mid_packed, &config).unwrap();
+    assert_eq!(result.dispatch, ProxyDispatch::Storage(U256::from(5), Some(SlotExtraction::new(32, address_mask))));
+
+    // Plain slots need no recipe
+    assert_eq!(detect_proxy(EIP_1967_CODE, &config).unwrap().dispatch, get_proxy_type(EIP_1967_CODE).unwrap().1);
+}
+
+#[test]
+fn test_immutable_slot() {
+    init();
+    // Same skeleton, the slot is an immutable pushed with PUSH32 at offset 13
+    let instance = |slot: [u8; 32]| [&hex_literal::hex!("366000600037600060003660007f")[..], &slot, &hex_literal::hex!("545af43d600060003e3d6000f3")].concat();
+    for slot in [
+        hex_literal::hex!("a7f1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f"),
+        hex_literal::hex!("0c1d2e3f405162738495a6b7c8d9eafb0c1d2e3f405162738495a6b7c8d9eafb"),
+    ] {
+        let result = detect_proxy(&instance(slot), &DetectorConfig::default()).unwrap();
+        assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::ImmutableSlotProxy, ProxyDispatch::Storage(U256::from_be_bytes(slot), None), RuleId::ImmutableStorageSlot));
+        assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::SlotConstant, 14, 32)]);
+    }
+}
+
+#[test]
+fn test_zero_idiom_forwarders() {
+    init();
+    let implementation = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    let forwarders: [(&[u8], usize); 3] = [
+        // PUSH0 instead of RETURNDATASIZE
+        (&hex_literal::hex!("365f5f375f5f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"), 10),
+        // PUSH1 0x00, the jump target moves accordingly
+        (&hex_literal::hex!("36600060003760006000600036600073bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91603157fd5bf3"), 16),
+        // DUP of zeros already on the stack
+        (&hex_literal::hex!("363d80373d8080368273bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"), 10),
+    ];
+    for (code, address_offset) in forwarders {
+        let result = detect_proxy(code, &DetectorConfig::default()).unwrap();
+        assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::EIP_1167, ProxyDispatch::Static(implementation), RuleId::Eip1167Pattern));
+        assert_eq!(result.provenance, vec![ByteProvenance::new(ProvenanceKind::ImplementationAddress, address_offset, 20)]);
+    }
+
+    // 7511 with PUSH1 0x00 before the call
+    let result = detect_proxy(&hex_literal::hex!("366000600037600060003660007300000000bebebebebebebebebebebebebebebebe5af43d5f5f3e5f3d91602f57fd5bf3"), &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_7511, RuleId::Eip7511Pattern));
+
+    // A non zero return size isn't the same forwarder, only the dynamic path matches it
+    let result = detect_proxy(&hex_literal::hex!("363d3d373d3d6001363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602c57fd5bf3"), &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::StaticAddress, RuleId::StaticDelegateCall));
+}
+
+#[test]
+fn test_detect_borrowed_corpus() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/corpus.bin")).unwrap();
+    let map = unsafe { memmap2::Mmap::map(&file).unwrap() };
+
+    // Length prefixed records, sliced straight out of the map
+    let mut codes = Vec::new();
+    let mut data = &map[..];
+    while let Some((len, rest)) = data.split_first_chunk::<4>() {
+        let (code, rest) = rest.split_at(u32::from_be_bytes(*len) as usize);
+        codes.push(code);
+        data = rest;
+    }
+    let types: Vec<Option<ProxyType>> = detect_proxies(codes.iter(), &config).map(|r| r.map(|r| r.proxy_type)).collect();
+    assert_eq!(types, vec![Some(ProxyType::EIP_1167), Some(ProxyType::EIP_1967), Some(ProxyType::EIP_7511)]);
+
+    let cows: Vec<Cow<[u8]>> = vec![Cow::Borrowed(codes[0]), Cow::Owned(codes[2].to_vec()), Cow::Borrowed(&[0x00])];
+    let types: Vec<Option<ProxyType>> = detect_proxies(cows, &config).map(|r| r.map(|r| r.proxy_type)).collect();
+    assert_eq!(types, vec![Some(ProxyType::EIP_1167), Some(ProxyType::EIP_7511), None]);
+}
+
+#[test]
+fn test_is_likely_proxy() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let proxies = [
+        EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE, GENERATED_ROUTER_CODE, GENERATED_ROUTER_LINEAR_CODE, VYPER_FORWARDER_V2_CODE,
+        VYPER_FORWARDER_V1_CODE, SAFE_PROXY_CODE, SOLADY_PUSH0_CLONE_CODE, BEACON_PROXY_CODE, TRANSPARENT_PROXY_CODE, UNITROLLER_CODE,
+        METAMORPHIC_INIT_CODE, SOLADY_CWIA_CODE, BLUEPRINT_1167_CODE,
+    ];
+    for (i, code) in proxies.iter().enumerate() {
+        assert!(detect_proxy(code, &config).is_some());
+        assert!(is_likely_proxy(code, &config), "proxy {}", i);
+    }
+
+    assert!(!is_likely_proxy(ERC20_BINARY_SEARCH_CODE, &config));
+    // 0xf4 as PUSH data: PUSH2 0xf4f4 POP, padded
+    let mut pushed = hex_literal::hex!("61f4f450").repeat(4);
+    assert!(!is_likely_proxy(&pushed, &config));
+    // A DELEGATECALL in a large contract needs a known slot
+    pushed.extend(hex_literal::hex!("6000600060006000305af450"));
+    assert!(is_likely_proxy(&pushed, &config));
+    let mut large = pushed.clone();
+    large.resize(LIKELY_PROXY_MAX_SIZE + 1, 0x5b);
+    assert!(!is_likely_proxy(&large, &config));
+    large.push(0x7f);
+    large.extend(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+    assert!(is_likely_proxy(&large, &config));
+    for (code, _) in EDGE_CODES {
+        assert!(!is_likely_proxy(code, &config));
+    }
+}
+
+/// Codes every entry point has to agree on, with the outcome they get.
+const EDGE_CODES: &[(&[u8], DetectOutcome)] = &[
+    (&[], DetectOutcome::NotAContract),
+    // STOP, INVALID, SELFDESTRUCT
+    (&[0x00], DetectOutcome::CodeTooSmall(1)),
+    (&[0xfe], DetectOutcome::CodeTooSmall(1)),
+    (&[0xff], DetectOutcome::CodeTooSmall(1)),
+    // selfdestruct(address(0)) stub
+    (&hex_literal::hex!("6000ff"), DetectOutcome::CodeTooSmall(3)),
+    // Blueprint magic without a payload
+    (&hex_literal::hex!("fe7100"), DetectOutcome::CodeTooSmall(3)),
+    (&hex_literal::hex!("363d3d373d3d3d363d"), DetectOutcome::CodeTooSmall(9)),
+    // Long enough to be analysed
+    (&hex_literal::hex!("60006000600060006000"), DetectOutcome::NotAProxy { reason: NotAProxyReason::NoDelegatecall }),
+    // 0xf4 only in a PUSH32 constant
+    (&hex_literal::hex!("7ff4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f4f400"), DetectOutcome::NotAProxy { reason: NotAProxyReason::NoDelegatecall }),
+    // The placeholder ahead of delegatecall(0, 0, 0, 0, 0, 0), which never runs
+    (&hex_literal::hex!("fe" "60006000600060006000f4"), DetectOutcome::NotAProxy { reason: NotAProxyReason::NoDelegatecall }),
+    // Metadata only, {"solc": 0x0008f4}
+    (&hex_literal::hex!("a164736f6c63430008f4" "000a"), DetectOutcome::NotAProxy { reason: NotAProxyReason::NoDelegatecall }),
+];
+
+#[test]
+fn test_edge_case_codes() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    for (code, outcome) in EDGE_CODES {
+        assert_eq!(&detect_proxy_outcome(code, &config), outcome, "code {:02x?}", code);
+        assert_eq!(detect_proxy(code, &config), None, "code {:02x?}", code);
+        assert_eq!(get_proxy_type(code), None, "code {:02x?}", code);
+        assert_eq!(detect_creation_code(code, &config), None, "code {:02x?}", code);
+    }
+
+    let codes: Vec<&[u8]> = EDGE_CODES.iter().map(|(code, _)| *code).collect();
+    assert!(detect_proxies(codes.iter(), &config).all(|result| result.is_none()));
+    let reports = Mutex::new(Vec::new());
+    let record = |completed: u64, _: Option<u64>, _: &str| reports.lock().unwrap().push(completed);
+    assert!(detect_proxies_with_progress(codes.iter(), &config, &record).all(|result| result.is_none()));
+    assert_eq!(reports.into_inner().unwrap().last(), Some(&(codes.len() as u64)));
+
+    // The shortest code forwarding its calldata is over the bound
+    let forwarder = hex_literal::hex!("365f5f375f5f365f335af4");
+    assert!(forwarder.len() > MIN_PROXY_CODE_SIZE);
+    assert!(matches!(detect_proxy_outcome(&forwarder, &config), DetectOutcome::Proxy(_)));
+}
+
+#[test]
+fn test_environment_opcodes() {
+    init();
+    // The hash of the previous block and the code hashes of the proxy itself and of an account
+    // it wasn't given, before delegating to the address in slot 0
+    const GUARDED_CODE: &[u8] = &hex_literal::hex!("43600190034050303f5060423f50600060003660006000545af400");
+    let result = detect_proxy(GUARDED_CODE, &DetectorConfig::default()).unwrap();
+    assert!(matches!(result.dispatch, ProxyDispatch::Storage(slot, _) if slot == U256::ZERO), "{:?}", result);
+    // Traced again in another environment, the block hash differs but not the outcome
+    let result = detect_proxy_outcome(GUARDED_CODE, &DetectorConfig { seed: Some(7), ..Default::default() });
+    assert!(matches!(result, DetectOutcome::Proxy(result) if matches!(result.dispatch, ProxyDispatch::Storage(slot, _) if slot == U256::ZERO)));
+    let env = TraceEnvironment::from_seed(7);
+    let number = U256::from(env.block_number - 1);
+    assert_eq!(env.dummy_block_hash(number), TraceEnvironment::from_seed(7).dummy_block_hash(number));
+    assert_ne!(env.dummy_block_hash(number), env.alternate().dummy_block_hash(number));
+}
+
+#[test]
+fn test_not_a_proxy_reasons() {
+    init();
+    // delegatecall(gas, caller, 0, 0, 0, 0): no rule left describes it
+    let code = hex_literal::hex!("6000600060006000335af400");
+    let config = DetectorConfig { seed: Some(1), rules: RulePolicy::default().disable(RuleId::StaticDelegateCall), ..Default::default() };
+    let DetectOutcome::Inconclusive { observations } = detect_proxy_outcome(&code, &config) else {
+        panic!("expected an inconclusive outcome");
+    };
+    assert!(!observations.static_delegation.is_empty());
+    assert_eq!(detect_proxy(&code, &config), None);
+
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    assert!(matches!(detect_proxy_outcome(&code, &config), DetectOutcome::Proxy(_)));
+
+    // The same DELEGATECALL behind a STOP
+    let code = hex_literal::hex!("006000600060006000335af400");
+    assert_eq!(detect_proxy_outcome(&code, &config), DetectOutcome::NotAProxy { reason: NotAProxyReason::DelegatecallNotReached });
+    assert_eq!(detect_proxy(&code, &config), None);
+}
+
+#[test]
+fn test_refine_proxy_type() {
+    init();
+    let slot = |hex: [u8; 32]| U256::from_be_bytes(hex);
+    let eip_1967 = slot(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+    let eip_1822 = slot(hex_literal::hex!("c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7"));
+    let pinned = [
+        (eip_1967, ProxyType::EIP_1967),
+        (slot(hex_literal::hex!("7050c9e0f4ca769c69bd3a8ef740bc37934f8e2c036e5a723fd8ee048ed3f8c3")), ProxyType::EIP_1967_ZOS),
+        (slot(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50")), ProxyType::EIP_1967_BEACON),
+        (eip_1822, ProxyType::EIP_1822),
+        (U256::ZERO, ProxyType::EIP_897),
+        (U256::from(0x101), ProxyType::EIP_1967_CUSTOM),
+    ];
+    for (slot, proxy_type) in pinned {
+        assert_eq!(refine_proxy_type(&SlotObservations { slot, ..Default::default() }), proxy_type, "slot {:#x}", slot);
+    }
+    let obs = |admin_forwarded, proxiable_uuid| SlotObservations { slot: eip_1967, admin_forwarded, proxiable_uuid, ..Default::default() };
+    assert_eq!(refine_proxy_type(&obs(Some(false), None)), ProxyType::EIP_1967_TRANSPARENT);
+    assert_eq!(refine_proxy_type(&obs(Some(true), None)), ProxyType::EIP_1967);
+    assert_eq!(refine_proxy_type(&obs(None, Some(eip_1967))), ProxyType::UUPS);
+    assert_eq!(refine_proxy_type(&obs(None, Some(eip_1822))), ProxyType::UUPS);
+    assert_eq!(refine_proxy_type(&obs(None, Some(U256::from(1)))), ProxyType::EIP_1967);
+    let safe = SlotObservations { slot: U256::ZERO, selectors: vec![Selector::from_u32_be(0xa619486e)], ..Default::default() };
+    assert_eq!(refine_proxy_type(&safe), ProxyType::GnosisSafe);
+
+    // Agrees with the detectors on the codes they classify, pinned rulesets included
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..config.clone() };
+    for (code, admin_forwarded) in [(EIP_1967_CODE, None), (EIP_897_CODE, None), (SAFE_PROXY_CODE, None), (UNITROLLER_CODE, None), (BEACON_PROXY_CODE, None), (TRANSPARENT_PROXY_CODE, Some(false))] {
+        let detected = detect_proxy(code, &config).unwrap();
+        let slot = match detected.dispatch {
+            ProxyDispatch::Storage(slot, _) | ProxyDispatch::Beacon(slot) => slot,
+            dispatch => panic!("unexpected dispatch {:?}", dispatch),
+        };
+        let obs = SlotObservations { admin_forwarded, ..SlotObservations::from_code(slot, code) };
+        assert_eq!(refine_proxy_type(&obs), detected.proxy_type);
+        if let Some(pinned) = detect_proxy(code, &frozen) {
+            assert_eq!(refine_proxy_type_with(&obs, &frozen), pinned.proxy_type);
+        }
+    }
+    // The frozen ruleset predates the Compound rule
+    let unitroller = SlotObservations::from_code(U256::from(2), UNITROLLER_CODE);
+    assert_eq!(refine_proxy_type_with(&unitroller, &frozen), ProxyType::EIP_897);
+}
+
+#[test]
+fn test_detect_progress() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let codes: Vec<&[u8]> = std::iter::repeat_n(&[0x00][..], 200).chain([EIP_1967_CODE]).collect();
+    let reports = Mutex::new(Vec::new());
+    let record = |completed: u64, total: Option<u64>, stage: &str| reports.lock().unwrap().push((completed, total, stage.to_string()));
+
+    let results: Vec<_> = detect_proxies_with_progress(codes.iter(), &config, &record).collect();
+    assert_eq!(results.last().unwrap().as_ref().unwrap().proxy_type, ProxyType::EIP_1967);
+    let reports = reports.into_inner().unwrap();
+    // Throttled, but starting at the first code and ending with all of them
+    assert!(reports.len() < codes.len());
+    assert_eq!(reports.first().unwrap().0, 1);
+    assert_eq!(reports.last().unwrap(), &(201, Some(201), "detect".to_string()));
+    assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+}
+
+#[test]
+fn test_detect_many_progress() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let codes: Vec<&[u8]> = vec![EIP_1967_CODE, &[0x00], EIP_1967_CODE, &[0x00, 0x00]];
+    let reports = Mutex::new(Vec::new());
+    let record = |completed: u64, total: Option<u64>, stage: &str| reports.lock().unwrap().push((completed, total, stage.to_string()));
+
+    let results = detect_many_with_progress(&codes, &config, &record);
+    assert_eq!(results.len(), 4);
+    assert_eq!(results, detect_many(&codes, &config));
+    // Each distinct code counts once
+    let reports = reports.into_inner().unwrap();
+    assert_eq!(reports.last().unwrap(), &(3, Some(3), "detect".to_string()));
+    assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+}
+
+/// [DefaultProbes], counting the codes it's asked to probe.
+#[derive(Debug, Default)]
+struct CountingProbes(AtomicUsize);
+
+impl CalldataStrategy for CountingProbes {
+    fn probes(&self, code: &Bytes) -> Vec<Bytes> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        DefaultProbes.probes(code)
+    }
+}
+
+/// An EIP-1167 clone of `implementation`.
+fn clone_of(implementation: u8) -> Vec<u8> {
+    [&hex_literal::hex!("363d3d373d3d3d363d73")[..], &[implementation; 20], &hex_literal::hex!("5af43d82803e903d91602b57fd5bf3")].concat()
+}
+
+#[test]
+fn test_detect_many() {
+    init();
+    let probes = Arc::new(CountingProbes::default());
+    let config = DetectorConfig { seed: Some(1), calldata: Some(probes.clone()), ..Default::default() };
+    // The clones of three factories and copies of one traced proxy, interleaved
+    let codes: Vec<Vec<u8>> = (0..400u32).map(|i| match i % 4 {
+        3 => EIP_1967_CODE.to_vec(),
+        factory => clone_of(0xa0 + factory as u8),
+    }).collect();
+
+    let start = Instant::now();
+    let results = detect_many(&codes, &config);
+    let batched = start.elapsed();
+    // The traced proxy is probed once for its 100 copies
+    assert_eq!(probes.0.load(Ordering::Relaxed), 1);
+    assert_eq!(results.len(), codes.len());
+    for (i, result) in results.iter().enumerate() {
+        let result = result.as_ref().unwrap().as_ref().unwrap();
+        match i % 4 {
+            3 => assert_eq!(result.proxy_type, ProxyType::EIP_1967),
+            factory => assert_eq!(result.dispatch, ProxyDispatch::Static(Address::repeat_byte(0xa0 + factory as u8))),
+        }
+    }
+
+    let start = Instant::now();
+    let looped: Vec<_> = detect_proxies(codes.iter(), &config).collect();
+    let one_by_one = start.elapsed();
+    assert_eq!(probes.0.load(Ordering::Relaxed), 101);
+    assert_eq!(results.into_iter().map(Result::unwrap).collect::<Vec<_>>(), looped);
+    assert!(batched < one_by_one, "batched {:?}, one by one {:?}", batched, one_by_one);
+
+    let empty: &[&[u8]] = &[];
+    assert!(detect_many(empty, &config).is_empty());
+    assert_eq!(detect_many(&[&[0x00][..]], &config), vec![Ok(None)]);
+}
+
+#[test]
+fn test_blueprint() {
+    init();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let target = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+
+    let blueprint = parse_blueprint(BLUEPRINT_1167_DATA_CODE).unwrap().unwrap();
+    assert_eq!((blueprint.version, blueprint.data, blueprint.initcode_offset), (1, &hex_literal::hex!("aabbcc")[..], 7));
+    assert_eq!(blueprint.initcode, &BLUEPRINT_1167_DATA_CODE[7..]);
+    assert_eq!(parse_blueprint(EIP_1967_CODE), Ok(None));
+
+    for (code, info) in [
+        (BLUEPRINT_1167_CODE, BlueprintInfo { version: 0, data_len: 0, initcode: true }),
+        (BLUEPRINT_1167_DATA_CODE, BlueprintInfo { version: 1, data_len: 3, initcode: true }),
+    ] {
+        let result = detect_blueprint(code, &config).unwrap().unwrap();
+        assert_eq!((result.proxy_type, &result.dispatch), (ProxyType::EIP_1167, &ProxyDispatch::Static(target)));
+        assert_eq!(result.blueprint, Some(info));
+        // Provenance points at the address inside the container
+        let address = result.provenance[0];
+        assert_eq!(&code[address.offset..address.offset + address.length], target.as_slice());
+        assert_eq!(detect_proxy(code, &config), Some(result));
+    }
+}
+
+#[test]
+fn test_malformed_blueprint() {
+    let config = DetectorConfig::default();
+    assert_eq!(detect_blueprint(BLUEPRINT_MALFORMED_CODE, &config), Err(DetectError::InvalidBytecode { position: 5, reason: "data section past the end of the code" }));
+    assert_eq!(parse_blueprint(&hex_literal::hex!("fe7103")), Err(DetectError::InvalidBytecode { position: 2, reason: "reserved data length encoding" }));
+    assert_eq!(parse_blueprint(&hex_literal::hex!("fe71")).unwrap_err().to_string(), "invalid bytecode at byte 2: missing version byte");
+    assert_eq!(parse_blueprint(&hex_literal::hex!("fe7100")), Err(DetectError::InvalidBytecode { position: 3, reason: "empty initcode" }));
+    assert_eq!(detect_proxy(BLUEPRINT_MALFORMED_CODE, &config), None);
+}
+
+/// Reverts after asking the address in slot 3 for `implementation()` when called with
+/// 0x11223344, delegates everything else to the address in slot 7.
+const HAND_DISPATCHER_CODE: &[u8] = &hex_literal::hex!(
+    "600035" "60e01c" "6311223344" "14" "601f" "57"
+    // Forward: calldatacopy, delegatecall(gas, sload(7), 0, calldatasize, 0, 0), stop
+    "36" "6000" "80" "37" "6000" "80" "36" "81" "6007" "54" "5a" "f4" "00"
+    // 0x1f: staticcall(gas, sload(3), 0, 4, 0, 0) with 0x5c60da1b, then revert
+    "5b" "635c60da1b" "60e0" "1b" "6000" "52" "6000" "80" "6004" "81" "6003" "54" "5a" "fa" "50" "6000" "80" "fd"
+);
+
+#[test]
+fn test_trace_dispatch() {
+    let code = Bytes::from_static(HAND_DISPATCHER_CODE);
+    let config = TraceConfig::new(TraceEnvironment::from_seed(1));
+
+    let forwarded = trace_dispatch(&code, Bytes::from_static(&hex_literal::hex!("aabbccdd")), &config).unwrap();
+    assert_eq!(forwarded.storage_access, vec![U256::from(7)]);
+    assert_eq!(forwarded.delegatecall_storage, vec![U256::from(7)]);
+    assert!(forwarded.delegatecall_unknown.is_empty() && forwarded.external_calls.is_empty());
+    assert!(!forwarded.reverted);
+
+    let asked = trace_dispatch(&code, Bytes::from_static(&hex_literal::hex!("11223344")), &config).unwrap();
+    let implementation = Selector::new(hex_literal::hex!("5c60da1b"));
+    assert_eq!(asked.storage_access, vec![U256::from(3)]);
+    assert!(asked.delegatecall_storage.is_empty());
+    assert_eq!(asked.external_calls.len(), 1);
+    assert_eq!(asked.external_calls[0].1, implementation);
+    assert_eq!(asked.storage_calls, vec![(U256::from(3), implementation)]);
+    assert!(asked.reverted);
+
+    // Writes are seen even when reverted: sstore(2, 1), revert
+    let reverted = trace_dispatch(&Bytes::from_static(&hex_literal::hex!("6001600255" "600080fd")), Bytes::new(), &config).unwrap();
+    assert_eq!((reverted.sstores, reverted.reverted), (vec![(U256::from(2), U256::from(1))], true));
+
+    // A caller that can't pay for the gas
+    let mut broke = config.clone();
+    broke.environment.basefee = 1_000_000_000_000_000_000;
+    assert!(matches!(trace_dispatch(&code, Bytes::new(), &broke), Err(TraceError::Transact(_))));
+}
+
+#[test]
+fn test_trace_dispatches_match_one_by_one() {
+    init();
+    let mut codes: Vec<Vec<u8>> = [
+        EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE, ERC20_BINARY_SEARCH_CODE, GENERATED_ROUTER_CODE, BEACON_PROXY_CODE, SAFE_PROXY_CODE,
+        TRANSPARENT_PROXY_CODE, TRANSPARENT_UPGRADE_PROXY_CODE, UNITROLLER_CODE, METAMORPHIC_INIT_CODE, HAND_DISPATCHER_CODE, INFINITE_LOOP_CODE,
+    ].iter().map(|code| code.to_vec()).collect();
+    let corpus = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/corpus.bin")).unwrap();
+    let mut data = &corpus[..];
+    while let Some((len, rest)) = data.split_first_chunk::<4>() {
+        let (code, rest) = rest.split_at(u32::from_be_bytes(*len) as usize);
+        codes.push(code.to_vec());
+        data = rest;
+    }
+
+    // What a call leaves behind mustn't leak into the next one, whatever the settings. The
+    // budget keeps the loop short and has to be restarted for each call
+    let configs = [
+        TraceConfig { max_steps: Some(50_000), ..TraceConfig::new(TraceEnvironment::from_seed(1)) },
+        TraceConfig { layout_analysis: true, call_value: U256::from(1), max_steps: Some(5_000), ..TraceConfig::new(TraceEnvironment::from_seed(2)) },
+        TraceConfig { storage: HashMap::from([(U256::from(7), U256::from(0xbeef))]), max_steps: Some(50_000), ..TraceConfig::new(TraceEnvironment::from_seed(3)) },
+    ];
+    for code in codes.into_iter().map(Bytes::from) {
+        let mut calldata = DefaultProbes.probes(&code);
+        calldata.extend(PushedSelectorProbes { max_selectors: 8 }.probes(&code));
+        calldata.push(Bytes::new());
+        for config in &configs {
+            let one_by_one: Vec<_> = calldata.iter().map(|calldata| trace_dispatch(&code, calldata.clone(), config)).collect();
+            assert_eq!(trace_dispatches(&code, calldata.clone(), config), one_by_one, "{}", code);
+        }
+    }
+}
+
+#[test]
+fn test_synthetic_storage_key() {
+    // delegatecall(gas, sload(0x1234), 0, calldatasize, 0, 0) after copying the calldata
+    let code = Bytes::from_static(&hex_literal::hex!("366000600037" "60006000366000" "611234" "54" "5af400"));
+    let key = Address::repeat_byte(0x5a);
+    let config = TraceConfig { storage_key: Some(key), ..TraceConfig::new(TraceEnvironment::from_seed(1)) };
+    let data = trace_dispatch(&code, Bytes::new(), &config).unwrap();
+    let target = data.calls.iter().find(|call| call.kind == CallKind::DelegateCall).unwrap().target;
+    assert_eq!(U256::from_be_slice(target.as_slice()), U256::from(0x1234) ^ U256::from_be_slice(key.as_slice()));
+    assert_eq!(data.delegatecall_storage, vec![U256::from(0x1234)]);
+    // Each environment has its own
+    assert_ne!(TraceEnvironment::from_seed(1).storage_key(), TraceEnvironment::from_seed(2).storage_key());
+
+    // The same address pushed by the code is a constant, not the slot's value
+    let constant = [&hex_literal::hex!("366000600037" "60006000366000" "611234" "54" "50" "73")[..], target.as_slice(), &hex_literal::hex!("5af400")].concat();
+    let data = trace_dispatch(&Bytes::from(constant), Bytes::new(), &config).unwrap();
+    assert!(data.delegatecall_storage.is_empty());
+    assert_eq!(data.delegatecall_from_code, vec![target]);
+}
+
+#[test]
+fn test_mangled_slot_value() {
+    init();
+    // delegatecall(gas, and(sload(0x1234), 2^128 - 1), 0, calldatasize, 0, 0): the high bytes
+    // of the address are cleared
+    const MANGLING_CODE: &[u8] = &hex_literal::hex!("366000600037" "60006000366000" "611234" "54" "6fffffffffffffffffffffffffffffffff" "16" "5af400");
+    let config = TraceConfig::new(TraceEnvironment::from_seed(1));
+    let data = trace_dispatch(&Bytes::from_static(MANGLING_CODE), Bytes::new(), &config).unwrap();
+    assert_eq!(data.delegatecall_storage, vec![U256::from(0x1234)]);
+    assert!(data.delegatecall_unknown.is_empty());
+    let mask = (U256::from(1) << 128) - U256::from(1);
+    assert_eq!(data.extraction(&U256::from(0x1234)), Some(SlotExtraction::new(0, mask)));
+
+    let result = detect_proxy(MANGLING_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.dispatch, ProxyDispatch::Storage(U256::from(0x1234), Some(SlotExtraction::new(0, mask))));
+}
+
+/// An EIP-1167 clone of 0xaa..aa behind a guard delegating everything to 0xbb..bb: the body
+/// matches statically but never runs
+const SPOOFED_CLONE_CODE: &[u8] = &hex_literal::hex!(
+    // calldatacopy(0, 0, calldatasize), delegatecall(gas, 0xbb..bb, 0, calldatasize, 0, 0)
+    "36" "6000" "6000" "37" "6000" "6000" "36" "6000" "73" "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb" "5a" "f4"
+    // returndatacopy(0, 0, returndatasize), return(0, returndatasize)
+    "3d" "6000" "6000" "3e" "3d" "6000" "f3"
+    "363d3d373d3d3d363d73" "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa" "5af43d82803e903d91602b57fd5bf3"
+);
+
+#[test]
+fn test_detect_all() {
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let clone = Address::repeat_byte(0xaa);
+    let guard = Address::repeat_byte(0xbb);
+    let opinions = detect_all(SPOOFED_CLONE_CODE, &config);
+    let summary: Vec<_> = opinions.iter().map(|(name, result)| (*name, result.proxy_type, result.dispatch.clone())).collect();
+    assert_eq!(summary, vec![("minimal", ProxyType::EIP_1167, ProxyDispatch::Static(clone)), ("trace", ProxyType::StaticAddress, ProxyDispatch::Static(guard))]);
+    // detect_proxy stops at the first
+    assert_eq!(detect_proxy(SPOOFED_CLONE_CODE, &config).unwrap(), opinions[0].1);
+
+    // On a tie the pattern wins, flagged with what the trace found
+    let agreed = consensus(&opinions).unwrap();
+    assert_eq!(agreed.dispatch, ProxyDispatch::Static(clone));
+    assert_eq!(agreed.findings, vec![Finding::StrategyConflict { rule: RuleId::StaticDelegateCall, dispatch: ProxyDispatch::Static(guard) }]);
+    assert_eq!(consensus(&opinions[1..]).unwrap().dispatch, ProxyDispatch::Static(guard));
+    assert_eq!(consensus(&[]), None);
+
+    // A real clone has nothing to conflict with
+    let opinions = detect_all(&hex_literal::hex!("363d3d373d3d3d363d73aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa5af43d82803e903d91602b57fd5bf3"), &config);
+    assert_eq!(opinions.len(), 2);
+    assert!(consensus(&opinions).unwrap().findings.is_empty());
+    assert!(detect_all(&[0x00], &config).is_empty());
+}
+
+/// Claims the clones of `0xaa..aa` are routers to `0xcc..cc`.
+#[derive(Debug)]
+struct ClaimClones;
+
+impl DetectionStrategy for ClaimClones {
+    fn name(&self) -> &'static str {
+        "claim_clones"
+    }
+
+    fn detect(&self, code: &[u8], _config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+        if !code.windows(20).any(|window| window == [0xaa; 20]) {
+            return None;
+        }
+        Some(ProxyDetectionResult::new(ProxyType::GeneratedRouter, ProxyDispatch::Static(Address::repeat_byte(0xcc)), RuleId::GeneratedRouterPattern))
+    }
+}
+
+#[test]
+fn test_detection_strategies() {
+    init();
+    let clone = hex_literal::hex!("363d3d373d3d3d363d73aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa5af43d82803e903d91602b57fd5bf3");
+    let config = DetectorConfig { seed: Some(1), strategies: vec![Arc::new(ClaimClones)], ..Default::default() };
+    // The caller's strategy shadows the minimal proxy pattern, and falls through elsewhere
+    let result = detect_proxy(&clone, &config).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch), (ProxyType::GeneratedRouter, ProxyDispatch::Static(Address::repeat_byte(0xcc))));
+    assert_eq!(detect_proxy(EIP_1967_CODE, &config).unwrap().proxy_type, ProxyType::EIP_1967);
+    let names: Vec<_> = detect_all(&clone, &config).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["claim_clones", "minimal", "trace"]);
+
+    // Without tracing, only the patterns are left
+    let config = DetectorConfig { static_only: true, ..config };
+    assert_eq!(detect_proxy(EIP_1967_CODE, &config), None);
+    let names: Vec<_> = detect_all(&clone, &config).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["claim_clones", "minimal"]);
+    let config = DetectorConfig { strategies: Vec::new(), ..config };
+    assert_eq!(detect_proxy(&clone, &config).unwrap().proxy_type, ProxyType::EIP_1167);
+}
+
+/// Loops until it runs out of gas, delegating nothing: `jumpdest`, `jump(9)` past some padding
+const INFINITE_LOOP_CODE: &[u8] = &hex_literal::hex!("600060006000505050" "5b" "6009" "56");
+
+#[test]
+fn test_trace_budget() {
+    let code = Bytes::from_static(INFINITE_LOOP_CODE);
+    let config = TraceConfig { max_steps: Some(10_000), ..TraceConfig::new(TraceEnvironment::from_seed(1)) };
+    assert!(matches!(trace_dispatch(&code, Bytes::new(), &config), Err(TraceError::BudgetExceeded { steps: 10_000, pc: 9..=12 })));
+    let config = TraceConfig { timeout: Some(Duration::from_millis(10)), ..TraceConfig::new(TraceEnvironment::from_seed(1)) };
+    assert!(matches!(trace_dispatch(&code, Bytes::new(), &config), Err(TraceError::BudgetExceeded { .. })));
+
+    // Probes over budget observed nothing, the detection goes on
+    let config = DetectorConfig { max_steps: Some(10_000), seed: Some(1), ..Default::default() };
+    let start = Instant::now();
+    assert_eq!(detect_proxy(INFINITE_LOOP_CODE, &config), None);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(detect_proxy(EIP_1967_CODE, &config).unwrap().proxy_type, ProxyType::EIP_1967);
+}
+
+/// Copies the calldata, then delegatecalls the address in its first 20 bytes
+const OPEN_DELEGATECALL_CODE: &[u8] = &hex_literal::hex!(
+    // calldatacopy(0, 0, calldatasize)
+    "36" "6000" "6000" "37"
+    // delegatecall(gas, shr(96, mload(0)), 0, calldatasize, 0, 0), stop
+    "6000" "6000" "36" "6000" "6000" "51" "6060" "1c" "5a" "f4" "00"
+);
+
+#[test]
+fn test_delegatecall_origins() {
+    init();
+    let config = TraceConfig::new(TraceEnvironment::from_seed(1));
+    let target = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+
+    let calldata = Bytes::from([target.as_slice(), &[0x11; 8]].concat());
+    let open = trace_dispatch(&Bytes::from_static(OPEN_DELEGATECALL_CODE), calldata, &config).unwrap();
+    assert_eq!(open.delegatecall_from_calldata, vec![target]);
+    assert!(open.delegatecall_from_code.is_empty() && open.delegatecall_unknown.is_empty() && open.delegatecall_storage.is_empty());
+
+    let minimal = Bytes::from_static(&hex_literal::hex!("363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3"));
+    let forwarded = trace_dispatch(&minimal, Bytes::from_static(&hex_literal::hex!("aabbccdd")), &config).unwrap();
+    assert_eq!(forwarded.delegatecall_from_code, vec![target]);
+    assert!(forwarded.delegatecall_from_calldata.is_empty() && forwarded.delegatecall_unknown.is_empty());
+
+    // Loaded from a slot then passed through memory, still storage
+    let stored = trace_dispatch(&Bytes::from_static(HAND_DISPATCHER_CODE), Bytes::from_static(&hex_literal::hex!("aabbccdd")), &config).unwrap();
+    assert_eq!(stored.delegatecall_storage, vec![U256::from(7)]);
+    assert!(stored.delegatecall_addresses().is_empty());
+
+    // Returned by another call: staticcall(gas, 0xbe.., 0, 0, 0, 32), delegatecall(gas, mload(0), ...)
+    let returned = hex_literal::hex!("6020" "6000" "6000" "6000" "73bebebebebebebebebebebebebebebebebebebebe" "5a" "fa" "50" "6000" "6000" "6000" "6000" "6000" "51" "5a" "f4" "00");
+    let unknown = trace_dispatch(&Bytes::copy_from_slice(&returned), Bytes::new(), &config).unwrap();
+    assert_eq!(unknown.delegatecall_unknown.len(), 1);
+    assert!(unknown.delegatecall_from_code.is_empty() && unknown.delegatecall_from_calldata.is_empty());
+
+    let result = detect_proxy(OPEN_DELEGATECALL_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::OpenDelegateCall, ProxyDispatch::Unknown, RuleId::OpenDelegateCall));
+    // Pinned rulesets don't know the rule
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert_ne!(detect_proxy(OPEN_DELEGATECALL_CODE, &frozen).map(|result| result.rule), Some(RuleId::OpenDelegateCall));
+}
+
+/// Delegatecalls the address in `selectorToFacet[msg.sig]` at slot 0, with two selectors
+/// pushed where a diamond would have built-in functions
+const SELECTOR_MAPPING_DIAMOND_CODE: &[u8] = &hex_literal::hex!(
+    "63aabbccdd" "50" "6311223344" "50"
+    // mstore(0, shr(224, calldataload(0))), mstore(0x20, 0), sload(keccak256(0, 0x40))
+    "6000" "35" "60e0" "1c" "6000" "52" "6000" "6020" "52" "6040" "6000" "20" "54"
+    // delegatecall(gas, facet, 0, 0, 0, 0), stop
+    "6000" "6000" "6000" "6000" "84" "5a" "f4" "00"
+);
+
+#[test]
+fn test_facet_slots() {
+    init();
+    let mapping_slot = |selector: [u8; 4]| {
+        let mut key = [0u8; 64];
+        key[28..32].copy_from_slice(&selector);
+        U256::from_be_bytes(alloy_primitives::keccak256(key).0)
+    };
+    let result = detect_proxy(SELECTOR_MAPPING_DIAMOND_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.proxy_type, ProxyType::DiamondOther);
+    assert_eq!(result.facet_slots, vec![
+        (Selector::new(hex_literal::hex!("11223344")), mapping_slot(hex_literal::hex!("11223344"))),
+        (Selector::new(hex_literal::hex!("aabbccdd")), mapping_slot(hex_literal::hex!("aabbccdd"))),
+    ]);
+
+    // A facet set to zero in the real storage isn't called
+    let storage = HashMap::from([(mapping_slot(hex_literal::hex!("aabbccdd")), U256::ZERO)]);
+    let config = DetectorConfig { widened: Some(WidenedAnalysis { storage, ..Default::default() }), ..Default::default() };
+    let result = detect_proxy(SELECTOR_MAPPING_DIAMOND_CODE, &config).unwrap();
+    assert_eq!(result.facet_slots.iter().map(|(selector, _)| *selector).collect::<Vec<_>>(), vec![Selector::new(hex_literal::hex!("11223344"))]);
+
+    // Not probed for other proxies
+    assert!(detect_proxy(EIP_1967_CODE, &DetectorConfig::default()).unwrap().facet_slots.is_empty());
+}
+
+/// Delegatecalls the address in `keccak256("myproject.proxy.implementation") - 1`, hashed at
+/// runtime so the slot isn't in the code
+const NAMESPACED_SLOT_CODE: &[u8] = &hex_literal::hex!(
+    // mstore(0, "myproject.proxy.implementation"), sload(sub(keccak256(0, 30), 1))
+    "7f" "6d7970726f6a6563742e70726f78792e696d706c656d656e746174696f6e" "0000" "6000" "52" "601e" "6000" "20" "6001" "90" "03" "54"
+    // delegatecall(gas, implementation, 0, 0, 0, 0), stop
+    "6000" "6000" "6000" "6000" "84" "5a" "f4" "00"
+);
+
+#[test]
+fn test_namespaced_slots() {
+    // The EIPs' own strings give the canonical slots
+    let eip_1967 = NamespacedSlots::of("eip1967.proxy");
+    assert_eq!(eip_1967.implementation, U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")));
+    assert_eq!(eip_1967.admin, U256::from_be_bytes(hex_literal::hex!("b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103")));
+    assert_eq!(eip_1967.beacon, U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50")));
+    assert_eq!(namespaced_slot("eip1967.proxy.implementation"), eip_1967.implementation);
+
+    let custom = namespaced_slot("myproject.proxy.implementation");
+    let candidates = ["eip1967.proxy.implementation", "myproject.proxy.implementation"];
+    assert_eq!(match_namespace(&candidates, &custom), Some("myproject.proxy.implementation"));
+    assert_eq!(match_namespace(&candidates, &(custom + U256::from(1))), None);
+
+    let result = detect_proxy(NAMESPACED_SLOT_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch.clone(), result.slot_namespace), (ProxyType::EIP_1967_CUSTOM, ProxyDispatch::Storage(custom, None), None));
+    let config = DetectorConfig { namespaces: candidates.iter().map(|namespace| namespace.to_string()).collect(), ..Default::default() };
+    let named = detect_proxy(NAMESPACED_SLOT_CODE, &config).unwrap();
+    assert_eq!(named.slot_namespace.as_deref(), Some("myproject.proxy.implementation"));
+    // Well known slots aren't named
+    assert_eq!(detect_proxy(EIP_1967_CODE, &config).unwrap().slot_namespace, None);
+}
+
+#[test]
+fn test_registries() {
+    init();
+    let custom = namespaced_slot("myproject.proxy.implementation");
+    let result = detect_proxy(EIP_1967_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.slot_name.as_deref(), Some("eip1967.proxy.implementation"));
+    let result = detect_proxy(NAMESPACED_SLOT_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.slot_name), (ProxyType::EIP_1967_CUSTOM, None));
+
+    // A protocol's own slot, known as the type it's registered with
+    let slots = SlotRegistry::default().register_slot(custom, ProxyType::EIP_1967_ZOS, "myproject.proxy.implementation");
+    let config = DetectorConfig { slots: Some(slots), ..Default::default() };
+    assert!(is_likely_proxy(NAMESPACED_SLOT_CODE, &config));
+    let result = detect_proxy(NAMESPACED_SLOT_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_1967_ZOS, RuleId::KnownStorageSlot));
+    assert_eq!(result.slot_name.as_deref(), Some("myproject.proxy.implementation"));
+    // Without the built-in slots the EIP-1967 one is just a slot the code pushes, still named
+    let config = DetectorConfig { slots: Some(SlotRegistry::empty()), ..Default::default() };
+    let result = detect_proxy(EIP_1967_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.slot_name.as_deref()), (ProxyType::ImmutableSlotProxy, Some("eip1967.proxy.implementation")));
+
+    // Pinned rulesets keep their tables
+    let polygon = U256::from_be_bytes(keccak256("matic.network.proxy.implementation").0);
+    assert_eq!(SlotRegistry::default().name(&polygon), Some("matic.network.proxy.implementation"));
+    assert!(!SlotRegistry::builtin(&Ruleset::pinned(14).unwrap()).contains_key(&polygon));
+
+    // A registry function of its own, answered and classified like the known resolvers
+    let registry = Address::repeat_byte(0xbe);
+    let get_logic = Selector::new(hex_literal::hex!("0c0c0c0c"));
+    let selectors = SelectorRegistry::default().register_selector(0x0c0c0c0c, ProxyType::External);
+    let config = DetectorConfig { selectors: Some(selectors), ..Default::default() };
+    assert!(config.synthetic_return_selectors().contains(&get_logic));
+    let result = detect_proxy(REGISTRY_PROXY_CODE, &config).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::External, ProxyDispatch::External(registry, get_logic), RuleId::ExternalResolver));
+}
+
+/// `NAMESPACED_SLOT_CODE` loading `keccak256("myproject.proxy.implementation")` itself
+const HASHED_SLOT_CODE: &[u8] = &hex_literal::hex!(
+    // mstore(0, "myproject.proxy.implementation"), sload(keccak256(0, 30))
+    "7f" "6d7970726f6a6563742e70726f78792e696d706c656d656e746174696f6e" "0000" "6000" "52" "601e" "6000" "20" "54"
+    // delegatecall(gas, implementation, 0, 0, 0, 0), stop
+    "6000" "6000" "6000" "6000" "84" "5a" "f4" "00"
+);
+
+#[test]
+fn test_slot_preimage() {
+    let name = b"myproject.proxy.implementation";
+    let result = detect_proxy(NAMESPACED_SLOT_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.slot_preimage, Some(SlotPreimage { preimage: Bytes::from_static(name), minus_one: true }));
+    assert_eq!(result.slot_preimage.unwrap().name(), Some("myproject.proxy.implementation"));
+
+    let result = detect_proxy(HASHED_SLOT_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.dispatch, ProxyDispatch::Storage(U256::from_be_bytes(keccak256(name).0), None));
+    assert_eq!(result.slot_preimage, Some(SlotPreimage { preimage: Bytes::from_static(name), minus_one: false }));
+
+    // Slots pushed as constants have no preimage to find
+    assert_eq!(detect_proxy(EIP_1967_CODE, &DetectorConfig::default()).unwrap().slot_preimage, None);
+    assert_eq!(SlotPreimage { preimage: Bytes::from_static(&[0x12, 0x00]), minus_one: false }.name(), None);
+}
+
+/// Reverts calls paying nothing, forwards the others to the EIP-1967 implementation
+const PAYABLE_ONLY_PROXY_CODE: &[u8] = &hex_literal::hex!(
+    // if iszero(callvalue) { revert(0, 0) }
+    "34" "6008" "57" "6000" "80" "fd" "5b"
+    // calldatacopy(0, 0, calldatasize), delegatecall(gas, sload(implementation slot), 0, calldatasize, 0, 0),
+    // returndatacopy(0, 0, returndatasize), return(0, returndatasize)
+    "36" "6000" "6000" "37" "6000" "6000" "36" "6000"
+    "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc" "54" "5a" "f4"
+    "3d" "6000" "6000" "3e" "3d" "6000" "f3"
+);
+
+#[test]
+fn test_probe_retry() {
+    init();
+    let result = detect_proxy(PAYABLE_ONLY_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_1967, RuleId::KnownStorageSlot));
+
+    // Traced without value it doesn't forward, with it it does
+    let mut trace = TraceConfig::new(TraceEnvironment::from_seed(1));
+    let calldata = Bytes::from_static(&hex_literal::hex!("aabbccdd"));
+    let unpaid = trace_dispatch(&Bytes::from_static(PAYABLE_ONLY_PROXY_CODE), calldata.clone(), &trace).unwrap();
+    assert!(unpaid.reverted && unpaid.delegatecall_storage.is_empty());
+    trace.call_value = U256::from(1);
+    let paid = trace_dispatch(&Bytes::from_static(PAYABLE_ONLY_PROXY_CODE), calldata, &trace).unwrap();
+    assert!(!paid.reverted && paid.delegatecall_storage.len() == 1);
+
+    // Empty calldata alone pays nothing and reverts too, the value is what reaches it
+    let empty_only = DetectorConfig { retry: ProbeRetry { call_value: U256::ZERO, empty_calldata: true }, ..Default::default() };
+    assert!(detect_proxy(PAYABLE_ONLY_PROXY_CODE, &empty_only).is_none());
+    let value_only = DetectorConfig { retry: ProbeRetry { call_value: U256::from(1), empty_calldata: false }, ..Default::default() };
+    assert_eq!(detect_proxy(PAYABLE_ONLY_PROXY_CODE, &value_only).map(|result| result.proxy_type), Some(ProxyType::EIP_1967));
+
+    // Pinned rulesets don't retry
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert!(detect_proxy(PAYABLE_ONLY_PROXY_CODE, &frozen).is_none());
+}
+
+/// Forwards `transfer(address,uint256)` and `0x11223344` to the EIP-1967 implementation and
+/// reverts on any other selector
+const SELECTIVE_PROXY_CODE: &[u8] = &hex_literal::hex!(
+    "6000" "35" "60e0" "1c"
+    "80" "63a9059cbb" "14" "601e" "57"
+    "80" "6311223344" "14" "601e" "57"
+    "6000" "80" "fd"
+    // 0x1e: calldatacopy(0, 0, calldatasize), delegatecall(gas, sload(implementation slot), 0, calldatasize, 0, 0),
+    // returndatacopy(0, 0, returndatasize), return(0, returndatasize)
+    "5b" "36" "6000" "6000" "37" "6000" "6000" "36" "6000"
+    "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc" "54" "5a" "f4"
+    "3d" "6000" "6000" "3e" "3d" "6000" "f3"
+);
+
+#[derive(Debug)]
+struct FixedProbes(Vec<Bytes>);
+
+impl CalldataStrategy for FixedProbes {
+    fn probes(&self, _code: &Bytes) -> Vec<Bytes> {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn test_calldata_strategy() {
+    init();
+    // The common selectors reach it, the probes it reverts on aren't classified
+    let result = detect_proxy(SELECTIVE_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_1967, RuleId::KnownStorageSlot));
+    let pushed = DetectorConfig { calldata: Some(Arc::new(PushedSelectorProbes::default())), ..Default::default() };
+    assert_eq!(detect_proxy(SELECTIVE_PROXY_CODE, &pushed).map(|result| result.proxy_type), Some(ProxyType::EIP_1967));
+
+    let code = Bytes::from_static(SELECTIVE_PROXY_CODE);
+    let probes = PushedSelectorProbes::default().probes(&code);
+    assert_eq!(probes.len(), 5);
+    assert_eq!(&probes[3][..4], &hex_literal::hex!("a9059cbb"));
+    assert_eq!(&probes[4][..4], &hex_literal::hex!("11223344"));
+    assert_eq!(PushedSelectorProbes { max_selectors: 1 }.probes(&code).len(), 4);
+    assert_eq!(DefaultProbes.probes(&code).len(), 7);
+
+    // Pinned rulesets only try the quick probes, unless given others
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert!(detect_proxy(SELECTIVE_PROXY_CODE, &frozen).is_none());
+    let domain = Arc::new(FixedProbes(vec![Bytes::from_static(&hex_literal::hex!("11223344"))]));
+    let frozen_domain = DetectorConfig { calldata: Some(domain), ..frozen };
+    assert_eq!(detect_proxy(SELECTIVE_PROXY_CODE, &frozen_domain).map(|result| result.proxy_type), Some(ProxyType::EIP_1967));
+
+    let junk = Arc::new(FixedProbes(vec![Bytes::from_static(&hex_literal::hex!("aabbccdd"))]));
+    assert!(detect_proxy(SELECTIVE_PROXY_CODE, &DetectorConfig { calldata: Some(junk), ..Default::default() }).is_none());
+
+    // No probes, nothing traced to classify
+    let none = DetectorConfig { calldata: Some(Arc::new(FixedProbes(vec![]))), anti_evasion: true, ..Default::default() };
+    assert!(detect_proxy(SELECTIVE_PROXY_CODE, &none).is_none());
+    assert!(detect_proxy_outcome(SELECTIVE_PROXY_CODE, &none).into_proxy().is_none());
+}
+
+/// Initcode of an ERC-1967 proxy whose constructor sets the implementation to 0xbb
+const ACCOUNT_PROXY_INITCODE: &str = concat!(
+    // sstore(implementation slot, 0xbb), codecopy(0, 0x42, 0x3b), return(0, 0x3b)
+    "7300000000000000000000000000000000000000bb",
+    "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc", "55",
+    "603b", "80", "6042", "6000", "39", "6000", "f3",
+    // Runtime: calldatacopy, delegatecall(gas, sload(implementation slot), 0, calldatasize, 0, 0),
+    // returndatacopy(0, 0, returndatasize), return(0, returndatasize)
+    "36", "6000", "6000", "37", "6000", "6000", "36", "6000",
+    "7f360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc", "54", "5a", "f4",
+    "3d", "6000", "6000", "3e", "3d", "6000", "f3",
+);
+
+/// `createAccount(owner, salt)` the way SimpleAccountFactory does it: returns the account at the
+/// CREATE2 address if it has code, else creates it with [ACCOUNT_PROXY_INITCODE]. Hand assembled
+/// after its `createAccount` flow, not the deployed factory's bytecode
+const ACCOUNT_FACTORY_CODE: &str = concat!(
+    // codecopy(0, 0x53, 0x7d), mstore(0xc0, keccak256(0, 0x7d))
+    "607d", "610053", "6000", "39", "607d", "6000", "20", "60c0", "52",
+    // mstore(0xa0, salt), mstore(0x80, address()), mstore8(0x8b, 0xff), keccak256(0x8b, 0x55) & mask
+    "6024", "35", "60a0", "52", "30", "6080", "52", "60ff", "608b", "53", "6055", "608b", "20",
+    "73ffffffffffffffffffffffffffffffffffffffff", "16",
+    // if extcodesize(account) { jump(0x4a) }, pop, create2(0, 0, 0x7d, salt)
+    "80", "3b", "604a", "57", "50", "6024", "35", "607d", "6000", "6000", "f5",
+    // 0x4a: return the account
+    "5b", "6000", "52", "6020", "6000", "f3",
+);
+
+#[test]
+fn test_counterfactual_account() {
+    init();
+    let factory_code = Bytes::from(alloy_primitives::hex::decode([ACCOUNT_FACTORY_CODE, ACCOUNT_PROXY_INITCODE].concat()).unwrap());
+    let salt = B256::with_last_byte(7);
+    let owner = Address::repeat_byte(0x0a);
+    // createAccount(address,uint256)
+    let calldata = Bytes::from([&hex_literal::hex!("5fbfb9cf")[..], owner.into_word().as_slice(), salt.as_slice()].concat());
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+
+    let account = analyze_counterfactual(&calldata, Some(factory_code.clone()), &config).unwrap();
+    let initcode = alloy_primitives::hex::decode(ACCOUNT_PROXY_INITCODE).unwrap();
+    assert_eq!(account.runtime[..], initcode[0x42..]);
+    assert_eq!(account.detection.map(|result| result.proxy_type), Some(ProxyType::EIP_1967));
+    assert_eq!(account.implementation, Some(Address::with_last_byte(0xbb)));
+    let create2 = account.create2.unwrap();
+    assert_eq!((create2.salt, create2.init_code_hash), (salt, keccak256(&initcode)));
+    let factory = Address::repeat_byte(0xfa);
+    assert_eq!(create2.address(&factory), factory.create2(salt, keccak256(&initcode)));
+
+    assert_eq!(analyze_counterfactual(&calldata, None, &config), Err(CounterfactualError::MissingFactoryCode));
+    let reverting = Bytes::from_static(&hex_literal::hex!("60006000fd"));
+    assert!(matches!(analyze_counterfactual(&calldata, Some(reverting), &config), Err(CounterfactualError::Failed(_))));
+    let creating_nothing = Bytes::from_static(&hex_literal::hex!("00"));
+    assert_eq!(analyze_counterfactual(&calldata, Some(creating_nothing), &config), Err(CounterfactualError::NothingDeployed));
+}
+
+/// Routes every call through a STATICCALL to itself, which delegatecalls the address in slot 7
+const READ_ONLY_ROUTER_CODE: &[u8] = &hex_literal::hex!(
+    // if eq(caller, address) { jump(0x17) }
+    "33" "30" "14" "6017" "57"
+    // calldatacopy(0, 0, calldatasize), staticcall(gas, address, 0, calldatasize, 0, 0), stop
+    "36" "6000" "6000" "37" "6000" "6000" "36" "6000" "30" "5a" "fa" "00"
+    // 0x17: calldatacopy, delegatecall(gas, sload(7), 0, calldatasize, 0, 0), stop
+    "5b" "36" "6000" "6000" "37" "6000" "6000" "36" "6000" "6007" "54" "5a" "f4" "00"
+);
+
+#[test]
+fn test_read_only_router() {
+    init();
+    let result = detect_proxy(READ_ONLY_ROUTER_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::ReadOnlyRouter, ProxyDispatch::Storage(U256::from(7), None), RuleId::ReadOnlyRouter));
+
+    // Standard proxies delegate in the call's own context
+    let result = detect_proxy(EIP_1967_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.proxy_type, ProxyType::EIP_1967);
+    let run = trace_dispatch(&Bytes::from_static(HAND_DISPATCHER_CODE), Bytes::from_static(&hex_literal::hex!("aabbccdd")), &TraceConfig::new(TraceEnvironment::from_seed(1))).unwrap();
+    assert_eq!(run.static_delegation, vec![false]);
+    assert!(!run.only_static_delegation());
+
+    // Pinned rulesets stub the self call and never see the delegation
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert_ne!(detect_proxy(READ_ONLY_ROUTER_CODE, &frozen).map(|result| result.proxy_type), Some(ProxyType::ReadOnlyRouter));
+}
+
+/// STATICCALLs `getLogic()` on a hardcoded registry and delegatecalls the address it returns
+const REGISTRY_PROXY_CODE: &[u8] = &hex_literal::hex!(
+    // mstore(0, shl(224, 0x0c0c0c0c)), staticcall(gas, 0xbe.., 0, 4, 0, 32), pop, mload(0)
+    "630c0c0c0c" "60e0" "1b" "6000" "52"
+    "6020" "6000" "6004" "6000" "73bebebebebebebebebebebebebebebebebebebebe" "5a" "fa" "50" "6000" "51"
+    // calldatacopy, delegatecall(gas, returned, 0, calldatasize, 0, 0), stop
+    "36" "6000" "6000" "37" "6000" "6000" "36" "6000" "84" "5a" "f4" "00"
+);
+
+#[test]
+fn test_call_tree() {
+    init();
+    let registry = Address::repeat_byte(0xbe);
+    let get_logic = Selector::new(hex_literal::hex!("0c0c0c0c"));
+    let mut config = TraceConfig::new(TraceEnvironment::from_seed(1));
+    config.synthetic_returns = vec![get_logic];
+    let run = trace_dispatch(&Bytes::from_static(REGISTRY_PROXY_CODE), Bytes::from_static(&hex_literal::hex!("aabbccdd")), &config).unwrap();
+    assert_eq!(run.delegatecall_registry, vec![(registry, get_logic)]);
+    assert!(run.delegatecall_addresses().is_empty());
+    let kinds: Vec<(CallKind, usize, Option<usize>)> = run.calls.iter().map(|call| (call.kind, call.depth, call.parent)).collect();
+    assert_eq!(kinds, vec![(CallKind::Call, 0, None), (CallKind::StaticCall, 1, Some(0)), (CallKind::DelegateCall, 1, Some(0))]);
+    assert_eq!((run.calls[1].target, run.calls[1].selector), (registry, Some(get_logic)));
+    assert_eq!((run.calls[2].target, run.calls[2].target_from), (synthetic_return(&registry, &get_logic), Some(1)));
+
+    // Without a synthetic return the delegatecall goes to whatever memory held
+    config.synthetic_returns.clear();
+    let run = trace_dispatch(&Bytes::from_static(REGISTRY_PROXY_CODE), Bytes::from_static(&hex_literal::hex!("aabbccdd")), &config).unwrap();
+    assert!(run.delegatecall_registry.is_empty());
+    assert_eq!(run.calls[2].target_from, None);
+
+    let answered = DetectorConfig { synthetic_returns: Some(vec![get_logic]), ..Default::default() };
+    let result = detect_proxy(REGISTRY_PROXY_CODE, &answered).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::External, ProxyDispatch::External(registry, get_logic), RuleId::RegistryDelegateCall));
+    assert_ne!(detect_proxy(REGISTRY_PROXY_CODE, &DetectorConfig::default()).map(|result| result.rule), Some(RuleId::RegistryDelegateCall));
+
+    // A beacon in a slot that isn't well known: implementation() on sload(0x1234)
+    let custom_beacon = hex_literal::hex!(
+        "635c60da1b" "60e0" "1b" "6000" "52"
+        "6020" "6000" "6004" "6000" "611234" "54" "5a" "fa" "50" "6000" "51"
+        "36" "6000" "6000" "37" "6000" "6000" "36" "6000" "84" "5a" "f4" "00"
+    );
+    let result = detect_proxy(&custom_beacon, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::External, ProxyDispatch::Beacon(U256::from(0x1234)), RuleId::RegistryDelegateCall));
+
+    // Beacon proxies still resolve through their slot
+    let result = detect_proxy(BEACON_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!(result.proxy_type, ProxyType::EIP_1967_BEACON);
+}
+
+/// Runs a hardcoded library on the first argument only, the way MultiSend-like helpers hand a
+/// library part of their calldata
+const DELEGATING_HELPER_CODE: &[u8] = &hex_literal::hex!(
+    // calldatacopy(0, 4, 0x20), delegatecall(gas, 0xbe.., 0, 0x20, 0, 0)
+    "6020" "6004" "6000" "37"
+    "6000" "6000" "6020" "6000" "73bebebebebebebebebebebebebebebebebebebebe" "5a" "f4"
+    // returndatacopy(0, 0, returndatasize), return(0, returndatasize)
+    "3d" "6000" "6000" "3e" "3d" "6000" "f3"
+);
+
+#[test]
+fn test_forwarding() {
+    init();
+    let library = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    let result = detect_proxy(DELEGATING_HELPER_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::DelegatesButNotProxy, ProxyDispatch::Static(library), RuleId::StaticDelegateCall));
+    let config = TraceConfig::new(TraceEnvironment::from_seed(1));
+    let calldata = Bytes::from_static(&hex_literal::hex!("aabbccdd"));
+    let run = trace_dispatch(&Bytes::from_static(DELEGATING_HELPER_CODE), calldata.clone(), &config).unwrap();
+    assert!(!run.forwards_calldata());
+
+    // Proxies pass the whole calldata and return what they get back
+    let run = trace_dispatch(&Bytes::from_static(EIP_1967_CODE), calldata.clone(), &config).unwrap();
+    let delegatecall = run.calls.iter().find(|call| call.kind == CallKind::DelegateCall).unwrap();
+    assert!(delegatecall.is_forwarding);
+
+    // Stopping after the whole calldata was passed doesn't forward either
+    let stopping = hex_literal::hex!("36" "6000" "6000" "37" "6000" "6000" "36" "6000" "6007" "54" "5a" "f4" "00");
+    let run = trace_dispatch(&Bytes::copy_from_slice(&stopping), calldata, &config).unwrap();
+    assert_eq!((run.delegatecall_storage.len(), run.forwards_calldata()), (1, false));
+
+    // Pinned rulesets don't require it
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert_eq!(detect_proxy(DELEGATING_HELPER_CODE, &frozen).map(|result| result.proxy_type), Some(ProxyType::StaticAddress));
+}
+
+/// Copies the first two bytes of 0xbe..'s code and jumps there
+const CODE_POINTER_CODE: &[u8] = &hex_literal::hex!(
+    // extcodecopy(0xbe.., 0, 0, 2), jump(shr(240, mload(0)))
+    "6002" "6000" "6000" "73bebebebebebebebebebebebebebebebebebebebe" "3c"
+    "6000" "51" "60f0" "1c" "56"
+);
+
+#[test]
+fn test_code_pointer() {
+    init();
+    let data = Address::from(hex_literal::hex!("bebebebebebebebebebebebebebebebebebebebe"));
+    let result = detect_proxy(CODE_POINTER_CODE, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::CodePointer, ProxyDispatch::Static(data), RuleId::CodePointer));
+    let run = trace_dispatch(&Bytes::from_static(CODE_POINTER_CODE), Bytes::from_static(&hex_literal::hex!("aabbccdd")), &TraceConfig::new(TraceEnvironment::from_seed(1))).unwrap();
+    assert_eq!((run.code_reads, run.code_jump), (vec![data], true));
+
+    // The pointer loaded from slot 3
+    let stored = hex_literal::hex!("6002" "6000" "6000" "6003" "54" "3c" "6000" "51" "60f0" "1c" "56");
+    let result = detect_proxy(&stored, &DetectorConfig::default()).unwrap();
+    assert_eq!((result.proxy_type, result.dispatch), (ProxyType::CodePointer, ProxyDispatch::Storage(U256::from(3), None)));
+
+    // Reading code as data, like SSTORE2 does, leaves the contract in control:
+    // extcodecopy(0xbe.., 0, 0, 32), return(0, 32)
+    let reader = hex_literal::hex!("6020" "6000" "6000" "73bebebebebebebebebebebebebebebebebebebebe" "3c" "6020" "6000" "f3");
+    assert!(detect_proxy(&reader, &DetectorConfig::default()).is_none());
+
+    let frozen = DetectorConfig { ruleset: AnalysisProfile::builtin("v1-frozen").unwrap().config.ruleset, ..Default::default() };
+    assert!(detect_proxy(CODE_POINTER_CODE, &frozen).is_none());
 }
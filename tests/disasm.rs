@@ -0,0 +1,99 @@
+use alloy_primitives::U256;
+use evm_proxy_tools::disasm::{any_opcode, disassemble, find_first_push_matching, fold_constants, FOLD_WINDOW, scan, InstructionVisitor, Instruction, OpcodePresence, Push32Constants, Push4Constants};
+use evm_proxy_tools::utils::opcode_iter;
+use evm_proxy_tools::Selector;
+use revm::interpreter::opcode;
+
+mod common;
+
+use common::fixtures::*;
+
+/// Every fixture, then the contracts of the binary corpus.
+fn codes() -> Vec<Vec<u8>> {
+    let mut codes: Vec<Vec<u8>> = [
+        EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE, ERC20_BINARY_SEARCH_CODE, BLUEPRINT_1167_CODE,
+        BLUEPRINT_1167_DATA_CODE, GENERATED_ROUTER_CODE, GENERATED_ROUTER_LINEAR_CODE, VYPER_FORWARDER_V2_CODE,
+        VYPER_FORWARDER_V1_CODE, SAFE_PROXY_CODE, SOLADY_PUSH0_CLONE_CODE, BEACON_PROXY_CODE, FALLBACK_SLOT_PROXY_CODE,
+        TRANSPARENT_PROXY_CODE, METAMORPHIC_INIT_CODE, SOLADY_CWIA_CODE, BLUEPRINT_MALFORMED_CODE,
+    ].iter().map(|code| code.to_vec()).collect();
+    let corpus = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/corpus.bin")).unwrap();
+    let mut data = &corpus[..];
+    while let Some((len, rest)) = data.split_first_chunk::<4>() {
+        let (code, rest) = rest.split_at(u32::from_be_bytes(*len) as usize);
+        codes.push(code.to_vec());
+        data = rest;
+    }
+    codes
+}
+
+#[test]
+fn test_fused_scan_matches_separate_passes() {
+    for code in codes() {
+        let mut fused = (OpcodePresence::new(opcode::DELEGATECALL), Push32Constants::default(), Push4Constants::default());
+        scan(&code, &mut fused);
+        let (delegates, pushed32, pushed4) = fused;
+
+        assert_eq!(delegates.found, disassemble(&code).any(|ins| ins.opcode == opcode::DELEGATECALL));
+        let values: Vec<U256> = disassemble(&code).filter(|ins| ins.opcode == opcode::PUSH32).filter_map(|ins| ins.push_value()).collect();
+        assert_eq!(pushed32.values, values);
+        let selectors: Vec<Selector> = disassemble(&code).filter(|ins| ins.opcode == opcode::PUSH4).filter_map(|ins| Selector::from_slice(ins.operand)).collect();
+        assert_eq!(pushed4.selectors, selectors);
+    }
+}
+
+#[test]
+fn test_early_stop() {
+    /// Counts the instructions it sees, done after `limit`.
+    struct Count { seen: usize, limit: usize }
+
+    impl InstructionVisitor for Count {
+        fn visit(&mut self, _: &Instruction) {
+            self.seen += 1;
+        }
+
+        fn done(&self) -> bool {
+            self.seen >= self.limit
+        }
+    }
+
+    // PUSH1 0 PUSH1 0 DELEGATECALL STOP STOP
+    let code = [0x60, 0x00, 0x60, 0x00, 0xf4, 0x00, 0x00];
+    let mut visitors = (OpcodePresence::new(opcode::DELEGATECALL), Count { seen: 0, limit: 4 });
+    scan(&code, &mut visitors);
+    assert!(visitors.0.found);
+    assert_eq!(visitors.1.seen, 4);
+
+    // Done visitors are skipped while the others go on
+    let mut visitors = (Count { seen: 0, limit: 1 }, Count { seen: 0, limit: usize::MAX });
+    scan(&code, &mut visitors);
+    assert_eq!((visitors.0.seen, visitors.1.seen), (1, 5));
+
+    // PUSH immediates aren't opcodes
+    assert!(!any_opcode(&[0x60, 0xf4], &[opcode::DELEGATECALL]));
+    assert!(any_opcode(&code, &[opcode::CALL, opcode::DELEGATECALL]));
+    assert_eq!(opcode_iter(&code).collect::<Vec<_>>(), vec![(0, opcode::PUSH1), (2, opcode::PUSH1), (4, opcode::DELEGATECALL), (5, opcode::STOP), (6, opcode::STOP)]);
+    assert_eq!(opcode_iter(&[0x7f, 0xf4]).collect::<Vec<_>>(), vec![(0, opcode::PUSH32)]);
+    let push = find_first_push_matching(&code, |ins| ins.offset > 0).unwrap();
+    assert_eq!(push.offset, 2);
+    assert!(find_first_push_matching(&code, |ins| ins.operand != [0]).is_none());
+}
+
+#[test]
+fn test_fold_constants() {
+    let values = |code: &[u8]| fold_constants(code).iter().map(|folded| folded.value).collect::<Vec<_>>();
+    // PUSH1 1 PUSH1 9 SUB: the top minus the next
+    assert_eq!(values(&[0x60, 0x01, 0x60, 0x09, 0x03]), vec![U256::from(8)]);
+    // PUSH1 2 PUSH1 1 SWAP1 SHL, DUP1 PUSH1 1 OR: computed values fold further
+    let folded = fold_constants(&[0x60, 0x02, 0x60, 0x01, 0x90, 0x1b, 0x80, 0x60, 0x01, 0x17]);
+    assert_eq!(folded.iter().map(|folded| folded.value).collect::<Vec<_>>(), vec![U256::from(4), U256::from(5)]);
+    assert_eq!(folded[1].pushes.iter().map(|push| push.offset).collect::<Vec<_>>(), vec![0, 2, 7]);
+
+    // Not across a JUMPDEST, nor through operands of other operations
+    assert!(values(&[0x60, 0x01, 0x5b, 0x60, 0x09, 0x03]).is_empty());
+    assert!(values(&[0x60, 0x01, 0x60, 0x09, 0x54, 0x01]).is_empty());
+    // Nor beyond the window
+    let mut code = vec![0x60, 0x01];
+    code.extend([0x60, 0x00, 0x50].repeat(FOLD_WINDOW / 2));
+    code.extend([0x60, 0x09, 0x01]);
+    assert!(values(&code).is_empty());
+}
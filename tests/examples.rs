@@ -0,0 +1,74 @@
+//! Runs the logic of the examples so they keep working, not just compiling.
+
+// Every example includes the support module on its own
+#![allow(clippy::duplicate_mod)]
+
+use alloy_primitives::Address;
+use ethers_contract::abigen;
+use ethers_core::abi::AbiDecode;
+use evm_proxy_tools::{ProxyDispatch, ProxyImplementation, ProxyType, RuleId, Selector};
+
+#[path = "../examples/detect_offline.rs"]
+mod detect_offline;
+
+#[path = "../examples/resolve_with_mock.rs"]
+mod resolve_with_mock;
+
+#[path = "../examples/custom_strategy.rs"]
+mod custom_strategy;
+
+abigen!(IDiamondLoupe, r"[
+    function facetFunctionSelectors(address facet) external view returns (bytes4[])
+]");
+
+#[test]
+fn test_detect_offline() {
+    let summary = detect_offline::run();
+    assert_eq!(summary.results.len(), 10);
+    assert_eq!((summary.matched_statically, summary.traced, summary.not_proxies), (6, 3, 1));
+    assert_eq!(summary.by_type.get("SoladyClone"), Some(&2));
+    let (_, erc20) = summary.results.iter().find(|(name, _)| name.starts_with("ERC-20")).unwrap();
+    assert!(erc20.is_none());
+}
+
+#[tokio::test]
+async fn test_resolve_with_mock() {
+    let resolved = resolve_with_mock::run().await.unwrap();
+    assert_eq!((resolved[0].address, resolved[0].proxy_type), (resolve_with_mock::PROXY, ProxyType::EIP_1967));
+    assert_eq!(resolved[0].implementation, ProxyImplementation::Single(resolve_with_mock::IMPLEMENTATION));
+    let ProxyImplementation::Facets(facets) = &resolved[1].implementation else { panic!("diamond should resolve to facets") };
+    assert_eq!((resolved[1].address, resolved[1].proxy_type), (resolve_with_mock::DIAMOND, ProxyType::EIP_2535));
+    assert_eq!(resolved[1].implementation.to_vec(), resolve_with_mock::FACETS.map(|(facet, _)| facet).to_vec());
+    for (facet, selector) in resolve_with_mock::FACETS {
+        assert_eq!(facets.get(&selector), Some(&facet));
+    }
+    // A loupe's bytes4, left aligned in its word, reads the same through the bindings as
+    // through Selector
+    let returndata = hex_literal::hex!(
+        "0000000000000000000000000000000000000000000000000000000000000020"
+        "0000000000000000000000000000000000000000000000000000000000000001"
+        "70a0823100000000000000000000000000000000000000000000000000000000"
+    );
+    let FacetFunctionSelectorsReturn(decoded) = FacetFunctionSelectorsReturn::decode(returndata).unwrap();
+    assert_eq!(Some(Selector::from(decoded[0])), Selector::from_slice(&returndata[64..68]));
+    assert_eq!(Selector::from(decoded[0]), resolve_with_mock::FACETS[0].1);
+
+    // Nothing deployed, nothing to detect
+    let chain = resolve_with_mock::chain();
+    assert!(resolve_with_mock::resolve(&chain, Address::ZERO).await.is_err());
+}
+
+#[test]
+fn test_custom_strategy() {
+    use custom_strategy::{Detection, Strategy, UNCHECKED_FORWARDER};
+
+    let results = custom_strategy::run();
+    let implementation = Address::repeat_byte(0xbe);
+    assert_eq!(results[0].1, Some(Detection::Custom { name: UNCHECKED_FORWARDER.name, implementation }));
+    let library = results.iter().filter(|(_, detection)| matches!(detection, Some(Detection::Library(_)))).count();
+    assert_eq!(library, 9);
+
+    // Without the custom pattern the library only sees a delegatecall to a fixed address
+    let Some(Detection::Library(result)) = Strategy::new(Vec::new()).detect(&custom_strategy::unchecked_forwarder_code()) else { panic!("should be a proxy") };
+    assert_eq!((result.proxy_type, result.dispatch, result.rule), (ProxyType::StaticAddress, ProxyDispatch::Static(implementation), RuleId::StaticDelegateCall));
+}
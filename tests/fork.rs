@@ -0,0 +1,119 @@
+mod common;
+
+use alloy_primitives::{Address, U256};
+use std::sync::{Arc, Mutex};
+
+use evm_proxy_tools::{detect_creation_code, detect_on_fork, detect_proxy, DetectionStrategy, DetectorConfig, Finding, ForkState, ProxyDetectionResult, ProxyDispatch, ProxyReadError, MAX_FORK_ROUNDS};
+use serde_json::{json, Value};
+
+use common::{block_param, rpc_error, FnRpc};
+
+const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+
+/// Storage and code responses recorded at the fixture's block, zero and no code elsewhere.
+fn recorded() -> impl Fn(&str, &Value) -> Result<Value, String> {
+    let fixture: Value = serde_json::from_str(include_str!("fixtures/fork.json")).unwrap();
+    move |method, params| {
+        let address = params[0].as_str().unwrap().to_lowercase();
+        match method {
+            "eth_getCode" => {
+                assert_eq!(block_param(&params[1]), fixture["block"].as_u64().unwrap());
+                Ok(fixture["code"].get(&address).cloned().unwrap_or(json!("0x")))
+            },
+            "eth_getStorageAt" => {
+                assert_eq!(block_param(&params[2]), fixture["block"].as_u64().unwrap());
+                let slot: U256 = params[1].as_str().unwrap().parse().unwrap();
+                let value = fixture["storage"][&address].get(format!("{:#x}", slot)).cloned();
+                Ok(value.unwrap_or(json!(format!("0x{}", "0".repeat(64)))))
+            },
+            _ => Err(format!("unexpected {}", method)),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_detect_on_fork() {
+    // The proxy keeps the slot of its implementation in slot 0, synthetic storage sends it to
+    // a slot derived from the synthetic value
+    let code = hex::decode("36600060003760006000366000600054545af400").unwrap();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let synthetic = detect_proxy(&code, &config).unwrap();
+    assert!(!matches!(synthetic.dispatch, ProxyDispatch::Storage(slot, _) if slot == U256::from(5)), "{:?}", synthetic.dispatch);
+
+    let (rpc, client) = FnRpc::provider(recorded());
+    let result = detect_on_fork(&rpc, PROXY, 19_000_000, &config).await.unwrap().unwrap();
+    assert!(matches!(result.dispatch, ProxyDispatch::Storage(slot, _) if slot == U256::from(5)), "{:?}", result.dispatch);
+    // The proxy's code, its two slots and the implementation's code, plus what the synthetic
+    // values answered before them led to: a slot and the code of two synthetic addresses
+    assert_eq!(client.calls(), 7);
+
+    // Failing reads fail the detection, with the node's response
+    let (rpc, _) = FnRpc::provider(|_: &str, _: &Value| Err(rpc_error(-32000, "missing trie node", None)));
+    let Err(ProxyReadError::Rpc(error)) = detect_on_fork(&rpc, PROXY, 19_000_000, &config).await else { panic!("should fail") };
+    assert_eq!(error.response.unwrap().message, "missing trie node");
+}
+
+#[tokio::test]
+async fn test_fork_rounds_exhausted() {
+    // Loads its implementation through a chain of sloads longer than the rounds, slot n
+    // holding n + 1: each round fetches one more link
+    let code = hex::decode(format!("366000600037600060003660006000{}5af400", "54".repeat(MAX_FORK_ROUNDS + 2))).unwrap();
+    let slots = Arc::new(Mutex::new(Vec::new()));
+    let seen = slots.clone();
+    let (rpc, _) = FnRpc::provider(move |method: &str, params: &Value| match method {
+        "eth_getCode" if params[0] == json!(format!("{:#x}", PROXY)) => Ok(json!(format!("0x{}", hex::encode(&code)))),
+        "eth_getCode" => Ok(json!("0x")),
+        "eth_getStorageAt" => {
+            let slot: U256 = params[1].as_str().unwrap().parse().unwrap();
+            seen.lock().unwrap().push(slot);
+            Ok(json!(format!("{:#066x}", slot + U256::from(1))))
+        },
+        _ => Err(format!("unexpected {}", method)),
+    });
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let result = detect_on_fork(&rpc, PROXY, 19_000_000, &config).await.unwrap().unwrap();
+    assert!(matches!(result.findings[..], [Finding::IncompleteForkState { unfetched }] if unfetched > 0), "{:?}", result.findings);
+    // What the last round read isn't fetched, no round would use it
+    let slots = slots.lock().unwrap();
+    assert!(slots.contains(&U256::from(MAX_FORK_ROUNDS - 2)), "{:?}", slots);
+    assert!(!slots.contains(&U256::from(MAX_FORK_ROUNDS - 1)), "{:?}", slots);
+}
+
+/// Keeps every config it's handed, and with them the fork state.
+#[derive(Debug, Default)]
+struct KeepingStrategy(Mutex<Vec<DetectorConfig>>);
+
+impl DetectionStrategy for KeepingStrategy {
+    fn name(&self) -> &'static str {
+        "keeping"
+    }
+
+    fn detect(&self, _code: &[u8], config: &DetectorConfig) -> Option<ProxyDetectionResult> {
+        self.0.lock().unwrap().push(config.clone());
+        None
+    }
+}
+
+#[tokio::test]
+async fn test_fork_config_outlives_detection() {
+    let keeping = Arc::new(KeepingStrategy::default());
+    let config = DetectorConfig { seed: Some(1), strategies: vec![keeping.clone()], ..Default::default() };
+    let (rpc, _) = FnRpc::provider(recorded());
+    let result = detect_on_fork(&rpc, PROXY, 19_000_000, &config).await.unwrap().unwrap();
+    assert!(matches!(result.dispatch, ProxyDispatch::Storage(slot, _) if slot == U256::from(5)), "{:?}", result.dispatch);
+    assert!(keeping.0.lock().unwrap().iter().all(|config| config.fork.is_some()));
+}
+
+#[test]
+fn test_creation_code_on_fork() {
+    // Deploys an EIP-1167 clone of 0xbebe… only at block 17,000,000
+    let runtime = "363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3";
+    let initcode = hex::decode(format!("43630103664014600b57005b602d6018600039602d6000f3{}", runtime)).unwrap();
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    assert_eq!(detect_creation_code(&initcode, &config), None);
+
+    let fork = Arc::new(ForkState::new(PROXY, 17_000_000));
+    let config = DetectorConfig { fork: Some(fork), ..config };
+    let result = detect_creation_code(&initcode, &config).unwrap();
+    assert_eq!(result.dispatch, ProxyDispatch::Static(Address::repeat_byte(0xbe)));
+}
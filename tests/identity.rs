@@ -0,0 +1,138 @@
+use alloy_primitives::{Address, Bytes, B256, U256};
+use evm_proxy_tools::{detect_proxy, merge_detections, ruleset_fingerprint, DetectionSet, DetectorConfig, Finding, ProxyDetectionResult, ProxyDispatch, ProxyType, RuleId, RULESET_VERSION};
+
+mod common;
+
+use common::fixtures::*;
+
+fn config() -> DetectorConfig {
+    DetectorConfig { seed: Some(1), ..Default::default() }
+}
+
+#[test]
+fn test_detection_id_snapshot() {
+    // These ids are persisted by scan pipelines: if they change, RULESET_VERSION must change
+    assert_eq!(RULESET_VERSION, 17);
+    let ids: Vec<(&str, String)> = corpus().into_iter()
+        .map(|(name, code)| (name, detect_proxy(code, &config()).unwrap().detection_id(code).to_string()))
+        .collect();
+    let expected: Vec<(&str, String)> = SNAPSHOT.iter().map(|(name, id)| (*name, id.to_string())).collect();
+    assert_eq!(ids, expected);
+}
+
+const SNAPSHOT: &[(&str, &str)] = &[
+    ("EIP_1967_CODE", "0xf8bacd67fcd35c6302589e61da94ced327590037f908f4493841c14ee6240527"),
+    ("EIP_897_CODE", "0x67b7366217b1a3dc21325e0e4bcfd01981f451c9aeeab197f13346d233e69f0e"),
+    ("DIAMOND_STANDARD_CODE", "0x9baaad34094ac02f70ddda3bd3d661153e90995d40a175035e8e9463f043a9aa"),
+    ("BLUEPRINT_1167_CODE", "0xeb937f937c7fb26d007f0171f187f2c17f2cee2857912c26f9f09ff2334ad267"),
+    ("BLUEPRINT_1167_DATA_CODE", "0xa1f36ee6d3a298b641f9fdcf0ecf808449fee45920b0bee34f49ca5c1eae46c1"),
+    ("GENERATED_ROUTER_CODE", "0xef9a48840e2a67bbddbac60b01dca1d5b56afef0345ed4df7d6f33a6a778202d"),
+    ("GENERATED_ROUTER_LINEAR_CODE", "0x0e9562056e4d93ad12931b955685a6c7d99b09992b1418f37b0d1ce58239e3f7"),
+    ("VYPER_FORWARDER_V2_CODE", "0x6e54f6e09e01c35482b739088b578ba0998340dc9d5048ef80ef644257227b02"),
+    ("VYPER_FORWARDER_V1_CODE", "0x3560f8af4c8ca2f15b57608bf23bb8f1aaecf36e7fa3b4d67efdedeccd9081f3"),
+    ("SAFE_PROXY_CODE", "0x0b36c344c8befebb20e2b31c8d74ac8f353cd17d3c1a37e0c570ab92f7ec8dd8"),
+    ("SOLADY_PUSH0_CLONE_CODE", "0x055bc88cb1bdddfcfdff37a05f3b75bc451d3e5dcd46b8cefa6d7067a76b8907"),
+    ("SOLADY_CWIA_CODE", "0xeb88ec500fec03e6e02eed032a79dbdf782a906876217215f6ca9059cc98bf37"),
+    ("BEACON_PROXY_CODE", "0x879d7b994a12d9ef0b2db81a2e7dd8a35baac21a9d8bfd94f5e721c379a8f767"),
+    ("FALLBACK_SLOT_PROXY_CODE", "0x9bf6dd9a333a21c475eaf306c3d002337c6b6429d0edae289d46654c5d8a06ff"),
+    ("TRANSPARENT_PROXY_CODE", "0xd949c805026ffe83fd426cc3cd5615f35df91af77562ba1cd65b53b64ba82293"),
+    ("UNITROLLER_CODE", "0x870c6bccf981bf29e593d4b548e6776e1349c1459d6c9d370397c678b8dd7f32"),
+];
+
+#[test]
+fn test_canonical_bytes() {
+    let code = hex_literal::hex!("6001");
+    let result = ProxyDetectionResult::new(ProxyType::EIP_1167, ProxyDispatch::Static(Address::repeat_byte(0xbe)), RuleId::Eip1167Pattern);
+    let mut expected = b"evm-proxy-tools/detection-id".to_vec();
+    expected.extend_from_slice(&RULESET_VERSION.to_be_bytes());
+    expected.extend_from_slice(alloy_primitives::keccak256(code).as_slice());
+    expected.extend_from_slice(b"\x16EIP-1167 Minimal Proxy");
+    expected.push(0x03);
+    expected.extend_from_slice(&[0xbe; 20]);
+    assert_eq!(result.canonical_bytes(&code), expected);
+    assert_eq!(result.detection_id(&code), alloy_primitives::keccak256(&expected));
+
+    // Integer widths of the variable length dispatches
+    let result = ProxyDetectionResult::new(ProxyType::SoladyClone, ProxyDispatch::StaticWithArgs(Address::ZERO, Bytes::from_static(&[0xaa, 0xbb])), RuleId::SoladyClonePattern);
+    assert!(result.canonical_bytes(&code).ends_with(&hex_literal::hex!("08" "0000000000000000000000000000000000000000" "00000002" "aabb")));
+}
+
+#[test]
+fn test_detection_id_normalization() {
+    let result = detect_proxy(EIP_1967_CODE, &config()).unwrap();
+    let id = result.detection_id(EIP_1967_CODE);
+
+    // Metadata doesn't matter, neither does how the result was reached
+    let mut recompiled = EIP_1967_CODE.to_vec();
+    let hash_byte = recompiled.len() - 20;
+    recompiled[hash_byte] ^= 0xff;
+    assert_eq!(result.detection_id(&recompiled), id);
+    let mut annotated = result.clone();
+    annotated.findings.push(Finding::EvasiveBehavior);
+    annotated.provenance.clear();
+    annotated.rule = RuleId::CustomStorageSlot;
+    assert_eq!(annotated.detection_id(EIP_1967_CODE), id);
+
+    // Logic, type and dispatch do
+    assert_ne!(result.detection_id(EIP_897_CODE), id);
+    let mut retyped = result.clone();
+    retyped.proxy_type = ProxyType::EIP_1967_CUSTOM;
+    assert_ne!(retyped.detection_id(EIP_1967_CODE), id);
+    let mut redispatched = result.clone();
+    redispatched.dispatch = ProxyDispatch::Storage(U256::from(1), None);
+    assert_ne!(redispatched.detection_id(EIP_1967_CODE), id);
+
+    // Clones of one template hash the same code, their implementations tell them apart
+    let other_implementation = [&SOLADY_PUSH0_CLONE_CODE[..9], &[0xca; 20], &SOLADY_PUSH0_CLONE_CODE[29..]].concat();
+    let clone = detect_proxy(SOLADY_PUSH0_CLONE_CODE, &config()).unwrap();
+    let other = detect_proxy(&other_implementation, &config()).unwrap();
+    let code_hash = |result: &ProxyDetectionResult, code: &[u8]| result.canonical_bytes(code)[32..64].to_vec();
+    assert_eq!(code_hash(&clone, SOLADY_PUSH0_CLONE_CODE), code_hash(&other, &other_implementation));
+    assert_ne!(clone.detection_id(SOLADY_PUSH0_CLONE_CODE), other.detection_id(&other_implementation));
+
+    // Order of slots and selectors isn't significant
+    let slots = |slots: Vec<u64>| ProxyDetectionResult::new(ProxyType::Unknown, ProxyDispatch::MultipleStorage(slots.into_iter().map(U256::from).collect()), RuleId::DiamondOther);
+    assert_eq!(slots(vec![1, 2]).detection_id(&[]), slots(vec![2, 1]).detection_id(&[]));
+}
+
+#[test]
+fn test_merge() {
+    let code = EIP_1967_CODE;
+    let result = detect_proxy(code, &config()).unwrap();
+    let id = result.detection_id(code);
+    let mut heuristic = result.clone();
+    heuristic.rule = RuleId::CustomStorageSlot;
+
+    // Worker a only saw the heuristic result, b the precise one and another contract
+    let mut a = DetectionSet::new();
+    assert!(a.insert_detected(code, heuristic.clone()));
+    let mut b = DetectionSet::new();
+    b.insert_detected(code, result.clone());
+    let clone = detect_proxy(SOLADY_PUSH0_CLONE_CODE, &config()).unwrap();
+    b.insert_detected(SOLADY_PUSH0_CLONE_CODE, clone.clone());
+
+    let merged = merge_detections([a.clone(), b.clone()]);
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged.get(&id), Some(&result));
+    assert_eq!(merged.get(&clone.detection_id(SOLADY_PUSH0_CLONE_CODE)), Some(&clone));
+    // Merging is order independent and idempotent
+    assert_eq!(merge_detections([b.clone(), a.clone()]), merged);
+    assert_eq!(merge_detections([merged.clone(), a, b]), merged);
+
+    // A less confident result doesn't replace the kept one
+    let mut set = merged.clone();
+    assert!(!set.insert(id, heuristic));
+    // Equally confident ones keep the first
+    assert!(!set.insert(id, result.clone()));
+    // More information wins between results of the same rule
+    let mut flagged = result;
+    flagged.evasive = true;
+    assert!(set.insert(id, flagged.clone()));
+    assert_eq!(set.get(&id), Some(&flagged));
+    assert_eq!(set.iter().map(|(id, _)| *id).collect::<Vec<B256>>().len(), 2);
+}
+
+#[test]
+fn test_ruleset_fingerprint() {
+    assert_eq!(ruleset_fingerprint(), ruleset_fingerprint());
+}
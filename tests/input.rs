@@ -0,0 +1,105 @@
+use evm_proxy_tools::utils::{normalize_code_input, CodeInput, CodeInputError};
+
+mod common;
+
+use common::fixtures::{BLUEPRINT_1167_CODE, DIAMOND_STANDARD_CODE, EIP_1967_CODE, EIP_897_CODE};
+
+const MINIMAL_PROXY: &str = "363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3";
+
+fn runtime(input: &str) -> Vec<u8> {
+    match normalize_code_input(input) {
+        Ok(CodeInput::RuntimeCode(code)) => code.to_vec(),
+        other => panic!("expected runtime code, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_runtime_code() {
+    let expected = hex::decode(MINIMAL_PROXY).unwrap();
+    assert_eq!(runtime(MINIMAL_PROXY), expected);
+    assert_eq!(runtime(&format!("0x{}", MINIMAL_PROXY)), expected);
+    assert_eq!(runtime(&format!("0X{}", MINIMAL_PROXY.to_uppercase())), expected);
+    for code in [EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE, BLUEPRINT_1167_CODE] {
+        assert_eq!(runtime(&hex::encode(code)), code);
+    }
+}
+
+#[test]
+fn test_whitespace() {
+    let expected = hex::decode(MINIMAL_PROXY).unwrap();
+    // Wrapped by an explorer, indented, with a trailing newline
+    let wrapped = format!("  0x{}\n{}\r\n\t{}\n", &MINIMAL_PROXY[..30], &MINIMAL_PROXY[30..61], &MINIMAL_PROXY[61..]);
+    assert_eq!(runtime(&wrapped), expected);
+    // Spaces between bytes, as hexdumps have them
+    let spaced = MINIMAL_PROXY.as_bytes().chunks(2).map(|b| std::str::from_utf8(b).unwrap()).collect::<Vec<_>>().join(" ");
+    assert_eq!(runtime(&spaced), expected);
+}
+
+#[test]
+fn test_malformed_hex() {
+    assert_eq!(normalize_code_input(""), Err(CodeInputError::Empty));
+    assert_eq!(normalize_code_input(" 0x \n"), Err(CodeInputError::Empty));
+    assert_eq!(normalize_code_input("0x6080604"), Err(CodeInputError::OddLength { position: 8 }));
+    assert_eq!(normalize_code_input("60 80 60 4"), Err(CodeInputError::OddLength { position: 9 }));
+    assert_eq!(normalize_code_input("0x60806g"), Err(CodeInputError::InvalidCharacter { position: 7, character: 'g' }));
+    // Positions count characters, not bytes
+    assert_eq!(normalize_code_input("é 6080"), Err(CodeInputError::InvalidCharacter { position: 0, character: 'é' }));
+    assert_eq!(normalize_code_input("0x0x6080"), Err(CodeInputError::InvalidCharacter { position: 3, character: 'x' }));
+    let message = normalize_code_input("0x6080604").unwrap_err().to_string();
+    assert!(message.contains("position 8"), "{}", message);
+}
+
+#[test]
+fn test_creation_code() {
+    // EIP-1167 factory initcode
+    let clone_initcode = format!("3d602d80600a3d3981f3{}", MINIMAL_PROXY);
+    match normalize_code_input(&clone_initcode).unwrap() {
+        CodeInput::LikelyCreationCode { initcode, runtime } => {
+            assert_eq!(runtime, 10..55);
+            assert_eq!(hex::encode(&initcode[runtime]), MINIMAL_PROXY);
+        },
+        other => panic!("expected creation code, got {:?}", other),
+    }
+
+    // A solc constructor followed by its runtime and an ABI encoded constructor argument, as in
+    // a creation transaction
+    let constructor = "6080604052348015600f57600080fd5b50";
+    let copy = format!("61{:04x}80610{:03x}6000396000f3fe", EIP_1967_CODE.len(), constructor.len() / 2 + 14);
+    let payload = format!("0x{}{}{}{}", constructor, copy, hex::encode(EIP_1967_CODE), "000000000000000000000000bebebebebebebebebebebebebebebebebebebebe");
+    match normalize_code_input(&payload).unwrap() {
+        CodeInput::LikelyCreationCode { initcode, runtime } => {
+            assert_eq!(&initcode[runtime.clone()], EIP_1967_CODE);
+            assert_eq!(runtime.end + 32, initcode.len());
+        },
+        other => panic!("expected creation code, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_creation_code_overflowing_range() {
+    // CODECOPY of u64::MAX bytes from offset 2: the range wraps around rather than ending past
+    // the code
+    let input = format!("67ffffffffffffffff60026000396000f3{}", MINIMAL_PROXY);
+    assert!(!matches!(normalize_code_input(&input), Ok(CodeInput::LikelyCreationCode { .. })));
+}
+
+#[test]
+fn test_abi_encoded() {
+    // (address, uint256, bool) as returned by eth_call
+    let encoded = "000000000000000000000000bebebebebebebebebebebebebebebebebebebebe\
+                   0000000000000000000000000000000000000000000000000de0b6b3a7640000\
+                   0000000000000000000000000000000000000000000000000000000000000001";
+    assert_eq!(normalize_code_input(encoded), Err(CodeInputError::LooksAbiEncoded { words: 3, zero_words: 3 }));
+    // A single word is too little to tell
+    assert!(matches!(normalize_code_input(&encoded[..64]), Ok(CodeInput::Unknown(_)) | Ok(CodeInput::RuntimeCode(_))));
+    let message = normalize_code_input(encoded).unwrap_err().to_string();
+    assert!(message.contains("ABI encoded"), "{}", message);
+}
+
+#[test]
+fn test_unknown() {
+    // Starts with an undefined opcode
+    let input = normalize_code_input("0c0d0e").unwrap();
+    assert_eq!(input, CodeInput::Unknown(vec![0x0c, 0x0d, 0x0e].into()));
+    assert_eq!(input.bytes().len(), 3);
+}
@@ -0,0 +1,329 @@
+mod common;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use alloy_primitives::{Address, Bytes};
+use ethers_core::abi::{encode, Token};
+use evm_proxy_tools::{DetectorConfig, ErrorCategory, Finding, Inspector, InspectorError, ProxyDispatch, ProxyImplementation, ProxyReadError, ProxyType, ReportDetail, RpcErrorKind, Selector};
+use serde_json::{json, Value};
+
+use common::{block_param, calls_around_drop, rpc_error, FnRpc};
+use common::fixtures::{DIAMOND_STANDARD_CODE, EIP_1967_CODE, TRANSPARENT_PROXY_CODE};
+
+const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+const IMPLEMENTATION: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000bb"));
+const FACET: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+
+const EIP_1967_SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+
+/// An EIP-1167 clone of `IMPLEMENTATION`.
+const CLONE_CODE: &[u8] = &hex_literal::hex!("363d3d373d3d3d363d7300000000000000000000000000000000000000bb5af43d82803e903d91602b57fd5bf3");
+
+fn word(address: &Address) -> String {
+    format!("0x{:0>64}", hex::encode(address))
+}
+
+/// A node on chain 1 with `PROXY` deployed with `code`, `IMPLEMENTATION` and `FACET` deployed
+/// with some code, `storage` at `PROXY` and `calls` answering `eth_call` by calldata prefix.
+fn chain(code: &'static [u8], storage: Vec<(&'static str, String)>, calls: Vec<(&'static str, String)>) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    let storage: HashMap<&str, String> = storage.into_iter().collect();
+    move |method, params| match method {
+        "eth_chainId" => Ok(json!("0x1")),
+        "eth_getCode" => {
+            let address: Address = params[0].as_str().unwrap().parse().unwrap();
+            Ok(match address {
+                PROXY => json!(Bytes::from_static(code)),
+                IMPLEMENTATION | FACET => json!("0x6001"),
+                _ => json!("0x"),
+            })
+        },
+        "eth_getStorageAt" => Ok(json!(storage.get(params[1].as_str().unwrap()).cloned().unwrap_or(word(&Address::ZERO)))),
+        "eth_call" => {
+            let data = params[0]["data"].as_str().or(params[0]["input"].as_str()).unwrap();
+            calls.iter().find(|(prefix, _)| data.starts_with(prefix))
+                .map(|(_, answer)| json!(answer))
+                .ok_or_else(|| rpc_error(3, "execution reverted", Some(json!("0x"))))
+        },
+        _ => panic!("unexpected {}", method),
+    }
+}
+
+async fn inspector(handler: impl Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static) -> Inspector<ethers_providers::Provider<FnRpc>> {
+    let (rpc, _) = FnRpc::provider(handler);
+    Inspector::from_provider(rpc, DetectorConfig::default()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_analyze_eip_1967() {
+    let inspector = inspector(chain(EIP_1967_CODE, vec![(EIP_1967_SLOT, word(&IMPLEMENTATION))], vec![])).await;
+    assert_eq!(inspector.chain_id(), 1);
+    let report = inspector.analyze(PROXY).await.unwrap();
+    assert_eq!((report.address, report.chain_id, report.code_size), (PROXY, 1, EIP_1967_CODE.len()));
+    let detection = report.detection.as_ref().unwrap();
+    assert_eq!(detection.proxy_type, ProxyType::EIP_1967);
+    assert_eq!(report.implementation.as_ref().unwrap(), &ProxyImplementation::Single(IMPLEMENTATION));
+    assert!(report.findings().is_empty());
+    assert_eq!(report.trust_set.parties(), vec![IMPLEMENTATION]);
+}
+
+#[tokio::test]
+async fn test_analyze_self_report_mismatch() {
+    // implementation() disagrees with the slot, the report carries the finding
+    let calls = vec![("0x5c60da1b", word(&FACET))];
+    let inspector = inspector(chain(EIP_1967_CODE, vec![(EIP_1967_SLOT, word(&IMPLEMENTATION))], calls)).await;
+    let report = inspector.analyze(PROXY).await.unwrap();
+    assert_eq!(report.findings(), &[Finding::SelfReportMismatch { slot_value: IMPLEMENTATION, getter_value: FACET }]);
+}
+
+#[tokio::test]
+async fn test_analyze_recent_implementation() {
+    // The proxy was deployed at block 100 and its implementation at 150, the head is 1000
+    let latest = chain(EIP_1967_CODE, vec![(EIP_1967_SLOT, word(&IMPLEMENTATION))], vec![]);
+    let handler = move |method: &str, params: &Value| match method {
+        "eth_blockNumber" => Ok(json!("0x3e8")),
+        "eth_getCode" if block_param(&params[1]) != u64::MAX => {
+            let address: Address = params[0].as_str().unwrap().parse().unwrap();
+            let deployed = if address == PROXY { 100 } else { 150 };
+            Ok(if block_param(&params[1]) >= deployed { json!("0x6001") } else { json!("0x") })
+        },
+        _ => latest(method, params),
+    };
+    let (rpc, _) = FnRpc::provider(handler);
+    let inspector = Inspector::from_provider(rpc, DetectorConfig::default()).await.unwrap();
+    assert!(inspector.analyze(PROXY).await.unwrap().findings().is_empty());
+
+    let inspector = inspector.freshness_window(100);
+    let report = inspector.analyze(PROXY).await.unwrap();
+    assert_eq!(report.findings(), &[Finding::RecentImplementation { proxy_deploy_block: 100, impl_deploy_block: 150 }]);
+    assert_eq!(report.summary().findings, vec!["RecentImplementation { proxy_deploy_block: 100, impl_deploy_block: 150 }"]);
+    // An info finding, left out below the full detail
+    assert!(inspector.detail(ReportDetail::Standard).analyze(PROXY).await.unwrap().findings().is_empty());
+}
+
+#[tokio::test]
+async fn test_analyze_clone() {
+    let inspector = inspector(chain(CLONE_CODE, vec![], vec![])).await;
+    let report = inspector.analyze(PROXY).await.unwrap();
+    let detection = report.detection.as_ref().unwrap();
+    assert_eq!((detection.proxy_type, &detection.dispatch), (ProxyType::EIP_1167, &ProxyDispatch::Static(IMPLEMENTATION)));
+    assert_eq!(report.implementation.as_ref().unwrap(), &ProxyImplementation::Single(IMPLEMENTATION));
+    assert_eq!(report.admin, None);
+}
+
+#[tokio::test]
+async fn test_analyze_diamond() {
+    let selector = Selector::new([0x12, 0x34, 0x56, 0x78]);
+    let facets = encode(&[Token::Array(vec![Token::Tuple(vec![
+        Token::Address(FACET.0 .0.into()),
+        Token::Array(vec![Token::FixedBytes(selector.as_bytes().to_vec())]),
+    ])])]);
+    let inspector = inspector(chain(DIAMOND_STANDARD_CODE, vec![], vec![("0x7a0ed627", format!("0x{}", hex::encode(facets)))])).await;
+    let report = inspector.analyze(PROXY).await.unwrap();
+    assert_eq!(report.detection.as_ref().unwrap().dispatch, ProxyDispatch::Facet_EIP_2535);
+    assert_eq!(report.implementation.as_ref().unwrap(), &ProxyImplementation::Facets(HashMap::from([(selector, FACET)])));
+}
+
+#[tokio::test]
+async fn test_analyze_at_block() {
+    // Upgraded to FACET after block 16, every read has to be at 16
+    let blocks = Arc::new(Mutex::new(Vec::new()));
+    let log = blocks.clone();
+    let latest = chain(TRANSPARENT_PROXY_CODE, vec![(EIP_1967_SLOT, word(&FACET))], vec![]);
+    let at_16 = chain(TRANSPARENT_PROXY_CODE, vec![(EIP_1967_SLOT, word(&IMPLEMENTATION))], vec![("0x5c60da1b", word(&IMPLEMENTATION))]);
+    let inspector = inspector(move |method, params| {
+        let block = match method {
+            "eth_getStorageAt" => &params[2],
+            "eth_getCode" | "eth_call" => &params[1],
+            _ => return latest(method, params),
+        };
+        log.lock().unwrap().push(block.clone());
+        if block == "0x10" { at_16(method, params) } else { latest(method, params) }
+    }).await;
+
+    let report = inspector.analyze_at(PROXY, Some(16u64.into())).await.unwrap();
+    assert_eq!(report.block, Some(16u64.into()));
+    assert_eq!(report.implementation.as_ref().unwrap(), &ProxyImplementation::Single(IMPLEMENTATION));
+    assert!(report.findings().is_empty());
+    let blocks = std::mem::take(&mut *blocks.lock().unwrap());
+    assert!(blocks.len() > 2 && blocks.iter().all(|block| block == "0x10"), "{:?}", blocks);
+
+    let report = inspector.analyze(PROXY).await.unwrap();
+    assert_eq!((report.block, report.implementation.unwrap()), (None, ProxyImplementation::Single(FACET)));
+    assert!(matches!(inspector.analyze_at(Address::repeat_byte(0xdd), Some(16u64.into())).await, Err(InspectorError::Read(ProxyReadError::NoCode(_)))));
+}
+
+#[tokio::test]
+async fn test_analyze_many() {
+    let inspector = inspector(chain(CLONE_CODE, vec![], vec![])).await;
+    let reports = inspector.analyze_many(&[PROXY, IMPLEMENTATION, Address::ZERO]).await;
+    assert!(reports[0].as_ref().unwrap().is_proxy());
+    // Code that isn't a proxy is reported as such, no code at all fails
+    let not_proxy = reports[1].as_ref().unwrap();
+    assert!(!not_proxy.is_proxy());
+    assert!(matches!(not_proxy.implementation, Err(ProxyReadError::UnknownProxy)));
+    assert!(matches!(reports[2], Err(InspectorError::Read(ProxyReadError::NoCode(Address::ZERO)))));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_dropped_batch_stops() {
+    // The analyses in flight stop at the request they were waiting on
+    let (rpc, client) = FnRpc::suspending_provider(chain(CLONE_CODE, vec![], vec![]));
+    let inspector = Inspector::from_provider(rpc, DetectorConfig::default()).await.unwrap();
+    let chain_id = client.calls();
+    let (before, after) = calls_around_drop(inspector.analyze_many(&[PROXY, IMPLEMENTATION, FACET]), &client).await;
+    assert!(before > chain_id);
+    assert_eq!(after, before);
+}
+
+/// A node on which each address in `clones` is an EIP-1167 clone of the one it maps to, other
+/// addresses having some code.
+fn clones(clones: Vec<(Address, Address)>) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    let clones: HashMap<Address, Address> = clones.into_iter().collect();
+    move |method, params| match method {
+        "eth_chainId" => Ok(json!("0x1")),
+        "eth_getCode" => {
+            let address: Address = params[0].as_str().unwrap().parse().unwrap();
+            Ok(match clones.get(&address) {
+                Some(implementation) => json!(format!("0x363d3d373d3d3d363d73{}5af43d82803e903d91602b57fd5bf3", hex::encode(implementation))),
+                None => json!("0x6001"),
+            })
+        },
+        "eth_getStorageAt" => Ok(json!(word(&Address::ZERO))),
+        _ => Err(rpc_error(3, "execution reverted", Some(json!("0x")))),
+    }
+}
+
+#[tokio::test]
+async fn test_follow_chain() {
+    let linear = inspector(clones(vec![(PROXY, IMPLEMENTATION), (IMPLEMENTATION, FACET)])).await;
+    let chain = linear.follow_at(PROXY, None, 8).await.unwrap();
+    assert_eq!(chain.iter().map(|report| report.address).collect::<Vec<_>>(), vec![PROXY, IMPLEMENTATION]);
+    assert_eq!(chain[1].implementation.as_ref().unwrap(), &ProxyImplementation::Single(FACET));
+    assert!(matches!(linear.follow_at(PROXY, None, 1).await, Err(InspectorError::ChainTooDeep(1))));
+    assert!(matches!(linear.follow_at(PROXY, None, 0).await, Err(InspectorError::ChainTooDeep(0))));
+    // A proxy whose implementation isn't a proxy is a chain of one
+    assert_eq!(linear.follow_at(IMPLEMENTATION, None, 1).await.unwrap().len(), 1);
+
+    let cyclic = inspector(clones(vec![(PROXY, IMPLEMENTATION), (IMPLEMENTATION, PROXY)])).await;
+    let error = cyclic.follow_at(PROXY, None, 8).await.unwrap_err();
+    assert!(matches!(&error, InspectorError::ChainCycle(cycle) if cycle == &[PROXY, IMPLEMENTATION, PROXY]));
+    assert_eq!(error.to_string(), format!("proxy chain cycle detected: {} -> {} -> {}", PROXY, IMPLEMENTATION, PROXY));
+}
+
+/// `handler` recording each request's method and first parameter into `log`.
+fn recording(handler: impl Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static, log: Arc<Mutex<Vec<String>>>) -> impl Fn(&str, &Value) -> Result<Value, String> + Send + Sync + 'static {
+    move |method, params| {
+        log.lock().unwrap().push(format!("{} {}", method, params[1]));
+        handler(method, params)
+    }
+}
+
+#[tokio::test]
+async fn test_report_detail() {
+    let mut requests = Vec::new();
+    let mut summaries = Vec::new();
+    for detail in [ReportDetail::Minimal, ReportDetail::Standard, ReportDetail::Full] {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let handler = recording(chain(TRANSPARENT_PROXY_CODE, vec![(EIP_1967_SLOT, word(&IMPLEMENTATION))], vec![("0x5c60da1b", word(&FACET))]), log.clone());
+        let report = inspector(handler).await.detail(detail).analyze(PROXY).await.unwrap();
+        assert_eq!(report.detail, detail);
+        let log = log.lock().unwrap().clone();
+        requests.push((log.iter().filter(|r| r.starts_with("eth_call")).count(), log.iter().filter(|r| r.starts_with("eth_getStorageAt")).count()));
+        summaries.push(serde_json::to_value(report.summary()).unwrap());
+    }
+    // Minimal doesn't check the self-report, only Full reads the admin
+    assert_eq!(requests[0].0, 0);
+    assert!(requests[1].0 > 0);
+    assert_eq!(requests[1].1, requests[0].1);
+    assert!(requests[2].1 > requests[1].1);
+
+    let implementation = json!(IMPLEMENTATION);
+    assert_eq!(summaries[0], json!({ "address": PROXY, "type": "EIP_1967_TRANSPARENT", "dispatch": "storage", "implementation": implementation }));
+    assert_eq!(summaries[1]["implementations"], json!([implementation]));
+    assert_eq!(summaries[1]["findings"].as_array().unwrap().len(), 1);
+    assert!(summaries[1].get("chain_id").is_none());
+    assert_eq!(summaries[2]["chain_id"], json!(1));
+    assert_eq!(summaries[2]["code_size"], json!(TRANSPARENT_PROXY_CODE.len()));
+    assert_eq!(summaries[2]["trust_set"]["entries"][0]["address"], implementation);
+}
+
+#[tokio::test]
+async fn test_admin_not_address() {
+    // The admin slot holds more than an address, the detection and resolution still stand
+    let admin_slot = "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+    let storage = vec![(EIP_1967_SLOT, word(&IMPLEMENTATION)), (admin_slot, format!("0x{}", "ff".repeat(32)))];
+    let report = inspector(chain(TRANSPARENT_PROXY_CODE, storage, vec![])).await.detail(ReportDetail::Full).analyze(PROXY).await.unwrap();
+    assert!(report.is_proxy());
+    assert_eq!(report.implementation.unwrap(), ProxyImplementation::Single(IMPLEMENTATION));
+    assert_eq!(report.admin, None);
+}
+
+#[tokio::test]
+async fn test_minimal_report_size() {
+    let facets = encode(&[Token::Array(vec![Token::Tuple(vec![
+        Token::Address(FACET.0 .0.into()),
+        Token::Array(vec![Token::FixedBytes(vec![0x12, 0x34, 0x56, 0x78])]),
+    ])])]);
+    let corpus: [(&'static [u8], _, _); 4] = [
+        (EIP_1967_CODE, vec![(EIP_1967_SLOT, word(&IMPLEMENTATION))], vec![]),
+        (CLONE_CODE, vec![], vec![]),
+        (DIAMOND_STANDARD_CODE, vec![], vec![("0x7a0ed627", format!("0x{}", hex::encode(facets)))]),
+        (&[0x60, 0x01], vec![], vec![]),
+    ];
+    for (code, storage, calls) in corpus {
+        let report = inspector(chain(code, storage, calls)).await.detail(ReportDetail::Minimal).analyze(PROXY).await.unwrap();
+        let json = serde_json::to_string(&report.summary()).unwrap();
+        assert!(json.len() < 200, "{}", json);
+    }
+}
+
+#[tokio::test]
+async fn test_builder_errors() {
+    assert!(matches!(Inspector::builder().build().await, Err(InspectorError::MissingUrl)));
+    let Err(error) = Inspector::builder().rpc_url("not a url").build().await else { panic!("should fail") };
+    assert!(matches!(error, InspectorError::InvalidUrl(_)));
+    assert_eq!((error.category(), error.is_retryable()), (ErrorCategory::User, false));
+    assert!(matches!(Inspector::builder().rpc_url("ftp://localhost:8545").build().await, Err(InspectorError::UnsupportedScheme(scheme)) if scheme == "ftp"));
+    // Nothing listens on the discard port, worth trying again
+    let Err(error) = Inspector::builder().rpc_url("ws://127.0.0.1:9").build().await else { panic!("should fail") };
+    assert!(matches!(error, InspectorError::Connect(_)) && error.source().is_some());
+    assert_eq!((error.category(), error.is_retryable()), (ErrorCategory::Rpc, true));
+    let Err(error) = Inspector::builder().rpc_url("http://127.0.0.1:9").build().await else { panic!("should fail") };
+    assert!(matches!(&error, InspectorError::ChainId(e) if e.kind == RpcErrorKind::Transport));
+    assert!(error.is_retryable());
+
+    // A node failing eth_chainId, its response kept as the source
+    let (rpc, _) = FnRpc::provider(|_, _| Err(rpc_error(-32601, "method not found", None)));
+    let Err(error) = Inspector::from_provider(rpc, DetectorConfig::default()).await else { panic!("should fail") };
+    assert!(matches!(&error, InspectorError::ChainId(e) if e.kind == RpcErrorKind::Rejected));
+    let source = error.source().unwrap().source().unwrap().to_string();
+    assert!(source.contains("method not found"), "{}", source);
+    assert_eq!((error.category(), error.is_retryable()), (ErrorCategory::Rpc, false));
+}
+
+
+#[tokio::test]
+async fn test_replay_transcript() {
+    // Each request is answered by an unused entry with the same method and params, so
+    // concurrent reads may arrive in any order
+    let fixture: Value = serde_json::from_str(include_str!("fixtures/inspector_transparent.json")).unwrap();
+    let entries = Arc::new(Mutex::new(fixture["requests"].as_array().unwrap().clone()));
+    let remaining = entries.clone();
+    let inspector = inspector(move |method, params| {
+        let mut entries = remaining.lock().unwrap();
+        let index = entries.iter().position(|entry| entry["method"] == method && &entry["params"] == params)
+            .unwrap_or_else(|| panic!("unrecorded {} {}", method, params));
+        let entry = entries.remove(index);
+        match entry.get("error") {
+            Some(error) => Err(error.to_string()),
+            None => Ok(entry["result"].clone()),
+        }
+    }).await;
+
+    let report = inspector.analyze(PROXY).await.unwrap();
+    assert_eq!(report.detection.as_ref().unwrap().proxy_type, ProxyType::EIP_1967_TRANSPARENT);
+    assert_eq!(report.implementation.as_ref().unwrap(), &ProxyImplementation::Single(IMPLEMENTATION));
+    assert!(entries.lock().unwrap().is_empty(), "{:?}", entries.lock().unwrap());
+}
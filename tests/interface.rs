@@ -0,0 +1,91 @@
+mod common;
+
+use common::fixtures::{EIP_1967_CODE, EIP_897_CODE, DIAMOND_STANDARD_CODE, ERC20_BINARY_SEARCH_CODE};
+use evm_proxy_tools::{recover_interface, InterfaceSketch, Selector};
+
+fn selectors(values: &[u32]) -> Vec<Selector> {
+    values.iter().copied().map(Selector::from).collect()
+}
+
+#[test]
+fn test_linear_dispatcher() {
+    // solc 0.7.5, upgradeToAndCall is the only payable function
+    assert_eq!(recover_interface(EIP_897_CODE), InterfaceSketch {
+        selectors: selectors(&[
+            0x3ad06d16, // upgradeTo(string,address)
+            0x54fd4d50, // version()
+            0x5c60da1b, // implementation()
+            0x6fde8202, // upgradeabilityOwner()
+            0xa9c45fcb, // upgradeToAndCall(string,address,bytes)
+            0xf1739cae, // transferProxyOwnership(address)
+        ]),
+        fallback: true,
+        receive: false,
+        payable_hints: selectors(&[0xa9c45fcb]),
+    });
+}
+
+#[test]
+fn test_via_ir_dispatcher() {
+    // solc 0.8.17 via-IR, the last selector is compared with SUB and the CALLVALUE guards
+    // sit behind a jump
+    assert_eq!(recover_interface(DIAMOND_STANDARD_CODE), InterfaceSketch {
+        selectors: selectors(&[
+            0x01ffc9a7, // supportsInterface(bytes4)
+            0x1f931c1c, // diamondCut((address,uint8,bytes4[])[],address,bytes)
+            0x2c408059, // getFallbackAddress()
+            0x52ef6b2c, // facetAddresses()
+            0x79ba5097, // acceptOwnership()
+            0x7a0ed627, // facets()
+            0x8ab5150a, // nomineeOwner()
+            0x8da5cb5b, // owner()
+            0x91423765, // setFallbackAddress(address)
+            0xadfca15e, // facetFunctionSelectors(address)
+            0xcdffacc6, // facetAddress(bytes4)
+            0xf2fde38b, // transferOwnership(address)
+        ]),
+        fallback: true,
+        receive: true,
+        payable_hints: vec![],
+    });
+}
+
+#[test]
+fn test_binary_search_dispatcher() {
+    let abi = [
+        0x06fdde03, // name()
+        0x095ea7b3, // approve(address,uint256)
+        0x18160ddd, // totalSupply()
+        0x23b872dd, // transferFrom(address,address,uint256)
+        0x313ce567, // decimals()
+        0x3644e515, // DOMAIN_SEPARATOR()
+        0x39509351, // increaseAllowance(address,uint256)
+        0x3f4ba83a, // unpause()
+        0x40c10f19, // mint(address,uint256)
+        0x42966c68, // burn(uint256)
+        0x5c975abb, // paused()
+        0x70a08231, // balanceOf(address)
+        0x715018a6, // renounceOwnership()
+        0x7ecebe00, // nonces(address)
+        0x8456cb59, // pause()
+        0x8da5cb5b, // owner()
+        0x95d89b41, // symbol()
+        0xa457c2d7, // decreaseAllowance(address,uint256)
+        0xa9059cbb, // transfer(address,uint256)
+        0xd505accf, // permit(address,address,uint256,uint256,uint8,bytes32,bytes32)
+        0xdd62ed3e, // allowance(address,address)
+        0xf2fde38b, // transferOwnership(address)
+    ];
+    let sketch = recover_interface(ERC20_BINARY_SEARCH_CODE);
+    assert_eq!(sketch.selectors, selectors(&abi));
+    assert_eq!(sketch.payable_hints, selectors(&[0x40c10f19]));
+    assert!(sketch.receive);
+    assert!(!sketch.fallback);
+}
+
+#[test]
+fn test_no_dispatcher() {
+    // OpenZeppelin proxy, only receive and fallback
+    assert_eq!(recover_interface(EIP_1967_CODE), InterfaceSketch { fallback: true, receive: true, ..Default::default() });
+    assert!(recover_interface(&[]).selectors.is_empty());
+}
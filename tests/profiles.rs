@@ -0,0 +1,91 @@
+use evm_proxy_tools::{detect_proxy, AnalysisProfile, ProfileError, ProxyType, RuleId, Ruleset, RULESET_VERSION};
+
+mod common;
+
+use common::fixtures::*;
+
+/// SOLADY_PUSH0_CLONE_CODE as the fixture first had it, with the EIP-7511 prologue.
+const LEGACY_SOLADY_PUSH0_CLONE_CODE: &[u8] = &hex_literal::hex!("365f5f375f5f365f73bebebebebebebebebebebebebebebebebebebebe5af43d5f5f3e6029573d5ffd5b3d5ff3");
+
+/// [corpus] with the Solady clone the vectors were recorded over: the EIP-7511 prologue that
+/// rulesets before 17 took for Solady's.
+fn recorded_corpus() -> Vec<(&'static str, &'static [u8])> {
+    corpus().into_iter()
+        .map(|(name, code)| if name == "SOLADY_PUSH0_CLONE_CODE" { (name, LEGACY_SOLADY_PUSH0_CLONE_CODE) } else { (name, code) })
+        .collect()
+}
+
+/// `(name, rule, detection id)` of the corpus under `profile`.
+fn vectors(profile: &AnalysisProfile) -> Vec<(&'static str, String, String)> {
+    recorded_corpus().into_iter().map(|(name, code)| {
+        let result = detect_proxy(code, &profile.config).unwrap();
+        (name, format!("{:?}", result.rule), profile.detection_id(&result, code).to_string())
+    }).collect()
+}
+
+#[test]
+fn test_v1_frozen_conformance() {
+    let profile = AnalysisProfile::builtin("v1-frozen").unwrap();
+    assert!(profile.frozen);
+    assert_eq!(profile.ruleset().version(), 3);
+    let expected: Vec<(&str, String, String)> = V1_FROZEN.iter().map(|(name, rule, id)| (*name, rule.to_string(), id.to_string())).collect();
+    assert_eq!(vectors(&profile), expected);
+}
+
+#[test]
+fn test_latest_moves_on() {
+    let frozen = AnalysisProfile::builtin("v1-frozen").unwrap();
+    let latest = AnalysisProfile::builtin("latest").unwrap();
+    assert_eq!(latest.ruleset(), Ruleset::LATEST);
+    assert_eq!(latest.ruleset().version(), RULESET_VERSION);
+
+    let result = detect_proxy(UNITROLLER_CODE, &latest.config).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::CompoundDelegator, RuleId::CompoundStorageSlot));
+    assert_eq!(latest.detection_id(&result, UNITROLLER_CODE), result.detection_id(UNITROLLER_CODE));
+    let result = detect_proxy(UNITROLLER_CODE, &frozen.config).unwrap();
+    assert_eq!((result.proxy_type, result.rule), (ProxyType::EIP_897, RuleId::LowStorageSlot));
+
+    // Rules a frozen ruleset doesn't have can't be enabled into it
+    let mut config = frozen.config.clone();
+    config.rules = config.rules.enable(RuleId::CompoundStorageSlot);
+    assert_eq!(detect_proxy(UNITROLLER_CODE, &config).unwrap().rule, RuleId::LowStorageSlot);
+
+    // Clones of a vanity address pushed with PUSH18 only match the pattern since ruleset 5
+    let vanity = hex_literal::hex!("363d3d373d3d3d363d71bebebebebebebebebebebebebebebebebebe5af43d82803e903d91602957fd5bf3");
+    assert_eq!(detect_proxy(&vanity, &latest.config).unwrap().rule, RuleId::Eip1167Pattern);
+    assert_eq!(detect_proxy(&vanity, &frozen.config).unwrap().rule, RuleId::StaticDelegateCall);
+
+    // Solady's PUSH0 clone only matches the pattern since ruleset 17
+    assert_eq!(detect_proxy(SOLADY_PUSH0_CLONE_CODE, &latest.config).unwrap().rule, RuleId::SoladyClonePattern);
+    assert_ne!(detect_proxy(SOLADY_PUSH0_CLONE_CODE, &frozen.config).unwrap().rule, RuleId::SoladyClonePattern);
+}
+
+#[test]
+fn test_builtin_profiles() {
+    for name in AnalysisProfile::BUILTIN {
+        assert_eq!(AnalysisProfile::builtin(name).unwrap().name, *name);
+    }
+    assert_eq!(AnalysisProfile::builtin("v0").unwrap_err(), ProfileError::UnknownProfile("v0".to_string()));
+    assert_ne!(Ruleset::pinned(3).unwrap().fingerprint(), Ruleset::LATEST.fingerprint());
+}
+
+/// Captured when the profile was frozen, the ids are those ruleset 3 gave before it was
+/// superseded. Never update these: a failure means the frozen behavior changed.
+const V1_FROZEN: &[(&str, &str, &str)] = &[
+    ("EIP_1967_CODE", "KnownStorageSlot", "0x55c7948ba4d37b1148b9d145b9c7c3cb9812bc2995a520bee48d1b7c0b1f8ca1"),
+    ("EIP_897_CODE", "LowStorageSlot", "0xdf2288f01ef05eb1dcf0c31994902a8d041a66ec9c83ab164fe30b53ba53b39d"),
+    ("DIAMOND_STANDARD_CODE", "DiamondLoupeSelector", "0xdcccb380b9b29971c43a597e9edd6d6f1a4b4d15a9a121fa0d46ad41400c8f5a"),
+    ("BLUEPRINT_1167_CODE", "Eip1167Pattern", "0x84ba7156df227c9101f9771ce39b3c7ddbe840fa44e283c66b961e81c824b441"),
+    ("BLUEPRINT_1167_DATA_CODE", "Eip1167Pattern", "0x893a7eab216b460a19a5058748051abb62d48930b7a1a06c97846411d47af327"),
+    ("GENERATED_ROUTER_CODE", "GeneratedRouterPattern", "0x0dd87192c4512d2bed3823bd279bef3ba8910768eb162ad346b2a63b3d60cbf4"),
+    ("GENERATED_ROUTER_LINEAR_CODE", "GeneratedRouterPattern", "0x51ef9633e2d8d08ae741a97b58b07b803eb98dd517910a9c37d641c145235c85"),
+    ("VYPER_FORWARDER_V2_CODE", "VyperForwarderPattern", "0xb07f0ab63b6f6ca07f547c8ed6f1d49f551ff1f1882123398adde4f15321a29f"),
+    ("VYPER_FORWARDER_V1_CODE", "VyperForwarderPattern", "0x2ed95edd2fb8b7b0236a6404b5f2cf4ef66aa009aa4e74bc3c4f41e65ceb2b8d"),
+    ("SAFE_PROXY_CODE", "SafeProxyPattern", "0xad83a4bc69d93560174ca8b2d10641db74fb39a52a910646f01d4c0800da5bfc"),
+    ("SOLADY_PUSH0_CLONE_CODE", "SoladyClonePattern", "0x4f116088ad7f22c65613af882c28110bcc7fff8355c68c9b81cae5998d319d4f"),
+    ("SOLADY_CWIA_CODE", "SoladyClonePattern", "0xe97a7b08b21c0e4943765da40628fe8aef87ee2dd1620d6d7d200bb300619811"),
+    ("BEACON_PROXY_CODE", "BeaconProxyPattern", "0x0e122e9042e38b5e6378fd89383f6889dba23c00fb57c57115d2603ddd8d7bc2"),
+    ("FALLBACK_SLOT_PROXY_CODE", "KnownStorageSlot", "0xc67094f64569b79f77c9d3bebd3f010859c13c3d3237809dcf4c193a3f770bca"),
+    ("TRANSPARENT_PROXY_CODE", "KnownStorageSlot", "0x1125489acc71d9e40df515940de105e537dab70ad8a612dd94db01a69a47d6c9"),
+    ("UNITROLLER_CODE", "LowStorageSlot", "0xf5c61e9d94222eb81a9294cf57505a2af507bc30658714d582f087d14724edcc"),
+];
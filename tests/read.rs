@@ -0,0 +1,1014 @@
+mod common;
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use ethers_core::{abi::{decode, encode, ParamType, Token}, types::{BlockId, BlockNumber, H160, H256}};
+use evm_proxy_tools::{check_self_report, check_upgrade_events, event_monitoring_advice, find_last_upgrade_block, get_event_monitoring_advice, UpgradeEventHistory, UpgradeSignal, classify_upgradeability, probe_call, probe_upgradeability, get_proxy_admin, detect_proxy, DetectorConfig, find_deploy_block, find_deploy_block_with_progress, get_proxy_freshness_with_progress, ErrorCategory, Finding, ProxyType, get_proxy_freshness, get_proxy_implementation, get_proxy_implementation_at, get_proxy_implementation_with_config, resolve_block_number, get_implementation_history, get_implementation_history_with_progress, scan_upgrade_events, scan_upgrade_events_with_progress, FacetCut, FacetCutAction, UpgradeEvent, read_facets, LoupeDecoding, LoupeFacets, ProxyDispatch, ProbeOutcome, ProxyImplementation, ProxyReadError, ReadConfig, RpcError, RpcErrorKind, Selector, StorageReader, SlotExtraction, MULTICALL3, verify_implementation};
+use serde_json::{json, Value};
+
+use common::{block_param, calls_around_drop, rpc_error, FnRpc};
+use common::fixtures::{BEACON_PROXY_CODE, SAFE_PROXY_CODE};
+
+const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+const IMPLEMENTATION: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000bb"));
+
+/// Answers `eth_getCode` as if `PROXY` and `IMPLEMENTATION` were deployed at the given blocks.
+fn code_timeline(proxy_deploy: u64, impl_deploy: u64, pruned_before: u64) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| {
+        assert_eq!(method, "eth_getCode");
+        let block = block_param(&params[1]);
+        if block < pruned_before {
+            return Err("missing trie node 0x1234 (path )".to_string());
+        }
+        let address: Address = params[0].as_str().unwrap().parse().unwrap();
+        let deployed = if address == PROXY { proxy_deploy } else { impl_deploy };
+        Ok(if block >= deployed { json!("0x6001") } else { json!("0x") })
+    }
+}
+
+#[tokio::test]
+async fn test_find_deploy_block() {
+    let (rpc, client) = FnRpc::provider(code_timeline(1_234_567, 17_000_001, 0));
+    assert_eq!(find_deploy_block(&rpc, &PROXY, 18_000_000, 64).await.unwrap(), Some(1_234_567));
+    assert_eq!(find_deploy_block(&rpc, &IMPLEMENTATION, 16_000_000, 64).await.unwrap(), None);
+    assert!(matches!(find_deploy_block(&rpc, &PROXY, 18_000_000, 4).await, Err(ProxyReadError::SearchBudgetExhausted(4))));
+    assert!(client.calls() > 0);
+}
+
+#[tokio::test]
+async fn test_proxy_freshness() {
+    let (rpc, _) = FnRpc::provider(code_timeline(1_000_000, 17_999_000, 0));
+    let freshness = get_proxy_freshness(&rpc, &PROXY, &IMPLEMENTATION, Some(18_000_000.into()), 64).await.unwrap();
+    assert_eq!(freshness.proxy_deploy_block, 1_000_000);
+    assert_eq!(freshness.impl_deploy_block, 17_999_000);
+    assert!(freshness.impl_newer_than_proxy);
+    // Deployed recently, but long after the proxy
+    assert!(!freshness.is_recent_swap(7200));
+    assert_eq!(freshness.finding(7200), None);
+
+    let (rpc, _) = FnRpc::provider(code_timeline(17_000_000, 17_000_500, 0));
+    let freshness = get_proxy_freshness(&rpc, &PROXY, &IMPLEMENTATION, Some(18_000_000.into()), 64).await.unwrap();
+    assert!(freshness.is_recent_swap(7200));
+    assert!(!freshness.is_recent_swap(100));
+    assert_eq!(freshness.finding(7200), Some(Finding::RecentImplementation { proxy_deploy_block: 17_000_000, impl_deploy_block: 17_000_500 }));
+
+    let (rpc, _) = FnRpc::provider(code_timeline(1_000_000, 900_000, 0));
+    let freshness = get_proxy_freshness(&rpc, &PROXY, &IMPLEMENTATION, Some(18_000_000.into()), 64).await.unwrap();
+    assert!(!freshness.impl_newer_than_proxy);
+    assert!(!freshness.is_recent_swap(u64::MAX));
+
+    let (rpc, _) = FnRpc::provider(code_timeline(1_000_000, 900_000, 17_000_000));
+    assert!(matches!(get_proxy_freshness(&rpc, &PROXY, &IMPLEMENTATION, Some(18_000_000.into()), 64).await, Err(ProxyReadError::HistoricalStateUnavailable(_))));
+}
+
+/// Checks that every stage reported strictly increasing counts within its total, if it has one.
+fn assert_monotonic(reports: &[(u64, Option<u64>, String)]) {
+    for (i, (completed, total, stage)) in reports.iter().enumerate() {
+        assert!(total.is_none_or(|total| *completed <= total), "{} past its total", stage);
+        if let Some((previous, _, _)) = reports[..i].iter().rev().find(|(_, _, s)| s == stage) {
+            assert!(completed > previous, "{} went from {} to {}", stage, previous, completed);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_search_progress() {
+    let reports = Mutex::new(Vec::new());
+    let record = |completed: u64, total: Option<u64>, stage: &str| reports.lock().unwrap().push((completed, total, stage.to_string()));
+
+    let (rpc, client) = FnRpc::provider(code_timeline(1_234_567, 17_000_001, 0));
+    assert_eq!(find_deploy_block_with_progress(&rpc, &PROXY, 18_000_000, 64, &record).await.unwrap(), Some(1_234_567));
+    let reports_so_far = reports.lock().unwrap().clone();
+    assert_monotonic(&reports_so_far);
+    // Every probe is reported, the last one being the final count
+    assert_eq!(reports_so_far.len(), client.calls());
+    assert_eq!(reports_so_far.last().unwrap().0, client.calls() as u64);
+    assert_eq!(reports_so_far.last().unwrap().1, Some(26));
+
+    reports.lock().unwrap().clear();
+    let (rpc, _) = FnRpc::provider(code_timeline(1_000_000, 17_999_000, 0));
+    get_proxy_freshness_with_progress(&rpc, &PROXY, &IMPLEMENTATION, Some(18_000_000.into()), 64, &record).await.unwrap();
+    let reports = reports.into_inner().unwrap();
+    assert_monotonic(&reports);
+    assert!(reports.iter().any(|(_, _, stage)| stage == "proxy-deploy-block"));
+    assert!(reports.iter().any(|(_, _, stage)| stage == "impl-deploy-block"));
+}
+
+#[tokio::test]
+async fn test_packed_slot_implementation() {
+    // Implementation in the high 20 bytes, a version counter in the low ones
+    let (rpc, _) = FnRpc::provider(|method, _| {
+        assert_eq!(method, "eth_getStorageAt");
+        Ok(json!("0x5a1d7e3c9b0f2486ae13c5d7092b8f64e1a3c7d9000000000000000000000007"))
+    });
+    let rpc = Arc::new(rpc);
+    let extraction = SlotExtraction::new(96, U256::MAX >> 96);
+    let implementation = get_proxy_implementation(rpc.clone(), &PROXY, &ProxyDispatch::Storage(U256::from(3), Some(extraction))).await.unwrap();
+    let expected = Address::new(hex_literal::hex!("5a1d7e3c9b0f2486ae13c5d7092b8f64e1a3c7d9"));
+    assert!(matches!(implementation, ProxyImplementation::Single(address) if address == expected));
+    assert!(matches!(get_proxy_implementation(rpc, &PROXY, &ProxyDispatch::Storage(U256::from(3), None)).await, Err(ProxyReadError::StorageNotAddress)));
+}
+
+#[tokio::test]
+async fn test_safe_singleton() {
+    let (rpc, _) = FnRpc::provider(|method, params| {
+        assert_eq!(method, "eth_getStorageAt");
+        assert_eq!(U256::from_str_radix(params[1].as_str().unwrap().trim_start_matches("0x"), 16).unwrap(), U256::ZERO);
+        Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb"))
+    });
+    let result = detect_proxy(SAFE_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    let implementation = get_proxy_implementation(Arc::new(rpc), &PROXY, &result.dispatch).await.unwrap();
+    assert!(matches!(implementation, ProxyImplementation::Single(address) if address == IMPLEMENTATION));
+}
+
+#[tokio::test]
+async fn test_dispatch_get_implementation() {
+    // Slot 1 holds IMPLEMENTATION at block 0x10, slot 2 BEACON
+    let (rpc, client) = FnRpc::provider(|method, params| {
+        assert_eq!(method, "eth_getStorageAt");
+        assert_eq!(block_param(&params[2]), 16);
+        match U256::from_str_radix(params[1].as_str().unwrap().trim_start_matches("0x"), 16).unwrap().to::<u64>() {
+            1 => Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb")),
+            _ => Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000cc")),
+        }
+    });
+    let rpc = Arc::new(rpc);
+    let block = Some(16u64.into());
+    assert_eq!(ProxyDispatch::Storage(U256::from(1), None).get_implementation(rpc.clone(), &PROXY, block).await.unwrap(), IMPLEMENTATION);
+    assert_eq!(client.calls(), 1);
+    // Static dispatches need no RPC
+    assert_eq!(ProxyDispatch::Static(BEACON).get_implementation(rpc.clone(), &PROXY, block).await.unwrap(), BEACON);
+    assert_eq!(client.calls(), 1);
+
+    let multiple = ProxyDispatch::MultipleStorage(vec![U256::from(1), U256::from(2)]);
+    assert!(matches!(multiple.get_implementation(rpc.clone(), &PROXY, block).await, Err(ProxyReadError::MultipleImplementations)));
+    assert!(matches!(ProxyDispatch::Facet_EIP_2535.get_implementation(rpc.clone(), &PROXY, block).await, Err(ProxyReadError::MultipleImplementations)));
+    assert_eq!(client.calls(), 1);
+    assert_eq!(multiple.get_implementations(rpc.clone(), &PROXY, block).await.unwrap(), vec![IMPLEMENTATION, BEACON]);
+    assert_eq!(ProxyDispatch::Static(BEACON).get_implementations(rpc, &PROXY, block).await.unwrap(), vec![BEACON]);
+}
+
+const BEACON: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+
+/// `PROXY`'s beacon slot holds `BEACON`, which answers `implementation()` with `IMPLEMENTATION`
+/// and has code if `beacon_code`.
+fn beacon_chain(beacon_code: &'static str) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| match method {
+        "eth_getStorageAt" => {
+            assert_eq!(params[0].as_str().unwrap().parse::<Address>().unwrap(), PROXY);
+            Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000cc"))
+        },
+        "eth_getCode" => {
+            assert_eq!(params[0].as_str().unwrap().parse::<Address>().unwrap(), BEACON);
+            Ok(json!(beacon_code))
+        },
+        "eth_call" => {
+            assert_eq!(params[0]["to"].as_str().unwrap().parse::<Address>().unwrap(), BEACON);
+            assert_eq!(params[0]["data"].as_str().or(params[0]["input"].as_str()), Some("0x5c60da1b"));
+            Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb"))
+        },
+        _ => Err(format!("unexpected {}", method)),
+    }
+}
+
+#[tokio::test]
+async fn test_beacon_implementation() {
+    let (rpc, client) = FnRpc::provider(beacon_chain("0x6001"));
+    let result = detect_proxy(BEACON_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    let implementation = get_proxy_implementation(Arc::new(rpc), &PROXY, &result.dispatch).await.unwrap();
+    // The implementation the beacon serves, not the beacon
+    assert!(matches!(implementation, ProxyImplementation::Single(address) if address == IMPLEMENTATION));
+    assert_eq!(client.calls(), 3);
+
+    let (rpc, _) = FnRpc::provider(beacon_chain("0x"));
+    let err = get_proxy_implementation(Arc::new(rpc), &PROXY, &result.dispatch).await.unwrap_err();
+    assert!(matches!(err, ProxyReadError::NoCode(address) if address == BEACON));
+}
+
+/// Answers `eth_getStorageAt` for `PROXY` with `admin` in the EIP-1967 admin slot and
+/// `zos_admin` in the ZeppelinOS one.
+fn admin_slots(admin: &'static str, zos_admin: &'static str) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| {
+        assert_eq!(method, "eth_getStorageAt");
+        assert_eq!(params[0].as_str().unwrap().parse::<Address>().unwrap(), PROXY);
+        match params[1].as_str().unwrap() {
+            "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103" => Ok(json!(admin)),
+            "0x10d6a54a4754c8869d6886b5f5d7fbfa5b4522237ea5c60d11bc4e7a1ff9390b" => Ok(json!(zos_admin)),
+            slot => Err(format!("unexpected slot {}", slot)),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_admin() {
+    const ZERO: &str = "0x0000000000000000000000000000000000000000000000000000000000000000";
+    const ADMIN: &str = "0x00000000000000000000000000000000000000000000000000000000000000cc";
+    let admin = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+
+    let (rpc, client) = FnRpc::provider(admin_slots(ADMIN, ZERO));
+    assert_eq!(get_proxy_admin(&rpc, &PROXY, None).await.unwrap(), Some(admin));
+    assert_eq!(client.calls(), 1);
+
+    // ZeppelinOS proxies keep it in their own slot
+    let (rpc, _) = FnRpc::provider(admin_slots(ZERO, ADMIN));
+    assert_eq!(get_proxy_admin(&rpc, &PROXY, None).await.unwrap(), Some(admin));
+
+    // UUPS proxies have none
+    let (rpc, client) = FnRpc::provider(admin_slots(ZERO, ZERO));
+    assert_eq!(get_proxy_admin(&rpc, &PROXY, None).await.unwrap(), None);
+    assert_eq!(client.calls(), 2);
+
+    let (rpc, _) = FnRpc::provider(admin_slots("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff", ZERO));
+    assert!(matches!(get_proxy_admin(&rpc, &PROXY, None).await, Err(ProxyReadError::StorageNotAddress)));
+}
+
+/// Answers `eth_call` with `answer(selector)`, an error meaning a revert.
+fn getters(answer: impl Fn(&str) -> Result<Value, String> + Send + Sync + 'static) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| {
+        assert_eq!(method, "eth_call");
+        let data = params[0]["data"].as_str().or(params[0]["input"].as_str()).unwrap();
+        answer(data)
+    }
+}
+
+#[tokio::test]
+async fn test_classify_upgradeability() {
+    const EIP_1967_SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+    const PROXIABLE: &str = "0xc5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7";
+    let uuid = |answer: &'static str| getters(move |data| {
+        assert_eq!(data, "0x52d1902d");
+        Ok(json!(answer))
+    });
+
+    // OpenZeppelin's UUPSUpgradeable answers with the EIP-1967 slot, EIP-1822's Proxiable with its own
+    for (proxy_type, answer) in [(ProxyType::EIP_1967, EIP_1967_SLOT), (ProxyType::EIP_1822, PROXIABLE), (ProxyType::EIP_1967_CUSTOM, EIP_1967_SLOT)] {
+        let (rpc, _) = FnRpc::provider(uuid(answer));
+        assert_eq!(classify_upgradeability(&rpc, proxy_type, &IMPLEMENTATION, None).await.unwrap(), ProxyType::UUPS);
+    }
+
+    // Any other slot, or no answer, says nothing about the upgrade logic
+    for answer in ["0x0000000000000000000000000000000000000000000000000000000000000001", "0x"] {
+        let (rpc, _) = FnRpc::provider(uuid(answer));
+        assert_eq!(classify_upgradeability(&rpc, ProxyType::EIP_1967, &IMPLEMENTATION, None).await.unwrap(), ProxyType::EIP_1967);
+    }
+
+    // Implementations of transparent proxies don't have the function
+    let (rpc, _) = FnRpc::provider(getters(|_| Err("execution reverted".to_string())));
+    assert_eq!(classify_upgradeability(&rpc, ProxyType::EIP_1967, &IMPLEMENTATION, None).await.unwrap(), ProxyType::EIP_1967);
+
+    // Proxies with another dispatch aren't asked
+    let (rpc, client) = FnRpc::provider(uuid(EIP_1967_SLOT));
+    assert_eq!(classify_upgradeability(&rpc, ProxyType::EIP_1167, &IMPLEMENTATION, None).await.unwrap(), ProxyType::EIP_1167);
+    assert_eq!(client.calls(), 0);
+
+    let (rpc, _) = FnRpc::provider(getters(|_| Err("connection refused".to_string())));
+    assert!(matches!(classify_upgradeability(&rpc, ProxyType::EIP_1967, &IMPLEMENTATION, None).await, Err(ProxyReadError::Rpc(_))));
+}
+
+#[tokio::test]
+async fn test_probe_outcomes() {
+    // Error(string) with "Ownable: caller is not the owner"
+    const REASON: &str = "0x08c379a000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000020\
+        4f776e61626c653a2063616c6c6572206973206e6f7420746865206f776e6572";
+    let owner_reason = || ProbeOutcome::RevertWithReason("Ownable: caller is not the owner".to_string());
+    let custom_error = Bytes::from(hex_literal::hex!("82b42900").to_vec());
+    let shapes = [
+        // Geth, Reth, Anvil and Alchemy: code 3 with the payload
+        (rpc_error(3, "execution reverted: Ownable: caller is not the owner", Some(json!(REASON))), owner_reason()),
+        (rpc_error(3, "execution reverted", Some(json!("0x82b42900"))), ProbeOutcome::RevertRaw(custom_error.clone())),
+        // Erigon and Infura: -32000, payload in the data when there is one
+        (rpc_error(-32000, "execution reverted", Some(json!(REASON))), owner_reason()),
+        (rpc_error(-32000, "execution reverted", None), ProbeOutcome::RevertRaw(Bytes::new())),
+        // Gateways nesting the payload, or only giving the reason in the message
+        (rpc_error(-32000, "execution reverted", Some(json!({ "message": "execution reverted", "data": "0x82b42900" }))), ProbeOutcome::RevertRaw(custom_error.clone())),
+        (rpc_error(-32000, "execution reverted: Ownable: caller is not the owner", None), owner_reason()),
+        // Nethermind: generic message, what happened in the data
+        (rpc_error(-32015, "VM execution error.", Some(json!(format!("Reverted {}", REASON)))), owner_reason()),
+        (rpc_error(-32015, "VM execution error.", Some(json!("stack underflow"))), ProbeOutcome::ExecutionError("VM execution error.: stack underflow".to_string())),
+        // Halts of Geth and Erigon
+        (rpc_error(-32000, "out of gas", None), ProbeOutcome::OutOfGas),
+        (rpc_error(-32000, "gas required exceeds allowance (50000000)", None), ProbeOutcome::OutOfGas),
+        (rpc_error(-32000, "invalid opcode: INVALID", None), ProbeOutcome::ExecutionError("invalid opcode: INVALID".to_string())),
+        (rpc_error(-32000, "invalid opcode: opcode 0xfe not defined", None), ProbeOutcome::ExecutionError("invalid opcode: opcode 0xfe not defined".to_string())),
+    ];
+    for (error, expected) in shapes {
+        let (rpc, _) = FnRpc::provider(getters(move |_| Err(error.clone())));
+        assert_eq!(probe_call(&rpc, &IMPLEMENTATION, Bytes::new(), None).await.unwrap(), expected);
+    }
+
+    let (rpc, _) = FnRpc::provider(getters(|_| Ok(json!("0x2a"))));
+    assert_eq!(probe_call(&rpc, &IMPLEMENTATION, Bytes::new(), None).await.unwrap(), ProbeOutcome::Success(Bytes::from(vec![0x2a])));
+    // Failing to get an answer isn't an outcome
+    for error in ["connection refused".to_string(), rpc_error(-32601, "the method eth_call does not exist", None)] {
+        let (rpc, _) = FnRpc::provider(getters(move |_| Err(error.clone())));
+        assert!(matches!(probe_call(&rpc, &IMPLEMENTATION, Bytes::new(), None).await, Err(ProxyReadError::Rpc(_))));
+    }
+
+    // Upgradeability checks report their probe
+    let (rpc, _) = FnRpc::provider(getters(|_| Err(rpc_error(-32000, "out of gas", None))));
+    assert_eq!(probe_upgradeability(&rpc, ProxyType::EIP_1967, &IMPLEMENTATION, None).await.unwrap(), (ProxyType::EIP_1967, Some(ProbeOutcome::OutOfGas)));
+    assert_eq!(probe_upgradeability(&rpc, ProxyType::EIP_1167, &IMPLEMENTATION, None).await.unwrap(), (ProxyType::EIP_1167, None));
+}
+
+#[tokio::test]
+async fn test_self_report_agrees() {
+    let (rpc, _) = FnRpc::provider(getters(|data| match data {
+        "0x5c60da1b" => Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb")),
+        _ => Err("execution reverted".to_string()),
+    }));
+    let report = check_self_report(&rpc, &PROXY, ProxyType::EIP_1967, IMPLEMENTATION).await.unwrap();
+    assert_eq!(report.selector, Selector::from(0x5c60da1b));
+    assert!(report.agrees());
+    assert_eq!(report.finding(), None);
+}
+
+#[tokio::test]
+async fn test_self_report_disagrees() {
+    let (rpc, client) = FnRpc::provider(getters(|data| match data {
+        // masterCopy() is tried first for Safe proxies
+        "0xa619486e" => Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000cc")),
+        _ => Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb")),
+    }));
+    let report = check_self_report(&rpc, &PROXY, ProxyType::GnosisSafe, IMPLEMENTATION).await.unwrap();
+    let getter_value = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+    assert_eq!(report.selector, Selector::from(0xa619486e));
+    assert_eq!((report.slot_value, report.getter_value), (IMPLEMENTATION, getter_value));
+    let finding = report.finding().unwrap();
+    assert_eq!(finding, Finding::SelfReportMismatch { slot_value: IMPLEMENTATION, getter_value });
+    assert_eq!(finding.severity(), evm_proxy_tools::Severity::High);
+    assert_eq!(client.calls(), 1);
+}
+
+#[tokio::test]
+async fn test_self_report_getter_reverts() {
+    let (rpc, client) = FnRpc::provider(getters(|_| Err("execution reverted".to_string())));
+    assert_eq!(check_self_report(&rpc, &PROXY, ProxyType::EIP_1967, IMPLEMENTATION).await, None);
+    assert_eq!(client.calls(), 3);
+
+    // Returning something that isn't an address doesn't count as a report either
+    let (rpc, _) = FnRpc::provider(getters(|_| Ok(json!("0x01"))));
+    assert_eq!(check_self_report(&rpc, &PROXY, ProxyType::EIP_1967, IMPLEMENTATION).await, None);
+}
+
+const UPGRADED: &str = "0xbc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b";
+
+/// Answers `eth_getLogs` for block 500 with `Upgraded(IMPLEMENTATION)` logged by `emitter`, if any.
+fn upgrade_logs(emitter: Option<&'static str>) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| {
+        assert_eq!(method, "eth_getLogs");
+        assert_eq!(params[0]["fromBlock"], json!("0x1f4"));
+        assert!(params[0]["topics"][0].as_array().unwrap().contains(&json!(UPGRADED)));
+        Ok(json!(emitter.into_iter().map(|emitter| json!({
+            "address": emitter,
+            "topics": [UPGRADED, "0x00000000000000000000000000000000000000000000000000000000000000bb"],
+            "data": "0x",
+            "blockNumber": "0x1f4",
+        })).collect::<Vec<_>>()))
+    }
+}
+
+#[tokio::test]
+async fn test_event_monitoring_advice() {
+    // The beacon is read to name it in the advice
+    let (rpc, client) = FnRpc::provider(beacon_chain("0x6001"));
+    let result = detect_proxy(BEACON_PROXY_CODE, &DetectorConfig::default()).unwrap();
+    let advice = get_event_monitoring_advice(&rpc, &PROXY, result.proxy_type, &result.dispatch, None).await.unwrap().unwrap();
+    assert_eq!(client.calls(), 1);
+    assert!(matches!(&advice.signals[1], UpgradeSignal::Events { address, .. } if *address == BEACON));
+    assert!(!advice.events_at_proxy());
+
+    // Upgrading a beacon proxy's implementation is logged by the beacon
+    let (rpc, _) = FnRpc::provider(upgrade_logs(Some("0x00000000000000000000000000000000000000cc")));
+    assert_eq!(check_upgrade_events(&rpc, &advice, &IMPLEMENTATION, 500).await.unwrap(), UpgradeEventHistory::AtAdvisedAddress);
+
+    // A UUPS implementation logging its upgrade from its own address, not through the proxy
+    let uups = event_monitoring_advice(&PROXY, ProxyType::UUPS, &ProxyDispatch::Storage(U256::ZERO, None), None).unwrap();
+    let (rpc, _) = FnRpc::provider(upgrade_logs(Some("0x00000000000000000000000000000000000000bb")));
+    assert_eq!(check_upgrade_events(&rpc, &uups, &IMPLEMENTATION, 500).await.unwrap(), UpgradeEventHistory::ElsewhereAt(IMPLEMENTATION));
+    let (rpc, _) = FnRpc::provider(upgrade_logs(None));
+    assert_eq!(check_upgrade_events(&rpc, &uups, &IMPLEMENTATION, 500).await.unwrap(), UpgradeEventHistory::Missing);
+}
+
+#[tokio::test]
+async fn test_find_last_upgrade_block() {
+    // The slot held another implementation until block 500
+    let (rpc, _) = FnRpc::provider(|method, params| {
+        assert_eq!(method, "eth_getStorageAt");
+        Ok(match block_param(&params[2]) {
+            block if block >= 500 => json!("0x00000000000000000000000000000000000000000000000000000000000000bb"),
+            block if block >= 100 => json!("0x00000000000000000000000000000000000000000000000000000000000000dd"),
+            _ => json!("0x0000000000000000000000000000000000000000000000000000000000000000"),
+        })
+    });
+    let dispatch = ProxyDispatch::Storage(U256::ZERO, None);
+    assert_eq!(find_last_upgrade_block(Arc::new(rpc), &PROXY, &dispatch, 1_000, 64).await.unwrap(), Some(500));
+
+    let (rpc, _) = FnRpc::provider(|_, params| match block_param(&params[2]) {
+        block if block < 900 => Err("missing trie node 0x1234 (path )".to_string()),
+        _ => Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb")),
+    });
+    assert!(matches!(find_last_upgrade_block(Arc::new(rpc), &PROXY, &dispatch, 1_000, 64).await, Err(ProxyReadError::HistoricalStateUnavailable(_))));
+}
+
+/// The implementation slot per block: none before 100, 0xdd until 500, 0xcc at 700 only and
+/// 0xbb otherwise. `Upgraded` logs are answered for at most 256 blocks at once.
+fn upgrade_timeline(method: &str, params: &Value) -> Result<Value, String> {
+    let word = |byte: &str| json!(format!("0x{:0>64}", byte));
+    match method {
+        "eth_getStorageAt" => Ok(match block_param(&params[2]) {
+            700 => word("cc"),
+            block if block >= 500 => word("bb"),
+            block if block >= 100 => word("dd"),
+            _ => word("0"),
+        }),
+        "eth_getLogs" => {
+            let (from, to) = (block_param(&params[0]["fromBlock"]), block_param(&params[0]["toBlock"]));
+            if to - from >= 256 {
+                return Err("query exceeds max block range 256".to_string());
+            }
+            let logs = [(100, "dd"), (500, "bb"), (700, "cc"), (701, "bb")];
+            Ok(json!(logs.iter().filter(|(block, _)| (from..=to).contains(block)).map(|(block, implementation)| json!({
+                "address": "0x00000000000000000000000000000000000000aa",
+                "topics": [UPGRADED, format!("0x{:0>64}", implementation)],
+                "data": "0x",
+                "blockNumber": format!("{:#x}", block),
+                "transactionHash": format!("0x{:0>64x}", block),
+            })).collect::<Vec<_>>()))
+        },
+        _ => Err(format!("unexpected {}", method)),
+    }
+}
+
+#[tokio::test]
+async fn test_implementation_history() {
+    let (dd, cc) = (Address::with_last_byte(0xdd), Address::with_last_byte(0xcc));
+    // Sampling every 300 blocks misses the upgrade undone at block 701
+    let (rpc, client) = FnRpc::provider(upgrade_timeline);
+    let dispatch = ProxyDispatch::Storage(U256::ZERO, None);
+    let history = get_implementation_history(Arc::new(rpc), &PROXY, &dispatch, 0, 1_000, 300).await.unwrap();
+    assert_eq!(history, vec![(100, dd), (500, IMPLEMENTATION)]);
+    assert!(client.calls() < 40, "{} calls", client.calls());
+
+    // The logs of an EIP-1967 proxy give it, asked in chunks the provider accepts
+    let (rpc, _) = FnRpc::provider(upgrade_timeline);
+    let dispatch = ProxyDispatch::Storage(U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")), None);
+    let history = get_implementation_history(Arc::new(rpc), &PROXY, &dispatch, 0, 1_000, 300).await.unwrap();
+    assert_eq!(history, vec![(100, dd), (500, IMPLEMENTATION), (700, cc), (701, IMPLEMENTATION)]);
+
+    // Starting after an upgrade lists the implementation the range started with
+    let (rpc, _) = FnRpc::provider(upgrade_timeline);
+    let history = get_implementation_history(Arc::new(rpc), &PROXY, &dispatch, 200, 600, 50).await.unwrap();
+    assert_eq!(history, vec![(200, dd), (500, IMPLEMENTATION)]);
+}
+
+#[tokio::test]
+async fn test_history_progress() {
+    let reports = Mutex::new(Vec::new());
+    let record = |completed: u64, total: Option<u64>, stage: &str| reports.lock().unwrap().push((completed, total, stage.to_string()));
+
+    // The log range halved down to 156 blocks, seven chunks, then four windows of 300 blocks
+    let (rpc, _) = FnRpc::provider(upgrade_timeline);
+    let dispatch = ProxyDispatch::Storage(U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc")), None);
+    let history = get_implementation_history_with_progress(Arc::new(rpc), &PROXY, &dispatch, 0, 1_000, 300, &record).await.unwrap();
+    assert_eq!(history.len(), 4);
+    let reports_so_far = reports.lock().unwrap().clone();
+    assert_monotonic(&reports_so_far);
+    assert_eq!(reports_so_far.iter().rfind(|(_, _, stage)| stage == "upgrade-logs"), Some(&(7, None, "upgrade-logs".to_string())));
+    assert_eq!(reports_so_far.last(), Some(&(4, Some(4), "history".to_string())));
+
+    reports.lock().unwrap().clear();
+    let (rpc, _) = FnRpc::provider(upgrade_timeline);
+    scan_upgrade_events_with_progress(&rpc, &PROXY, 0, 1_000, &record).await.unwrap();
+    assert_eq!(reports.into_inner().unwrap().last(), Some(&(7, None, "upgrade-logs".to_string())));
+}
+
+#[tokio::test]
+async fn test_scan_upgrade_events() {
+    let word = |byte: &str| format!("0x{:0>64}", byte);
+    let address = |byte: u8| Token::Address(H160::from_low_u64_be(byte as u64));
+    let admins = encode(&[address(0x01), address(0x02)]);
+    let cut = encode(&[
+        Token::Array(vec![
+            Token::Tuple(vec![address(0xfa), Token::Uint(0.into()), Token::Array(vec![Token::FixedBytes(vec![0x11, 0x22, 0x33, 0x44]), Token::FixedBytes(vec![0x55, 0x66, 0x77, 0x88])])]),
+            Token::Tuple(vec![address(0x00), Token::Uint(2.into()), Token::Array(vec![Token::FixedBytes(vec![0xcd, 0xff, 0xac, 0xc6])])]),
+        ]),
+        address(0xfb),
+        Token::Bytes(vec![0xe1, 0xc7, 0x39, 0x2a]),
+    ]);
+    let logs = json!([
+        { "topics": [UPGRADED, word("bb")], "data": "0x", "blockNumber": "0x64" },
+        // Not indexed, so not the standard event
+        { "topics": [UPGRADED], "data": word("cc"), "blockNumber": "0x65" },
+        { "topics": ["0x7e644d79422f17c01e4894b5f4f588d331ebfa28653d42ae832dc59e38c9798f"], "data": format!("0x{}", hex::encode(admins)), "blockNumber": "0x66" },
+        { "topics": ["0x1cf3b03a6cf19fa2baba4df148e9dcabedea7f8a5c07840e207e5c089be95d3e", word("cc")], "data": "0x", "blockNumber": "0x67" },
+        { "topics": ["0x8faa70878671ccd212d20771b795c50af8fd3ff6cf27f4bde57e5d4de0aeb673"], "data": format!("0x{}", hex::encode(cut)), "blockNumber": "0x68" },
+    ]);
+    let (rpc, _) = FnRpc::provider(move |method, params| {
+        assert_eq!(method, "eth_getLogs");
+        assert_eq!(params[0]["address"], json!("0x00000000000000000000000000000000000000aa"));
+        assert_eq!(params[0]["topics"][0].as_array().unwrap().len(), 4);
+        Ok(json!(logs.as_array().unwrap().iter().map(|log| {
+            let mut log = log.clone();
+            log["address"] = json!("0x00000000000000000000000000000000000000aa");
+            log["transactionHash"] = json!(format!("0x{:0>64}", log["blockNumber"].as_str().unwrap().trim_start_matches("0x")));
+            log
+        }).collect::<Vec<_>>()))
+    });
+    let events = scan_upgrade_events(&rpc, &PROXY, 0, 1_000).await.unwrap();
+    let transaction = |block: u8| B256::left_padding_from(&[block]);
+    assert_eq!(events, vec![
+        UpgradeEvent::Upgraded { block: 100, transaction: transaction(0x64), implementation: IMPLEMENTATION },
+        UpgradeEvent::AdminChanged { block: 102, transaction: transaction(0x66), previous_admin: Address::with_last_byte(0x01), new_admin: Address::with_last_byte(0x02) },
+        UpgradeEvent::BeaconUpgraded { block: 103, transaction: transaction(0x67), beacon: BEACON },
+        UpgradeEvent::DiamondCut {
+            block: 104,
+            transaction: transaction(0x68),
+            cuts: vec![
+                FacetCut { facet: FACET, action: FacetCutAction::Add, selectors: vec![Selector::new(hex_literal::hex!("11223344")), Selector::new(hex_literal::hex!("55667788"))] },
+                FacetCut { facet: Address::ZERO, action: FacetCutAction::Remove, selectors: vec![Selector::new(hex_literal::hex!("cdffacc6"))] },
+            ],
+            init: Address::with_last_byte(0xfb),
+            data: Bytes::from(hex_literal::hex!("e1c7392a")),
+        },
+    ]);
+    assert_eq!(events[3].name(), "DiamondCut");
+}
+
+#[tokio::test]
+async fn test_scan_upgrade_events_limits() {
+    let upgraded = json!([{
+        "address": "0x00000000000000000000000000000000000000aa",
+        "topics": [UPGRADED, format!("0x{:0>64}", "bb")],
+        "data": "0x",
+        "blockNumber": "0x64",
+        "transactionHash": format!("0x{:0>64}", "64"),
+    }]);
+    // Rate limited once, in words that also fit a range cap, then answered for the whole range
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let (rpc, client) = FnRpc::provider({
+        let (ranges, upgraded) = (ranges.clone(), upgraded.clone());
+        move |_, params| {
+            let mut ranges = ranges.lock().unwrap();
+            ranges.push((block_param(&params[0]["fromBlock"]), block_param(&params[0]["toBlock"])));
+            if ranges.len() == 1 {
+                return Err(rpc_error(-32005, "request rate limit exceeded", None));
+            }
+            Ok(upgraded.clone())
+        }
+    });
+    let events = scan_upgrade_events(&rpc, &PROXY, 0, 1_000).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!((client.calls(), ranges.lock().unwrap().clone()), (2, vec![(0, 1_000), (0, 1_000)]));
+
+    // A range cap under the same code halves the range rather than retrying it
+    let (rpc, client) = FnRpc::provider(move |_, params| {
+        if block_param(&params[0]["toBlock"]) - block_param(&params[0]["fromBlock"]) >= 500 {
+            return Err(rpc_error(-32005, "query returned more than 10000 results", None));
+        }
+        Ok(if block_param(&params[0]["fromBlock"]) == 0 { upgraded.clone() } else { json!([]) })
+    });
+    assert_eq!(scan_upgrade_events(&rpc, &PROXY, 0, 1_000).await.unwrap().len(), 1);
+    assert!(client.calls() < 12, "{} calls", client.calls());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_dropped_reads_stop() {
+    // A block search stops at the request it was waiting on
+    let (rpc, client) = FnRpc::suspending_provider(code_timeline(1_234_567, 17_000_001, 0));
+    assert_eq!(calls_around_drop(find_deploy_block(&rpc, &PROXY, 18_000_000, 64), &client).await, (1, 1));
+
+    let (rpc, client) = FnRpc::suspending_provider(code_timeline(1_000_000, 17_999_000, 0));
+    assert_eq!(calls_around_drop(get_proxy_freshness(&rpc, &PROXY, &IMPLEMENTATION, Some(18_000_000.into()), 64), &client).await, (1, 1));
+
+    // Concurrent slot reads are all in flight, none is left running
+    let (rpc, client) = FnRpc::suspending_provider(|_, _| Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb")));
+    let dispatch = ProxyDispatch::MultipleStorage(vec![U256::from(1), U256::from(2), U256::from(3)]);
+    assert_eq!(calls_around_drop(get_proxy_implementation(Arc::new(rpc), &PROXY, &dispatch), &client).await, (3, 3));
+
+    let (rpc, client) = FnRpc::suspending_provider(|_, _| Ok(json!("0x00000000000000000000000000000000000000000000000000000000000000bb")));
+    let dispatch = ProxyDispatch::Storage(U256::ZERO, None);
+    assert_eq!(calls_around_drop(find_last_upgrade_block(Arc::new(rpc), &PROXY, &dispatch, 1_000, 64), &client).await, (1, 1));
+}
+
+const FACET: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000fa"));
+
+/// `facets()` returning `[(FACET, [0x11223344, 0x55667788])]` with the tuple offset counted
+/// from the array length, junk padding the selectors and junk after the data, as some
+/// hand-written loupes do. `address_word` replaces the facet address. Modelled on the reported
+/// layouts, not captured from a deployed loupe.
+fn hand_written_loupe(address_word: &str, tuple_offset: &str) -> String {
+    let words = [
+        "20",
+        "01",
+        tuple_offset,
+        address_word,
+        "40",
+        "02",
+        "11223344deadbeef",
+        "55667788deadbeef",
+    ];
+    let mut data: String = words.iter().map(|word| {
+        if word.len() == 16 { format!("{:0<64}", word) } else { format!("{:0>64}", word) }
+    }).collect();
+    data.push_str("cafe");
+    format!("0x{}", data)
+}
+
+#[tokio::test]
+async fn test_lenient_loupe() {
+    let facet = "fa";
+    let (rpc, _) = FnRpc::provider(getters(move |_| Ok(json!(hand_written_loupe(facet, "40")))));
+    // Strict decoding stays the default and fails
+    let Err(ProxyReadError::Loupe(error)) = read_facets(&rpc, &PROXY, None, LoupeDecoding::default()).await else { panic!("strict decoding should fail") };
+    assert!(error.lenient.is_none());
+    assert!(!error.strict.is_empty());
+    let facets = read_facets(&rpc, &PROXY, None, LoupeDecoding::Lenient).await.unwrap();
+    assert_eq!(facets, LoupeFacets {
+        facets: vec![(FACET, vec![Selector::new(hex_literal::hex!("11223344")), Selector::new(hex_literal::hex!("55667788"))])],
+        leniently_decoded: true,
+    });
+
+    // Junk in the high bytes of the address isn't a facet, the strict error is kept
+    let (rpc, _) = FnRpc::provider(getters(|_| Ok(json!(hand_written_loupe("ff000000000000000000000000000000000000000000fa", "40")))));
+    let Err(ProxyReadError::Loupe(error)) = read_facets(&rpc, &PROXY, None, LoupeDecoding::Lenient).await else { panic!("junk address should fail") };
+    assert!(!error.strict.is_empty());
+    assert!(error.lenient.unwrap().contains("implausible facet address"));
+
+    // An offset past the end
+    let (rpc, _) = FnRpc::provider(getters(|_| Ok(json!(hand_written_loupe("fa", "4000")))));
+    let Err(ProxyReadError::Loupe(error)) = read_facets(&rpc, &PROXY, None, LoupeDecoding::Lenient).await else { panic!("offset past the end should fail") };
+    assert!(error.lenient.is_some());
+}
+
+#[tokio::test]
+async fn test_canonical_loupe() {
+    // With the canonical offset the strict decoding reads the response, padding and all
+    let (rpc, _) = FnRpc::provider(getters(|_| Ok(json!(hand_written_loupe("fa", "20")))));
+    let facets = read_facets(&rpc, &PROXY, None, LoupeDecoding::Lenient).await.unwrap();
+    assert!(!facets.leniently_decoded);
+    assert_eq!(facets.facets[0].0, FACET);
+}
+
+#[tokio::test]
+async fn test_facet_selectors() {
+    // Every selector of a facet is kept, the facet listed once
+    let (rpc, _) = FnRpc::provider(getters(|_| Ok(json!(hand_written_loupe("fa", "20")))));
+    let implementation = get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::Facet_EIP_2535).await.unwrap();
+    let ProxyImplementation::Facets(ref facets) = implementation else { panic!("expected facets, got {:?}", implementation) };
+    assert_eq!(facets, &HashMap::from([
+        (Selector::new(hex_literal::hex!("11223344")), FACET),
+        (Selector::new(hex_literal::hex!("55667788")), FACET),
+    ]));
+    assert_eq!(implementation.to_vec(), vec![FACET]);
+}
+
+const DIAMOND_BASE: U256 = U256::from_be_bytes(hex_literal::hex!("c8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c131b"));
+
+/// The selectors of a diamond's cut, loupe and ownership facets, and of an ERC-20 facet.
+const CUT: [u32; 1] = [0x1f931c1c];
+const LOUPE: [u32; 5] = [0x7a0ed627, 0xadfca15e, 0x52ef6b2c, 0xcdffacc6, 0x01ffc9a7];
+const TOKEN: [u32; 4] = [0xa9059cbb, 0x70a08231, 0x095ea7b3, 0x23b872dd];
+
+fn mapping_slot(key: B256, slot: U256) -> U256 {
+    U256::from_be_bytes(keccak256([key.0, slot.to_be_bytes::<32>()].concat()).0)
+}
+
+fn array_data_slot(slot: U256) -> U256 {
+    U256::from_be_bytes(keccak256(slot.to_be_bytes::<32>()).0)
+}
+
+/// Writes a `bytes4[]` at `slot` the way solc packs it.
+fn store_selectors(storage: &mut HashMap<U256, U256>, slot: U256, selectors: &[u32]) {
+    storage.insert(slot, U256::from(selectors.len()));
+    for (i, selector) in selectors.iter().enumerate() {
+        let word = storage.entry(array_data_slot(slot) + U256::from(i / 8)).or_default();
+        *word |= U256::from(*selector) << (32 * (i % 8));
+    }
+}
+
+/// Answers `eth_getStorageAt` from `storage`, zero elsewhere.
+fn storage_of(storage: HashMap<U256, U256>) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| {
+        assert_eq!(method, "eth_getStorageAt");
+        let slot = U256::from_str_radix(params[1].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+        Ok(json!(B256::from(storage.get(&slot).copied().unwrap_or_default())))
+    }
+}
+
+/// The diamond with `facets` in the reference LibDiamond layout: every selector in `selectors`,
+/// its facet and position in the mapping at the base.
+fn selectors_layout(facets: &[(Address, &[u32])]) -> HashMap<U256, U256> {
+    let mut storage = HashMap::new();
+    let selectors: Vec<(u32, Address)> = facets.iter().flat_map(|(facet, selectors)| selectors.iter().map(move |selector| (*selector, *facet))).collect();
+    store_selectors(&mut storage, DIAMOND_BASE + U256::from(1), &selectors.iter().map(|(selector, _)| *selector).collect::<Vec<_>>());
+    for (position, (selector, facet)) in selectors.iter().enumerate() {
+        let key = B256::right_padding_from(&selector.to_be_bytes());
+        storage.insert(mapping_slot(key, DIAMOND_BASE), U256::from(position) << 160 | U256::from_be_slice(facet.as_slice()));
+    }
+    storage
+}
+
+/// The diamond with `facets` in the layout listing `facetAddresses`, with the selectors and
+/// position of each facet in a mapping.
+fn facet_addresses_layout(facets: &[(Address, &[u32])]) -> HashMap<U256, U256> {
+    let mut storage = HashMap::new();
+    let addresses = DIAMOND_BASE + U256::from(2);
+    storage.insert(addresses, U256::from(facets.len()));
+    for (position, (facet, selectors)) in facets.iter().enumerate() {
+        storage.insert(array_data_slot(addresses) + U256::from(position), U256::from_be_slice(facet.as_slice()));
+        let entry = mapping_slot(B256::left_padding_from(facet.as_slice()), DIAMOND_BASE + U256::from(1));
+        store_selectors(&mut storage, entry, selectors);
+        storage.insert(entry + U256::from(1), U256::from(position));
+        for (selector_position, selector) in selectors.iter().enumerate() {
+            let key = B256::right_padding_from(&selector.to_be_bytes());
+            storage.insert(mapping_slot(key, DIAMOND_BASE), U256::from(selector_position) << 160 | U256::from_be_slice(facet.as_slice()));
+        }
+    }
+    storage
+}
+
+#[tokio::test]
+async fn test_diamond_storage() {
+    let facets: [(Address, &[u32]); 3] = [(FACET, &CUT), (Address::with_last_byte(0xfb), &LOUPE), (Address::with_last_byte(0xfc), &TOKEN)];
+    let expected: HashMap<Selector, Address> = facets.iter().flat_map(|(facet, selectors)| selectors.iter().map(move |selector| (Selector::from(*selector), *facet))).collect();
+    // 10 selectors, the reference layout packs them in two words
+    for storage in [selectors_layout(&facets), facet_addresses_layout(&facets)] {
+        let (rpc, _) = FnRpc::provider(storage_of(storage));
+        let implementation = get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::FacetStorageSlot).await.unwrap();
+        assert_eq!(implementation, ProxyImplementation::Facets(expected.clone()));
+        assert_eq!(implementation.to_vec().len(), 3);
+    }
+
+    // A word that can't be the `selectors` length falls back to the other layout
+    let mut storage = facet_addresses_layout(&facets);
+    storage.insert(DIAMOND_BASE + U256::from(1), U256::from_be_slice(FACET.as_slice()));
+    let (rpc, _) = FnRpc::provider(storage_of(storage));
+    assert_eq!(get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::FacetStorageSlot).await.unwrap(), ProxyImplementation::Facets(expected));
+
+    // Neither layout: a facet out of its position
+    let mut storage = facet_addresses_layout(&facets);
+    let entry = mapping_slot(B256::left_padding_from(FACET.as_slice()), DIAMOND_BASE + U256::from(1));
+    storage.insert(entry + U256::from(1), U256::from(7));
+    let (rpc, _) = FnRpc::provider(storage_of(storage));
+    assert!(matches!(get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::FacetStorageSlot).await, Err(ProxyReadError::UnknownDiamondLayout)));
+
+    // A diamond without facets yet
+    let (rpc, _) = FnRpc::provider(storage_of(HashMap::new()));
+    assert_eq!(get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::FacetStorageSlot).await.unwrap(), ProxyImplementation::Facets(HashMap::new()));
+}
+
+#[tokio::test]
+async fn test_granular_loupe() {
+    let selectors = |selectors: &[[u8; 4]]| format!("0x{}", hex::encode(encode(&[Token::Array(selectors.iter().map(|selector| Token::FixedBytes(selector.to_vec())).collect())])));
+    // facets() runs out of gas, the selectors of the second facet can't be read
+    let loupe = move |empty_facets: bool| getters(move |data| match &data[..10] {
+        "0x7a0ed627" if empty_facets => Ok(json!(format!("0x{}", hex::encode(encode(&[Token::Array(vec![])]))))),
+        "0x7a0ed627" => Err("out of gas".to_string()),
+        "0x52ef6b2c" => Ok(json!(format!("0x{}", hex::encode(encode(&[Token::Array(vec![Token::Address(H160::from_low_u64_be(0xfa)), Token::Address(H160::from_low_u64_be(0xfb))])]))))),
+        "0xadfca15e" if data.ends_with("fa") => Ok(json!(selectors(&[hex_literal::hex!("11223344"), hex_literal::hex!("55667788")]))),
+        "0xadfca15e" => Err("execution reverted".to_string()),
+        selector => panic!("unexpected call {}", selector),
+    });
+    let expected = ProxyImplementation::Facets(HashMap::from([
+        (Selector::new(hex_literal::hex!("11223344")), FACET),
+        (Selector::new(hex_literal::hex!("55667788")), FACET),
+    ]));
+    for empty_facets in [false, true] {
+        let (rpc, client) = FnRpc::provider(loupe(empty_facets));
+        assert_eq!(get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::Facet_EIP_2535).await.unwrap(), expected);
+        assert_eq!(client.calls(), 4);
+    }
+
+    // Without the granular functions either, the facets() error stands
+    let (rpc, _) = FnRpc::provider(getters(|_| Err("execution reverted".to_string())));
+    assert!(matches!(get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::Facet_EIP_2535).await, Err(ProxyReadError::Rpc(e)) if e.kind == RpcErrorKind::Rejected));
+}
+
+/// A diamond with facets 0xfa, 0xfb and 0xfc of one selector each, their last byte, that only
+/// has the granular loupe functions. With `multicall`, Multicall3 is deployed.
+fn granular_diamond(multicall: bool) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    fn answer(data: &[u8]) -> Result<Vec<u8>, String> {
+        match &data[..4] {
+            [0x52, 0xef, 0x6b, 0x2c] => Ok(encode(&[Token::Array([0xfa, 0xfb, 0xfc].iter().map(|facet| Token::Address(H160::from_low_u64_be(*facet))).collect())])),
+            [0xad, 0xfc, 0xa1, 0x5e] => Ok(encode(&[Token::Array(vec![Token::FixedBytes(vec![0, 0, 0, data[35]])])])),
+            _ => Err("execution reverted".to_string()),
+        }
+    }
+    move |method, params| {
+        assert_eq!(method, "eth_call");
+        let data = hex::decode(params[0]["data"].as_str().or(params[0]["input"].as_str()).unwrap().trim_start_matches("0x")).unwrap();
+        if params[0]["to"] != json!("0xca11bde05977b3631167028862be2a173976ca11") {
+            return answer(&data).map(|output| json!(format!("0x{}", hex::encode(output))));
+        }
+        if !multicall {
+            return Ok(json!("0x"));
+        }
+        let call3 = ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+        let calls = decode(&[ParamType::Array(Box::new(call3))], &data[4..]).unwrap().remove(0).into_array().unwrap();
+        let results = calls.into_iter().map(|call| {
+            let call = call.into_tuple().unwrap();
+            let output = answer(&call[2].clone().into_bytes().unwrap());
+            Token::Tuple(vec![Token::Bool(output.is_ok()), Token::Bytes(output.unwrap_or_default())])
+        }).collect();
+        Ok(json!(format!("0x{}", hex::encode(encode(&[Token::Array(results)])))))
+    }
+}
+
+#[tokio::test]
+async fn test_multicall_loupe() {
+    let expected = ProxyImplementation::Facets([0xfa, 0xfb, 0xfc].into_iter().map(|facet| (Selector::from(facet as u32), Address::with_last_byte(facet))).collect());
+    let read = |multicall: bool, config: ReadConfig| async move {
+        let (rpc, client) = FnRpc::provider(granular_diamond(multicall));
+        let implementation = get_proxy_implementation_with_config(Arc::new(rpc), &PROXY, &ProxyDispatch::Facet_EIP_2535, None, &config).await.unwrap();
+        (implementation, client.calls())
+    };
+    // facets(), facetAddresses() and a call per facet
+    assert_eq!(read(true, ReadConfig::default()).await, (expected.clone(), 5));
+    // The facets' selectors in one aggregate3, or two of at most two calls
+    let multicall = ReadConfig { multicall: Some(MULTICALL3), ..Default::default() };
+    assert_eq!(read(true, multicall.clone()).await, (expected.clone(), 3));
+    assert_eq!(read(true, ReadConfig { max_batch: 2, ..multicall.clone() }).await, (expected.clone(), 4));
+    // Without a multicall deployed the calls are made one by one
+    assert_eq!(read(false, multicall).await, (expected, 6));
+}
+
+#[tokio::test]
+async fn test_read_retries() {
+    let dispatch = ProxyDispatch::Storage(U256::from(1), None);
+    // Rate limited twice, then answered
+    let flaky = |failures: usize| {
+        let calls = Arc::new(Mutex::new(0));
+        move |method: &str, _: &Value| {
+            assert_eq!(method, "eth_getStorageAt");
+            let mut calls = calls.lock().unwrap();
+            *calls += 1;
+            if *calls <= failures {
+                return Err(rpc_error(429, "Too Many Requests", None));
+            }
+            Ok(json!(format!("0x{}", hex::encode(B256::left_padding_from(IMPLEMENTATION.as_slice())))))
+        }
+    };
+    let config = ReadConfig { retry_delay: Duration::from_millis(1), ..Default::default() };
+    let (rpc, client) = FnRpc::provider(flaky(2));
+    let implementation = get_proxy_implementation_with_config(Arc::new(rpc), &PROXY, &dispatch, None, &config).await.unwrap();
+    assert_eq!((implementation, client.calls()), (ProxyImplementation::Single(IMPLEMENTATION), 3));
+
+    // Without retries the first error stands, with its response
+    let (rpc, client) = FnRpc::provider(flaky(2));
+    let Err(ProxyReadError::Rpc(error)) = get_proxy_implementation_with_config(Arc::new(rpc), &PROXY, &dispatch, None, &ReadConfig { retries: 0, ..config.clone() }).await else { panic!("should fail") };
+    assert_eq!((error.kind, error.attempts, error.response.map(|r| r.code)), (RpcErrorKind::RateLimited, 1, Some(429)));
+    assert_eq!(client.calls(), 1);
+
+    // Nor after running out of them
+    let (rpc, client) = FnRpc::provider(flaky(usize::MAX));
+    let Err(ProxyReadError::Rpc(error)) = get_proxy_implementation_with_config(Arc::new(rpc), &PROXY, &dispatch, None, &config).await else { panic!("should fail") };
+    assert_eq!((error.kind, error.attempts), (RpcErrorKind::RateLimited, 4));
+    assert_eq!(client.calls(), 4);
+    // Batch tooling may still try again later
+    let error = ProxyReadError::Rpc(error);
+    assert_eq!((error.category(), error.is_retryable()), (ErrorCategory::Rpc, true));
+
+    // Errors the node answers with aren't retried
+    let (rpc, client) = FnRpc::provider(|_: &str, _: &Value| Err(rpc_error(3, "execution reverted", None)));
+    let Err(ProxyReadError::Rpc(error)) = get_proxy_implementation_with_config(Arc::new(rpc), &PROXY, &dispatch, None, &config).await else { panic!("should fail") };
+    assert_eq!((error.kind, error.attempts), (RpcErrorKind::Rejected, 1));
+    assert_eq!(client.calls(), 1);
+    // With the response as the source
+    let error = ProxyReadError::Rpc(error);
+    let source = std::error::Error::source(&error).unwrap().to_string();
+    assert!(source.contains("execution reverted"), "{}", source);
+    assert_eq!((error.category(), error.is_retryable()), (ErrorCategory::Rpc, false));
+    assert_eq!((ProxyReadError::NoCode(PROXY).category(), ProxyReadError::StorageNotAddress.category()), (ErrorCategory::User, ErrorCategory::Decoding));
+}
+
+#[tokio::test]
+async fn test_verify_implementation() {
+    let code = |_: &str, params: &Value| {
+        let address: Address = params[0].as_str().unwrap().parse().unwrap();
+        Ok(if address == IMPLEMENTATION { json!("0x6001") } else { json!("0x") })
+    };
+    let (rpc, client) = FnRpc::provider(code);
+    let resolved = verify_implementation(&rpc, &ProxyImplementation::Multiple(vec![IMPLEMENTATION, PROXY, IMPLEMENTATION]), None, &ReadConfig::default()).await.unwrap();
+    assert_eq!(resolved.iter().map(|r| (r.address, r.has_code, r.code_hash)).collect::<Vec<_>>(), vec![
+        (IMPLEMENTATION, true, keccak256([0x60, 0x01])),
+        (PROXY, false, keccak256([])),
+    ]);
+    assert_eq!(client.calls(), 2);
+
+    // An empty slot is an uninitialized proxy, not a codeless implementation
+    let (rpc, client) = FnRpc::provider(code);
+    assert!(matches!(verify_implementation(&rpc, &ProxyImplementation::Single(Address::ZERO), None, &ReadConfig::default()).await, Err(ProxyReadError::UninitializedProxy)));
+    assert_eq!(client.calls(), 0);
+}
+
+#[tokio::test]
+async fn test_external_resolver() {
+    const RESOLVER: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+    let resolve = |answer: Result<Value, String>| {
+        let (rpc, _) = FnRpc::provider(move |method: &str, params: &Value| {
+            assert_eq!((method, params[0]["to"].as_str()), ("eth_call", Some("0x00000000000000000000000000000000000000cc")));
+            answer.clone()
+        });
+        async move { get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::External(RESOLVER, Selector::from(0xaaf10f42))).await }
+    };
+    let word = json!(format!("0x{}", hex::encode(B256::left_padding_from(IMPLEMENTATION.as_slice()))));
+    assert_eq!(resolve(Ok(word)).await.unwrap(), ProxyImplementation::Single(IMPLEMENTATION));
+
+    // Empty and dirty returns aren't addresses, reverts keep their data
+    assert!(matches!(resolve(Ok(json!("0x"))).await, Err(ProxyReadError::ResolverNotAddress(RESOLVER, _, output)) if output.is_empty()));
+    let dirty = format!("0x{}", "ff".repeat(32));
+    assert!(matches!(resolve(Ok(json!(dirty))).await, Err(ProxyReadError::ResolverNotAddress(_, _, output)) if output.len() == 32));
+    let reverted = resolve(Err(rpc_error(3, "execution reverted", Some(json!("0x82b42900"))))).await;
+    assert!(matches!(&reverted, Err(ProxyReadError::ResolverFailed(RESOLVER, _, ProbeOutcome::RevertRaw(data))) if data[..] == hex_literal::hex!("82b42900")));
+    assert!(reverted.unwrap_err().to_string().contains("0x82b42900"));
+
+    // Diamond resolvers answer facetAddress(bytes4) for one selector, all their facets are asked
+    let (rpc, _) = FnRpc::provider(|_: &str, params: &Value| {
+        assert_eq!(params[0]["data"].as_str().or(params[0]["input"].as_str()), Some("0x52ef6b2c"));
+        Ok(json!(format!("0x{}", hex::encode(encode(&[Token::Array(vec![Token::Address(H160::from_low_u64_be(0xfa)), Token::Address(H160::from_low_u64_be(0xfb))])])))))
+    });
+    let facets = get_proxy_implementation(Arc::new(rpc), &PROXY, &ProxyDispatch::External(RESOLVER, Selector::from(0xcdffacc6))).await.unwrap();
+    assert_eq!(facets, ProxyImplementation::Multiple(vec![Address::with_last_byte(0xfa), Address::with_last_byte(0xfb)]));
+}
+
+#[tokio::test]
+async fn test_block_tags() {
+    // Tags and hashes reach the node as given
+    let blocks = Arc::new(Mutex::new(Vec::new()));
+    let (rpc, _) = FnRpc::provider({
+        let blocks = blocks.clone();
+        move |method: &str, params: &Value| {
+            assert_eq!(method, "eth_getStorageAt");
+            blocks.lock().unwrap().push(params[2].clone());
+            Ok(json!(format!("0x{}", hex::encode(B256::left_padding_from(IMPLEMENTATION.as_slice())))))
+        }
+    });
+    let rpc = Arc::new(rpc);
+    let dispatch = ProxyDispatch::Storage(U256::from(1), None);
+    let hash = H256::repeat_byte(0xbb);
+    for block in [BlockNumber::Finalized.into(), BlockNumber::Safe.into(), BlockNumber::Pending.into(), BlockNumber::Latest.into(), hash.into()] {
+        get_proxy_implementation_at(rpc.clone(), &PROXY, &dispatch, Some(block)).await.unwrap();
+    }
+    assert_eq!(*blocks.lock().unwrap(), vec![json!("finalized"), json!("safe"), json!("pending"), json!("latest"), json!({ "blockHash": format!("{:?}", hash) })]);
+
+    // Searches resolve them to a number first
+    let timeline = code_timeline(1_000_000, 17_999_000, 0);
+    let (rpc, _) = FnRpc::provider(move |method: &str, params: &Value| match method {
+        "eth_getBlockByNumber" => {
+            assert_eq!(params[0], json!("finalized"));
+            Ok(json!({ "number": "0x112a880" }))
+        },
+        _ => timeline(method, params),
+    });
+    assert_eq!(resolve_block_number(&rpc, Some(BlockNumber::Finalized.into())).await.unwrap(), 18_000_000);
+    assert_eq!(resolve_block_number(&rpc, Some(17_000_000.into())).await.unwrap(), 17_000_000);
+    let freshness = get_proxy_freshness(&rpc, &PROXY, &IMPLEMENTATION, Some(BlockNumber::Finalized.into()), 64).await.unwrap();
+    assert_eq!((freshness.head_block, freshness.impl_deploy_block), (18_000_000, 17_999_000));
+}
+
+/// Accounts in memory, without a node: the storage and code of each, and what calls return.
+#[derive(Default)]
+struct FakeReader {
+    storage: HashMap<(Address, U256), B256>,
+    code: HashMap<Address, Bytes>,
+    calls: HashMap<(Address, Bytes), Bytes>,
+}
+
+impl StorageReader for FakeReader {
+    async fn storage_at(&self, address: Address, slot: U256, _: Option<BlockId>) -> Result<B256, RpcError> {
+        Ok(self.storage.get(&(address, slot)).copied().unwrap_or_default())
+    }
+
+    async fn code_at(&self, address: Address, _: Option<BlockId>) -> Result<Bytes, RpcError> {
+        Ok(self.code.get(&address).cloned().unwrap_or_default())
+    }
+
+    async fn call_at(&self, to: Address, data: Bytes, _: Option<BlockId>) -> Result<Bytes, RpcError> {
+        self.calls.get(&(to, data)).cloned().ok_or_else(|| RpcError { kind: RpcErrorKind::Rejected, attempts: 1, response: None, message: "execution reverted".to_string() })
+    }
+}
+
+#[tokio::test]
+async fn test_storage_reader() {
+    const BEACON: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000be"));
+    let mut reader = FakeReader::default();
+    reader.storage.insert((PROXY, U256::from(1)), B256::left_padding_from(BEACON.as_slice()));
+    reader.code.insert(BEACON, Bytes::from_static(&[0x60, 0x01]));
+    reader.calls.insert((BEACON, Bytes::from_static(&hex_literal::hex!("5c60da1b"))), B256::left_padding_from(IMPLEMENTATION.as_slice()).into());
+    for (slot, word) in selectors_layout(&[(FACET, &[0x11223344])]) {
+        reader.storage.insert((PROXY, slot), word.into());
+    }
+    let reader = Arc::new(reader);
+
+    let read = |dispatch: ProxyDispatch| {
+        let reader = reader.clone();
+        async move { get_proxy_implementation(reader, &PROXY, &dispatch).await }
+    };
+    assert_eq!(read(ProxyDispatch::Storage(U256::from(1), None)).await.unwrap(), ProxyImplementation::Single(BEACON));
+    assert_eq!(read(ProxyDispatch::Beacon(U256::from(1))).await.unwrap(), ProxyImplementation::Single(IMPLEMENTATION));
+    assert_eq!(read(ProxyDispatch::FacetStorageSlot).await.unwrap(), ProxyImplementation::Facets(HashMap::from([(Selector::from(0x11223344), FACET)])));
+    // A beacon without code, and a loupe that reverts
+    assert!(matches!(read(ProxyDispatch::Beacon(U256::from(2))).await, Err(ProxyReadError::NoCode(Address::ZERO))));
+    assert!(matches!(read(ProxyDispatch::Facet_EIP_2535).await, Err(ProxyReadError::Rpc(e)) if e.kind == RpcErrorKind::Rejected));
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::sync::Arc;
+
+use alloy_primitives::{Address, U256};
+use evm_proxy_tools::{detect_proxy, resolve_with_redetection, Contradiction, DetectorConfig, Finding, ProxyDispatch, ProxyImplementation, ProxyReadError, ProxyType, RedetectConfig, RuleId};
+use serde_json::{json, Value};
+
+use common::FnRpc;
+use common::fixtures::{EIP_1967_CODE, FALLBACK_SLOT_PROXY_CODE, SOLADY_PUSH0_CLONE_CODE};
+
+const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+const IMPLEMENTATION: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000bb"));
+
+const EIP_1967_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+/// The slot [FALLBACK_SLOT_PROXY_CODE] falls back to.
+const FALLBACK_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("6b1c0ffee5e2c0e5d5e1b1f3b0a1c2d3e4f5061728394a5b6c7d8e9fa0b1c2d3"));
+
+/// A chain where `PROXY` holds `storage` (zero elsewhere) and only `IMPLEMENTATION` has code.
+fn chain(storage: Vec<(U256, Address)>) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| match method {
+        "eth_getStorageAt" => {
+            assert_eq!(params[0].as_str().unwrap().parse::<Address>().unwrap(), PROXY);
+            let slot: U256 = params[1].as_str().unwrap().parse().unwrap();
+            let value = storage.iter().find(|(s, _)| *s == slot).map(|(_, address)| address.into_word()).unwrap_or_default();
+            Ok(json!(value.to_string()))
+        },
+        "eth_getCode" => {
+            let address: Address = params[0].as_str().unwrap().parse().unwrap();
+            Ok(json!(if address == IMPLEMENTATION { "0x6001" } else { "0x" }))
+        },
+        _ => Err(format!("unexpected {}", method)),
+    }
+}
+
+#[tokio::test]
+async fn test_correction_found() {
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    // With synthetic storage the EIP-1967 slot is never zero
+    let detection = detect_proxy(FALLBACK_SLOT_PROXY_CODE, &config).unwrap();
+    assert_eq!(detection.dispatch, ProxyDispatch::Storage(EIP_1967_SLOT, None));
+
+    let (rpc, _) = FnRpc::provider(chain(vec![(FALLBACK_SLOT, IMPLEMENTATION)]));
+    let resolution = resolve_with_redetection(Arc::new(rpc), &PROXY, FALLBACK_SLOT_PROXY_CODE, detection, &config, &RedetectConfig::default()).await;
+    assert_eq!(resolution.detection.proxy_type, ProxyType::ImmutableSlotProxy);
+    assert_eq!(resolution.detection.rule, RuleId::ImmutableStorageSlot);
+    assert_eq!(resolution.detection.dispatch, ProxyDispatch::Storage(FALLBACK_SLOT, None));
+    assert_eq!(resolution.detection.findings, vec![Finding::DispatchCorrected { original: ProxyDispatch::Storage(EIP_1967_SLOT, None) }]);
+    assert_eq!(resolution.implementation.unwrap(), ProxyImplementation::Single(IMPLEMENTATION));
+    assert_eq!(resolution.passes, 1);
+    // Both pushed slots and the 5 well known ones, one of them the EIP-1967 slot
+    assert_eq!(resolution.storage_reads, 6);
+}
+
+#[tokio::test]
+async fn test_contradiction_confirmed() {
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let detection = detect_proxy(EIP_1967_CODE, &config).unwrap();
+    let (rpc, _) = FnRpc::provider(chain(Vec::new()));
+    let resolution = resolve_with_redetection(Arc::new(rpc), &PROXY, EIP_1967_CODE, detection.clone(), &config, &RedetectConfig::default()).await;
+    assert_eq!(resolution.detection.dispatch, detection.dispatch);
+    assert_eq!(resolution.detection.findings, vec![Finding::ResolutionContradiction(Contradiction::UninitializedSlot(EIP_1967_SLOT))]);
+    assert_eq!(resolution.implementation.unwrap(), ProxyImplementation::Single(Address::ZERO));
+    assert_eq!(resolution.passes, 2);
+
+    // Without budget the contradiction is reported right away
+    let (rpc, client) = FnRpc::provider(chain(Vec::new()));
+    let redetect = RedetectConfig { max_passes: 0, max_storage_reads: 0, ..Default::default() };
+    let resolution = resolve_with_redetection(Arc::new(rpc), &PROXY, EIP_1967_CODE, detection, &config, &redetect).await;
+    assert_eq!((resolution.passes, resolution.storage_reads), (0, 0));
+    assert_eq!(resolution.detection.findings.len(), 1);
+    assert_eq!(client.calls(), 1);
+}
+
+#[tokio::test]
+async fn test_dangling_target() {
+    let config = DetectorConfig::default();
+    let detection = detect_proxy(SOLADY_PUSH0_CLONE_CODE, &config).unwrap();
+    let ProxyDispatch::Static(target) = detection.dispatch else { panic!("clones dispatch statically") };
+    let (rpc, _) = FnRpc::provider(chain(Vec::new()));
+    let resolution = resolve_with_redetection(Arc::new(rpc), &PROXY, SOLADY_PUSH0_CLONE_CODE, detection, &config, &RedetectConfig::default()).await;
+    assert_eq!(resolution.detection.findings, vec![Finding::ResolutionContradiction(Contradiction::DanglingTarget(target))]);
+}
+
+#[tokio::test]
+async fn test_no_contradiction() {
+    let config = DetectorConfig { seed: Some(1), ..Default::default() };
+    let detection = detect_proxy(EIP_1967_CODE, &config).unwrap();
+    let (rpc, client) = FnRpc::provider(chain(vec![(EIP_1967_SLOT, IMPLEMENTATION)]));
+    let resolution = resolve_with_redetection(Arc::new(rpc), &PROXY, EIP_1967_CODE, detection.clone(), &config, &RedetectConfig::default()).await;
+    assert_eq!(resolution.detection, detection);
+    assert_eq!((resolution.passes, resolution.storage_reads), (0, 0));
+    // The slot, then the implementation's code
+    assert_eq!(client.calls(), 2);
+
+    // Errors unrelated to the dispatch aren't contradictions
+    let (rpc, _) = FnRpc::provider(|_: &str, _: &Value| Err("connection refused".to_string()));
+    let resolution = resolve_with_redetection(Arc::new(rpc), &PROXY, EIP_1967_CODE, detection, &config, &RedetectConfig::default()).await;
+    assert!(matches!(resolution.implementation, Err(ProxyReadError::Rpc(_))));
+    assert!(resolution.detection.findings.is_empty());
+}
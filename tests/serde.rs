@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use alloy_primitives::{Address, Bytes, U256};
+use evm_proxy_tools::{DispatchKind, ProxyDispatch, ProxyImplementation, ProxyType, Selector, SlotExtraction};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+const EIP_1967_SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+
+/// `value` as JSON, after checking it reads back the same.
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: &T) -> Value {
+    let json = serde_json::to_value(value).unwrap();
+    assert_eq!(&serde_json::from_value::<T>(json.clone()).unwrap(), value);
+    json
+}
+
+#[test]
+fn test_proxy_type() {
+    assert_eq!(round_trip(&ProxyType::EIP_1967_TRANSPARENT), json!("EIP_1967_TRANSPARENT"));
+    assert_eq!(round_trip(&ProxyType::UUPS), json!("UUPS"));
+    assert!(serde_json::from_value::<ProxyType>(json!("EIP_9999")).is_err());
+}
+
+#[test]
+fn test_map_keys() {
+    let mut counts: HashMap<ProxyType, u64> = HashMap::new();
+    for proxy_type in [ProxyType::EIP_1967, ProxyType::EIP_1167, ProxyType::EIP_1967] {
+        *counts.entry(proxy_type).or_default() += 1;
+    }
+    assert_eq!(counts[&ProxyType::EIP_1967], 2);
+    let summary: BTreeMap<ProxyType, u64> = counts.into_iter().collect();
+    assert_eq!(summary.keys().collect::<Vec<_>>(), [&ProxyType::EIP_1167, &ProxyType::EIP_1967]);
+    let mut all = ProxyType::ALL.to_vec();
+    all.reverse();
+    all.sort();
+    assert_eq!(all, ProxyType::ALL);
+
+    let slot: U256 = EIP_1967_SLOT.parse().unwrap();
+    let implementation = Address::repeat_byte(0xbb);
+    let mut dispatches = [
+        ProxyDispatch::External(implementation, Selector::new([0x12, 0x34, 0x56, 0x78])),
+        ProxyDispatch::Static(implementation),
+        ProxyDispatch::Storage(slot, None),
+        ProxyDispatch::Facet_EIP_2535,
+        ProxyDispatch::Storage(U256::ZERO, Some(SlotExtraction::default())),
+        ProxyDispatch::Storage(U256::ZERO, None),
+        ProxyDispatch::StaticWithArgs(implementation, Bytes::from_static(&[1])),
+        ProxyDispatch::Unknown,
+    ];
+    let unique: HashSet<ProxyDispatch> = dispatches.iter().cloned().chain([ProxyDispatch::Static(implementation)]).collect();
+    assert_eq!(unique.len(), dispatches.len());
+    dispatches.sort();
+    assert_eq!(dispatches[..4], [
+        ProxyDispatch::Unknown,
+        ProxyDispatch::Storage(U256::ZERO, None),
+        ProxyDispatch::Storage(U256::ZERO, Some(SlotExtraction::default())),
+        ProxyDispatch::Storage(slot, None),
+    ]);
+    let kinds: Vec<DispatchKind> = dispatches.iter().map(ProxyDispatch::kind).collect();
+    assert_eq!(kinds, [DispatchKind::Unknown, DispatchKind::Storage, DispatchKind::Storage, DispatchKind::Storage, DispatchKind::Static, DispatchKind::Static, DispatchKind::Facets, DispatchKind::External]);
+    assert!(kinds.is_sorted());
+}
+
+#[test]
+fn test_proxy_dispatch() {
+    let slot: U256 = EIP_1967_SLOT.parse().unwrap();
+    let implementation = Address::repeat_byte(0xbb);
+    let selector = Selector::new([0x12, 0x34, 0x56, 0x78]);
+    assert_eq!(round_trip(&ProxyDispatch::Storage(slot, None)), json!({ "kind": "storage", "slot": EIP_1967_SLOT }));
+    assert_eq!(round_trip(&ProxyDispatch::Storage(U256::ZERO, Some(SlotExtraction::new(16, U256::MAX >> 96)))), json!({
+        "kind": "storage",
+        "slot": "0x0",
+        "extraction": { "shift": 16, "mask": "0xffffffffffffffffffffffffffffffffffffffff" },
+    }));
+    assert_eq!(round_trip(&ProxyDispatch::Static(implementation)), json!({ "kind": "static", "implementation": implementation }));
+    assert_eq!(round_trip(&ProxyDispatch::StaticWithArgs(implementation, Bytes::from_static(&[0xaa, 0xbb]))), json!({ "kind": "static_with_args", "implementation": implementation, "args": "0xaabb" }));
+    assert_eq!(round_trip(&ProxyDispatch::Facet_EIP_2535), json!({ "kind": "facets" }));
+    assert_eq!(round_trip(&ProxyDispatch::External(implementation, selector)), json!({ "kind": "external", "address": implementation, "selector": "0x12345678" }));
+    for dispatch in [ProxyDispatch::Unknown, ProxyDispatch::MultipleStorage(vec![slot, U256::from(1)]), ProxyDispatch::Beacon(slot), ProxyDispatch::FacetStorageSlot, ProxyDispatch::PerSelector(vec![(selector, implementation)])] {
+        round_trip(&dispatch);
+    }
+    assert!(serde_json::from_value::<ProxyDispatch>(json!({ "kind": "storage" })).is_err());
+}
+
+#[test]
+fn test_proxy_implementation() {
+    let implementation = Address::repeat_byte(0xbb);
+    assert_eq!(round_trip(&ProxyImplementation::Single(implementation)), json!({ "kind": "single", "implementation": implementation }));
+    assert_eq!(round_trip(&ProxyImplementation::Multiple(vec![implementation, Address::ZERO])), json!({ "kind": "multiple", "implementations": [implementation, Address::ZERO] }));
+    // Facets are sorted by selector, whatever the map's order
+    let facets = HashMap::from([(Selector::new([0, 0, 0, 2]), Address::repeat_byte(0xcc)), (Selector::new([0, 0, 0, 1]), Address::repeat_byte(0xaa))]);
+    let facets = ProxyImplementation::Facets(facets);
+    round_trip(&facets);
+    assert_eq!(serde_json::to_string(&facets).unwrap(), concat!(r#"{"kind":"facets","facets":{"#, r#""0x00000001":"0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa","#, r#""0x00000002":"0xcccccccccccccccccccccccccccccccccccccccc"}}"#));
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, U256};
+use evm_proxy_tools::{trust_set, ProxyDetectionResult, ProxyDispatch, ProxyImplementation, ProxyType, RuleId, Selector, TrustMechanism, TrustRole, TrustedParty};
+
+const PROXY: Address = Address::repeat_byte(0xaa);
+const IMPLEMENTATION: Address = Address::repeat_byte(0xbb);
+const ADMIN: Address = Address::repeat_byte(0xad);
+const BEACON: Address = Address::repeat_byte(0xbe);
+
+const IMPLEMENTATION_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"));
+const ADMIN_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103"));
+const BEACON_SLOT: U256 = U256::from_be_bytes(hex_literal::hex!("a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50"));
+
+fn party(address: Address, role: TrustRole, mechanism: TrustMechanism, mutable_by: Option<Address>) -> TrustedParty {
+    TrustedParty { address, role, mechanism, mutable_by }
+}
+
+#[test]
+fn test_transparent_proxy() {
+    let mut detection = ProxyDetectionResult::new(ProxyType::EIP_1967_TRANSPARENT, ProxyDispatch::Storage(IMPLEMENTATION_SLOT, None), RuleId::TransparentAdminBranch);
+    detection.admin_slot = Some(ADMIN_SLOT);
+    let trust = trust_set(&PROXY, &detection, Some(&ProxyImplementation::Single(IMPLEMENTATION)), Some(ADMIN), None);
+    assert_eq!(trust.entries, vec![
+        party(ADMIN, TrustRole::Admin, TrustMechanism::AdminRights(ADMIN_SLOT), Some(ADMIN)),
+        party(IMPLEMENTATION, TrustRole::Implementation, TrustMechanism::StorageSlot(IMPLEMENTATION_SLOT), Some(ADMIN)),
+    ]);
+
+    // UUPS implementations upgrade the proxy themselves
+    let detection = ProxyDetectionResult::new(ProxyType::UUPS, ProxyDispatch::Storage(IMPLEMENTATION_SLOT, None), RuleId::KnownStorageSlot);
+    let trust = trust_set(&PROXY, &detection, Some(&ProxyImplementation::Single(IMPLEMENTATION)), None, None);
+    assert_eq!(trust.entries, vec![party(IMPLEMENTATION, TrustRole::Implementation, TrustMechanism::StorageSlot(IMPLEMENTATION_SLOT), Some(IMPLEMENTATION))]);
+
+    // Nothing is known of an unresolved implementation
+    assert!(trust_set(&PROXY, &detection, None, None, None).entries.is_empty());
+}
+
+#[test]
+fn test_beacon_proxy() {
+    let detection = ProxyDetectionResult::new(ProxyType::EIP_1967_BEACON, ProxyDispatch::Beacon(BEACON_SLOT), RuleId::BeaconProxyPattern);
+    let trust = trust_set(&PROXY, &detection, Some(&ProxyImplementation::Single(IMPLEMENTATION)), None, Some(BEACON));
+    let implementation = Selector::new(hex_literal::hex!("5c60da1b"));
+    assert_eq!(trust.entries, vec![
+        party(IMPLEMENTATION, TrustRole::Implementation, TrustMechanism::Getter { target: BEACON, selector: implementation }, Some(BEACON)),
+        party(BEACON, TrustRole::Beacon, TrustMechanism::StorageSlot(BEACON_SLOT), None),
+    ]);
+    assert_eq!(trust.parties(), vec![IMPLEMENTATION, BEACON]);
+}
+
+#[test]
+fn test_registry_and_clone() {
+    let get_logic = Selector::new([0x0c; 4]);
+    let detection = ProxyDetectionResult::new(ProxyType::External, ProxyDispatch::External(BEACON, get_logic), RuleId::RegistryDelegateCall);
+    let trust = trust_set(&PROXY, &detection, Some(&ProxyImplementation::Single(IMPLEMENTATION)), None, None);
+    assert_eq!(trust.entries, vec![
+        party(IMPLEMENTATION, TrustRole::Implementation, TrustMechanism::Getter { target: BEACON, selector: get_logic }, Some(BEACON)),
+        party(BEACON, TrustRole::Registry, TrustMechanism::Hardcoded, None),
+    ]);
+
+    // A clone's implementation can't change
+    let detection = ProxyDetectionResult::new(ProxyType::EIP_1167, ProxyDispatch::Static(IMPLEMENTATION), RuleId::Eip1167Pattern);
+    let trust = trust_set(&PROXY, &detection, None, None, None);
+    assert_eq!(trust.entries, vec![party(IMPLEMENTATION, TrustRole::Implementation, TrustMechanism::Hardcoded, None)]);
+}
+
+#[test]
+fn test_diamond() {
+    let (a, b) = (Selector::new([0x11; 4]), Selector::new([0x22; 4]));
+    let facets = ProxyImplementation::Facets(HashMap::from([(a, BEACON), (b, IMPLEMENTATION)]));
+    let mut detection = ProxyDetectionResult::new(ProxyType::EIP_2535, ProxyDispatch::Facet_EIP_2535, RuleId::DiamondLoupeSelector);
+    detection.facet_slots = vec![(b, U256::from(7))];
+    let facets_getter = TrustMechanism::Getter { target: PROXY, selector: Selector::new(hex_literal::hex!("7a0ed627")) };
+    // Sorted by address whatever the map's order
+    assert_eq!(trust_set(&PROXY, &detection, Some(&facets), None, None).entries, vec![
+        party(IMPLEMENTATION, TrustRole::Facet, TrustMechanism::StorageSlot(U256::from(7)), Some(PROXY)),
+        party(BEACON, TrustRole::Facet, facets_getter, Some(PROXY)),
+    ]);
+}
+
+#[test]
+fn test_roles_deduplicated() {
+    // The admin is also the implementation of both slots
+    let mut detection = ProxyDetectionResult::new(ProxyType::EIP_897, ProxyDispatch::MultipleStorage(vec![U256::from(2), U256::from(1)]), RuleId::LowStorageSlot);
+    detection.admin_slot = Some(ADMIN_SLOT);
+    let trust = trust_set(&PROXY, &detection, Some(&ProxyImplementation::Multiple(vec![ADMIN, ADMIN])), Some(ADMIN), None);
+    assert_eq!(trust.entries, vec![
+        party(ADMIN, TrustRole::Implementation, TrustMechanism::StorageSlot(U256::from(1)), Some(ADMIN)),
+        party(ADMIN, TrustRole::Admin, TrustMechanism::AdminRights(ADMIN_SLOT), Some(ADMIN)),
+    ]);
+    assert_eq!(trust.parties(), vec![ADMIN]);
+    assert_eq!(trust.roles_of(&ADMIN), vec![TrustRole::Implementation, TrustRole::Admin]);
+
+    let json = serde_json::to_value(&trust).unwrap();
+    assert_eq!(json["entries"][1]["role"], "Admin");
+    assert_eq!(json["entries"][1]["mutable_by"], serde_json::json!(ADMIN));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use alloy_primitives::Address;
+use ethers_core::abi::{encode, Token};
+use ethers_core::types::H160;
+use evm_proxy_tools::{characterize_upgrade, compare_proxy_code, diff_facets, CodeComparison, FacetDiff, ProxyImplementation, Selector};
+use serde_json::{json, Value};
+
+use common::{block_param, FnRpc};
+use common::fixtures::{DIAMOND_STANDARD_CODE, EIP_1967_CODE, EIP_897_CODE};
+
+const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+const IMPL_A: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000bb"));
+const IMPL_B: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+const UPGRADE_BLOCK: u64 = 100;
+
+fn hex_value(bytes: &[u8]) -> Value {
+    json!(format!("0x{}", alloy_primitives::hex::encode(bytes)))
+}
+
+fn word(address: &Address) -> Value {
+    hex_value(address.into_word().as_slice())
+}
+
+/// A chain where the proxy's code and implementation switch at [UPGRADE_BLOCK]. `IMPL_A` and
+/// `IMPL_B` have different code, `eth_call` answers the diamond loupe with `facets(block)`.
+fn chain(proxy_before: &'static [u8], proxy_after: Vec<u8>, impl_before: Address, impl_after: Address, facets: fn(u64) -> Vec<Address>) -> impl Fn(&str, &Value) -> Result<Value, String> {
+    move |method, params| {
+        let upgraded = |param: &Value| block_param(param) >= UPGRADE_BLOCK;
+        match method {
+            "eth_getCode" => {
+                let address: Address = params[0].as_str().unwrap().parse().unwrap();
+                Ok(match address {
+                    PROXY if upgraded(&params[1]) => hex_value(&proxy_after),
+                    PROXY => hex_value(proxy_before),
+                    IMPL_A => json!("0x6001"),
+                    _ => json!("0x6002"),
+                })
+            },
+            "eth_getStorageAt" => Ok(word(if upgraded(&params[2]) { &impl_after } else { &impl_before })),
+            "eth_call" => {
+                let facets = facets(block_param(&params[1])).into_iter()
+                    .map(|facet| Token::Tuple(vec![Token::Address(H160::from(facet.0 .0)), Token::Array(vec![Token::FixedBytes(vec![0x12, 0x34, 0x56, 0x78])])]))
+                    .collect();
+                Ok(hex_value(&encode(&[Token::Array(facets)])))
+            },
+            _ => Err(format!("unexpected {}", method)),
+        }
+    }
+}
+
+fn no_facets(_: u64) -> Vec<Address> {
+    Vec::new()
+}
+
+#[tokio::test]
+async fn test_nothing_changed() {
+    let (rpc, _) = FnRpc::provider(chain(EIP_1967_CODE, EIP_1967_CODE.to_vec(), IMPL_A, IMPL_A, no_facets));
+    let upgrade = characterize_upgrade(Arc::new(rpc), &PROXY, 50, 150).await.unwrap();
+    assert_eq!(upgrade.proxy_code, CodeComparison::Identical);
+    assert_eq!(upgrade.implementation_before, Some(ProxyImplementation::Single(IMPL_A)));
+    assert!(!upgrade.implementation_changed());
+    assert_eq!(upgrade.implementation_code, Some(CodeComparison::Identical));
+    assert_eq!(upgrade.facets, None);
+}
+
+#[tokio::test]
+async fn test_implementation_only() {
+    let (rpc, _) = FnRpc::provider(chain(EIP_1967_CODE, EIP_1967_CODE.to_vec(), IMPL_A, IMPL_B, no_facets));
+    let upgrade = characterize_upgrade(Arc::new(rpc), &PROXY, 50, 150).await.unwrap();
+    assert!(upgrade.proxy_logic_unchanged());
+    assert!(upgrade.implementation_changed());
+    assert_eq!(upgrade.implementation_after, Some(ProxyImplementation::Single(IMPL_B)));
+    assert!(!upgrade.implementation_code.unwrap().same_logic());
+}
+
+#[tokio::test]
+async fn test_metadata_only() {
+    // Same sources, different metadata hash
+    let mut recompiled = EIP_1967_CODE.to_vec();
+    let hash_byte = recompiled.len() - 20;
+    recompiled[hash_byte] ^= 0xff;
+    let (rpc, _) = FnRpc::provider(chain(EIP_1967_CODE, recompiled, IMPL_A, IMPL_A, no_facets));
+    let CodeComparison::Changed(summary) = compare_proxy_code(&rpc, &PROXY, 50, 150).await.unwrap() else { panic!("code should differ") };
+    assert!(summary.metadata_changed);
+    assert!(!summary.logic_changed);
+    assert_eq!(summary.immutables_changed, 0);
+
+    let upgrade = characterize_upgrade(Arc::new(rpc), &PROXY, 50, 150).await.unwrap();
+    assert!(upgrade.proxy_logic_unchanged());
+    assert!(!upgrade.implementation_changed());
+}
+
+#[tokio::test]
+async fn test_proxy_logic_changed() {
+    let (rpc, _) = FnRpc::provider(chain(EIP_897_CODE, EIP_1967_CODE.to_vec(), IMPL_A, IMPL_B, no_facets));
+    let upgrade = characterize_upgrade(Arc::new(rpc), &PROXY, 50, 150).await.unwrap();
+    let CodeComparison::Changed(summary) = &upgrade.proxy_code else { panic!("code should differ") };
+    assert!(summary.logic_changed);
+    assert_eq!((summary.before_len, summary.after_len), (EIP_897_CODE.len(), EIP_1967_CODE.len()));
+    assert!(upgrade.implementation_changed());
+}
+
+#[tokio::test]
+async fn test_diamond_facets() {
+    fn facets(block: u64) -> Vec<Address> {
+        if block >= UPGRADE_BLOCK { vec![IMPL_A, IMPL_B] } else { vec![IMPL_A, PROXY] }
+    }
+    let (rpc, _) = FnRpc::provider(chain(DIAMOND_STANDARD_CODE, DIAMOND_STANDARD_CODE.to_vec(), IMPL_A, IMPL_A, facets));
+    let upgrade = characterize_upgrade(Arc::new(rpc), &PROXY, 50, 150).await.unwrap();
+    assert_eq!(upgrade.proxy_code, CodeComparison::Identical);
+    assert!(upgrade.implementation_changed());
+    assert_eq!(upgrade.implementation_code, None);
+    // Every facet routes 0x12345678, the last one wins
+    let selector = Selector::from(0x12345678);
+    let expected = FacetDiff { added: vec![IMPL_B], removed: vec![PROXY], replaced_selectors: BTreeMap::from([(selector, (PROXY, IMPL_B))]), ..FacetDiff::default() };
+    assert_eq!(upgrade.facets, Some(expected));
+}
+
+#[test]
+fn test_diff_facets() {
+    let (cut, loupe, owner) = (Selector::from(0x1f931c1c), Selector::from(0x7a0ed627), Selector::from(0x8da5cb5b));
+    let before = ProxyImplementation::Facets(HashMap::from([(cut, IMPL_A), (loupe, IMPL_A), (owner, IMPL_A)]));
+    let after = ProxyImplementation::Facets(HashMap::from([(cut, IMPL_A), (loupe, IMPL_B), (Selector::from(0x12345678), IMPL_B)]));
+    let diff = diff_facets(&before, &after);
+    assert_eq!((diff.added, diff.removed), (vec![IMPL_B], vec![]));
+    assert_eq!(diff.added_selectors, BTreeMap::from([(Selector::from(0x12345678), IMPL_B)]));
+    assert_eq!(diff.removed_selectors, BTreeMap::from([(owner, IMPL_A)]));
+    assert_eq!(diff.replaced_selectors, BTreeMap::from([(loupe, (IMPL_A, IMPL_B))]));
+    assert_eq!((cut.label(), Selector::from(0x12345678).label()), (Some("diamondCut((address,uint8,bytes4[])[],address,bytes)"), None));
+
+    // Not a diamond yet, every selector is added
+    let diff = diff_facets(&ProxyImplementation::Single(IMPL_A), &after);
+    assert_eq!(diff.added_selectors.len(), 3);
+    assert!(diff.removed_selectors.is_empty() && diff.replaced_selectors.is_empty());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256, U256};
+use evm_proxy_tools::{watch_implementation, watch_implementation_logs, EventMonitoringAdvice, ImplementationChange, ProxyDispatch, ProxyImplementation, ProxyReadError, ProxyType, UpgradeSignal};
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+use common::{block_param, rpc_error, FnRpc};
+
+const PROXY: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000aa"));
+const IMPL_A: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000bb"));
+const IMPL_B: Address = Address::new(hex_literal::hex!("00000000000000000000000000000000000000cc"));
+
+fn word(address: &Address) -> Value {
+    json!(format!("0x{}", alloy_primitives::hex::encode(address.into_word())))
+}
+
+#[tokio::test]
+async fn test_watch_implementation() {
+    // A block per poll, upgraded to IMPL_B at block 4, the node failing at block 3
+    let head = Arc::new(AtomicU64::new(0));
+    let polled = head.clone();
+    let (rpc, _) = FnRpc::provider(move |method, params| match method {
+        "eth_blockNumber" => Ok(json!(format!("{:#x}", polled.fetch_add(1, Ordering::SeqCst) + 1))),
+        "eth_getStorageAt" => match block_param(&params[2]) {
+            3 => Err(rpc_error(-32602, "invalid params", None)),
+            block if block >= 4 => Ok(word(&IMPL_B)),
+            _ => Ok(word(&IMPL_A)),
+        },
+        _ => Err(format!("unexpected {}", method)),
+    });
+    let dispatch = ProxyDispatch::Storage(U256::from(1), None);
+    let changes: Vec<_> = watch_implementation(Arc::new(rpc), PROXY, dispatch, Duration::from_millis(1)).take(3).collect().await;
+
+    assert_eq!(changes[0].as_ref().unwrap(), &ImplementationChange { block: 1, previous: None, current: ProxyImplementation::Single(IMPL_A) });
+    // Block 2 didn't change anything, block 3 failed and block 4 is the upgrade
+    assert!(matches!(changes[1], Err(ProxyReadError::Rpc(_))));
+    assert_eq!(changes[2].as_ref().unwrap(), &ImplementationChange { block: 4, previous: Some(ProxyImplementation::Single(IMPL_A)), current: ProxyImplementation::Single(IMPL_B) });
+    assert_eq!(head.load(Ordering::SeqCst), 4);
+}
+
+/// A node at block 1 where the implementation is `IMPL_A`.
+fn quiet_chain(method: &str, _params: &Value) -> Result<Value, String> {
+    match method {
+        "eth_blockNumber" => Ok(json!("0x1")),
+        "eth_getStorageAt" => Ok(word(&IMPL_A)),
+        "eth_subscribe" => Ok(json!("0x1")),
+        _ => Err(format!("unexpected {}", method)),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_dropped_watch_stops() {
+    // Polling stops with the stream, nothing is left running between polls
+    let (rpc, client) = FnRpc::provider(quiet_chain);
+    let dispatch = ProxyDispatch::Storage(U256::from(1), None);
+    let mut changes = Box::pin(watch_implementation(Arc::new(rpc), PROXY, dispatch.clone(), Duration::from_millis(1)));
+    assert!(changes.next().await.unwrap().is_ok());
+    let before = client.calls();
+    drop(changes);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(client.calls(), before);
+
+    // As does the log watch waiting on its subscription
+    let (rpc, client) = FnRpc::provider(quiet_chain);
+    let advice = EventMonitoringAdvice { proxy: PROXY, proxy_type: ProxyType::EIP_1967, signals: vec![UpgradeSignal::Events { address: PROXY, topics: vec![B256::ZERO] }] };
+    let mut changes = Box::pin(watch_implementation_logs(Arc::new(rpc), PROXY, dispatch, &advice));
+    assert!(changes.next().await.unwrap().is_ok());
+    let before = client.calls();
+    drop(changes);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(client.calls(), before);
+}